@@ -0,0 +1,53 @@
+use std::hint::black_box;
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use raytracer_minecraft::camera::Camera;
+use raytracer_minecraft::color::Color;
+use raytracer_minecraft::cube::Cube;
+use raytracer_minecraft::light::Light;
+use raytracer_minecraft::material::Material;
+use raytracer_minecraft::math::{Float, Vec3};
+use raytracer_minecraft::ray_intersect::Ray;
+use raytracer_minecraft::render::cast_ray;
+use raytracer_minecraft::sampler::PixelSample;
+use raytracer_minecraft::scene::Scene;
+use raytracer_minecraft::settings::RenderSettings;
+use raytracer_minecraft::sky::{Ambient, Sky};
+
+// Un suelo con una fila de bloques y cuatro luces que llegan a todo, para que el sombreado de cada
+// luz pese más que la intersección. Compara `cargo bench --bench cast_ray` con y sin `--features simd`.
+fn four_light_scene() -> Scene {
+    let stone = Material::new(Color::new(128, 128, 128), 20.0, [0.9, 0.2, 0.0, 0.0], 1.0);
+    let gold = Material::new(Color::new(240, 200, 60), 80.0, [0.7, 0.6, 0.0, 0.0], 1.0);
+    let mut objects = vec![Cube::new(Vec3::new(-6.0, -1.5, -12.0), Vec3::new(6.0, -1.0, 0.0), &stone)];
+    for i in 0..6 {
+        let x = -4.5 + i as Float * 1.5;
+        objects.push(Cube::new(Vec3::new(x, -1.0, -7.0), Vec3::new(x + 1.0, 0.0, -6.0), if i % 2 == 0 { &stone } else { &gold }));
+    }
+    let lights = vec![
+        Light::new(Vec3::new(-4.0, 5.0, -2.0), Color::new(255, 240, 220), 1.2, 40.0),
+        Light::new(Vec3::new(4.0, 5.0, -2.0), Color::new(200, 220, 255), 1.0, 40.0),
+        Light::new(Vec3::new(0.0, 3.0, -10.0), Color::new(255, 160, 80), 0.8, 40.0),
+        Light::new(Vec3::new(0.0, 8.0, 4.0), Color::new(255, 255, 255), 0.6, 40.0),
+    ];
+    let camera = Camera::new(Vec3::new(0.0, 2.0, 4.0), Vec3::new(0.0, -0.5, -6.0), Vec3::new(0.0, 1.0, 0.0));
+    Scene::new(objects, lights, Arc::new(Sky::Procedural), camera, Ambient::flat(Color::new(100, 110, 130)))
+}
+
+fn cast_ray_four_lights(c: &mut Criterion) {
+    let scene = four_light_scene();
+    let settings = RenderSettings::default();
+    let (width, height) = (64, 48);
+    let rays: Vec<Ray> = (0..width * height)
+        .filter_map(|index| scene.camera.ray((index % width) as Float, (index / width) as Float, width as Float, height as Float))
+        .map(|(origin, direction)| Ray::new(origin, direction))
+        .collect();
+
+    c.bench_function("cast_ray 64x48, 4 lights", |b| {
+        b.iter(|| rays.iter().map(|ray| cast_ray(black_box(ray), &scene, &settings, &PixelSample::default(), 0).to_hex()).fold(0u32, u32::wrapping_add))
+    });
+}
+
+criterion_group!(benches, cast_ray_four_lights);
+criterion_main!(benches);
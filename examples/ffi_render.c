@@ -0,0 +1,54 @@
+/*
+ * Minimal C client for the FFI API. Build the library and run from the repo root:
+ *
+ *   cargo build --release --features ffi
+ *   cc examples/ffi_render.c -Iinclude -Ltarget/release -lraytracer_minecraft -o ffi_render
+ *   LD_LIBRARY_PATH=target/release ./ffi_render out.ppm
+ */
+#include <stdio.h>
+#include <stdlib.h>
+#include "raytracer_minecraft.h"
+
+static int check(RtRenderer *renderer, int32_t status, const char *what) {
+    if (status != RT_OK) {
+        const char *message = rt_renderer_last_error(renderer);
+        fprintf(stderr, "%s failed (%d): %s\n", what, status, message ? message : "no details");
+        return 0;
+    }
+    return 1;
+}
+
+int main(int argc, char **argv) {
+    const char *output = argc > 1 ? argv[1] : "ffi_render.ppm";
+    const uint32_t width = 320, height = 240;
+
+    RtRenderer *renderer = rt_renderer_create();
+    if (!renderer) {
+        fprintf(stderr, "could not create renderer\n");
+        return 1;
+    }
+
+    uint8_t *pixels = malloc((size_t)width * height * 4);
+    int ok = pixels
+        && check(renderer, rt_renderer_load_scene(renderer, "demo"), "load_scene")
+        && check(renderer, rt_renderer_set_camera(renderer, 0.0f, 1.0f, 6.5f, 0.0f, 1.0f, 0.0f, 60.0f), "set_camera")
+        && check(renderer, rt_renderer_render(renderer, pixels, width, height, 4), "render");
+
+    if (ok) {
+        FILE *file = fopen(output, "wb");
+        if (file) {
+            fprintf(file, "P6\n%u %u\n255\n", width, height);
+            for (size_t i = 0; i < (size_t)width * height; i++) {
+                fwrite(&pixels[i * 4], 1, 3, file);
+            }
+            fclose(file);
+            printf("wrote %s\n", output);
+        } else {
+            ok = 0;
+        }
+    }
+
+    free(pixels);
+    rt_renderer_destroy(renderer);
+    return ok ? 0 : 1;
+}
@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracer_minecraft::resource_pack::ResourcePack;
+use raytracer_minecraft::texture::MAX_TEXTURE_SIZE;
+
+// Los nombres que pide el registro de materiales, y uno antiguo para la carpeta textures/blocks
+const BLOCKS: [&str; 11] = [
+    "grass_block_top", "grass_block_side", "oak_planks", "stone", "oak_log", "oak_leaves", "glowstone", "glass", "sand", "gravel", "planks_oak",
+];
+
+// Cualquier zip abre un paquete o da un error, y cada textura que se pide sale o falla sin
+// descomprimir más de lo que su entrada declara
+fuzz_target!(|data: &[u8]| {
+    if let Ok(pack) = ResourcePack::from_bytes(data.to_vec()) {
+        for block in BLOCKS {
+            if let Some(Ok(texture)) = pack.block_texture(block) {
+                assert!(texture.width <= MAX_TEXTURE_SIZE && texture.height <= MAX_TEXTURE_SIZE);
+            }
+        }
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracer_minecraft::scene;
+
+// Cualquier texto da una escena o un RayTracerError, nunca un pánico; los límites de tamaño de las
+// estructuras, el terreno y las posiciones hacen que tampoco pueda agotar la memoria
+fuzz_target!(|data: &[u8]| {
+    if let Ok(contents) = std::str::from_utf8(data) {
+        let _ = scene::from_str(contents);
+    }
+});
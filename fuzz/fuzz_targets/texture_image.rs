@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracer_minecraft::texture::{Texture, MAX_TEXTURE_SIZE};
+
+// Cualquier imagen da una textura de como mucho MAX_TEXTURE_SIZE de lado o un error de `image`
+fuzz_target!(|data: &[u8]| {
+    if let Ok(texture) = Texture::from_bytes(data) {
+        assert!(texture.width <= MAX_TEXTURE_SIZE && texture.height <= MAX_TEXTURE_SIZE);
+        assert_eq!(texture.pixels.len(), (texture.width * texture.height) as usize);
+    }
+});
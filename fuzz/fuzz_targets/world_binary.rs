@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raytracer_minecraft::world::{World, MAX_BLOCKS};
+
+// Cualquier archivo da un mundo o un error. Lo que se acepta tiene a lo sumo MAX_BLOCKS bloques y
+// se vuelve a leer igual después de guardarlo.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(world) = World::from_bytes(data) {
+        assert!(world.blocks.len() <= MAX_BLOCKS);
+        let reloaded = World::from_bytes(&world.to_bytes()).expect("a saved world loads again");
+        assert_eq!(reloaded.blocks.len(), world.blocks.len());
+        assert_eq!(reloaded.lights.len(), world.lights.len());
+    }
+});
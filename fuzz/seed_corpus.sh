@@ -0,0 +1,21 @@
+#!/bin/sh
+# Siembra fuzz/corpus con lo que trae el repositorio: las escenas, las texturas de assets/, los
+# mundos binarios de las escenas de bloques y un resource pack hecho con esas texturas. Se ejecuta
+# desde la raíz del repositorio.
+set -e
+mkdir -p fuzz/corpus/scene_toml fuzz/corpus/world_binary fuzz/corpus/texture_image fuzz/corpus/resource_pack
+cp scenes/*.toml fuzz/corpus/scene_toml/
+cp assets/*.jpg fuzz/corpus/texture_image/
+for scene in forest caves; do
+    cargo run --release --no-default-features --features headless -- \
+        convert "scenes/$scene.toml" "fuzz/corpus/world_binary/$scene.world"
+done
+
+# Las texturas se leen por su contenido, así que unos .jpg con nombre de .png sirven de paquete
+pack=$(mktemp -d)
+mkdir -p "$pack/assets/minecraft/textures/block"
+cp assets/stone_block.jpg "$pack/assets/minecraft/textures/block/stone.png"
+cp assets/glowstone_texture.jpg "$pack/assets/minecraft/textures/block/glowstone.png"
+(cd "$pack" && python3 -m zipfile -c pack.zip assets)
+cp "$pack/pack.zip" fuzz/corpus/resource_pack/
+rm -rf "$pack"
@@ -0,0 +1,95 @@
+#ifndef RAYTRACER_MINECRAFT_H
+#define RAYTRACER_MINECRAFT_H
+
+/* Generated with cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define RT_OK 0
+
+#define RT_ERR_NULL_POINTER -1
+
+#define RT_ERR_INVALID_ARGUMENT -2
+
+#define RT_ERR_SCENE -3
+
+#define RT_ERR_PANIC -4
+
+/**
+ * Largest width or height `rt_renderer_render` accepts. A failed allocation aborts the process,
+ * which no error code can report, so bigger images are refused up front.
+ */
+#define RT_MAX_SIZE 16384
+
+typedef struct RtRenderer RtRenderer;
+
+/**
+ * Creates a renderer with an empty scene. Returns NULL on failure.
+ */
+RtRenderer *rt_renderer_create(void);
+
+/**
+ * Destroys a renderer created with `rt_renderer_create`.
+ *
+ * # Safety
+ * `renderer` must be NULL or a pointer returned by `rt_renderer_create` that was not destroyed yet.
+ */
+void rt_renderer_destroy(RtRenderer *renderer);
+
+/**
+ * Loads a scene by path ("demo" selects the built-in house).
+ *
+ * # Safety
+ * `renderer` must be a valid renderer and `path` a NUL-terminated string.
+ */
+int32_t rt_renderer_load_scene(RtRenderer *renderer, const char *path);
+
+/**
+ * Loads a scene from the contents of a scene file held in memory.
+ *
+ * # Safety
+ * `renderer` must be a valid renderer and `data` must point to `len` readable bytes.
+ */
+int32_t rt_renderer_load_scene_buffer(RtRenderer *renderer, const uint8_t *data, uintptr_t len);
+
+/**
+ * Sets the camera position, look-at target and vertical field of view in degrees.
+ *
+ * # Safety
+ * `renderer` must be a valid renderer.
+ */
+int32_t rt_renderer_set_camera(RtRenderer *renderer,
+                               float eye_x,
+                               float eye_y,
+                               float eye_z,
+                               float target_x,
+                               float target_y,
+                               float target_z,
+                               float fov_degrees);
+
+/**
+ * Renders the scene into `buffer`, which receives `width * height` RGBA8 pixels row by row.
+ * Sizes above `RT_MAX_SIZE` on either side are rejected with `RT_ERR_INVALID_ARGUMENT`.
+ *
+ * # Safety
+ * `renderer` must be a valid renderer and `buffer` must point to `width * height * 4` writable bytes.
+ */
+int32_t rt_renderer_render(RtRenderer *renderer,
+                           uint8_t *buffer,
+                           uint32_t width,
+                           uint32_t height,
+                           uint32_t samples_per_pixel);
+
+/**
+ * Returns the message of the last failed call on this renderer, or NULL.
+ * The string stays valid until the next call on the same renderer.
+ *
+ * # Safety
+ * `renderer` must be NULL or a valid renderer.
+ */
+const char *rt_renderer_last_error(const RtRenderer *renderer);
+
+#endif /* RAYTRACER_MINECRAFT_H */
@@ -0,0 +1,84 @@
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use crate::error::RayTracerError;
+
+// Directorio indicado con --assets; tiene prioridad sobre todo lo demás
+static ASSET_ROOT: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+pub const ASSET_ROOT_ENV: &str = "RAYTRACER_ASSETS";
+
+pub fn set_asset_root(root: Option<PathBuf>) {
+    *ASSET_ROOT.write().unwrap_or_else(|e| e.into_inner()) = root;
+}
+
+// Directorios donde se busca un archivo relativo, en orden: --assets, $RAYTRACER_ASSETS, el
+// directorio de la escena que lo nombra (si lo hay), el directorio de trabajo y el del ejecutable
+fn search_roots(scene_dir: Option<&Path>) -> Vec<(PathBuf, &'static str)> {
+    let mut roots = Vec::new();
+    if let Some(root) = ASSET_ROOT.read().unwrap_or_else(|e| e.into_inner()).clone() {
+        roots.push((root, "--assets"));
+    }
+    if let Some(root) = std::env::var_os(ASSET_ROOT_ENV).filter(|root| !root.is_empty()) {
+        roots.push((PathBuf::from(root), ASSET_ROOT_ENV));
+    }
+    if let Some(dir) = scene_dir {
+        roots.push((dir.to_path_buf(), "scene directory"));
+    }
+    roots.push((PathBuf::from("."), "working directory"));
+    if let Some(dir) = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+        roots.push((dir, "executable directory"));
+    }
+    roots
+}
+
+// Busca `path` en los directorios de `search_roots`. Las rutas de las texturas incluidas empiezan
+// por "assets/", así que en cada directorio también se prueba sin ese prefijo: --assets puede
+// apuntar tanto a la carpeta del proyecto como a la propia carpeta assets/.
+// Si no se encuentra, el error enumera todos los lugares revisados.
+pub fn resolve_asset(path: &str, scene_dir: Option<&Path>) -> Result<PathBuf, RayTracerError> {
+    let relative = Path::new(path);
+    if relative.is_absolute() {
+        return if relative.exists() {
+            Ok(relative.to_path_buf())
+        } else {
+            Err(RayTracerError::Asset(format!("{} not found", path)))
+        };
+    }
+
+    let mut searched = Vec::new();
+    for (root, source) in search_roots(scene_dir) {
+        let mut candidates = vec![root.join(relative)];
+        if let Ok(inside) = relative.strip_prefix("assets") {
+            candidates.push(root.join(inside));
+        }
+        for candidate in candidates {
+            if candidate.exists() {
+                return Ok(candidate);
+            }
+            searched.push(format!("{} ({})", candidate.display(), source));
+        }
+    }
+    Err(RayTracerError::Asset(format!("{} not found; searched {}", path, searched.join(", "))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scene_directory_is_searched_and_errors_list_every_location() {
+        let dir = std::env::temp_dir().join(format!("raytracer_assets_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("textures")).unwrap();
+        std::fs::write(dir.join("textures/brick.png"), b"").unwrap();
+
+        assert_eq!(resolve_asset("textures/brick.png", Some(&dir)).unwrap(), dir.join("textures/brick.png"));
+
+        let error = resolve_asset("textures/nothing.png", Some(&dir)).unwrap_err();
+        assert!(matches!(error, RayTracerError::Asset(_)), "{:?}", error);
+        let error = error.to_string();
+        assert!(error.contains(&dir.join("textures/nothing.png").display().to_string()), "{}", error);
+        assert!(error.contains("working directory") && error.contains("executable directory"), "{}", error);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::{HashMap, HashSet};
+use crate::cube::{Cube, FaceDescriptor};
+use crate::math::{to_f32, Float, Vec3};
+use crate::ray_intersect::{CubeFace, FaceOrientation};
+
+// Lado de un bloque en unidades de la escena
+pub const BLOCK_SIZE: Float = 0.5;
+
+// Forma de un bloque de la cuadrícula; los que no son cubos completos se dividen en varias cajas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockShape {
+    Full,
+    BottomSlab, // Media altura, apoyada en el suelo del bloque
+    TopSlab,    // Media altura, pegada al techo del bloque
+    Carpet,     // Lámina fina sobre el suelo
+    Fence,      // Poste central con brazos hacia los vecinos conectados
+    // Losa completa más un escalón hacia `facing`; al revés, la losa queda arriba y el escalón abajo
+    Stairs { facing: Facing, upside_down: bool },
+}
+
+// Orientación horizontal, con los nombres de los estados de bloque de Minecraft
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Facing {
+    North, // -z
+    East,  // +x
+    South, // +z
+    West,  // -x
+}
+
+impl std::str::FromStr for Facing {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "north" => Ok(Facing::North),
+            "east" => Ok(Facing::East),
+            "south" => Ok(Facing::South),
+            "west" => Ok(Facing::West),
+            _ => Err(format!("Unknown facing '{}' (expected north, east, south or west)", name)),
+        }
+    }
+}
+
+// Hacia dónde mira el frente (la cara Front, +z) de un bloque completo girado, como el estado
+// `facing` de los hornos. Arriba y abajo inclinan el frente hacia el techo o el suelo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North, // -z
+    East,  // +x
+    South, // +z, el bloque sin girar
+    West,  // -x
+    Up,    // +y
+    Down,  // -y
+}
+
+impl std::str::FromStr for Direction {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "north" => Ok(Direction::North),
+            "east" => Ok(Direction::East),
+            "south" => Ok(Direction::South),
+            "west" => Ok(Direction::West),
+            "up" => Ok(Direction::Up),
+            "down" => Ok(Direction::Down),
+            _ => Err(format!("Unknown facing '{}' (expected north, east, south, west, up or down)", name)),
+        }
+    }
+}
+
+impl Direction {
+    pub const ALL: [Direction; 6] = [Direction::North, Direction::East, Direction::South, Direction::West, Direction::Up, Direction::Down];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::North => "north",
+            Direction::East => "east",
+            Direction::South => "south",
+            Direction::West => "west",
+            Direction::Up => "up",
+            Direction::Down => "down",
+        }
+    }
+}
+
+// Eje a lo largo del que va la veta de un tronco, como el estado `axis` de Minecraft; sin girar es y
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl std::str::FromStr for Axis {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "x" => Ok(Axis::X),
+            "y" => Ok(Axis::Y),
+            "z" => Ok(Axis::Z),
+            _ => Err(format!("Unknown axis '{}' (expected x, y or z)", name)),
+        }
+    }
+}
+
+impl Axis {
+    pub const ALL: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Axis::X => "x",
+            Axis::Y => "y",
+            Axis::Z => "z",
+        }
+    }
+}
+
+// Giro de las texturas de un bloque completo: hacia dónde mira su frente o por dónde va su veta
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlockTurn {
+    Facing(Direction),
+    Axis(Axis),
+}
+
+impl BlockTurn {
+    pub fn all() -> impl Iterator<Item = BlockTurn> {
+        Direction::ALL.into_iter().map(BlockTurn::Facing).chain(Axis::ALL.into_iter().map(BlockTurn::Axis))
+    }
+
+    // Gira un vector del bloque sin girar al bloque girado
+    fn turn(&self, v: Vec3) -> Vec3 {
+        match self {
+            BlockTurn::Facing(Direction::South) | BlockTurn::Axis(Axis::Y) => v,
+            BlockTurn::Facing(Direction::North) => Vec3::new(-v.x, v.y, -v.z),
+            BlockTurn::Facing(Direction::East) => Vec3::new(v.z, v.y, -v.x),
+            BlockTurn::Facing(Direction::West) => Vec3::new(-v.z, v.y, v.x),
+            BlockTurn::Facing(Direction::Up) => Vec3::new(v.x, v.z, -v.y),
+            BlockTurn::Facing(Direction::Down) | BlockTurn::Axis(Axis::Z) => Vec3::new(v.x, -v.z, v.y),
+            BlockTurn::Axis(Axis::X) => Vec3::new(v.y, -v.x, v.z),
+        }
+    }
+
+    // Texturas de cada cara del bloque girado: la cara del bloque sin girar que cae en ella y el
+    // giro que lleva sus coordenadas de textura del mundo a las de esa cara
+    pub fn face_descriptors(&self) -> [FaceDescriptor; 6] {
+        let unit = |axis: usize| {
+            let mut vector = Vec3::zeros();
+            vector[axis] = 1.0;
+            vector
+        };
+        // Cuánto cambian u y v de la textura al avanzar una unidad en u o en v de la cara
+        let steps = |orientation: &FaceOrientation| {
+            let origin = orientation.apply((0.0, 0.0));
+            let (along_u, along_v) = (orientation.apply((1.0, 0.0)), orientation.apply((0.0, 1.0)));
+            [along_u.0 - origin.0, along_v.0 - origin.0, along_u.1 - origin.1, along_v.1 - origin.1]
+        };
+
+        let mut faces = Cube::unturned_faces();
+        for texture_face in CubeFace::ALL {
+            let face = CubeFace::from_normal(&self.turn(texture_face.normal()));
+            let (texture_u, texture_v) = texture_face.uv_axes();
+            let (turned_u, turned_v) = (self.turn(unit(texture_u)), self.turn(unit(texture_v)));
+            let (u, v) = face.uv_axes();
+            let wanted = [turned_u[u], turned_u[v], turned_v[u], turned_v[v]].map(to_f32);
+            let orientation = FaceOrientation::all()
+                .find(|orientation| steps(orientation) == wanted)
+                .expect("a quarter turn maps face axes onto face axes");
+            faces[face as usize] = FaceDescriptor { texture_face, orientation };
+        }
+        faces
+    }
+}
+
+// Vecinos horizontales en el orden -z, +x, +z, -x
+pub const NEIGHBOR_OFFSETS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+impl BlockShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockShape::Full => "full",
+            BlockShape::BottomSlab => "slab",
+            BlockShape::TopSlab => "top_slab",
+            BlockShape::Carpet => "carpet",
+            BlockShape::Fence => "fence",
+            BlockShape::Stairs { .. } => "stairs",
+        }
+    }
+
+    // Solo los bloques completos tapan del todo las caras de sus vecinos; detrás de una losa
+    // o una valla todavía se ve el bloque de al lado
+    pub fn is_full(&self) -> bool {
+        *self == BlockShape::Full
+    }
+
+    // Las vallas se unen a otras vallas y a bloques completos
+    pub fn connects_fence(&self) -> bool {
+        matches!(self, BlockShape::Full | BlockShape::Fence)
+    }
+
+    // Cajas (min, max) que forman el bloque, en coordenadas locales de 0 a 1.
+    // `connected` indica, para cada vecino de NEIGHBOR_OFFSETS, si una valla debe extender un brazo hacia él.
+    pub fn boxes(&self, connected: [bool; 4]) -> Vec<(Vec3, Vec3)> {
+        let unit = |x0: Float, y0: Float, z0: Float, x1: Float, y1: Float, z1: Float| {
+            (Vec3::new(x0, y0, z0) / 16.0, Vec3::new(x1, y1, z1) / 16.0)
+        };
+        match self {
+            BlockShape::Full => vec![unit(0.0, 0.0, 0.0, 16.0, 16.0, 16.0)],
+            BlockShape::BottomSlab => vec![unit(0.0, 0.0, 0.0, 16.0, 8.0, 16.0)],
+            BlockShape::TopSlab => vec![unit(0.0, 8.0, 0.0, 16.0, 16.0, 16.0)],
+            BlockShape::Carpet => vec![unit(0.0, 0.0, 0.0, 16.0, 1.0, 16.0)],
+            BlockShape::Fence => {
+                let mut boxes = vec![unit(6.0, 0.0, 6.0, 10.0, 16.0, 10.0)];
+                // Dos travesaños por brazo, como en Minecraft
+                for (y0, y1) in [(6.0, 9.0), (12.0, 15.0)] {
+                    if connected[0] { boxes.push(unit(7.0, y0, 0.0, 9.0, y1, 6.0)); }
+                    if connected[1] { boxes.push(unit(10.0, y0, 7.0, 16.0, y1, 9.0)); }
+                    if connected[2] { boxes.push(unit(7.0, y0, 10.0, 9.0, y1, 16.0)); }
+                    if connected[3] { boxes.push(unit(0.0, y0, 7.0, 6.0, y1, 9.0)); }
+                }
+                boxes
+            }
+            BlockShape::Stairs { facing, upside_down } => {
+                let (slab_y, step_y) = if *upside_down { ((8.0, 16.0), (0.0, 8.0)) } else { ((0.0, 8.0), (8.0, 16.0)) };
+                // El escalón ocupa la mitad del bloque del lado hacia el que mira
+                let (x0, x1, z0, z1) = match facing {
+                    Facing::North => (0.0, 16.0, 0.0, 8.0),
+                    Facing::East => (8.0, 16.0, 0.0, 16.0),
+                    Facing::South => (0.0, 16.0, 8.0, 16.0),
+                    Facing::West => (0.0, 8.0, 0.0, 16.0),
+                };
+                vec![
+                    unit(0.0, slab_y.0, 0.0, 16.0, slab_y.1, 16.0),
+                    unit(x0, step_y.0, z0, x1, step_y.1, z1),
+                ]
+            }
+        }
+    }
+
+    // Cajas en coordenadas de la escena para el bloque en la posición `pos` de la cuadrícula
+    pub fn world_boxes(&self, pos: [i32; 3], connected: [bool; 4]) -> Vec<(Vec3, Vec3)> {
+        let origin = Vec3::new(pos[0] as Float, pos[1] as Float, pos[2] as Float) * BLOCK_SIZE;
+        self.boxes(connected)
+            .into_iter()
+            .map(|(min, max)| (origin + min * BLOCK_SIZE, origin + max * BLOCK_SIZE))
+            .collect()
+    }
+}
+
+impl std::str::FromStr for BlockShape {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "full" => Ok(BlockShape::Full),
+            "slab" => Ok(BlockShape::BottomSlab),
+            "top_slab" => Ok(BlockShape::TopSlab),
+            "carpet" => Ok(BlockShape::Carpet),
+            "fence" => Ok(BlockShape::Fence),
+            "stairs" => Ok(BlockShape::Stairs { facing: Facing::North, upside_down: false }),
+            _ => Err(format!("Unknown block shape '{}' (expected full, slab, top_slab, carpet, fence or stairs)", name)),
+        }
+    }
+}
+
+// Une los bloques enteros vecinos con la misma clave en cajas lo más grandes posible: primero a lo
+// largo de x, luego la fila a lo largo de z y luego la capa hacia arriba. Devuelve la posición del
+// primer bloque, la del último más uno y la clave de cada caja, siempre en el mismo orden.
+pub fn merge_blocks<K: Copy + PartialEq>(blocks: &HashMap<[i32; 3], K>) -> Vec<([i32; 3], [i32; 3], K)> {
+    let mut positions: Vec<[i32; 3]> = blocks.keys().copied().collect();
+    positions.sort_by_key(|&[x, y, z]| (y, z, x));
+    let mut merged = HashSet::new();
+    let mut boxes = Vec::new();
+    for start in positions {
+        if merged.contains(&start) {
+            continue;
+        }
+        let key = blocks[&start];
+        let free = |pos: [i32; 3], merged: &HashSet<[i32; 3]>| blocks.get(&pos) == Some(&key) && !merged.contains(&pos);
+        let [x0, y0, z0] = start;
+        let mut x1 = x0 + 1;
+        while free([x1, y0, z0], &merged) {
+            x1 += 1;
+        }
+        let mut z1 = z0 + 1;
+        while (x0..x1).all(|x| free([x, y0, z1], &merged)) {
+            z1 += 1;
+        }
+        let mut y1 = y0 + 1;
+        while (x0..x1).all(|x| (z0..z1).all(|z| free([x, y1, z], &merged))) {
+            y1 += 1;
+        }
+        for x in x0..x1 {
+            for y in y0..y1 {
+                for z in z0..z1 {
+                    merged.insert([x, y, z]);
+                }
+            }
+        }
+        boxes.push((start, [x1, y1, z1], key));
+    }
+    boxes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Material;
+    use crate::ray_intersect::{Ray, RayIntersect};
+    use crate::texture::Texture;
+
+    const RED: Color = Color { r: 255, g: 0, b: 0 };
+    const GREEN: Color = Color { r: 0, g: 255, b: 0 };
+
+    // Corners of a face where the red and the green texels of a 2x2 marker texture show up, counted
+    // 0 top left, 1 top right, 2 bottom left and 3 bottom right
+    fn marker_corners(orientation: FaceOrientation) -> (usize, usize) {
+        let mut marker = Texture::checkerboard(2, 1, Color::new(0, 0, 0), Color::new(0, 0, 0));
+        marker.pixels[0] = RED;
+        marker.pixels[1] = GREEN;
+        let seen = [(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)].map(|uv| {
+            let (u, v) = orientation.apply(uv);
+            marker.sample(u, v)
+        });
+        let corner = |color: Color| seen.iter().position(|&texel| texel == color).expect("the marker is on the face");
+        (corner(RED), corner(GREEN))
+    }
+
+    #[test]
+    fn each_orientation_turns_the_marker_its_own_way() {
+        let corners: Vec<(usize, usize)> = FaceOrientation::all().map(marker_corners).collect();
+        // Quarter turns go clockwise; mirroring then swaps left and right
+        assert_eq!(corners, vec![(0, 1), (1, 3), (3, 2), (2, 0), (1, 0), (3, 1), (2, 3), (0, 2)]);
+    }
+
+    // Boxes in sixteenths of a block, which is how Minecraft models give them
+    fn sixteenths(boxes: Vec<(Vec3, Vec3)>) -> Vec<[Float; 6]> {
+        boxes.into_iter().map(|(min, max)| [min.x, min.y, min.z, max.x, max.y, max.z].map(|value| value * 16.0)).collect()
+    }
+
+    #[test]
+    fn shapes_are_made_of_their_boxes() {
+        assert_eq!(sixteenths(BlockShape::Full.boxes([true; 4])), vec![[0.0, 0.0, 0.0, 16.0, 16.0, 16.0]]);
+        assert_eq!(sixteenths(BlockShape::BottomSlab.boxes([false; 4])), vec![[0.0, 0.0, 0.0, 16.0, 8.0, 16.0]]);
+        assert_eq!(sixteenths(BlockShape::TopSlab.boxes([false; 4])), vec![[0.0, 8.0, 0.0, 16.0, 16.0, 16.0]]);
+        assert_eq!(sixteenths(BlockShape::Carpet.boxes([false; 4])), vec![[0.0, 0.0, 0.0, 16.0, 1.0, 16.0]]);
+        assert!(BlockShape::Full.is_full());
+        assert!(!BlockShape::BottomSlab.is_full() && !BlockShape::Fence.is_full());
+
+        // A lone fence is just its post; each connected neighbor adds two rails reaching the edge
+        // of the block on that side
+        let post = [6.0, 0.0, 6.0, 10.0, 16.0, 10.0];
+        assert_eq!(sixteenths(BlockShape::Fence.boxes([false; 4])), vec![post]);
+        assert_eq!(
+            sixteenths(BlockShape::Fence.boxes([false, true, false, false])),
+            vec![post, [10.0, 6.0, 7.0, 16.0, 9.0, 9.0], [10.0, 12.0, 7.0, 16.0, 15.0, 9.0]],
+        );
+        let all = sixteenths(BlockShape::Fence.boxes([true; 4]));
+        assert_eq!(all.len(), 1 + 4 * 2);
+        let reaching = |edge: fn(&[Float; 6]) -> bool| all.iter().filter(|rail| edge(rail)).count();
+        assert_eq!(reaching(|rail| rail[2] == 0.0), 2); // north, -z
+        assert_eq!(reaching(|rail| rail[3] == 16.0), 2); // east, +x
+        assert_eq!(reaching(|rail| rail[5] == 16.0), 2); // south, +z
+        assert_eq!(reaching(|rail| rail[0] == 0.0), 2); // west, -x
+    }
+
+    #[test]
+    fn stairs_step_up_toward_their_facing() {
+        let nearest = |boxes: &[(Vec3, Vec3)], origin: Vec3, direction: Vec3| {
+            let ray = Ray::new(origin, direction);
+            boxes.iter()
+                .filter_map(|&(min, max)| Cube::new(min, max, &Material::black()).ray_intersect(&ray))
+                .filter(|hit| hit.t > 0.0)
+                .min_by(|a, b| a.t.total_cmp(&b.t))
+                .expect("the ray hits the stairs")
+        };
+        let center = Vec3::new(0.25, 0.0, 0.25);
+        let facings = [(Facing::North, 0.0, -1.0), (Facing::East, 1.0, 0.0), (Facing::South, 0.0, 1.0), (Facing::West, -1.0, 0.0)];
+        for (facing, dx, dz) in facings {
+            let toward = Vec3::new(dx, 0.0, dz);
+            for upside_down in [false, true] {
+                let stairs = BlockShape::Stairs { facing, upside_down };
+                let boxes = stairs.world_boxes([0, 0, 0], [false; 4]);
+                assert_eq!(boxes.len(), 2, "{:?}", stairs);
+
+                // Seen from the side the slab covers, the step half sticks out and the other half
+                // stops at the slab
+                let (start, along, step_end, slab_end) = if upside_down { (-3.0, 1.0, 0.0, 0.25) } else { (3.0, -1.0, 0.5, 0.25) };
+                let down = Vec3::new(0.0, along, 0.0);
+                let on_step = nearest(&boxes, center + toward * 0.125 + Vec3::new(0.0, start, 0.0), down);
+                let off_step = nearest(&boxes, center - toward * 0.125 + Vec3::new(0.0, start, 0.0), down);
+                assert_eq!((on_step.point.y, off_step.point.y), (step_end, slab_end), "{:?}", stairs);
+
+                // The riser in the middle of the block faces away from `facing`, with its texture
+                // upright like the sides of a full block
+                let height = if upside_down { 0.125 } else { 0.375 };
+                let riser = nearest(&boxes, center - toward * 3.0 + Vec3::new(0.0, height, 0.0), toward);
+                assert!((riser.point - Vec3::new(0.25, height, 0.25)).magnitude() < 1e-4, "{:?} {:?}", stairs, riser.point);
+                assert!((riser.normal + toward).magnitude() < 1e-4, "{:?}", stairs);
+                assert_eq!(riser.texture_face, riser.face);
+                assert!((riser.uv.1 - to_f32(height)).abs() < 1e-4, "{:?} {:?}", stairs, riser.uv);
+            }
+        }
+    }
+
+    #[test]
+    fn turns_give_every_face_texture_one_place() {
+        for turn in BlockTurn::all() {
+            let faces = turn.face_descriptors();
+            for face in CubeFace::ALL {
+                assert_eq!(faces.iter().filter(|descriptor| descriptor.texture_face == face).count(), 1, "{:?} {:?}", turn, face);
+            }
+        }
+        assert_eq!(BlockTurn::Facing(Direction::South).face_descriptors(), Cube::unturned_faces());
+        assert_eq!(BlockTurn::Axis(Axis::Y).face_descriptors(), Cube::unturned_faces());
+    }
+
+    #[test]
+    fn turned_blocks_show_their_textures_where_they_turned() {
+        let block = |turn: BlockTurn| {
+            Cube::new(Vec3::zeros(), Vec3::new(1.0, 1.0, 1.0), &Material::black()).with_faces(turn.face_descriptors())
+        };
+        let from_east = |cube: &Cube| cube.ray_intersect(&Ray::new(Vec3::new(3.0, 0.5, 0.5), Vec3::new(-1.0, 0.0, 0.0))).unwrap();
+        let from_above = |cube: &Cube, x: Float, z: Float| {
+            cube.ray_intersect(&Ray::new(Vec3::new(x, 3.0, z), Vec3::new(0.0, -1.0, 0.0))).unwrap()
+        };
+
+        // A furnace facing east shows its front there and keeps its top
+        let furnace = block(BlockTurn::Facing(Direction::East));
+        assert_eq!(from_east(&furnace).texture_face, CubeFace::Front);
+        assert_eq!(from_above(&furnace, 0.5, 0.5).texture_face, CubeFace::Top);
+
+        // A log along x has its end grain to the east and bark on top, with the grain (the
+        // texture's v, up the side of a standing log) running along x
+        let log = block(BlockTurn::Axis(Axis::X));
+        assert_eq!(from_east(&log).texture_face, CubeFace::Top);
+        let (a, b, c) = (from_above(&log, 0.2, 0.3), from_above(&log, 0.6, 0.3), from_above(&log, 0.2, 0.7));
+        assert!(matches!(a.texture_face, CubeFace::Left | CubeFace::Right | CubeFace::Front | CubeFace::Back));
+        assert_eq!(a.uv.0, b.uv.0);
+        assert_ne!(a.uv.1, b.uv.1);
+        assert_ne!(a.uv.0, c.uv.0);
+    }
+}
@@ -1,10 +1,92 @@
-use nalgebra_glm::Vec3;
-use std::f32::consts::PI;
+use crate::cubemap::CubemapFace;
+use serde::{Deserialize, Serialize};
+use crate::math::{Float, Vec3};
+use crate::math::consts::PI;
+use crate::settings::Convergence;
 
+// How pixels map to rays
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    Perspective,
+    // Equirectangular 360° image: longitude across the width (the view direction in the middle)
+    // and latitude down the height, always level with the world's up vector
+    Panorama,
+    // Two panoramas stacked top (left eye) and bottom (right eye); each eye is offset sideways
+    // from the column's direction, so depth reads correctly all the way around
+    StereoPanorama { eye_separation: Float },
+    // One square 90° face of a cubemap from the eye; the center and roll are ignored
+    CubemapFace(CubemapFace),
+    // Equidistant fisheye: the angle from the view direction grows linearly with the distance from the
+    // image center, reaching fov / 2 at the edge of a circle that fits the shorter side. Pixels outside
+    // the circle see nothing.
+    Fisheye { fov: Float },
+    // Panini: a rectilinear projection of the view onto a cylinder seen from `d` radii behind its axis.
+    // d = 0 is plain perspective and d = 1 keeps verticals and radial lines straight at wide angles.
+    // `fov` is horizontal.
+    Panini { fov: Float, d: Float },
+}
+
+impl Projection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Perspective => "perspective",
+            Projection::Panorama => "panorama",
+            Projection::StereoPanorama { .. } => "stereo_panorama",
+            Projection::CubemapFace(_) => "cubemap",
+            Projection::Fisheye { .. } => "fisheye",
+            Projection::Panini { .. } => "panini",
+        }
+    }
+
+    // Image height the projection needs for a given width: 2:1 per eye for panoramas, square cubemap faces
+    pub fn fixed_height(&self, width: usize) -> Option<usize> {
+        match self {
+            Projection::Perspective | Projection::Fisheye { .. } | Projection::Panini { .. } => None,
+            Projection::Panorama => Some(width / 2),
+            Projection::StereoPanorama { .. } | Projection::CubemapFace(_) => Some(width),
+        }
+    }
+}
+
+// Default eye separation for stereo panoramas: 6.5 cm, with a 0.5 unit block being one meter
+pub const DEFAULT_EYE_SEPARATION: Float = 0.0325;
+
+impl std::str::FromStr for Projection {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "perspective" => Ok(Projection::Perspective),
+            "panorama" => Ok(Projection::Panorama),
+            "stereo_panorama" => Ok(Projection::StereoPanorama { eye_separation: DEFAULT_EYE_SEPARATION }),
+            "fisheye" => Ok(Projection::Fisheye { fov: PI }),
+            "panini" => Ok(Projection::Panini { fov: PI * 5.0 / 6.0, d: 1.0 }),
+            _ => Err(format!(
+                "Unknown projection '{}' (expected perspective, panorama, stereo_panorama, fisheye or panini)",
+                name
+            )),
+        }
+    }
+}
+
+// Eye and center at the end of the frame, for motion blur; in between the camera moves in a straight line
+#[derive(Clone, Copy, Debug)]
+pub struct CameraMotion {
+    pub eye: Vec3,
+    pub center: Vec3,
+}
+
+#[derive(Clone)]
 pub struct Camera {
     pub eye: Vec3,    // Camera position in world space
     pub center: Vec3, // Point the camera is looking at
     pub up: Vec3,     // Up vector
+    pub fov: Float,   // Vertical field of view in radians
+    pub roll: Float,  // Rotation around the view direction in radians, positive rolls to the left
+    pub projection: Projection,
+    pub shift: Float, // Horizontal image shift for off-axis stereo, in the same units as the tangent of the fov
+    pub motion: Option<CameraMotion>,
 }
 
 impl Camera {
@@ -13,14 +95,33 @@ impl Camera {
             eye,
             center,
             up,
+            fov: PI / 3.0,
+            roll: 0.0,
+            projection: Projection::Perspective,
+            shift: 0.0,
+            motion: None,
         }
     }
 
-    pub fn basis_change(&self, vector: &Vec3) -> Vec3 {
+    // Vista con la que se abre la ventana (y la API de C) si la escena no trae cámara
+    pub fn default_view() -> Self {
+        Camera::new(Vec3::new(0.0, 0.0, 6.5), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    // Forward, right and up axes of the view, including roll
+    fn axes(&self) -> (Vec3, Vec3, Vec3) {
         let forward = (self.center - self.eye).normalize();
         let right = forward.cross(&self.up).normalize();
         let up = right.cross(&forward).normalize();
 
+        // Roll is applied last, around the forward axis, so it composes with orbit and pitch
+        let (sin, cos) = self.roll.sin_cos();
+        (forward, right * cos + up * sin, up * cos - right * sin)
+    }
+
+    pub fn basis_change(&self, vector: &Vec3) -> Vec3 {
+        let (forward, right, up) = self.axes();
+
         let rotated = 
         vector.x * right +
         vector.y * up -
@@ -29,22 +130,173 @@ impl Camera {
         rotated.normalize()
     }
 
-    pub fn orbit(&mut self, delta_yaw: f32, delta_pitch: f32) {
-        // Calculate the vector from the center to the eye (radius vector) and measure the distance
-        let radius_vector = self.eye - self.center;
-        let radius = radius_vector.magnitude();
+    // World space direction of the ray through pixel coordinates (x, y) of a width x height image
+    pub fn ray_direction(&self, x: Float, y: Float, width: Float, height: Float) -> Vec3 {
+        let perspective_scale = (self.fov * 0.5).tan();
+        let screen_x = ((2.0 * x) / width - 1.0) * (width / height) * perspective_scale + self.shift;
+        let screen_y = (-(2.0 * y) / height + 1.0) * perspective_scale;
+        self.basis_change(&Vec3::new(screen_x, screen_y, -1.0).normalize())
+    }
+
+    // Pixel coordinates where a world point appears in a width x height perspective image, the inverse
+    // of ray_direction. None for points behind the camera and for the other projections.
+    pub fn project(&self, point: &Vec3, width: Float, height: Float) -> Option<(Float, Float)> {
+        if self.projection != Projection::Perspective {
+            return None;
+        }
+        let (forward, right, up) = self.axes();
+        let relative = point - self.eye;
+        let depth = relative.dot(&forward);
+        if depth <= 0.0 {
+            return None;
+        }
+        let perspective_scale = (self.fov * 0.5).tan();
+        let screen_x = relative.dot(&right) / depth;
+        let screen_y = relative.dot(&up) / depth;
+        let x = ((screen_x - self.shift) / (perspective_scale * width / height) + 1.0) * width * 0.5;
+        let y = (1.0 - screen_y / perspective_scale) * height * 0.5;
+        Some((x, y))
+    }
+
+    // Camera for one eye of a stereo pair, moved `offset` along the view's right axis (negative for the
+    // left eye). Both eyes converge on the orbit center unless `convergence` keeps them parallel.
+    pub fn stereo_eye(&self, offset: Float, convergence: Convergence) -> Camera {
+        let (_, right, _) = self.axes();
+        let distance = (self.center - self.eye).magnitude();
+        let mut eye = self.clone();
+        eye.eye += right * offset;
+        match convergence {
+            Convergence::Parallel => eye.center += right * offset,
+            Convergence::Shift => {
+                eye.center += right * offset;
+                // A point on the view axis at the orbit center lands in the middle of both images
+                eye.shift = -offset / distance;
+            }
+            Convergence::ToeIn => {}
+        }
+        // El ojo sigue el mismo recorrido que la cámara durante el frame
+        if let Some(motion) = &mut eye.motion {
+            motion.eye += eye.eye - self.eye;
+            motion.center += eye.center - self.center;
+        }
+        eye
+    }
+
+    // Camera at `time` through the frame, from 0 (start) to 1 (the position in `motion`)
+    pub fn at_time(&self, time: Float) -> Camera {
+        let mut camera = self.clone();
+        if let Some(motion) = &self.motion {
+            camera.eye += (motion.eye - self.eye) * time;
+            camera.center += (motion.center - self.center) * time;
+        }
+        camera
+    }
+
+    // Origin and direction of the ray through pixel coordinates (x, y) for the camera's projection,
+    // or None where the projection doesn't cover the image (outside a fisheye's circle)
+    pub fn ray(&self, x: Float, y: Float, width: Float, height: Float) -> Option<(Vec3, Vec3)> {
+        let ray = match self.projection {
+            Projection::Perspective => (self.eye, self.ray_direction(x, y, width, height)),
+            Projection::Panorama => (self.eye, self.panorama_direction(x / width, y / height).0),
+            Projection::StereoPanorama { eye_separation } => {
+                let half = height * 0.5;
+                let (v, side) = if y < half { (y / half, -0.5) } else { ((y - half) / half, 0.5) };
+                let (direction, right) = self.panorama_direction(x / width, v);
+                (self.eye + right * (eye_separation * side), direction)
+            }
+            Projection::CubemapFace(face) => {
+                let direction = face.direction(2.0 * x / width - 1.0, 2.0 * y / height - 1.0);
+                (self.eye, direction.normalize())
+            }
+            Projection::Fisheye { fov } => {
+                let size = width.min(height);
+                let screen_x = (2.0 * x - width) / size;
+                let screen_y = (height - 2.0 * y) / size;
+                let radius = (screen_x * screen_x + screen_y * screen_y).sqrt();
+                if radius > 1.0 {
+                    return None;
+                }
+                let angle = radius * fov * 0.5;
+                // En el centro exacto la dirección es la de la vista
+                let (sin, cos) = angle.sin_cos();
+                let scale = if radius > 0.0 { sin / radius } else { 0.0 };
+                (self.eye, self.basis_change(&Vec3::new(screen_x * scale, screen_y * scale, -cos)))
+            }
+            Projection::Panini { fov, d } => {
+                // Coordenadas en el plano de la imagen, escaladas para que los bordes izquierdo y derecho
+                // caigan en ±fov / 2
+                let half_fov = fov * 0.5;
+                let edge = (d + 1.0) / (d + half_fov.cos()) * half_fov.sin();
+                let plane_x = (2.0 * x / width - 1.0) * edge;
+                let plane_y = (1.0 - 2.0 * y / height) * edge * height / width;
+
+                // Inversa de x = S sin(lon) con S = (d + 1) / (d + cos(lon))
+                let k = plane_x * plane_x / ((d + 1.0) * (d + 1.0));
+                let discriminant = k * k * d * d - (k + 1.0) * (k * d * d - 1.0);
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let cos_lon = (-k * d + discriminant.sqrt()) / (k + 1.0);
+                let s = (d + 1.0) / (d + cos_lon);
+                let lon = plane_x.atan2(s * cos_lon);
+                (self.eye, self.basis_change(&Vec3::new(lon.sin(), plane_y / s, -lon.cos())))
+            }
+        };
+        Some(ray)
+    }
+
+    // Direction for normalized panorama coordinates (u, v) in [0, 1], plus the horizontal right vector
+    // of its column. The frame is built from the horizontal part of the view direction, so looking
+    // straight up or down still gives a valid frame and the poles are just the up and down vectors.
+    fn panorama_direction(&self, u: Float, v: Float) -> (Vec3, Vec3) {
+        let up = self.up.normalize();
+        let view = self.center - self.eye;
+        let mut forward = view - up * view.dot(&up);
+        if forward.magnitude() < 1e-6 {
+            // Mirando en vertical: cualquier dirección horizontal sirve como centro
+            forward = up.cross(&Vec3::new(1.0, 0.0, 0.0));
+            if forward.magnitude() < 1e-6 {
+                forward = up.cross(&Vec3::new(0.0, 0.0, 1.0));
+            }
+        }
+        let forward = forward.normalize();
+        let right = forward.cross(&up);
+
+        // u = 0 and u = 1 both land directly behind the camera, so the image wraps without a seam
+        let longitude = (u - 0.5) * 2.0 * PI;
+        let latitude = (0.5 - v) * PI;
+        let (sin_lon, cos_lon) = longitude.sin_cos();
+        let column = forward * cos_lon + right * sin_lon;
+        let column_right = right * cos_lon - forward * sin_lon;
+        (column * latitude.cos() + up * latitude.sin(), column_right)
+    }
+
+    // Moves eye and center together along the view's right and up axes, so the orbit pivot follows
+    pub fn pan(&mut self, delta_right: Float, delta_up: Float) {
+        let (_, right, up) = self.axes();
+        let offset = right * delta_right + up * delta_up;
+        self.eye += offset;
+        self.center += offset;
+    }
+
+    // Centers the orbit on a box and moves the eye along the current view direction until the box fits
+    pub fn frame(&mut self, min: &Vec3, max: &Vec3) {
+        let (forward, _, _) = self.axes();
+        let target = (min + max) * 0.5;
+        let radius = (max - min).magnitude() * 0.5;
+        self.center = target;
+        self.eye = target - forward * (radius / (self.fov * 0.5).sin());
+    }
 
-        // Calculate current yaw (rotation around Y-axis)
-        // atan2(z, x) gives us the angle in the XZ plane
-        // Range: [-π, π], where 0 is along positive X-axis, π/2 is along positive Z-axis
-        let current_yaw = radius_vector.z.atan2(radius_vector.x);
+    // Scales the distance to the orbit pivot by `factor`, keeping it within [min_distance, max_distance]
+    pub fn dolly(&mut self, factor: Float, min_distance: Float, max_distance: Float) {
+        let offset = self.eye - self.center;
+        let distance = (offset.magnitude() * factor).clamp(min_distance, max_distance);
+        self.eye = self.center + offset.normalize() * distance;
+    }
 
-        // Calculate current pitch (rotation around X-axis)
-        // xz here refers to the proyection of the radius over the x axis
-        let radius_xz = (radius_vector.x * radius_vector.x + radius_vector.z * radius_vector.z).sqrt();
-        // We use -y because positive pitch is when we look up (negative y in our coordinate system)
-        // Range: [-π/2, π/2], where 0 is horizontal, π/2 is looking straight up
-        let current_pitch = (-radius_vector.y).atan2(radius_xz);
+    pub fn orbit(&mut self, delta_yaw: Float, delta_pitch: Float) {
+        let (current_yaw, current_pitch, radius) = to_spherical(&(self.eye - self.center));
 
         // Apply delta rotations
         // Keep yaw in range [0, 2π] for consistency
@@ -52,18 +304,212 @@ impl Camera {
         // Clamp pitch to slightly less than [-π/2, π/2] to prevent gimbal lock
         let new_pitch = (current_pitch + delta_pitch).clamp(-PI / 2.0 + 0.1, PI / 2.0 - 0.1);
 
-        // Calculate new eye position
-        // We use spherical coordinates to cartesian conversion:
-        // x = r * cos(yaw) * cos(pitch)
-        // y = -r * sin(pitch)  // Negative because positive y is up
-        // z = r * sin(yaw) * cos(pitch)
+        self.eye = self.center + from_spherical(new_yaw, new_pitch, radius);
+    }
+
+    // Moves a fraction `amount` (0 to 1) of the way toward `target`. The eye follows the orbit's arc
+    // around the center, and the distance changes by a constant ratio per step like the wheel dolly.
+    pub fn ease_toward(&mut self, target: &Camera, amount: Float) {
+        let (yaw, pitch, radius) = to_spherical(&(self.eye - self.center));
+        let (target_yaw, target_pitch, target_radius) = to_spherical(&(target.eye - target.center));
+
+        // Turn the short way around
+        let mut delta_yaw = (target_yaw - yaw) % (2.0 * PI);
+        if delta_yaw > PI {
+            delta_yaw -= 2.0 * PI;
+        } else if delta_yaw < -PI {
+            delta_yaw += 2.0 * PI;
+        }
+
+        self.center += (target.center - self.center) * amount;
+        if radius < 1e-6 || target_radius < 1e-6 {
+            // With the eye on the center there is no orbit to follow, so it goes in a straight line
+            self.eye += (target.eye - self.eye) * amount;
+        } else {
+            self.eye = self.center + from_spherical(
+                yaw + delta_yaw * amount,
+                pitch + (target_pitch - pitch) * amount,
+                radius * (target_radius / radius).powf(amount),
+            );
+        }
+        self.roll += (target.roll - self.roll) * amount;
+        self.fov += (target.fov - self.fov) * amount;
+        self.up = target.up;
+        self.projection = target.projection;
+    }
+
+    // Whether both cameras produce the same image up to `epsilon` in position and angle
+    pub fn is_near(&self, other: &Camera, epsilon: Float) -> bool {
+        self.projection == other.projection
+            && (self.eye - other.eye).magnitude() < epsilon
+            && (self.center - other.center).magnitude() < epsilon
+            && (self.roll - other.roll).abs() < epsilon
+            && (self.fov - other.fov).abs() < epsilon
+    }
+}
+
+// Yaw, pitch and radius of a vector from the orbit center to the eye
+fn to_spherical(radius_vector: &Vec3) -> (Float, Float, Float) {
+    let radius = radius_vector.magnitude();
 
-        let new_eye = self.center + Vec3::new(
-            radius * new_yaw.cos() * new_pitch.cos(),
-            -radius * new_pitch.sin(),
-            radius * new_yaw.sin() * new_pitch.cos()
-        );
+    // Calculate current yaw (rotation around Y-axis)
+    // atan2(z, x) gives us the angle in the XZ plane
+    // Range: [-π, π], where 0 is along positive X-axis, π/2 is along positive Z-axis
+    let yaw = radius_vector.z.atan2(radius_vector.x);
 
-        self.eye = new_eye;
+    // Calculate current pitch (rotation around X-axis)
+    // xz here refers to the proyection of the radius over the x axis
+    let radius_xz = (radius_vector.x * radius_vector.x + radius_vector.z * radius_vector.z).sqrt();
+    // We use -y because positive pitch is when we look up (negative y in our coordinate system)
+    // Range: [-π/2, π/2], where 0 is horizontal, π/2 is looking straight up
+    let pitch = (-radius_vector.y).atan2(radius_xz);
+
+    (yaw, pitch, radius)
+}
+
+// We use spherical coordinates to cartesian conversion:
+// x = r * cos(yaw) * cos(pitch)
+// y = -r * sin(pitch)  // Negative because positive y is up
+// z = r * sin(yaw) * cos(pitch)
+fn from_spherical(yaw: Float, pitch: Float, radius: Float) -> Vec3 {
+    Vec3::new(
+        radius * yaw.cos() * pitch.cos(),
+        -radius * pitch.sin(),
+        radius * yaw.sin() * pitch.cos()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Looking down -z from the origin, so camera space and world space are the same
+    fn looking_forward(projection: Projection) -> Camera {
+        Camera { projection, ..Camera::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0)) }
     }
-}
\ No newline at end of file
+
+    fn direction(camera: &Camera, x: Float, y: Float, width: Float, height: Float) -> Vec3 {
+        let (_, direction) = camera.ray(x, y, width, height).expect("the pixel is covered");
+        assert!(direction.iter().all(|value| value.is_finite()), "{:?} at ({}, {})", camera.projection, x, y);
+        assert!((direction.magnitude() - 1.0).abs() < 1e-4, "{:?} at ({}, {})", camera.projection, x, y);
+        direction
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn panorama_poles_are_the_up_and_down_vectors() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        // Also looking straight up, where the view has no horizontal part to center the image on
+        let straight_up = Camera { center: Vec3::new(0.0, 5.0, 0.0), ..looking_forward(Projection::Panorama) };
+        for camera in [looking_forward(Projection::Panorama), straight_up] {
+            for x in [0.0, 90.0, 179.5, 360.0] {
+                assert_close(direction(&camera, x, 0.0, 360.0, 180.0), up);
+                assert_close(direction(&camera, x, 180.0, 360.0, 180.0), -up);
+            }
+        }
+        assert_close(direction(&looking_forward(Projection::Panorama), 180.0, 90.0, 360.0, 180.0), Vec3::new(0.0, 0.0, -1.0));
+
+        // Each eye of a stereo panorama has its own poles, in its half of the image
+        let stereo = looking_forward(Projection::StereoPanorama { eye_separation: 0.065 });
+        for y in [0.0, 180.0] {
+            assert_close(direction(&stereo, 45.0, y, 360.0, 360.0), up);
+            assert_close(direction(&stereo, 45.0, y + 179.999, 360.0, 360.0), -up);
+        }
+    }
+
+    #[test]
+    fn panoramas_wrap_without_a_seam_behind_the_camera() {
+        let behind = Vec3::new(0.0, 0.0, 1.0);
+        for projection in [Projection::Panorama, Projection::StereoPanorama { eye_separation: 0.065 }] {
+            let camera = looking_forward(projection);
+            for y in [45.0, 90.0, 270.0] {
+                // The left and right edges both look straight back, from the same eye
+                let (left_origin, left) = camera.ray(0.0, y, 360.0, 360.0).unwrap();
+                let (right_origin, right) = camera.ray(360.0, y, 360.0, 360.0).unwrap();
+                assert_close(left_origin, right_origin);
+                assert_close(left, right);
+                assert!(left.dot(&behind) > 0.0);
+
+                // Stepping across the edge turns as far as stepping between any two columns
+                let across = (direction(&camera, 359.5, y, 360.0, 360.0) - direction(&camera, 0.5, y, 360.0, 360.0)).magnitude();
+                let within = (direction(&camera, 1.5, y, 360.0, 360.0) - direction(&camera, 0.5, y, 360.0, 360.0)).magnitude();
+                assert!((across - within).abs() < 1e-4, "{:?}: {} across the edge, {} elsewhere", projection, across, within);
+            }
+        }
+    }
+
+    #[test]
+    fn fisheye_angle_grows_with_the_distance_from_the_center() {
+        let camera = looking_forward(Projection::Fisheye { fov: PI });
+        assert_close(direction(&camera, 50.0, 50.0, 100.0, 100.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_close(direction(&camera, 50.0, 0.0, 100.0, 100.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_close(direction(&camera, 100.0, 50.0, 100.0, 100.0), Vec3::new(1.0, 0.0, 0.0));
+        let halfway = direction(&camera, 75.0, 50.0, 100.0, 100.0);
+        assert!((halfway.x.atan2(-halfway.z) - PI / 4.0).abs() < 1e-4);
+        assert!(camera.ray(0.0, 0.0, 100.0, 100.0).is_none(), "the corner is outside the circle");
+
+        // A full 360° fisheye sees straight back all around its rim
+        let full = looking_forward(Projection::Fisheye { fov: 2.0 * PI });
+        for (x, y) in [(50.0, 0.0), (0.0, 50.0), (100.0, 50.0), (50.0, 100.0)] {
+            assert_close(direction(&full, x, y, 100.0, 100.0), Vec3::new(0.0, 0.0, 1.0));
+        }
+    }
+
+    // Where a direction (in camera space) lands in a width x height Panini image: longitude is
+    // projected onto the cylinder from `d` radii behind its axis and height is scaled along with it
+    fn panini_pixel(direction: Vec3, fov: Float, d: Float, width: Float, height: Float) -> (Float, Float) {
+        let half_fov = fov * 0.5;
+        let edge = (d + 1.0) / (d + half_fov.cos()) * half_fov.sin();
+        let longitude = direction.x.atan2(-direction.z);
+        let horizontal = (direction.x * direction.x + direction.z * direction.z).sqrt();
+        let s = (d + 1.0) / (d + longitude.cos());
+        let (plane_x, plane_y) = (s * longitude.sin(), s * direction.y / horizontal);
+        ((plane_x / edge + 1.0) * width * 0.5, (1.0 - plane_y / (edge * height / width)) * height * 0.5)
+    }
+
+    #[test]
+    fn panini_rays_project_back_to_their_pixels() {
+        for d in [0.0, 0.5, 1.0, 2.0] {
+            for fov in [PI / 2.0, PI * 5.0 / 6.0] {
+                let camera = looking_forward(Projection::Panini { fov, d });
+                for x in [0.0, 13.0, 100.0, 160.0, 200.0] {
+                    for y in [0.0, 40.0, 75.0, 150.0] {
+                        let (px, py) = panini_pixel(direction(&camera, x, y, 200.0, 150.0), fov, d, 200.0, 150.0);
+                        assert!((px - x).abs() < 1e-2 && (py - y).abs() < 1e-2, "d = {}, fov = {}: ({}, {}) came back as ({}, {})", d, fov, x, y, px, py);
+                    }
+                }
+
+                // The left and right edges are fov / 2 to either side
+                let edge = direction(&camera, 200.0, 75.0, 200.0, 150.0);
+                assert!((edge.x.atan2(-edge.z) - fov * 0.5).abs() < 1e-4, "d = {}, fov = {}", d, fov);
+            }
+        }
+    }
+
+    #[test]
+    fn easing_from_or_to_a_zero_radius_stays_finite() {
+        let on_center = Camera::new(Vec3::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, 3.0), Vec3::new(0.0, 1.0, 0.0));
+        let orbiting = Camera::new(Vec3::new(0.0, 1.0, 6.5), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        for (from, to) in [(&on_center, &orbiting), (&orbiting, &on_center)] {
+            let mut camera = from.clone();
+            camera.ease_toward(to, 0.5);
+            assert!(camera.eye.iter().all(|value| value.is_finite()), "{:?}", camera.eye);
+            assert_close(camera.eye, (from.eye + to.eye) * 0.5);
+            camera.ease_toward(to, 1.0);
+            assert!(camera.is_near(to, 1e-4));
+        }
+    }
+
+    #[test]
+    fn cameras_with_other_projections_are_not_near() {
+        let camera = Camera::default_view();
+        assert!(camera.is_near(&camera.clone(), 1e-6));
+        let panorama = Camera { projection: Projection::Panorama, ..camera.clone() };
+        assert!(!camera.is_near(&panorama, 1e-6));
+        let fisheye = Camera { projection: Projection::Fisheye { fov: PI }, ..camera.clone() };
+        assert!(!fisheye.is_near(&Camera { projection: Projection::Fisheye { fov: PI / 2.0 }, ..camera }, 1e-6));
+    }
+}
@@ -0,0 +1,241 @@
+// Los subcomandos del ejecutable y su ayuda. Cada uno tiene sus propias opciones; las comunes
+// (escena, recursos, hilos, cielo y ajustes de render) valen para todos salvo diff e info.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    View,
+    Render,
+    Bench,
+    Convert,
+    Export,
+    Serve,
+    Diff,
+    Info,
+}
+
+pub const COMMANDS: [Command; 8] = [
+    Command::View,
+    Command::Render,
+    Command::Bench,
+    Command::Convert,
+    Command::Export,
+    Command::Serve,
+    Command::Diff,
+    Command::Info,
+];
+
+impl Command {
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::View => "view",
+            Command::Render => "render",
+            Command::Bench => "bench",
+            Command::Convert => "convert",
+            Command::Export => "export",
+            Command::Serve => "serve",
+            Command::Diff => "diff",
+            Command::Info => "info",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        COMMANDS.into_iter().find(|command| command.name() == name)
+    }
+
+    pub fn usage(self) -> &'static str {
+        match self {
+            Command::View => "view [options]",
+            Command::Render => "render <job file> [options]",
+            Command::Bench => "bench [options]",
+            Command::Convert => "convert <scene> <output.world> [options]",
+            Command::Export => "export <output.obj|output.glb> [options]",
+            Command::Serve => "serve <port> [options]",
+            Command::Diff => "diff <image> <image> [--threshold <0-1>] [--out <heatmap.png>]",
+            Command::Info => "info <image.png>",
+        }
+    }
+
+    fn summary(self) -> &'static str {
+        match self {
+            Command::View => "Open the interactive window (the default without a command)",
+            Command::Render => "Render the shots of a job file without a window",
+            Command::Bench => "Time repeated renders of a scene from its camera",
+            Command::Convert => "Save a block scene as a binary .world file",
+            Command::Export => "Write a scene's visible geometry as OBJ + MTL or binary glTF",
+            Command::Serve => "Serve renders of a scene over HTTP",
+            Command::Diff => "Compare two images and optionally write a heatmap",
+            Command::Info => "Print the render metadata stored in a PNG",
+        }
+    }
+
+    fn options(self) -> &'static str {
+        match self {
+            Command::View => "\
+  --light-gizmos               Start with the light markers shown (H toggles them)
+  --mouse-sensitivity <deg>    Degrees turned per pixel dragged
+  --zoom-step <factor>         Distance factor per mouse wheel notch
+  --camera-smoothing <s>       Time constant the view follows the controls with, 0 for none
+  --target-frame-time <ms>     Frame time the resolution adapts to while moving, 0 for none
+  --min-render-scale <scale>   Lowest adaptive resolution scale
+  --max-render-scale <scale>   Highest adaptive resolution scale
+  --stream-radius <blocks>     Load a .world scene in chunks around the camera, 0 for all of it
+  --minimap-size <px>          Side of the top-down map M shows
+  --minimap-corner <corner>    top_left, top_right, bottom_left or bottom_right
+  --minimap-zoom <px>          Map pixels per block
+  --inset-camera <slot>        Show camera preset 1-9 in an inset (Y swaps it with the main view)
+  --inset-size <fraction>      Fraction of the window the inset takes
+  --inset-aspect <ratio>       Width / height of the inset's image, letterboxed to fit
+  --inset-corner <corner>      top_left, top_right, bottom_left or bottom_right
+  --undo-depth <n>             Block edits Ctrl+Z can undo
+  --palette <a,b,...>          Materials R and Shift+R cycle through, the whole registry by default
+  --keybindings <file>         TOML file of `action = "key"` lines that rebinds the movement keys
+",
+            Command::Render => "\
+  --panorama                  Render every shot as a 360° equirectangular panorama
+  --stereo-panorama           Render every shot as a stereo panorama (left eye on top)
+  --light-gizmos              Mark the lights' positions in every shot
+  --alpha <mode>              straight or premultiplied: save RGBA PNGs with the sky transparent
+  --overlay <image>           Composite a logo (PNG with alpha) in a corner of every shot
+  --caption <text>            Write a line of text in the same corner, under the logo
+  --overlay-corner <corner>   top_left, top_right, bottom_left or bottom_right (the default)
+  --overlay-offset <px>       Distance from the corner's two edges (16 by default)
+  --overlay-opacity <0-1>     Opacity of the logo and the caption (1 by default)
+",
+            Command::Bench => "\
+  --frames <n>    Timed renders after one warm-up render (5 by default)
+",
+            Command::Convert => "\
+  Only block scene files (.toml) can be read for now; .vox, .schem and heightmaps can't.
+",
+            Command::Export => "\
+  --format <obj|gltf>    Output format (by default from the extension: .obj, or .glb/.gltf)
+",
+            Command::Serve => "\
+  --bind <address>    Address to listen on, 127.0.0.1 by default (0.0.0.0 for every interface)
+  --light-gizmos      Mark the lights' positions in every render
+",
+            Command::Diff => "\
+  --threshold <0-1>     Largest channel difference allowed (0 by default, any change fails)
+  --out <heatmap.png>   Write the differences over the first image in dim gray
+",
+            Command::Info => "",
+        }
+    }
+
+    // diff e info solo leen imágenes: no cargan configuración, escena ni recursos
+    pub fn takes_global_options(self) -> bool {
+        !matches!(self, Command::Diff | Command::Info)
+    }
+}
+
+const GLOBAL_OPTIONS: &str = "\
+  --scene <path>            Scene file (.toml or .world), the built-in demo by default
+  --config <path>           Settings file instead of raytracer.toml
+  --print-config            Print the effective settings and where each came from, then exit
+  --assets <dir>            Directory searched first for scenes, textures and environment maps
+  --resource-pack <zip>     Take the block textures from a Minecraft resource pack
+  --threads <n>             Render threads, 0 for one per core
+  --strict                  Fail when the scene has warnings
+  --strict-assets           Fail when a texture is missing instead of drawing a checkerboard
+  --hdri <file>             Equirectangular environment map instead of the procedural sky
+  --cubemap <file>          Cubemap cross environment map instead of the procedural sky
+  --hdri-rotation <deg>     Turn the environment map around the vertical axis
+  --quality <draft|preview|final>, --width <px>, --height <px>, --spp <n> and every other
+  raytracer.toml setting as --setting-name <value> (see the README)
+";
+
+pub enum Invocation {
+    Run { command: Command, operands: Vec<String> },
+    Help(Option<Command>),
+}
+
+// El subcomando, sus operandos (los argumentos que siguen al nombre hasta la primera opción) o la
+// ayuda pedida con `help [subcomando]`, --help o -h. Sin subcomando se abre la ventana.
+pub fn parse(args: &[String]) -> Result<Invocation, String> {
+    let wants_help = args.iter().skip(1).any(|arg| arg == "--help" || arg == "-h");
+    match args.get(1).map(String::as_str) {
+        Some("help") => match args.get(2) {
+            None => Ok(Invocation::Help(None)),
+            Some(name) => Command::from_name(name).map(|command| Invocation::Help(Some(command))).ok_or_else(|| unknown_command(name)),
+        },
+        Some(name) if !name.starts_with('-') => {
+            let command = Command::from_name(name).ok_or_else(|| unknown_command(name))?;
+            if wants_help {
+                return Ok(Invocation::Help(Some(command)));
+            }
+            let operands = args[2..].iter().take_while(|arg| !arg.starts_with('-')).cloned().collect();
+            Ok(Invocation::Run { command, operands })
+        }
+        _ if wants_help => Ok(Invocation::Help(None)),
+        _ => Ok(Invocation::Run { command: Command::View, operands: Vec::new() }),
+    }
+}
+
+fn unknown_command(name: &str) -> String {
+    let names: Vec<&str> = COMMANDS.iter().map(|command| command.name()).collect();
+    format!("Unknown command '{}' (expected {}, or help)", name, names.join(", "))
+}
+
+// Los modos de antes de los subcomandos siguen funcionando durante una versión, con un aviso, pero
+// no salen en la ayuda. Cada uno se reescribe como su subcomando, conservando las demás opciones.
+const LEGACY_FLAGS: [(&str, &str); 5] = [
+    ("--jobs", "render <job file>"),
+    ("--export-world", "convert <scene> <output.world>"),
+    ("--export-obj", "export <output.obj>"),
+    ("--export-gltf", "export <output.glb>"),
+    ("--serve", "serve <port>"),
+];
+
+pub fn upgrade_legacy(args: &[String]) -> Option<Vec<String>> {
+    if args.get(1).is_some_and(|arg| !arg.starts_with('-')) {
+        return None;
+    }
+    let (flag, replacement, index) = LEGACY_FLAGS.iter()
+        .find_map(|&(flag, replacement)| Some((flag, replacement, args.iter().position(|arg| arg == flag)?)))?;
+    eprintln!("warning: {} is deprecated and will be removed in the next release; use `{}`", flag, replacement);
+
+    let value = args.get(index + 1).cloned();
+    let rest = args.iter().enumerate().skip(1)
+        .filter(|&(position, _)| position != index && position != index + 1)
+        .map(|(_, arg)| arg.clone());
+    let mut upgraded = vec![args[0].clone()];
+    match flag {
+        "--jobs" => upgraded.push("render".to_string()),
+        "--serve" => upgraded.push("serve".to_string()),
+        "--export-world" => {
+            upgraded.push("convert".to_string());
+            let scene = args.iter().position(|arg| arg == "--scene").and_then(|index| args.get(index + 1));
+            upgraded.push(scene.cloned().unwrap_or_else(|| "demo".to_string()));
+        }
+        _ => upgraded.push("export".to_string()),
+    }
+    upgraded.extend(value);
+    upgraded.extend(rest);
+    // Antes el formato lo decidía la opción y no la extensión de la salida
+    match flag {
+        "--export-obj" => upgraded.extend(["--format".to_string(), "obj".to_string()]),
+        "--export-gltf" => upgraded.extend(["--format".to_string(), "gltf".to_string()]),
+        _ => {}
+    }
+    Some(upgraded)
+}
+
+pub fn help(command: Option<Command>) -> String {
+    let Some(command) = command else {
+        let commands: String = COMMANDS.iter()
+            .map(|command| format!("  {:<10}{}\n", command.name(), command.summary()))
+            .collect();
+        return format!(
+            "Usage: RayTracerMinecraft [command] [options]\n\nCommands:\n{}\nGlobal options (every command but diff and info):\n{}\nRun `RayTracerMinecraft help <command>` for the options of a command.\n",
+            commands, GLOBAL_OPTIONS
+        );
+    };
+    let mut text = format!("{}\n\nUsage: RayTracerMinecraft {}\n", command.summary(), command.usage());
+    if !command.options().is_empty() {
+        text += &format!("\nOptions:\n{}", command.options());
+    }
+    if command.takes_global_options() {
+        text += &format!("\nGlobal options:\n{}", GLOBAL_OPTIONS);
+    }
+    text
+}
@@ -21,6 +21,33 @@ impl Color {
         Color { r, g, b }
     }
 
+    // Convierte radiancia lineal (sin límite superior) a un color de 8 bits con corrección gamma
+    pub fn from_linear(rgb: [f32; 3]) -> Self {
+        let encode = |value: f32| (value.max(0.0).powf(1.0 / 2.2).min(1.0) * 255.0) as u8;
+        Color { r: encode(rgb[0]), g: encode(rgb[1]), b: encode(rgb[2]) }
+    }
+
+    // Color de un cuerpo negro a `kelvin` grados (1000 a 15000), con la aproximación de Tanner
+    // Helland: 6500 K es blanco, las temperaturas bajas van del naranja al rojo y las altas al azul
+    pub fn from_kelvin(kelvin: f32) -> Self {
+        let t = kelvin.clamp(1000.0, 15000.0) / 100.0;
+        let r = if t <= 66.0 { 255.0 } else { 329.698_73 * (t - 60.0).powf(-0.133_204_76) };
+        let g = if t <= 66.0 {
+            99.470_8 * t.ln() - 161.119_57
+        } else {
+            288.122_17 * (t - 60.0).powf(-0.075_514_85)
+        };
+        let b = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.517_73 * (t - 10.0).ln() - 305.044_8
+        };
+        let channel = |value: f32| value.clamp(0.0, 255.0).round() as u8;
+        Color { r: channel(r), g: channel(g), b: channel(b) }
+    }
+
     pub const fn black() -> Self {
         Color { r: 0, g: 0, b: 0 }
     }
@@ -111,3 +138,25 @@ impl fmt::Display for Color {
         write!(f, "Color(r: {}, g: {}, b: {})", self.r, self.g, self.b)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kelvin_anchor_points() {
+        // Daylight is close to neutral white
+        let daylight = Color::from_kelvin(6500.0);
+        assert!(daylight.r >= 250 && daylight.g >= 245 && daylight.b >= 240, "{:?}", daylight);
+        // Candlelight is strongly orange with almost no blue
+        let candle = Color::from_kelvin(2000.0);
+        assert_eq!(candle.r, 255);
+        assert!((120..=150).contains(&candle.g) && candle.b < 40, "{:?}", candle);
+        // Clear sky is bluer than it is red
+        let sky = Color::from_kelvin(12000.0);
+        assert!(sky.b == 255 && sky.r < 220, "{:?}", sky);
+        // Out of range temperatures are clamped instead of producing garbage
+        assert_eq!(Color::from_kelvin(100.0), Color::from_kelvin(1000.0));
+        assert_eq!(Color::from_kelvin(40000.0), Color::from_kelvin(15000.0));
+    }
+}
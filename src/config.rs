@@ -0,0 +1,535 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use raytracer_minecraft::assets::resolve_asset;
+use raytracer_minecraft::color::Color;
+use raytracer_minecraft::cubemap::CubemapFace;
+use raytracer_minecraft::settings::{AlphaMode, Backend, Convergence, EnvSampling, FlareElements, FocusFalloff, Quality, RenderMode, RenderSettings, Sampler, StereoMode, TextureFilter, TileOrder, ToneMapping};
+use raytracer_minecraft::math::Float;
+use raytracer_minecraft::minimap::Corner;
+use raytracer_minecraft::presets::SLOTS;
+use raytracer_minecraft::sky::{EnvironmentMap, Sky};
+
+const CONFIG_FILE_NAME: &str = "raytracer.toml";
+
+// Valores que puede definir raytracer.toml; todos son opcionales
+#[derive(Deserialize, Default)]
+struct ConfigFile {
+    quality: Option<String>,
+    width: Option<usize>,
+    height: Option<usize>,
+    spp: Option<u32>,
+    max_depth: Option<u32>,
+    half_res_secondary: Option<bool>,
+    probe_roughness: Option<f32>,
+    shadow_samples: Option<u32>,
+    ao_samples: Option<u32>,
+    filter: Option<String>,
+    parallax_steps: Option<u32>,
+    env_sampling: Option<String>,
+    sampler: Option<String>,
+    threads: Option<usize>,
+    tile_order: Option<String>,
+    focus_radius: Option<f32>,
+    focus_falloff: Option<String>,
+    equalize_samples: Option<bool>,
+    stereo: Option<String>,
+    eye_separation: Option<Float>,
+    convergence: Option<String>,
+    shutter_angle: Option<Float>,
+    white_balance: Option<f32>,
+    tone_mapping: Option<String>,
+    fog_start: Option<Float>,
+    fog_end: Option<Float>,
+    dither: Option<bool>,
+    denoise: Option<bool>,
+    alpha: Option<String>,
+    lens_flare: Option<f32>,
+    flare_threshold: Option<f32>,
+    flare_elements: Option<String>,
+    smooth_lighting: Option<bool>,
+    skylight: Option<bool>,
+    ambient_strength: Option<f32>,
+    render_mode: Option<String>,
+    backend: Option<String>,
+    outlines: Option<bool>,
+    outline_width: Option<f32>,
+    outline_color: Option<[u8; 3]>,
+    toon_bands: Option<u32>,
+    toon_outline_width: Option<u32>,
+    toon_outline_color: Option<[u8; 3]>,
+    mouse_sensitivity: Option<Float>,
+    zoom_step: Option<Float>,
+    camera_smoothing: Option<Float>,
+    target_frame_time: Option<f32>,
+    min_render_scale: Option<f32>,
+    max_render_scale: Option<f32>,
+    stream_radius: Option<Float>,
+    minimap_size: Option<usize>,
+    minimap_corner: Option<String>,
+    minimap_zoom: Option<Float>,
+    inset_camera: Option<usize>,
+    inset_size: Option<f32>,
+    inset_aspect: Option<f32>,
+    inset_corner: Option<String>,
+    undo_depth: Option<usize>,
+    palette: Option<Vec<String>>,
+    keybindings: Option<String>,
+    assets: Option<String>,
+}
+
+const KNOWN_KEYS: [&str; 61] = [
+    "quality", "width", "height", "spp", "max_depth", "half_res_secondary", "probe_roughness", "shadow_samples", "ao_samples", "filter", "parallax_steps", "env_sampling", "sampler",
+    "threads", "tile_order", "focus_radius", "focus_falloff", "equalize_samples", "stereo", "eye_separation", "convergence", "shutter_angle", "white_balance",
+    "tone_mapping", "fog_start", "fog_end", "dither", "denoise", "alpha", "lens_flare", "flare_threshold", "flare_elements", "smooth_lighting", "skylight", "ambient_strength",
+    "render_mode", "backend", "outlines", "outline_width", "outline_color", "toon_bands", "toon_outline_width", "toon_outline_color",
+    "mouse_sensitivity", "zoom_step", "camera_smoothing", "target_frame_time", "min_render_scale", "max_render_scale", "stream_radius",
+    "minimap_size", "minimap_corner", "minimap_zoom", "inset_camera", "inset_size", "inset_aspect", "inset_corner",
+    "undo_depth", "palette", "keybindings", "assets",
+];
+
+// Opciones de la ventana interactiva que no afectan a la imagen
+#[derive(Clone, Debug)]
+pub struct Controls {
+    pub mouse_sensitivity: Float, // Grados de órbita por píxel arrastrado
+    pub zoom_step: Float,         // Factor de distancia por cada paso de la rueda
+    pub camera_smoothing: Float,  // Constante de tiempo en segundos con la que la vista sigue a los controles; 0 la desactiva
+    pub target_frame_time: f32,   // Milisegundos por frame a los que se ajusta la resolución al moverse; 0 la deja fija
+    pub min_render_scale: f32,    // Límites de la escala del render respecto a la ventana
+    pub max_render_scale: f32,
+    pub stream_radius: Float,     // Bloques alrededor de la cámara que se cargan de un mundo binario; 0 lo carga entero
+    pub minimap_size: usize,      // Lado del minimapa (M) en píxeles de la ventana
+    pub minimap_corner: Corner,
+    pub minimap_zoom: Float,      // Píxeles del minimapa por bloque
+    pub inset_camera: usize,      // Preset que se ve en el recuadro; 0 no lo muestra
+    pub inset_size: f32,          // Fracción de la ventana que ocupa el recuadro
+    pub inset_aspect: f32,        // Proporción (ancho / alto) de la imagen del recuadro
+    pub inset_corner: Corner,
+    pub undo_depth: usize,        // Ediciones de bloques que Ctrl+Z puede deshacer; 0 no guarda ninguna
+    pub palette: Vec<String>,     // Materiales que recorren R y Shift+R; vacía, todo el registro
+    pub keybindings: Option<PathBuf>, // Archivo de atajos que cambia las teclas de movimiento
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls {
+            mouse_sensitivity: 0.25,
+            zoom_step: 1.1,
+            camera_smoothing: 0.1,
+            target_frame_time: 0.0,
+            min_render_scale: 0.25,
+            max_render_scale: 1.0,
+            stream_radius: 0.0,
+            minimap_size: 160,
+            minimap_corner: Corner::TopRight,
+            minimap_zoom: 2.0,
+            inset_camera: 0,
+            inset_size: 0.3,
+            inset_aspect: 16.0 / 9.0,
+            inset_corner: Corner::BottomRight,
+            undo_depth: 100,
+            palette: Vec::new(),
+            keybindings: None,
+        }
+    }
+}
+
+// Effective settings plus where each value came from, for --print-config
+pub struct Config {
+    pub settings: RenderSettings,
+    pub controls: Controls,
+    pub asset_root: Option<PathBuf>, // Directorio donde se buscan primero los recursos
+    sources: BTreeMap<&'static str, String>,
+}
+
+impl Config {
+    fn set_source(&mut self, key: &'static str, source: &str) {
+        self.sources.insert(key, source.to_string());
+    }
+
+    fn source(&self, key: &str) -> &str {
+        self.sources.get(key).map(String::as_str).unwrap_or("default")
+    }
+
+    pub fn print(&self) {
+        let s = &self.settings;
+        let values = [
+            ("quality", format!("\"{}\"", s.quality.name())),
+            ("width", s.width.to_string()),
+            ("height", s.height.to_string()),
+            ("spp", s.samples_per_pixel.to_string()),
+            ("max_depth", s.max_depth.to_string()),
+            ("half_res_secondary", s.half_res_secondary.to_string()),
+            ("probe_roughness", s.probe_roughness.to_string()),
+            ("shadow_samples", s.shadow_samples.to_string()),
+            ("ao_samples", s.ao_samples.to_string()),
+            ("filter", format!("\"{}\"", s.texture_filter.name())),
+            ("parallax_steps", s.parallax_steps.to_string()),
+            ("env_sampling", format!("\"{}\"", s.env_sampling.name())),
+            ("sampler", format!("\"{}\"", s.sampler.name())),
+            ("threads", s.threads.to_string()),
+            ("tile_order", format!("\"{}\"", s.tile_order.name())),
+            ("focus_radius", s.focus_radius.to_string()),
+            ("focus_falloff", format!("\"{}\"", s.focus_falloff.name())),
+            ("equalize_samples", s.equalize_samples.to_string()),
+            ("stereo", format!("\"{}\"", s.stereo.name())),
+            ("eye_separation", s.eye_separation.to_string()),
+            ("convergence", format!("\"{}\"", s.convergence.name())),
+            ("shutter_angle", s.shutter_angle.to_string()),
+            ("white_balance", s.white_balance.to_string()),
+            ("tone_mapping", format!("\"{}\"", s.tone_mapping.name())),
+            ("fog_start", s.fog_start.to_string()),
+            ("fog_end", s.fog_end.to_string()),
+            ("dither", s.dither.to_string()),
+            ("denoise", s.denoise.to_string()),
+            ("alpha", format!("\"{}\"", s.alpha.name())),
+            ("lens_flare", s.lens_flare.to_string()),
+            ("flare_threshold", s.flare_threshold.to_string()),
+            ("flare_elements", format!("\"{}\"", s.flare_elements.name())),
+            ("smooth_lighting", s.smooth_lighting.to_string()),
+            ("skylight", s.skylight.to_string()),
+            ("ambient_strength", s.ambient_strength.to_string()),
+            ("render_mode", format!("\"{}\"", s.render_mode.name())),
+            ("backend", format!("\"{}\"", s.backend.name())),
+            ("outlines", s.outlines.to_string()),
+            ("outline_width", s.outline_width.to_string()),
+            ("outline_color", format!("[{}, {}, {}]", s.outline_color.r, s.outline_color.g, s.outline_color.b)),
+            ("toon_bands", s.toon_bands.to_string()),
+            ("toon_outline_width", s.toon_outline_width.to_string()),
+            ("toon_outline_color", format!("[{}, {}, {}]", s.toon_outline_color.r, s.toon_outline_color.g, s.toon_outline_color.b)),
+            ("mouse_sensitivity", self.controls.mouse_sensitivity.to_string()),
+            ("zoom_step", self.controls.zoom_step.to_string()),
+            ("camera_smoothing", self.controls.camera_smoothing.to_string()),
+            ("target_frame_time", self.controls.target_frame_time.to_string()),
+            ("min_render_scale", self.controls.min_render_scale.to_string()),
+            ("max_render_scale", self.controls.max_render_scale.to_string()),
+            ("stream_radius", self.controls.stream_radius.to_string()),
+            ("minimap_size", self.controls.minimap_size.to_string()),
+            ("minimap_corner", format!("\"{}\"", self.controls.minimap_corner.name())),
+            ("minimap_zoom", self.controls.minimap_zoom.to_string()),
+            ("inset_camera", self.controls.inset_camera.to_string()),
+            ("inset_size", self.controls.inset_size.to_string()),
+            ("inset_aspect", self.controls.inset_aspect.to_string()),
+            ("inset_corner", format!("\"{}\"", self.controls.inset_corner.name())),
+            ("undo_depth", self.controls.undo_depth.to_string()),
+            ("palette", format!("{:?}", self.controls.palette)),
+            ("keybindings", quoted_path(&self.controls.keybindings)),
+            ("assets", quoted_path(&self.asset_root)),
+        ];
+        for (key, value) in values {
+            println!("{:<18} = {:<10} # {}", key, value, self.source(key));
+        }
+    }
+}
+
+fn quoted_path(path: &Option<PathBuf>) -> String {
+    format!("{:?}", path.as_ref().map(|path| path.display().to_string()).unwrap_or_default())
+}
+
+// Busca raytracer.toml en el directorio actual y después junto al ejecutable
+fn find_config_file() -> Option<PathBuf> {
+    let cwd_candidate = PathBuf::from(CONFIG_FILE_NAME);
+    if cwd_candidate.is_file() {
+        return Some(cwd_candidate);
+    }
+    let exe_dir = std::env::current_exe().ok()?.parent()?.to_path_buf();
+    let exe_candidate = exe_dir.join(CONFIG_FILE_NAME);
+    exe_candidate.is_file().then_some(exe_candidate)
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let table: toml::Table = contents.parse()
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+    // Las claves desconocidas solo generan un aviso para que las configuraciones antiguas sigan funcionando
+    for key in table.keys().filter(|key| !KNOWN_KEYS.contains(&key.as_str())) {
+        eprintln!("warning: {}: unknown setting '{}' ignored", path.display(), key);
+    }
+
+    table.try_into().map_err(|e| format!("Invalid value in {}: {}", path.display(), e))
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter().position(|arg| arg == flag)
+        .and_then(|index| args.get(index + 1))
+        .map(String::as_str)
+}
+
+fn parse_flag<T: std::str::FromStr>(args: &[String], flag: &str) -> Result<Option<T>, String> {
+    match flag_value(args, flag) {
+        Some(value) => value.parse().map(Some).map_err(|_| format!("Invalid value '{}' for {}", value, flag)),
+        None if args.iter().any(|arg| arg == flag) => Err(format!("{} requires a value", flag)),
+        None => Ok(None),
+    }
+}
+
+fn parse_quality(name: &str) -> Result<Quality, String> {
+    Quality::from_name(name).ok_or_else(|| format!("Unknown quality preset '{}' (expected draft, preview or final)", name))
+}
+
+// Built-in defaults, overridden by raytracer.toml (or --config), overridden by command line flags.
+// The quality preset is applied first so individual values can still override it.
+pub fn load(args: &[String]) -> Result<Config, String> {
+    let mut config = Config { settings: RenderSettings::default(), controls: Controls::default(), asset_root: None, sources: BTreeMap::new() };
+
+    let path = match flag_value(args, "--config") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => find_config_file(),
+    };
+    let file = match &path {
+        Some(path) => read_config_file(path)?,
+        None => ConfigFile::default(),
+    };
+    // Las rutas del archivo son relativas a su carpeta; las de la línea de comandos, al directorio actual
+    let file_dir = path.as_ref().and_then(|path| path.parent()).map(Path::to_path_buf).unwrap_or_default();
+    let file_source = path.map(|path| path.display().to_string()).unwrap_or_default();
+
+    let quality = match (flag_value(args, "--quality"), &file.quality) {
+        (Some(name), _) => Some((parse_quality(name)?, "command line --quality".to_string())),
+        (None, Some(name)) => Some((parse_quality(name)?, file_source.clone())),
+        (None, None) => None,
+    };
+    if let Some((quality, source)) = quality {
+        config.settings = quality.settings(&config.settings);
+        config.set_source("quality", &source);
+        for key in ["spp", "max_depth", "shadow_samples", "ao_samples", "filter", "parallax_steps", "denoise"] {
+            config.set_source(key, &format!("{} preset", quality.name()));
+        }
+    }
+
+    macro_rules! layer {
+        ($key:literal, $flag:literal, $file_value:expr, $($field:ident).+) => {
+            if let Some(value) = $file_value {
+                config.$($field).+ = value;
+                config.set_source($key, &file_source);
+            }
+            if let Some(value) = parse_flag(args, $flag)? {
+                config.$($field).+ = value;
+                config.set_source($key, concat!("command line ", $flag));
+            }
+        };
+    }
+
+    layer!("width", "--width", file.width, settings.width);
+    layer!("height", "--height", file.height, settings.height);
+    layer!("spp", "--spp", file.spp, settings.samples_per_pixel);
+    layer!("max_depth", "--max-depth", file.max_depth, settings.max_depth);
+    // Sin valor en la línea de comandos: --half-res-secondary lo activa
+    if let Some(half_res_secondary) = file.half_res_secondary {
+        config.settings.half_res_secondary = half_res_secondary;
+        config.set_source("half_res_secondary", &file_source);
+    }
+    if args.iter().any(|arg| arg == "--half-res-secondary") {
+        config.settings.half_res_secondary = true;
+        config.set_source("half_res_secondary", "command line --half-res-secondary");
+    }
+    layer!("probe_roughness", "--probe-roughness", file.probe_roughness, settings.probe_roughness);
+    layer!("shadow_samples", "--shadow-samples", file.shadow_samples, settings.shadow_samples);
+    layer!("ao_samples", "--ao-samples", file.ao_samples, settings.ao_samples);
+    layer!("filter", "--filter", file.filter.as_deref().map(str::parse::<TextureFilter>).transpose()?, settings.texture_filter);
+    layer!("parallax_steps", "--parallax-steps", file.parallax_steps, settings.parallax_steps);
+    layer!("env_sampling", "--env-sampling", file.env_sampling.as_deref().map(str::parse::<EnvSampling>).transpose()?, settings.env_sampling);
+    layer!("sampler", "--sampler", file.sampler.as_deref().map(str::parse::<Sampler>).transpose()?, settings.sampler);
+    layer!("threads", "--threads", file.threads, settings.threads);
+    layer!("tile_order", "--tile-order", file.tile_order.as_deref().map(str::parse::<TileOrder>).transpose()?, settings.tile_order);
+    layer!("focus_radius", "--focus-radius", file.focus_radius, settings.focus_radius);
+    layer!("focus_falloff", "--focus-falloff", file.focus_falloff.as_deref().map(str::parse::<FocusFalloff>).transpose()?, settings.focus_falloff);
+    // Sin valor en la línea de comandos: --equalize-samples lo activa
+    if let Some(equalize_samples) = file.equalize_samples {
+        config.settings.equalize_samples = equalize_samples;
+        config.set_source("equalize_samples", &file_source);
+    }
+    if args.iter().any(|arg| arg == "--equalize-samples") {
+        config.settings.equalize_samples = true;
+        config.set_source("equalize_samples", "command line --equalize-samples");
+    }
+    layer!("stereo", "--stereo", file.stereo.as_deref().map(str::parse::<StereoMode>).transpose()?, settings.stereo);
+    layer!("eye_separation", "--eye-separation", file.eye_separation, settings.eye_separation);
+    layer!("convergence", "--convergence", file.convergence.as_deref().map(str::parse::<Convergence>).transpose()?, settings.convergence);
+    layer!("shutter_angle", "--shutter-angle", file.shutter_angle, settings.shutter_angle);
+    layer!("white_balance", "--white-balance", file.white_balance, settings.white_balance);
+    layer!("tone_mapping", "--tone-mapping", file.tone_mapping.as_deref().map(str::parse::<ToneMapping>).transpose()?, settings.tone_mapping);
+    layer!("fog_start", "--fog-start", file.fog_start, settings.fog_start);
+    layer!("fog_end", "--fog-end", file.fog_end, settings.fog_end);
+    // Sin valor en la línea de comandos: --dither lo activa
+    if let Some(dither) = file.dither {
+        config.settings.dither = dither;
+        config.set_source("dither", &file_source);
+    }
+    if args.iter().any(|arg| arg == "--dither") {
+        config.settings.dither = true;
+        config.set_source("dither", "command line --dither");
+    }
+    // Como dither; el preset final lo activa y `denoise = false` en el archivo lo vuelve a quitar
+    if let Some(denoise) = file.denoise {
+        config.settings.denoise = denoise;
+        config.set_source("denoise", &file_source);
+    }
+    if args.iter().any(|arg| arg == "--denoise") {
+        config.settings.denoise = true;
+        config.set_source("denoise", "command line --denoise");
+    }
+    layer!("alpha", "--alpha", file.alpha.as_deref().map(str::parse::<AlphaMode>).transpose()?, settings.alpha);
+    layer!("lens_flare", "--lens-flare", file.lens_flare, settings.lens_flare);
+    layer!("flare_threshold", "--flare-threshold", file.flare_threshold, settings.flare_threshold);
+    layer!("flare_elements", "--flare-elements", file.flare_elements.as_deref().map(str::parse::<FlareElements>).transpose()?, settings.flare_elements);
+    if let Some(smooth_lighting) = file.smooth_lighting {
+        config.settings.smooth_lighting = smooth_lighting;
+        config.set_source("smooth_lighting", &file_source);
+    }
+    if args.iter().any(|arg| arg == "--smooth-lighting") {
+        config.settings.smooth_lighting = true;
+        config.set_source("smooth_lighting", "command line --smooth-lighting");
+    }
+    if let Some(skylight) = file.skylight {
+        config.settings.skylight = skylight;
+        config.set_source("skylight", &file_source);
+    }
+    if args.iter().any(|arg| arg == "--skylight") {
+        config.settings.skylight = true;
+        config.set_source("skylight", "command line --skylight");
+    }
+    layer!("ambient_strength", "--ambient-strength", file.ambient_strength, settings.ambient_strength);
+    layer!("render_mode", "--render-mode", file.render_mode.as_deref().map(str::parse::<RenderMode>).transpose()?, settings.render_mode);
+    layer!("backend", "--backend", file.backend.as_deref().map(str::parse::<Backend>).transpose()?, settings.backend);
+    layer!("outline_width", "--outline-width", file.outline_width, settings.outline_width);
+    // Sin valor en la línea de comandos: --outlines los activa
+    if let Some(outlines) = file.outlines {
+        config.settings.outlines = outlines;
+        config.set_source("outlines", &file_source);
+    }
+    if args.iter().any(|arg| arg == "--outlines") {
+        config.settings.outlines = true;
+        config.set_source("outlines", "command line --outlines");
+    }
+    if let Some([r, g, b]) = file.outline_color {
+        config.settings.outline_color = Color::new(r, g, b);
+        config.set_source("outline_color", &file_source);
+    }
+    layer!("toon_bands", "--toon-bands", file.toon_bands, settings.toon_bands);
+    layer!("toon_outline_width", "--toon-outline-width", file.toon_outline_width, settings.toon_outline_width);
+    if let Some([r, g, b]) = file.toon_outline_color {
+        config.settings.toon_outline_color = Color::new(r, g, b);
+        config.set_source("toon_outline_color", &file_source);
+    }
+    layer!("mouse_sensitivity", "--mouse-sensitivity", file.mouse_sensitivity, controls.mouse_sensitivity);
+    layer!("zoom_step", "--zoom-step", file.zoom_step, controls.zoom_step);
+    layer!("camera_smoothing", "--camera-smoothing", file.camera_smoothing, controls.camera_smoothing);
+    layer!("target_frame_time", "--target-frame-time", file.target_frame_time, controls.target_frame_time);
+    layer!("min_render_scale", "--min-render-scale", file.min_render_scale, controls.min_render_scale);
+    layer!("max_render_scale", "--max-render-scale", file.max_render_scale, controls.max_render_scale);
+    layer!("stream_radius", "--stream-radius", file.stream_radius, controls.stream_radius);
+    layer!("minimap_size", "--minimap-size", file.minimap_size, controls.minimap_size);
+    layer!("minimap_corner", "--minimap-corner", file.minimap_corner.as_deref().map(str::parse::<Corner>).transpose()?, controls.minimap_corner);
+    layer!("minimap_zoom", "--minimap-zoom", file.minimap_zoom, controls.minimap_zoom);
+    layer!("inset_camera", "--inset-camera", file.inset_camera, controls.inset_camera);
+    layer!("inset_size", "--inset-size", file.inset_size, controls.inset_size);
+    layer!("inset_aspect", "--inset-aspect", file.inset_aspect, controls.inset_aspect);
+    layer!("inset_corner", "--inset-corner", file.inset_corner.as_deref().map(str::parse::<Corner>).transpose()?, controls.inset_corner);
+    layer!("undo_depth", "--undo-depth", file.undo_depth, controls.undo_depth);
+    // En la línea de comandos, los nombres separados por comas
+    if let Some(palette) = file.palette {
+        config.controls.palette = palette;
+        config.set_source("palette", &file_source);
+    }
+    if let Some(names) = parse_flag::<String>(args, "--palette")? {
+        config.controls.palette = names.split(',').map(str::trim).filter(|name| !name.is_empty()).map(String::from).collect();
+        config.set_source("palette", "command line --palette");
+    }
+    if let Some(keybindings) = file.keybindings {
+        config.controls.keybindings = Some(file_dir.join(keybindings));
+        config.set_source("keybindings", &file_source);
+    }
+    if let Some(keybindings) = parse_flag::<PathBuf>(args, "--keybindings")? {
+        config.controls.keybindings = Some(keybindings);
+        config.set_source("keybindings", "command line --keybindings");
+    }
+    if let Some(assets) = file.assets {
+        config.asset_root = Some(file_dir.join(assets));
+        config.set_source("assets", &file_source);
+    }
+    if let Some(assets) = parse_flag::<PathBuf>(args, "--assets")? {
+        config.asset_root = Some(assets);
+        config.set_source("assets", "command line --assets");
+    }
+
+    if config.settings.width == 0 || config.settings.height == 0 {
+        return Err(format!("Invalid resolution {}x{}", config.settings.width, config.settings.height));
+    }
+    if config.settings.parallax_steps == 0 {
+        return Err("parallax_steps must be at least 1".to_string());
+    }
+    if config.settings.toon_bands == 0 {
+        return Err("toon_bands must be at least 1".to_string());
+    }
+    if !(0.0..=2.0).contains(&config.settings.focus_radius) {
+        return Err(format!("focus_radius must be between 0 and 2 image diagonals, got {}", config.settings.focus_radius));
+    }
+    if !(0.0..=1.0).contains(&config.settings.probe_roughness) {
+        return Err(format!("probe_roughness must be between 0 and 1, got {}", config.settings.probe_roughness));
+    }
+    if config.controls.target_frame_time < 0.0 {
+        return Err(format!("target_frame_time must be a time in milliseconds or 0, got {}", config.controls.target_frame_time));
+    }
+    let (min_scale, max_scale) = (config.controls.min_render_scale, config.controls.max_render_scale);
+    if !(min_scale > 0.0 && min_scale <= max_scale && max_scale <= 1.0) {
+        return Err(format!("Render scales must satisfy 0 < min_render_scale <= max_render_scale <= 1, got {} and {}", min_scale, max_scale));
+    }
+    if config.controls.stream_radius < 0.0 {
+        return Err(format!("stream_radius must be a distance in blocks or 0, got {}", config.controls.stream_radius));
+    }
+    if config.controls.minimap_zoom <= 0.0 {
+        return Err(format!("minimap_zoom must be a positive number of pixels per block, got {}", config.controls.minimap_zoom));
+    }
+    if config.controls.inset_camera > SLOTS {
+        return Err(format!("inset_camera must be a camera preset slot from 1 to {} or 0, got {}", SLOTS, config.controls.inset_camera));
+    }
+    if !(config.controls.inset_size > 0.0 && config.controls.inset_size <= 1.0 && config.controls.inset_aspect > 0.0) {
+        return Err(format!(
+            "inset_size must be a fraction of the window above 0 and inset_aspect a positive width / height ratio, got {} and {}",
+            config.controls.inset_size, config.controls.inset_aspect
+        ));
+    }
+    if !(0.0..=1.0).contains(&config.settings.ambient_strength) {
+        return Err(format!("ambient_strength must be between 0 and 1, got {}", config.settings.ambient_strength));
+    }
+    let (fog_start, fog_end) = (config.settings.fog_start, config.settings.fog_end);
+    if fog_end < 0.0 || (fog_end > 0.0 && !(0.0..fog_end).contains(&fog_start)) {
+        return Err(format!("Fog distances must satisfy 0 <= fog_start < fog_end, or fog_end = 0 for no fog, got {} and {}", fog_start, fog_end));
+    }
+    if config.asset_root.as_ref().is_some_and(|dir| !dir.is_dir()) {
+        return Err(format!("assets must be an existing directory, got {}", quoted_path(&config.asset_root)));
+    }
+    if config.settings.white_balance < 0.0 {
+        return Err(format!("white_balance must be a temperature in kelvin or 0, got {}", config.settings.white_balance));
+    }
+
+    Ok(config)
+}
+
+// --hdri <file> (or --cubemap <file>) replaces the procedural sky; --hdri-rotation <degrees> spins it around Y
+pub fn load_sky(args: &[String]) -> Result<Sky, String> {
+    let rotation: Float = parse_flag(args, "--hdri-rotation")?.unwrap_or(0.0);
+    let map = match (flag_value(args, "--hdri"), flag_value(args, "--cubemap")) {
+        (Some(_), Some(_)) => return Err("--hdri and --cubemap can't be combined".to_string()),
+        (Some(path), None) => {
+            let path = resolve_asset(path, None).map_err(|e| format!("Environment map {}", e))?;
+            EnvironmentMap::load(&path.to_string_lossy(), rotation.to_radians())
+                .map_err(|e| format!("Failed to load environment map {}: {}", path.display(), e))?
+        }
+        (None, Some(path)) => {
+            // Una cruz se busca por su nombre; seis imágenes, por la primera cara
+            let path = resolve_asset(path, None)
+                .or_else(|e| {
+                    let first_face = resolve_asset(&CubemapFace::PositiveX.face_path(path), None).map_err(|_| format!("Cubemap {}", e))?;
+                    Ok::<_, String>(first_face.with_file_name(Path::new(path).file_name().unwrap_or_default()))
+                })?;
+            EnvironmentMap::load_cubemap(&path.to_string_lossy(), rotation.to_radians())
+                .map_err(|e| format!("Failed to load cubemap {}: {}", path.display(), e))?
+        }
+        (None, None) => return Ok(Sky::Procedural),
+    };
+    Ok(Sky::Hdri(map))
+}
@@ -1,177 +1,277 @@
-use nalgebra_glm::Vec3;
-use crate::Material;
-use crate::ray_intersect::{RayIntersect, Intersect, CubeFace};
-
+use crate::math::{Float, Vec3};
+use crate::material::Material;
+use crate::ray_intersect::{RayIntersect, Hit, CubeFace, FaceOrientation, Ray};
+
+// Cómo se pinta una cara de la caja: la cara del bloque sin girar cuya textura lleva y cómo se gira
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FaceDescriptor {
+    pub texture_face: CubeFace,
+    pub orientation: FaceOrientation,
+}
 
+#[derive(Clone, Debug)]
 pub struct Cube {
     pub min: Vec3,
     pub max: Vec3,
     pub material: Material,
+    pub faces: [FaceDescriptor; 6], // Por cara, en el orden de CubeFace::ALL
 }
 
 impl Cube {
-    // Constructor for Cube, now accepts a reference to Material
+    // Constructor for Cube, now accepts a reference to Material.
+    // Las esquinas se ordenan por componente, así que da igual en qué orden vengan: con min > max
+    // en algún eje el test de las placas nunca acertaría. Las cajas sin grosor las avisa `validate`.
     pub fn new(min: Vec3, max: Vec3, material: &Material) -> Self {
         Cube {
-            min,
-            max,
+            min: min.inf(&max),
+            max: min.sup(&max),
             material: material.clone(),  // Clone the material to own it
+            faces: Cube::unturned_faces(),
         }
     }
 
-    fn calculate_normal(&self, hit_point: Vec3) -> Vec3 {
-        if (hit_point.x - self.min.x).abs() < 1e-4 {
-            return Vec3::new(-1.0, 0.0, 0.0);
-        }
-        if (hit_point.x - self.max.x).abs() < 1e-4 {
-            return Vec3::new(1.0, 0.0, 0.0);
-        }
-        if (hit_point.y - self.min.y).abs() < 1e-4 {
-            return Vec3::new(0.0, -1.0, 0.0);
-        }
-        if (hit_point.y - self.max.y).abs() < 1e-4 {
-            return Vec3::new(0.0, 1.0, 0.0);
-        }
-        if (hit_point.z - self.min.z).abs() < 1e-4 {
-            return Vec3::new(0.0, 0.0, -1.0);
-        }
-        Vec3::new(0.0, 0.0, 1.0)
+    // Cada cara con su propia textura, sin girar
+    pub fn unturned_faces() -> [FaceDescriptor; 6] {
+        CubeFace::ALL.map(|face| FaceDescriptor { texture_face: face, orientation: FaceOrientation::default() })
     }
 
-    pub fn intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect {
-        let mut tmin = (self.min.x - ray_origin.x) / ray_direction.x;
-        let mut tmax = (self.max.x - ray_origin.x) / ray_direction.x;
-
-        if tmin > tmax {
-            std::mem::swap(&mut tmin, &mut tmax);
-        }
-
-        let mut tymin = (self.min.y - ray_origin.y) / ray_direction.y;
-        let mut tymax = (self.max.y - ray_origin.y) / ray_direction.y;
-
-        if tymin > tymax {
-            std::mem::swap(&mut tymin, &mut tymax);
-        }
+    // La misma caja con otras texturas por cara, como las de un bloque orientado
+    pub fn with_faces(mut self, faces: [FaceDescriptor; 6]) -> Self {
+        self.faces = faces;
+        self
+    }
 
-        if tmin > tymax || tymin > tmax {
-            return Intersect::empty();
+    // Distancia a la que un rayo que empieza dentro (o en la superficie) sale de la caja
+    pub fn exit_distance(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Float {
+        let mut exit = Float::INFINITY;
+        for axis in 0..3 {
+            let bound = if ray_direction[axis] > 0.0 { self.max[axis] } else { self.min[axis] };
+            if ray_direction[axis] != 0.0 {
+                exit = exit.min((bound - ray_origin[axis]) / ray_direction[axis]);
+            }
         }
+        exit.max(0.0)
+    }
 
-        tmin = tmin.max(tymin);
-        tmax = tmax.min(tymax);
+    // Cara de la placa por la que entra el rayo en `axis`: si avanza en sentido positivo entra por
+    // el lado mínimo y la normal apunta hacia atrás
+    fn entry_face(axis: usize, direction: Float) -> (CubeFace, Vec3) {
+        let mut normal = Vec3::zeros();
+        normal[axis] = if direction > 0.0 { -1.0 } else { 1.0 };
+        (CubeFace::from_normal(&normal), normal)
+    }
+}
 
-        let mut tzmin = (self.min.z - ray_origin.z) / ray_direction.z;
-        let mut tzmax = (self.max.z - ray_origin.z) / ray_direction.z;
 
-        if tzmin > tzmax {
-            std::mem::swap(&mut tzmin, &mut tzmax);
+impl RayIntersect for Cube {
+    // Prueba de las placas. Se rechazan las cajas que el rayo deja atrás antes de t_min o que
+    // alcanza a t_max o más lejos.
+    // La cara sale del eje que fijó tmin al recorrer las placas, no de comparar distancias ni
+    // puntos: en una arista con dos distancias iguales gana el primer eje, y las dos caras son
+    // válidas. Un eje con dirección 0 da distancias infinitas o NaN, y nunca sube tmin.
+    fn ray_intersect(&self, ray: &Ray) -> Option<Hit> {
+        let mut tmin = Float::NEG_INFINITY;
+        let mut tmax = Float::INFINITY;
+        let mut entry_axis = None;
+
+        for axis in 0..3 {
+            let mut near = (self.min[axis] - ray.origin[axis]) * ray.inv_dir[axis];
+            let mut far = (self.max[axis] - ray.origin[axis]) * ray.inv_dir[axis];
+            if near > far {
+                std::mem::swap(&mut near, &mut far);
+            }
+
+            if near > tmin {
+                tmin = near;
+                entry_axis = Some(axis);
+            }
+            if far < tmax {
+                tmax = far;
+            }
+            if tmin > tmax {
+                return None;
+            }
         }
 
-        if tmin > tzmax || tzmin > tmax {
-            return Intersect::empty();
+        // Sin eje de entrada la dirección es nula
+        let axis = entry_axis?;
+        if tmax < ray.t_min || tmin >= ray.t_max {
+            return None;
         }
 
-        tmin = tmin.max(tzmin);
-        tmax = tmax.min(tzmax);
-
-        if tmin < 0.0 && tmax < 0.0 {
-            return Intersect::empty();
-        }
+        let intersection_point = ray.at(tmin);
+        let (face, normal) = Cube::entry_face(axis, ray.dir[axis]);
+        let hit = Hit::new(intersection_point, normal, tmin, face);
 
-        let intersection_point = ray_origin + ray_direction * tmin;
-
-        // Determine which face was hit
-        let face = if (intersection_point.x - self.min.x).abs() < 1e-4 {
-            CubeFace::Left
-        } else if (intersection_point.x - self.max.x).abs() < 1e-4 {
-            CubeFace::Right
-        } else if (intersection_point.y - self.min.y).abs() < 1e-4 {
-            CubeFace::Bottom
-        } else if (intersection_point.y - self.max.y).abs() < 1e-4 {
-            CubeFace::Top
-        } else if (intersection_point.z - self.min.z).abs() < 1e-4 {
-            CubeFace::Back
-        } else {
-            CubeFace::Front
-        };
-
-        Intersect {
-            is_intersecting: true,
-            point: intersection_point,
-            normal: self.calculate_normal(intersection_point),
-            distance: tmin,
-            material: self.material.clone(),
-            face,
-        }
+        let descriptor = &self.faces[face as usize];
+        Some(Hit { uv: descriptor.orientation.apply(hit.uv), texture_face: descriptor.texture_face, ..hit })
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // Las 8 combinaciones de ejes con las esquinas intercambiadas dan la misma caja
+    fn permutations(a: Vec3, b: Vec3) -> Vec<(Vec3, Vec3)> {
+        (0..8)
+            .map(|mask: usize| {
+                let mut min = a;
+                let mut max = b;
+                for axis in 0..3 {
+                    if mask & (1 << axis) != 0 {
+                        std::mem::swap(&mut min[axis], &mut max[axis]);
+                    }
+                }
+                (min, max)
+            })
+            .collect()
+    }
 
-impl RayIntersect for Cube {
-    fn ray_intersect(&self, ray_origin: &Vec3, ray_dir: &Vec3) -> Intersect {
-        let mut tmin = (self.min.x - ray_origin.x) / ray_dir.x;
-        let mut tmax = (self.max.x - ray_origin.x) / ray_dir.x;
-
-        if tmin > tmax {
-            std::mem::swap(&mut tmin, &mut tmax);
+    #[test]
+    fn swapped_corners_are_normalized() {
+        let a = Vec3::new(-1.0, 0.0, 0.5);
+        let b = Vec3::new(2.0, 1.5, 3.0);
+        for (min, max) in permutations(a, b) {
+            let cube = Cube::new(min, max, &Material::black());
+            assert_eq!((cube.min, cube.max), (a, b), "corners {:?} {:?}", min, max);
         }
+    }
 
-        let mut tymin = (self.min.y - ray_origin.y) / ray_dir.y;
-        let mut tymax = (self.max.y - ray_origin.y) / ray_dir.y;
-
-        if tymin > tymax {
-            std::mem::swap(&mut tymin, &mut tymax);
+    #[test]
+    fn swapped_corners_are_hit_like_the_ordered_box() {
+        let a = Vec3::new(-1.0, -1.0, -1.0);
+        let b = Vec3::new(1.0, 0.5, 2.0);
+        let rays = [
+            (Vec3::new(0.2, 5.0, 0.3), Vec3::new(0.0, -1.0, 0.0)),
+            (Vec3::new(-4.0, 0.1, 0.0), Vec3::new(1.0, 0.05, 0.1).normalize()),
+            (Vec3::new(0.0, 0.0, 6.0), Vec3::new(0.1, -0.1, -1.0).normalize()),
+            (Vec3::new(3.0, 3.0, 3.0), Vec3::new(-1.0, -1.0, -1.0).normalize()),
+        ];
+        let reference = Cube::new(a, b, &Material::black());
+        for (min, max) in permutations(a, b) {
+            let cube = Cube::new(min, max, &Material::black());
+            for (origin, direction) in &rays {
+                let ray = Ray::new(*origin, *direction);
+                let expected = reference.ray_intersect(&ray);
+                assert!(expected.is_some(), "the reference box missed");
+                assert_eq!(cube.ray_intersect(&ray), expected, "corners {:?} {:?}", min, max);
+            }
         }
+    }
 
-        if tmin > tymax || tymin > tmax {
-            return Intersect::empty();
-        }
+    #[test]
+    fn rays_only_accept_hits_inside_their_bounds() {
+        let cube = Cube::new(Vec3::new(-1.0, -1.0, 2.0), Vec3::new(1.0, 1.0, 3.0), &Material::black());
+        let forward = Vec3::new(0.0, 0.0, 1.0);
+        assert_eq!(cube.ray_intersect(&Ray::new(Vec3::zeros(), forward)).map(|hit| hit.t), Some(2.0));
+        assert!(cube.ray_intersect(&Ray::segment(Vec3::zeros(), forward, 2.5)).is_some());
+        assert!(cube.ray_intersect(&Ray::segment(Vec3::zeros(), forward, 2.0)).is_none(), "t_max is exclusive");
+        assert!(cube.ray_intersect(&Ray::new(Vec3::zeros(), -forward)).is_none(), "the box is behind the ray");
+        // From inside, the box still counts and the hit lies behind the origin
+        let inside = cube.ray_intersect(&Ray::new(Vec3::new(0.0, 0.0, 2.5), forward));
+        assert_eq!(inside.map(|hit| hit.t), Some(-0.5));
+        assert!(cube.ray_intersect(&Ray::bounded(Vec3::zeros(), forward, 3.5, Float::INFINITY)).is_none());
+    }
 
-        tmin = tmin.max(tymin);
-        tmax = tmax.min(tymax);
+    // La normal tiene que ser la de una de las caras que se juntan en el punto, y mirar al rayo
+    fn assert_valid_face(hit: &Hit, valid: &[Vec3], direction: &Vec3) {
+        assert!(valid.contains(&hit.normal), "normal {:?} is not one of {:?}", hit.normal, valid);
+        assert_eq!(hit.face, CubeFace::from_normal(&hit.normal));
+        assert!(hit.normal.dot(direction) < 0.0, "normal {:?} faces away from the ray", hit.normal);
+    }
 
-        let mut tzmin = (self.min.z - ray_origin.z) / ray_dir.z;
-        let mut tzmax = (self.max.z - ray_origin.z) / ray_dir.z;
+    #[test]
+    fn rays_at_edges_and_corners_get_one_of_the_faces_that_meet_there() {
+        let cube = Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), &Material::black());
+        let (left, top, back) = (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        // Edge between the left and top faces; both slab distances are exactly 1
+        let direction = Vec3::new(1.0, -1.0, 0.0);
+        let hit = cube.ray_intersect(&Ray::new(Vec3::new(-1.0, 2.0, 0.5), direction)).expect("the edge is ahead");
+        assert_eq!(hit.t, 1.0);
+        assert_valid_face(&hit, &[left, top], &direction);
+
+        // Corner where the left, top and back faces meet
+        let direction = Vec3::new(1.0, -1.0, 1.0);
+        let hit = cube.ray_intersect(&Ray::new(Vec3::new(-1.0, 2.0, -1.0), direction)).expect("the corner is ahead");
+        assert_eq!(hit.point, Vec3::new(0.0, 1.0, 0.0));
+        assert_valid_face(&hit, &[left, top, back], &direction);
+
+        // Grazing along a face, parallel to the other two axes
+        let direction = Vec3::new(0.0, 0.0, 1.0);
+        let hit = cube.ray_intersect(&Ray::new(Vec3::new(0.0, 1.0, -2.0), direction)).expect("the ray runs along the edge");
+        assert_valid_face(&hit, &[back], &direction);
+    }
 
-        if tzmin > tzmax {
-            std::mem::swap(&mut tzmin, &mut tzmax);
+    #[test]
+    fn edges_far_from_the_origin_keep_their_faces() {
+        // Far from the origin the hit point is rounded, but the face comes from the slab that
+        // set the entry distance, so it can't flip to a side the ray never crossed
+        let min = Vec3::new(1.0e5, 2.0e5, -3.0e5);
+        let cube = Cube::new(min, min + Vec3::new(1.0, 1.0, 1.0), &Material::black());
+        let (left, top) = (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        for step in 0..200 {
+            let offset = (step as Float - 100.0) * 1e-7;
+            let direction = Vec3::new(1.0, -1.0 + offset, 0.01 * offset).normalize();
+            let origin = Vec3::new(min.x - 3.0, min.y + 4.0, min.z + 0.5);
+            if let Some(hit) = cube.ray_intersect(&Ray::new(origin, direction)) {
+                assert_valid_face(&hit, &[left, top], &direction);
+            }
         }
+    }
 
-        if tmin > tzmax || tzmin > tmax {
-            return Intersect::empty();
-        }
+    // Cajas de 0.1 a 5 de lado cerca del origen y orígenes de rayo hasta 20 unidades alrededor
+    fn point(range: f64) -> impl Strategy<Value = Vec3> {
+        (-range..range, -range..range, -range..range).prop_map(|(x, y, z)| Vec3::new(x as Float, y as Float, z as Float))
+    }
 
-        tmin = tmin.max(tzmin);
-        tmax = tmax.min(tzmax);
+    fn any_cube() -> impl Strategy<Value = Cube> {
+        (point(10.0), (0.1f64..5.0, 0.1f64..5.0, 0.1f64..5.0))
+            .prop_map(|(min, (x, y, z))| Cube::new(min, min + Vec3::new(x as Float, y as Float, z as Float), &Material::black()))
+    }
+
+    // Dentro de la caja con un margen de `margin` en cada cara
+    fn strictly_inside(cube: &Cube, point: &Vec3, margin: Float) -> bool {
+        (0..3).all(|axis| point[axis] > cube.min[axis] + margin && point[axis] < cube.max[axis] - margin)
+    }
 
-        if tmin < 0.0 && tmax < 0.0 {
-            return Intersect::empty();
+    proptest! {
+        #[test]
+        fn hits_are_the_first_crossing_of_the_surface(cube in any_cube(), origin in point(20.0), target in (0.0f64..1.0, 0.0f64..1.0, 0.0f64..1.0)) {
+            prop_assume!((0..3).any(|axis| origin[axis] < cube.min[axis] || origin[axis] > cube.max[axis]));
+            // A ray aimed at a point inside the box has to hit it, no further away than that point
+            let inside = cube.min + (cube.max - cube.min).component_mul(&Vec3::new(target.0 as Float, target.1 as Float, target.2 as Float));
+            let direction = (inside - origin).normalize();
+            let hit = cube.ray_intersect(&Ray::new(origin, direction));
+            prop_assert!(hit.is_some(), "missed a box the ray points into");
+            let hit = hit.unwrap();
+            let epsilon = 1e-3;
+            prop_assert!(hit.t >= 0.0 && hit.t <= (inside - origin).magnitude() + epsilon, "t = {}", hit.t);
+
+            // On the box, on the face the normal names, and facing the ray
+            prop_assert!((0..3).all(|axis| hit.point[axis] >= cube.min[axis] - epsilon && hit.point[axis] <= cube.max[axis] + epsilon), "{:?} is off the box", hit.point);
+            let axis = hit.normal.iamax();
+            let face = if hit.normal[axis] > 0.0 { cube.max[axis] } else { cube.min[axis] };
+            prop_assert!((hit.point[axis] - face).abs() <= epsilon, "{:?} is not on the face with normal {:?}", hit.point, hit.normal);
+            prop_assert!(hit.normal.dot(&direction) < 0.0);
+
+            // Nothing closer: just before the hit the ray is not yet inside the box
+            let before = origin + direction * (hit.t - 10.0 * epsilon).max(0.0);
+            prop_assert!(!strictly_inside(&cube, &before, epsilon), "{:?} is already inside at t = {}", before, hit.t);
         }
 
-        let intersection_point = ray_origin + ray_dir * tmin;
-
-        // Determine which face was hit
-        let face = if (intersection_point.x - self.min.x).abs() < 1e-4 {
-            CubeFace::Left
-        } else if (intersection_point.x - self.max.x).abs() < 1e-4 {
-            CubeFace::Right
-        } else if (intersection_point.y - self.min.y).abs() < 1e-4 {
-            CubeFace::Bottom
-        } else if (intersection_point.y - self.max.y).abs() < 1e-4 {
-            CubeFace::Top
-        } else if (intersection_point.z - self.min.z).abs() < 1e-4 {
-            CubeFace::Back
-        } else {
-            CubeFace::Front
-        };
-
-        Intersect {
-            point: intersection_point,
-            distance: tmin,
-            normal: self.calculate_normal(intersection_point),
-            material: self.material.clone(),
-            is_intersecting: true,
-            face,  // Add this line
+        #[test]
+        fn misses_never_pass_through_the_box(cube in any_cube(), origin in point(20.0), direction in point(1.0)) {
+            prop_assume!(direction.magnitude() > 0.1);
+            let direction = direction.normalize();
+            if cube.ray_intersect(&Ray::new(origin, direction)).is_none() {
+                for step in 0..=400 {
+                    let sample = origin + direction * (step as Float * 0.25);
+                    prop_assert!(!strictly_inside(&cube, &sample, 1e-3), "the ray goes through {:?}", sample);
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
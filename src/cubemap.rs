@@ -0,0 +1,184 @@
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::framebuffer::Framebuffer;
+use crate::math::{Float, Vec3};
+
+// Caras de un cubemap en el orden habitual de OpenGL
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CubemapFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+pub const CUBEMAP_FACES: [CubemapFace; 6] = [
+    CubemapFace::PositiveX,
+    CubemapFace::NegativeX,
+    CubemapFace::PositiveY,
+    CubemapFace::NegativeY,
+    CubemapFace::PositiveZ,
+    CubemapFace::NegativeZ,
+];
+
+impl CubemapFace {
+    // Sufijo del archivo de cada cara: probe_px.png, probe_nx.png, ...
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            CubemapFace::PositiveX => "px",
+            CubemapFace::NegativeX => "nx",
+            CubemapFace::PositiveY => "py",
+            CubemapFace::NegativeY => "ny",
+            CubemapFace::PositiveZ => "pz",
+            CubemapFace::NegativeZ => "nz",
+        }
+    }
+
+    // Archivo de la cara para un cubemap guardado como seis imágenes: "probe.png" -> "probe_px.png"
+    pub fn face_path(&self, path: &str) -> String {
+        let path = Path::new(path);
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+        let name = match path.extension() {
+            Some(extension) => format!("{}_{}.{}", stem, self.suffix(), extension.to_string_lossy()),
+            None => format!("{}_{}", stem, self.suffix()),
+        };
+        path.with_file_name(name).to_string_lossy().into_owned()
+    }
+
+    // Celda (columna, fila) de la cara en la cruz horizontal de 4x3:
+    //        +Y
+    //    -X  +Z  +X  -Z
+    //        -Y
+    pub fn cross_cell(&self) -> (usize, usize) {
+        match self {
+            CubemapFace::PositiveY => (1, 0),
+            CubemapFace::NegativeX => (0, 1),
+            CubemapFace::PositiveZ => (1, 1),
+            CubemapFace::PositiveX => (2, 1),
+            CubemapFace::NegativeZ => (3, 1),
+            CubemapFace::NegativeY => (1, 2),
+        }
+    }
+
+    // Copia la cara renderizada, cuadrada, a su celda de la cruz. Con alfa las celdas vacías de la
+    // cruz quedan transparentes
+    pub fn paste_into_cross(&self, cross: &mut Framebuffer, face: &Framebuffer) {
+        let size = face.width;
+        let (column, row) = self.cross_cell();
+        for y in 0..size {
+            let start = (row * size + y) * cross.width + column * size;
+            cross.buffer[start..start + size].copy_from_slice(&face.buffer[y * size..(y + 1) * size]);
+        }
+        if let Some(alpha) = &face.alpha {
+            let cross_alpha = cross.alpha.get_or_insert_with(|| vec![0; cross.buffer.len()]);
+            for y in 0..size {
+                let start = (row * size + y) * cross.width + column * size;
+                cross_alpha[start..start + size].copy_from_slice(&alpha[y * size..(y + 1) * size]);
+            }
+        }
+    }
+
+    // Dirección (sin normalizar) para las coordenadas s, t de la cara en [-1, 1], con s hacia la derecha
+    // de la imagen y t hacia abajo. Sigue la tabla de la especificación de OpenGL, así que las caras se
+    // pueden usar tal cual en cualquier visor o motor que espere cubemaps estándar.
+    pub fn direction(&self, s: Float, t: Float) -> Vec3 {
+        match self {
+            CubemapFace::PositiveX => Vec3::new(1.0, -t, -s),
+            CubemapFace::NegativeX => Vec3::new(-1.0, -t, s),
+            CubemapFace::PositiveY => Vec3::new(s, 1.0, t),
+            CubemapFace::NegativeY => Vec3::new(s, -1.0, -t),
+            CubemapFace::PositiveZ => Vec3::new(s, -t, 1.0),
+            CubemapFace::NegativeZ => Vec3::new(-s, -t, -1.0),
+        }
+    }
+
+    // Cara que ve la dirección y sus coordenadas s, t en [-1, 1]; la inversa de `direction`
+    pub fn from_direction(direction: &Vec3) -> (CubemapFace, Float, Float) {
+        let (x, y, z) = (direction.x, direction.y, direction.z);
+        let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+        if ax >= ay && ax >= az {
+            if x > 0.0 {
+                (CubemapFace::PositiveX, -z / ax, -y / ax)
+            } else {
+                (CubemapFace::NegativeX, z / ax, -y / ax)
+            }
+        } else if ay >= az {
+            if y > 0.0 {
+                (CubemapFace::PositiveY, x / ay, z / ay)
+            } else {
+                (CubemapFace::NegativeY, x / ay, -z / ay)
+            }
+        } else if z > 0.0 {
+            (CubemapFace::PositiveZ, x / az, -y / az)
+        } else {
+            (CubemapFace::NegativeZ, -x / az, -y / az)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::camera::{Camera, Projection};
+    use crate::color::Color;
+    use crate::cube::Cube;
+    use crate::material::Material;
+    use crate::ray_intersect::Ray;
+    use crate::render::{cast_ray, render};
+    use crate::sampler::PixelSample;
+    use crate::scene::Scene;
+    use crate::settings::RenderSettings;
+    use crate::sky::{Ambient, EnvironmentMap, Sky};
+
+    #[test]
+    fn faces_and_directions_are_inverse() {
+        for face in CUBEMAP_FACES {
+            for (s, t) in [(0.0, 0.0), (0.5, -0.25), (-0.9, 0.7)] {
+                let (found, found_s, found_t) = CubemapFace::from_direction(&face.direction(s, t).normalize());
+                assert_eq!(found, face);
+                assert!((found_s - s).abs() < 1e-5 && (found_t - t).abs() < 1e-5, "{:?} {} {}", face, s, t);
+            }
+        }
+    }
+
+    #[test]
+    fn a_rendered_probe_lights_a_mirror_as_the_sky() {
+        // A sky that is red wherever x > 0 and blue elsewhere, seen from the origin as a 4x3 cross
+        let pixels = (0..4).flat_map(|_| (0..8).map(|x| if x >= 4 { [1.0, 0.0, 0.0] } else { [0.0, 0.0, 1.0] })).collect();
+        let sky = Sky::Hdri(EnvironmentMap::from_pixels(8, 4, pixels, 0.0));
+        let ambient = Ambient::flat(Color::new(0, 0, 0));
+        let size = 8;
+        let settings = RenderSettings { width: size, height: size, ..RenderSettings::default() };
+        let mut camera = Camera::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+        let mut cross = Framebuffer::new(size * 4, size * 3);
+        let sky = Arc::new(sky);
+        for face in CUBEMAP_FACES {
+            camera.projection = Projection::CubemapFace(face);
+            let probe = Scene::new(Vec::<Cube>::new(), Vec::new(), sky.clone(), camera.clone(), ambient);
+            let mut framebuffer = Framebuffer::new(size, size);
+            render(&mut framebuffer, &probe, &settings);
+            face.paste_into_cross(&mut cross, &framebuffer);
+        }
+        let path = std::env::temp_dir().join(format!("raytracer_minecraft_probe_{}.png", std::process::id()));
+        cross.save(&path.to_string_lossy()).unwrap();
+        let loaded = EnvironmentMap::load_cubemap(&path.to_string_lossy(), 0.0);
+        std::fs::remove_file(&path).unwrap();
+
+        // A mirror cube under the loaded probe reflects red toward +x and blue toward -x
+        let mirror = Material::new(Color::new(0, 0, 0), 0.0, [0.0, 0.0, 1.0, 0.0], 1.0);
+        let cube = Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0), &mirror);
+        let scene = Scene::new([cube], Vec::new(), Arc::new(Sky::Hdri(loaded.unwrap())), Camera::default_view(), ambient);
+        let seen_from = |x: Float| {
+            let origin = Vec3::new(x, 0.0, 5.0);
+            let ray = Ray::new(origin, (Vec3::new(0.0, 0.0, 1.0) - origin).normalize());
+            cast_ray(&ray, &scene, &RenderSettings::default(), &PixelSample::default(), 0)
+        };
+        let (toward_x, away_from_x) = (seen_from(-3.0), seen_from(3.0));
+        assert!(toward_x.r > 200 && toward_x.b < 50, "{:?}", toward_x);
+        assert!(away_from_x.b > 200 && away_from_x.r < 50, "{:?}", away_from_x);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::math::{consts, Float, Vec3};
+use crate::color::Color;
+use crate::light::Light;
+
+// La luna es una luz direccional: muy lejos y con un radio de influencia que apenas la atenúa
+const MOON_DISTANCE: Float = 1000.0;
+const MOON_COLOR: Color = Color::new(150, 170, 255);
+const MOON_INTENSITY: f32 = 0.3;
+// Cuánto tarda en hacerse plena noche una vez que el sol se pone (en seno de su elevación)
+const DUSK_LENGTH: f32 = 0.3;
+
+pub struct DayNightCycle {
+    pub time: f32,
+    pub day_color: Color,
+    pub night_color: Color,
+    pub sun_position: Vec3,
+}
+
+impl DayNightCycle {
+    pub fn new() -> Self {
+        DayNightCycle {
+            time: 0.5, // Empezamos a mitad del día
+            day_color: Color::new(255, 255, 255),
+            night_color: Color::new(10, 10, 50),
+            sun_position: Vec3::new(0.0, 5.0, 0.0), // Posición inicial del sol
+        }
+    }
+
+    // Ciclo en un momento dado, de 0 a 1 (0.5 es el atardecer y 0.75 medianoche)
+    pub fn at(time: f32) -> Self {
+        let mut cycle = DayNightCycle::new();
+        cycle.update(time - cycle.time);
+        cycle
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        self.time += delta;
+        if self.time > 1.0 {
+            self.time -= 1.0;
+        }
+        if self.time < 0.0 {
+            self.time += 1.0;
+        }
+
+        // Actualizar la posición del sol
+        let angle = self.time as Float * 2.0 * consts::PI;
+        self.sun_position = Vec3::new(
+            5.0 * angle.cos(),
+            5.0 * angle.sin() + 1.0, // De noche el sol se esconde bajo el horizonte
+            5.0 * angle.sin(),
+        );
+    }
+
+    pub fn get_current_color(&self) -> Color {
+        let t = (self.time * std::f32::consts::PI * 2.0).sin() * 0.5 + 0.5;
+        Color::lerp(&self.night_color, &self.day_color, t)
+    }
+
+    pub fn get_light_intensity(&self) -> f32 {
+        ((self.time * std::f32::consts::PI * 2.0).sin() * 0.4 + 0.6).max(0.2)
+    }
+
+    // 0 durante el día y hasta 1 en plena noche. La noche es la segunda mitad del ciclo, cuando el
+    // sol está bajo el horizonte; se va oscureciendo en lugar de cambiar de golpe.
+    pub fn night_amount(&self) -> f32 {
+        if self.time <= 0.5 {
+            return 0.0;
+        }
+        (-(self.time * std::f32::consts::PI * 2.0).sin() / DUSK_LENGTH).clamp(0.0, 1.0)
+    }
+
+    // Luz principal (sol) para el momento actual del ciclo
+    pub fn sun_light(&self) -> Light {
+        Light::new(
+            self.sun_position,
+            self.get_current_color(),
+            self.get_light_intensity() * 2.0 * (1.0 - self.night_amount()), // Ajusta este factor según sea necesario
+            10.0
+        )
+    }
+
+    // Luz fría y débil de la luna, en el lado contrario al sol; de día no hay luna
+    pub fn moon_light(&self) -> Option<Light> {
+        let night = self.night_amount();
+        if night <= 0.0 {
+            return None;
+        }
+        let position = -self.sun_position.normalize() * MOON_DISTANCE;
+        Some(Light::new(position, MOON_COLOR, MOON_INTENSITY * night, MOON_DISTANCE * 10.0))
+    }
+}
@@ -0,0 +1,636 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use crate::block::BLOCK_SIZE;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::math::{Float, Vec3};
+use crate::primitive::Primitive;
+use crate::ray_intersect::{Ray, RayIntersect};
+use crate::structures::{self, check_position, PlacedBlock, MAX_COORDINATE};
+
+// Bloques que puede cambiar una sola edición; un relleno más grande se rechaza antes de recorrerlo
+pub const MAX_EDIT_BLOCKS: u64 = 1 << 20;
+
+// Aceleración de los bloques que caen, en bloques por segundo al cuadrado: uno tarda un cuarto de
+// segundo en caer un bloque y algo menos de un segundo en caer diez
+pub const FALL_ACCELERATION: f32 = 32.0;
+
+// Una edición de bloques de las que se pueden deshacer
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+    Place(PlacedBlock),           // Pone el bloque, reemplazando el que hubiera en su posición
+    Remove([i32; 3]),
+    SetMaterial([i32; 3], String), // Cambia el material del bloque que haya, con la misma forma
+    // La región llena de bloques enteros de ese material, o vacía con None
+    Fill { region: Region, material: Option<String> },
+    // Los bloques copiados con la esquina menor en `at`; los huecos de la copia también vacían su posición
+    Paste { at: [i32; 3], clipboard: Clipboard },
+}
+
+impl Edit {
+    // El material que pone la edición, si pone alguno
+    pub fn material(&self) -> Option<&str> {
+        match self {
+            Edit::Place(block) => Some(&block.material),
+            Edit::Remove(_) => None,
+            Edit::SetMaterial(_, material) => Some(material),
+            Edit::Fill { material, .. } => material.as_deref(),
+            Edit::Paste { .. } => None, // Los bloques copiados salen de la escena, así que sus materiales existen
+        }
+    }
+}
+
+// Caja de posiciones de bloques con las dos esquinas incluidas, como la que se selecciona en la ventana
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub min: [i32; 3],
+    pub max: [i32; 3],
+}
+
+impl Region {
+    // La caja entre dos esquinas opuestas cualesquiera
+    pub fn new(a: [i32; 3], b: [i32; 3]) -> Self {
+        Region { min: [0, 1, 2].map(|axis| a[axis].min(b[axis])), max: [0, 1, 2].map(|axis| a[axis].max(b[axis])) }
+    }
+
+    pub fn size(&self) -> [i32; 3] {
+        [0, 1, 2].map(|axis| self.max[axis] - self.min[axis] + 1)
+    }
+
+    pub fn volume(&self) -> u64 {
+        self.size().iter().map(|&side| side as u64).product()
+    }
+
+    pub fn contains(&self, pos: [i32; 3]) -> bool {
+        (0..3).all(|axis| (self.min[axis]..=self.max[axis]).contains(&pos[axis]))
+    }
+
+    // La parte de la caja dentro de los límites del mundo (ver `structures::check_position`), o None
+    // si queda entera fuera
+    pub fn clipped(&self) -> Option<Region> {
+        let min = self.min.map(|value| value.max(-MAX_COORDINATE));
+        let max = self.max.map(|value| value.min(MAX_COORDINATE));
+        (0..3).all(|axis| min[axis] <= max[axis]).then_some(Region { min, max })
+    }
+
+    fn positions(&self) -> impl Iterator<Item = [i32; 3]> {
+        let (min, max) = (self.min, self.max);
+        (min[1]..=max[1]).flat_map(move |y| (min[2]..=max[2]).flat_map(move |z| (min[0]..=max[0]).map(move |x| [x, y, z])))
+    }
+}
+
+// Bloques copiados de una región, con las posiciones relativas a su esquina menor. Las posiciones
+// de la caja sin bloque son aire, que también se pega.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clipboard {
+    size: [i32; 3],
+    blocks: Vec<PlacedBlock>,
+}
+
+impl Clipboard {
+    pub fn size(&self) -> [i32; 3] {
+        self.size
+    }
+
+    pub fn blocks(&self) -> &[PlacedBlock] {
+        &self.blocks
+    }
+
+    // La copia girada un cuarto de vuelta en sentido horario visto desde arriba, con las escaleras y
+    // los bloques orientados girados con ella
+    pub fn rotated(&self) -> Clipboard {
+        let mut blocks = self.blocks.clone();
+        structures::rotate(&mut blocks, [0, 0, 0], self.size[0], self.size[2], 1);
+        Clipboard { size: [self.size[2], self.size[1], self.size[0]], blocks }
+    }
+}
+
+// Lo que una edición cambió en una posición: lo que había y lo que quedó. Deshacer vuelve a poner
+// `before` y rehacer `after`, así que cada edición guarda justo lo necesario para invertirse.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub pos: [i32; 3],
+    pub before: Option<PlacedBlock>,
+    pub after: Option<PlacedBlock>,
+    pub fell_from: Option<[i32; 3]>, // Si `after` llegó cayendo, de dónde
+}
+
+// Los bloques de una escena por posición, como se editan en la ventana. Con dos bloques en la misma
+// posición se queda el último.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockGrid {
+    blocks: BTreeMap<[i32; 3], PlacedBlock>,
+    falling: BTreeSet<String>, // Materiales que caen cuando se quedan sin nada debajo, como la arena
+}
+
+impl BlockGrid {
+    pub fn new(blocks: impl IntoIterator<Item = PlacedBlock>) -> Self {
+        BlockGrid { blocks: blocks.into_iter().map(|block| (block.pos, block)).collect(), falling: BTreeSet::new() }
+    }
+
+    // La rejilla con gravedad para los bloques de esos materiales. Solo caen tras una edición; los
+    // que la escena ya trae en el aire se quedan hasta que se toque algo a su lado.
+    pub fn with_gravity(mut self, materials: impl IntoIterator<Item = String>) -> Self {
+        self.falling = materials.into_iter().collect();
+        self
+    }
+
+    pub fn get(&self, pos: [i32; 3]) -> Option<&PlacedBlock> {
+        self.blocks.get(&pos)
+    }
+
+    // Siempre en el mismo orden, así que las mismas posiciones dan las mismas cajas
+    pub fn blocks(&self) -> impl Iterator<Item = &PlacedBlock> {
+        self.blocks.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    // Los bloques de la parte de `region` dentro del mundo, o None si queda entera fuera
+    pub fn copy(&self, region: &Region) -> Option<Clipboard> {
+        let region = region.clipped()?;
+        // Las claves se ordenan primero por x, así que el rango ya descarta las columnas de fuera
+        let blocks = self.blocks.range(region.min..=region.max)
+            .filter(|(pos, _)| region.contains(**pos))
+            .map(|(pos, block)| PlacedBlock { pos: [0, 1, 2].map(|axis| pos[axis] - region.min[axis]), ..block.clone() })
+            .collect();
+        Some(Clipboard { size: region.size(), blocks })
+    }
+
+    fn set(&mut self, pos: [i32; 3], block: Option<PlacedBlock>) -> Option<PlacedBlock> {
+        match block {
+            Some(block) => self.blocks.insert(pos, block),
+            None => self.blocks.remove(&pos),
+        }
+    }
+
+    // Aplica la edición y devuelve lo que cambió, solo las posiciones que de verdad cambian: quitar
+    // donde no hay nada o repintar con el mismo material no cambia nada
+    pub fn apply(&mut self, edit: &Edit) -> Result<Vec<Change>, String> {
+        let targets: Vec<([i32; 3], Option<PlacedBlock>)> = match edit {
+            Edit::Place(block) => {
+                check_position(block.pos)?;
+                vec![(block.pos, Some(block.clone()))]
+            }
+            Edit::Remove(pos) => vec![(*pos, None)],
+            Edit::SetMaterial(pos, material) => match self.get(*pos) {
+                Some(block) => vec![(*pos, Some(PlacedBlock { material: material.clone(), ..block.clone() }))],
+                None => Vec::new(),
+            },
+            // Las regiones se recortan a los límites del mundo: lo que queda fuera no se toca
+            Edit::Fill { region, material } => {
+                let Some(region) = region.clipped() else { return Ok(Vec::new()) };
+                check_volume(&region)?;
+                region.positions()
+                    .map(|pos| (pos, material.as_deref().map(|material| PlacedBlock::full(pos, material))))
+                    .collect()
+            }
+            Edit::Paste { at, clipboard } => {
+                let end = [0, 1, 2].map(|axis| at[axis].saturating_add(clipboard.size[axis] - 1));
+                let Some(region) = Region::new(*at, end).clipped() else { return Ok(Vec::new()) };
+                check_volume(&region)?;
+                let copied: HashMap<[i32; 3], &PlacedBlock> = clipboard.blocks.iter().map(|block| (block.pos, block)).collect();
+                region.positions()
+                    .map(|pos| {
+                        let offset = [0, 1, 2].map(|axis| pos[axis] - at[axis]);
+                        (pos, copied.get(&offset).map(|&block| PlacedBlock { pos, ..block.clone() }))
+                    })
+                    .collect()
+            }
+        };
+        let mut changes = Vec::new();
+        for (pos, after) in targets {
+            if self.get(pos) != after.as_ref() {
+                let before = self.set(pos, after.clone());
+                changes.push(Change { pos, before, after, fell_from: None });
+            }
+        }
+        self.settle(&mut changes);
+        Ok(changes)
+    }
+
+    // Deja caer los bloques con gravedad que la edición dejó sin apoyo o puso en el aire, y los que
+    // se apoyaban en ellos, y apunta cada movimiento en `changes` para que se deshaga con la
+    // edición. Van de abajo arriba, así que una pila cae entera y siempre queda igual. Un bloque sin
+    // nada debajo en toda su columna se queda donde está: no tiene dónde caer.
+    fn settle(&mut self, changes: &mut Vec<Change>) {
+        if self.falling.is_empty() {
+            return;
+        }
+        // Como [y, x, z], para sacarlas por altura
+        let mut pending: BTreeSet<[i32; 3]> = changes.iter()
+            .flat_map(|change| {
+                let [x, y, z] = change.pos;
+                [[y, x, z], [y + 1, x, z]]
+            })
+            .collect();
+        while let Some([y, x, z]) = pending.pop_first() {
+            let pos = [x, y, z];
+            let falls = self.get(pos).is_some_and(|block| self.falling.contains(&block.material));
+            if !falls || self.get([x, y - 1, z]).is_some() {
+                continue;
+            }
+            let Some(ground) = self.ground_below(pos) else { continue };
+            let landing = [x, ground + 1, z];
+            let block = self.set(pos, None).expect("the falling block is in the grid");
+            let landed = PlacedBlock { pos: landing, ..block.clone() };
+            changes.push(Change { pos, before: Some(block), after: None, fell_from: None });
+            self.set(landing, Some(landed.clone()));
+            changes.push(Change { pos: landing, before: None, after: Some(landed), fell_from: Some(pos) });
+            pending.insert([y + 1, x, z]);
+        }
+    }
+
+    // Altura del bloque más alto por debajo de `pos` en su columna
+    fn ground_below(&self, [x, y, z]: [i32; 3]) -> Option<i32> {
+        // Las claves se ordenan por x, luego y y luego z, así que el rango es la franja de esa x
+        // por debajo de y, con todas las z
+        self.blocks.range([x, i32::MIN, i32::MIN]..[x, y, i32::MIN])
+            .rev()
+            .find(|(pos, _)| pos[2] == z)
+            .map(|(pos, _)| pos[1])
+    }
+
+    fn revert(&mut self, changes: &[Change]) {
+        for change in changes.iter().rev() {
+            self.set(change.pos, change.before.clone());
+        }
+    }
+
+    fn replay(&mut self, changes: &[Change]) {
+        for change in changes {
+            self.set(change.pos, change.after.clone());
+        }
+    }
+}
+
+fn check_volume(region: &Region) -> Result<(), String> {
+    match region.volume() {
+        volume if volume > MAX_EDIT_BLOCKS => Err(format!("An edit can change at most {} blocks, this one covers {}", MAX_EDIT_BLOCKS, volume)),
+        _ => Ok(()),
+    }
+}
+
+// Las ediciones hechas, que Ctrl+Z deshace de la última a la primera, y las deshechas, que Ctrl+Y
+// rehace. Guarda como mucho `depth` ediciones; al pasarse se olvida la más antigua, y una edición
+// nueva descarta lo que se podía rehacer.
+pub struct EditHistory {
+    done: VecDeque<Vec<Change>>,
+    undone: Vec<Vec<Change>>,
+    depth: usize,
+}
+
+impl EditHistory {
+    pub fn new(depth: usize) -> Self {
+        EditHistory { done: VecDeque::new(), undone: Vec::new(), depth }
+    }
+
+    // Aplica la edición a `grid` y la apunta. Devuelve si cambió algún bloque.
+    pub fn apply(&mut self, grid: &mut BlockGrid, edit: &Edit) -> Result<bool, String> {
+        let changes = grid.apply(edit)?;
+        if changes.is_empty() {
+            return Ok(false);
+        }
+        self.undone.clear();
+        self.done.push_back(changes);
+        while self.done.len() > self.depth {
+            self.done.pop_front();
+        }
+        Ok(true)
+    }
+
+    // Devuelven si había algo que deshacer o rehacer
+    pub fn undo(&mut self, grid: &mut BlockGrid) -> bool {
+        let Some(changes) = self.done.pop_back() else { return false };
+        grid.revert(&changes);
+        self.undone.push(changes);
+        true
+    }
+
+    pub fn redo(&mut self, grid: &mut BlockGrid) -> bool {
+        let Some(changes) = self.undone.pop() else { return false };
+        grid.replay(&changes);
+        self.done.push_back(changes);
+        true
+    }
+
+    pub fn undo_steps(&self) -> usize {
+        self.done.len()
+    }
+
+    pub fn redo_steps(&self) -> usize {
+        self.undone.len()
+    }
+
+    // Lo que cambió la última edición hecha o rehecha, con las caídas que provocó
+    pub fn last_changes(&self) -> Option<&[Change]> {
+        self.done.back().map(Vec::as_slice)
+    }
+}
+
+// Los bloques que una edición dejó caer, que ya están en su sitio en la rejilla, mientras se ven
+// bajando hasta él. Todos empiezan a la vez y caen con la misma aceleración, así que una pila baja
+// junta y sus bloques llegan a la vez.
+#[derive(Clone, Debug)]
+pub struct FallAnimation {
+    falls: Vec<(PlacedBlock, i32)>, // El bloque donde quedó y cuántos bloques cae
+    elapsed: f32,
+}
+
+impl FallAnimation {
+    // Las caídas apuntadas en `changes`, o None si no cayó nada
+    pub fn new(changes: &[Change]) -> Option<Self> {
+        let falls: Vec<(PlacedBlock, i32)> = changes.iter()
+            .filter_map(|change| Some((change.after.clone()?, change.fell_from?[1] - change.pos[1])))
+            .collect();
+        (!falls.is_empty()).then_some(FallAnimation { falls, elapsed: 0.0 })
+    }
+
+    pub fn advance(&mut self, seconds: f32) {
+        self.elapsed += seconds;
+    }
+
+    // Los bloques que siguen en el aire y a cuántos bloques de su sitio van
+    pub fn airborne(&self) -> impl Iterator<Item = (&PlacedBlock, f32)> + '_ {
+        let fallen = 0.5 * FALL_ACCELERATION * self.elapsed * self.elapsed;
+        self.falls.iter().filter_map(move |(block, height)| {
+            let left = *height as f32 - fallen;
+            (left > 0.0).then_some((block, left))
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.airborne().next().is_none()
+    }
+}
+
+// Bloque que se ve en el píxel (x, y) de una imagen de width x height y su vecino por la cara vista,
+// donde iría un bloque nuevo puesto contra ella
+pub fn pick_block(objects: &[Primitive], camera: &Camera, x: Float, y: Float, width: Float, height: Float) -> Option<([i32; 3], [i32; 3])> {
+    let (origin, direction) = camera.ray(x, y, width, height)?;
+    let ray = Ray::new(origin, direction);
+    let hit = objects.iter()
+        .filter_map(|object| object.ray_intersect(&ray).filter(|hit| hit.t > 0.0))
+        .min_by(|a, b| a.t.total_cmp(&b.t))?;
+    let block_at = |point: Vec3| [point.x, point.y, point.z].map(|value| (value / BLOCK_SIZE).floor() as i32);
+    let step = hit.normal * (BLOCK_SIZE * 0.5);
+    Some((block_at(hit.point - step), block_at(hit.point + step)))
+}
+
+// El material que sigue a `current` en `palette`, o el anterior si no `forward`, dando la vuelta en
+// los extremos. Uno que no está en la paleta pasa al primero o al último.
+pub fn next_material<'a>(palette: &'a [String], current: &str, forward: bool) -> Option<&'a str> {
+    let len = palette.len();
+    let index = match (palette.iter().position(|name| name == current), forward) {
+        (Some(index), true) => (index + 1) % len,
+        (Some(index), false) => (index + len - 1) % len,
+        (None, true) => 0,
+        (None, false) => len.checked_sub(1)?,
+    };
+    palette.get(index).map(String::as_str)
+}
+
+// Dibuja encima de la imagen las doce aristas de `region` vistas desde `camera`, el contorno de la
+// selección. Las aristas con un extremo detrás de la cámara no se dibujan.
+pub fn draw_region(pixels: &mut [u32], width: usize, height: usize, camera: &Camera, region: &Region, color: Color) {
+    let corner = |index: usize| {
+        let [x, y, z] = [0, 1, 2].map(|axis| if index & (1 << axis) == 0 { region.min[axis] } else { region.max[axis] + 1 });
+        Vec3::new(x as Float, y as Float, z as Float) * BLOCK_SIZE
+    };
+    let (w, h) = (width as Float, height as Float);
+    for start in 0..8 {
+        for end in [1, 2, 4].map(|bit| start | bit).into_iter().filter(|&end| end != start) {
+            let (Some(a), Some(b)) = (camera.project(&corner(start), w, h), camera.project(&corner(end), w, h)) else {
+                continue;
+            };
+            // Un paso por píxel, sin pasar de unos pocos anchos de ventana por las aristas muy largas
+            let steps = (b.0 - a.0).abs().max((b.1 - a.1).abs()).min(4.0 * (w + h)).ceil().max(1.0) as usize;
+            for step in 0..=steps {
+                let t = step as Float / steps as Float;
+                let (x, y) = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+                if x >= 0.0 && y >= 0.0 && x < w && y < h {
+                    pixels[y as usize * width + x as usize] = color.to_hex();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockShape;
+    use crate::cube::Cube;
+    use crate::block::Facing;
+    use crate::material::Material;
+
+    const MATERIALS: [&str; 4] = ["stone", "wood", "grass", "glass"];
+
+    fn hill() -> BlockGrid {
+        BlockGrid::new((0..6).flat_map(|x| (0..6).filter(move |&z| x + z < 8).map(move |z| PlacedBlock::full([x, (x + z) % 3, z], "stone"))))
+    }
+
+    // A small deterministic generator, so the script is the same on every run
+    struct Script(u64);
+
+    impl Script {
+        fn next(&mut self, below: i32) -> i32 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % below as u64) as i32
+        }
+
+        fn pos(&mut self) -> [i32; 3] {
+            [self.next(8) - 1, self.next(4) - 1, self.next(8) - 1]
+        }
+
+        fn edit(&mut self, grid: &BlockGrid) -> Edit {
+            let material = MATERIALS[self.next(4) as usize].to_string();
+            match self.next(5) {
+                0 => Edit::Place(PlacedBlock::full(self.pos(), &material)),
+                1 => Edit::Remove(self.pos()),
+                2 => Edit::SetMaterial(self.pos(), material),
+                3 => Edit::Fill { region: Region::new(self.pos(), self.pos()), material: (self.next(2) == 0).then_some(material) },
+                _ => {
+                    let copied = grid.copy(&Region::new(self.pos(), self.pos())).unwrap();
+                    let clipboard = if self.next(2) == 0 { copied.rotated() } else { copied };
+                    Edit::Paste { at: self.pos(), clipboard }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn undoing_every_edit_restores_the_world() {
+        let original = hill();
+        let mut grid = original.clone();
+        let mut history = EditHistory::new(100);
+        let mut script = Script(7);
+        let mut states = vec![grid.clone()];
+        for _ in 0..50 {
+            let edit = script.edit(&grid);
+            if history.apply(&mut grid, &edit).unwrap() {
+                states.push(grid.clone());
+            }
+        }
+        assert!(history.undo_steps() > 10, "many edits change something: {}", history.undo_steps());
+        assert_ne!(grid, original);
+
+        // Each undo goes back exactly one state, and redoing them all comes back to the end
+        for state in states.iter().rev().skip(1) {
+            assert!(history.undo(&mut grid));
+            assert_eq!(&grid, state);
+        }
+        assert!(!history.undo(&mut grid), "nothing left to undo");
+        assert_eq!(grid, original);
+        while history.redo(&mut grid) {}
+        assert_eq!(&grid, states.last().unwrap());
+    }
+
+    #[test]
+    fn edits_only_record_what_they_change() {
+        let mut grid = hill();
+        let changes = grid.apply(&Edit::Fill { region: Region::new([0, 0, 0], [1, 2, 1]), material: Some("stone".to_string()) }).unwrap();
+        // The hill already has one stone block in each of the four columns
+        assert_eq!(changes.len(), 3 * 4 - 4);
+        assert!(changes.iter().all(|change| change.before.is_none()));
+        assert!(grid.apply(&Edit::Remove([40, 0, 0])).unwrap().is_empty());
+        assert!(grid.apply(&Edit::SetMaterial([40, 0, 0], "wood".to_string())).unwrap().is_empty());
+        assert!(grid.apply(&Edit::SetMaterial([0, 0, 0], "stone".to_string())).unwrap().is_empty());
+
+        let slab = PlacedBlock { shape: BlockShape::BottomSlab, ..PlacedBlock::full([5, 0, 0], "stone") };
+        grid.apply(&Edit::Place(slab.clone())).unwrap();
+        let changes = grid.apply(&Edit::SetMaterial([5, 0, 0], "wood".to_string())).unwrap();
+        assert_eq!(changes[0].after, Some(PlacedBlock { material: "wood".to_string(), ..slab.clone() }), "the shape is kept");
+        assert_eq!(changes[0].before, Some(slab));
+
+        let error = grid.apply(&Edit::Fill { region: Region::new([0, 0, 0], [1024, 1024, 0]), material: None }).unwrap_err();
+        assert!(error.contains("at most"), "{}", error);
+    }
+
+    #[test]
+    fn pastes_are_single_edits_turned_and_clipped_to_the_world() {
+        let stairs = PlacedBlock { shape: BlockShape::Stairs { facing: Facing::North, upside_down: false }, ..PlacedBlock::full([1, 1, 0], "wood") };
+        let mut grid = BlockGrid::new([PlacedBlock::full([0, 0, 0], "stone"), PlacedBlock::full([1, 0, 0], "wood"), stairs]);
+        let clipboard = grid.copy(&Region::new([1, 1, 0], [0, 0, 0])).unwrap();
+        assert_eq!((clipboard.size(), clipboard.blocks().len()), ([2, 2, 1], 3));
+
+        // A quarter turn clockwise seen from above sends +x to +z
+        let turned = clipboard.rotated();
+        assert_eq!(turned.size(), [1, 2, 2]);
+        let mut history = EditHistory::new(10);
+        history.apply(&mut grid, &Edit::Place(PlacedBlock::full([5, 1, 5], "glass"))).unwrap();
+        assert!(history.apply(&mut grid, &Edit::Paste { at: [5, 0, 5], clipboard: turned }).unwrap());
+        assert_eq!(grid.get([5, 0, 6]).map(|block| block.material.as_str()), Some("wood"));
+        assert_eq!(grid.get([5, 1, 6]).map(|block| block.shape), Some(BlockShape::Stairs { facing: Facing::East, upside_down: false }));
+        assert_eq!(grid.get([5, 1, 5]), None, "the copy's air is pasted too");
+        assert!(history.undo(&mut grid));
+        assert_eq!(grid.len(), 4, "one undo takes the whole paste back");
+        assert!(grid.get([5, 1, 5]).is_some());
+
+        // Only the part inside the world is pasted, and a region wholly outside it copies nothing
+        let edge = MAX_COORDINATE;
+        let changes = grid.apply(&Edit::Paste { at: [edge, 0, 0], clipboard }).unwrap();
+        assert_eq!(changes.iter().map(|change| change.pos).collect::<Vec<_>>(), vec![[edge, 0, 0]]);
+        assert!(grid.apply(&Edit::Fill { region: Region::new([edge + 1, 0, 0], [edge + 9, 0, 0]), material: None }).unwrap().is_empty());
+        assert_eq!(grid.copy(&Region::new([edge + 1, 0, 0], [edge + 9, 0, 0])), None);
+    }
+
+    #[test]
+    fn materials_cycle_through_the_palette_both_ways() {
+        let palette: Vec<String> = ["stone", "wood", "glass"].map(String::from).to_vec();
+        assert_eq!(next_material(&palette, "wood", true), Some("glass"));
+        assert_eq!(next_material(&palette, "glass", true), Some("stone"));
+        assert_eq!(next_material(&palette, "stone", false), Some("glass"));
+        assert_eq!(next_material(&palette, "grass", true), Some("stone"));
+        assert_eq!(next_material(&palette, "grass", false), Some("glass"));
+        assert_eq!(next_material(&[], "stone", false), None);
+    }
+
+    #[test]
+    fn selections_are_outlined_where_the_camera_sees_them() {
+        let camera = Camera::new(Vec3::new(0.25, 0.25, 4.0), Vec3::new(0.25, 0.25, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let mut pixels = vec![0; 64 * 64];
+        draw_region(&mut pixels, 64, 64, &camera, &Region::new([0, 0, 0], [0, 0, 0]), Color::new(255, 255, 255));
+        let marked = pixels.iter().filter(|&&pixel| pixel != 0).count();
+        assert!(marked > 8, "{} pixels", marked);
+        assert_eq!(pixels[32 * 64 + 32], 0, "only the edges are drawn");
+
+        let behind = Camera::new(Vec3::new(0.25, 0.25, -4.0), Vec3::new(0.25, 0.25, -8.0), Vec3::new(0.0, 1.0, 0.0));
+        let mut pixels = vec![0; 64 * 64];
+        draw_region(&mut pixels, 64, 64, &behind, &Region::new([0, 0, 0], [0, 0, 0]), Color::new(255, 255, 255));
+        assert!(pixels.iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn history_is_capped_and_new_edits_drop_the_redo_steps() {
+        let mut grid = BlockGrid::default();
+        let mut history = EditHistory::new(3);
+        for x in 0..5 {
+            assert!(history.apply(&mut grid, &Edit::Place(PlacedBlock::full([x, 0, 0], "stone"))).unwrap());
+        }
+        assert_eq!(history.undo_steps(), 3);
+        while history.undo(&mut grid) {}
+        assert_eq!(grid.len(), 2, "the two oldest edits can't be undone");
+
+        assert!(history.redo(&mut grid));
+        assert!(history.apply(&mut grid, &Edit::Remove([0, 0, 0])).unwrap());
+        assert_eq!(history.redo_steps(), 0);
+        assert!(!history.apply(&mut grid, &Edit::Remove([0, 0, 0])).unwrap(), "nothing changed, nothing recorded");
+        assert_eq!(history.undo_steps(), 2);
+    }
+
+    #[test]
+    fn unsupported_sand_falls_within_the_edit_that_freed_it() {
+        let sand = |pos: [i32; 3]| PlacedBlock::full(pos, "sand");
+        let ground = (0..3).map(|x| PlacedBlock::full([x, 0, 0], "stone"));
+        let column = [PlacedBlock::full([1, 3, 0], "stone"), sand([1, 4, 0]), sand([1, 5, 0]), sand([1, 6, 0])];
+        let mut grid = BlockGrid::new(ground.chain(column)).with_gravity(["sand".to_string()]);
+        let original = grid.clone();
+        let mut history = EditHistory::new(10);
+
+        // Taking out the stone under the stack drops all of it onto the ground, in the same order
+        assert!(history.apply(&mut grid, &Edit::Remove([1, 3, 0])).unwrap());
+        let column = |grid: &BlockGrid| (1..=6).map(|y| grid.get([1, y, 0]).map(|block| block.material.clone())).collect::<Vec<_>>();
+        let sand_name = Some("sand".to_string());
+        assert_eq!(column(&grid), [sand_name.clone(), sand_name.clone(), sand_name, None, None, None]);
+        let fallen = grid.clone();
+
+        let mut animation = FallAnimation::new(history.last_changes().unwrap()).expect("three blocks fell");
+        assert_eq!(animation.airborne().map(|(block, left)| (block.pos[1], left)).collect::<Vec<_>>(), [(1, 3.0), (2, 3.0), (3, 3.0)]);
+        animation.advance(0.25);
+        assert!(animation.airborne().all(|(_, left)| (left - 2.0).abs() < 1e-4), "the stack falls together");
+        animation.advance(0.25);
+        assert!(animation.is_done());
+
+        // One undo puts back the stone and the stack where they were
+        assert!(history.undo(&mut grid));
+        assert_eq!(grid, original);
+        assert!(history.redo(&mut grid));
+        assert_eq!(grid, fallen);
+
+        // Sand placed in the air drops onto the first block under it, or stays with nothing under it
+        history.apply(&mut grid, &Edit::Place(sand([0, 5, 0]))).unwrap();
+        assert_eq!(grid.get([0, 1, 0]).map(|block| block.material.as_str()), Some("sand"));
+        history.apply(&mut grid, &Edit::Place(sand([9, 5, 9]))).unwrap();
+        assert!(grid.get([9, 5, 9]).is_some());
+        assert!(FallAnimation::new(history.last_changes().unwrap()).is_none());
+        history.apply(&mut grid, &Edit::Place(PlacedBlock::full([2, 5, 0], "stone"))).unwrap();
+        assert!(grid.get([2, 5, 0]).is_some(), "stone doesn't fall");
+    }
+
+    #[test]
+    fn picking_finds_the_block_and_where_a_new_one_goes() {
+        let material = Material::new(Color::new(128, 128, 128), 10.0, [0.9, 0.1, 0.0, 0.0], 1.0);
+        let objects = [Primitive::Cube(Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.5, 1.0), &material))];
+        let camera = Camera::new(Vec3::new(0.75, 3.0, 0.25), Vec3::new(0.75, 0.0, 0.25), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(pick_block(&objects, &camera, 50.0, 50.0, 100.0, 100.0), Some(([1, 0, 0], [1, 1, 0])));
+        let away = Camera::new(Vec3::new(0.75, 3.0, 0.25), Vec3::new(0.75, 6.0, 0.25), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(pick_block(&objects, &away, 50.0, 50.0, 100.0, 100.0), None);
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+use std::path::PathBuf;
+
+// Errores de las funciones públicas de la biblioteca, con el mensaje ya listo para el usuario.
+// Quien llama decide qué hacer con ellos: el programa termina con un código u otro según la
+// variante, y la ventana sigue abierta con la escena anterior si falla una recarga.
+#[derive(Debug)]
+pub enum RayTracerError {
+    Asset(String),  // Un recurso que no está en ninguno de los sitios donde se busca o no se entiende
+    Scene(String),  // Un archivo de escena o de mundo que no se entiende o no es válido
+    Image(String),  // Una imagen que no se pudo decodificar o codificar
+    Io { path: PathBuf, source: std::io::Error }, // Un archivo que no se pudo leer o escribir
+    Window(String), // La ventana no se pudo abrir o dibujar
+    Gpu(String),    // No hay GPU o el backend de GPU no puede con la escena
+}
+
+impl RayTracerError {
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        RayTracerError::Io { path: path.into(), source }
+    }
+
+    // Error de `image` al leer o escribir `path`; los de lectura y escritura conservan su causa
+    pub fn image(path: impl Into<PathBuf>, error: image::ImageError) -> Self {
+        let path = path.into();
+        match error {
+            image::ImageError::IoError(source) => RayTracerError::Io { path, source },
+            error => RayTracerError::Image(format!("{}: {}", path.display(), error)),
+        }
+    }
+}
+
+impl fmt::Display for RayTracerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RayTracerError::Asset(message)
+            | RayTracerError::Scene(message)
+            | RayTracerError::Image(message)
+            | RayTracerError::Window(message)
+            | RayTracerError::Gpu(message) => write!(f, "{}", message),
+            RayTracerError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for RayTracerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RayTracerError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// Los módulos que informan con `String` (trabajos, servidor, FFI) pueden usar `?` directamente
+impl From<RayTracerError> for String {
+    fn from(error: RayTracerError) -> Self {
+        error.to_string()
+    }
+}
@@ -0,0 +1,499 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+use crate::block::BLOCK_SIZE;
+use crate::color::Color;
+use crate::cube::Cube;
+use crate::error::RayTracerError;
+use crate::material::Material;
+use crate::math::{to_f32, Float, Vec3};
+use crate::ray_intersect::{CubeFace, FaceOrientation};
+use crate::render::{shininess_to_roughness, texture_slot};
+use crate::texture::Texture;
+
+// Exportación de la geometría a OBJ + MTL o a glTF binario (.glb), por ejemplo para llevar una
+// construcción a Blender.
+//
+// Convención: las coordenadas se escriben tal cual, en el sistema de la escena, que es de mano
+// derecha con +Y hacia arriba. Es la convención habitual de OBJ y la que espera el importador de
+// Blender con sus opciones por defecto (Forward -Z, Up Y), que lo convierte a su +Z arriba, y la de
+// glTF. Una unidad de la escena es un metro, así que un bloque mide 0.5.
+//
+// Cada cara sale como un cuadrilátero en sentido antihorario visto desde fuera, con su normal. Las
+// caras tapadas por completo por un bloque opaco vecino no se escriben, y las tapadas en parte se
+// escriben bloque a bloque solo donde se ven. Las coordenadas de textura repiten la textura una vez
+// por bloque a partir de la posición en el mundo, como el renderer (sin el reflejo que este hace en
+// las coordenadas negativas ni el uv_transform de la escena), así que las cajas unidas se ven igual.
+
+// Caras de cada eje, la del lado negativo primero
+const FACES: [[CubeFace; 2]; 3] = [
+    [CubeFace::Left, CubeFace::Right],
+    [CubeFace::Bottom, CubeFace::Top],
+    [CubeFace::Back, CubeFace::Front],
+];
+
+// Ejes de la textura (u, v) en cada eje de la normal, los mismos de `Hit::uv`
+const UV_AXES: [(usize, usize); 3] = [(2, 1), (0, 2), (0, 1)];
+
+pub struct ObjExport {
+    pub obj: String,
+    pub mtl: String,
+    pub textures: Vec<(String, Texture)>, // Imágenes que nombra el MTL, con su nombre de archivo
+}
+
+// Escribe `path` (.obj), el .mtl con el mismo nombre y una imagen PNG por textura usada, todo en
+// la misma carpeta
+pub fn export_obj(objects: &[Cube], path: &Path) -> Result<(), RayTracerError> {
+    let stem = path.file_stem().and_then(|stem| stem.to_str())
+        .ok_or_else(|| RayTracerError::io(path, std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a valid OBJ file name")))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mtl_name = format!("{}.mtl", stem);
+    let export = to_obj(objects, &mtl_name, stem);
+    let write = |name: &Path, contents: &str| std::fs::write(name, contents).map_err(|e| RayTracerError::io(name, e));
+    write(path, &export.obj)?;
+    write(&dir.join(&mtl_name), &export.mtl)?;
+    for (name, texture) in &export.textures {
+        let file = dir.join(name);
+        texture_image(texture).save(&file).map_err(|e| RayTracerError::image(&file, e))?;
+    }
+    Ok(())
+}
+
+fn texture_image(texture: &Texture) -> image::RgbImage {
+    image::RgbImage::from_fn(texture.width, texture.height, |x, y| {
+        let color = texture.pixels[(y * texture.width + x) as usize];
+        image::Rgb([color.r, color.g, color.b])
+    })
+}
+
+// Normales de las caras en el orden de FACES: -x, +x, -y, +y, -z, +z
+pub const NORMALS: [[Float; 3]; 6] = [[-1.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0], [0.0, 0.0, 1.0]];
+
+// Cara visible: esquinas en sentido antihorario vista desde fuera, coordenadas de textura como las
+// del renderer (v hacia abajo en la imagen) y su normal, como índice en NORMALS
+pub struct Quad {
+    pub corners: [Vec3; 4],
+    pub uvs: [[Float; 2]; 4],
+    pub normal: usize,
+}
+
+// Caras visibles que comparten material y textura, en el orden en que aparecen en la escena
+pub struct FaceGroup<'a> {
+    pub name: String,         // Nombre del material, con `_<slot>` si usa otra textura que la primera
+    pub material: &'a Material,
+    pub slot: Option<usize>,  // Textura del material que llevan, si tiene
+    pub quads: Vec<Quad>,
+}
+
+// Caras visibles de la escena agrupadas por material; los materiales sin nombre del registro se
+// llaman `object_<índice>`
+pub fn face_groups(objects: &[Cube]) -> Vec<FaceGroup<'_>> {
+    let occupied = opaque_cells(objects);
+    let mut groups: Vec<FaceGroup> = Vec::new();
+    let mut group_of: HashMap<String, usize> = HashMap::new();
+    for (index, cube) in objects.iter().enumerate() {
+        let material = &cube.material;
+        let base_name = match material.name.as_str() {
+            "" => format!("object_{}", index),
+            name => name.to_string(),
+        };
+        for axis in 0..3 {
+            let (b, c) = ((axis + 1) % 3, (axis + 2) % 3);
+            let (u_axis, v_axis) = UV_AXES[axis];
+            for positive in [false, true] {
+                let descriptor = &cube.faces[FACES[axis][usize::from(positive)] as usize];
+                let slot = (!material.textures.is_empty()).then(|| texture_slot(material, &descriptor.texture_face));
+                let name = match slot {
+                    Some(slot) if slot > 0 => format!("{}_{}", base_name, slot),
+                    _ => base_name.clone(),
+                };
+                let group = *group_of.entry(name.clone()).or_insert_with(|| {
+                    groups.push(FaceGroup { name, material, slot, quads: Vec::new() });
+                    groups.len() - 1
+                });
+                let plane = if positive { cube.max[axis] } else { cube.min[axis] };
+                for [b0, b1, c0, c1] in visible_quads(cube, axis, positive, &occupied) {
+                    // Antihorario visto desde +axis; del lado negativo se recorre al revés
+                    let mut corners = [(b0, c0), (b1, c0), (b1, c1), (b0, c1)].map(|(bv, cv)| {
+                        let mut point = Vec3::zeros();
+                        point[axis] = plane;
+                        point[b] = bv;
+                        point[c] = cv;
+                        point
+                    });
+                    if !positive {
+                        corners.reverse();
+                    }
+                    let mut uvs = corners.map(|point| [point[u_axis] / BLOCK_SIZE, point[v_axis] / BLOCK_SIZE]);
+                    // Girar las coordenadas sin reducirlas a [0, 1) da lo mismo con la textura repetida
+                    if descriptor.orientation != FaceOrientation::default() {
+                        uvs = uvs.map(|[u, v]| {
+                            let (u, v) = descriptor.orientation.apply((to_f32(u), to_f32(v)));
+                            [u as Float, v as Float]
+                        });
+                    }
+                    groups[group].quads.push(Quad { corners, uvs, normal: axis * 2 + usize::from(positive) });
+                }
+            }
+        }
+    }
+    groups.retain(|group| !group.quads.is_empty());
+    groups
+}
+
+// Nombre de la imagen de la textura de un grupo al exportarla junto al archivo
+fn texture_file(prefix: &str, group: &FaceGroup) -> Option<String> {
+    group.slot.map(|_| format!("{}_{}.png", prefix, group.name))
+}
+
+// OBJ que usa la biblioteca de materiales `mtl_name`; las texturas se llaman `<prefix>_<grupo>.png`
+pub fn to_obj(objects: &[Cube], mtl_name: &str, prefix: &str) -> ObjExport {
+    let mut obj = format!("# Exported from RayTracerMinecraft: right-handed, +Y up, 1 unit = 1 m\nmtllib {}\n", mtl_name);
+    for [x, y, z] in NORMALS {
+        writeln!(obj, "vn {} {} {}", x, y, z).unwrap();
+    }
+    let mut mtl = String::new();
+    let mut textures: Vec<(String, Texture)> = Vec::new();
+    let mut vertices = 0;
+    for group in face_groups(objects) {
+        let texture = texture_file(prefix, &group);
+        if let (Some(file), Some(slot)) = (&texture, group.slot) {
+            textures.push((file.clone(), group.material.textures[slot].clone()));
+        }
+        write_material(&mut mtl, &group.name, group.material, texture.as_deref());
+        writeln!(obj, "g {}\nusemtl {}", group.name, group.name).unwrap();
+        for quad in &group.quads {
+            let mut face_line = String::from("f");
+            for (point, [u, v]) in quad.corners.iter().zip(quad.uvs) {
+                // Las imágenes empiezan arriba y la v de OBJ abajo, de ahí el signo
+                writeln!(obj, "v {} {} {}\nvt {} {}", point.x, point.y, point.z, u, -v).unwrap();
+                vertices += 1;
+                write!(face_line, " {}/{}/{}", vertices, vertices, quad.normal + 1).unwrap();
+            }
+            writeln!(obj, "{}", face_line).unwrap();
+        }
+    }
+    ObjExport { obj, mtl, textures }
+}
+
+fn write_material(mtl: &mut String, name: &str, material: &Material, texture: Option<&str>) {
+    let [_, specular, _, transparency] = material.properties;
+    let channel = |value: u8| value as f32 / 255.0;
+    writeln!(mtl, "newmtl {}", name).unwrap();
+    // Con textura, Blender toma el color de la imagen; el color del material solo la teñiría
+    match texture {
+        Some(_) => writeln!(mtl, "Kd 1 1 1").unwrap(),
+        None => writeln!(mtl, "Kd {} {} {}", channel(material.color.r), channel(material.color.g), channel(material.color.b)).unwrap(),
+    }
+    writeln!(mtl, "Ks {} {} {}\nNs {}\nd {}\nNi {}", specular, specular, specular, material.shininess, 1.0 - transparency, material.refractive_index).unwrap();
+    let emission = material.emission;
+    if emission != Color::black() {
+        writeln!(mtl, "Ke {} {} {}", channel(emission.r), channel(emission.g), channel(emission.b)).unwrap();
+    }
+    if let Some(texture) = texture {
+        writeln!(mtl, "map_Kd {}", texture).unwrap();
+    }
+    mtl.push('\n');
+}
+
+pub fn export_gltf(objects: &[Cube], path: &Path) -> Result<(), RayTracerError> {
+    let glb = to_glb(objects)?;
+    std::fs::write(path, glb).map_err(|e| RayTracerError::io(path, e))
+}
+
+// glTF 2.0 binario con una malla por grupo de caras (material y textura), con posiciones, normales,
+// coordenadas de textura e índices, y las texturas incrustadas como PNG. Los materiales Phong se
+// aproximan a PBR: el color base sale del color o de la textura, la transparencia pasa a alfa, la
+// reflectividad a metalicidad y el brillo a rugosidad con la inversa de la conversión del renderer.
+pub fn to_glb(objects: &[Cube]) -> Result<Vec<u8>, RayTracerError> {
+    let groups = face_groups(objects);
+    if groups.is_empty() {
+        return Err(RayTracerError::Scene("The scene has no visible faces to export".to_string()));
+    }
+    let mut bin: Vec<u8> = Vec::new();
+    let mut views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut meshes = Vec::new();
+    let mut materials = Vec::new();
+    let mut images = Vec::new();
+    // Vista del búfer con `bytes`, alineada a 4 como piden los accesores
+    let mut push_view = |bin: &mut Vec<u8>, bytes: &[u8], target: Option<u32>| {
+        let offset = bin.len();
+        bin.extend_from_slice(bytes);
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+        let target = target.map(|target| format!(",\"target\":{}", target)).unwrap_or_default();
+        views.push(format!("{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{}{}}}", offset, bytes.len(), target));
+        views.len() - 1
+    };
+    for group in &groups {
+        let vertex_count = group.quads.len() * 4;
+        let (mut positions, mut normals, mut uvs, mut indices) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+        for (i, quad) in group.quads.iter().enumerate() {
+            for (point, uv) in quad.corners.iter().zip(quad.uvs) {
+                for axis in 0..3 {
+                    let value = to_f32(point[axis]);
+                    min[axis] = min[axis].min(value);
+                    max[axis] = max[axis].max(value);
+                    positions.extend_from_slice(&value.to_le_bytes());
+                    normals.extend_from_slice(&to_f32(NORMALS[quad.normal][axis]).to_le_bytes());
+                }
+                // glTF también cuenta v desde arriba de la imagen, como el renderer
+                for value in uv {
+                    uvs.extend_from_slice(&to_f32(value).to_le_bytes());
+                }
+            }
+            let first = (i * 4) as u32;
+            for index in [0, 1, 2, 0, 2, 3] {
+                indices.extend_from_slice(&(first + index).to_le_bytes());
+            }
+        }
+        let mut accessor = |view: usize, component: u32, count: usize, kind: &str, bounds: &str| {
+            accessors.push(format!("{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"{}\"{}}}", view, component, count, kind, bounds));
+            accessors.len() - 1
+        };
+        let bounds = format!(",\"min\":[{},{},{}],\"max\":[{},{},{}]", min[0], min[1], min[2], max[0], max[1], max[2]);
+        let position = accessor(push_view(&mut bin, &positions, Some(ARRAY_BUFFER)), FLOAT, vertex_count, "VEC3", &bounds);
+        let normal = accessor(push_view(&mut bin, &normals, Some(ARRAY_BUFFER)), FLOAT, vertex_count, "VEC3", "");
+        let uv = accessor(push_view(&mut bin, &uvs, Some(ARRAY_BUFFER)), FLOAT, vertex_count, "VEC2", "");
+        let index = accessor(push_view(&mut bin, &indices, Some(ELEMENT_ARRAY_BUFFER)), UNSIGNED_INT, group.quads.len() * 6, "SCALAR", "");
+
+        let material = group.material;
+        let [_, _, reflectivity, transparency] = material.properties;
+        let alpha = 1.0 - transparency;
+        let base = match group.slot {
+            Some(slot) => {
+                let mut png = Vec::new();
+                texture_image(&material.textures[slot])
+                    .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+                    .map_err(|e| RayTracerError::Image(format!("Failed to encode texture of {}: {}", group.name, e)))?;
+                let view = push_view(&mut bin, &png, None);
+                images.push(format!("{{\"bufferView\":{},\"mimeType\":\"image/png\"}}", view));
+                format!("\"baseColorFactor\":[1,1,1,{}],\"baseColorTexture\":{{\"index\":{}}}", alpha, images.len() - 1)
+            }
+            None => {
+                let [r, g, b] = linear(material.color);
+                format!("\"baseColorFactor\":[{},{},{},{}]", r, g, b, alpha)
+            }
+        };
+        let [er, eg, eb] = linear(material.emission);
+        let blend = if alpha < 1.0 { ",\"alphaMode\":\"BLEND\"" } else { "" };
+        materials.push(format!(
+            "{{\"name\":{},\"pbrMetallicRoughness\":{{{},\"metallicFactor\":{},\"roughnessFactor\":{}}},\"emissiveFactor\":[{},{},{}]{}}}",
+            json_string(&group.name), base, reflectivity.clamp(0.0, 1.0), shininess_to_roughness(material.shininess), er, eg, eb, blend,
+        ));
+        meshes.push(format!(
+            "{{\"name\":{},\"primitives\":[{{\"attributes\":{{\"POSITION\":{},\"NORMAL\":{},\"TEXCOORD_0\":{}}},\"indices\":{},\"material\":{}}}]}}",
+            json_string(&group.name), position, normal, uv, index, materials.len() - 1,
+        ));
+    }
+
+    let nodes: Vec<String> = (0..meshes.len()).map(|mesh| format!("{{\"mesh\":{}}}", mesh)).collect();
+    let list = |items: &[String]| items.join(",");
+    let mut json = format!(
+        "{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"RayTracerMinecraft\"}},\"scene\":0,\"scenes\":[{{\"nodes\":[{}]}}],\"nodes\":[{}],\"meshes\":[{}],\"materials\":[{}],\"accessors\":[{}],\"bufferViews\":[{}],\"buffers\":[{{\"byteLength\":{}}}]",
+        (0..nodes.len()).map(|node| node.to_string()).collect::<Vec<_>>().join(","),
+        list(&nodes), list(&meshes), list(&materials), list(&accessors), list(&views), bin.len(),
+    );
+    if !images.is_empty() {
+        // Texturas pixeladas como en el juego y repetidas en cada bloque
+        let textures: Vec<String> = (0..images.len()).map(|image| format!("{{\"sampler\":0,\"source\":{}}}", image)).collect();
+        write!(json, ",\"images\":[{}],\"textures\":[{}],\"samplers\":[{{\"magFilter\":9728,\"minFilter\":9728}}]", list(&images), list(&textures)).unwrap();
+    }
+    json.push('}');
+    while json.len() % 4 != 0 {
+        json.push(' ');
+    }
+
+    let mut glb = Vec::with_capacity(12 + 8 + json.len() + 8 + bin.len());
+    glb.extend_from_slice(b"glTF");
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+    glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"JSON");
+    glb.extend_from_slice(json.as_bytes());
+    glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"BIN\0");
+    glb.extend_from_slice(&bin);
+    Ok(glb)
+}
+
+// Constantes de glTF (las de OpenGL) para los tipos de componente y los destinos de las vistas
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// glTF guarda los colores en lineal; los de los materiales son sRGB
+fn linear(color: Color) -> [f32; 3] {
+    [color.r, color.g, color.b].map(|value| (value as f32 / 255.0).powf(2.2))
+}
+
+fn json_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn on_grid(value: Float) -> bool {
+    let blocks = value / BLOCK_SIZE;
+    (blocks - blocks.round()).abs() < 1e-4
+}
+
+fn cell(value: Float) -> i32 {
+    (value / BLOCK_SIZE).round() as i32
+}
+
+// Bloques de la cuadrícula ocupados por cajas opacas alineadas con ella; las demás (cristal,
+// portales, cajas libres o partes de losas y vallas) no tapan a sus vecinos
+fn opaque_cells(objects: &[Cube]) -> HashSet<[i32; 3]> {
+    let mut cells = HashSet::new();
+    for cube in objects {
+        let aligned = (0..3).all(|axis| on_grid(cube.min[axis]) && on_grid(cube.max[axis]));
+        if !aligned || cube.material.properties[3] > 0.0 || cube.material.portal.is_some() {
+            continue;
+        }
+        for x in cell(cube.min.x)..cell(cube.max.x) {
+            for y in cell(cube.min.y)..cell(cube.max.y) {
+                for z in cell(cube.min.z)..cell(cube.max.z) {
+                    cells.insert([x, y, z]);
+                }
+            }
+        }
+    }
+    cells
+}
+
+// Partes visibles de una cara como rectángulos [b0, b1, c0, c1] en los otros dos ejes: la cara
+// entera si se ve entera o no está alineada con la cuadrícula, nada si está tapada del todo y si no
+// un cuadrado por cada bloque que se ve
+fn visible_quads(cube: &Cube, axis: usize, positive: bool, occupied: &HashSet<[i32; 3]>) -> Vec<[Float; 4]> {
+    let (b, c) = ((axis + 1) % 3, (axis + 2) % 3);
+    let plane = if positive { cube.max[axis] } else { cube.min[axis] };
+    let whole = vec![[cube.min[b], cube.max[b], cube.min[c], cube.max[c]]];
+    if ![plane, cube.min[b], cube.max[b], cube.min[c], cube.max[c]].into_iter().all(on_grid) {
+        return whole;
+    }
+    // Bloque del otro lado de la cara
+    let outside = if positive { cell(plane) } else { cell(plane) - 1 };
+    let mut visible = Vec::new();
+    let mut hidden = 0;
+    for i in cell(cube.min[b])..cell(cube.max[b]) {
+        for j in cell(cube.min[c])..cell(cube.max[c]) {
+            let mut neighbor = [0; 3];
+            neighbor[axis] = outside;
+            neighbor[b] = i;
+            neighbor[c] = j;
+            if occupied.contains(&neighbor) {
+                hidden += 1;
+            } else {
+                let (bi, cj) = (i as Float * BLOCK_SIZE, j as Float * BLOCK_SIZE);
+                visible.push([bi, bi + BLOCK_SIZE, cj, cj + BLOCK_SIZE]);
+            }
+        }
+    }
+    if hidden == 0 { whole } else { visible }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(name: &str, material: Material) -> Material {
+        Material { name: name.to_string(), ..material }
+    }
+
+    fn faces(obj: &str) -> Vec<&str> {
+        obj.lines().filter(|line| line.starts_with("f ")).collect()
+    }
+
+    #[test]
+    fn touching_blocks_hide_their_shared_faces() {
+        let stone = named("stone", Material::new(Color::new(128, 128, 128), 30.0, [0.7, 0.1, 0.1, 0.0], 1.0));
+        let glass = named("glass", Material::new(Color::new(0, 0, 0), 60.0, [0.1, 0.1, 0.1, 0.5], 1.0));
+        let block = |x: Float, material: &Material| Cube::new(Vec3::new(x, 0.0, 0.0), Vec3::new(x + 0.5, 0.5, 0.5), material);
+        let export = to_obj(&[block(0.0, &stone), block(0.5, &stone), block(1.0, &glass)], "test.mtl", "test");
+        // 6 caras por bloque menos las dos entre las piedras y la del cristal que toca la piedra;
+        // el cristal no tapa la cara de la piedra que da a él
+        assert_eq!(faces(&export.obj).len(), 18 - 3);
+        assert!(export.obj.contains("mtllib test.mtl") && export.obj.contains("usemtl glass"));
+        assert!(export.mtl.contains("newmtl stone") && export.mtl.contains("d 0.5"));
+
+        // Una caja grande tapada a medias solo escribe los bloques que se ven
+        let floor = Cube::new(Vec3::new(0.0, -0.5, 0.0), Vec3::new(1.5, 0.0, 0.5), &stone);
+        let export = to_obj(&[floor, block(0.0, &stone)], "test.mtl", "test");
+        let top = faces(&export.obj).len() - 5 - 5;
+        assert_eq!(top, 2, "the floor's top should be split into its two visible blocks");
+    }
+
+    #[test]
+    fn faces_wind_outward_and_grass_uses_its_top_texture() {
+        let grass = named("grass", Material::new(Color::new(0, 255, 0), 50.0, [0.8, 0.2, 0.0, 0.0], 1.0)
+            .with_textures(vec![Texture::missing(), Texture::missing()]));
+        let cube = Cube::new(Vec3::new(-0.5, 0.0, 1.0), Vec3::new(0.0, 0.5, 1.5), &grass);
+        let export = to_obj(&[cube], "grass.mtl", "scene");
+        let vertices: Vec<Vec3> = export.obj.lines()
+            .filter_map(|line| line.strip_prefix("v "))
+            .map(|line| {
+                let values: Vec<Float> = line.split(' ').map(|value| value.parse().unwrap()).collect();
+                Vec3::new(values[0], values[1], values[2])
+            })
+            .collect();
+        let normals = [Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0)];
+        for face in faces(&export.obj) {
+            let corners: Vec<[usize; 3]> = face[2..].split(' ')
+                .map(|corner| {
+                    let parts: Vec<usize> = corner.split('/').map(|part| part.parse().unwrap()).collect();
+                    [parts[0], parts[1], parts[2]]
+                })
+                .collect();
+            let [a, b, c] = [0, 1, 2].map(|i| vertices[corners[i][0] - 1]);
+            let winding = (b - a).cross(&(c - a)).normalize();
+            assert!((winding - normals[corners[0][2] - 1]).norm() < 1e-4, "{} winds inward", face);
+        }
+        assert_eq!(export.textures.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["scene_grass_1.png", "scene_grass.png"]);
+        assert!(export.mtl.contains("newmtl grass\n") && export.mtl.contains("map_Kd scene_grass.png"));
+        assert!(export.obj.contains("usemtl grass_1"));
+    }
+
+    #[test]
+    fn glb_has_a_mesh_per_material_and_consistent_chunks() {
+        let stone = named("stone", Material::new(Color::new(128, 128, 128), 30.0, [0.7, 0.1, 0.1, 0.0], 1.0)
+            .with_textures(vec![Texture::missing()]));
+        let glass = named("glass", Material::new(Color::new(0, 0, 0), 60.0, [0.1, 0.1, 0.1, 0.5], 1.0));
+        let objects = [
+            Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.5, 0.5), &stone),
+            Cube::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.5, 1.0, 0.5), &glass),
+        ];
+        let glb = to_glb(&objects).unwrap();
+        let u32_at = |at: usize| u32::from_le_bytes(glb[at..at + 4].try_into().unwrap()) as usize;
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!((u32_at(4), u32_at(8)), (2, glb.len()));
+        let json_length = u32_at(12);
+        assert_eq!((&glb[16..20], json_length % 4), (&b"JSON"[..], 0));
+        let json = std::str::from_utf8(&glb[20..20 + json_length]).unwrap();
+        let bin_length = u32_at(20 + json_length);
+        assert_eq!(&glb[24 + json_length..28 + json_length], b"BIN\0");
+        assert_eq!(28 + json_length + bin_length, glb.len());
+        assert!(json.contains(&format!("\"buffers\":[{{\"byteLength\":{}}}]", bin_length)));
+
+        assert_eq!(json.matches("\"primitives\"").count(), 2);
+        assert!(json.contains("\"name\":\"stone\"") && json.contains("\"mimeType\":\"image/png\""));
+        assert!(json.contains("\"alphaMode\":\"BLEND\""), "glass should be transparent");
+        assert!(json.contains("\"min\":[0,0,0],\"max\":[1,0.5,0.5]"));
+        assert!(to_glb(&[]).is_err());
+    }
+
+    // Necesita gltf_validator (https://github.com/KhronosGroup/glTF-Validator) en el PATH:
+    // cargo test --features gltf-validator
+    #[cfg(feature = "gltf-validator")]
+    #[test]
+    fn demo_passes_the_gltf_validator() {
+        let mut missing = Vec::new();
+        let path = std::env::temp_dir().join("raytracer_minecraft_demo.glb");
+        export_gltf(&crate::scene::demo_scene(&mut missing), &path).unwrap();
+        let output = std::process::Command::new("gltf_validator").arg(&path).arg("-o").output().expect("gltf_validator on the PATH");
+        let report = String::from_utf8_lossy(&output.stdout);
+        assert!(report.contains("\"numErrors\": 0") || report.contains("\"numErrors\":0"), "{}", report);
+    }
+}
@@ -0,0 +1,216 @@
+// C-compatible API for embedding the renderer (built with `--features ffi`).
+// The header in include/raytracer_minecraft.h is generated from this file with cbindgen.
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use crate::day_night::DayNightCycle;
+use crate::framebuffer::Framebuffer;
+use crate::math::{Float, Vec3};
+use crate::render::render;
+use crate::scene::{self, LoadedScene, Scene};
+use crate::settings::RenderSettings;
+use crate::sky::Sky;
+
+pub const RT_OK: i32 = 0;
+pub const RT_ERR_NULL_POINTER: i32 = -1;
+pub const RT_ERR_INVALID_ARGUMENT: i32 = -2;
+pub const RT_ERR_SCENE: i32 = -3;
+pub const RT_ERR_PANIC: i32 = -4;
+
+/// Largest width or height `rt_renderer_render` accepts. A failed allocation aborts the process,
+/// which no error code can report, so bigger images are refused up front.
+pub const RT_MAX_SIZE: u32 = 16384;
+
+pub struct RtRenderer {
+    scene: Scene,
+    last_error: Option<CString>,
+}
+
+impl RtRenderer {
+    fn new() -> Self {
+        RtRenderer { scene: Self::scene_of(&LoadedScene::new(Vec::new())), last_error: None }
+    }
+
+    fn scene_of(loaded: &LoadedScene) -> Scene {
+        loaded.scene(Arc::new(Sky::Procedural), &DayNightCycle::new())
+    }
+
+    // The camera set through the API survives loading another scene
+    fn set_scene(&mut self, loaded: LoadedScene) {
+        let camera = self.scene.camera.clone();
+        self.scene = Self::scene_of(&loaded);
+        self.scene.camera = camera;
+    }
+
+    fn set_error(&mut self, message: String) {
+        // Interior NUL bytes can't be represented in a C string
+        self.last_error = CString::new(message.replace('\0', " ")).ok();
+    }
+}
+
+// Runs `f` with the renderer, converting errors and panics into status codes
+fn guarded<F>(renderer: *mut RtRenderer, f: F) -> i32
+where
+    F: FnOnce(&mut RtRenderer) -> Result<(), (i32, String)>,
+{
+    if renderer.is_null() {
+        return RT_ERR_NULL_POINTER;
+    }
+    let renderer = unsafe { &mut *renderer };
+    renderer.last_error = None;
+
+    match catch_unwind(AssertUnwindSafe(|| f(renderer))) {
+        Ok(Ok(())) => RT_OK,
+        Ok(Err((code, message))) => {
+            renderer.set_error(message);
+            code
+        }
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            renderer.set_error(format!("panic: {}", message));
+            RT_ERR_PANIC
+        }
+    }
+}
+
+/// Creates a renderer with an empty scene. Returns NULL on failure.
+#[no_mangle]
+pub extern "C" fn rt_renderer_create() -> *mut RtRenderer {
+    catch_unwind(|| Box::into_raw(Box::new(RtRenderer::new()))).unwrap_or(std::ptr::null_mut())
+}
+
+/// Destroys a renderer created with `rt_renderer_create`.
+///
+/// # Safety
+/// `renderer` must be NULL or a pointer returned by `rt_renderer_create` that was not destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_destroy(renderer: *mut RtRenderer) {
+    if !renderer.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(renderer))));
+    }
+}
+
+/// Loads a scene by path ("demo" selects the built-in house).
+///
+/// # Safety
+/// `renderer` must be a valid renderer and `path` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_load_scene(renderer: *mut RtRenderer, path: *const c_char) -> i32 {
+    if path.is_null() {
+        return RT_ERR_NULL_POINTER;
+    }
+    guarded(renderer, |renderer| {
+        let path = CStr::from_ptr(path).to_str()
+            .map_err(|_| (RT_ERR_INVALID_ARGUMENT, "path is not valid UTF-8".to_string()))?;
+        let loaded = scene::load(path).map_err(|e| (RT_ERR_SCENE, e.to_string()))?;
+        renderer.set_scene(loaded);
+        Ok(())
+    })
+}
+
+/// Loads a scene from the contents of a scene file held in memory.
+///
+/// # Safety
+/// `renderer` must be a valid renderer and `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_load_scene_buffer(renderer: *mut RtRenderer, data: *const u8, len: usize) -> i32 {
+    if data.is_null() {
+        return RT_ERR_NULL_POINTER;
+    }
+    guarded(renderer, |renderer| {
+        let bytes = std::slice::from_raw_parts(data, len);
+        let contents = std::str::from_utf8(bytes)
+            .map_err(|_| (RT_ERR_INVALID_ARGUMENT, "scene buffer is not valid UTF-8".to_string()))?;
+        let loaded = scene::from_str(contents).map_err(|e| (RT_ERR_SCENE, e.to_string()))?;
+        renderer.set_scene(loaded);
+        Ok(())
+    })
+}
+
+/// Sets the camera position, look-at target and vertical field of view in degrees.
+///
+/// # Safety
+/// `renderer` must be a valid renderer.
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_set_camera(
+    renderer: *mut RtRenderer,
+    eye_x: f32, eye_y: f32, eye_z: f32,
+    target_x: f32, target_y: f32, target_z: f32,
+    fov_degrees: f32,
+) -> i32 {
+    guarded(renderer, |renderer| {
+        if ![eye_x, eye_y, eye_z, target_x, target_y, target_z].iter().all(|value| value.is_finite()) {
+            return Err((RT_ERR_INVALID_ARGUMENT, "camera coordinates must be finite".to_string()));
+        }
+        let eye = Vec3::new(eye_x as Float, eye_y as Float, eye_z as Float);
+        let target = Vec3::new(target_x as Float, target_y as Float, target_z as Float);
+        if (eye - target).magnitude() == 0.0 || !(fov_degrees > 0.0 && fov_degrees < 180.0) {
+            return Err((RT_ERR_INVALID_ARGUMENT, "invalid camera parameters".to_string()));
+        }
+        renderer.scene.camera.eye = eye;
+        renderer.scene.camera.center = target;
+        renderer.scene.camera.fov = (fov_degrees as Float).to_radians();
+        Ok(())
+    })
+}
+
+/// Renders the scene into `buffer`, which receives `width * height` RGBA8 pixels row by row.
+/// Sizes above `RT_MAX_SIZE` on either side are rejected with `RT_ERR_INVALID_ARGUMENT`.
+///
+/// # Safety
+/// `renderer` must be a valid renderer and `buffer` must point to `width * height * 4` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_render(
+    renderer: *mut RtRenderer,
+    buffer: *mut u8,
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+) -> i32 {
+    if buffer.is_null() {
+        return RT_ERR_NULL_POINTER;
+    }
+    guarded(renderer, |renderer| {
+        let sides = 1..=RT_MAX_SIZE;
+        let bytes = match (width as usize).checked_mul(height as usize).and_then(|pixels| pixels.checked_mul(4)) {
+            Some(bytes) if sides.contains(&width) && sides.contains(&height) => bytes,
+            _ => return Err((RT_ERR_INVALID_ARGUMENT, format!("invalid size {}x{} (1 to {} on each side)", width, height, RT_MAX_SIZE))),
+        };
+        let settings = RenderSettings {
+            width: width as usize,
+            height: height as usize,
+            samples_per_pixel,
+            ..RenderSettings::default()
+        };
+        let mut framebuffer = Framebuffer::new(settings.width, settings.height);
+        render(&mut framebuffer, &renderer.scene, &settings);
+
+        let output = std::slice::from_raw_parts_mut(buffer, bytes);
+        for (pixel, rgba) in framebuffer.buffer.iter().zip(output.chunks_exact_mut(4)) {
+            rgba[0] = (pixel >> 16) as u8;
+            rgba[1] = (pixel >> 8) as u8;
+            rgba[2] = *pixel as u8;
+            rgba[3] = 255;
+        }
+        Ok(())
+    })
+}
+
+/// Returns the message of the last failed call on this renderer, or NULL.
+/// The string stays valid until the next call on the same renderer.
+///
+/// # Safety
+/// `renderer` must be NULL or a valid renderer.
+#[no_mangle]
+pub unsafe extern "C" fn rt_renderer_last_error(renderer: *const RtRenderer) -> *const c_char {
+    if renderer.is_null() {
+        return std::ptr::null();
+    }
+    match &(*renderer).last_error {
+        Some(message) => message.as_ptr(),
+        None => std::ptr::null(),
+    }
+}
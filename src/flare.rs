@@ -0,0 +1,241 @@
+use rayon::prelude::*;
+
+use crate::block::BLOCK_SIZE;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::math::{to_f32, Float, Vec3};
+use crate::primitive::Primitive;
+use crate::ray_intersect::{Ray, RayIntersect};
+use crate::scene::Scene;
+use crate::settings::{FlareElements, RenderSettings};
+use crate::sky::Sky;
+
+// Color del destello del sol, algo más cálido que el blanco como su disco en el cielo
+const SUN_FLARE_COLOR: Color = Color::new(255, 240, 200);
+// Fuera de la imagen el destello se apaga a lo largo de esta fracción de la diagonal
+const FADE_MARGIN: f32 = 0.15;
+// Puntos alrededor de la fuente, en fracción de la diagonal, con los que se mide cuánto la tapa la
+// escena; con varios el destello se apaga poco a poco cuando la fuente pasa detrás de un borde
+const OCCLUSION_RADIUS: f32 = 0.006;
+const OCCLUSION_SAMPLES: [(f32, f32); 5] = [(0.0, 0.0), (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)];
+// Las luces de los bloques emisivos están en su centro: lo que quede a menos de esto no las tapa
+const EMITTER_CLEARANCE: Float = BLOCK_SIZE * 0.9;
+// Como mucho se dibujan las fuentes más fuertes, para que un campo de antorchas no cueste un frame
+const MAX_SOURCES: usize = 8;
+
+// Reflejos en la línea de la fuente al centro: posición (0 en el centro, 1 en la fuente), radio en
+// fracción de la diagonal, tinte y opacidad
+const GHOSTS: [(f32, f32, [f32; 3], f32); 4] = [
+    (0.5, 0.02, [0.7, 1.0, 0.7], 0.18),
+    (-0.3, 0.035, [0.6, 0.8, 1.0], 0.12),
+    (-0.7, 0.05, [1.0, 0.7, 0.5], 0.10),
+    (-1.2, 0.08, [0.8, 0.6, 1.0], 0.07),
+];
+
+// Una fuente de destello en la imagen, con su fuerza ya reducida por lo que la tapa y por lo que
+// se sale de la imagen
+#[derive(Clone, Copy, Debug)]
+pub struct FlareSource {
+    pub x: f32,
+    pub y: f32,
+    pub color: Color,
+    pub strength: f32,
+}
+
+// El sol, si el cielo simulado lo muestra, y las luces de al menos `flare_threshold` que están
+// delante de la cámara. Solo con la proyección en perspectiva.
+pub fn flare_sources(scene: &Scene, camera: &Camera, settings: &RenderSettings, width: usize, height: usize) -> Vec<FlareSource> {
+    let objects = scene.objects();
+    let mut sources = Vec::new();
+    let (sun, lights) = match (scene.sky.as_ref(), scene.lights.split_first()) {
+        (Sky::Procedural, Some((sun, lights))) => (Some(sun), lights),
+        _ => (None, scene.lights.as_slice()),
+    };
+    if let Some(sun) = sun {
+        let day = 1.0 - scene.ambient.night;
+        let direction = sun.position.normalize();
+        if day > 0.0 && sun.enabled {
+            if let Some((x, y, visible)) = source(camera, objects, &(camera.eye + direction * 1e4), None, (width, height)) {
+                sources.push(FlareSource { x, y, color: SUN_FLARE_COLOR, strength: day * visible });
+            }
+        }
+    }
+    for light in lights.iter().filter(|light| light.enabled && light.intensity >= settings.flare_threshold) {
+        let strength = (light.intensity / settings.flare_threshold.max(1e-3)).min(2.0) * 0.5;
+        let distance = (light.position - camera.eye).magnitude();
+        if let Some((x, y, visible)) = source(camera, objects, &light.position, Some(distance), (width, height)) {
+            sources.push(FlareSource { x, y, color: light.color, strength: strength * visible });
+        }
+    }
+    sources.sort_by(|a, b| b.strength.total_cmp(&a.strength));
+    sources.truncate(MAX_SOURCES);
+    sources
+}
+
+// Posición en pantalla de una fuente y cuánto se ve, de 0 a 1, contando la parte que tapa la escena
+// y la que se sale de la imagen. `distance` es None para el sol, que está infinitamente lejos y lo
+// tapa cualquier cosa.
+fn source(camera: &Camera, objects: &[Primitive], position: &Vec3, distance: Option<Float>, (width, height): (usize, usize)) -> Option<(f32, f32, f32)> {
+    let (x, y) = camera.project(position, width as Float, height as Float)?;
+    let (x, y) = (to_f32(x), to_f32(y));
+    let fade = screen_fade(x, y, width, height);
+    if fade <= 0.0 {
+        return None;
+    }
+    // Profundidad del impacto primario en unos pocos puntos de la fuente en pantalla
+    let diagonal = (width as f32).hypot(height as f32);
+    let visible = OCCLUSION_SAMPLES.iter()
+        .filter(|(dx, dy)| {
+            let (sample_x, sample_y) = (x + dx * OCCLUSION_RADIUS * diagonal, y + dy * OCCLUSION_RADIUS * diagonal);
+            let Some((origin, direction)) = camera.ray(sample_x as Float, sample_y as Float, width as Float, height as Float) else {
+                return false;
+            };
+            let reach = distance.map_or(Float::INFINITY, |distance| (distance - EMITTER_CLEARANCE).max(0.0));
+            let ray = Ray::segment(origin, direction.normalize(), reach);
+            !objects.iter().any(|object| object.ray_intersect(&ray).is_some_and(|hit| hit.t > 0.0))
+        })
+        .count();
+    let visible = fade * visible as f32 / OCCLUSION_SAMPLES.len() as f32;
+    (visible > 0.0).then_some((x, y, visible))
+}
+
+// 1 con la fuente dentro de la imagen, bajando con suavidad hasta 0 a FADE_MARGIN de la diagonal
+// por fuera, para que el destello no aparezca ni desaparezca de golpe en el borde
+pub fn screen_fade(x: f32, y: f32, width: usize, height: usize) -> f32 {
+    let (width, height) = (width as f32, height as f32);
+    let outside_x = (-x).max(x - width).max(0.0);
+    let outside_y = (-y).max(y - height).max(0.0);
+    let t = (1.0 - outside_x.hypot(outside_y) / (FADE_MARGIN * width.hypot(height))).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Suma el destello de cada fuente a la imagen: la raya, el halo con un núcleo brillante y los
+// reflejos, cada uno con los elementos elegidos y multiplicado por `intensity`
+pub fn draw_lens_flare(pixels: &mut [u32], width: usize, height: usize, sources: &[FlareSource], elements: FlareElements, intensity: f32) {
+    if sources.is_empty() || intensity <= 0.0 {
+        return;
+    }
+    let diagonal = (width as f32).hypot(height as f32);
+    let (center_x, center_y) = (width as f32 * 0.5, height as f32 * 0.5);
+    pixels.par_chunks_mut(width.max(1)).enumerate().for_each(|(row, line)| {
+        let py = row as f32 + 0.5;
+        for (column, pixel) in line.iter_mut().enumerate() {
+            let px = column as f32 + 0.5;
+            let mut added = [0.0f32; 3];
+            for source in sources {
+                let (dx, dy) = (px - source.x, py - source.y);
+                let distance = dx.hypot(dy);
+                let mut amount = 0.0;
+                if elements.streak {
+                    let thickness = (dy / (0.004 * diagonal)).powi(2);
+                    amount += 0.6 * (-thickness).exp() * (-dx.abs() / (0.25 * diagonal)).exp();
+                }
+                if elements.halo {
+                    let ring = (distance - 0.1 * diagonal) / (0.012 * diagonal);
+                    amount += 0.25 * (-ring * ring).exp() + 0.8 * (-distance / (0.02 * diagonal)).exp();
+                }
+                let color = [source.color.r, source.color.g, source.color.b].map(|channel| channel as f32);
+                for (c, value) in added.iter_mut().enumerate() {
+                    *value += color[c] * amount * source.strength;
+                }
+                if elements.ghosts {
+                    for &(position, radius, tint, opacity) in &GHOSTS {
+                        let ghost_x = center_x + (source.x - center_x) * position;
+                        let ghost_y = center_y + (source.y - center_y) * position;
+                        let offset = (px - ghost_x).hypot(py - ghost_y) / (radius * diagonal);
+                        let disc = (1.0 - offset.powi(4)).max(0.0) * opacity * source.strength;
+                        for (c, value) in added.iter_mut().enumerate() {
+                            *value += color[c] * tint[c] * disc;
+                        }
+                    }
+                }
+            }
+            let [r, g, b] = [16, 8, 0].map(|shift| {
+                let channel = ((*pixel >> shift) & 0xFF) as f32 + added[(16 - shift) / 8] * intensity;
+                channel.round().min(255.0) as u32
+            });
+            *pixel = (r << 16) | (g << 8) | b;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::cube::Cube;
+    use crate::light::Light;
+    use crate::material::Material;
+    use crate::sky::Ambient;
+
+    // Cámara en el origen mirando hacia -z con el sol justo delante, en el centro de la imagen
+    fn sun_scene(sun_direction: Vec3, objects: &[Cube]) -> Scene {
+        let camera = Camera::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+        let sun = Light::new(sun_direction * 100.0, Color::new(255, 255, 255), 1.0, 10.0);
+        Scene::new(objects.to_vec(), vec![sun], Arc::new(Sky::Procedural), camera, Ambient::flat(Color::new(80, 120, 200)))
+    }
+
+    fn sources(scene: &Scene) -> Vec<FlareSource> {
+        flare_sources(scene, &scene.camera, &RenderSettings::default(), 64, 48)
+    }
+
+    fn brightness(pixels: &[u32]) -> u32 {
+        pixels.iter().map(|pixel| ((pixel >> 16) & 0xFF) + ((pixel >> 8) & 0xFF) + (pixel & 0xFF)).sum()
+    }
+
+    #[test]
+    fn the_visible_sun_flares_and_a_blocked_one_does_not() {
+        let scene = sun_scene(Vec3::new(0.0, 0.0, -1.0), &[]);
+        let found = sources(&scene);
+        assert_eq!(found.len(), 1);
+        assert!((found[0].x - 32.0).abs() < 0.5 && (found[0].y - 24.0).abs() < 0.5, "{:?}", found[0]);
+        assert!((found[0].strength - 1.0).abs() < 1e-3);
+
+        let mut pixels = vec![0; 64 * 48];
+        draw_lens_flare(&mut pixels, 64, 48, &found, FlareElements::ALL, 1.0);
+        assert!(pixels[24 * 64 + 32] > 0x808080, "the core is bright");
+        let mut streak_only = vec![0; 64 * 48];
+        draw_lens_flare(&mut streak_only, 64, 48, &found, FlareElements { streak: true, halo: false, ghosts: false }, 1.0);
+        assert!(streak_only[24 * 64 + 2] > 0 && streak_only[4 * 64 + 32] == 0, "the streak is horizontal");
+
+        // A wall in front of the sun hides it, and nothing is drawn without sources
+        let wall = Cube::new(Vec3::new(-5.0, -5.0, -3.0), Vec3::new(5.0, 5.0, -2.0), &Material::new(Color::new(90, 90, 90), 1.0, [1.0, 0.0, 0.0, 0.0], 1.0));
+        assert!(sources(&sun_scene(Vec3::new(0.0, 0.0, -1.0), &[wall])).is_empty());
+        let mut untouched = vec![0x102030; 64 * 48];
+        draw_lens_flare(&mut untouched, 64, 48, &[], FlareElements::ALL, 1.0);
+        assert!(untouched.iter().all(|&pixel| pixel == 0x102030));
+    }
+
+    #[test]
+    fn ghosts_line_up_through_the_center() {
+        let source = FlareSource { x: 48.0, y: 12.0, color: Color::new(255, 255, 255), strength: 1.0 };
+        let mut pixels = vec![0; 64 * 48];
+        draw_lens_flare(&mut pixels, 64, 48, &[source], FlareElements { streak: false, halo: false, ghosts: true }, 1.0);
+        // The ghost at -0.7 sits on the opposite side of the center from the source
+        let (x, y) = (32.0 - 16.0 * 0.7, 24.0 + 12.0 * 0.7);
+        assert!(pixels[y as usize * 64 + x as usize] > 0);
+        assert_eq!(pixels[47 * 64 + 63], 0);
+    }
+
+    #[test]
+    fn the_flare_fades_smoothly_as_the_source_leaves_the_frame() {
+        assert_eq!(screen_fade(10.0, 10.0, 64, 48), 1.0);
+        assert_eq!(screen_fade(64.0, 24.0, 64, 48), 1.0);
+        let mut previous = 1.0;
+        let mut total = Vec::new();
+        for step in 0..=40 {
+            let x = 60.0 + step as f32 * 0.5;
+            let fade = screen_fade(x, 24.0, 64, 48);
+            assert!(fade <= previous && previous - fade < 0.1, "fade jumped from {} to {} at x = {}", previous, fade, x);
+            previous = fade;
+
+            let mut pixels = vec![0; 64 * 48];
+            let source = FlareSource { x, y: 24.0, color: Color::new(255, 255, 255), strength: fade };
+            draw_lens_flare(&mut pixels, 64, 48, &[source], FlareElements::ALL, 1.0);
+            total.push(brightness(&pixels));
+        }
+        assert_eq!(previous, 0.0, "far outside the frame there is no flare");
+        assert_eq!(total.last(), Some(&0));
+        assert!(total.windows(2).all(|pair| pair[0].abs_diff(pair[1]) < total[0] / 10), "the flare popped: {:?}", total);
+    }
+}
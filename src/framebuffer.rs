@@ -1,7 +1,13 @@
+use std::path::Path;
+
+use crate::error::RayTracerError;
+use crate::metadata::RenderMetadata;
+
 pub struct Framebuffer {
     pub width: usize,
     pub height: usize,
     pub buffer: Vec<u32>,
+    pub alpha: Option<Vec<u8>>, // Cobertura de cada píxel, solo si el render la calculó
     background_color: u32,
     current_color: u32,
 }
@@ -12,6 +18,7 @@ impl Framebuffer {
             width,
             height,
             buffer: vec![0; width * height],
+            alpha: None,
             background_color: 0x000000,
             current_color: 0xFFFFFF,
         }
@@ -36,4 +43,114 @@ impl Framebuffer {
     pub fn set_current_color(&mut self, color: u32) {
         self.current_color = color;
     }
-}
\ No newline at end of file
+
+    pub fn to_image(&self) -> image::RgbImage {
+        let mut img = image::RgbImage::new(self.width as u32, self.height as u32);
+        for (index, &pixel) in self.buffer.iter().enumerate() {
+            let x = (index % self.width) as u32;
+            let y = (index / self.width) as u32;
+            img.put_pixel(x, y, image::Rgb([(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]));
+        }
+        img
+    }
+
+    // Como `to_image`, con el canal alfa del render (opaco si no lo hay)
+    pub fn to_rgba_image(&self) -> image::RgbaImage {
+        let mut img = image::RgbaImage::new(self.width as u32, self.height as u32);
+        for (index, &pixel) in self.buffer.iter().enumerate() {
+            let x = (index % self.width) as u32;
+            let y = (index / self.width) as u32;
+            img.put_pixel(x, y, image::Rgba([(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8, self.alpha_at(index)]));
+        }
+        img
+    }
+
+    fn alpha_at(&self, index: usize) -> u8 {
+        self.alpha.as_ref().map_or(255, |alpha| alpha[index])
+    }
+
+    // RGBA si el render calculó la cobertura y RGB si no
+    fn to_dynamic_image(&self) -> image::DynamicImage {
+        match self.alpha {
+            Some(_) => image::DynamicImage::ImageRgba8(self.to_rgba_image()),
+            None => image::DynamicImage::ImageRgb8(self.to_image()),
+        }
+    }
+
+    // Guarda el contenido del framebuffer como una imagen (el formato se deduce de la extensión).
+    // Con canal alfa el formato tiene que admitirlo: un .jpg falla
+    pub fn save(&self, path: &str) -> Result<(), RayTracerError> {
+        self.to_dynamic_image().save(path).map_err(|e| RayTracerError::image(path, e))
+    }
+
+    // Codifica el framebuffer como PNG en memoria
+    pub fn to_png(&self) -> Result<Vec<u8>, image::ImageError> {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        self.to_dynamic_image().write_to(&mut bytes, image::ImageFormat::Png)?;
+        Ok(bytes.into_inner())
+    }
+
+    // Como `save`, pero un .png lleva además `metadata`; los demás formatos se guardan sin ella
+    pub fn save_with_metadata(&self, path: &str, metadata: &RenderMetadata) -> Result<(), RayTracerError> {
+        if !Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png")) {
+            return self.save(path);
+        }
+        let png = self.to_png_with_metadata(metadata).map_err(|e| RayTracerError::Image(format!("{}: {}", path, e)))?;
+        std::fs::write(path, png).map_err(|e| RayTracerError::io(path, e))
+    }
+
+    // PNG en memoria con cada entrada de `metadata` en un bloque de texto antes de la imagen: tEXt si
+    // el valor es ASCII e iTXt (UTF-8) si no, como una ruta de escena con acentos
+    pub fn to_png_with_metadata(&self, metadata: &RenderMetadata) -> Result<Vec<u8>, png::EncodingError> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width as u32, self.height as u32);
+        encoder.set_color(if self.alpha.is_some() { png::ColorType::Rgba } else { png::ColorType::Rgb });
+        encoder.set_depth(png::BitDepth::Eight);
+        for (key, value) in &metadata.entries {
+            if value.is_ascii() {
+                encoder.add_text_chunk(key.clone(), value.clone())?;
+            } else {
+                encoder.add_itxt_chunk(key.clone(), value.clone())?;
+            }
+        }
+        let mut writer = encoder.write_header()?;
+        let pixels: Vec<u8> = match self.alpha {
+            Some(_) => self.buffer.iter().enumerate()
+                .flat_map(|(index, &pixel)| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8, self.alpha_at(index)])
+                .collect(),
+            None => self.buffer.iter().flat_map(|&pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]).collect(),
+        };
+        writer.write_image_data(&pixels)?;
+        writer.finish()?;
+        Ok(png)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saving_to_a_missing_directory_is_an_io_error() {
+        let framebuffer = Framebuffer::new(2, 2);
+        let error = framebuffer.save("/nonexistent-raytracer-dir/frame.png").unwrap_err();
+        assert!(matches!(error, RayTracerError::Io { .. }), "{:?}", error);
+        assert!(error.to_string().starts_with("/nonexistent-raytracer-dir/frame.png: "));
+    }
+
+    #[test]
+    fn a_frame_with_coverage_is_written_as_rgba() {
+        let mut framebuffer = Framebuffer::new(2, 1);
+        framebuffer.buffer = vec![0x336699, 0x000000];
+        framebuffer.alpha = Some(vec![255, 0]);
+        let metadata = RenderMetadata { entries: Vec::new() };
+        for png in [framebuffer.to_png().unwrap(), framebuffer.to_png_with_metadata(&metadata).unwrap()] {
+            let image = image::load_from_memory(&png).unwrap();
+            assert_eq!(image.color(), image::ColorType::Rgba8);
+            let image = image.to_rgba8();
+            assert_eq!(image.get_pixel(0, 0).0, [0x33, 0x66, 0x99, 255]);
+            assert_eq!(image.get_pixel(1, 0).0, [0, 0, 0, 0]);
+        }
+        framebuffer.alpha = None;
+        assert_eq!(image::load_from_memory(&framebuffer.to_png().unwrap()).unwrap().color(), image::ColorType::Rgb8);
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex, OnceLock};
+
+use rayon::prelude::*;
+use wgpu::util::DeviceExt;
+
+use crate::color::Color;
+use crate::error::RayTracerError;
+use crate::framebuffer::Framebuffer;
+use crate::light::LightLink;
+use crate::math::{consts, to_f32, Float, Vec3};
+use crate::primitive::Primitive;
+use crate::ray_intersect::CubeFace;
+use crate::render::{texture_slot, white_balance_gains};
+use crate::sampler::pixel_offset;
+use crate::scene::Scene;
+use crate::settings::{AlphaMode, RenderMode, RenderSettings, StereoMode, ToneMapping};
+use crate::sky::Sky;
+use crate::texture::Texture;
+
+// Backend experimental: un sombreador de cómputo (gpu.wgsl) traza en la GPU el rayo primario de
+// cada píxel, las sombras duras y los reflejos y refracciones. Solo sabe de cajas y del sombreado
+// básico; lo demás (oclusión ambiental, mapas salvo el emisivo, nieve y lluvia, variación por
+// bloque, sondas) se ignora, y las escenas o modos que no puede dibujar vuelven a la CPU.
+
+const NONE: u32 = u32::MAX;
+// Resolución de la imagen equirectangular del cielo que se calcula en la CPU en cada frame
+const SKY_WIDTH: u32 = 512;
+const SKY_HEIGHT: u32 = 256;
+// Los buffers vacíos no se pueden enlazar, así que todos tienen al menos este tamaño
+const MIN_BUFFER_SIZE: usize = 256;
+
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    frame: Mutex<()>, // Los ámbitos de error son del dispositivo, así que los frames van de uno en uno
+}
+
+// El dispositivo se abre la primera vez que se pide y se comparte entre todas las escenas; si no
+// hay adaptador se recuerda el error para no volver a buscarlo en cada frame
+fn gpu() -> Result<&'static Gpu, RayTracerError> {
+    static GPU: OnceLock<Result<Gpu, String>> = OnceLock::new();
+    GPU.get_or_init(|| pollster::block_on(Gpu::open())).as_ref().map_err(|e| RayTracerError::Gpu(e.clone()))
+}
+
+impl Gpu {
+    async fn open() -> Result<Gpu, String> {
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions { power_preference: wgpu::PowerPreference::HighPerformance, ..Default::default() })
+            .await
+            .ok_or("No GPU adapter found")?;
+        let descriptor = wgpu::DeviceDescriptor {
+            label: Some("raytracer"),
+            required_features: wgpu::Features::empty(),
+            // Los límites del adaptador, que suelen permitir buffers más grandes que los de por defecto
+            required_limits: adapter.limits(),
+            memory_hints: wgpu::MemoryHints::Performance,
+        };
+        let (device, queue) = adapter.request_device(&descriptor, None).await.map_err(|e| format!("Failed to open the GPU: {}", e))?;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("gpu.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("gpu.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("raytracer"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Ok(Gpu { device, queue, pipeline, frame: Mutex::new(()) })
+    }
+
+    // Ejecuta `f` capturando los errores de validación y de memoria, que wgpu por defecto convierte
+    // en pánicos, para que un buffer demasiado grande vuelva a la CPU en lugar de cerrar el programa
+    fn checked<T>(&self, f: impl FnOnce() -> T) -> Result<T, RayTracerError> {
+        self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let value = f();
+        let validation = pollster::block_on(self.device.pop_error_scope());
+        let memory = pollster::block_on(self.device.pop_error_scope());
+        match validation.or(memory) {
+            Some(error) => Err(RayTracerError::Gpu(error.to_string())),
+            None => Ok(value),
+        }
+    }
+
+    fn storage(&self, label: &str, mut bytes: Vec<u8>) -> wgpu::Buffer {
+        bytes.resize(bytes.len().max(MIN_BUFFER_SIZE), 0);
+        self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some(label), contents: &bytes, usage: wgpu::BufferUsages::STORAGE })
+    }
+}
+
+fn push_f32(bytes: &mut Vec<u8>, values: &[f32]) {
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn push_u32(bytes: &mut Vec<u8>, values: &[u32]) {
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn rgb(color: Color) -> [f32; 3] {
+    [color.r as f32, color.g as f32, color.b as f32]
+}
+
+fn vec4(v: &Vec3, w: f32) -> [f32; 4] {
+    [to_f32(v.x), to_f32(v.y), to_f32(v.z), w]
+}
+
+// La textura tal como la ve `Texture::sample`, con su región y giro ya aplicados, leída en el
+// centro de cada texel para que la GPU solo tenga que repetirla
+fn bake(texture: &Texture) -> Vec<u32> {
+    let (width, height) = (texture.width, texture.height);
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| texture.sample((x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32).to_hex())
+        .collect()
+}
+
+// Cajas, materiales y texturas de una escena ya en la GPU. Dependen solo de los objetos, así que
+// la escena las guarda hasta que cambian, como la rejilla de luz.
+pub struct SceneBuffers {
+    cubes: wgpu::Buffer,
+    cube_count: u32,
+    materials: wgpu::Buffer,
+    textures: wgpu::Buffer,
+    texels: wgpu::Buffer,
+}
+
+impl SceneBuffers {
+    // Error si la escena tiene algo que el sombreador no sabe dibujar
+    pub fn new(objects: &[Primitive]) -> Result<SceneBuffers, String> {
+        let gpu = gpu()?;
+        let mut cubes = Vec::new();
+        let mut materials = Vec::new();
+        let mut textures = Vec::new();
+        let mut texels: Vec<u32> = Vec::new();
+        // Los materiales con nombre salen del registro y se repiten en miles de bloques; se suben una vez
+        let mut named: HashMap<&str, u32> = HashMap::new();
+
+        for object in objects {
+            let Some(cube) = object.as_cube() else {
+                return Err("the GPU backend only draws boxes".to_string());
+            };
+            let material = &cube.material;
+            if material.portal.is_some() {
+                return Err("the GPU backend doesn't draw portals".to_string());
+            }
+            if material.dispersion > 0.0 {
+                return Err("the GPU backend doesn't split light by color".to_string());
+            }
+
+            let index = match named.get(material.name.as_str()) {
+                Some(&index) => index,
+                None => {
+                    let index = (materials.len() / 96) as u32;
+                    if !material.name.is_empty() {
+                        named.insert(&material.name, index);
+                    }
+                    let mut upload = |texture: &Texture| {
+                        push_u32(&mut textures, &[texels.len() as u32, texture.width, texture.height, 0]);
+                        texels.extend(bake(texture));
+                        (textures.len() / 16 - 1) as u32
+                    };
+                    // Cada textura del material se sube una sola vez aunque la usen varias caras
+                    let uploaded: Vec<u32> = material.textures.iter().map(&mut upload).collect();
+                    let slots = CubeFace::ALL.map(|face| uploaded.get(texture_slot(material, &face)).copied().unwrap_or(NONE));
+                    let emissive = material.emissive_map.as_ref().map_or(NONE, &mut upload);
+
+                    let [r, g, b] = rgb(material.color);
+                    push_f32(&mut materials, &[r, g, b, material.shininess]);
+                    let [r, g, b] = rgb(material.emission);
+                    push_f32(&mut materials, &[r, g, b, material.emission_strength]);
+                    push_f32(&mut materials, &material.properties);
+                    let [r, g, b] = rgb(material.transmission_tint);
+                    push_f32(&mut materials, &[r, g, b, material.refractive_index]);
+                    push_u32(&mut materials, &[slots[0], slots[1], slots[2], slots[3], slots[4], slots[5], emissive, NONE]);
+                    index
+                }
+            };
+
+            // Un byte por cara: cara de la textura, cuartos de vuelta y reflejo
+            let faces = cube.faces.map(|descriptor| {
+                let orientation = descriptor.orientation;
+                descriptor.texture_face as u32 | (orientation.quarter_turns as u32 % 4) << 3 | (orientation.mirrored as u32) << 5
+            });
+            push_f32(&mut cubes, &vec4(&cube.min, 0.0));
+            push_f32(&mut cubes, &vec4(&cube.max, 0.0));
+            push_u32(&mut cubes, &[index, faces[0] | faces[1] << 8 | faces[2] << 16 | faces[3] << 24, faces[4] | faces[5] << 8, 0]);
+        }
+
+        let texel_bytes = texels.iter().flat_map(|texel| texel.to_le_bytes()).collect();
+        gpu.checked(|| SceneBuffers {
+            cubes: gpu.storage("cubes", cubes),
+            cube_count: objects.len() as u32,
+            materials: gpu.storage("materials", materials),
+            textures: gpu.storage("textures", textures),
+            texels: gpu.storage("texels", texel_bytes),
+        })
+        .map_err(|e| e.to_string())
+    }
+}
+
+// Si el backend puede dibujar el frame con estas opciones; si no, se renderiza en la CPU sin avisar
+pub fn handles(settings: &RenderSettings) -> bool {
+    settings.render_mode == RenderMode::Shaded && settings.stereo == StereoMode::Off && !settings.checkerboard && settings.alpha == AlphaMode::Off && settings.lens_flare <= 0.0 && !settings.denoise
+}
+
+// Avisa una sola vez de que la GPU no está disponible o no puede con la escena
+pub fn warn_fallback(error: &RayTracerError) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!("warning: rendering on the CPU instead of the GPU: {}", error);
+    }
+}
+
+// Renderiza el frame en la GPU con una muestra por píxel. Con un error el framebuffer queda como
+// estaba y quien llama puede renderizar en la CPU.
+pub fn render(framebuffer: &mut Framebuffer, scene: &Scene, settings: &RenderSettings) -> Result<(), RayTracerError> {
+    let gpu = gpu()?;
+    let buffers = scene.gpu_buffers().as_ref().map_err(|e| RayTracerError::Gpu(e.clone()))?;
+    let lights: Vec<_> = scene.lights.iter().filter(|light| light.enabled).collect();
+    if lights.iter().any(|light| light.link != LightLink::All) {
+        return Err(RayTracerError::Gpu("the GPU backend doesn't support light linking".to_string()));
+    }
+    // El cielo simulado sale de la primera luz; sin luces la CPU tampoco lo puede dibujar
+    if matches!(*scene.sky, Sky::Procedural) && scene.lights.is_empty() {
+        return Err(RayTracerError::Gpu("the procedural sky needs a light".to_string()));
+    }
+
+    let (width, height) = (framebuffer.width as u32, framebuffer.height as u32);
+    let (image_width, image_height) = (framebuffer.width as Float, framebuffer.height as Float);
+    // Dos vec4 por píxel: origen y dirección; w = 0 en la dirección si el píxel no tiene rayo
+    let rays: Vec<u8> = (0..width * height)
+        .into_par_iter()
+        .flat_map_iter(|index| {
+            let (x, y) = (index % width, index / width);
+            let (offset_x, offset_y) = pixel_offset(settings.sampler, x, y, 0, 1);
+            let ray = scene.camera.ray(x as Float + offset_x, y as Float + offset_y, image_width, image_height);
+            let values = match ray {
+                Some((origin, direction)) => [vec4(&origin, 1.0), vec4(&direction, 1.0)],
+                None => [[0.0; 4]; 2],
+            };
+            values.into_iter().flatten().flat_map(f32::to_le_bytes).collect::<Vec<u8>>()
+        })
+        .collect();
+
+    let mut light_bytes = Vec::new();
+    for light in &lights {
+        push_f32(&mut light_bytes, &vec4(&light.position, to_f32(light.radius)));
+        let [r, g, b] = rgb(light.color);
+        push_f32(&mut light_bytes, &[r, g, b, light.intensity]);
+        push_f32(&mut light_bytes, &[if light.cast_shadows { 1.0 } else { 0.0 }, light.falloff as u8 as f32, 0.0, 0.0]);
+    }
+
+    // Cielo equirectangular: la columna da el ángulo alrededor de Y desde -Z y la fila el ángulo desde +Y
+    let sky: Vec<u8> = (0..SKY_WIDTH * SKY_HEIGHT)
+        .into_par_iter()
+        .flat_map_iter(|index| {
+            let (x, y) = (index % SKY_WIDTH, index / SKY_WIDTH);
+            let phi = ((x as Float + 0.5) / SKY_WIDTH as Float - 0.5) * 2.0 * consts::PI;
+            let theta = (y as Float + 0.5) / SKY_HEIGHT as Float * consts::PI;
+            let direction = Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+            scene.sky.color(&direction, &scene.lights, &scene.ambient).to_hex().to_le_bytes()
+        })
+        .collect();
+
+    let gains = white_balance_gains(settings.white_balance);
+    let mut params = Vec::new();
+    push_u32(&mut params, &[width, height, buffers.cube_count, lights.len() as u32]);
+    push_u32(&mut params, &[settings.max_depth, SKY_WIDTH, SKY_HEIGHT, 0]);
+    let tone_mapping = ToneMapping::ALL.iter().position(|mapping| *mapping == settings.tone_mapping).unwrap_or(0);
+    push_f32(&mut params, &[gains[0], gains[1], gains[2], tone_mapping as f32]);
+    for face in CubeFace::ALL {
+        let [r, g, b] = rgb(scene.ambient.at(&face.normal()));
+        push_f32(&mut params, &[r, g, b, settings.ambient_strength]);
+    }
+
+    let output_size = (framebuffer.buffer.len() * 4) as u64;
+    let _frame = gpu.frame.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let staging = gpu.checked(|| {
+        let params = gpu.device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some("params"), contents: &params, usage: wgpu::BufferUsages::UNIFORM });
+        let rays = gpu.storage("rays", rays);
+        let lights = gpu.storage("lights", light_bytes);
+        let sky = gpu.storage("sky", sky);
+        let output = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output"),
+            size: output_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size: output_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bindings = [&params, &rays, &buffers.cubes, &buffers.materials, &buffers.textures, &buffers.texels, &lights, &sky, &output];
+        let entries: Vec<wgpu::BindGroupEntry> = bindings
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry { binding: binding as u32, resource: buffer.as_entire_binding() })
+            .collect();
+        let bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("frame"),
+            layout: &gpu.pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+
+        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("frame") });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("trace"), timestamp_writes: None });
+            pass.set_pipeline(&gpu.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            // Grupos de 8x8 píxeles, como @workgroup_size en el sombreador
+            pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+        }
+        encoder.copy_buffer_to_buffer(&output, 0, &staging, 0, output_size);
+        gpu.queue.submit(Some(encoder.finish()));
+        staging
+    })?;
+
+    let slice = staging.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    gpu.device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+    receiver
+        .recv()
+        .map_err(|_| RayTracerError::Gpu("the GPU never returned the frame".to_string()))?
+        .map_err(|e| RayTracerError::Gpu(format!("Failed to read the frame back from the GPU: {}", e)))?;
+
+    let pixels = slice.get_mapped_range();
+    for (pixel, bytes) in framebuffer.buffer.iter_mut().zip(pixels.chunks_exact(4)) {
+        *pixel = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    drop(pixels);
+    staging.unmap();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::cube::Cube;
+    use crate::image_diff::ImageDiff;
+    use crate::light::Light;
+    use crate::material::Material;
+    use crate::render::render;
+    use crate::sky::Ambient;
+    use std::sync::Arc;
+
+    // Flat, textured and mirrored boxes on a floor with one shadow-casting light
+    fn scene() -> Scene {
+        let stone = Material::new(Color::new(150, 150, 150), 10.0, [0.9, 0.1, 0.0, 0.0], 1.0);
+        let checker = Material::new(Color::new(200, 80, 40), 30.0, [0.8, 0.3, 0.0, 0.0], 1.0)
+            .with_textures(vec![Texture::checkerboard(4, 4, Color::new(220, 40, 40), Color::new(30, 30, 200))]);
+        let mirror = Material::new(Color::new(230, 230, 230), 120.0, [0.3, 0.6, 0.6, 0.0], 1.0);
+        let objects = vec![
+            Cube::new(Vec3::new(-4.0, -1.5, -8.0), Vec3::new(4.0, -1.0, 0.0), &stone),
+            Cube::new(Vec3::new(-2.0, -1.0, -5.0), Vec3::new(-0.5, 0.5, -3.5), &checker),
+            Cube::new(Vec3::new(0.5, -1.0, -6.0), Vec3::new(2.0, 1.0, -4.5), &mirror),
+        ];
+        let lights = vec![Light::new(Vec3::new(3.0, 6.0, 2.0), Color::new(255, 250, 230), 1.5, 30.0)];
+        let camera = Camera::new(Vec3::new(0.0, 1.0, 3.0), Vec3::new(0.0, -0.5, -4.0), Vec3::new(0.0, 1.0, 0.0));
+        Scene::new(objects, lights, Arc::new(Sky::Procedural), camera, Ambient::flat(Color::new(120, 140, 170)))
+    }
+
+    #[test]
+    fn gpu_frames_stay_close_to_the_cpu_render() {
+        if let Err(error) = gpu() {
+            eprintln!("skipping: {}", error);
+            return;
+        }
+        let scene = scene();
+        let settings = RenderSettings { width: 96, height: 72, ..RenderSettings::default() };
+        let mut cpu = Framebuffer::new(settings.width, settings.height);
+        render(&mut cpu, &scene, &settings);
+        let mut gpu_frame = Framebuffer::new(settings.width, settings.height);
+        super::render(&mut gpu_frame, &scene, &settings).expect("the GPU renders boxes");
+
+        // Channel-by-channel differences: small on average, large only on a few edge pixels
+        let diff = ImageDiff::new(&cpu.to_image(), &gpu_frame.to_image(), 32.0 / 255.0).unwrap();
+        assert!(diff.mean_error < 4.0 / 255.0, "{}", diff.summary());
+        assert!(diff.over_threshold * 100 <= cpu.buffer.len(), "{}", diff.summary());
+    }
+
+    #[test]
+    fn scenes_the_shader_cannot_draw_are_refused() {
+        let mut portal_scene = scene();
+        let portal = Material::new(Color::new(120, 40, 200), 1.0, [1.0, 0.0, 0.0, 0.0], 1.0)
+            .with_portal(crate::material::Portal { target_offset: Vec3::new(0.0, 0.0, 4.0), rotation: 0.0 });
+        portal_scene.set_objects([Cube::new(Vec3::new(-1.0, -1.0, -5.0), Vec3::new(1.0, 1.0, -4.0), &portal)]);
+        // Without an adapter the scene is refused too, just for another reason
+        assert!(portal_scene.gpu_buffers().is_err());
+        assert!(!handles(&RenderSettings { render_mode: RenderMode::Clay, ..RenderSettings::default() }));
+        assert!(handles(&RenderSettings::default()));
+    }
+}
@@ -0,0 +1,422 @@
+// Trazado de rayos en la GPU: un hilo por píxel con el rayo primario que calcula la CPU, sombras
+// duras de las luces puntuales y reflejos y refracciones con una pila de rayos pendientes. Sigue a
+// `shade_ray` de render.rs paso a paso, incluido el redondeo a 8 bits de cada operación de color,
+// para que la imagen se parezca a la de la CPU, que sigue siendo la referencia.
+
+struct Params {
+    size: vec4<u32>,             // Ancho, alto, número de cajas y de luces
+    extra: vec4<u32>,            // Rebotes máximos, ancho y alto del cielo
+    gains: vec4<f32>,            // Balance de blancos por canal; w: curva de tone mapping (índice en ToneMapping::ALL)
+    ambient: array<vec4<f32>, 6>, // Luz ambiental para la normal de cada cara, en el orden de CubeFace; w: ambient_strength
+}
+
+struct Cube {
+    min: vec4<f32>,
+    max: vec4<f32>,
+    material: u32,
+    faces_low: u32,  // Un byte por cara (0 a 3): cara de la textura, cuartos de vuelta y reflejo
+    faces_high: u32, // Caras 4 y 5
+    pad: u32,
+}
+
+struct Material {
+    color: vec4<f32>,      // w: brillo (shininess)
+    emission: vec4<f32>,   // w: intensidad del mapa emisivo
+    properties: vec4<f32>, // Difuso, especular, reflectividad y transparencia
+    tint: vec4<f32>,       // Tinte de transmisión; w: índice de refracción
+    textures_a: vec4<u32>, // Textura de cada cara (0 a 3); NONE si el material no tiene
+    textures_b: vec4<u32>, // Caras 4 y 5 y el mapa emisivo
+}
+
+struct Light {
+    position: vec4<f32>, // w: radio
+    color: vec4<f32>,    // w: intensidad
+    shadows: vec4<f32>,  // x: 1 si proyecta sombras, y: caída (como en Falloff::ALL)
+}
+
+struct Hit {
+    found: bool,
+    t: f32,
+    point: vec3<f32>,
+    normal: vec3<f32>,
+    face: u32,
+    cube: u32,
+}
+
+struct Task {
+    origin: vec3<f32>,
+    dir: vec3<f32>,
+    weight: vec3<f32>,
+    depth: u32,
+}
+
+const NONE: u32 = 0xffffffffu;
+const FAR: f32 = 3.0e38;
+const ORIGIN_BIAS: f32 = 3.0517578e-5; // 256 * f32::EPSILON, como en render.rs
+const EPSILON: f32 = 1.1920929e-7;
+const PI: f32 = 3.14159265;
+const SKYBOX_COLOR: vec3<f32> = vec3<f32>(68.0, 142.0, 228.0);
+const STACK_SIZE: u32 = 16u;
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> rays: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read> cubes: array<Cube>;
+@group(0) @binding(3) var<storage, read> materials: array<Material>;
+@group(0) @binding(4) var<storage, read> textures: array<vec4<u32>>; // Inicio, ancho y alto en `texels`
+@group(0) @binding(5) var<storage, read> texels: array<u32>;
+@group(0) @binding(6) var<storage, read> lights: array<Light>;
+@group(0) @binding(7) var<storage, read> sky: array<u32>;
+@group(0) @binding(8) var<storage, read_write> output: array<u32>;
+
+// Light::falloff: la fracción de la intensidad que llega a `distance`, 0 fuera del radio
+fn falloff(mode: f32, distance: f32, radius: f32) -> f32 {
+    if (distance > radius) {
+        return 0.0;
+    }
+    let x = distance / radius;
+    switch (u32(mode)) {
+        case 1u: { return 1.0; }
+        case 2u: { return 1.0 - x; }
+        case 3u: { return (1.0 - x * x) * (1.0 - x * x); }
+        default: { return 1.0 / (1.0 + distance * distance / (radius * radius)); }
+    }
+}
+
+// Las operaciones de Color, que trabajan en u8 y truncan
+fn color_mul(a: vec3<f32>, b: vec3<f32>) -> vec3<f32> {
+    return floor(a * b / 255.0);
+}
+
+fn color_scale(a: vec3<f32>, scalar: f32) -> vec3<f32> {
+    return clamp(floor(a * scalar), vec3<f32>(0.0), vec3<f32>(255.0));
+}
+
+fn color_add(a: vec3<f32>, b: vec3<f32>) -> vec3<f32> {
+    return min(a + b, vec3<f32>(255.0));
+}
+
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14);
+}
+
+// Como ToneMapping::apply: 0 deja el color, 1 es reinhard y 2 aces
+fn tone_map(color: vec3<f32>, mode: f32) -> vec3<f32> {
+    if (mode < 0.5) {
+        return color;
+    }
+    let linear = pow(max(color / 255.0, vec3<f32>(0.0)), vec3<f32>(2.2));
+    var mapped = aces(linear) / aces(vec3<f32>(1.0));
+    if (mode < 1.5) {
+        mapped = 2.0 * linear / (1.0 + linear);
+    }
+    return pow(mapped, vec3<f32>(1.0 / 2.2)) * 255.0;
+}
+
+fn unpack(texel: u32) -> vec3<f32> {
+    return vec3<f32>(f32((texel >> 16u) & 255u), f32((texel >> 8u) & 255u), f32(texel & 255u));
+}
+
+fn wrap(coordinate: f32) -> f32 {
+    return min(coordinate - floor(coordinate), 1.0 - EPSILON / 2.0);
+}
+
+fn sample_texture(index: u32, u: f32, v: f32) -> vec3<f32> {
+    let info = textures[index];
+    let x = min(u32(wrap(u) * f32(info.y)), info.y - 1u);
+    let y = min(u32(wrap(v) * f32(info.z)), info.z - 1u);
+    return unpack(texels[info.x + y * info.y + x]);
+}
+
+// El cielo está en una imagen equirectangular que la CPU rellena en cada frame
+fn sample_sky(ray_direction: vec3<f32>) -> vec3<f32> {
+    let direction = normalize(ray_direction);
+    let width = params.extra.y;
+    let height = params.extra.z;
+    let phi = atan2(direction.x, -direction.z);
+    let theta = acos(clamp(direction.y, -1.0, 1.0));
+    let x = min(u32(wrap(phi / (2.0 * PI) + 0.5) * f32(width)), width - 1u);
+    let y = min(u32(theta / PI * f32(height)), height - 1u);
+    return unpack(sky[y * width + x]);
+}
+
+// Distancia de entrada y eje por el que entra el rayo, o un eje negativo si no toca la caja. Como
+// en cube.rs, cuenta aunque el origen esté dentro y gana el primer eje en las aristas; los ejes en
+// los que el rayo no avanza se tratan aparte para no depender de infinitos en la GPU.
+fn intersect_cube(cube: Cube, origin: vec3<f32>, dir: vec3<f32>, t_max: f32) -> vec2<f32> {
+    var low = cube.min.xyz;
+    var high = cube.max.xyz;
+    var o = origin;
+    var d = dir;
+    var tmin = -FAR;
+    var tmax = FAR;
+    var axis = -1.0;
+    for (var a = 0; a < 3; a++) {
+        if (d[a] == 0.0) {
+            if (o[a] < low[a] || o[a] > high[a]) {
+                return vec2<f32>(0.0, -1.0);
+            }
+            continue;
+        }
+        var near = (low[a] - o[a]) / d[a];
+        var far = (high[a] - o[a]) / d[a];
+        if (near > far) {
+            let swap = near;
+            near = far;
+            far = swap;
+        }
+        if (near > tmin) {
+            tmin = near;
+            axis = f32(a);
+        }
+        if (far < tmax) {
+            tmax = far;
+        }
+        if (tmin > tmax) {
+            return vec2<f32>(0.0, -1.0);
+        }
+    }
+    if (axis < 0.0 || tmax < 0.0 || tmin >= t_max) {
+        return vec2<f32>(0.0, -1.0);
+    }
+    return vec2<f32>(tmin, axis);
+}
+
+fn closest_hit(origin: vec3<f32>, dir: vec3<f32>) -> Hit {
+    var hit = Hit(false, FAR, vec3<f32>(0.0), vec3<f32>(0.0), 0u, 0u);
+    var axis = 0u;
+    for (var i = 0u; i < params.size.z; i++) {
+        let entry = intersect_cube(cubes[i], origin, dir, hit.t);
+        if (entry.y >= 0.0) {
+            hit.found = true;
+            hit.t = entry.x;
+            hit.cube = i;
+            axis = u32(entry.y);
+        }
+    }
+    if (!hit.found) {
+        return hit;
+    }
+    hit.point = origin + dir * hit.t;
+    var d = dir;
+    var normal = vec3<f32>(0.0);
+    normal[axis] = select(1.0, -1.0, d[axis] > 0.0);
+    hit.normal = normal;
+    // Cara en el orden de CubeFace: arriba, abajo, izquierda, derecha, delante, detrás
+    if (axis == 0u) {
+        hit.face = select(2u, 3u, normal.x > 0.0);
+    } else if (axis == 1u) {
+        hit.face = select(1u, 0u, normal.y > 0.0);
+    } else {
+        hit.face = select(5u, 4u, normal.z > 0.0);
+    }
+    return hit;
+}
+
+fn occluded(origin: vec3<f32>, dir: vec3<f32>, distance: f32) -> bool {
+    for (var i = 0u; i < params.size.z; i++) {
+        if (intersect_cube(cubes[i], origin, dir, distance).y >= 0.0) {
+            return true;
+        }
+    }
+    return false;
+}
+
+fn bias_at(point: vec3<f32>, distance: f32) -> f32 {
+    let magnitude = max(abs(point.x), max(abs(point.y), abs(point.z)));
+    return ORIGIN_BIAS * max(max(magnitude, abs(distance)), 1.0);
+}
+
+fn offset_origin(hit: Hit, direction: vec3<f32>) -> vec3<f32> {
+    let offset = hit.normal * bias_at(hit.point, hit.t);
+    return select(hit.point + offset, hit.point - offset, dot(direction, hit.normal) < 0.0);
+}
+
+fn refract_dir(incident: vec3<f32>, normal: vec3<f32>, eta_t: f32) -> vec3<f32> {
+    let cosi = -clamp(dot(incident, normal), -1.0, 1.0);
+    var n_cosi = cosi;
+    var eta = eta_t;
+    var n_normal = normal;
+    if (cosi < 0.0) {
+        n_cosi = -cosi;
+        eta = 1.0 / eta_t;
+        n_normal = -normal;
+    }
+    let k = 1.0 - eta * eta * (1.0 - n_cosi * n_cosi);
+    if (k < 0.0) {
+        return incident - 2.0 * dot(incident, n_normal) * n_normal;
+    }
+    return eta * incident + (eta * n_cosi - sqrt(k)) * n_normal;
+}
+
+// Byte de la cara `face` de la caja: cara de la textura en los bits 0 a 2, cuartos de vuelta en el
+// 3 y el 4 y el reflejo en el 5
+fn face_descriptor(cube: Cube, face: u32) -> u32 {
+    if (face < 4u) {
+        return (cube.faces_low >> (face * 8u)) & 255u;
+    }
+    return (cube.faces_high >> ((face - 4u) * 8u)) & 255u;
+}
+
+// Coordenadas de textura de la cara, con el giro que le da el descriptor de la caja
+fn face_uv(cube: Cube, hit: Hit) -> vec2<f32> {
+    var p = hit.point;
+    var uv: vec2<f32>;
+    if (hit.face <= 1u) {
+        uv = vec2<f32>(p.x, p.z);
+    } else if (hit.face <= 3u) {
+        uv = vec2<f32>(p.z, p.y);
+    } else {
+        uv = vec2<f32>(p.x, p.y);
+    }
+    uv = abs(uv - trunc(uv));
+
+    let packed = face_descriptor(cube, hit.face);
+    let turns = (packed >> 3u) & 3u;
+    var u = uv.x;
+    var v = uv.y;
+    if (turns == 1u) {
+        let old = u;
+        u = v;
+        v = 1.0 - old;
+    } else if (turns == 2u) {
+        u = 1.0 - u;
+        v = 1.0 - v;
+    } else if (turns == 3u) {
+        let old = u;
+        u = 1.0 - v;
+        v = old;
+    }
+    if (((packed >> 5u) & 1u) == 1u) {
+        u = 1.0 - u;
+    }
+    return vec2<f32>(u, v);
+}
+
+fn texture_face(cube: Cube, face: u32) -> u32 {
+    return face_descriptor(cube, face) & 7u;
+}
+
+fn face_texture(material: Material, face: u32) -> u32 {
+    if (face < 4u) {
+        var textures_a = material.textures_a;
+        return textures_a[face];
+    }
+    var textures_b = material.textures_b;
+    return textures_b[face - 4u];
+}
+
+// Luz directa, emisión y ambiente de un impacto, ya multiplicados por lo que no reflejan ni
+// refractan, como `direct` en `Shading`
+fn shade(hit: Hit, ray_origin: vec3<f32>) -> vec3<f32> {
+    let cube = cubes[hit.cube];
+    let material = materials[cube.material];
+
+    let uv = face_uv(cube, hit);
+    let scaled = vec2<f32>((uv.x * 2.0) % 1.0, (uv.y * 2.0) % 1.0);
+    let slot = face_texture(material, texture_face(cube, hit.face));
+    var material_color = material.color.xyz;
+    if (slot != NONE) {
+        material_color = sample_texture(slot, scaled.x, scaled.y);
+    }
+    let glowing = any(material.emission.xyz != vec3<f32>(0.0));
+
+    var color = vec3<f32>(0.0);
+    for (var i = 0u; i < params.size.w; i++) {
+        let light = lights[i];
+        let to_light = light.position.xyz - hit.point;
+        let distance = length(to_light);
+        let attenuation = falloff(light.shadows.y, distance, light.position.w);
+        if (attenuation <= 0.0) {
+            continue;
+        }
+        let light_dir = normalize(to_light);
+        var shadow = 0.0;
+        if (light.shadows.x > 0.0 && occluded(offset_origin(hit, light_dir), light_dir, distance)) {
+            shadow = 0.95;
+        }
+        let intensity = (1.0 - shadow) * light.color.w * attenuation;
+
+        let diffuse_amount = material.properties.x * max(dot(hit.normal, light_dir), 0.0) * intensity;
+        let diffuse = color_scale(color_mul(material_color, light.color.xyz), diffuse_amount);
+        let view_dir = normalize(ray_origin - hit.point);
+        let halfway = normalize(light_dir + view_dir);
+        // pow(0, 0) no está definido en WGSL; en Rust da 1
+        let specular_intensity = select(pow(max(dot(halfway, hit.normal), 0.0), material.color.w), 1.0, material.color.w == 0.0);
+        let specular = color_scale(light.color.xyz, material.properties.y * specular_intensity * intensity);
+
+        if (glowing) {
+            color = color_add(color_add(color, color_scale(material_color, 0.7)), color_scale(color_add(diffuse, specular), 0.3));
+        } else {
+            color = color_add(color_add(color, diffuse), specular);
+        }
+    }
+
+    if (glowing) {
+        color = color_add(color_scale(color, 0.6), color_scale(material_color, 0.4 * material.emission.w));
+    } else {
+        color = color_add(color, color_scale(material.emission.xyz, material.emission.w));
+    }
+    let emissive = material.textures_b.z;
+    if (emissive != NONE) {
+        color = color_add(color, color_scale(sample_texture(emissive, scaled.x, scaled.y), material.emission.w));
+    }
+    color = color_add(color, color_scale(color_mul(material_color, params.ambient[hit.face].xyz), params.ambient[hit.face].w));
+
+    return color_scale(color, 1.0 - material.properties.z - material.properties.w);
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.size.x || id.y >= params.size.y) {
+        return;
+    }
+    let index = id.y * params.size.x + id.x;
+    let origin = rays[index * 2u];
+    let dir = rays[index * 2u + 1u];
+    // Fuera de la imagen que cubre la proyección el píxel queda negro, como en la CPU
+    if (dir.w == 0.0) {
+        output[index] = 0u;
+        return;
+    }
+
+    // Cada rayo pendiente lleva el peso con el que su color llega al píxel; los reflejos y las
+    // refracciones se suman en lugar de mezclarse por niveles como en la CPU
+    var stack: array<Task, STACK_SIZE>;
+    stack[0] = Task(origin.xyz, dir.xyz, vec3<f32>(1.0), 0u);
+    var count = 1u;
+    var color = vec3<f32>(0.0);
+    loop {
+        if (count == 0u) {
+            break;
+        }
+        count -= 1u;
+        let task = stack[count];
+        if (task.depth > params.extra.x) {
+            color += task.weight * SKYBOX_COLOR;
+            continue;
+        }
+        let hit = closest_hit(task.origin, task.dir);
+        if (!hit.found) {
+            color += task.weight * sample_sky(task.dir);
+            continue;
+        }
+        color += task.weight * shade(hit, task.origin);
+
+        let material = materials[cubes[hit.cube].material];
+        let reflectivity = material.properties.z;
+        let transparency = material.properties.w;
+        if (reflectivity > 0.0 && count < STACK_SIZE) {
+            let reflected = normalize(task.dir - 2.0 * dot(task.dir, hit.normal) * hit.normal);
+            stack[count] = Task(offset_origin(hit, reflected), reflected, task.weight * reflectivity, task.depth + 1u);
+            count += 1u;
+        }
+        if (transparency > 0.0 && count < STACK_SIZE) {
+            let refracted = refract_dir(task.dir, hit.normal, material.tint.w);
+            stack[count] = Task(offset_origin(hit, refracted), refracted, task.weight * transparency * material.tint.xyz / 255.0, task.depth + 1u);
+            count += 1u;
+        }
+    }
+
+    let final_color = clamp(floor(tone_map(color * params.gains.xyz, params.gains.w)), vec3<f32>(0.0), vec3<f32>(255.0));
+    output[index] = (u32(final_color.x) << 16u) | (u32(final_color.y) << 8u) | u32(final_color.z);
+}
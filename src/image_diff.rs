@@ -0,0 +1,113 @@
+use image::{Rgb, RgbImage};
+
+// Diferencias canal a canal entre dos imágenes del mismo tamaño, con los errores de 0 a 1. La usan
+// `diff` en la línea de comandos y los tests que comparan renders, así que cuentan lo mismo.
+pub struct ImageDiff {
+    pub max_error: f32,        // Mayor diferencia de un canal
+    pub mean_error: f32,       // Media de las diferencias de todos los canales de todos los píxeles
+    pub changed_pixels: usize, // Píxeles con algún canal distinto
+    pub over_threshold: usize, // Píxeles con algún canal que se aleja más que `threshold`
+    pub threshold: f32,
+    errors: Vec<f32>, // Mayor diferencia de un canal de cada píxel, para el mapa de calor
+    width: u32,
+    height: u32,
+}
+
+impl ImageDiff {
+    pub fn new(a: &RgbImage, b: &RgbImage, threshold: f32) -> Result<Self, String> {
+        if a.dimensions() != b.dimensions() {
+            return Err(format!(
+                "The images have different sizes: {}x{} and {}x{}",
+                a.width(), a.height(), b.width(), b.height()
+            ));
+        }
+        let mut total: u64 = 0;
+        let errors: Vec<f32> = a.pixels().zip(b.pixels())
+            .map(|(a, b)| {
+                let channels = [0, 1, 2].map(|channel| a[channel].abs_diff(b[channel]));
+                total += channels.iter().map(|&difference| u64::from(difference)).sum::<u64>();
+                f32::from(channels.into_iter().max().unwrap_or(0)) / 255.0
+            })
+            .collect();
+        let mean_error = match errors.len() {
+            0 => 0.0,
+            pixels => total as f32 / (255.0 * 3.0 * pixels as f32),
+        };
+        Ok(ImageDiff {
+            max_error: errors.iter().copied().fold(0.0, f32::max),
+            mean_error,
+            changed_pixels: errors.iter().filter(|&&error| error > 0.0).count(),
+            over_threshold: errors.iter().filter(|&&error| error > threshold).count(),
+            threshold,
+            errors,
+            width: a.width(),
+            height: a.height(),
+        })
+    }
+
+    pub fn exceeds_threshold(&self) -> bool {
+        self.over_threshold > 0
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "max error {:.4}, mean error {:.6}, {} of {} pixels differ, {} by more than {}",
+            self.max_error, self.mean_error, self.changed_pixels, self.errors.len(), self.over_threshold, self.threshold
+        )
+    }
+
+    // Mapa de calor sobre `base` en gris a un cuarto de brillo: cada píxel distinto va del rojo
+    // oscuro (apenas cambia) al amarillo y al blanco (la mayor diferencia de la imagen)
+    pub fn heatmap(&self, base: &RgbImage) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            let error = self.errors[(y * self.width + x) as usize];
+            if error == 0.0 {
+                let [r, g, b] = base.get_pixel(x, y).0.map(u32::from);
+                return Rgb([((r * 30 + g * 59 + b * 11) / 400) as u8; 3]);
+            }
+            let heat = 0.25 + 0.75 * error / self.max_error;
+            Rgb([0.0, 1.0, 2.0].map(|start: f32| ((heat * 3.0 - start).clamp(0.0, 1.0) * 255.0).round() as u8))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> RgbImage {
+        RgbImage::from_fn(8, 4, |x, y| Rgb([x as u8 * 30, y as u8 * 60, 100]))
+    }
+
+    #[test]
+    fn identical_images_have_no_error() {
+        let diff = ImageDiff::new(&gradient(), &gradient(), 0.0).unwrap();
+        assert_eq!((diff.max_error, diff.mean_error, diff.changed_pixels, diff.over_threshold), (0.0, 0.0, 0, 0));
+        assert!(!diff.exceeds_threshold());
+        assert_eq!(diff.heatmap(&gradient()).get_pixel(0, 0).0, [(100 * 11 / 400) as u8; 3]);
+    }
+
+    #[test]
+    fn errors_are_counted_per_channel_against_the_threshold() {
+        let mut changed = gradient();
+        changed.put_pixel(2, 1, Rgb([60 + 51, 60, 100]));
+        changed.put_pixel(5, 3, Rgb([150, 180, 100 - 10]));
+        let diff = ImageDiff::new(&gradient(), &changed, 0.1).unwrap();
+        assert_eq!(diff.max_error, 51.0 / 255.0);
+        assert!((diff.mean_error - 61.0 / (255.0 * 3.0 * 32.0)).abs() < 1e-7, "{}", diff.mean_error);
+        assert_eq!((diff.changed_pixels, diff.over_threshold), (2, 1));
+        assert!(diff.exceeds_threshold());
+        assert!(!ImageDiff::new(&gradient(), &changed, 0.2).unwrap().exceeds_threshold(), "the threshold is inclusive");
+
+        let heatmap = diff.heatmap(&gradient());
+        assert_eq!(heatmap.get_pixel(2, 1).0, [255, 255, 255], "the largest difference is white");
+        let faint = heatmap.get_pixel(5, 3).0;
+        assert!(faint[0] > 0 && faint[2] == 0, "{:?}", faint);
+    }
+
+    #[test]
+    fn mismatched_sizes_are_an_error() {
+        let error = ImageDiff::new(&gradient(), &RgbImage::new(4, 8), 0.0).err().expect("the sizes differ");
+        assert_eq!(error, "The images have different sizes: 8x4 and 4x8");
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use serde::Deserialize;
+use raytracer_minecraft::camera::{Camera, CameraMotion, Projection};
+use raytracer_minecraft::cubemap::{CubemapFace, CUBEMAP_FACES};
+use raytracer_minecraft::day_night::DayNightCycle;
+use raytracer_minecraft::framebuffer::Framebuffer;
+use raytracer_minecraft::math::{Float, Vec3};
+use raytracer_minecraft::metadata::RenderMetadata;
+use raytracer_minecraft::overlay::Overlay;
+use raytracer_minecraft::probes::ReflectionProbes;
+use raytracer_minecraft::render::{render, render_with_progress};
+use raytracer_minecraft::scene::{self, LoadedScene};
+use raytracer_minecraft::settings::RenderSettings;
+use raytracer_minecraft::sky::Sky;
+
+// A job file is a list of shots:
+//
+// [[job]]
+// scene = "demo"
+// eye = [0.0, 2.0, 6.5]
+// target = [0.0, 1.0, 0.0]
+// fov = 60.0   # Defaults to 60, or 180 for fisheye and 150 (horizontal) for panini
+// width = 1920   # width, height and spp default to the effective settings
+// height = 1080
+// spp = 16
+// projection = "panorama"   # perspective (default), panorama, stereo_panorama, fisheye or panini;
+// eye_separation = 0.0325   # panoramas are 2:1 per eye, so height follows width
+// panini_d = 1.0            # 0 is perspective, 1 the classic Panini
+// eye_end = [0.5, 2.0, 6.5]      # Where the camera is one frame later; with shutter_angle above 0
+// target_end = [0.0, 1.0, 0.0]   # the samples spread along the move (motion blur)
+// shutter_angle = 180.0
+// light_gizmos = true   # mark the lights' positions, for checking a lighting setup
+// time_of_day = 0.75   # 0 to 1 through the day/night cycle: 0.5 (default) is dusk, 0.75 midnight
+// cubemap = "faces"   # faces (six images, output_px.png ...) or cross (one 4x3 image); width is the face size
+// output = "renders/front.png"
+#[derive(Deserialize)]
+struct JobFile {
+    #[serde(default, rename = "job")]
+    jobs: Vec<Job>,
+}
+
+#[derive(Deserialize)]
+struct Job {
+    #[serde(default = "default_scene")]
+    scene: String,
+    eye: [Float; 3],
+    target: [Float; 3],
+    eye_end: Option<[Float; 3]>,
+    target_end: Option<[Float; 3]>,
+    fov: Option<Float>, // Grados
+    #[serde(default)]
+    roll: Float, // Grados
+    width: Option<usize>,
+    height: Option<usize>,
+    spp: Option<u32>,
+    shutter_angle: Option<Float>,
+    projection: Option<String>,
+    eye_separation: Option<Float>,
+    panini_d: Option<Float>,
+    cubemap: Option<String>,
+    #[serde(default)]
+    light_gizmos: bool,
+    time_of_day: Option<f32>,
+    output: String,
+}
+
+fn default_scene() -> String { "demo".to_string() }
+
+fn parse_job_file(path: &str) -> Result<Vec<Job>, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read job file {}: {}", path, e))?;
+    let file: JobFile = toml::from_str(&contents)
+        .map_err(|e| format!("Failed to parse job file {}: {}", path, e))?;
+    Ok(file.jobs)
+}
+
+impl Job {
+    // --panorama y --stereo-panorama reemplazan la proyección de todos los trabajos
+    // Los cubemaps se renderizan cara por cara; la proyección devuelta solo fija el tamaño cuadrado
+    fn projection(&self, projection_override: Option<Projection>) -> Result<Projection, String> {
+        if let Some(layout) = &self.cubemap {
+            if self.projection.is_some() {
+                return Err("cubemap and projection can't be combined".to_string());
+            }
+            if layout != "faces" && layout != "cross" {
+                return Err(format!("Unknown cubemap layout '{}' (expected faces or cross)", layout));
+            }
+            return Ok(Projection::CubemapFace(CubemapFace::PositiveX));
+        }
+        let projection = match (projection_override, &self.projection) {
+            (Some(projection), _) => projection,
+            (None, Some(name)) => name.parse()?,
+            (None, None) => Projection::Perspective,
+        };
+        let fov = |default: Float| self.fov.map_or(default, Float::to_radians);
+        match projection {
+            Projection::StereoPanorama { eye_separation } => Ok(Projection::StereoPanorama {
+                eye_separation: self.eye_separation.unwrap_or(eye_separation),
+            }),
+            Projection::Fisheye { fov: default_fov } => Ok(Projection::Fisheye { fov: fov(default_fov) }),
+            Projection::Panini { fov: default_fov, d } => Ok(Projection::Panini { fov: fov(default_fov), d: self.panini_d.unwrap_or(d) }),
+            _ => Ok(projection),
+        }
+    }
+
+    fn settings(&self, defaults: &RenderSettings, projection: &Projection) -> RenderSettings {
+        let width = self.width.unwrap_or(defaults.width);
+        RenderSettings {
+            width,
+            height: projection.fixed_height(width).unwrap_or(self.height.unwrap_or(defaults.height)),
+            samples_per_pixel: self.spp.unwrap_or(defaults.samples_per_pixel),
+            shutter_angle: self.shutter_angle.unwrap_or(defaults.shutter_angle),
+            light_gizmos: self.light_gizmos || defaults.light_gizmos,
+            ..defaults.clone()
+        }
+    }
+}
+
+type LoadedScenes = HashMap<String, LoadedScene>;
+
+// Lo que vale igual para todos los trabajos de un archivo
+struct JobOptions<'a> {
+    sky: Arc<Sky>,
+    overlay: Option<&'a Overlay>,
+    strict_assets: bool,
+    strict: bool,
+}
+
+fn run_job(job: &Job, projection: Projection, settings: &RenderSettings, options: &JobOptions, scenes: &mut LoadedScenes) -> Result<(), String> {
+    if settings.width == 0 || settings.height == 0 {
+        return Err(format!("Invalid resolution {}x{}", settings.width, settings.height));
+    }
+
+    // Las escenas (y sus texturas) se cargan una sola vez y se reutilizan entre trabajos
+    if !scenes.contains_key(&job.scene) {
+        let start = Instant::now();
+        let loaded = scene::load(&job.scene)?;
+        println!("  loaded scene {} and its textures in {:.2}s", job.scene, start.elapsed().as_secs_f32());
+        scenes.insert(job.scene.clone(), loaded);
+    }
+    let loaded = &scenes[&job.scene];
+    // Con --strict-assets una textura que falta hace fallar el trabajo en lugar de mostrar el damero
+    if options.strict_assets && !loaded.missing_textures.is_empty() {
+        return Err(format!("Missing textures: {}", loaded.missing_textures.join(", ")));
+    }
+    if options.strict && !loaded.warnings.is_empty() {
+        return Err(format!("Scene {} has {} warning(s) and --strict is set", job.scene, loaded.warnings.len()));
+    }
+
+    let mut camera = Camera::new(
+        Vec3::new(job.eye[0], job.eye[1], job.eye[2]),
+        Vec3::new(job.target[0], job.target[1], job.target[2]),
+        Vec3::new(0.0, 1.0, 0.0),
+    );
+    camera.fov = job.fov.unwrap_or(60.0).to_radians();
+    camera.roll = job.roll.to_radians();
+    camera.projection = projection;
+    if job.eye_end.is_some() || job.target_end.is_some() {
+        let end = |value: Option<[Float; 3]>, start: [Float; 3]| {
+            let [x, y, z] = value.unwrap_or(start);
+            Vec3::new(x, y, z)
+        };
+        camera.motion = Some(CameraMotion { eye: end(job.eye_end, job.eye), center: end(job.target_end, job.target) });
+    }
+
+    let day_night_cycle = job.time_of_day.map_or_else(DayNightCycle::new, DayNightCycle::at);
+    let mut scene = loaded.scene(options.sky.clone(), &day_night_cycle);
+    scene.camera = camera;
+
+    // Las sondas dependen de la hora del trabajo, así que se renderizan en cada uno
+    let positions = loaded.probe_positions();
+    let mut settings = settings.clone();
+    if !positions.is_empty() {
+        let start = Instant::now();
+        settings.probes = Some(Arc::new(ReflectionProbes::render(&positions, &scene, &settings)));
+        println!("  rendered {} reflection probe(s) in {:.2}s", positions.len(), start.elapsed().as_secs_f32());
+    }
+    let settings = &settings;
+
+    if job.cubemap.is_none() {
+        let mut framebuffer = Framebuffer::new(settings.width, settings.height);
+        let start = Instant::now();
+        // El avance se imprime cada 10%, en el orden de tiles de los ajustes
+        let mut reported = 0;
+        render_with_progress(&mut framebuffer, &scene, settings, &mut |_, done, total| {
+            let percent = done * 100 / total;
+            if percent >= reported + 10 {
+                reported = percent - percent % 10;
+                eprintln!("  {}%", reported);
+            }
+        });
+        let metadata = RenderMetadata::new(&job.scene, &scene.camera, settings, start.elapsed());
+        // El logo y el texto van sobre la imagen terminada; los cubemaps se quedan sin ellos, que
+        // se usan como mapas de entorno
+        if let Some(overlay) = options.overlay {
+            overlay.apply(&mut framebuffer, settings.alpha).map_err(|e| e.to_string())?;
+        }
+        return save(&framebuffer, &job.output, &metadata);
+    }
+
+    // Cubemap: seis caras cuadradas, guardadas por separado o juntas en una cruz de 4x3
+    let size = settings.width;
+    let mut cross = Framebuffer::new(size * 4, size * 3);
+    let start = Instant::now();
+    for face in CUBEMAP_FACES {
+        scene.camera.projection = Projection::CubemapFace(face);
+        let mut framebuffer = Framebuffer::new(size, size);
+        let face_start = Instant::now();
+        render(&mut framebuffer, &scene, settings);
+        if job.cubemap.as_deref() == Some("cross") {
+            face.paste_into_cross(&mut cross, &framebuffer);
+        } else {
+            let metadata = RenderMetadata::new(&job.scene, &scene.camera, settings, face_start.elapsed());
+            save(&framebuffer, &face.face_path(&job.output), &metadata)?;
+        }
+    }
+    if job.cubemap.as_deref() == Some("cross") {
+        // La cruz cuenta con su tamaño y el tiempo de las seis caras
+        let settings = RenderSettings { width: cross.width, height: cross.height, ..settings.clone() };
+        let metadata = RenderMetadata::new(&job.scene, &scene.camera, &settings, start.elapsed());
+        save(&cross, &job.output, &metadata)?;
+    }
+    Ok(())
+}
+
+fn save(framebuffer: &Framebuffer, output: &str, metadata: &RenderMetadata) -> Result<(), String> {
+    if let Some(parent) = std::path::Path::new(output).parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+    }
+    framebuffer.save_with_metadata(output, metadata)
+        .map_err(|e| format!("Failed to write {}", e))
+}
+
+// Runs every job in the file sequentially, continuing past failures.
+// Returns true when all jobs succeeded.
+pub fn run_job_file(path: &str, defaults: &RenderSettings, sky: Arc<Sky>, projection_override: Option<Projection>, overlay: Option<&Overlay>, strict_assets: bool, strict: bool) -> bool {
+    let jobs = match parse_job_file(path) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            eprintln!("{}", e);
+            return false;
+        }
+    };
+
+    let options = JobOptions { sky, overlay, strict_assets, strict };
+    let mut scenes = LoadedScenes::new();
+    let mut failures = Vec::new();
+    let total_start = Instant::now();
+
+    for (index, job) in jobs.iter().enumerate() {
+        let projection = match job.projection(projection_override) {
+            Ok(projection) => projection,
+            Err(e) => {
+                eprintln!("[{}/{}] {} failed: {}", index + 1, jobs.len(), job.output, e);
+                failures.push((index + 1, job.output.clone(), e));
+                continue;
+            }
+        };
+        let settings = job.settings(defaults, &projection);
+        println!("[{}/{}] {} ({}x{}, {} spp, {})", index + 1, jobs.len(), job.output, settings.width, settings.height, settings.samples_per_pixel, projection.name());
+        let start = Instant::now();
+        match run_job(job, projection, &settings, &options, &mut scenes) {
+            Ok(()) => println!("  done in {:.2}s", start.elapsed().as_secs_f32()),
+            Err(e) => {
+                eprintln!("  failed: {}", e);
+                failures.push((index + 1, job.output.clone(), e));
+            }
+        }
+    }
+
+    println!(
+        "{} of {} jobs succeeded in {:.2}s",
+        jobs.len() - failures.len(),
+        jobs.len(),
+        total_start.elapsed().as_secs_f32()
+    );
+    for (number, output, error) in &failures {
+        eprintln!("  job {} ({}): {}", number, output, error);
+    }
+
+    failures.is_empty()
+}
@@ -0,0 +1,108 @@
+use std::path::Path;
+use minifb::Key;
+
+// Teclas de los movimientos continuos de la ventana. Un archivo de atajos (`keybindings` en
+// raytracer.toml o --keybindings) cambia las que nombra con líneas `acción = "tecla"`; el resto de
+// atajos (Ctrl, los de un solo toque y el modo de edición de luces) siguen fijos.
+pub struct KeyBindings {
+    pub zoom_in: Key,
+    pub zoom_out: Key,
+    pub orbit_left: Key,
+    pub orbit_right: Key,
+    pub orbit_up: Key,
+    pub orbit_down: Key,
+    pub pan_up: Key,
+    pub pan_down: Key,
+    pub pan_left: Key,
+    pub pan_right: Key,
+    pub roll_left: Key,
+    pub roll_right: Key,
+    pub sun_back: Key,
+    pub sun_forward: Key,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            zoom_in: Key::W,
+            zoom_out: Key::S,
+            orbit_left: Key::Left,
+            orbit_right: Key::Right,
+            orbit_up: Key::Up,
+            orbit_down: Key::Down,
+            pan_up: Key::I,
+            pan_down: Key::K,
+            pan_left: Key::J,
+            pan_right: Key::L,
+            roll_left: Key::Z,
+            roll_right: Key::X,
+            sun_back: Key::Q,
+            sun_forward: Key::E,
+        }
+    }
+}
+
+const ACTIONS: [&str; 14] = [
+    "zoom_in", "zoom_out", "orbit_left", "orbit_right", "orbit_up", "orbit_down",
+    "pan_up", "pan_down", "pan_left", "pan_right", "roll_left", "roll_right", "sun_back", "sun_forward",
+];
+
+const KEY_NAMES: [(&str, Key); 58] = [
+    ("a", Key::A), ("b", Key::B), ("c", Key::C), ("d", Key::D), ("e", Key::E), ("f", Key::F), ("g", Key::G),
+    ("h", Key::H), ("i", Key::I), ("j", Key::J), ("k", Key::K), ("l", Key::L), ("m", Key::M), ("n", Key::N),
+    ("o", Key::O), ("p", Key::P), ("q", Key::Q), ("r", Key::R), ("s", Key::S), ("t", Key::T), ("u", Key::U),
+    ("v", Key::V), ("w", Key::W), ("x", Key::X), ("y", Key::Y), ("z", Key::Z),
+    ("0", Key::Key0), ("1", Key::Key1), ("2", Key::Key2), ("3", Key::Key3), ("4", Key::Key4),
+    ("5", Key::Key5), ("6", Key::Key6), ("7", Key::Key7), ("8", Key::Key8), ("9", Key::Key9),
+    ("left", Key::Left), ("right", Key::Right), ("up", Key::Up), ("down", Key::Down),
+    ("page_up", Key::PageUp), ("page_down", Key::PageDown), ("home", Key::Home), ("end", Key::End),
+    ("insert", Key::Insert), ("delete", Key::Delete), ("space", Key::Space), ("tab", Key::Tab),
+    ("comma", Key::Comma), ("period", Key::Period), ("semicolon", Key::Semicolon), ("apostrophe", Key::Apostrophe),
+    ("slash", Key::Slash), ("backslash", Key::Backslash), ("minus", Key::Minus), ("equal", Key::Equal),
+    ("left_bracket", Key::LeftBracket), ("right_bracket", Key::RightBracket),
+];
+
+impl KeyBindings {
+    fn slot(&mut self, action: &str) -> Option<&mut Key> {
+        Some(match action {
+            "zoom_in" => &mut self.zoom_in,
+            "zoom_out" => &mut self.zoom_out,
+            "orbit_left" => &mut self.orbit_left,
+            "orbit_right" => &mut self.orbit_right,
+            "orbit_up" => &mut self.orbit_up,
+            "orbit_down" => &mut self.orbit_down,
+            "pan_up" => &mut self.pan_up,
+            "pan_down" => &mut self.pan_down,
+            "pan_left" => &mut self.pan_left,
+            "pan_right" => &mut self.pan_right,
+            "roll_left" => &mut self.roll_left,
+            "roll_right" => &mut self.roll_right,
+            "sun_back" => &mut self.sun_back,
+            "sun_forward" => &mut self.sun_forward,
+            _ => return None,
+        })
+    }
+
+    // Como raytracer.toml, las acciones desconocidas solo avisan; una tecla que no existe es un error
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read key bindings {}: {}", path.display(), e))?;
+        let table: toml::Table = contents.parse()
+            .map_err(|e| format!("Failed to parse key bindings {}: {}", path.display(), e))?;
+
+        let mut bindings = KeyBindings::default();
+        for (action, value) in &table {
+            let Some(slot) = bindings.slot(action) else {
+                eprintln!("warning: {}: unknown action '{}' ignored (expected {})", path.display(), action, ACTIONS.join(", "));
+                continue;
+            };
+            let name = value.as_str()
+                .ok_or_else(|| format!("{}: the key for '{}' must be a string such as \"W\"", path.display(), action))?;
+            *slot = KEY_NAMES.iter()
+                .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+                .map(|&(_, key)| key)
+                .ok_or_else(|| format!("{}: unknown key '{}' for '{}'", path.display(), name, action))?;
+        }
+        Ok(bindings)
+    }
+}
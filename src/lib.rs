@@ -0,0 +1,47 @@
+pub mod framebuffer;
+pub mod metadata;
+pub mod image_diff;
+pub mod ray_intersect;
+pub mod color;
+pub mod rgb;
+pub mod camera;
+pub mod light;
+pub mod material;
+pub mod cube;
+pub mod texture;
+pub mod scene;
+pub mod render;
+pub mod day_night;
+pub mod settings;
+pub mod sky;
+pub mod block;
+pub mod math;
+pub mod cubemap;
+pub mod sampler;
+pub mod light_grid;
+pub mod assets;
+pub mod resource_pack;
+pub mod structures;
+pub mod noise;
+pub mod terrain;
+pub mod village;
+pub mod world;
+pub mod streaming;
+pub mod edit;
+pub mod minimap;
+pub mod overlay;
+pub mod flare;
+pub mod export;
+pub mod tiles;
+pub mod scaling;
+pub mod probes;
+pub mod presets;
+pub mod sphere;
+pub mod plane;
+pub mod primitive;
+pub mod error;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "gpu")]
+pub mod gpu;
@@ -1,16 +1,124 @@
-use nalgebra_glm::Vec3;
+use std::collections::HashSet;
+use crate::math::{to_f32, Float, Vec3};
 use crate::color::Color;
 
-#[derive(Clone, Copy)]
+// Light linking: qué objetos ilumina una luz, por su índice en la lista de objetos de la escena
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum LightLink {
+    #[default]
+    All,
+    Only(HashSet<usize>),
+    Except(HashSet<usize>),
+}
+
+impl LightLink {
+    pub fn affects(&self, object: usize) -> bool {
+        match self {
+            LightLink::All => true,
+            LightLink::Only(objects) => objects.contains(&object),
+            LightLink::Except(objects) => !objects.contains(&object),
+        }
+    }
+}
+
+// Cómo se apaga una luz con la distancia dentro de su radio; más allá no ilumina nunca
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Falloff {
+    #[default]
+    InverseSquare, // 1 / (1 + (d/r)²): la de siempre, baja deprisa y aún da la mitad en el borde
+    None,          // La misma intensidad hasta el borde
+    Linear,        // 1 - d/r
+    Quadratic,     // (1 - (d/r)²)²: suave y llega a 0 justo en el borde
+}
+
+impl Falloff {
+    // En el orden de su número en los mundos binarios y en la GPU
+    pub const ALL: [Falloff; 4] = [Falloff::InverseSquare, Falloff::None, Falloff::Linear, Falloff::Quadratic];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Falloff::InverseSquare => "inverse_square",
+            Falloff::None => "none",
+            Falloff::Linear => "linear",
+            Falloff::Quadratic => "quadratic",
+        }
+    }
+
+    // Fracción de la intensidad que llega a `distance` de una luz de radio `radius`
+    pub fn attenuation(self, distance: Float, radius: Float) -> f32 {
+        if distance > radius {
+            return 0.0;
+        }
+        let x = distance / radius;
+        match self {
+            Falloff::InverseSquare => to_f32(1.0 / (1.0 + distance * distance / (radius * radius))),
+            Falloff::None => 1.0,
+            Falloff::Linear => to_f32(1.0 - x),
+            Falloff::Quadratic => to_f32((1.0 - x * x) * (1.0 - x * x)),
+        }
+    }
+}
+
+impl std::str::FromStr for Falloff {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        Falloff::ALL.into_iter().find(|falloff| falloff.name() == name).ok_or_else(|| {
+            format!("Unknown falloff '{}' (expected inverse_square, none, linear or quadratic)", name)
+        })
+    }
+}
+
+#[derive(Clone)]
 pub struct Light {
     pub position: Vec3,
     pub color: Color,
     pub intensity: f32,
-    pub radius: f32,  // Radio de influencia de la luz
+    pub radius: Float, // Radio de influencia de la luz
+    pub enabled: bool,      // Las luces apagadas no se calculan
+    pub cast_shadows: bool, // Sin sombras la luz atraviesa los objetos, como una luz de relleno
+    pub link: LightLink,
+    pub falloff: Falloff,
 }
 
 impl Light {
-    pub fn new(position: Vec3, color: Color, intensity: f32, radius: f32) -> Self {
-        Light { position, color, intensity, radius }
+    pub fn new(position: Vec3, color: Color, intensity: f32, radius: Float) -> Self {
+        Light { position, color, intensity, radius, enabled: true, cast_shadows: true, link: LightLink::All, falloff: Falloff::InverseSquare }
     }
-}
\ No newline at end of file
+
+    pub fn with_shadows(mut self, cast_shadows: bool) -> Self {
+        self.cast_shadows = cast_shadows;
+        self
+    }
+
+    pub fn with_link(mut self, link: LightLink) -> Self {
+        self.link = link;
+        self
+    }
+
+    pub fn with_falloff(mut self, falloff: Falloff) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    // Entrada [[light]] de un archivo de escena con los valores actuales, para pegarla de vuelta.
+    // Los grupos de include/exclude no se pueden reconstruir a partir de los índices y se omiten.
+    pub fn to_toml(&self) -> String {
+        let mut entry = format!(
+            "[[light]]\nposition = [{:.2}, {:.2}, {:.2}]\ncolor = [{}, {}, {}]\nintensity = {:.2}\nradius = {:.2}\n",
+            self.position.x, self.position.y, self.position.z,
+            self.color.r, self.color.g, self.color.b,
+            self.intensity, self.radius,
+        );
+        if !self.enabled {
+            entry.push_str("enabled = false\n");
+        }
+        if !self.cast_shadows {
+            entry.push_str("cast_shadows = false\n");
+        }
+        if self.falloff != Falloff::InverseSquare {
+            entry.push_str(&format!("falloff = \"{}\"\n", self.falloff.name()));
+        }
+        entry
+    }
+}
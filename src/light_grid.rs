@@ -0,0 +1,362 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::color::Color;
+use crate::cube::Cube;
+use crate::math::{Float, Vec3};
+
+// Celda de la rejilla de bloques de 0.5
+pub type Cell = [i32; 3];
+
+pub const MAX_LIGHT_LEVEL: u8 = 15;
+
+const NEIGHBORS: [Cell; 6] = [[1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0], [0, 0, 1], [0, 0, -1]];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Channel {
+    Block,
+    Sky,
+}
+
+// Luz de bloque al estilo de Minecraft: los bloques emisivos tienen nivel 15 y la luz se extiende por
+// el aire perdiendo un nivel por paso. Los bloques enteros la frenan; losas, vallas y cubos fuera de
+// la rejilla no cuentan como sólidos. Colocar o quitar un bloque solo recalcula la zona afectada.
+//
+// La luz del cielo funciona igual, pero sus fuentes son las celdas sin ningún bloque encima, que
+// tienen siempre nivel 15 y no se guardan. Solo se guarda la luz de las celdas de aire tapadas
+// (cuevas, interiores, bajo los aleros), y por debajo del bloque más bajo de la escena no hay nada.
+#[derive(Clone, Debug, Default)]
+pub struct LightGrid {
+    solid: HashSet<Cell>,
+    sources: HashMap<Cell, u8>,
+    levels: HashMap<Cell, u8>,
+    heights: HashMap<[i32; 2], i32>, // Altura del bloque más alto de cada columna
+    bottom: i32,
+    sky: HashMap<Cell, u8>,
+}
+
+impl LightGrid {
+    pub fn from_objects<'a>(objects: impl IntoIterator<Item = &'a Cube>) -> Self {
+        let mut grid = LightGrid::default();
+        for object in objects {
+            let cells = block_cells(object);
+            let emissive = object.material.emission != Color::new(0, 0, 0);
+            for cell in cells {
+                grid.solid.insert(cell);
+                if emissive {
+                    grid.sources.insert(cell, MAX_LIGHT_LEVEL);
+                    grid.levels.insert(cell, MAX_LIGHT_LEVEL);
+                }
+            }
+        }
+        let seeds: VecDeque<Cell> = grid.sources.keys().copied().collect();
+        grid.propagate(Channel::Block, seeds);
+        grid.rebuild_sky();
+        grid
+    }
+
+    pub fn cell_at(point: &Vec3) -> Cell {
+        [(point.x * 2.0).floor() as i32, (point.y * 2.0).floor() as i32, (point.z * 2.0).floor() as i32]
+    }
+
+    pub fn level(&self, cell: Cell) -> u8 {
+        self.levels.get(&cell).copied().unwrap_or(0)
+    }
+
+    pub fn sky_level(&self, cell: Cell) -> u8 {
+        if self.solid.contains(&cell) {
+            0
+        } else if self.is_open(cell) {
+            MAX_LIGHT_LEVEL
+        } else {
+            self.sky.get(&cell).copied().unwrap_or(0)
+        }
+    }
+
+    // Coloca un bloque entero en `cell`; con `emission` > 0 además emite luz de ese nivel
+    pub fn place(&mut self, cell: Cell, emission: u8) {
+        let previous = self.level(cell);
+        let previous_sky = self.sky_level(cell);
+        let height = self.heights.get(&[cell[0], cell[2]]).copied();
+        self.solid.insert(cell);
+        self.sources.remove(&cell);
+        self.levels.remove(&cell);
+        let relight = self.unlight(Channel::Block, VecDeque::from([(cell, previous)]));
+        self.propagate(Channel::Block, relight);
+        if emission > 0 {
+            self.sources.insert(cell, emission.min(MAX_LIGHT_LEVEL));
+            self.levels.insert(cell, emission.min(MAX_LIGHT_LEVEL));
+            self.propagate(Channel::Block, VecDeque::from([cell]));
+        }
+
+        if self.heights.is_empty() || cell[1] < self.bottom {
+            self.rebuild_sky();
+            return;
+        }
+        self.sky.remove(&cell);
+        let mut darkened = VecDeque::from([(cell, previous_sky)]);
+        if height.is_none_or(|height| cell[1] > height) {
+            // La columna queda tapada desde el bloque nuevo hacia abajo
+            self.heights.insert([cell[0], cell[2]], cell[1]);
+            let covered = height.map_or(self.bottom, |height| height + 1);
+            darkened.extend((covered..cell[1]).map(|y| ([cell[0], y, cell[2]], MAX_LIGHT_LEVEL)));
+        }
+        let relight = self.unlight(Channel::Sky, darkened);
+        self.propagate(Channel::Sky, relight);
+    }
+
+    // Quita el bloque de `cell`, que pasa a ser aire y deja pasar la luz de sus vecinos
+    pub fn remove(&mut self, cell: Cell) {
+        if !self.solid.remove(&cell) {
+            return;
+        }
+        let previous = self.levels.remove(&cell).unwrap_or(0);
+        let mut relight = VecDeque::new();
+        if self.sources.remove(&cell).is_some() {
+            relight = self.unlight(Channel::Block, VecDeque::from([(cell, previous)]));
+        }
+        relight.extend(self.lit_neighbors(Channel::Block, cell));
+        self.propagate(Channel::Block, relight);
+
+        if cell[1] == self.bottom && !self.solid.iter().any(|solid| solid[1] == self.bottom) {
+            self.rebuild_sky();
+            return;
+        }
+        let column = [cell[0], cell[2]];
+        let mut relight = VecDeque::new();
+        if self.heights.get(&column) == Some(&cell[1]) {
+            // La columna se abre hasta el siguiente bloque de debajo
+            let below = (self.bottom..cell[1]).rev().find(|&y| self.solid.contains(&[cell[0], y, cell[2]]));
+            match below {
+                Some(height) => self.heights.insert(column, height),
+                None => self.heights.remove(&column),
+            };
+            for y in below.map_or(self.bottom, |height| height + 1)..=cell[1] {
+                self.sky.remove(&[cell[0], y, cell[2]]);
+                relight.push_back([cell[0], y, cell[2]]);
+            }
+        } else {
+            relight.extend(self.lit_neighbors(Channel::Sky, cell));
+        }
+        self.propagate(Channel::Sky, relight);
+    }
+
+    // Sin bloques encima (ni en la columna)
+    fn is_open(&self, cell: Cell) -> bool {
+        self.heights.get(&[cell[0], cell[2]]).is_none_or(|&height| cell[1] > height)
+    }
+
+    fn rebuild_sky(&mut self) {
+        self.heights.clear();
+        self.sky.clear();
+        for cell in &self.solid {
+            let height = self.heights.entry([cell[0], cell[2]]).or_insert(cell[1]);
+            *height = (*height).max(cell[1]);
+        }
+        self.bottom = self.solid.iter().map(|cell| cell[1]).min().unwrap_or(0);
+
+        // La luz entra en el aire tapado desde las celdas abiertas que lo tocan
+        let mut seeds = VecDeque::new();
+        for (&[x, z], &height) in &self.heights {
+            for y in self.bottom..height {
+                let cell = [x, y, z];
+                if self.solid.contains(&cell) {
+                    continue;
+                }
+                seeds.extend(NEIGHBORS.iter().map(|offset| offset_cell(cell, offset)).filter(|&neighbor| self.is_open(neighbor)));
+            }
+        }
+        self.propagate(Channel::Sky, seeds);
+    }
+
+    fn channel_level(&self, channel: Channel, cell: Cell) -> u8 {
+        match channel {
+            Channel::Block => self.level(cell),
+            Channel::Sky => self.sky_level(cell),
+        }
+    }
+
+    fn channel_levels(&mut self, channel: Channel) -> &mut HashMap<Cell, u8> {
+        match channel {
+            Channel::Block => &mut self.levels,
+            Channel::Sky => &mut self.sky,
+        }
+    }
+
+    // Celdas que la luz puede cambiar: aire, y para la luz del cielo además tapado y dentro de la escena
+    fn accepts(&self, channel: Channel, cell: Cell) -> bool {
+        !self.solid.contains(&cell) && match channel {
+            Channel::Block => true,
+            Channel::Sky => !self.is_open(cell) && cell[1] >= self.bottom,
+        }
+    }
+
+    fn is_source(&self, channel: Channel, cell: Cell) -> bool {
+        match channel {
+            Channel::Block => self.sources.contains_key(&cell),
+            Channel::Sky => self.is_open(cell),
+        }
+    }
+
+    fn lit_neighbors(&self, channel: Channel, cell: Cell) -> Vec<Cell> {
+        NEIGHBORS.iter()
+            .map(|offset| offset_cell(cell, offset))
+            .filter(|&neighbor| self.channel_level(channel, neighbor) > 1)
+            .collect()
+    }
+
+    // Extiende la luz desde las celdas de la cola a sus vecinos de aire
+    fn propagate(&mut self, channel: Channel, mut queue: VecDeque<Cell>) {
+        while let Some(cell) = queue.pop_front() {
+            let level = self.channel_level(channel, cell);
+            if level <= 1 {
+                continue;
+            }
+            for offset in &NEIGHBORS {
+                let neighbor = offset_cell(cell, offset);
+                if self.accepts(channel, neighbor) && self.channel_level(channel, neighbor) < level - 1 {
+                    self.channel_levels(channel).insert(neighbor, level - 1);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    // Apaga la luz que venía de las celdas de la cola (con el nivel que tenían). Devuelve las celdas
+    // iluminadas por otras fuentes que quedan en el borde, desde las que hay que volver a extenderla.
+    fn unlight(&mut self, channel: Channel, mut queue: VecDeque<(Cell, u8)>) -> VecDeque<Cell> {
+        let mut relight = VecDeque::new();
+        while let Some((cell, level)) = queue.pop_front() {
+            for offset in &NEIGHBORS {
+                let neighbor = offset_cell(cell, offset);
+                let neighbor_level = self.channel_level(channel, neighbor);
+                if neighbor_level == 0 {
+                    continue;
+                }
+                if neighbor_level < level && !self.is_source(channel, neighbor) {
+                    self.channel_levels(channel).remove(&neighbor);
+                    queue.push_back((neighbor, neighbor_level));
+                } else {
+                    relight.push_back(neighbor);
+                }
+            }
+        }
+        relight
+    }
+}
+
+fn offset_cell(cell: Cell, offset: &Cell) -> Cell {
+    [cell[0] + offset[0], cell[1] + offset[1], cell[2] + offset[2]]
+}
+
+// Celdas de la rejilla que el cubo llena por completo; ninguna si no está alineado con ella
+fn block_cells(cube: &Cube) -> Vec<Cell> {
+    let on_grid = |value: Float| ((value * 2.0) - (value * 2.0).round()).abs() < 1e-3;
+    if !(0..3).all(|axis| on_grid(cube.min[axis]) && on_grid(cube.max[axis])) {
+        return Vec::new();
+    }
+    let range = |axis: usize| (cube.min[axis] * 2.0).round() as i32..(cube.max[axis] * 2.0).round() as i32;
+    let mut cells = Vec::new();
+    for x in range(0) {
+        for y in range(1) {
+            for z in range(2) {
+                cells.push([x, y, z]);
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+
+    fn stone() -> Material {
+        Material::new(Color::new(128, 128, 128), 1.0, [1.0, 0.0, 0.0, 0.0], 1.0)
+    }
+
+    fn block_at(cell: Cell, material: &Material) -> Cube {
+        let min = Vec3::new(cell[0] as Float * 0.5, cell[1] as Float * 0.5, cell[2] as Float * 0.5);
+        Cube::new(min, min + Vec3::new(0.5, 0.5, 0.5), material)
+    }
+
+    fn assert_same_levels(grid: &LightGrid, rebuilt: &LightGrid, edit: Cell) {
+        for x in -20..20 {
+            for y in -20..20 {
+                for z in -1..2 {
+                    let cell = [x, y, z];
+                    assert_eq!(grid.level(cell), rebuilt.level(cell), "block light at {:?} after editing {:?}", cell, edit);
+                    assert_eq!(grid.sky_level(cell), rebuilt.sky_level(cell), "sky light at {:?} after editing {:?}", cell, edit);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn light_floods_through_air_and_updates_when_blocks_change() {
+        let stone = stone();
+        let glow = stone.clone().with_emission(Color::new(255, 200, 120), 1.0);
+        let mut objects = vec![block_at([0, 0, 0], &glow), block_at([3, 0, 0], &stone)];
+        let mut grid = LightGrid::from_objects(&objects);
+
+        // One level less per step, in every direction, and nothing behind the wall at x = 3 (cell 3)
+        assert_eq!(grid.level([0, 0, 0]), 15);
+        assert_eq!(grid.level([1, 0, 0]), 14);
+        assert_eq!(grid.level([-3, 0, 0]), 12);
+        assert_eq!(grid.level([0, 5, 0]), 10);
+        assert_eq!(grid.level([2, 0, 0]), 13);
+        assert_eq!(grid.level([4, 0, 0]), 9, "light has to go around the wall");
+        assert_eq!(grid.level([0, 15, 0]), 0);
+
+        // Incremental edits match rebuilding from scratch
+        let edits: [(Cell, Option<u8>); 4] = [([1, 0, 0], Some(0)), ([0, 0, 0], None), ([3, 0, 0], None), ([5, 0, 0], Some(15))];
+        for (cell, edit) in edits {
+            let [x, y, z] = cell.map(|value| value as Float * 0.5);
+            objects.retain(|object| object.min != Vec3::new(x, y, z));
+            match edit {
+                Some(emission) => {
+                    grid.place(cell, emission);
+                    objects.push(block_at(cell, if emission > 0 { &glow } else { &stone }));
+                }
+                None => grid.remove(cell),
+            }
+            assert_same_levels(&grid, &LightGrid::from_objects(&objects), cell);
+        }
+    }
+
+    #[test]
+    fn tunnels_get_darker_away_from_the_entrance() {
+        // A slab of rock 5 cells thick with a dead-end tunnel dug in from x = 0 at height 1
+        let stone = stone();
+        let mut objects = Vec::new();
+        for x in 0..14 {
+            for y in 0..5 {
+                for z in -1..2 {
+                    if !(x < 12 && y == 1 && z == 0) {
+                        objects.push(block_at([x, y, z], &stone));
+                    }
+                }
+            }
+        }
+        let mut grid = LightGrid::from_objects(&objects);
+        assert_eq!(grid.sky_level([-1, 1, 0]), 15, "outside the entrance is open sky");
+        let levels: Vec<u8> = (0..12).map(|x| grid.sky_level([x, 1, 0])).collect();
+        assert_eq!(levels[0], 14);
+        assert!(levels.windows(2).all(|pair| pair[1] < pair[0]), "{:?}", levels);
+        assert_eq!(levels[11], 3);
+        assert_eq!(grid.sky_level([5, 3, 0]), 0, "rock has no sky light");
+
+        // Digging a shaft down to the tunnel lets the sky in from above, and filling it shuts it out again
+        let shaft = [[8, 4, 0], [8, 3, 0], [8, 2, 0]];
+        for cell in shaft {
+            grid.remove(cell);
+            let [x, y, z] = cell.map(|value| value as Float * 0.5);
+            objects.retain(|object| object.min != Vec3::new(x, y, z));
+            assert_same_levels(&grid, &LightGrid::from_objects(&objects), cell);
+        }
+        assert_eq!(grid.sky_level([8, 1, 0]), 15);
+        assert_eq!(grid.sky_level([11, 1, 0]), 12);
+        grid.place([8, 4, 0], 0);
+        objects.push(block_at([8, 4, 0], &stone));
+        assert_same_levels(&grid, &LightGrid::from_objects(&objects), [8, 4, 0]);
+    }
+}
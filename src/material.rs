@@ -1,45 +1,168 @@
+use crate::math::{Float, Vec3};
 use crate::color::Color;
 use crate::texture::Texture;
 
+// Superficie que continúa los rayos desde su pareja (portal del Nether): la pareja está en
+// `target_offset` respecto al bloque y girada `rotation` radianes alrededor del eje Y
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Portal {
+    pub target_offset: Vec3,
+    pub rotation: Float,
+}
+
+// Lluvia sobre las caras superiores a cielo abierto: empieza a los `start` segundos y tarda `ramp`
+// en mojarlas del todo, hasta `wetness` (0 seco, 1 empapado)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rain {
+    pub wetness: f32,
+    pub start: f32,
+    pub ramp: f32,
+}
+
+impl Rain {
+    pub fn wetness_at(&self, time: f32) -> f32 {
+        let progress = if self.ramp > 0.0 {
+            ((time - self.start) / self.ramp).clamp(0.0, 1.0)
+        } else if time >= self.start {
+            1.0
+        } else {
+            0.0
+        };
+        self.wetness * progress
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Material {
+    pub name: String, // Nombre en el registro de `scene::materials`; vacío en los materiales hechos a mano
     pub color: Color,
     pub shininess: f32,
     pub properties: [f32; 4],
     pub refractive_index: f32,
+    pub dispersion: f32, // Diferencia de índice de refracción entre el azul y el rojo; 0 no separa la luz
     pub textures: Vec<Texture>,
     pub emission: Color,
+    pub transmission_tint: Color, // Color que toma la luz al atravesar el material (blanco = sin tinte)
+    pub portal: Option<Portal>,
+    // Mapas de un canal (se lee el rojo, lineal) que sustituyen por texel al peso especular y al brillo
+    pub specular_map: Option<Texture>,  // Multiplica properties[1]
+    pub roughness_map: Option<Texture>, // 0 = pulido, 1 = mate; reemplaza a shininess
+    // Mapa de color emitido por texel (vetas de mineral, cara de calabaza); se suma a `emission`
+    pub emissive_map: Option<Texture>,
+    pub emission_strength: f32, // Multiplica la emisión, el mapa emisivo y la luz que da el bloque
+    // Relieve por texel (rojo: blanco = superficie, negro = lo más hondo) para el parallax mapping
+    pub height_map: Option<Texture>,
+    pub parallax_depth: f32, // Profundidad del relieve como fracción del lado de la cara
+    pub snow_cover: f32,     // Fracción de texels de la cara superior con nieve cuando queda a cielo abierto
+    pub rain: Option<Rain>,
+    // Variación por bloque para que los campos grandes no repitan el mismo dibujo
+    pub randomize_rotation: bool, // Gira o refleja la textura de la cara superior
+    pub tint_jitter: f32,         // Cuánto puede variar el brillo (y un poco el tono) de cada bloque; 0 nada
+    pub gravity: bool,            // Sus bloques caen al quitarles lo que tienen debajo, como la arena
 }
 
 impl Material {
     pub fn new(color: Color, shininess: f32, properties: [f32; 4], refractive_index: f32) -> Self {
         Material {
+            name: String::new(),
             color,
             shininess,
             properties,
             refractive_index,
+            dispersion: 0.0,
             textures: Vec::new(),
             emission: Color::new(0, 0, 0), // Por defecto, no emite luz
+            transmission_tint: Color::new(255, 255, 255),
+            portal: None,
+            specular_map: None,
+            roughness_map: None,
+            emissive_map: None,
+            emission_strength: 1.0,
+            height_map: None,
+            parallax_depth: 0.0,
+            snow_cover: 0.0,
+            rain: None,
+            randomize_rotation: false,
+            tint_jitter: 0.0,
+            gravity: false,
         }
     }
 
-    pub fn with_emission(mut self, emission: Color) -> Self {
+    pub fn with_emission(mut self, emission: Color, strength: f32) -> Self {
         self.emission = emission;
+        self.emission_strength = strength;
         self
     }
 
     // Method to create a black material with default values
     pub fn black() -> Self {
         Material {
+            name: String::new(),            // Not one of the named materials
             color: Color::new(0, 0, 0),    // Use integer values for Color
             shininess: 0.0,                 // Default shininess
             properties: [0.0, 0.0, 0.0, 0.0], // Default properties (all set to 0)
             refractive_index: 1.0,          // Default refractive index (e.g., for air)
+            dispersion: 0.0,                // Every color refracts the same
             textures: Vec::new(),            // Empty textures vector
             emission: Color::new(0, 0, 0),   // No emission for black material
+            transmission_tint: Color::new(255, 255, 255), // Transmitted light is not tinted
+            portal: None,                   // Not a portal
+            specular_map: None,             // Flat specular weight
+            roughness_map: None,            // Flat shininess
+            emissive_map: None,             // No emissive texels
+            emission_strength: 1.0,         // Emission used as is
+            height_map: None,               // Flat faces
+            parallax_depth: 0.0,            // No relief
+            snow_cover: 0.0,                // No snow
+            rain: None,                     // Dry
+            randomize_rotation: false,      // Every block the same way round
+            tint_jitter: 0.0,               // Every block the same color
+            gravity: false,                 // Stays put
         }
     }
 
+    pub fn with_transmission_tint(mut self, tint: Color) -> Self {
+        self.transmission_tint = tint;
+        self
+    }
+
+    pub fn with_dispersion(mut self, dispersion: f32) -> Self {
+        self.dispersion = dispersion;
+        self
+    }
+
+    pub fn with_gravity(mut self) -> Self {
+        self.gravity = true;
+        self
+    }
+
+    pub fn with_portal(mut self, portal: Portal) -> Self {
+        self.portal = Some(portal);
+        self
+    }
+
+    pub fn with_specular_map(mut self, map: Texture) -> Self {
+        self.specular_map = Some(map);
+        self
+    }
+
+    pub fn with_roughness_map(mut self, map: Texture) -> Self {
+        self.roughness_map = Some(map);
+        self
+    }
+
+    pub fn with_emissive_map(mut self, map: Texture, strength: f32) -> Self {
+        self.emissive_map = Some(map);
+        self.emission_strength = strength;
+        self
+    }
+
+    pub fn with_height_map(mut self, map: Texture, depth: f32) -> Self {
+        self.height_map = Some(map);
+        self.parallax_depth = depth;
+        self
+    }
+
     pub fn with_textures(mut self, textures: Vec<Texture>) -> Self {
         self.textures = textures;
         self
@@ -59,4 +182,9 @@ impl Material {
     pub fn is_transparent(&self) -> bool {
         self.properties[3] > 0.0
     }
+
+    // Cada bloque de este material se ve un poco distinto según su posición
+    pub fn varies_per_block(&self) -> bool {
+        self.randomize_rotation || self.tint_jitter > 0.0
+    }
 }
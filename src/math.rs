@@ -0,0 +1,24 @@
+// Tipos numéricos de la geometría: rayos, cajas, puntos de impacto, cámara y luces.
+// Con la feature `f64` se trazan en doble precisión para escenas muy lejos del origen;
+// los colores, las texturas y el framebuffer siguen en f32/u8 en ambos modos.
+
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+#[cfg(feature = "f64")]
+pub type Float = f64;
+
+#[cfg(not(feature = "f64"))]
+pub use nalgebra_glm::Vec3;
+#[cfg(feature = "f64")]
+pub use nalgebra_glm::DVec3 as Vec3;
+
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
+// Paso de la geometría a los cálculos de color, que siempre son f32
+#[allow(clippy::unnecessary_cast)]
+pub fn to_f32(value: Float) -> f32 {
+    value as f32
+}
@@ -0,0 +1,136 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crate::assets::resolve_asset;
+use crate::camera::Camera;
+use crate::error::RayTracerError;
+use crate::math::Vec3;
+use crate::settings::RenderSettings;
+
+// Cómo se hizo un render, como pares de clave y texto que se guardan en los bloques tEXt/iTXt del
+// PNG: la escena y un hash de su archivo, la cámara, los ajustes que cambian la imagen, la versión
+// y lo que tardó. Así una imagen de hace semanas dice de dónde salió; `raytracer info` los lee.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderMetadata {
+    pub entries: Vec<(String, String)>,
+}
+
+impl RenderMetadata {
+    pub fn new(scene: &str, camera: &Camera, settings: &RenderSettings, duration: Duration) -> Self {
+        let vector = |v: &Vec3| format!("{}, {}, {}", v.x, v.y, v.z);
+        let white_balance = match settings.white_balance {
+            kelvin if kelvin > 0.0 => format!("{} K", kelvin),
+            _ => "off".to_string(),
+        };
+        let lens_flare = match settings.lens_flare {
+            intensity if intensity > 0.0 => format!("{} ({})", intensity, settings.flare_elements.name()),
+            _ => "off".to_string(),
+        };
+        let mut entries = vec![
+            ("Software", format!("RayTracerMinecraft {}", env!("CARGO_PKG_VERSION"))),
+            ("Scene", scene.to_string()),
+        ];
+        entries.extend(scene_hash(scene).map(|hash| ("Scene hash", hash)));
+        entries.extend([
+            ("Camera eye", vector(&camera.eye)),
+            ("Camera target", vector(&camera.center)),
+            ("Camera fov", format!("{}", camera.fov.to_degrees())),
+            ("Camera roll", format!("{}", camera.roll.to_degrees())),
+            ("Projection", camera.projection.name().to_string()),
+            ("Resolution", format!("{}x{}", settings.width, settings.height)),
+            ("Samples per pixel", settings.samples_per_pixel.to_string()),
+            ("Sampler", settings.sampler.name().to_string()),
+            ("Quality", settings.quality.name().to_string()),
+            ("Render mode", settings.render_mode.name().to_string()),
+            ("White balance", white_balance),
+            ("Dither", settings.dither.to_string()),
+            ("Denoise", settings.denoise.to_string()),
+            ("Alpha", settings.alpha.name().to_string()),
+            ("Lens flare", lens_flare),
+            ("Render time", format!("{:.2}s", duration.as_secs_f32())),
+        ]);
+        RenderMetadata { entries: entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect() }
+    }
+}
+
+// CRC-32 del archivo de la escena, para saber si cambió desde el render. La demo no tiene archivo.
+fn scene_hash(scene: &str) -> Option<String> {
+    if scene == "demo" {
+        return None;
+    }
+    let data = std::fs::read(resolve_asset(scene, None).ok()?).ok()?;
+    let mut crc = flate2::Crc::new();
+    crc.update(&data);
+    Some(format!("crc32:{:08x}", crc.sum()))
+}
+
+// Los pares de texto de un PNG: primero los tEXt, luego los zTXt y por último los iTXt, cada grupo
+// en el orden del archivo. Solo se leen los que van antes de la imagen, que es donde los pone
+// `Framebuffer::to_png_with_metadata`.
+pub fn read_png(bytes: &[u8]) -> Result<Vec<(String, String)>, png::DecodingError> {
+    let reader = png::Decoder::new(bytes).read_info()?;
+    let info = reader.info();
+    let mut entries: Vec<(String, String)> = info.uncompressed_latin1_text.iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+        .collect();
+    for chunk in &info.compressed_latin1_text {
+        entries.push((chunk.keyword.clone(), chunk.get_text()?));
+    }
+    for chunk in &info.utf8_text {
+        entries.push((chunk.keyword.clone(), chunk.get_text()?));
+    }
+    Ok(entries)
+}
+
+pub fn read_png_file(path: &Path) -> Result<Vec<(String, String)>, RayTracerError> {
+    let bytes = std::fs::read(path).map_err(|e| RayTracerError::io(path, e))?;
+    read_png(&bytes).map_err(|e| RayTracerError::Image(format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::Framebuffer;
+    use crate::math::Float;
+
+    fn value<'a>(entries: &'a [(String, String)], key: &str) -> Option<&'a str> {
+        entries.iter().find(|(known, _)| known == key).map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn metadata_round_trips_through_a_png() {
+        let mut camera = Camera::new(Vec3::new(1.0, 2.0, 3.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        camera.fov = Float::to_radians(45.0);
+        let settings = RenderSettings { width: 3, height: 2, samples_per_pixel: 4, ..RenderSettings::default() };
+        let metadata = RenderMetadata::new("scenes/forest.toml", &camera, &settings, Duration::from_millis(1500));
+
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.buffer[4] = 0x12_34_56;
+        let png = framebuffer.to_png_with_metadata(&metadata).unwrap();
+        let entries = read_png(&png).unwrap();
+        assert_eq!(entries, metadata.entries);
+        assert_eq!(value(&entries, "Scene"), Some("scenes/forest.toml"));
+        assert!(value(&entries, "Scene hash").is_some_and(|hash| hash.starts_with("crc32:")), "{:?}", entries);
+        assert_eq!(value(&entries, "Camera eye"), Some("1, 2, 3"));
+        assert_eq!(value(&entries, "Resolution"), Some("3x2"));
+        assert_eq!(value(&entries, "Samples per pixel"), Some("4"));
+        assert_eq!(value(&entries, "Render time"), Some("1.50s"));
+
+        // The pixels are the same as without metadata
+        let image = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(image.get_pixel(1, 1).0, [0x12, 0x34, 0x56]);
+    }
+
+    #[test]
+    fn non_ascii_values_use_itxt_and_plain_pngs_have_none() {
+        let settings = RenderSettings { width: 1, height: 1, ..RenderSettings::default() };
+        let metadata = RenderMetadata::new("escenas/cañón.toml", &Camera::default_view(), &settings, Duration::ZERO);
+        assert_eq!(value(&metadata.entries, "Scene hash"), None, "the file doesn't exist");
+        let entries = read_png(&Framebuffer::new(1, 1).to_png_with_metadata(&metadata).unwrap()).unwrap();
+        assert_eq!(value(&entries, "Scene"), Some("escenas/cañón.toml"));
+        assert_eq!(entries.len(), metadata.entries.len());
+
+        assert!(read_png(&Framebuffer::new(1, 1).to_png().unwrap()).unwrap().is_empty());
+        assert!(read_png(b"not a png").is_err());
+    }
+}
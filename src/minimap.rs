@@ -0,0 +1,307 @@
+use crate::block::BLOCK_SIZE;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::math::{consts::PI, Float};
+use crate::primitive::Primitive;
+use crate::ray_intersect::CubeFace;
+use crate::render::texture_slot;
+use crate::scaling::blit;
+
+// Píxeles entre el mapa y los bordes de la ventana
+const MARGIN: usize = 8;
+const BACKGROUND: u32 = 0x202020; // Columnas sin nada
+const BORDER: u32 = 0xE0E0E0;
+const MARKER: u32 = 0xFF3030;
+// Media apertura en radianes y largo en fracción del lado de la cuña que marca hacia dónde se mira
+const WEDGE_HALF_ANGLE: Float = 0.5;
+const WEDGE_LENGTH: Float = 0.2;
+
+// Esquina de la ventana en la que va el mapa
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Corner::TopLeft => "top_left",
+            Corner::TopRight => "top_right",
+            Corner::BottomLeft => "bottom_left",
+            Corner::BottomRight => "bottom_right",
+        }
+    }
+}
+
+impl std::str::FromStr for Corner {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "top_left" => Ok(Corner::TopLeft),
+            "top_right" => Ok(Corner::TopRight),
+            "bottom_left" => Ok(Corner::BottomLeft),
+            "bottom_right" => Ok(Corner::BottomRight),
+            _ => Err(format!("Unknown corner '{}' (expected top_left, top_right, bottom_left or bottom_right)", name)),
+        }
+    }
+}
+
+// Lo más alto de cada columna de bloques (x, z) de un rectángulo de columnas: su altura y el color
+// de su cara de arriba. Los planos no cuentan, ocuparían el mapa entero.
+pub struct TopDown {
+    min: [i32; 2],
+    max: [i32; 2], // Exclusivo
+    columns: Vec<Option<(Float, Color)>>,
+}
+
+impl TopDown {
+    pub fn new(objects: &[Primitive], min: [i32; 2], max: [i32; 2]) -> Self {
+        let width = (max[0] - min[0]).max(0) as usize;
+        let depth = (max[1] - min[1]).max(0) as usize;
+        let mut columns: Vec<Option<(Float, Color)>> = vec![None; width * depth];
+        for object in objects {
+            let Some((low, high)) = object.bounds() else { continue };
+            // Columnas que toca la caja, recortadas al rectángulo; una valla fina toca una
+            let range = |axis: usize, low: Float, high: Float| {
+                let first = (low / BLOCK_SIZE).floor() as i32;
+                let last = ((high / BLOCK_SIZE).ceil() as i32).max(first + 1);
+                first.max(min[axis])..last.min(max[axis])
+            };
+            let (xs, zs) = (range(0, low.x, high.x), range(1, low.z, high.z));
+            if xs.is_empty() || zs.is_empty() {
+                continue;
+            }
+            let color = top_color(object);
+            for z in zs {
+                for x in xs.clone() {
+                    let column = &mut columns[(z - min[1]) as usize * width + (x - min[0]) as usize];
+                    if column.is_none_or(|(top, _)| high.y > top) {
+                        *column = Some((high.y, color));
+                    }
+                }
+            }
+        }
+        TopDown { min, max, columns }
+    }
+
+    // Altura y color de lo más alto de la columna, si hay algo y cae en el rectángulo
+    pub fn column(&self, x: i32, z: i32) -> Option<(Float, Color)> {
+        if x < self.min[0] || x >= self.max[0] || z < self.min[1] || z >= self.max[1] {
+            return None;
+        }
+        let width = (self.max[0] - self.min[0]) as usize;
+        self.columns[(z - self.min[1]) as usize * width + (x - self.min[0]) as usize]
+    }
+
+    fn covers(&self, min: [i32; 2], max: [i32; 2]) -> bool {
+        self.min[0] <= min[0] && self.min[1] <= min[1] && max[0] <= self.max[0] && max[1] <= self.max[1]
+    }
+}
+
+// Color de la cara de arriba: la media de unos pocos texels de su textura, o el color del material
+fn top_color(object: &Primitive) -> Color {
+    let material = object.material();
+    let Some(texture) = material.textures.get(texture_slot(material, &CubeFace::Top)) else {
+        return material.color;
+    };
+    let mut sum = [0u32; 3];
+    for v in 0..4 {
+        for u in 0..4 {
+            let texel = texture.sample((u as f32 + 0.5) / 4.0, (v as f32 + 0.5) / 4.0);
+            for (total, channel) in sum.iter_mut().zip([texel.r, texel.g, texel.b]) {
+                *total += u32::from(channel);
+            }
+        }
+    }
+    let [r, g, b] = sum.map(|total| (total / 16) as u8);
+    Color::new(r, g, b)
+}
+
+// Mapa visto desde arriba de la zona alrededor de la cámara, con el norte (-z) arriba, que se dibuja
+// en una esquina de la imagen. Las columnas se leen de los objetos solo cuando cambian (`invalidate`)
+// o la cámara sale de la zona leída, y el mapa se redibuja solo cuando la cámara cambia de columna;
+// en cada frame solo se copia y se le pintan encima la posición y la cuña de la vista.
+pub struct Minimap {
+    pub size: usize,   // Lado en píxeles de la ventana
+    pub corner: Corner,
+    pub zoom: Float,   // Píxeles por bloque
+    top_down: Option<TopDown>,
+    image: Option<(([i32; 2], usize, Float), Vec<u32>)>, // Mapa dibujado y la columna, lado y zoom de los que salió
+}
+
+impl Minimap {
+    pub fn new(size: usize, corner: Corner, zoom: Float) -> Self {
+        Minimap { size, corner, zoom, top_down: None, image: None }
+    }
+
+    // Los objetos de la escena cambiaron: se vuelven a leer en el próximo `draw`
+    pub fn invalidate(&mut self) {
+        self.top_down = None;
+        self.image = None;
+    }
+
+    // Dibuja el mapa sobre `pixels`, una imagen de `width` x `height`; si no cabe, no dibuja nada
+    pub fn draw(&mut self, objects: &[Primitive], camera: &Camera, pixels: &mut [u32], width: usize, height: usize) {
+        let size = self.size;
+        if size < 8 || size + 2 * MARGIN > width || size + 2 * MARGIN > height {
+            return;
+        }
+        let cell = [camera.eye.x, camera.eye.z].map(|value| (value / BLOCK_SIZE).floor() as i32);
+        let reach = (size as Float / 2.0 / self.zoom).ceil() as i32 + 1;
+        let (min, max) = (cell.map(|value| value - reach), cell.map(|value| value + reach + 1));
+        if !self.top_down.as_ref().is_some_and(|top_down| top_down.covers(min, max)) {
+            // Se lee el doble de lo que se ve para no tener que volver a leer a cada paso
+            let (min, max) = (cell.map(|value| value - 2 * reach), cell.map(|value| value + 2 * reach + 1));
+            self.top_down = Some(TopDown::new(objects, min, max));
+            self.image = None;
+        }
+        let key = (cell, size, self.zoom);
+        if self.image.as_ref().is_none_or(|(drawn, _)| *drawn != key) {
+            let top_down = self.top_down.as_ref().expect("read above");
+            self.image = Some((key, self.render(top_down, cell)));
+        }
+        let Some((_, image)) = &self.image else { return };
+
+        let left = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => MARGIN,
+            Corner::TopRight | Corner::BottomRight => width - size - MARGIN,
+        };
+        let top = match self.corner {
+            Corner::TopLeft | Corner::TopRight => MARGIN,
+            Corner::BottomLeft | Corner::BottomRight => height - size - MARGIN,
+        };
+        blit(pixels, width, image, size, left, top);
+
+        // Cuña hacia donde mira la cámara, aclarando lo que tapa, y la posición encima
+        let center = size as Float / 2.0;
+        let direction = [camera.center.x - camera.eye.x, camera.center.z - camera.eye.z];
+        let length = WEDGE_LENGTH * size as Float;
+        if direction[0].hypot(direction[1]) > 1e-6 {
+            let heading = direction[1].atan2(direction[0]);
+            let extent = length.ceil() as i32;
+            for dy in -extent..=extent {
+                for dx in -extent..=extent {
+                    let (x, y) = (dx as Float + 0.5, dy as Float + 0.5);
+                    let mut turn = (y.atan2(x) - heading).abs();
+                    turn = turn.min(2.0 * PI - turn);
+                    if x.hypot(y) <= length && turn <= WEDGE_HALF_ANGLE {
+                        let pixel = &mut pixels[(top + (center as i32 + dy) as usize) * width + left + (center as i32 + dx) as usize];
+                        *pixel = Color::lerp(&Color::from_hex(*pixel), &Color::new(255, 255, 255), 0.5).to_hex();
+                    }
+                }
+            }
+        }
+        let marker = (size / 40).max(1);
+        for y in center as usize - marker..=center as usize + marker {
+            for x in center as usize - marker..=center as usize + marker {
+                pixels[(top + y) * width + left + x] = MARKER;
+            }
+        }
+    }
+
+    // El mapa de la zona centrada en la columna `cell`, con relieve como los mapas de Minecraft: más
+    // oscuro donde la columna del norte es más alta y más claro donde es más baja
+    fn render(&self, top_down: &TopDown, cell: [i32; 2]) -> Vec<u32> {
+        let size = self.size;
+        let column_at = |pixel: usize| ((pixel as Float + 0.5 - size as Float / 2.0) / self.zoom).floor() as i32;
+        let mut image = vec![BACKGROUND; size * size];
+        for py in 0..size {
+            for px in 0..size {
+                let (x, z) = (cell[0] + column_at(px), cell[1] + column_at(py));
+                let Some((top, color)) = top_down.column(x, z) else { continue };
+                let shade = match top_down.column(x, z - 1) {
+                    Some((north, _)) if north > top => 0.8,
+                    Some((north, _)) if north < top => 1.15,
+                    _ => 1.0,
+                };
+                image[py * size + px] = color.mul_scalar(shade).to_hex();
+            }
+        }
+        for index in 0..size {
+            for pixel in [index, (size - 1) * size + index, index * size, index * size + size - 1] {
+                image[pixel] = BORDER;
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::Cube;
+    use crate::material::Material;
+    use crate::math::Vec3;
+
+    fn material(color: Color) -> Material {
+        Material::new(color, 10.0, [0.9, 0.1, 0.0, 0.0], 1.0)
+    }
+
+    fn block(x: i32, y: i32, z: i32, color: Color) -> Primitive {
+        let min = Vec3::new(x as Float, y as Float, z as Float) * BLOCK_SIZE;
+        Primitive::Cube(Cube::new(min, min + Vec3::new(BLOCK_SIZE, BLOCK_SIZE, BLOCK_SIZE), &material(color)))
+    }
+
+    fn camera_above(x: Float, z: Float) -> Camera {
+        Camera::new(Vec3::new(x, 5.0, z), Vec3::new(x, 0.0, z - 1.0), Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    #[test]
+    fn columns_show_their_highest_block() {
+        let (red, blue) = (Color::new(200, 0, 0), Color::new(0, 0, 200));
+        let wide = Primitive::Cube(Cube::new(Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 0.5, 1.0), &material(blue)));
+        let objects = [block(0, 3, 0, red), wide, block(0, 0, 0, blue)];
+        let top_down = TopDown::new(&objects, [-10, -10], [10, 10]);
+        assert_eq!(top_down.column(0, 0), Some((2.0, red)));
+        assert_eq!(top_down.column(-2, 1), Some((0.5, blue)), "a merged box covers all of its columns");
+        assert_eq!(top_down.column(2, 0), None);
+        assert_eq!(top_down.column(20, 0), None, "outside of the rectangle");
+    }
+
+    #[test]
+    fn the_map_goes_in_its_corner_with_the_camera_in_the_middle() {
+        let red = Color::new(200, 0, 0);
+        let mut minimap = Minimap::new(40, Corner::BottomRight, 4.0);
+        let (width, height) = (100, 80);
+        let mut pixels = vec![0; width * height];
+        minimap.draw(&[block(0, 0, 0, red)], &camera_above(0.25, 0.25), &mut pixels, width, height);
+        assert_eq!(pixels[0], 0, "the rest of the image is left alone");
+        let (left, top) = (width - 40 - MARGIN, height - 40 - MARGIN);
+        assert_eq!(pixels[top * width + left], BORDER);
+        assert_eq!(pixels[(top + 20) * width + left + 20], MARKER);
+        // The block under the camera fills the 4x4 pixels below and right of the center, and the
+        // wedge lightens what lies north of it
+        assert_eq!(pixels[(top + 21) * width + left + 22], red.to_hex());
+        let lightened = Color::lerp(&Color::from_hex(BACKGROUND), &Color::new(255, 255, 255), 0.5);
+        assert_eq!(pixels[(top + 17) * width + left + 20], lightened.to_hex());
+        assert_eq!(pixels[(top + 30) * width + left + 20], BACKGROUND);
+
+        let mut small = vec![0; 30 * 30];
+        minimap.draw(&[block(0, 0, 0, red)], &camera_above(0.25, 0.25), &mut small, 30, 30);
+        assert!(small.iter().all(|&pixel| pixel == 0), "a map that doesn't fit isn't drawn");
+    }
+
+    #[test]
+    fn objects_are_read_again_only_when_invalidated() {
+        let (red, blue) = (Color::new(200, 0, 0), Color::new(0, 0, 200));
+        let mut minimap = Minimap::new(40, Corner::TopLeft, 4.0);
+        let mut pixels = vec![0; 100 * 100];
+        let camera = camera_above(0.25, 0.25);
+        let at = |pixels: &[u32]| pixels[(MARGIN + 21) * 100 + MARGIN + 22];
+        minimap.draw(&[block(0, 0, 0, red)], &camera, &mut pixels, 100, 100);
+        assert_eq!(at(&pixels), red.to_hex());
+        minimap.draw(&[block(0, 0, 0, blue)], &camera, &mut pixels, 100, 100);
+        assert_eq!(at(&pixels), red.to_hex(), "the cached columns are drawn");
+        minimap.invalidate();
+        minimap.draw(&[block(0, 0, 0, blue)], &camera, &mut pixels, 100, 100);
+        assert_eq!(at(&pixels), blue.to_hex());
+
+        // Moving one block over scrolls the map by one block
+        minimap.draw(&[block(0, 0, 0, blue)], &camera_above(0.75, 0.25), &mut pixels, 100, 100);
+        assert_eq!(pixels[(MARGIN + 21) * 100 + MARGIN + 18], blue.to_hex());
+    }
+}
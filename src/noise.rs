@@ -0,0 +1,112 @@
+// Azar con semilla para la generación procedural: la misma semilla da siempre el mismo resultado,
+// en cualquier máquina y con cualquier número de hilos
+
+// Generador SplitMix64: pequeño, rápido y suficiente para colocar bloques
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniforme en [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    // Entero uniforme entre `min` y `max`, ambos incluidos
+    pub fn range(&mut self, min: i32, max: i32) -> i32 {
+        min + (self.next_u64() % (max - min + 1) as u64) as i32
+    }
+
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+}
+
+// Semilla propia de una posición de la cuadrícula, para decidir algo por bloque sin recorrerlos en orden
+pub fn position_seed(pos: [i32; 3], seed: u64) -> u64 {
+    let [x, y, z] = pos.map(|value| value as u32 as u64);
+    let mut rng = Rng::new(seed ^ x.wrapping_mul(0x8CB9_2BA7_2F3D_8DD7) ^ y.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ z.wrapping_mul(0xC2B2_AE3D_27D4_EB4F));
+    rng.next_u64()
+}
+
+// Valor en [0, 1) fijo para cada posición de la cuadrícula
+pub fn lattice(pos: [i32; 3], seed: u64) -> f32 {
+    Rng::new(position_seed(pos, seed)).next_f32()
+}
+
+// Ruido de valor: los valores de la cuadrícula entera se interpolan con una curva suave, así que
+// el resultado, en [0, 1), cambia de forma continua con el punto
+pub fn value_noise(point: [f32; 3], seed: u64) -> f32 {
+    let cell = point.map(|value| value.floor() as i32);
+    let t = [0, 1, 2].map(|axis| {
+        let f = point[axis] - cell[axis] as f32;
+        f * f * (3.0 - 2.0 * f)
+    });
+    let corner = |dx: i32, dy: i32, dz: i32| lattice([cell[0] + dx, cell[1] + dy, cell[2] + dz], seed);
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let layer = |dy: i32| {
+        let near = lerp(corner(0, dy, 0), corner(1, dy, 0), t[0]);
+        let far = lerp(corner(0, dy, 1), corner(1, dy, 1), t[0]);
+        lerp(near, far, t[2])
+    };
+    lerp(layer(0), layer(1), t[1])
+}
+
+// Suma de `octaves` capas de ruido, cada una del doble de frecuencia y la mitad de peso, en [0, 1)
+pub fn fractal_noise(point: [f32; 3], octaves: u32, seed: u64) -> f32 {
+    let (mut total, mut weight, mut frequency, mut weights) = (0.0, 1.0, 1.0, 0.0);
+    for octave in 0..octaves {
+        total += weight * value_noise(point.map(|value| value * frequency), seed.wrapping_add(u64::from(octave)));
+        weights += weight;
+        weight *= 0.5;
+        frequency *= 2.0;
+    }
+    total / weights
+}
+
+// Ruido de crestas en [0, 1]: vale 1 donde el ruido fractal cruza su valor medio, así que los
+// valores altos forman láminas y túneles finos en lugar de manchas
+pub fn ridged_noise(point: [f32; 3], octaves: u32, seed: u64) -> f32 {
+    1.0 - (fractal_noise(point, octaves, seed) * 2.0 - 1.0).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        let mut rng = Rng::new(7);
+        assert!((0..1000).map(|_| rng.range(-2, 3)).all(|value| (-2..=3).contains(&value)));
+    }
+
+    #[test]
+    fn noise_is_continuous_and_matches_the_lattice() {
+        let seed = 3;
+        assert_eq!(value_noise([2.0, 0.0, -5.0], seed), lattice([2, 0, -5], seed));
+        for step in 0..100 {
+            let x = step as f32 * 0.05;
+            let (a, b) = (value_noise([x, 0.3, 1.7], seed), value_noise([x + 0.001, 0.3, 1.7], seed));
+            assert!((0.0..1.0).contains(&a));
+            assert!((a - b).abs() < 0.01, "jump at {}: {} to {}", x, a, b);
+            assert!((0.0..=1.0).contains(&ridged_noise([x, 0.3, 1.7], 3, seed)));
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+
+use crate::error::RayTracerError;
+use crate::framebuffer::Framebuffer;
+use crate::minimap::Corner;
+use crate::settings::AlphaMode;
+
+// Píxeles entre el logo y el texto y los bordes de su esquina, si no se pide otra cosa
+pub const DEFAULT_OFFSET: usize = 16;
+// Alto de imagen por cada aumento del texto: a 1080 píxeles de alto las letras miden 7 x 4 = 28
+const CAPTION_LINES: usize = 270;
+const CAPTION_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+// Sombra un aumento abajo a la derecha, para que el texto se lea también sobre el cielo claro
+const CAPTION_SHADOW: Rgba<u8> = Rgba([0, 0, 0, 160]);
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+// Letras de 5x7 píxeles, una fila por byte con la columna izquierda en el bit 4. Las minúsculas se
+// dibujan como mayúsculas y lo que no está aquí, como '?'
+const GLYPHS: [(char, [u8; 7]); 58] = [
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    (';', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000]),
+    ('!', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100]),
+    ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+    ('\'', [0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('"', [0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('/', [0b00001, 0b00010, 0b00010, 0b00100, 0b01000, 0b01000, 0b10000]),
+    ('&', [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101]),
+    ('%', [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011]),
+    ('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+    ('@', [0b01110, 0b10001, 0b10111, 0b10101, 0b10111, 0b10000, 0b01110]),
+    ('*', [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000]),
+    ('|', [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+];
+
+fn glyph(character: char) -> [u8; 7] {
+    let character = character.to_ascii_uppercase();
+    let find = |wanted: char| GLYPHS.iter().find(|(name, _)| *name == wanted).map(|(_, rows)| *rows);
+    find(character).or_else(|| find('?')).unwrap_or_default()
+}
+
+// Un logo y/o un texto que se componen en una esquina de las imágenes guardadas, justo antes de
+// escribirlas. Sin ninguno de los dos no hace nada.
+#[derive(Clone, Debug)]
+pub struct Overlay {
+    pub logo: Option<RgbaImage>,
+    pub caption: Option<String>, // Debajo del logo, alineado hacia el lado de la esquina
+    pub corner: Corner,
+    pub offset: usize, // Píxeles hasta los dos bordes de la esquina
+    pub opacity: f32,  // Multiplica el alfa del logo y del texto
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Overlay { logo: None, caption: None, corner: Corner::BottomRight, offset: DEFAULT_OFFSET, opacity: 1.0 }
+    }
+}
+
+impl Overlay {
+    pub fn load_logo(path: &str) -> Result<RgbaImage, RayTracerError> {
+        image::open(path).map(|image| image.to_rgba8()).map_err(|e| RayTracerError::image(path, e))
+    }
+
+    // Compone el logo y el texto sobre el framebuffer. Un logo que no cabe entre los márgenes se
+    // reduce sin deformarlo; un texto que no cabe ni con las letras más pequeñas es un error. Si el
+    // framebuffer tiene canal alfa, `alpha` dice cómo está guardado su color, y el alfa resultante
+    // es el de poner el logo encima.
+    pub fn apply(&self, framebuffer: &mut Framebuffer, alpha: AlphaMode) -> Result<(), RayTracerError> {
+        let layer = self.layer(framebuffer.width, framebuffer.height)?;
+        let (layer_width, layer_height) = (layer.width() as usize, layer.height() as usize);
+        let left = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => self.offset,
+            Corner::TopRight | Corner::BottomRight => framebuffer.width - self.offset - layer_width,
+        };
+        let top = match self.corner {
+            Corner::TopLeft | Corner::TopRight => self.offset,
+            Corner::BottomLeft | Corner::BottomRight => framebuffer.height - self.offset - layer_height,
+        };
+
+        let opacity = self.opacity.clamp(0.0, 1.0);
+        for (x, y, pixel) in layer.enumerate_pixels() {
+            let cover = pixel[3] as f32 / 255.0 * opacity;
+            if cover <= 0.0 {
+                continue;
+            }
+            let index = (top + y as usize) * framebuffer.width + left + x as usize;
+            let below = framebuffer.alpha.as_ref().map_or(1.0, |alpha| alpha[index] as f32 / 255.0);
+            // Todo se mezcla premultiplicado: el color de debajo solo lo está ya en ese modo
+            let existing = framebuffer.buffer[index];
+            let under = [existing >> 16, existing >> 8, existing].map(|channel| {
+                let value = (channel & 0xFF) as f32;
+                if alpha == AlphaMode::Straight { value * below } else { value }
+            });
+            let coverage = cover + below * (1.0 - cover);
+            let mixed: [f32; 3] = std::array::from_fn(|c| {
+                let value = pixel[c] as f32 * cover + under[c] * (1.0 - cover);
+                if alpha == AlphaMode::Straight && coverage > 0.0 { value / coverage } else { value }
+            });
+            let [r, g, b] = mixed.map(|value| value.round().clamp(0.0, 255.0) as u32);
+            framebuffer.buffer[index] = (r << 16) | (g << 8) | b;
+            if let Some(alpha) = framebuffer.alpha.as_mut() {
+                alpha[index] = (coverage * 255.0).round() as u8;
+            }
+        }
+        Ok(())
+    }
+
+    // El logo, reducido si hace falta, con el texto debajo, los dos pegados al lado de la esquina
+    fn layer(&self, width: usize, height: usize) -> Result<RgbaImage, RayTracerError> {
+        let (room_width, room_height) = match (width.checked_sub(2 * self.offset), height.checked_sub(2 * self.offset)) {
+            (Some(room_width), Some(room_height)) if room_width > 0 && room_height > 0 => (room_width as u32, room_height as u32),
+            _ => return Err(RayTracerError::Image(format!("An overlay offset of {} pixels leaves no room in a {}x{} image", self.offset, width, height))),
+        };
+        let caption = match self.caption.as_deref().filter(|text| !text.is_empty()) {
+            Some(text) => Some(caption_image(text, (height / CAPTION_LINES).max(1) as u32, room_width, room_height)?),
+            None => None,
+        };
+        // Entre el logo y el texto queda el alto de dos píxeles de una letra
+        let gap = caption.as_ref().map_or(0, |caption| caption.height() / (GLYPH_HEIGHT + 1) * 2);
+        let caption_height = caption.as_ref().map_or(0, |caption| caption.height() + gap);
+
+        let logo = match &self.logo {
+            Some(logo) if logo.width() > 0 && logo.height() > 0 => {
+                let room_height = room_height.saturating_sub(caption_height);
+                if room_height == 0 {
+                    return Err(RayTracerError::Image(format!("The caption leaves no room for the overlay image in a {}x{} image", width, height)));
+                }
+                if logo.width() <= room_width && logo.height() <= room_height {
+                    Some(logo.clone())
+                } else {
+                    let scale = (room_width as f32 / logo.width() as f32).min(room_height as f32 / logo.height() as f32);
+                    let size = |side: u32, room: u32| ((side as f32 * scale).round() as u32).clamp(1, room);
+                    Some(imageops::resize(logo, size(logo.width(), room_width), size(logo.height(), room_height), FilterType::Triangle))
+                }
+            }
+            _ => None,
+        };
+
+        let logo_size = logo.as_ref().map_or((0, 0), |logo| (logo.width(), logo.height()));
+        let caption_width = caption.as_ref().map_or(0, RgbaImage::width);
+        let layer_width = logo_size.0.max(caption_width);
+        let layer_height = if logo.is_some() { logo_size.1 + caption_height } else { caption_height - gap };
+        let mut layer = RgbaImage::new(layer_width, layer_height);
+        let right_aligned = matches!(self.corner, Corner::TopRight | Corner::BottomRight);
+        let x = |part_width: u32| if right_aligned { (layer_width - part_width) as i64 } else { 0 };
+        if let Some(logo) = &logo {
+            imageops::replace(&mut layer, logo, x(logo.width()), 0);
+        }
+        if let Some(caption) = &caption {
+            imageops::replace(&mut layer, caption, x(caption.width()), (layer_height - caption.height()) as i64);
+        }
+        Ok(layer)
+    }
+}
+
+// El texto con las letras de 5x7 a `preferred_scale` aumentos, o a menos si no cabe en el espacio
+fn caption_image(text: &str, preferred_scale: u32, room_width: u32, room_height: u32) -> Result<RgbaImage, RayTracerError> {
+    let characters: Vec<char> = text.chars().collect();
+    // Una columna vacía entre letras y la sombra un aumento más allá
+    let size = |scale: u32| ((characters.len() as u32 * (GLYPH_WIDTH + 1) - 1) * scale + scale, (GLYPH_HEIGHT + 1) * scale);
+    let Some(scale) = (1..=preferred_scale).rev().find(|&scale| size(scale).0 <= room_width && size(scale).1 <= room_height) else {
+        return Err(RayTracerError::Image(format!(
+            "The caption \"{}\" is {}x{} pixels even with the smallest letters, more than the {}x{} the image leaves inside the offset",
+            text, size(1).0, size(1).1, room_width, room_height
+        )));
+    };
+
+    let (width, height) = size(scale);
+    let mut image = RgbaImage::new(width, height);
+    for (shift, color) in [(scale, CAPTION_SHADOW), (0, CAPTION_COLOR)] {
+        for (index, &character) in characters.iter().enumerate() {
+            let left = index as u32 * (GLYPH_WIDTH + 1) * scale + shift;
+            for (row, bits) in glyph(character).iter().enumerate() {
+                for column in (0..GLYPH_WIDTH).filter(|column| bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0) {
+                    let (x, y) = (left + column * scale, row as u32 * scale + shift);
+                    for (dx, dy) in (0..scale).flat_map(|dx| (0..scale).map(move |dy| (dx, dy))) {
+                        image.put_pixel(x + dx, y + dy, color);
+                    }
+                }
+            }
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray_frame(width: usize, height: usize) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(width, height);
+        framebuffer.buffer.fill(0x808080);
+        framebuffer
+    }
+
+    fn red_logo(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([255, 0, 0, 255]))
+    }
+
+    #[test]
+    fn logo_lands_in_its_corner_at_the_given_opacity() {
+        let mut framebuffer = gray_frame(20, 10);
+        let overlay = Overlay { logo: Some(red_logo(4, 3)), offset: 2, opacity: 0.5, ..Overlay::default() };
+        overlay.apply(&mut framebuffer, AlphaMode::Off).unwrap();
+        // Bottom right: columns 14 to 17 and rows 5 to 7, half red and half gray
+        assert_eq!(framebuffer.buffer[7 * 20 + 17], (192 << 16) | (64 << 8) | 64);
+        assert_eq!(framebuffer.buffer[5 * 20 + 14], framebuffer.buffer[7 * 20 + 17]);
+        let touched = framebuffer.buffer.iter().filter(|&&pixel| pixel != 0x808080).count();
+        assert_eq!(touched, 12, "only the logo's pixels change");
+        assert!(framebuffer.alpha.is_none());
+    }
+
+    #[test]
+    fn oversized_logos_shrink_and_impossible_offsets_fail() {
+        let mut framebuffer = gray_frame(20, 10);
+        let overlay = Overlay { logo: Some(red_logo(40, 10)), offset: 1, corner: Corner::TopLeft, ..Overlay::default() };
+        overlay.apply(&mut framebuffer, AlphaMode::Off).unwrap();
+        // 40x10 scaled to the 18 columns left between the offsets keeps its 4:1 shape
+        let red_rows: Vec<usize> = (0..10).filter(|y| framebuffer.buffer[y * 20 + 1] == 0xFF0000).collect();
+        assert_eq!(red_rows, vec![1, 2, 3, 4]);
+        assert_eq!(framebuffer.buffer[20 + 19], 0x808080, "the right margin stays clear");
+
+        let overlay = Overlay { logo: Some(red_logo(4, 4)), offset: 5, ..Overlay::default() };
+        let error = overlay.apply(&mut gray_frame(20, 10), AlphaMode::Off).unwrap_err();
+        assert!(error.to_string().contains("leaves no room in a 20x10 image"), "{}", error);
+    }
+
+    #[test]
+    fn captions_use_the_bitmap_font_and_reject_text_that_cannot_fit() {
+        let mut framebuffer = gray_frame(64, 16);
+        let overlay = Overlay { caption: Some("Hi!".to_string()), offset: 0, corner: Corner::TopLeft, ..Overlay::default() };
+        overlay.apply(&mut framebuffer, AlphaMode::Off).unwrap();
+        // The left stem of the H is white from the top row down, with its shadow beside it
+        assert!((0..7).all(|y| framebuffer.buffer[y * 64] == 0xFFFFFF));
+        assert_ne!(framebuffer.buffer[7 * 64 + 1], 0x808080, "the shadow sits one pixel down and right");
+        assert_eq!(framebuffer.buffer[63], 0x808080);
+        assert_eq!(glyph('h'), glyph('H'));
+        assert_eq!(glyph('~'), glyph('?'));
+
+        let overlay = Overlay { caption: Some("far too long for this frame".to_string()), offset: 0, ..Overlay::default() };
+        let error = overlay.apply(&mut gray_frame(64, 16), AlphaMode::Off).unwrap_err();
+        assert!(error.to_string().contains("even with the smallest letters"), "{}", error);
+    }
+
+    #[test]
+    fn logos_over_a_transparent_sky_become_opaque() {
+        for mode in [AlphaMode::Straight, AlphaMode::Premultiplied] {
+            let mut framebuffer = Framebuffer::new(8, 8);
+            framebuffer.alpha = Some(vec![0; 64]);
+            let overlay = Overlay { logo: Some(red_logo(2, 2)), offset: 0, opacity: 0.5, corner: Corner::TopLeft, ..Overlay::default() };
+            overlay.apply(&mut framebuffer, mode).unwrap();
+            let alpha = framebuffer.alpha.as_ref().unwrap();
+            assert_eq!(alpha[0], 128);
+            assert_eq!(alpha[2], 0);
+            // Straight keeps the logo's own red, premultiplied carries half of it
+            let expected = if mode == AlphaMode::Straight { 0xFF0000 } else { 0x800000 };
+            assert_eq!(framebuffer.buffer[0], expected, "{:?}", mode);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::math::{Float, Vec3};
+use crate::material::Material;
+use crate::ray_intersect::{RayIntersect, Hit, CubeFace, Ray};
+
+// Plano infinito que pasa por `point`, como una superficie de agua o un suelo sin bordes. Se ve
+// desde los dos lados, pero la normal siempre es la suya.
+#[derive(Clone)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Material,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: &Material) -> Self {
+        Plane { point, normal: normal.normalize(), material: material.clone() }
+    }
+}
+
+impl RayIntersect for Plane {
+    fn ray_intersect(&self, ray: &Ray) -> Option<Hit> {
+        let denominator = ray.dir.dot(&self.normal);
+        if denominator.abs() < 1e-9 {
+            return None;
+        }
+        let distance: Float = (self.point - ray.origin).dot(&self.normal) / denominator;
+        if distance < ray.t_min || distance >= ray.t_max {
+            return None;
+        }
+
+        Some(Hit::new(ray.at(distance), self.normal, distance, CubeFace::from_normal(&self.normal)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rays_hit_the_plane_ahead_of_them() {
+        let water = Plane::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 2.0, 0.0), &Material::black());
+        let down = Vec3::new(0.0, -1.0, 1.0).normalize();
+        let hit = water.ray_intersect(&Ray::new(Vec3::zeros(), down)).expect("the plane is below the ray");
+        assert!((hit.point.y + 1.0).abs() < 1e-9 && (hit.point.z - 1.0).abs() < 1e-9);
+        assert_eq!((hit.normal, hit.face), (Vec3::new(0.0, 1.0, 0.0), CubeFace::Top));
+
+        // Parallel, pointing away or stopping short: no hit
+        assert!(water.ray_intersect(&Ray::new(Vec3::zeros(), Vec3::new(1.0, 0.0, 0.0))).is_none());
+        assert!(water.ray_intersect(&Ray::new(Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0))).is_none());
+        assert!(water.ray_intersect(&Ray::segment(Vec3::zeros(), down, 1.0)).is_none());
+        // From below it's hit too
+        assert!(water.ray_intersect(&Ray::new(Vec3::new(0.0, -3.0, 0.0), Vec3::new(0.0, 1.0, 0.0))).is_some());
+    }
+}
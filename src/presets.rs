@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::camera::{Camera, Projection};
+use crate::math::{Float, Vec3};
+
+// Ranuras de cámara guardadas (Ctrl+1 a Ctrl+9). inset_camera se valida contra ellas también en las
+// compilaciones sin ventana, por eso están en la biblioteca
+pub const SLOTS: usize = 9;
+
+// Las posiciones de cámara guardadas se escriben junto a la escena:
+//
+// [[preset]]
+// slot = 1
+// eye = [0.0, 2.0, 6.5]
+// center = [0.0, 1.0, 0.0]
+// up = [0.0, 1.0, 0.0]
+// fov = 60.0   # Grados
+// roll = 0.0   # Grados
+// projection = "perspective"   # O una tabla como [preset.projection.panini] con fov (radianes) y d
+#[derive(Serialize, Deserialize, Default)]
+struct PresetFile {
+    #[serde(default, rename = "preset")]
+    presets: Vec<Preset>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Preset {
+    slot: usize,
+    eye: [Float; 3],
+    center: [Float; 3],
+    up: [Float; 3],
+    fov: Float,
+    roll: Float,
+    // Los archivos guardados antes de que se guardara la proyección son de perspectiva
+    #[serde(default = "perspective")]
+    projection: Projection,
+}
+
+fn perspective() -> Projection {
+    Projection::Perspective
+}
+
+pub struct CameraPresets {
+    path: PathBuf,
+    slots: [Option<Preset>; SLOTS],
+}
+
+fn to_array(v: &Vec3) -> [Float; 3] {
+    [v.x, v.y, v.z]
+}
+
+fn to_vec3(a: &[Float; 3]) -> Vec3 {
+    Vec3::new(a[0], a[1], a[2])
+}
+
+impl CameraPresets {
+    // cameras.toml junto al archivo de escena; la escena demo no tiene archivo y usa el directorio actual
+    pub fn path_for_scene(scene_name: &str) -> PathBuf {
+        match scene_name {
+            "demo" => PathBuf::from("cameras.toml"),
+            path => Path::new(path).with_file_name("cameras.toml"),
+        }
+    }
+
+    // Un archivo que todavía no existe equivale a no tener nada guardado
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let mut presets = CameraPresets { path, slots: Default::default() };
+        let contents = match std::fs::read_to_string(&presets.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(presets),
+            Err(e) => return Err(format!("Failed to read {}: {}", presets.path.display(), e)),
+        };
+        let file: PresetFile = toml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {}: {}", presets.path.display(), e))?;
+        for preset in file.presets {
+            if !(1..=SLOTS).contains(&preset.slot) {
+                return Err(format!("{}: slot {} is out of range (1 to {})", presets.path.display(), preset.slot, SLOTS));
+            }
+            let index = preset.slot - 1;
+            presets.slots[index] = Some(preset);
+        }
+        Ok(presets)
+    }
+
+    // Guarda la cámara en `slot` (de 1 a SLOTS) y reescribe el archivo
+    pub fn store(&mut self, slot: usize, camera: &Camera) -> Result<(), String> {
+        self.slots[slot - 1] = Some(Preset {
+            slot,
+            eye: to_array(&camera.eye),
+            center: to_array(&camera.center),
+            up: to_array(&camera.up),
+            fov: camera.fov.to_degrees(),
+            roll: camera.roll.to_degrees(),
+            projection: camera.projection,
+        });
+        let file = PresetFile { presets: self.slots.iter().flatten().cloned().collect() };
+        let contents = toml::to_string(&file)
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))?;
+        std::fs::write(&self.path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+
+    pub fn recall(&self, slot: usize) -> Option<Camera> {
+        self.slots[slot - 1].as_ref().map(|preset| {
+            let mut camera = Camera::new(to_vec3(&preset.eye), to_vec3(&preset.center), to_vec3(&preset.up));
+            camera.fov = preset.fov.to_radians();
+            camera.roll = preset.roll.to_radians();
+            camera.projection = preset.projection;
+            camera
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::consts::PI;
+
+    #[test]
+    fn presets_survive_a_reload_with_their_projection() {
+        let path = std::env::temp_dir().join(format!("raytracer_minecraft_cameras_{}.toml", std::process::id()));
+        let mut panini = Camera::new(Vec3::new(1.0, 2.0, 3.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        panini.fov = 0.9;
+        panini.roll = 0.1;
+        panini.projection = Projection::Panini { fov: PI * 2.0 / 3.0, d: 0.5 };
+        let mut fisheye = panini.clone();
+        fisheye.projection = Projection::Fisheye { fov: PI };
+
+        let mut presets = CameraPresets::load(path.clone()).unwrap();
+        presets.store(2, &panini).unwrap();
+        presets.store(5, &fisheye).unwrap();
+        presets.store(7, &Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0))).unwrap();
+        let reloaded = CameraPresets::load(path.clone());
+        std::fs::remove_file(&path).unwrap();
+        let reloaded = reloaded.unwrap();
+
+        let recalled = reloaded.recall(2).unwrap();
+        assert!(recalled.is_near(&panini, 1e-4), "eye, center, fov and roll come back");
+        assert_eq!(recalled.projection, panini.projection);
+        assert_eq!(reloaded.recall(5).unwrap().projection, fisheye.projection);
+        assert_eq!(reloaded.recall(7).unwrap().projection, Projection::Perspective);
+        assert!(reloaded.recall(1).is_none());
+    }
+
+    #[test]
+    fn presets_saved_without_a_projection_are_perspective() {
+        let path = std::env::temp_dir().join(format!("raytracer_minecraft_old_cameras_{}.toml", std::process::id()));
+        std::fs::write(&path, "[[preset]]\nslot = 3\neye = [0.0, 2.0, 6.5]\ncenter = [0.0, 1.0, 0.0]\nup = [0.0, 1.0, 0.0]\nfov = 60.0\nroll = 0.0\n").unwrap();
+        let presets = CameraPresets::load(path.clone());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(presets.unwrap().recall(3).unwrap().projection, Projection::Perspective);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::cube::Cube;
+use crate::material::Material;
+use crate::math::Vec3;
+use crate::plane::Plane;
+use crate::ray_intersect::{Hit, Ray, RayIntersect};
+use crate::sphere::Sphere;
+
+// Cualquier objeto que puede ir en una escena. Es un enum y no un `Box<dyn RayIntersect>` para que
+// el bucle de intersección, que recorre todos los objetos en cada rayo, no pague una llamada
+// indirecta por objeto: con solo cajas el match siempre toma la misma rama.
+#[derive(Clone)]
+pub enum Primitive {
+    Cube(Cube),
+    Sphere(Sphere),
+    Plane(Plane),
+}
+
+impl Primitive {
+    pub fn material(&self) -> &Material {
+        match self {
+            Primitive::Cube(cube) => &cube.material,
+            Primitive::Sphere(sphere) => &sphere.material,
+            Primitive::Plane(plane) => &plane.material,
+        }
+    }
+
+    // La misma forma con otro material, como en el modo arcilla
+    pub fn with_material(&self, material: &Material) -> Primitive {
+        match self {
+            Primitive::Cube(cube) => Primitive::Cube(Cube::new(cube.min, cube.max, material).with_faces(cube.faces)),
+            Primitive::Sphere(sphere) => Primitive::Sphere(Sphere::new(sphere.center, sphere.radius, material)),
+            Primitive::Plane(plane) => Primitive::Plane(Plane::new(plane.point, plane.normal, material)),
+        }
+    }
+
+    // Esquinas de la caja que lo contiene; los planos no tienen
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        match self {
+            Primitive::Cube(cube) => Some((cube.min, cube.max)),
+            Primitive::Sphere(sphere) => {
+                let extent = Vec3::new(sphere.radius, sphere.radius, sphere.radius);
+                Some((sphere.center - extent, sphere.center + extent))
+            }
+            Primitive::Plane(_) => None,
+        }
+    }
+
+    // Las partes que trabajan con la rejilla de bloques (luz suave, rejilla de luz, portales) solo
+    // tienen en cuenta las cajas
+    pub fn as_cube(&self) -> Option<&Cube> {
+        match self {
+            Primitive::Cube(cube) => Some(cube),
+            _ => None,
+        }
+    }
+}
+
+impl RayIntersect for Primitive {
+    #[inline]
+    fn ray_intersect(&self, ray: &Ray) -> Option<Hit> {
+        match self {
+            Primitive::Cube(cube) => cube.ray_intersect(ray),
+            Primitive::Sphere(sphere) => sphere.ray_intersect(ray),
+            Primitive::Plane(plane) => plane.ray_intersect(ray),
+        }
+    }
+}
+
+impl From<Cube> for Primitive {
+    fn from(cube: Cube) -> Self {
+        Primitive::Cube(cube)
+    }
+}
+
+impl From<Sphere> for Primitive {
+    fn from(sphere: Sphere) -> Self {
+        Primitive::Sphere(sphere)
+    }
+}
+
+impl From<Plane> for Primitive {
+    fn from(plane: Plane) -> Self {
+        Primitive::Plane(plane)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn primitives_dispatch_to_their_shape() {
+        let red = Material::new(Color::new(255, 0, 0), 10.0, [1.0, 0.0, 0.0, 0.0], 0.0);
+        let primitives: Vec<Primitive> = vec![
+            Cube::new(Vec3::new(-1.0, -1.0, -6.0), Vec3::new(1.0, 1.0, -5.0), &red).into(),
+            Sphere::new(Vec3::new(0.0, 0.0, -3.0), 0.5, &red).into(),
+            Plane::new(Vec3::new(0.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0), &red).into(),
+        ];
+        let ray = Ray::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0));
+        let distances: Vec<Option<f64>> = primitives.iter().map(|primitive| primitive.ray_intersect(&ray).map(|hit| hit.t as f64)).collect();
+        assert_eq!(distances, vec![Some(5.0), Some(2.5), Some(10.0)]);
+
+        assert_eq!(primitives[1].bounds(), Some((Vec3::new(-0.5, -0.5, -3.5), Vec3::new(0.5, 0.5, -2.5))));
+        assert!(primitives[2].bounds().is_none());
+        assert!(primitives[0].as_cube().is_some() && primitives[1].as_cube().is_none());
+        let gray = Material::new(Color::new(90, 90, 90), 1.0, [1.0, 0.0, 0.0, 0.0], 0.0);
+        assert!(primitives.iter().all(|primitive| primitive.with_material(&gray).material().color == gray.color));
+    }
+}
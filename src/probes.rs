@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use crate::block::BLOCK_SIZE;
+use crate::camera::{Camera, Projection};
+use crate::color::Color;
+use crate::cube::Cube;
+use crate::cubemap::{CubemapFace, CUBEMAP_FACES};
+use crate::framebuffer::Framebuffer;
+use crate::math::{Float, Vec3};
+use crate::render::render;
+use crate::scene::Scene;
+use crate::settings::{RenderMode, RenderSettings, StereoMode};
+
+// Lado en píxeles de cada cara de una sonda
+pub const PROBE_SIZE: usize = 32;
+// Bloques por lado de los chunks en que se colocan las sondas automáticas
+pub const PROBE_CHUNK_BLOCKS: i32 = 16;
+
+// Cubemap pequeño de la escena visto desde un punto, que los reflejos poco importantes leen en lugar
+// de trazar rayos
+#[derive(Clone, Debug)]
+pub struct ReflectionProbe {
+    pub position: Vec3,
+    size: usize,
+    faces: Vec<Vec<u32>>, // En el orden de CUBEMAP_FACES
+}
+
+impl ReflectionProbe {
+    // Renderiza las seis caras con el modo de cubemap, una muestra por píxel y sin sondas, para que
+    // ninguna lea una versión vieja de sí misma
+    pub fn render(position: Vec3, size: usize, scene: &Scene, settings: &RenderSettings) -> Self {
+        let settings = RenderSettings {
+            width: size,
+            height: size,
+            samples_per_pixel: 1,
+            render_mode: RenderMode::Shaded,
+            stereo: StereoMode::Off,
+            checkerboard: false,
+            half_res_secondary: false,
+            focus_radius: 0.0,
+            outlines: false,
+            light_gizmos: false,
+            dither: false,
+            denoise: false,
+            probes: None,
+            ..settings.clone()
+        };
+        let mut scene = scene.clone();
+        scene.camera = Camera::new(position, position + Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+        let faces = CUBEMAP_FACES
+            .iter()
+            .map(|&face| {
+                scene.camera.projection = Projection::CubemapFace(face);
+                let mut framebuffer = Framebuffer::new(size, size);
+                render(&mut framebuffer, &scene, &settings);
+                framebuffer.buffer
+            })
+            .collect();
+        ReflectionProbe { position, size, faces }
+    }
+
+    // Color que ve la sonda en `direction`, del texel más cercano
+    pub fn sample(&self, direction: &Vec3) -> Color {
+        let (face, s, t) = CubemapFace::from_direction(direction);
+        let index = CUBEMAP_FACES.iter().position(|&candidate| candidate == face).unwrap_or(0);
+        let texel = |coordinate: Float| (((coordinate + 1.0) * 0.5 * self.size as Float) as usize).min(self.size - 1);
+        let pixel = self.faces[index][texel(t) * self.size + texel(s)];
+        Color::new((pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8)
+    }
+}
+
+// Sondas de una escena. Se renderizan al cargarla y otra vez cada vez que cambian sus bloques.
+#[derive(Clone, Debug, Default)]
+pub struct ReflectionProbes {
+    probes: Vec<ReflectionProbe>,
+}
+
+impl ReflectionProbes {
+    pub fn render(positions: &[Vec3], scene: &Scene, settings: &RenderSettings) -> Self {
+        let probes = positions
+            .iter()
+            .map(|&position| ReflectionProbe::render(position, PROBE_SIZE, scene, settings))
+            .collect();
+        ReflectionProbes { probes }
+    }
+
+    pub fn nearest(&self, point: &Vec3) -> Option<&ReflectionProbe> {
+        self.probes
+            .iter()
+            .min_by(|a, b| (a.position - point).magnitude_squared().total_cmp(&(b.position - point).magnitude_squared()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.probes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.probes.is_empty()
+    }
+}
+
+// Sondas automáticas: una por chunk de PROBE_CHUNK_BLOCKS bloques de lado con algo que refleje, en el
+// centro del chunk en horizontal y un bloque por encima de lo más alto que refleja. El orden solo
+// depende de los chunks, así que es el mismo en cada carga.
+pub fn auto_positions(objects: &[Cube]) -> Vec<Vec3> {
+    let chunk = PROBE_CHUNK_BLOCKS as Float * BLOCK_SIZE;
+    let mut tops: BTreeMap<[i32; 3], Float> = BTreeMap::new();
+    for object in objects.iter().filter(|object| object.material.properties[2] > 0.0) {
+        let center = (object.min + object.max) * 0.5;
+        let key = [0, 1, 2].map(|axis| (center[axis] / chunk).floor() as i32);
+        let top = tops.entry(key).or_insert(Float::NEG_INFINITY);
+        *top = top.max(object.max.y);
+    }
+    tops.into_iter()
+        .map(|([x, _, z], top)| Vec3::new((x as Float + 0.5) * chunk, top + BLOCK_SIZE, (z as Float + 0.5) * chunk))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::material::Material;
+    use crate::ray_intersect::Ray;
+    use crate::render::cast_ray;
+    use crate::sampler::PixelSample;
+    use crate::sky::{Ambient, Sky};
+
+    fn uniform_probe(position: Vec3, color: u32) -> ReflectionProbe {
+        ReflectionProbe { position, size: 2, faces: vec![vec![color; 4]; 6] }
+    }
+
+    #[test]
+    fn probes_sample_their_faces_and_the_nearest_wins() {
+        let mut probe = uniform_probe(Vec3::zeros(), 0);
+        // +Y is the third face; its texel (1, 0) looks toward +x and -z
+        probe.faces[2][1] = 0xFF0000;
+        assert_eq!(probe.sample(&Vec3::new(0.5, 1.0, -0.5)), Color::new(255, 0, 0));
+        assert_eq!(probe.sample(&Vec3::new(-0.5, 1.0, -0.5)), Color::new(0, 0, 0));
+
+        let probes = ReflectionProbes { probes: vec![uniform_probe(Vec3::zeros(), 1), uniform_probe(Vec3::new(10.0, 0.0, 0.0), 2)] };
+        assert_eq!(probes.nearest(&Vec3::new(7.0, 3.0, 0.0)).unwrap().position.x, 10.0);
+        assert!(ReflectionProbes::default().nearest(&Vec3::zeros()).is_none());
+    }
+
+    #[test]
+    fn rough_and_deep_reflections_read_the_probe() {
+        let red = 0xFF0000;
+        let probes = Arc::new(ReflectionProbes { probes: vec![uniform_probe(Vec3::new(0.0, 1.0, 0.0), red)] });
+        let settings = RenderSettings { probes: Some(probes), probe_roughness: 0.5, ..RenderSettings::default() };
+        let ambient = Ambient::flat(Color::new(0, 0, 0));
+        // A black mirror floor seen from above: all its color comes from the reflection
+        let reflection = |shininess: f32| {
+            let mirror = Material::new(Color::new(0, 0, 0), shininess, [0.0, 0.0, 1.0, 0.0], 0.0);
+            let objects = vec![Cube::new(Vec3::new(-2.0, -1.0, -2.0), Vec3::new(2.0, 0.0, 2.0), &mirror)];
+            let scene = Scene::new(objects, Vec::new(), Arc::new(Sky::Procedural), Camera::default_view(), ambient);
+            cast_ray(&Ray::new(Vec3::new(0.0, 2.0, 0.5), Vec3::new(0.0, -1.0, -0.2).normalize()), &scene, &settings, &PixelSample::default(), 0)
+        };
+        assert_eq!(reflection(5.0), Color::new(255, 0, 0), "a rough surface should read the probe");
+        assert_ne!(reflection(1000.0), Color::new(255, 0, 0), "a sharp mirror should still trace the sky");
+    }
+
+    #[test]
+    fn auto_probes_go_above_reflective_blocks_one_per_chunk() {
+        let matte = Material::new(Color::new(0, 0, 0), 10.0, [1.0, 0.0, 0.0, 0.0], 0.0);
+        let shiny = Material::new(Color::new(0, 0, 0), 10.0, [0.5, 0.0, 0.5, 0.0], 0.0);
+        let objects = [
+            Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.5, 0.5, 0.5), &shiny),
+            Cube::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.5, 1.0, 0.5), &shiny),
+            Cube::new(Vec3::new(20.0, 0.0, 0.0), Vec3::new(20.5, 0.5, 0.5), &matte),
+            Cube::new(Vec3::new(-3.0, 0.0, 0.0), Vec3::new(-2.5, 0.5, 0.5), &shiny),
+        ];
+        // Chunks are 8 units wide: the first two share one, the matte block gets none
+        assert_eq!(auto_positions(&objects), vec![Vec3::new(-4.0, 1.0, 4.0), Vec3::new(4.0, 1.5, 4.0)]);
+    }
+}
@@ -1,17 +1,18 @@
-use nalgebra_glm::Vec3;
-use crate::material::Material;
+use crate::math::{to_f32, Float, Vec3};
 
-#[derive(Debug, Clone)]
-pub struct Intersect {
-    pub is_intersecting: bool,
+// Impacto de un rayo. No lleva el material: se busca con `object` en los objetos de la escena.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
     pub point: Vec3,
     pub normal: Vec3,
-    pub distance: f32,
-    pub material: Material,
+    pub t: Float, // Distancia a lo largo del rayo; negativa si el origen está dentro del objeto
     pub face: CubeFace,
+    pub uv: (f32, f32),
+    pub texture_face: CubeFace, // Cara del bloque sin girar que se ve aquí; la misma que `face` salvo en cajas orientadas
+    pub object: usize, // Índice en la escena; una forma suelta devuelve 0 y la escena lo rellena
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CubeFace {
     Top,
     Bottom,
@@ -21,52 +22,129 @@ pub enum CubeFace {
     Back,
 }
 
-impl Intersect {
-    pub fn new() -> Self {
-        Self {
-            is_intersecting: false,
-            point: Vec3::new(0.0, 0.0, 0.0),
-            normal: Vec3::new(0.0, 0.0, 0.0),
-            distance: f32::MAX,
-            material: Material::black(),
-            face: CubeFace::Top,  // Default to Top, or whichever face makes sense as a default
+impl CubeFace {
+    // En el orden en que se declaran, que es el de los índices `face as usize`
+    pub const ALL: [CubeFace; 6] = [CubeFace::Top, CubeFace::Bottom, CubeFace::Left, CubeFace::Right, CubeFace::Front, CubeFace::Back];
+
+    // Cara de una caja con la normal más parecida, para las superficies que no son cajas
+    pub fn from_normal(normal: &Vec3) -> Self {
+        let axis = normal.iamax();
+        match (axis, normal[axis] >= 0.0) {
+            (0, true) => CubeFace::Right,
+            (0, false) => CubeFace::Left,
+            (1, true) => CubeFace::Top,
+            (1, false) => CubeFace::Bottom,
+            (_, true) => CubeFace::Front,
+            (_, false) => CubeFace::Back,
         }
     }
 
-    pub fn empty() -> Self {
-        Self {
-            is_intersecting: false,
-            point: Vec3::new(0.0, 0.0, 0.0),
-            normal: Vec3::new(0.0, 0.0, 0.0),
-            distance: f32::MAX,
-            material: Material::black(),
-            face: CubeFace::Top,  // or any default face you prefer
+    pub fn normal(&self) -> Vec3 {
+        match self {
+            CubeFace::Top => Vec3::new(0.0, 1.0, 0.0),
+            CubeFace::Bottom => Vec3::new(0.0, -1.0, 0.0),
+            CubeFace::Left => Vec3::new(-1.0, 0.0, 0.0),
+            CubeFace::Right => Vec3::new(1.0, 0.0, 0.0),
+            CubeFace::Front => Vec3::new(0.0, 0.0, 1.0),
+            CubeFace::Back => Vec3::new(0.0, 0.0, -1.0),
         }
     }
 
-    pub fn texture_coords(&self) -> (f32, f32) {
-        match self.face {
-            CubeFace::Top | CubeFace::Bottom => {
-                let u = self.point.x.fract().abs();
-                let v = self.point.z.fract().abs();
-                (u, v)
-            },
-            CubeFace::Left | CubeFace::Right => {
-                let u = self.point.z.fract().abs();
-                let v = self.point.y.fract().abs();
-                (u, v)
-            },
-            CubeFace::Front | CubeFace::Back => {
-                let u = self.point.x.fract().abs();
-                let v = self.point.y.fract().abs();
-                (u, v)
-            },
+    // Ejes del mundo de los que salen u y v en esta cara
+    pub fn uv_axes(&self) -> (usize, usize) {
+        match self {
+            CubeFace::Top | CubeFace::Bottom => (0, 2),
+            CubeFace::Left | CubeFace::Right => (2, 1),
+            CubeFace::Front | CubeFace::Back => (0, 1),
         }
     }
 }
 
+impl Hit {
+    pub fn new(point: Vec3, normal: Vec3, t: Float, face: CubeFace) -> Self {
+        Hit { point, normal, t, face, uv: texture_coords(&point, face), texture_face: face, object: 0 }
+    }
+}
+
+// Coordenadas de textura de un punto en una cara, tomadas de las coordenadas del mundo
+fn texture_coords(point: &Vec3, face: CubeFace) -> (f32, f32) {
+    let (u_axis, v_axis) = face.uv_axes();
+    let (u, v) = (point[u_axis].fract().abs(), point[v_axis].fract().abs());
+    // Las texturas trabajan en f32 aunque la geometría use f64
+    (to_f32(u), to_f32(v))
+}
+
+// Giro de la textura de una cara: `quarter_turns` cuartos de vuelta y después, si `mirrored`, un
+// reflejo de izquierda a derecha. Se aplica a las coordenadas de textura antes de leerla.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FaceOrientation {
+    pub quarter_turns: u8,
+    pub mirrored: bool,
+}
+
+impl FaceOrientation {
+    pub fn new(quarter_turns: u8, mirrored: bool) -> Self {
+        FaceOrientation { quarter_turns: quarter_turns % 4, mirrored }
+    }
+
+    // Las ocho orientaciones posibles, primero las que no reflejan
+    pub fn all() -> impl Iterator<Item = FaceOrientation> {
+        [false, true].into_iter().flat_map(|mirrored| (0..4).map(move |turns| FaceOrientation::new(turns, mirrored)))
+    }
+
+    pub fn apply(&self, (u, v): (f32, f32)) -> (f32, f32) {
+        let (u, v) = match self.quarter_turns % 4 {
+            1 => (v, 1.0 - u),
+            2 => (1.0 - u, 1.0 - v),
+            3 => (1.0 - v, u),
+            _ => (u, v),
+        };
+        if self.mirrored { (1.0 - u, v) } else { (u, v) }
+    }
+}
+
+// Rayo con el intervalo de distancias [t_min, t_max) en que se aceptan impactos. Una caja que el
+// rayo atraviesa dentro del intervalo cuenta aunque el origen esté dentro de ella; entonces la
+// distancia del impacto es negativa, como antes de que existiera el intervalo.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub dir: Vec3,
+    pub inv_dir: Vec3, // 1 / dir por componente, para no dividir en cada caja
+    pub t_min: Float,
+    pub t_max: Float,
+    // Canal de color (0 rojo, 1 verde, 2 azul) que sigue el rayo desde que un material dispersivo
+    // separó la luz; None lleva los tres
+    pub channel: Option<usize>,
+}
+
+impl Ray {
+    // Rayo sin límite de distancia hacia delante
+    pub fn new(origin: Vec3, dir: Vec3) -> Self {
+        Ray::bounded(origin, dir, 0.0, Float::INFINITY)
+    }
+
+    // Rayo que solo acepta impactos antes de `t_max`, como los de sombra hacia una luz
+    pub fn segment(origin: Vec3, dir: Vec3, t_max: Float) -> Self {
+        Ray::bounded(origin, dir, 0.0, t_max)
+    }
+
+    pub fn bounded(origin: Vec3, dir: Vec3, t_min: Float, t_max: Float) -> Self {
+        Ray { origin, dir, inv_dir: dir.map(|component| 1.0 / component), t_min, t_max, channel: None }
+    }
+
+    pub fn with_channel(mut self, channel: Option<usize>) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    pub fn at(&self, t: Float) -> Vec3 {
+        self.origin + self.dir * t
+    }
+}
+
 pub trait RayIntersect {
-  fn ray_intersect(&self, ray_origin: &Vec3, ray_direction: &Vec3) -> Intersect;
+  fn ray_intersect(&self, ray: &Ray) -> Option<Hit>;
 }
 
 
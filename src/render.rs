@@ -0,0 +1,2400 @@
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::framebuffer::Framebuffer;
+use crate::color::Color;
+use crate::ray_intersect::{Hit, RayIntersect, CubeFace, FaceOrientation, Ray};
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::light_grid::{LightGrid, MAX_LIGHT_LEVEL};
+use crate::math::{consts, to_f32, Float, Vec3};
+use crate::cube::Cube;
+use crate::primitive::Primitive;
+use crate::material::Material;
+use crate::rgb::Rgb;
+use crate::sampler::{halton, pixel_offset, Dimension, PixelSample};
+#[cfg(feature = "gpu")]
+use crate::settings::Backend;
+use crate::settings::{AlphaMode, EnvSampling, RenderMode, RenderSettings, Sampler, StereoMode, TextureFilter};
+use crate::tiles::{focused_samples, ordered_tiles, FOCUS_BOOST};
+use crate::flare::{draw_lens_flare, flare_sources};
+use crate::scene::Scene;
+use crate::sky::{AmbientSettings, EnvironmentMap, Sky};
+use crate::texture::{clamp_to_face, Texture};
+
+
+// Desplazamiento relativo de los rayos secundarios. El error de redondeo de un punto de impacto
+// crece con sus coordenadas y con la distancia recorrida, así que el desplazamiento escala con ambas;
+// al depender de la precisión del tipo, con f64 es mucho menor.
+const ORIGIN_BIAS: Float = 256.0 * Float::EPSILON;
+const SKYBOX_COLOR: Color = Color::new(68, 142, 228);
+
+
+fn bias_at(point: &Vec3, distance: Float) -> Float {
+    let magnitude = point.x.abs().max(point.y.abs()).max(point.z.abs());
+    ORIGIN_BIAS * magnitude.max(distance.abs()).max(1.0)
+}
+
+
+fn offset_origin(intersect: &Hit, direction: &Vec3) -> Vec3 {
+    let offset = intersect.normal * bias_at(&intersect.point, intersect.t);
+    if direction.dot(&intersect.normal) < 0.0 {
+        intersect.point - offset
+    } else {
+        intersect.point + offset
+    }
+}
+
+
+fn reflect(incident: &Vec3, normal: &Vec3) -> Vec3 {
+    incident - 2.0 * incident.dot(normal) * normal
+}
+
+
+fn refract(incident: &Vec3, normal: &Vec3, eta_t: Float) -> Vec3 {
+    let cosi = -incident.dot(normal).max(-1.0).min(1.0);
+   
+    let (n_cosi, eta, n_normal);
+
+
+    if cosi < 0.0 {
+        // Ray is entering the object
+        n_cosi = -cosi;
+        eta = 1.0 / eta_t;
+        n_normal = -normal;
+    } else {
+        // Ray is leaving the object
+        n_cosi = cosi;
+        eta = eta_t;
+        n_normal = *normal;
+    }
+   
+    let k = 1.0 - eta * eta * (1.0 - n_cosi * n_cosi);
+   
+    if k < 0.0 {
+        // Total internal reflection
+        reflect(incident, &n_normal)
+    } else {
+        eta * incident + (eta * n_cosi - k.sqrt()) * n_normal
+    }
+}
+
+
+// Refracción en un material dispersivo: cada canal se refracta con su propio índice, el rojo con el
+// más bajo y el azul con el más alto, separados `dispersion` en total, y se sigue a través del objeto
+// hasta la cara por la que sale, donde se refracta otra vez, como en un prisma. Un rayo que ya sigue
+// un canal se queda con él; si no, se lanzan los tres y se toma un canal de cada uno.
+fn dispersed_refraction(ray: &Ray, intersect: &Hit, object: &Primitive, scene: &Scene, settings: &RenderSettings, pixel: &PixelSample, depth: u32) -> Color {
+    let material = object.material();
+    let trace = |channel: usize| {
+        let index = (material.refractive_index + (channel as f32 - 1.0) * 0.5 * material.dispersion) as Float;
+        transmitted(&Ray { channel: Some(channel), ..*ray }, intersect, index, scene, settings, pixel, depth)
+    };
+    match ray.channel {
+        Some(channel) => trace(channel),
+        None => {
+            let (red, green, blue) = (trace(0), trace(1), trace(2));
+            Color::new(red.r, green.g, blue.b)
+        }
+    }
+}
+
+// Color que llega a través del objeto de `intersect` con índice de refracción `index`: el rayo se
+// refracta al entrar, cruza el objeto hasta su cara de salida y se refracta otra vez allí. Seguir
+// el rayo desde dentro volvería a dar con la cara de entrada, que está detrás del origen.
+fn transmitted(ray: &Ray, intersect: &Hit, index: Float, scene: &Scene, settings: &RenderSettings, pixel: &PixelSample, depth: u32) -> Color {
+    let object = &scene.objects()[intersect.object];
+    let inside = refract(&ray.dir, &intersect.normal, index);
+    let mut origin = offset_origin(intersect, &inside);
+    let mut direction = inside;
+    if let Some(exit) = exit_hit(object, &origin, &inside) {
+        direction = refract(&inside, &exit.normal, index);
+        origin = offset_origin(&exit, &direction);
+    }
+    cast_ray(&Ray::new(origin, direction).with_channel(ray.channel), scene, settings, pixel, depth + 1)
+}
+
+// Cara por la que sale de `object` un rayo que empieza dentro, con la normal hacia fuera. Los
+// planos no tienen interior.
+fn exit_hit(object: &Primitive, origin: &Vec3, direction: &Vec3) -> Option<Hit> {
+    let (t, normal) = match object {
+        Primitive::Cube(cube) => {
+            let t = cube.exit_distance(origin, direction);
+            let point = origin + direction * t;
+            // La cara de salida es la del eje cuyo límite queda más cerca del punto
+            let axis = (0..3)
+                .filter(|&axis| direction[axis] != 0.0)
+                .min_by(|&a, &b| {
+                    let gap = |axis: usize| (point[axis] - if direction[axis] > 0.0 { cube.max[axis] } else { cube.min[axis] }).abs();
+                    gap(a).total_cmp(&gap(b))
+                })?;
+            let mut normal = Vec3::zeros();
+            normal[axis] = direction[axis].signum();
+            (t, normal)
+        }
+        Primitive::Sphere(sphere) => {
+            let to_origin = origin - sphere.center;
+            let (a, half_b) = (direction.dot(direction), to_origin.dot(direction));
+            let c = to_origin.dot(&to_origin) - sphere.radius * sphere.radius;
+            let t = (-half_b + (half_b * half_b - a * c).max(0.0).sqrt()) / a;
+            (t, (origin + direction * t - sphere.center) / sphere.radius)
+        }
+        Primitive::Plane(_) => return None,
+    };
+    Some(Hit::new(origin + direction * t, normal, t, CubeFace::from_normal(&normal)))
+}
+
+
+// Gira un vector alrededor del eje Y
+fn rotate_y(v: &Vec3, angle: Float) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(v.x * cos + v.z * sin, v.y, -v.x * sin + v.z * cos)
+}
+
+
+// Cuánto tiñe el portal lo que se ve a través
+const PORTAL_TINT: f32 = 0.3;
+
+
+// Nieve de las caras superiores (ver `Material::snow_cover`)
+const SNOW_COLOR: Color = Color::new(240, 244, 250);
+const SNOW_SPECULAR: f32 = 0.05;
+
+
+// Superficies mojadas: se oscurecen, el brillo se vuelve más fino y reflejan como el agua (Schlick)
+const WET_DARKENING: f32 = 0.3;
+const WET_SPECULAR: f32 = 0.5;
+const WET_SHININESS: f32 = 400.0;
+const WATER_REFLECTANCE: f32 = 0.02; // A incidencia normal
+
+
+// Radio del disco sobre el que se reparten las muestras de sombras suaves
+const SOFT_SHADOW_RADIUS: Float = 0.25;
+// Distancia máxima a la que un objeto ocluye la luz ambiental
+const AO_DISTANCE: Float = 1.0;
+const GOLDEN_ANGLE: Float = 2.399_963;
+
+
+// Base ortonormal (tangente, bitangente) alrededor de un vector unitario
+fn orthonormal_basis(n: &Vec3) -> (Vec3, Vec3) {
+    let helper = if n.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+    let tangent = n.cross(&helper).normalize();
+    let bitangent = n.cross(&tangent);
+    (tangent, bitangent)
+}
+
+
+// Punto `i` de `samples` en el disco unidad, como (radio, ángulo), para sombras suaves y oclusión.
+// Regular usa la misma espiral de Fibonacci en todos los puntos; stratified y blue_noise la giran y
+// desplazan por punto, lo que cambia las bandas que deja un número bajo de muestras por ruido fino;
+// halton toma su propio par de dimensiones de la secuencia, rotado por píxel y continuado entre sus muestras.
+fn disk_sample(sampler: Sampler, dimension: Dimension, point: &Vec3, pixel: &PixelSample, i: u32, samples: u32) -> (Float, Float) {
+    let (u, v) = match sampler {
+        Sampler::Regular => return (((i as Float + 0.5) / samples as Float).sqrt(), i as Float * GOLDEN_ANGLE),
+        Sampler::Stratified | Sampler::BlueNoise => {
+            let (shift_r, shift_angle) = point_hash(point);
+            let angle = i as Float * GOLDEN_ANGLE + shift_angle as Float * 2.0 * consts::PI;
+            return (((i as Float + shift_r as Float) / samples as Float).sqrt(), angle);
+        }
+        Sampler::Halton => halton(dimension, pixel.index * samples + i, pixel.rotation(dimension)),
+    };
+    (u.sqrt(), v * 2.0 * consts::PI)
+}
+
+
+fn cast_shadow(intersect: &Hit, light: &Light, scene: &Scene, settings: &RenderSettings, pixel: &PixelSample) -> f32 {
+    let objects = scene.objects();
+    let samples = settings.shadow_samples.max(1);
+    if samples == 1 {
+        return cast_shadow_towards(intersect, light, &light.position, objects);
+    }
+
+    // Sombras suaves: repartimos las muestras en un disco (espiral de Fibonacci) alrededor de la luz
+    let (tangent, bitangent) = orthonormal_basis(&(light.position - intersect.point).normalize());
+    let total: f32 = (0..samples)
+        .map(|i| {
+            let (r, angle) = disk_sample(settings.sampler, Dimension::Shadow, &intersect.point, pixel, i, samples);
+            let r = SOFT_SHADOW_RADIUS * r;
+            let position = light.position + tangent * (r * angle.cos()) + bitangent * (r * angle.sin());
+            cast_shadow_towards(intersect, light, &position, objects)
+        })
+        .sum();
+    total / samples as f32
+}
+
+
+fn cast_shadow_towards(intersect: &Hit, light: &Light, light_position: &Vec3, objects: &[Primitive]) -> f32 {
+    let light_dir = light_position - intersect.point;
+    let distance_to_light = light_dir.magnitude();
+    
+    if distance_to_light > light.radius {
+        return 1.0;
+    }
+
+    let light_dir = light_dir.normalize();
+    let shadow_ray_origin = offset_origin(intersect, &light_dir);
+
+    let shadow_ray = Ray::segment(shadow_ray_origin, light_dir, distance_to_light);
+    if objects.iter().any(|object| object.ray_intersect(&shadow_ray).is_some()) {
+        return 0.95; // Permitimos que algo de luz pase a través de los objetos
+    }
+
+    0.0
+}
+
+
+fn is_occluded(origin: &Vec3, direction: &Vec3, objects: &[Primitive], max_distance: Float) -> bool {
+    let ray = Ray::segment(*origin, *direction, max_distance);
+    objects.iter().any(|object| object.ray_intersect(&ray).is_some_and(|hit| hit.t > 0.0))
+}
+
+
+// Textura del material que lleva cada cara, para materiales con alguna textura
+pub fn texture_slot(material: &Material, face: &CubeFace) -> usize {
+    match &material.color {
+        color if *color == Color::new(0, 255, 0) => {
+            // Esto es césped
+            match face {
+                CubeFace::Top => 0, // Textura de césped para la cara superior
+                _ => 1, // Textura de tierra para las otras caras
+            }
+        },
+        color if *color == Color::new(128, 128, 128) => {
+            // Esto es piedra, usa la textura de piedra para todas las caras
+            0 // Asumiendo que la textura de piedra es la primera (y única) en el vector de texturas
+        },
+        _ => 0, // Para otros materiales, usa la primera textura
+    }
+}
+
+// Dos números en [0, 1) propios del bloque de 0.5 que contiene el punto de impacto, iguales en cada
+// frame y cada vez que se carga la escena. El punto se mete un poco en el bloque para que no dependa
+// de la cara; así un cubo grande que cubre varios bloques también varía de uno a otro.
+fn block_hash(intersect: &Hit) -> (f32, f32) {
+    let inside = (intersect.point - intersect.normal * (TEXEL_SIZE * 0.5)) * 2.0;
+    point_hash(&Vec3::new(inside.x.floor(), inside.y.floor(), inside.z.floor()))
+}
+
+// Coordenadas de textura giradas `variant % 4` cuartos de vuelta y reflejadas si `variant >= 4`
+fn vary_uv(u: f32, v: f32, variant: u32) -> (f32, f32) {
+    FaceOrientation::new((variant % 4) as u8, variant >= 4).apply((u, v))
+}
+
+// Brillo de ±`jitter` y un tono algo más cálido o más frío, según el bloque
+fn jitter_tint(color: Color, (a, b): (f32, f32), jitter: f32) -> Color {
+    let value = 1.0 + jitter * ((a * 8.0).fract() * 2.0 - 1.0);
+    let warmth = 0.5 * jitter * (b * 2.0 - 1.0);
+    let channel = |c: u8, factor: f32| (c as f32 * value * factor).clamp(0.0, 255.0) as u8;
+    Color::new(channel(color.r, 1.0 + warmth), channel(color.g, 1.0), channel(color.b, 1.0 - warmth))
+}
+
+// Si nada por encima del punto le tapa el cielo: bajo los aleros y las hojas no nieva ni llueve
+fn is_open_to_sky(intersect: &Hit, objects: &[Primitive]) -> bool {
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    !is_occluded(&offset_origin(intersect, &up), &up, objects, Float::INFINITY)
+}
+
+// Si el punto está en una cara superior con nieve: el texel (16 por bloque) entra en la fracción
+// cubierta y la cara queda a cielo abierto
+fn is_snow_covered(intersect: &Hit, material: &Material, objects: &[Primitive]) -> bool {
+    let cover = material.snow_cover;
+    if cover <= 0.0 || !matches!(intersect.face, CubeFace::Top) {
+        return false;
+    }
+    let texel = Vec3::new((intersect.point.x / TEXEL_SIZE).floor(), 0.0, (intersect.point.z / TEXEL_SIZE).floor());
+    point_hash(&texel).0 < cover && is_open_to_sky(intersect, objects)
+}
+
+// Cuánto está mojado el punto por la lluvia en el instante `time` (0 seco)
+fn wetness(intersect: &Hit, material: &Material, objects: &[Primitive], time: f32) -> f32 {
+    let Some(rain) = material.rain else {
+        return 0.0;
+    };
+    let wetness = rain.wetness_at(time);
+    if wetness <= 0.0 || !matches!(intersect.face, CubeFace::Top) || !is_open_to_sky(intersect, objects) {
+        return 0.0;
+    }
+    wetness
+}
+
+
+// Fracción de luz ambiental que llega al punto (1 = sin oclusión)
+fn ambient_occlusion(intersect: &Hit, objects: &[Primitive], settings: &RenderSettings, pixel: &PixelSample) -> f32 {
+    let samples = settings.ao_samples;
+    if samples == 0 {
+        return 1.0;
+    }
+
+    // Direcciones con distribución coseno sobre el hemisferio de la normal
+    let (tangent, bitangent) = orthonormal_basis(&intersect.normal);
+    let origin = offset_origin(intersect, &intersect.normal);
+    let occluded = (0..samples)
+        .filter(|&i| {
+            let (r, angle) = disk_sample(settings.sampler, Dimension::Occlusion, &intersect.point, pixel, i, samples);
+            let direction = (tangent * (r * angle.cos()) + bitangent * (r * angle.sin()) + intersect.normal * (1.0 - r * r).sqrt()).normalize();
+            is_occluded(&origin, &direction, objects, AO_DISTANCE)
+        })
+        .count();
+    1.0 - occluded as f32 / samples as f32
+}
+
+
+// Rotación aleatoria pero fija por punto, para que píxeles vecinos no repitan las mismas direcciones
+// Exponente de Blinn-Phong equivalente a una rugosidad en [0, 1] (alpha = rugosidad²);
+// se limita para que una superficie pulida no produzca un brillo infinitamente pequeño
+fn roughness_to_shininess(roughness: f32) -> f32 {
+    let alpha = (roughness * roughness).max(0.01);
+    (2.0 / (alpha * alpha) - 2.0).clamp(1.0, 10000.0)
+}
+
+// Inversa de `roughness_to_shininess`, para llevar un material Phong a PBR
+pub fn shininess_to_roughness(shininess: f32) -> f32 {
+    (2.0 / (shininess.max(0.0) + 2.0)).sqrt().sqrt().clamp(0.0, 1.0)
+}
+
+fn point_hash(point: &Vec3) -> (f32, f32) {
+    let mut h = to_f32(point.x).to_bits().wrapping_mul(0x8da6_b343)
+        ^ to_f32(point.y).to_bits().wrapping_mul(0xd816_3841)
+        ^ to_f32(point.z).to_bits().wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    ((h & 0xffff) as f32 / 65536.0, (h >> 16) as f32 / 65536.0)
+}
+
+
+// Irradiancia del mapa de entorno que llega al punto, dividida entre π (radiancia lineal equivalente).
+// Ambas estrategias estiman la misma integral; el muestreo por importancia converge con menos muestras
+// cuando la luz se concentra en una zona pequeña del mapa, como el sol.
+fn environment_irradiance(intersect: &Hit, objects: &[Primitive], map: &EnvironmentMap, samples: u32, sampling: EnvSampling) -> [f32; 3] {
+    let normal = intersect.normal;
+    let (tangent, bitangent) = orthonormal_basis(&normal);
+    let origin = offset_origin(intersect, &normal);
+    let (shift_u, shift_v) = point_hash(&intersect.point);
+
+    let mut sum = [0.0f32; 3];
+    for i in 0..samples {
+        // Retícula de Fibonacci desplazada por punto
+        let u1 = ((i as f32 + 0.5) / samples as f32 + shift_u).fract();
+        let u2 = (i as f32 * 0.618_034 + shift_v).fract();
+
+        let (direction, weight) = match sampling {
+            EnvSampling::Cosine => {
+                // pdf = cos/π, así que L·cos/(π·pdf) = L
+                let (u1, u2) = (u1 as Float, u2 as Float);
+                let r = u1.sqrt();
+                let angle = 2.0 * consts::PI * u2;
+                let direction = tangent * (r * angle.cos()) + bitangent * (r * angle.sin()) + normal * (1.0 - u1).max(0.0).sqrt();
+                (direction, 1.0)
+            }
+            EnvSampling::Importance => {
+                let (direction, pdf) = map.sample_direction(u1, u2);
+                let cos_theta = direction.dot(&normal);
+                if pdf <= 0.0 || cos_theta <= 0.0 {
+                    continue;
+                }
+                (direction, cos_theta / (consts::PI * pdf))
+            }
+        };
+
+        if is_occluded(&origin, &direction, objects, Float::INFINITY) {
+            continue;
+        }
+        let radiance = map.sample(&direction);
+        for channel in 0..3 {
+            sum[channel] += radiance[channel] * to_f32(weight);
+        }
+    }
+    sum.map(|value| value / samples as f32)
+}
+
+
+// Parallax mapping: desplaza las coordenadas de la cara a lo largo del rayo hasta la profundidad
+// que indica el mapa de altura. `depth_at` devuelve esa profundidad en [0, 1] (0 = superficie) y
+// `depth` la escala a fracción del lado de la cara. Con un paso se usa el desplazamiento simple
+// (limitado, sin dividir por el coseno, para que no se dispare en ángulos rasantes); con más se
+// baja por capas hasta quedar por debajo del relieve (steep parallax). El resultado se limita a la
+// cara para no leer la región vecina de un atlas.
+fn parallax_uv(intersect: &Hit, ray_direction: &Vec3, (u, v): (f32, f32), depth: f32, steps: u32, depth_at: impl Fn(f32, f32) -> f32) -> (f32, f32) {
+    // Ejes del mundo a lo largo de los que crecen u y v; fract().abs() los invierte en coordenadas negativas
+    let point = &intersect.point;
+    let axis = |component: Float, direction: Float| if component < 0.0 { -direction } else { direction };
+    let (along_u, along_v) = match intersect.face {
+        CubeFace::Top | CubeFace::Bottom => (axis(point.x, ray_direction.x), axis(point.z, ray_direction.z)),
+        CubeFace::Left | CubeFace::Right => (axis(point.z, ray_direction.z), axis(point.y, ray_direction.y)),
+        CubeFace::Front | CubeFace::Back => (axis(point.x, ray_direction.x), axis(point.y, ray_direction.y)),
+    };
+    let (along_u, along_v) = (to_f32(along_u), to_f32(along_v));
+    let clamp = |(u, v): (f32, f32)| (clamp_to_face(u), clamp_to_face(v));
+
+    if steps <= 1 {
+        let offset = depth * depth_at(u, v);
+        return clamp((u + along_u * offset, v + along_v * offset));
+    }
+
+    // Cada capa baja depth / steps; el rayo avanza lo que le cuesta bajar esa altura
+    let cos_theta = to_f32(-ray_direction.dot(&intersect.normal)).max(0.05);
+    let layer = 1.0 / steps as f32;
+    let (step_u, step_v) = (along_u / cos_theta * depth * layer, along_v / cos_theta * depth * layer);
+    let mut current = (u, v);
+    let mut layer_depth = 0.0;
+    for _ in 0..steps {
+        if layer_depth >= depth_at(current.0, current.1) {
+            break;
+        }
+        current = clamp((current.0 + step_u, current.1 + step_v));
+        layer_depth += layer;
+    }
+    current
+}
+
+
+// Color de un rayo separado en lo que aporta la superficie que toca (luz directa, ambiente y emisión)
+// y lo que llega por sus reflejos y refracciones, los dos ya ponderados, para poder calcular la parte
+// secundaria a menos resolución. La distancia y la normal del impacto guían esa reconstrucción.
+#[derive(Clone, Copy, Debug)]
+struct Shading {
+    direct: Color,
+    secondary: Color,
+    secondary_weight: f32, // Reflectividad más transparencia; 0 si la superficie no lanza rayos secundarios
+    distance: Float,       // Infinita si el rayo no toca nada
+    normal: Vec3,
+}
+
+impl Shading {
+    fn direct(color: Color) -> Shading {
+        Shading { direct: color, secondary: Color::black(), secondary_weight: 0.0, distance: Float::INFINITY, normal: Vec3::zeros() }
+    }
+}
+
+pub fn cast_ray(ray: &Ray, scene: &Scene, settings: &RenderSettings, pixel: &PixelSample, depth: u32) -> Color {
+    let shading = shade_ray(ray, scene, settings, pixel, depth, true);
+    shading.direct + shading.secondary
+}
+
+// Como cast_ray, pero con las dos partes por separado. Con `secondary` a false no se lanzan los rayos
+// de reflexión y refracción y esa parte queda en negro, aunque su peso sí se calcula.
+fn shade_ray(ray: &Ray, scene: &Scene, settings: &RenderSettings, pixel: &PixelSample, depth: u32, secondary: bool) -> Shading {
+    if depth > settings.max_depth {
+        return Shading::direct(SKYBOX_COLOR);
+    }
+    let objects = scene.objects();
+    let (ray_origin, ray_direction) = (&ray.origin, &ray.dir);
+
+
+    // Cada impacto acorta el rayo, así que los objetos siguientes solo cuentan si están más cerca
+    let closest = objects.iter().enumerate().fold(None, |closest: Option<Hit>, (index, object)| {
+        let ray = Ray { t_max: closest.as_ref().map_or(ray.t_max, |hit| hit.t), ..*ray };
+        object.ray_intersect(&ray).map(|hit| Hit { object: index, ..hit }).or(closest)
+    });
+    let Some(intersect) = closest else {
+        return Shading::direct(scene.sky.color(ray_direction, &scene.lights, &scene.ambient));
+    };
+    let (hit_index, hit_object) = (intersect.object, &objects[intersect.object]);
+    let material = hit_object.material();
+
+
+    // Los portales no se sombrean: el rayo atraviesa el bloque y sigue desde la pareja.
+    // Cada salto cuenta como un rebote, así que dos portales enfrentados terminan en max_depth.
+    if let (Some(portal), Some(block)) = (material.portal, hit_object.as_cube()) {
+        let center = (block.min + block.max) * 0.5;
+        let exit_point = intersect.point + ray_direction * block.exit_distance(&intersect.point, ray_direction);
+        let origin = center + portal.target_offset + rotate_y(&(exit_point - center), portal.rotation);
+        let direction = rotate_y(ray_direction, portal.rotation);
+        let through = cast_ray(&Ray::new(origin + direction * bias_at(&origin, 0.0), direction).with_channel(ray.channel), scene, settings, pixel, depth + 1);
+
+        // Brillo ondulante que se desplaza por la superficie con el tiempo
+        let (u, v) = intersect.uv;
+        let shimmer = 0.5 + 0.5 * ((u * 4.0 + v * 6.0) * std::f32::consts::TAU + settings.time * 2.0).sin();
+        let tint = material.color.mul_scalar(PORTAL_TINT * (0.7 + 0.3 * shimmer));
+        return Shading { distance: intersect.t, normal: intersect.normal, ..Shading::direct(through.mul_scalar(1.0 - PORTAL_TINT) + tint) };
+    }
+
+
+    let (u, v) = intersect.uv;
+    // Escala u y v para que coincidan con el tamaño de unidad de 0.5
+    let scaled_u = (u * 2.0) % 1.0;
+    let scaled_v = (v * 2.0) % 1.0;
+    // Giro o reflejo de la cara superior elegido por bloque, con los ocho valores de `block_hash`
+    let variant = if material.randomize_rotation && matches!(intersect.face, CubeFace::Top) {
+        (block_hash(&intersect).0 * 8.0) as u32
+    } else {
+        0
+    };
+    let sample_at = |texture: &Texture, u: f32, v: f32| {
+        let (u, v) = vary_uv(u, v, variant);
+        match settings.texture_filter {
+            TextureFilter::Nearest => texture.sample(u, v),
+            TextureFilter::Bilinear => texture.sample_bilinear(u, v),
+        }
+    };
+    // Con mapa de altura, todos los mapas se leen donde el rayo habría tocado el relieve
+    let (scaled_u, scaled_v) = match &material.height_map {
+        Some(map) if material.parallax_depth > 0.0 => {
+            let depth_at = |u: f32, v: f32| 1.0 - sample_at(map, u, v).r as f32 / 255.0;
+            parallax_uv(&intersect, ray_direction, (scaled_u, scaled_v), material.parallax_depth, settings.parallax_steps, depth_at)
+        }
+        _ => (scaled_u, scaled_v),
+    };
+    let sample = |texture: &Texture| sample_at(texture, scaled_u, scaled_v);
+
+    let material_color = if !material.textures.is_empty() {
+        sample(&material.textures[texture_slot(material, &intersect.texture_face)])
+    } else {
+        material.color
+    };
+
+    let material_color = if material.tint_jitter > 0.0 {
+        jitter_tint(material_color, block_hash(&intersect), material.tint_jitter)
+    } else {
+        material_color
+    };
+
+    // La nieve tapa la textura y el brillo del bloque
+    let snowy = is_snow_covered(&intersect, material, objects);
+    let material_color = if snowy { SNOW_COLOR } else { material_color };
+    // La lluvia oscurece el color y añade una película de agua que brilla y refleja
+    let wet = if snowy { 0.0 } else { wetness(&intersect, material, objects, settings.time) };
+    let material_color = if wet > 0.0 { material_color.mul_scalar(1.0 - WET_DARKENING * wet) } else { material_color };
+
+    // Sin mapas se usan los valores del material tal cual
+    let specular_weight = match &material.specular_map {
+        _ if snowy => SNOW_SPECULAR,
+        Some(map) => material.properties[1] * sample(map).r as f32 / 255.0,
+        None => material.properties[1],
+    };
+    let shininess = match &material.roughness_map {
+        Some(map) => roughness_to_shininess(sample(map).r as f32 / 255.0),
+        None => material.shininess,
+    };
+    let (specular_weight, shininess) = if wet > 0.0 {
+        (specular_weight + WET_SPECULAR * wet, shininess + (WET_SHININESS - shininess).max(0.0) * wet)
+    } else {
+        (specular_weight, shininess)
+    };
+
+
+    let is_glowstone = material.emission != Color::new(0, 0, 0);
+
+    // La luz de cada luz se suma en carriles f32 y se pasa a Color una vez, al final del bucle
+    let mut lit = Rgb::black();
+    let material_rgb = Rgb::from(material_color);
+    let toon = settings.render_mode == RenderMode::Toon;
+    let fast = settings.render_mode == RenderMode::Fast;
+
+    for light in scene.lights.iter().filter(|light| light.enabled && light.link.affects(hit_index)) {
+        let light_dir = light.position - intersect.point;
+        let distance_to_light = light_dir.magnitude();
+        // Fuera del radio (o en su borde, con las caídas que llegan a 0) no hace falta el rayo de sombra
+        let attenuation = light.falloff.attenuation(distance_to_light, light.radius);
+
+        if attenuation > 0.0 {
+            let light_dir = light_dir.normalize();
+            let shadow_intensity = if light.cast_shadows && !fast { cast_shadow(&intersect, light, scene, settings, pixel) } else { 0.0 };
+            if shadow_intensity < 1.0 {
+                let light_intensity = (1.0 - shadow_intensity) * light.intensity * attenuation;
+
+                let diffuse_intensity = to_f32(intersect.normal.dot(&light_dir).max(0.0));
+                // En modo toon la luz difusa (con sombras y atenuación) cae en bandas lisas
+                let diffuse_amount = if toon {
+                    material.properties[0] * toon_band(diffuse_intensity * light_intensity, settings.toon_bands)
+                } else {
+                    material.properties[0] * diffuse_intensity * light_intensity
+                };
+                let light_rgb = Rgb::from(light.color);
+                let diffuse = material_rgb.mul(&light_rgb).mul_scalar(diffuse_amount);
+
+                let view_dir = (ray_origin - intersect.point).normalize();
+                let halfway = (light_dir + view_dir).normalize();
+                let mut specular_intensity = to_f32(halfway.dot(&intersect.normal).max(0.0)).powf(shininess);
+                if toon {
+                    // Brillo de borde duro: todo o nada
+                    specular_intensity = if specular_intensity > 0.5 { 1.0 } else { 0.0 };
+                }
+                let specular = light_rgb.mul_scalar(specular_weight * specular_intensity * light_intensity);
+
+                if is_glowstone {
+                    // Para el glowstone, usamos más el color de la textura y menos la iluminación calculada
+                    lit = lit + material_rgb.mul_scalar(0.7) + (diffuse + specular).mul_scalar(0.3);
+                } else {
+                    lit = lit + diffuse + specular;
+                }
+            }
+        }
+    }
+    let mut final_color = lit.to_color();
+
+    // Celda de aire delante de la cara, de la que se leen los niveles de luz
+    let light_cell = LightGrid::cell_at(&(intersect.point + intersect.normal * 0.25));
+    if fast {
+        let level = scene.light_grid().level(light_cell);
+        if level > 0 {
+            let amount = material.properties[0] * light_brightness(level);
+            final_color = final_color + material_color.mul(&BLOCK_LIGHT_COLOR).mul_scalar(amount);
+        }
+    }
+
+    let corner_light = if settings.smooth_lighting { smooth_lighting(&intersect, hit_object, objects) } else { 1.0 };
+    if corner_light < 1.0 {
+        final_color = final_color.mul_scalar(corner_light);
+    }
+
+    // Añadimos la emisión de luz del material
+    if is_glowstone {
+        // Para el glowstone, mezclamos la emisión con el color de la textura, tanto más cuanto más fuerte
+        final_color = final_color.mul_scalar(0.6) + material_color.mul_scalar(0.4 * material.emission_strength);
+    } else {
+        final_color = final_color + material.emission.mul_scalar(material.emission_strength);
+    }
+    // Los texeles brillantes del mapa emisivo se suman encima, sin depender de las luces
+    if let Some(map) = &material.emissive_map {
+        final_color = final_color + sample(map).mul_scalar(material.emission_strength);
+    }
+
+    // Añade iluminación ambiental; con un mapa de entorno se reúne su luz en lugar de usar el color promedio
+    let ambient_light = match &*scene.sky {
+        Sky::Hdri(map) if settings.ao_samples > 0 => {
+            let irradiance = environment_irradiance(&intersect, objects, map, settings.ao_samples, settings.env_sampling);
+            material_color.mul(&Color::from_linear(irradiance)).mul_scalar(settings.ambient_strength)
+        }
+        _ => {
+            let occlusion = ambient_occlusion(&intersect, objects, settings, pixel);
+            material_color.mul(&scene.ambient.at(&intersect.normal)).mul_scalar(settings.ambient_strength * occlusion)
+        }
+    };
+    // En cuevas e interiores llega menos luz del cielo; el sol directo sigue dependiendo de las sombras
+    let sky_light = if settings.skylight {
+        corner_light * light_brightness(scene.light_grid().sky_level(light_cell))
+    } else {
+        corner_light
+    };
+    final_color = final_color + if sky_light < 1.0 { ambient_light.mul_scalar(sky_light) } else { ambient_light };
+
+    // Color reflejado
+    let mut reflect_color = Color::black();
+    // La nieve es opaca y mate aunque el bloque de debajo sea vidrio o refleje
+    let reflectivity = if snowy {
+        0.0
+    } else if wet > 0.0 {
+        let cos_theta = to_f32(-ray_direction.normalize().dot(&intersect.normal)).clamp(0.0, 1.0);
+        let fresnel = WATER_REFLECTANCE + (1.0 - WATER_REFLECTANCE) * (1.0 - cos_theta).powi(5);
+        (material.properties[2] + wet * fresnel).min(1.0 - material.properties[3])
+    } else {
+        material.properties[2]
+    };
+    if secondary && reflectivity > 0.0 {
+        let reflect_dir = reflect(&ray_direction, &intersect.normal).normalize();
+        // Los reflejos poco importantes (superficies rugosas o a partir del segundo rebote) leen la
+        // sonda más cercana; los espejos nítidos vistos directamente se siguen trazando
+        let probe = settings.probes.as_ref()
+            .filter(|_| depth > 0 || shininess_to_roughness(shininess) > settings.probe_roughness)
+            .and_then(|probes| probes.nearest(&intersect.point));
+        reflect_color = match probe {
+            Some(probe) => probe.sample(&reflect_dir),
+            None => {
+                let reflect_origin = offset_origin(&intersect, &reflect_dir);
+                cast_ray(&Ray::new(reflect_origin, reflect_dir).with_channel(ray.channel), scene, settings, pixel, depth + 1)
+            }
+        };
+    }
+
+
+    // Color refractado
+    let mut refract_color = Color::black();
+    let transparency = if snowy { 0.0 } else { material.properties[3] };
+    if secondary && transparency > 0.0 {
+        refract_color = if material.dispersion > 0.0 {
+            dispersed_refraction(ray, &intersect, hit_object, scene, settings, pixel, depth)
+        } else {
+            transmitted(ray, &intersect, material.refractive_index as Float, scene, settings, pixel, depth)
+        };
+        // El vidrio tintado colorea todo lo que se ve a través, sin importar su grosor
+        refract_color = refract_color.mul(&material.transmission_tint);
+    }
+
+
+    // Combinación de los colores difuso, especular, reflejado, refractado y emitido
+    let shading = Shading {
+        direct: final_color * (1.0 - reflectivity - transparency),
+        secondary: (reflect_color * reflectivity) + (refract_color * transparency),
+        secondary_weight: reflectivity + transparency,
+        distance: intersect.t,
+        normal: intersect.normal,
+    };
+    match scene.fog.or(settings.fog()).map(|fog| fog.amount(intersect.t)) {
+        Some(fog) if fog > 0.0 => {
+            let sky = scene.sky.color(ray_direction, &scene.lights, &scene.ambient);
+            Shading {
+                direct: Color::lerp(&shading.direct, &sky, fog),
+                secondary: shading.secondary.mul_scalar(1.0 - fog),
+                secondary_weight: shading.secondary_weight * (1.0 - fog),
+                ..shading
+            }
+        }
+        _ => shading,
+    }
+}
+
+
+
+
+// Ruido de distribución triangular (-0.5 a 1.5) que se suma antes de truncar a 8 bits para romper
+// las bandas de los degradados. Depende solo del píxel, así que no parpadea entre frames.
+fn dither_offset(x: u32, y: u32) -> f32 {
+    let (a, b) = point_hash(&Vec3::new(x as Float, y as Float, 1.0));
+    a + b - 0.5
+}
+
+fn quantize(value: f32, offset: f32) -> u8 {
+    (value + offset).clamp(0.0, 255.0) as u8
+}
+
+// Ganancias por canal del balance de blancos: dividen por el color de una luz de `kelvin` grados
+// (normalizado al verde, como hacen las cámaras) para que esa luz se vea blanca. 0 no cambia nada.
+pub fn white_balance_gains(kelvin: f32) -> [f32; 3] {
+    if kelvin <= 0.0 {
+        return [1.0, 1.0, 1.0];
+    }
+    let reference = Color::from_kelvin(kelvin);
+    let green = (reference.g as f32).max(1.0);
+    [green / (reference.r as f32).max(1.0), 1.0, green / (reference.b as f32).max(1.0)]
+}
+
+pub fn render(framebuffer: &mut Framebuffer, scene: &Scene, settings: &RenderSettings) {
+    render_with_progress(framebuffer, scene, settings, &mut |_, _, _| {});
+}
+
+// Como `render`, pero llama a `on_progress` con la imagen a medias y los tiles terminados / totales
+// cada vez que acaba una tanda de tiles. Los píxeles que faltan conservan lo que tenía el
+// framebuffer, así que en la ventana el frame nuevo va tapando al anterior. Los modos clay y
+// fast y el estéreo no informan del progreso: solo se ve el frame terminado. Con el backend de GPU
+// tampoco; si la GPU falla o no puede con la escena, el frame se renderiza en la CPU. El canal alfa
+// que pide `settings.alpha` queda en `framebuffer.alpha`, salvo en estéreo, que sale opaco.
+pub fn render_with_progress(framebuffer: &mut Framebuffer, scene: &Scene, settings: &RenderSettings, on_progress: &mut dyn FnMut(&[u32], usize, usize)) {
+    framebuffer.alpha = None;
+    #[cfg(feature = "gpu")]
+    if settings.backend == Backend::Gpu && crate::gpu::handles(settings) {
+        match crate::gpu::render(framebuffer, scene, settings) {
+            Ok(()) => {
+                if settings.light_gizmos {
+                    draw_light_gizmos(&mut framebuffer.buffer, framebuffer.width, framebuffer.height, &scene.camera, &scene.lights, scene.objects());
+                }
+                return;
+            }
+            Err(error) => crate::gpu::warn_fallback(&error),
+        }
+    }
+    match settings.render_mode {
+        RenderMode::Clay => render_clay(framebuffer, scene, settings),
+        RenderMode::Fast => render_fast(framebuffer, scene, settings),
+        _ => render_frame(framebuffer, scene, settings, on_progress),
+    }
+}
+
+fn render_frame(framebuffer: &mut Framebuffer, scene: &Scene, settings: &RenderSettings, on_progress: &mut dyn FnMut(&[u32], usize, usize)) {
+    if settings.stereo == StereoMode::Off {
+        render_view(framebuffer, scene, &scene.camera, settings, on_progress);
+        return;
+    }
+
+    // Estéreo: cada ojo se renderiza por separado y luego se combinan
+    let half_separation = settings.eye_separation * 0.5;
+    let left = scene.camera.stereo_eye(-half_separation, settings.convergence);
+    let right = scene.camera.stereo_eye(half_separation, settings.convergence);
+    let (eye_width, eye_height) = match settings.stereo {
+        StereoMode::SideBySide => (framebuffer.width / 2, framebuffer.height),
+        _ => (framebuffer.width, framebuffer.height),
+    };
+    let mut left_buffer = Framebuffer::new(eye_width, eye_height);
+    let mut right_buffer = Framebuffer::new(eye_width, eye_height);
+    render_view(&mut left_buffer, scene, &left, settings, &mut |_, _, _| {});
+    render_view(&mut right_buffer, scene, &right, settings, &mut |_, _, _| {});
+
+    match settings.stereo {
+        StereoMode::Anaglyph => {
+            for (index, pixel) in framebuffer.buffer.iter_mut().enumerate() {
+                *pixel = (left_buffer.buffer[index] & 0xFF0000) | (right_buffer.buffer[index] & 0x00FFFF);
+            }
+        }
+        _ => {
+            // Con un ancho impar la última columna no es de ningún ojo y queda en negro, para que no
+            // se vea lo que hubiera del frame anterior
+            for y in 0..eye_height {
+                let row = y * framebuffer.width;
+                let eye_row = y * eye_width..(y + 1) * eye_width;
+                framebuffer.buffer[row..row + eye_width].copy_from_slice(&left_buffer.buffer[eye_row.clone()]);
+                framebuffer.buffer[row + eye_width..row + 2 * eye_width].copy_from_slice(&right_buffer.buffer[eye_row]);
+                framebuffer.buffer[row + 2 * eye_width..row + framebuffer.width].fill(0);
+            }
+        }
+    }
+}
+
+// Renderiza la escena vista desde `camera`, que en estéreo es la de un ojo y no la de la escena
+fn render_view(framebuffer: &mut Framebuffer, scene: &Scene, camera: &Camera, settings: &RenderSettings, on_progress: &mut dyn FnMut(&[u32], usize, usize)) {
+    let objects = scene.objects();
+    let width = framebuffer.width as Float;
+    let height = framebuffer.height as Float;
+
+    // Las muestras por píxel se distribuyen en una cuadrícula regular dentro del píxel; cerca del
+    // foco la cuadrícula de cada tile puede ser más fina
+    let grid_for = |samples: u32| (samples.max(1) as f32).sqrt().ceil() as u32;
+    let focus = settings.focus.unwrap_or([to_f32(width) * 0.5, to_f32(height) * 0.5]);
+    let focus_radius = if settings.equalize_samples { 0.0 } else { settings.focus_radius * to_f32(width.hypot(height)) };
+
+
+
+
+    // Fracción del frame durante la que se reparten las muestras en el tiempo (desenfoque de movimiento)
+    let shutter = if camera.motion.is_some() { (settings.shutter_angle / 360.0).clamp(0.0, 1.0) } else { 0.0 };
+
+    let gains = white_balance_gains(settings.white_balance);
+
+    // Búfer temporal para los colores de los píxeles; empieza con el frame anterior para que los
+    // tiles pendientes no se vean negros mientras llegan los nuevos
+    let mut pixel_buffer = framebuffer.buffer.clone();
+
+    // En damero solo se trazan los píxeles con x + y + frame par; el resto se reconstruye al final
+    let checkerboard = settings.checkerboard && settings.render_mode != RenderMode::Samples;
+    let traced = |x: u32, y: u32| !checkerboard || (x + y + settings.frame % 2) % 2 == 0;
+
+
+
+
+    // Con half_res_secondary solo los píxeles con x + y par lanzan reflejos y refracciones; el damero
+    // ya traza la mitad de los píxeles, así que con él se lanzan en todos
+    let half_secondary = settings.half_res_secondary && !checkerboard && settings.render_mode != RenderMode::Samples;
+
+
+
+
+    // Con alfa cada píxel cuenta cuántas de sus muestras primarias dan con algo; la luz secundaria
+    // se traza en todos los píxeles para no mezclar al reconstruirla bordes con el cielo
+    let alpha = settings.alpha != AlphaMode::Off && !checkerboard && settings.render_mode != RenderMode::Samples;
+    let half_secondary = half_secondary && !alpha;
+
+    // El denoise necesita la normal y la profundidad de cada píxel, que el damero no tiene para la
+    // mitad que no traza
+    let denoise = settings.denoise && !checkerboard && settings.render_mode != RenderMode::Samples;
+
+
+
+
+    // Media de las muestras de un píxel con grid x grid muestras; con `secondary` a false, sin rayos
+    // de reflexión ni refracción
+    let shade = |x: u32, y: u32, grid: u32, secondary: bool| -> PixelShading {
+        let sample_count = grid * grid;
+
+        // Cada píxel recorre los instantes con una secuencia de razón áurea desde un punto propio,
+        // para que el ruido del tiempo no forme bandas entre píxeles vecinos
+        let time_start = point_hash(&Vec3::new(x as Float, y as Float, 0.0)).0 as Float;
+
+        let mut pixel = PixelShading::new(grid, secondary);
+        for sample in 0..sample_count {
+            // Desplazamiento dentro del píxel; con una sola muestra queda en la esquina como antes
+            let (offset_x, offset_y) = pixel_offset(settings.sampler, x, y, sample, grid);
+
+            let moved;
+            let camera = if shutter > 0.0 {
+                moved = camera.at_time((time_start + sample as Float * 0.618_034).fract() * shutter);
+                &moved
+            } else {
+                camera
+            };
+
+            // Fuera de la imagen que cubre la proyección (el círculo del ojo de pez) el píxel queda negro
+            let Some((origin, rotated_direction)) = camera.ray(x as Float + offset_x, y as Float + offset_y, width, height) else {
+                continue;
+            };
+
+            let pixel_sample = PixelSample { x, y, index: sample };
+            let mut shading = shade_ray(&Ray::new(origin, rotated_direction), scene, settings, &pixel_sample, 0, secondary);
+            if settings.outlines && is_on_block_edge(&origin, &rotated_direction, objects, settings.outline_width) {
+                shading = Shading { direct: settings.outline_color, secondary: Color::black(), secondary_weight: 0.0, ..shading };
+            }
+            pixel.add(&shading);
+        }
+        pixel.average(sample_count)
+    };
+
+    // Color final de un píxel a partir de la media de sus muestras
+    let finish = |x: u32, y: u32, [r, g, b]: [f32; 3]| -> u32 {
+        let offset = if settings.dither { dither_offset(x, y) } else { 0.0 };
+        let balance = |value: f32, gain: f32| quantize(settings.tone_mapping.apply(value * gain), offset);
+        Color::new(balance(r, gains[0]), balance(g, gains[1]), balance(b, gains[2])).to_hex()
+    };
+
+    // Los tiles se reparten en tandas de unos pocos por hilo, en el orden de `tile_order`; al
+    // acabar cada tanda se copian al búfer y se avisa del progreso
+    let tiles = ordered_tiles(framebuffer.width, framebuffer.height, settings.tile_order);
+    let batch_size = rayon::current_num_threads().max(1) * 4;
+    let mut done = 0;
+    // Con half_secondary se guarda cada píxel por partes para reconstruir después la luz secundaria,
+    // con alfa para sacar al final la cobertura y con denoise como guía del filtro
+    let keep_shadings = half_secondary || alpha || denoise;
+    let mut shadings = if keep_shadings { vec![PixelShading::new(1, true); pixel_buffer.len()] } else { Vec::new() };
+    for batch in tiles.chunks(batch_size) {
+        let shaded: Vec<(Vec<u32>, Vec<PixelShading>)> = batch
+            .par_iter()
+            .map(|tile| {
+                let grid = grid_for(focused_samples(tile, settings.samples_per_pixel.max(1), focus, focus_radius, settings.focus_falloff));
+                let pixels = (tile.y..tile.y + tile.height).flat_map(|y| (tile.x..tile.x + tile.width).map(move |x| (x as u32, y as u32)));
+                if settings.render_mode == RenderMode::Samples {
+                    // Gris proporcional a las muestras, con blanco para el máximo que puede dar el foco
+                    let most = grid_for(settings.samples_per_pixel.max(1) * FOCUS_BOOST).pow(2);
+                    let level = (255 * grid * grid / most) as u8;
+                    return (pixels.map(|_| Color::new(level, level, level).to_hex()).collect(), Vec::new());
+                }
+                let previous = &pixel_buffer;
+                let mut parts = Vec::new();
+                let colors = pixels
+                    .map(|(x, y)| {
+                        if !traced(x, y) {
+                            return previous[y as usize * framebuffer.width + x as usize];
+                        }
+                        let pixel = shade(x, y, grid, !half_secondary || (x + y) % 2 == 0);
+                        if keep_shadings {
+                            parts.push(pixel);
+                        }
+                        if alpha {
+                            return finish(x, y, pixel.covered_color(settings.alpha));
+                        }
+                        // Mientras tanto, los píxeles sin luz secundaria se ven solo con la directa
+                        finish(x, y, if pixel.traced { pixel.color } else { pixel.direct })
+                    })
+                    .collect();
+                (colors, parts)
+            })
+            .collect();
+        for (tile, (pixels, parts)) in batch.iter().zip(shaded) {
+            for (row, line) in pixels.chunks(tile.width).enumerate() {
+                let start = (tile.y + row) * framebuffer.width + tile.x;
+                pixel_buffer[start..start + tile.width].copy_from_slice(line);
+            }
+            for (row, line) in parts.chunks(tile.width).enumerate() {
+                let start = (tile.y + row) * framebuffer.width + tile.x;
+                shadings[start..start + tile.width].copy_from_slice(line);
+            }
+        }
+        done += batch.len();
+        on_progress(&pixel_buffer, done, tiles.len());
+    }
+    if checkerboard {
+        pixel_buffer = fill_checkerboard(&pixel_buffer, framebuffer.width, framebuffer.height, settings.frame);
+    }
+    if half_secondary {
+        let (image_width, image_height) = (framebuffer.width, framebuffer.height);
+        pixel_buffer = (0..pixel_buffer.len())
+            .into_par_iter()
+            .map(|index| {
+                let pixel = &shadings[index];
+                if pixel.traced || pixel.secondary_weight <= 0.0 {
+                    return pixel_buffer[index];
+                }
+                let (x, y) = (index % image_width, index / image_width);
+                let color = match upsample_secondary(&shadings, image_width, image_height, x, y) {
+                    Some(secondary) => [0, 1, 2].map(|c| pixel.direct[c] + secondary[c]),
+                    // Ningún vecino es de la misma superficie: el píxel se traza entero
+                    None => shade(x as u32, y as u32, pixel.grid, true).color,
+                };
+                finish(x as u32, y as u32, color)
+            })
+            .collect();
+    }
+    if denoise {
+        pixel_buffer = denoise_pixels(&pixel_buffer, &shadings, framebuffer.width, framebuffer.height);
+    }
+
+
+
+
+    if settings.render_mode == RenderMode::Toon {
+        draw_toon_outlines(&mut pixel_buffer, framebuffer.width, framebuffer.height, camera, objects, settings);
+    }
+    if settings.lens_flare > 0.0 {
+        let sources = flare_sources(scene, camera, settings, framebuffer.width, framebuffer.height);
+        draw_lens_flare(&mut pixel_buffer, framebuffer.width, framebuffer.height, &sources, settings.flare_elements, settings.lens_flare);
+    }
+    if settings.light_gizmos {
+        draw_light_gizmos(&mut pixel_buffer, framebuffer.width, framebuffer.height, camera, &scene.lights, objects);
+    }
+
+    // Finalmente, vuelca el pixel_buffer en el framebuffer
+    for (index, &pixel) in pixel_buffer.iter().enumerate() {
+        let x = (index % framebuffer.width as usize) as u32;
+        let y = (index / framebuffer.width as usize) as u32;
+        framebuffer.set_current_color(pixel);
+        framebuffer.point(x as usize, y as usize);
+    }
+    if alpha {
+        framebuffer.alpha = Some(shadings.iter().map(|pixel| (pixel.coverage * 255.0).round() as u8).collect());
+    }
+}
+
+
+// Media de las muestras de un píxel, con la luz directa y la secundaria también por separado y la
+// distancia y la normal del primer impacto como guías para reconstruir la secundaria
+#[derive(Clone, Copy, Debug)]
+struct PixelShading {
+    color: [f32; 3], // Las dos partes sumadas muestra a muestra, como las devuelve cast_ray
+    direct: [f32; 3],
+    secondary: [f32; 3],
+    secondary_weight: f32,
+    distance: Float,
+    normal: Vec3,
+    grid: u32,
+    traced: bool, // Si se lanzaron los rayos secundarios
+    covered: [f32; 3], // Como `color`, pero solo con las muestras cuyo rayo primario dio con algo
+    coverage: f32,     // Fracción de esas muestras; el cielo visto en un reflejo sí cuenta
+}
+
+impl PixelShading {
+    fn new(grid: u32, traced: bool) -> Self {
+        PixelShading {
+            color: [0.0; 3],
+            direct: [0.0; 3],
+            secondary: [0.0; 3],
+            secondary_weight: 0.0,
+            distance: Float::INFINITY,
+            normal: Vec3::zeros(),
+            grid,
+            traced,
+            covered: [0.0; 3],
+            coverage: 0.0,
+        }
+    }
+
+    fn add(&mut self, shading: &Shading) {
+        let combined = shading.direct + shading.secondary;
+        for (sum, color) in [(&mut self.color, combined), (&mut self.direct, shading.direct), (&mut self.secondary, shading.secondary)] {
+            sum[0] += color.r as f32;
+            sum[1] += color.g as f32;
+            sum[2] += color.b as f32;
+        }
+        self.secondary_weight += shading.secondary_weight;
+        if shading.distance.is_finite() {
+            for (sum, value) in self.covered.iter_mut().zip([combined.r, combined.g, combined.b]) {
+                *sum += value as f32;
+            }
+            self.coverage += 1.0;
+        }
+        if !self.distance.is_finite() {
+            self.distance = shading.distance;
+            self.normal = shading.normal;
+        }
+    }
+
+    fn average(mut self, count: u32) -> Self {
+        let n = count as f32;
+        for sum in [&mut self.color, &mut self.direct, &mut self.secondary, &mut self.covered] {
+            for value in sum.iter_mut() {
+                *value /= n;
+            }
+        }
+        self.secondary_weight /= n;
+        self.coverage /= n;
+        self
+    }
+
+    // Color del píxel con alfa: premultiplicado es la media de las muestras cubiertas sobre todas
+    // (el cielo pone negro) y straight la media solo entre ellas, sin rastro del cielo
+    fn covered_color(&self, mode: AlphaMode) -> [f32; 3] {
+        match mode {
+            AlphaMode::Straight if self.coverage > 0.0 => self.covered.map(|value| value / self.coverage),
+            AlphaMode::Off => self.color,
+            _ => self.covered,
+        }
+    }
+}
+
+// Diferencia de profundidad, relativa a la distancia del píxel, a la que el peso de un vecino cae a 1/e
+const GUIDE_DEPTH_SIGMA: f32 = 0.05;
+// Por debajo de este peso total los vecinos no se parecen lo bastante y el píxel se traza entero
+const MIN_GUIDE_WEIGHT: f32 = 0.1;
+
+// Luz secundaria de un píxel que no la ha trazado, a partir de sus cuatro vecinos, que en el patrón
+// alterno sí lo han hecho. Cada vecino pesa según lo que se parezcan la normal, la profundidad y la
+// fracción reflejada o refractada, y su luz se reescala a la fracción de este píxel. Los vecinos de
+// otra superficie no cuentan, así que un vidrio no se mezcla con la pared de al lado. None si
+// ninguno vale.
+fn upsample_secondary(shadings: &[PixelShading], width: usize, height: usize, x: usize, y: usize) -> Option<[f32; 3]> {
+    let pixel = &shadings[y * width + x];
+    let mut sum = [0.0; 3];
+    let mut total = 0.0;
+    for (nx, ny) in [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)] {
+        if nx >= width || ny >= height {
+            continue;
+        }
+        let neighbor = &shadings[ny * width + nx];
+        if !neighbor.traced || neighbor.secondary_weight <= 0.0 {
+            continue;
+        }
+        let normal = to_f32(pixel.normal.dot(&neighbor.normal)).max(0.0).powi(8);
+        let depth = (-to_f32((pixel.distance - neighbor.distance).abs() / pixel.distance) / GUIDE_DEPTH_SIGMA).exp();
+        let fraction = 1.0 - (pixel.secondary_weight - neighbor.secondary_weight).abs() / pixel.secondary_weight.max(neighbor.secondary_weight);
+        let weight = normal * depth * fraction.max(0.0);
+        if weight <= 0.0 {
+            continue;
+        }
+        let scale = weight * pixel.secondary_weight / neighbor.secondary_weight;
+        for (sum, value) in sum.iter_mut().zip(neighbor.secondary) {
+            *sum += value * scale;
+        }
+        total += weight;
+    }
+    (total >= MIN_GUIDE_WEIGHT).then(|| sum.map(|value| value / total))
+}
+
+// Margen de color por canal con el que el valor del frame anterior se sigue aceptando en el damero
+const CHECKERBOARD_TOLERANCE: u32 = 8;
+
+// Reconstruye los píxeles que el damero no ha trazado este frame, los de x + y + frame impar, que aún
+// guardan el valor del frame anterior. Si ese valor cae dentro del rango de color de sus cuatro vecinos,
+// recién trazados, se conserva; si no, lo más probable es que ahí haya aparecido algo que antes estaba
+// tapado y se usa la media de los vecinos en lugar de un color viejo.
+fn fill_checkerboard(pixels: &[u32], width: usize, height: usize, frame: u32) -> Vec<u32> {
+    let channels = |pixel: u32| [(pixel >> 16) & 0xFF, (pixel >> 8) & 0xFF, pixel & 0xFF];
+    (0..pixels.len())
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            if (x + y + frame as usize % 2) % 2 == 0 {
+                return pixels[index];
+            }
+            let neighbors: Vec<[u32; 3]> = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)]
+                .into_iter()
+                .filter(|&(x, y)| x < width && y < height)
+                .map(|(x, y)| channels(pixels[y * width + x]))
+                .collect();
+            if neighbors.is_empty() {
+                return pixels[index];
+            }
+            let previous = channels(pixels[index]);
+            let in_range = (0..3).all(|c| {
+                let low = neighbors.iter().map(|n| n[c]).min().unwrap();
+                let high = neighbors.iter().map(|n| n[c]).max().unwrap();
+                (low.saturating_sub(CHECKERBOARD_TOLERANCE)..=high + CHECKERBOARD_TOLERANCE).contains(&previous[c])
+            });
+            if in_range {
+                return pixels[index];
+            }
+            let average = |c: usize| neighbors.iter().map(|n| n[c]).sum::<u32>() / neighbors.len() as u32;
+            (average(0) << 16) | (average(1) << 8) | average(2)
+        })
+        .collect()
+}
+
+// Radio en píxeles del filtro de denoise y diferencia de color (media cuadrática de los tres canales,
+// en niveles de 0 a 255) a la que el peso de un vecino cae a 1/e
+const DENOISE_RADIUS: i64 = 2;
+const DENOISE_COLOR_SIGMA: f32 = 32.0;
+
+// Denoise: filtro bilateral guiado. Cada píxel pasa a ser la media de los de un cuadrado de 5x5 a su
+// alrededor, pesados por la distancia en la imagen, el parecido del color y, como en upsample_secondary,
+// el de la normal y la profundidad del primer impacto. El ruido de las sombras suaves y la oclusión se
+// alisa sin cruzar los bordes de los bloques ni los saltos de profundidad; el cielo no se toca.
+fn denoise_pixels(pixels: &[u32], shadings: &[PixelShading], width: usize, height: usize) -> Vec<u32> {
+    let channels = |pixel: u32| [(pixel >> 16) & 0xFF, (pixel >> 8) & 0xFF, pixel & 0xFF].map(|c| c as f32);
+    (0..pixels.len())
+        .into_par_iter()
+        .map(|index| {
+            let pixel = &shadings[index];
+            if !pixel.distance.is_finite() {
+                return pixels[index];
+            }
+            let (x, y) = ((index % width) as i64, (index / width) as i64);
+            let center = channels(pixels[index]);
+            let mut sum = [0.0f32; 3];
+            let mut total = 0.0;
+            for ny in (y - DENOISE_RADIUS).max(0)..=(y + DENOISE_RADIUS).min(height as i64 - 1) {
+                for nx in (x - DENOISE_RADIUS).max(0)..=(x + DENOISE_RADIUS).min(width as i64 - 1) {
+                    let neighbor_index = ny as usize * width + nx as usize;
+                    let neighbor = &shadings[neighbor_index];
+                    if !neighbor.distance.is_finite() {
+                        continue;
+                    }
+                    let color = channels(pixels[neighbor_index]);
+                    let spatial = ((nx - x).pow(2) + (ny - y).pow(2)) as f32 / (DENOISE_RADIUS * DENOISE_RADIUS) as f32;
+                    let difference = (0..3).map(|c| (color[c] - center[c]).powi(2)).sum::<f32>() / (3.0 * DENOISE_COLOR_SIGMA * DENOISE_COLOR_SIGMA);
+                    let normal = to_f32(pixel.normal.dot(&neighbor.normal)).max(0.0).powi(8);
+                    let depth = (-to_f32((pixel.distance - neighbor.distance).abs() / pixel.distance) / GUIDE_DEPTH_SIGMA).exp();
+                    let weight = (-spatial - difference).exp() * normal * depth;
+                    for (sum, value) in sum.iter_mut().zip(color) {
+                        *sum += value * weight;
+                    }
+                    total += weight;
+                }
+            }
+            // El propio píxel pesa 1, así que el total nunca es 0
+            let [r, g, b] = sum.map(|value| (value / total).round() as u32);
+            (r << 16) | (g << 8) | b
+        })
+        .collect()
+}
+
+// Redondea hacia arriba a la siguiente de `bands` bandas por unidad de luz, así que cualquier
+// punto iluminado recibe al menos la primera banda
+fn toon_band(amount: f32, bands: u32) -> f32 {
+    let bands = bands.max(1) as f32;
+    (amount * bands).ceil() / bands
+}
+
+
+// Objeto más cercano que golpea el rayo y el punto de impacto
+fn nearest_hit<'a>(origin: &Vec3, direction: &Vec3, objects: &'a [Primitive]) -> Option<(&'a Primitive, Hit)> {
+    let ray = Ray::new(*origin, *direction);
+    objects.iter()
+        .enumerate()
+        .filter_map(|(index, object)| object.ray_intersect(&ray).map(|hit| (object, Hit { object: index, ..hit })))
+        .min_by(|(_, a), (_, b)| a.t.total_cmp(&b.t))
+}
+
+
+// Contornos del modo toon: se traza un rayo por píxel para obtener la distancia y la normal de lo que
+// se ve, y se oscurecen los píxeles junto a un salto de profundidad o un cambio de normal. La línea
+// queda del lado del objeto más cercano, y los bloques vecinos en el mismo plano no se separan.
+fn draw_toon_outlines(pixel_buffer: &mut [u32], width: usize, height: usize, camera: &Camera, objects: &[Primitive], settings: &RenderSettings) {
+    let surfaces: Vec<Option<(Float, Vec3)>> = (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = ((index % width) as Float, (index / width) as Float);
+            let (origin, direction) = camera.ray(x, y, width as Float, height as Float)?;
+            nearest_hit(&origin, &direction, objects).map(|(_, hit)| (hit.t, hit.normal))
+        })
+        .collect();
+
+    let reach = settings.toon_outline_width as i64;
+    let is_edge = |index: usize| {
+        let Some((depth, normal)) = surfaces[index] else {
+            return false;
+        };
+        let (x, y) = ((index % width) as i64, (index / width) as i64);
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                match surfaces[ny as usize * width + nx as usize] {
+                    None => return true,
+                    Some((other_depth, other_normal)) => {
+                        let farther = other_depth - depth > TOON_DEPTH_JUMP * depth;
+                        if farther || normal.dot(&other_normal) < TOON_CREASE_COSINE {
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+        false
+    };
+    let edges: Vec<bool> = (0..width * height).into_par_iter().map(is_edge).collect();
+    for (pixel, edge) in pixel_buffer.iter_mut().zip(edges) {
+        if edge {
+            *pixel = settings.toon_outline_color.to_hex();
+        }
+    }
+}
+
+// Salto relativo de profundidad y coseno entre normales a partir de los que hay contorno
+const TOON_DEPTH_JUMP: Float = 0.1;
+const TOON_CREASE_COSINE: Float = 0.7;
+
+
+// Tamaño de un texel en el mundo: las texturas de 16 texels cubren bloques de 0.5
+const TEXEL_SIZE: Float = 0.5 / 16.0;
+// Luz que pierde una esquina por cada bloque vecino
+const SMOOTH_LIGHTING_STEP: f32 = 0.2;
+
+// Si el primer punto que ve el rayo está a menos de `width` texels de una arista de su cubo. Cada
+// cubo usa sus propios límites, así que dos bloques pegados en el mismo plano siguen mostrando la
+// arista que comparten.
+fn is_on_block_edge(origin: &Vec3, direction: &Vec3, objects: &[Primitive], width: f32) -> bool {
+    let Some((Primitive::Cube(object), hit)) = nearest_hit(origin, direction, objects) else {
+        return false;
+    };
+    let (point, min, max) = (hit.point, object.min, object.max);
+    let threshold = width as Float * TEXEL_SIZE;
+    face_axes(&hit.face).iter().any(|&axis| (point[axis] - min[axis]).min(max[axis] - point[axis]) < threshold)
+}
+
+// Ejes que recorren una cara
+fn face_axes(face: &CubeFace) -> [usize; 2] {
+    match face {
+        CubeFace::Top | CubeFace::Bottom => [0, 2],
+        CubeFace::Left | CubeFace::Right => [1, 2],
+        CubeFace::Front | CubeFace::Back => [0, 1],
+    }
+}
+
+
+// Iluminación suave de Minecraft: cada esquina de la cara se oscurece según los bloques que la rodean
+// delante de la cara (los dos de los lados y el de la diagonal, de 0 a 3) y el resultado se interpola
+// sobre la cara. No lanza rayos, así que no hace ruido ni con una muestra. Solo cuentan los bloques
+// enteros de la rejilla de 0.5; en caras fuera de la rejilla (losas, vallas, cubos sueltos) devuelve 1.
+fn smooth_lighting(intersect: &Hit, object: &Primitive, objects: &[Primitive]) -> f32 {
+    let on_grid = |value: Float| ((value * 2.0) - (value * 2.0).round()).abs() < 1e-3;
+    let Some(object) = object.as_cube() else {
+        return 1.0;
+    };
+    if !(0..3).all(|axis| on_grid(object.min[axis]) && on_grid(object.max[axis])) {
+        return 1.0;
+    }
+    // Celda de la rejilla justo delante de la cara
+    let front = intersect.point + intersect.normal * (TEXEL_SIZE * 0.5);
+    let cell = [(front.x * 2.0).floor(), (front.y * 2.0).floor(), (front.z * 2.0).floor()];
+    let occupied = |offset: [Float; 3]| {
+        let min = Vec3::new(cell[0] + offset[0], cell[1] + offset[1], cell[2] + offset[2]) * 0.5;
+        let max = min + Vec3::new(0.5, 0.5, 0.5);
+        let covers = |cube: &Cube| (0..3).all(|axis| cube.min[axis] <= min[axis] + 1e-3 && cube.max[axis] >= max[axis] - 1e-3);
+        objects.iter().filter_map(Primitive::as_cube).any(covers)
+    };
+
+    let [a, b] = face_axes(&intersect.face);
+    let step = |axis: usize, sign: Float| {
+        let mut offset = [0.0; 3];
+        offset[axis] = sign;
+        offset
+    };
+    let corner = |sign_a: Float, sign_b: Float| {
+        let side_a = occupied(step(a, sign_a));
+        let side_b = occupied(step(b, sign_b));
+        let mut diagonal = step(a, sign_a);
+        diagonal[b] = sign_b;
+        // Con los dos lados tapados la esquina queda a oscuras aunque la diagonal esté libre
+        let level = if side_a && side_b { 3 } else { side_a as u32 + side_b as u32 + occupied(diagonal) as u32 };
+        1.0 - SMOOTH_LIGHTING_STEP * level as f32
+    };
+
+    let u = to_f32((intersect.point[a] * 2.0).rem_euclid(1.0));
+    let v = to_f32((intersect.point[b] * 2.0).rem_euclid(1.0));
+    let low = corner(-1.0, -1.0) * (1.0 - u) + corner(1.0, -1.0) * u;
+    let high = corner(-1.0, 1.0) * (1.0 - u) + corner(1.0, 1.0) * u;
+    low * (1.0 - v) + high * v
+}
+
+
+// Gris neutro del 18 % (en sRGB) para el modo arcilla
+const CLAY_COLOR: Color = Color::new(118, 118, 118);
+const CLAY_SKY: Sky = Sky::Gradient { top: Color::new(200, 200, 200), bottom: Color::new(90, 90, 90) };
+const CLAY_AO_SAMPLES: u32 = 8;
+const CLAY_SHADOW_SAMPLES: u32 = 4;
+
+// Modo arcilla: la misma escena con todos los materiales cambiados por un gris difuso sin texturas,
+// reflejos ni emisión, iluminada por una luz principal suave por encima y a la izquierda de la cámara
+// y por la oclusión ambiental, sobre un fondo degradado. Los marcadores de las luces no se dibujan.
+fn render_clay(framebuffer: &mut Framebuffer, scene: &Scene, settings: &RenderSettings) {
+    let (objects, camera) = (scene.objects(), &scene.camera);
+    let clay = Material::new(CLAY_COLOR, 1.0, [1.0, 0.0, 0.0, 0.0], 1.0);
+    let clay_objects: Vec<Primitive> = objects.iter().map(|object| object.with_material(&clay)).collect();
+
+    // La luz se coloca según el tamaño de la escena para que ilumine igual a cualquier escala; los
+    // planos, que no tienen tamaño, no cuentan
+    let (min, max) = objects.iter().filter_map(Primitive::bounds).fold(
+        (camera.center, camera.center),
+        |(min, max), (object_min, object_max)| (min.inf(&object_min), max.sup(&object_max)),
+    );
+    let size = (max - min).magnitude().max(1.0);
+    let forward = (camera.center - camera.eye).normalize();
+    let right = forward.cross(&camera.up).normalize();
+    let up = right.cross(&forward);
+    let key_direction = (up * 0.7 - right * 0.6 - forward).normalize();
+    let key = Light::new((min + max) * 0.5 + key_direction * size, Color::new(255, 255, 255), 1.0, size * 4.0);
+
+    let settings = RenderSettings {
+        render_mode: RenderMode::Shaded,
+        max_depth: 0,
+        light_gizmos: false,
+        lens_flare: 0.0,
+        ao_samples: settings.ao_samples.max(CLAY_AO_SAMPLES),
+        shadow_samples: settings.shadow_samples.max(CLAY_SHADOW_SAMPLES),
+        ..settings.clone()
+    };
+    let ambient = CLAY_SKY.ambient(&Color::new(0, 0, 0), &AmbientSettings::default());
+    render(framebuffer, &Scene::new(clay_objects, vec![key], Arc::new(CLAY_SKY), camera.clone(), ambient), &settings);
+}
+
+
+// Color de la luz de bloque del modo rápido, el de una antorcha
+const BLOCK_LIGHT_COLOR: Color = Color::new(255, 214, 170);
+
+// Brillo de un nivel de luz (de bloque o del cielo) con la curva de Minecraft: el nivel 15 da la luz completa y
+// los niveles bajos caen mucho más deprisa que en línea recta
+fn light_brightness(level: u8) -> f32 {
+    let fraction = level as f32 / MAX_LIGHT_LEVEL as f32;
+    fraction / (4.0 - 3.0 * fraction)
+}
+
+// Modo rápido y aproximado: sin rayos de sombra ni oclusión. Las luces de los bloques
+// emisivos se sustituyen por los niveles de luz propagados por la rejilla, que se leen en la celda
+// delante de cada cara; el sol, la luna y las luces de la escena siguen iluminando, sin sombras.
+fn render_fast(framebuffer: &mut Framebuffer, scene: &Scene, settings: &RenderSettings) {
+    let emissive: Vec<(Vec3, Vec3)> = scene.objects().iter()
+        .filter(|object| object.material().emission != Color::new(0, 0, 0))
+        .filter_map(Primitive::bounds)
+        .collect();
+    let lights: Vec<Light> = scene.lights.iter()
+        .filter(|light| !emissive.iter().any(|(min, max)| (0..3).all(|axis| (min[axis]..=max[axis]).contains(&light.position[axis]))))
+        .cloned()
+        .collect();
+
+    let settings = RenderSettings {
+        ao_samples: 0,
+        shadow_samples: 1,
+        ..settings.clone()
+    };
+    // La copia comparte los objetos y la rejilla de luz con la escena original
+    let mut scene = scene.clone();
+    scene.lights = lights;
+    render_frame(framebuffer, &scene, &settings, &mut |_, _, _| {});
+}
+
+
+// Radio en píxeles de los marcadores de las luces
+const GIZMO_RADIUS: Float = 4.0;
+
+// Marca la posición de cada luz con un disco de su color encima de la imagen ya calculada, así que
+// siempre mide unos pocos píxeles y no proyecta sombras ni ilumina nada. Las luces tapadas por la
+// escena se dibujan solo con el contorno y las apagadas en gris. Solo con la proyección en perspectiva.
+fn draw_light_gizmos(pixel_buffer: &mut [u32], width: usize, height: usize, camera: &Camera, lights: &[Light], objects: &[Primitive]) {
+    for light in lights {
+        let Some((center_x, center_y)) = camera.project(&light.position, width as Float, height as Float) else {
+            continue;
+        };
+        let to_light = light.position - camera.eye;
+        let distance = to_light.magnitude();
+        let hidden = is_occluded(&camera.eye, &(to_light / distance), objects, distance);
+        let color = if light.enabled { light.color } else { Color::new(128, 128, 128) };
+
+        let reach = GIZMO_RADIUS.ceil() as i64 + 1;
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let (x, y) = (center_x.floor() as i64 + dx, center_y.floor() as i64 + dy);
+                if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+                    continue;
+                }
+                let offset = ((x as Float + 0.5 - center_x).powi(2) + (y as Float + 0.5 - center_y).powi(2)).sqrt();
+                if offset > GIZMO_RADIUS + 1.0 || (hidden && offset < GIZMO_RADIUS - 1.5) {
+                    continue;
+                }
+                // Borde oscuro de un píxel para que el marcador se vea sobre cualquier fondo
+                let pixel_color = if offset > GIZMO_RADIUS { Color::new(0, 0, 0) } else { color };
+                pixel_buffer[y as usize * width + x as usize] = pixel_color.to_hex();
+            }
+        }
+    }
+}
+
+
+// Bloque visible en las coordenadas (x, y) de una imagen de width x height, por ejemplo bajo el ratón
+pub fn pick<'a>(objects: &'a [Primitive], camera: &Camera, x: Float, y: Float, width: Float, height: Float) -> Option<&'a Primitive> {
+    let (origin, direction) = camera.ray(x, y, width, height)?;
+    let ray = Ray::new(origin, direction);
+    objects.iter()
+        .filter_map(|object| object.ray_intersect(&ray).filter(|hit| hit.t > 0.0).map(|hit| (object, hit.t)))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(object, _)| object)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+    use crate::material::{Material, Portal, Rain};
+    use crate::light::{Falloff, LightLink};
+    use crate::day_night::DayNightCycle;
+    use crate::sky::{Ambient, Fog};
+    use crate::settings::ToneMapping;
+
+    // Escena de prueba con estos objetos y luces; la cámara solo importa al renderizar
+    fn scene(objects: &[Cube], lights: &[Light], sky: Sky, ambient: &Ambient) -> Scene {
+        Scene::new(objects.to_vec(), lights.to_vec(), Arc::new(sky), Camera::default_view(), *ambient)
+    }
+
+    fn scene_seen_from(camera: &Camera, objects: &[Cube], lights: &[Light], sky: Sky, ambient: &Ambient) -> Scene {
+        let mut scene = scene(objects, lights, sky, ambient);
+        scene.camera = camera.clone();
+        scene
+    }
+
+    fn primitives(objects: &[Cube]) -> Vec<Primitive> {
+        objects.iter().cloned().map(Primitive::from).collect()
+    }
+
+    // Light and camera straight above a point on the top face of a gray slab, so the highlight
+    // is at its peak and only the specular weight differs between points
+    fn shade_top_face_at(material: &Material, x: Float) -> Color {
+        let slab = Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 0.0, 1.0), material);
+        let above = Vec3::new(x, 2.0, 0.1);
+        let lights = [Light::new(above, Color::new(255, 255, 255), 1.0, 10.0)];
+        let settings = RenderSettings::default();
+        let ambient = Ambient::flat(Color::new(0, 0, 0));
+        cast_ray(&Ray::new(above, Vec3::new(0.0, -1.0, 0.0)), &scene(&[slab], &lights, Sky::Procedural, &ambient), &settings, &PixelSample::default(), 0)
+    }
+
+    fn map(pixels: Vec<Color>) -> Texture {
+        Texture { width: pixels.len() as u32, height: 1, pixels, uv_transform: Default::default(), source: None }
+    }
+
+    fn brightness(color: Color) -> u32 {
+        color.r as u32 + color.g as u32 + color.b as u32
+    }
+
+    fn gray() -> Material {
+        Material::new(Color::new(100, 100, 100), 50.0, [0.5, 1.0, 0.0, 0.0], 0.0)
+    }
+
+    #[test]
+    fn specular_map_varies_highlight_across_face() {
+        // Left half of the map black, right half white. The top face maps x to u = 2 * fract(x),
+        // so x = 0.1 samples the black texel and x = 0.35 the white one
+        let material = gray().with_specular_map(map(vec![Color::new(0, 0, 0), Color::new(255, 255, 255)]));
+        let dull = shade_top_face_at(&material, 0.1);
+        let shiny = shade_top_face_at(&material, 0.35);
+        assert!(brightness(shiny) > brightness(dull), "shiny {:?} should be brighter than dull {:?}", shiny, dull);
+        assert_eq!(dull, shade_top_face_at(&gray().with_specular_map(map(vec![Color::new(0, 0, 0)])), 0.35));
+    }
+
+    #[test]
+    fn missing_or_white_specular_map_matches_scalar() {
+        let white = gray().with_specular_map(map(vec![Color::new(255, 255, 255)]));
+        for x in [0.1, 0.35] {
+            assert_eq!(shade_top_face_at(&white, x), shade_top_face_at(&gray(), x));
+        }
+    }
+
+    #[test]
+    fn roughness_map_spreads_highlight() {
+        // Seen slightly off the peak, a rough texel keeps more of the highlight than a polished one
+        let rough = gray().with_roughness_map(map(vec![Color::new(255, 255, 255)]));
+        let polished = gray().with_roughness_map(map(vec![Color::new(0, 0, 0)]));
+        let off_peak = |material: &Material| {
+            let slab = Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 0.0, 1.0), material);
+            let lights = [Light::new(Vec3::new(0.6, 2.0, 0.1), Color::new(255, 255, 255), 1.0, 10.0)];
+            let eye = Vec3::new(0.1, 2.0, 0.1);
+            let direction = (Vec3::new(0.2, 0.0, 0.1) - eye).normalize();
+            cast_ray(&Ray::new(eye, direction), &scene(&[slab], &lights, Sky::Procedural, &Ambient::flat(Color::new(0, 0, 0))), &RenderSettings::default(), &PixelSample::default(), 0)
+        };
+        assert!(brightness(off_peak(&rough)) > brightness(off_peak(&polished)));
+    }
+
+    #[test]
+    fn emissive_map_lights_only_bright_texels_in_the_dark() {
+        // No lights and no ambient: whatever is seen comes from the emissive map
+        let ore = gray().with_emissive_map(map(vec![Color::new(0, 0, 0), Color::new(255, 140, 40)]), 1.0);
+        let settings = RenderSettings::default();
+        let dark = Ambient::flat(Color::new(0, 0, 0));
+        let look_down_at = |objects: &[Cube], x: Float| {
+            cast_ray(&Ray::new(Vec3::new(x, 2.0, 0.1), Vec3::new(0.0, -1.0, 0.0)), &scene(objects, &[], Sky::Procedural, &dark), &settings, &PixelSample::default(), 0)
+        };
+        let slab = || Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 0.0, 1.0), &ore);
+        assert_eq!(look_down_at(&[slab()], 0.1), Color::new(0, 0, 0));
+        assert!(brightness(look_down_at(&[slab()], 0.35)) > 0);
+
+        // Seen in a mirror the speckle still glows and the base stays dark: a ceiling of ore over a
+        // mirror floor, looked at straight down from between the two
+        let mirror = Material::new(Color::new(0, 0, 0), 60.0, [0.0, 0.0, 0.9, 0.0], 1.0);
+        let objects = [
+            Cube::new(Vec3::new(-1.0, 2.0, -1.0), Vec3::new(1.0, 3.0, 1.0), &ore),
+            Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 0.0, 1.0), &mirror),
+        ];
+        let in_mirror = |x: Float| {
+            cast_ray(&Ray::new(Vec3::new(x, 1.0, 0.1), Vec3::new(0.0, -1.0, 0.0)), &scene(&objects, &[], Sky::Procedural, &dark), &settings, &PixelSample::default(), 0)
+        };
+        let direct = |x: Float| {
+            cast_ray(&Ray::new(Vec3::new(x, 1.0, 0.1), Vec3::new(0.0, 1.0, 0.0)), &scene(&objects, &[], Sky::Procedural, &dark), &settings, &PixelSample::default(), 0)
+        };
+        let (speckle, base) = if brightness(direct(0.35)) > 0 { (0.35, 0.1) } else { (0.1, 0.35) };
+        assert_eq!(direct(base), Color::new(0, 0, 0));
+        assert!(brightness(in_mirror(speckle)) > brightness(in_mirror(base)));
+    }
+
+    #[test]
+    fn parallax_offset_stays_inside_the_face() {
+        // A deep relief seen at a grazing angle near the face's edge would land far outside it
+        let material = gray();
+        let slab = Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 0.0, 1.0), &material);
+        let eye = Vec3::new(-0.9, 0.05, 0.1);
+        let direction = (Vec3::new(0.45, 0.0, 0.1) - eye).normalize();
+        let intersect = slab.ray_intersect(&Ray::new(eye, direction)).expect("the ray should graze the slab");
+        for steps in [1, 8] {
+            let (u, v) = parallax_uv(&intersect, &direction, (0.9, 0.2), 4.0, steps, |_, _| 1.0);
+            assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v), "({}, {}) with {} steps", u, v, steps);
+            assert!(u > 0.9, "the offset should follow the ray, got u = {}", u);
+        }
+        // A flat height map (all white) leaves the coordinates alone
+        assert_eq!(parallax_uv(&intersect, &direction, (0.9, 0.2), 4.0, 8, |_, _| 0.0), (0.9, 0.2));
+    }
+
+    #[test]
+    fn white_balance_neutralizes_a_warm_light() {
+        // A white slab under a 3200 K light, seen from straight above
+        let white = Material::new(Color::new(255, 255, 255), 1.0, [0.6, 0.0, 0.0, 0.0], 1.0);
+        let lights = [Light::new(Vec3::new(0.0, 1.0, 0.0), Color::from_kelvin(3200.0), 1.0, 10.0)];
+        let camera = Camera::new(Vec3::new(0.0, 3.0, 0.01), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let center = |white_balance: f32| {
+            let settings = RenderSettings { width: 8, height: 8, white_balance, ..RenderSettings::default() };
+            let mut framebuffer = Framebuffer::new(8, 8);
+            let slab = Cube::new(Vec3::new(-2.0, -1.0, -2.0), Vec3::new(2.0, 0.0, 2.0), &white);
+            render(&mut framebuffer, &scene_seen_from(&camera, &[slab], &lights, Sky::Procedural, &Ambient::flat(Color::new(0, 0, 0))), &settings);
+            let [r, g, b] = framebuffer.to_image().get_pixel(4, 4).0;
+            Color::new(r, g, b)
+        };
+
+        let warm = center(0.0);
+        assert!(warm.r as i32 - warm.b as i32 > 60, "uncorrected light should look orange, got {:?}", warm);
+        let neutral = center(3200.0);
+        let spread = neutral.r.max(neutral.g).max(neutral.b) - neutral.r.min(neutral.g).min(neutral.b);
+        assert!(spread <= 8, "balanced light should look neutral, got {:?}", neutral);
+    }
+
+    #[test]
+    fn hemisphere_ambient_tints_faces_by_their_normal() {
+        let white = Material::new(Color::new(255, 255, 255), 1.0, [0.0, 0.0, 0.0, 0.0], 1.0);
+        let slab = Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 0.0, 1.0), &white);
+        let settings = RenderSettings::default();
+        let ambient = Ambient { sky: Color::new(100, 150, 250), ground: Color::new(200, 120, 40), intensity: 1.0, night: 0.0 };
+        let look = |from: Vec3, direction: Vec3, ambient: &Ambient| {
+            cast_ray(&Ray::new(from, direction), &scene(std::slice::from_ref(&slab), &[], Sky::Procedural, ambient), &settings, &PixelSample::default(), 0)
+        };
+        let top = look(Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0), &ambient);
+        let bottom = look(Vec3::new(0.0, -3.0, 0.0), Vec3::new(0.0, 1.0, 0.0), &ambient);
+        let side = look(Vec3::new(3.0, -0.5, 0.0), Vec3::new(-1.0, 0.0, 0.0), &ambient);
+
+        assert!(top.b > top.r, "the top should be lit by the blue sky, got {:?}", top);
+        assert!(bottom.r > bottom.b, "the bottom should pick up the ground tint, got {:?}", bottom);
+        assert_eq!(side, Color::new(15, 13, 14), "a side face gets the halfway mix");
+
+        // Equal colors are the old flat ambient
+        let flat = Ambient::flat(Color::new(100, 150, 250));
+        assert_eq!(look(Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0), &flat), look(Vec3::new(0.0, -3.0, 0.0), Vec3::new(0.0, 1.0, 0.0), &flat));
+    }
+
+    #[test]
+    fn lights_without_shadows_shine_through_and_disabled_lights_are_skipped() {
+        // A gray slab with a roof between it and the light
+        let material = gray();
+        let objects = [
+            Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 0.0, 1.0), &material),
+            Cube::new(Vec3::new(-1.0, 1.0, -1.0), Vec3::new(1.0, 1.2, 1.0), &material),
+        ];
+        let settings = RenderSettings::default();
+        let ambient = Ambient::flat(Color::new(0, 0, 0));
+        let shade = |light: Light| {
+            cast_ray(&Ray::new(Vec3::new(0.3, 0.5, 0.0), Vec3::new(0.0, -1.0, 0.0)), &scene(&objects, &[light], Sky::Procedural, &ambient), &settings, &PixelSample::default(), 0)
+        };
+        let light = Light::new(Vec3::new(0.0, 2.0, 0.0), Color::new(255, 255, 255), 1.0, 10.0);
+
+        let shadowed = shade(light.clone());
+        let fill = shade(light.clone().with_shadows(false));
+        assert!(brightness(fill) > brightness(shadowed) * 5, "{:?} vs {:?}", fill, shadowed);
+
+        let mut off = light.clone().with_shadows(false);
+        off.enabled = false;
+        assert_eq!(shade(off), Color::new(0, 0, 0));
+        let unlinked = light.with_shadows(false).with_link(LightLink::Except([0].into()));
+        assert_eq!(shade(unlinked), Color::new(0, 0, 0));
+    }
+
+    #[test]
+    fn falloff_dims_light_with_distance_and_nothing_past_the_radius() {
+        let material = gray();
+        let objects = [Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 0.0, 1.0), &material)];
+        let settings = RenderSettings::default();
+        let ambient = Ambient::flat(Color::new(0, 0, 0));
+        // The point seen is a little over 2 units below the light
+        let shade = |falloff: Falloff, radius: Float| {
+            let light = Light::new(Vec3::new(0.0, 2.0, 0.0), Color::new(255, 255, 255), 1.0, radius).with_falloff(falloff);
+            let scene = scene(&objects, &[light], Sky::Procedural, &ambient);
+            brightness(cast_ray(&Ray::new(Vec3::new(0.3, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)), &scene, &settings, &PixelSample::default(), 0))
+        };
+
+        // Halfway to the edge: none keeps it all, then inverse square, quadratic and linear
+        let lit: Vec<_> = [Falloff::None, Falloff::InverseSquare, Falloff::Quadratic, Falloff::Linear].into_iter().map(|falloff| shade(falloff, 4.0)).collect();
+        assert!(lit.windows(2).all(|pair| pair[0] > pair[1]) && lit[3] > 0, "{:?}", lit);
+        for falloff in Falloff::ALL {
+            assert_eq!(shade(falloff, 2.0), 0, "{} lights past its radius", falloff.name());
+        }
+    }
+
+    #[test]
+    fn ambient_strength_lights_faces_no_light_reaches() {
+        // The underside of a slab, with the only light above it
+        let material = gray();
+        let objects = [Cube::new(Vec3::new(-1.0, 1.0, -1.0), Vec3::new(1.0, 1.2, 1.0), &material)];
+        let lights = [Light::new(Vec3::new(1.0, 3.0, 0.0), Color::new(255, 255, 255), 1.0, 10.0)];
+        let ambient = Ambient::flat(Color::new(200, 200, 200));
+        let shade = |ambient_strength: f32| {
+            let settings = RenderSettings { ambient_strength, ..RenderSettings::default() };
+            cast_ray(&Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)), &scene(&objects, &lights, Sky::Procedural, &ambient), &settings, &PixelSample::default(), 0)
+        };
+
+        assert_eq!(shade(0.0), Color::new(0, 0, 0));
+        let (dim, bright) = (shade(0.1), shade(0.5));
+        assert!(brightness(dim) > 0 && brightness(bright) > brightness(dim) * 4, "{:?} vs {:?}", dim, bright);
+    }
+
+    #[test]
+    fn light_gizmos_mark_lights_only_when_requested() {
+        // An empty scene with the light straight ahead, at the center of the image
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let lights = [Light::new(Vec3::new(0.0, 0.0, 0.0), Color::new(255, 0, 255), 1.0, 10.0)];
+        let center_pixel = |light_gizmos: bool| {
+            let settings = RenderSettings { width: 32, height: 32, light_gizmos, ..RenderSettings::default() };
+            let mut framebuffer = Framebuffer::new(32, 32);
+            render(&mut framebuffer, &scene_seen_from(&camera, &[], &lights, Sky::Procedural, &Ambient::flat(Color::new(0, 0, 0))), &settings);
+            let [r, g, b] = framebuffer.to_image().get_pixel(16, 16).0;
+            Color::new(r, g, b)
+        };
+        assert_eq!(center_pixel(true), Color::new(255, 0, 255));
+        assert_ne!(center_pixel(false), Color::new(255, 0, 255));
+    }
+
+    #[test]
+    fn clay_mode_renders_everything_in_neutral_gray() {
+        let red_mirror = Material::new(Color::new(255, 0, 0), 50.0, [0.8, 0.5, 0.6, 0.0], 1.0);
+        let objects = [Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0), &red_mirror)];
+        let camera = Camera::new(Vec3::new(2.0, 2.0, 4.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let lights = [Light::new(Vec3::new(0.0, 3.0, 3.0), Color::new(0, 255, 0), 1.0, 10.0)];
+        let settings = RenderSettings { width: 24, height: 24, render_mode: RenderMode::Clay, ..RenderSettings::default() };
+        let mut framebuffer = Framebuffer::new(24, 24);
+        render(&mut framebuffer, &scene_seen_from(&camera, &objects, &lights, Sky::Procedural, &Ambient::flat(Color::new(0, 0, 255))), &settings);
+
+        let image = framebuffer.to_image();
+        assert!(image.pixels().all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]), "clay renders should have no color");
+        // The cube in the middle differs from the background gradient around it
+        assert_ne!(image.get_pixel(12, 12), image.get_pixel(0, 12));
+    }
+
+    #[test]
+    fn dispersive_glass_splits_a_sharp_edge_into_colors() {
+        // A slab of glass seen at an angle shifts what's behind it sideways, by more for blue than
+        // for red; behind it a glowing wall ends right between where red and green land
+        let glass = |dispersion: f32| Material::new(Color::new(0, 0, 0), 0.0, [0.0, 0.0, 0.0, 1.0], 1.5).with_dispersion(dispersion);
+        let glow = Material::new(Color::new(255, 255, 255), 0.0, [1.0, 0.0, 0.0, 0.0], 1.0).with_emission(Color::new(255, 255, 255), 1.0);
+        let dark = Material::new(Color::new(0, 0, 0), 0.0, [1.0, 0.0, 0.0, 0.0], 1.0);
+        let walls = [
+            Cube::new(Vec3::new(-10.0, -10.0, -4.0), Vec3::new(2.72, 10.0, -3.0), &glow),
+            Cube::new(Vec3::new(2.72, -10.0, -4.0), Vec3::new(10.0, 10.0, -3.0), &dark),
+        ];
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.3, 0.0, -1.0).normalize());
+        let seen = |dispersion: f32| {
+            let slab = Cube::new(Vec3::new(-10.0, -10.0, -2.0), Vec3::new(10.0, 10.0, 0.0), &glass(dispersion));
+            let objects = [slab, walls[0].clone(), walls[1].clone()];
+            cast_ray(&ray, &scene(&objects, &[], Sky::Procedural, &Ambient::flat(Color::new(0, 0, 0))), &RenderSettings::default(), &PixelSample::default(), 0)
+        };
+        let split = seen(0.2);
+        assert!(split.r > 50 && split.g == 0 && split.b == 0, "{:?}", split);
+        assert_eq!(seen(0.001), Color::new(0, 0, 0), "with little dispersion every color misses the glowing wall");
+    }
+
+    #[test]
+    fn emission_strength_scales_the_glow_and_the_light_it_gives() {
+        let glowing = |strength: f32| {
+            Material::new(Color::new(60, 60, 60), 0.0, [0.5, 0.0, 0.0, 0.0], 1.0).with_emission(Color::new(255, 200, 120), strength)
+        };
+        let [dim, normal, bright] = [0.5, 1.0, 2.0].map(|strength| brightness(shade_top_face_at(&glowing(strength), 0.1)));
+        assert!(dim < normal && normal < bright, "{} {} {}", dim, normal, bright);
+
+        let light_of = |strength: f32| {
+            let block = Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), &glowing(strength));
+            crate::scene::generate_lights_from_emissive_objects(&[block])[0].intensity
+        };
+        assert_eq!(light_of(0.5) * 2.0, light_of(1.0));
+    }
+
+    #[test]
+    fn fog_fades_distant_hits_into_the_sky() {
+        let wall = Cube::new(Vec3::new(-50.0, -50.0, -21.0), Vec3::new(50.0, 50.0, -20.0), &gray());
+        let lights = [Light::new(Vec3::new(0.0, 5.0, 0.0), Color::new(255, 255, 255), 1.0, 30.0)];
+        let mut scene = scene(&[wall], &lights, Sky::Procedural, &Ambient::flat(Color::new(90, 120, 200)));
+        let ray = Ray::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0));
+        let settings = RenderSettings::default();
+        let clear = cast_ray(&ray, &scene, &settings, &PixelSample::default(), 0);
+        let sky = scene.sky.color(&ray.dir, &scene.lights, &scene.ambient);
+        assert_ne!(clear, sky);
+
+        scene.fog = Some(Fog { start: 30.0, end: 40.0 });
+        assert_eq!(cast_ray(&ray, &scene, &settings, &PixelSample::default(), 0), clear, "the wall is nearer than the fog");
+        scene.fog = Some(Fog { start: 5.0, end: 15.0 });
+        assert_eq!(cast_ray(&ray, &scene, &settings, &PixelSample::default(), 0), sky, "past the end only the sky is left");
+        scene.fog = Some(Fog { start: 10.0, end: 30.0 });
+        let halfway = cast_ray(&ray, &scene, &settings, &PixelSample::default(), 0);
+        assert!(halfway != clear && halfway != sky, "{:?}", halfway);
+
+        // The settings' fog applies when the scene has none of its own, and the scene's wins otherwise
+        let foggy = RenderSettings { fog_start: 5.0, fog_end: 15.0, ..RenderSettings::default() };
+        assert_eq!(cast_ray(&ray, &scene, &foggy, &PixelSample::default(), 0), halfway);
+        scene.fog = None;
+        assert_eq!(cast_ray(&ray, &scene, &foggy, &PixelSample::default(), 0), sky);
+    }
+
+    #[test]
+    fn tone_mapping_keeps_black_and_white_and_bends_the_midtones() {
+        for mapping in ToneMapping::ALL {
+            assert_eq!(mapping.apply(0.0), 0.0, "{:?}", mapping);
+            assert!((mapping.apply(255.0) - 255.0).abs() < 0.01, "{:?}", mapping);
+        }
+        assert_eq!(ToneMapping::None.apply(100.5), 100.5);
+        assert!(ToneMapping::Reinhard.apply(100.0) > 120.0);
+        assert!(ToneMapping::Aces.apply(100.0) != 100.0);
+
+        let wall = Cube::new(Vec3::new(-50.0, -50.0, -21.0), Vec3::new(50.0, 50.0, -20.0), &gray());
+        let lights = [Light::new(Vec3::new(0.0, 5.0, 0.0), Color::new(255, 255, 255), 1.0, 30.0)];
+        let scene = scene(&[wall], &lights, Sky::Procedural, &Ambient::flat(Color::new(90, 120, 200)));
+        let center = |tone_mapping: ToneMapping| {
+            let settings = RenderSettings { width: 8, height: 8, tone_mapping, ..RenderSettings::default() };
+            let mut framebuffer = Framebuffer::new(8, 8);
+            render(&mut framebuffer, &scene, &settings);
+            Color::from_hex(framebuffer.buffer[4 * 8 + 4])
+        };
+        assert!(brightness(center(ToneMapping::Reinhard)) > brightness(center(ToneMapping::None)));
+    }
+
+    #[test]
+    fn importance_sampling_the_environment_beats_cosine_sampling_with_a_small_sun() {
+        // A dim HDR sky with a bright 4x4 texel sun about 45° up, which most cosine samples miss
+        let (width, height) = (64, 32);
+        let mut pixels = vec![[0.2f32; 3]; width * height];
+        for y in 6..10 {
+            pixels[y * width + 38..y * width + 42].fill([30.0; 3]);
+        }
+        let map = EnvironmentMap::from_pixels(width, height, pixels, 0.0);
+
+        // Every point faces up with nothing around it, so all of them get the same irradiance;
+        // only the per-point shift of the sample pattern changes
+        let irradiance_at = |i: usize, samples: u32, sampling: EnvSampling| {
+            let point = Vec3::new(i as Float * 0.37, 0.0, i as Float * 0.61);
+            environment_irradiance(&Hit::new(point, CubeFace::Top.normal(), 1.0, CubeFace::Top), &[], &map, samples, sampling)[0]
+        };
+        let reference = irradiance_at(0, 1 << 16, EnvSampling::Cosine);
+        assert!((irradiance_at(0, 1 << 16, EnvSampling::Importance) - reference).abs() < reference * 0.02, "both converge to the same value");
+
+        let rmse = |sampling: EnvSampling| {
+            let squared: f32 = (0..64).map(|i| (irradiance_at(i, 16, sampling) - reference).powi(2)).sum();
+            (squared / 64.0).sqrt()
+        };
+        let (cosine, importance) = (rmse(EnvSampling::Cosine), rmse(EnvSampling::Importance));
+        assert!(importance * 4.0 < cosine, "importance {} against cosine {} (reference {})", importance, cosine, reference);
+    }
+
+    #[test]
+    fn touching_cubes_neither_shadow_themselves_nor_leak_at_the_seam() {
+        // The cubes of scenes/regression_touching.toml and regression_far.toml: a floor of two slabs and
+        // a wall of two blocks standing on it, each pair touching at x = 0, at the origin and 500 units out
+        for offset in [Vec3::zeros(), Vec3::new(500.0, 0.0, -500.0)] {
+            let at = |x: Float, y: Float, z: Float| offset + Vec3::new(x, y, z);
+            let floor = [Cube::new(at(-2.0, -0.5, -2.0), at(0.0, 0.0, 2.0), &gray()), Cube::new(at(0.0, -0.5, -2.0), at(2.0, 0.0, 2.0), &gray())];
+            let split_wall = [Cube::new(at(-1.0, 0.0, -0.5), at(0.0, 1.0, 0.0), &gray()), Cube::new(at(0.0, 0.0, -0.5), at(1.0, 1.0, 0.0), &gray())];
+            let solid_wall = [Cube::new(at(-1.0, 0.0, -0.5), at(1.0, 1.0, 0.0), &gray())];
+            let with_floor = |wall: &[Cube]| [&floor[..], wall].concat();
+            let ambient = Ambient::flat(Color::new(40, 40, 40));
+
+            // A low light in front of the wall, so the floor behind it is in its shadow
+            let shade = |objects: &[Cube], shadows: bool, from: Vec3, direction: Vec3| {
+                let mut light = Light::new(at(0.0, 0.6, -3.0), Color::new(255, 255, 255), 3.0, 100.0);
+                light.cast_shadows = shadows;
+                cast_ray(&Ray::new(from, direction), &scene(objects, &[light], Sky::Procedural, &ambient), &RenderSettings::default(), &PixelSample::default(), 0)
+            };
+            let down = Vec3::new(0.0, -1.0, 0.0);
+            let toward_wall = Vec3::new(0.0, 0.0, 1.0);
+
+            for x in [-0.3, -1e-3, -1e-5, 0.0, 1e-5, 1e-3, 0.3] {
+                // No acne: lit faces next to the seams look the same with and without shadow rays
+                for z in [-1.5, -1.0] {
+                    let from = at(x, 1.0, z);
+                    assert_eq!(shade(&with_floor(&split_wall), true, from, down), shade(&with_floor(&split_wall), false, from, down), "floor at {:?}", from);
+                }
+                let from = at(x, 0.5, -2.0);
+                assert_eq!(shade(&with_floor(&split_wall), true, from, toward_wall), shade(&with_floor(&split_wall), false, from, toward_wall), "wall at {:?}", from);
+
+                // No leaks: behind the wall, shadow rays running along its seam are stopped as by one solid block
+                for z in [0.5, 1.0, 1.5] {
+                    let from = at(x, 1.0, z);
+                    let shadowed = shade(&with_floor(&split_wall), true, from, down);
+                    assert_eq!(shadowed, shade(&with_floor(&solid_wall), true, from, down), "floor at {:?}", from);
+                    assert!(brightness(shadowed) < brightness(shade(&with_floor(&split_wall), false, from, down)), "the wall shades {:?}", from);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn focus_gets_more_samples_unless_equalized() {
+        let material = gray();
+        let objects = [Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0), &material)];
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let settings = RenderSettings {
+            width: 96,
+            height: 96,
+            render_mode: RenderMode::Samples,
+            focus_radius: 0.5,
+            focus: Some([16.0, 16.0]),
+            ..RenderSettings::default()
+        };
+        let levels = |settings: &RenderSettings| {
+            let mut framebuffer = Framebuffer::new(96, 96);
+            let mut batches = Vec::new();
+            render_with_progress(&mut framebuffer, &scene_seen_from(&camera, &objects, &[], Sky::Procedural, &Ambient::flat(Color::new(0, 0, 0))), settings, &mut |_, done, total| {
+                batches.push((done, total));
+            });
+            assert_eq!(batches.last(), Some(&(9, 9)));
+            let image = framebuffer.to_image();
+            (image.get_pixel(16, 16)[0], image.get_pixel(80, 80)[0])
+        };
+        // One sample away from the focus and four on it: a quarter of white
+        assert_eq!(levels(&settings), (255, 63));
+        assert_eq!(levels(&RenderSettings { equalize_samples: true, ..settings }), (63, 63));
+    }
+
+    #[test]
+    fn secondary_upsampling_skips_neighbors_on_other_surfaces() {
+        let surface = |normal: Vec3, distance: Float, secondary: f32, traced: bool| PixelShading {
+            secondary: [secondary; 3],
+            secondary_weight: 0.5,
+            distance,
+            normal,
+            ..PixelShading::new(1, traced)
+        };
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let side = Vec3::new(1.0, 0.0, 0.0);
+        // A row of three pixels: the middle one only has direct light
+        let row = [surface(up, 5.0, 100.0, true), surface(up, 5.0, 0.0, false), surface(side, 5.0, 200.0, true)];
+        let upsampled = upsample_secondary(&row, 3, 1, 1, 0).unwrap();
+        assert!(upsampled.iter().all(|&value| (value - 100.0).abs() < 1e-3), "the wall leaked in: {:?}", upsampled);
+
+        // Far behind along the same normal is another surface too
+        let row = [surface(up, 50.0, 100.0, true), surface(up, 5.0, 0.0, false), surface(side, 5.0, 200.0, true)];
+        assert_eq!(upsample_secondary(&row, 3, 1, 1, 0), None);
+    }
+
+    #[test]
+    fn half_res_secondary_stays_close_to_the_full_render() {
+        let mirror = Material::new(Color::new(90, 90, 90), 50.0, [0.5, 0.0, 0.5, 0.0], 0.0);
+        let objects = [Cube::new(Vec3::new(-4.0, -1.0, -4.0), Vec3::new(4.0, 0.0, 4.0), &mirror)];
+        let camera = Camera::new(Vec3::new(0.0, 2.0, 5.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let render_with = |half_res_secondary: bool| {
+            let settings = RenderSettings { width: 32, height: 32, half_res_secondary, ..RenderSettings::default() };
+            let mut framebuffer = Framebuffer::new(32, 32);
+            render(&mut framebuffer, &scene_seen_from(&camera, &objects, &[], Sky::Procedural, &Ambient::flat(Color::new(60, 60, 60))), &settings);
+            framebuffer.to_image()
+        };
+        let (full, half) = (render_with(false), render_with(true));
+        for (a, b) in full.pixels().zip(half.pixels()) {
+            assert!((0..3).all(|c| a[c].abs_diff(b[c]) <= 10), "{:?} vs {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn alpha_covers_primary_hits_and_keeps_reflected_sky_opaque() {
+        // A mirror fills the left half of the image and part of the middle column; the rest is sky
+        let mirror = Material::new(Color::new(90, 90, 90), 50.0, [0.2, 0.0, 0.8, 0.0], 0.0);
+        let objects = [Cube::new(Vec3::new(-50.0, -50.0, -1.0), Vec3::new(0.18, 50.0, 0.0), &mirror)];
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let scene = scene_seen_from(&camera, &objects, &[], Sky::Procedural, &Ambient::flat(Color::new(60, 60, 60)));
+        let render_with = |alpha: AlphaMode| {
+            let settings = RenderSettings { width: 16, height: 16, samples_per_pixel: 16, alpha, ..RenderSettings::default() };
+            let mut framebuffer = Framebuffer::new(16, 16);
+            render(&mut framebuffer, &scene, &settings);
+            framebuffer
+        };
+        let straight = render_with(AlphaMode::Straight);
+        let premultiplied = render_with(AlphaMode::Premultiplied);
+        let alpha = straight.alpha.clone().expect("no alpha channel");
+        assert_eq!(premultiplied.alpha.as_ref(), Some(&alpha));
+        assert!(render_with(AlphaMode::Off).alpha.is_none());
+
+        let row = 8 * 16;
+        assert_eq!(alpha[row + 2], 255, "the mirror only reflects the sky but is opaque");
+        assert_eq!(alpha[row + 14], 0);
+        assert_eq!(premultiplied.buffer[row + 14], 0, "uncovered pixels are black when premultiplied");
+        let edge = (0..16).find(|&x| alpha[row + x] > 0 && alpha[row + x] < 255).expect("no partly covered pixel at 16 spp");
+
+        // Straight alpha keeps the mirror's color with no sky fringe; premultiplied scales it by the coverage
+        let channels = |pixel: u32| [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8];
+        let inside = channels(straight.buffer[row + edge - 1]);
+        let straight_edge = channels(straight.buffer[row + edge]);
+        let premultiplied_edge = channels(premultiplied.buffer[row + edge]);
+        for c in 0..3 {
+            assert!(inside[c].abs_diff(straight_edge[c]) <= 12, "{:?} vs {:?}", inside, straight_edge);
+            let expected = straight_edge[c] as f32 * alpha[row + edge] as f32 / 255.0;
+            assert!((premultiplied_edge[c] as f32 - expected).abs() <= 2.0, "{:?} vs {:?} at alpha {}", premultiplied_edge, straight_edge, alpha[row + edge]);
+        }
+    }
+
+    #[test]
+    fn checkerboard_keeps_matching_history_and_averages_disocclusions() {
+        // 3x3 image traced on even x + y; the odd pixels still hold the previous frame
+        let gray = 0x808080;
+        let mut pixels = vec![gray; 9];
+        pixels[1] = 0x848484; // Close to its neighbors: kept
+        pixels[3] = 0xFF0000; // Stale red that no neighbor has: replaced by their average
+        pixels[0] = 0x404040;
+        let filled = fill_checkerboard(&pixels, 3, 3, 0);
+        assert_eq!(filled[1], 0x848484);
+        assert_eq!(filled[3], (0x40 + 0x80 + 0x80) / 3 * 0x010101);
+        assert_eq!(filled[0], 0x404040, "traced pixels are never touched");
+
+        // On the next frame the other half is traced and pixel 0 becomes a reconstructed one
+        let mut pixels = vec![gray; 9];
+        pixels[0] = 0x000000;
+        assert_eq!(fill_checkerboard(&pixels, 3, 3, 1)[0], gray);
+    }
+
+    #[test]
+    fn denoise_smooths_a_surface_but_keeps_edges_and_the_sky() {
+        // 8x4 image: a floor facing up with ±20 levels of noise, a wall facing +x right next to it at
+        // the same distance, and a column of sky
+        let (width, height) = (8, 4);
+        let floor = PixelShading { distance: 3.0, normal: Vec3::new(0.0, 1.0, 0.0), ..PixelShading::new(1, true) };
+        let wall = PixelShading { normal: Vec3::new(1.0, 0.0, 0.0), ..floor };
+        let sky = PixelShading::new(1, true);
+        let (pixels, shadings): (Vec<u32>, Vec<PixelShading>) = (0..width * height)
+            .map(|index| match (index % width, index / width) {
+                (x @ 0..=3, y) => (if (x + y) % 2 == 0 { 0x505050 } else { 0x787878 }, floor),
+                (4..=6, _) => (0xC8C8C8, wall),
+                _ => (0x4080FF, sky),
+            })
+            .unzip();
+
+        let denoised = denoise_pixels(&pixels, &shadings, width, height);
+        for y in 0..height {
+            for x in 0..4 {
+                let level = (denoised[y * width + x] & 0xFF) as i32;
+                assert!((level - 100).abs() < 16, "floor pixel ({}, {}) is still at {}", x, y, level);
+            }
+            for x in 4..7 {
+                assert_eq!(denoised[y * width + x], 0xC8C8C8, "the floor leaked into the wall at ({}, {})", x, y);
+            }
+            assert_eq!(denoised[y * width + 7], 0x4080FF, "the sky is left alone");
+        }
+    }
+
+    // Color seen straight down -z from (x, 0, 5), lit only by a white ambient light at full strength
+    fn seen_ahead(x: Float, objects: &[Cube]) -> Color {
+        let ray = Ray::new(Vec3::new(x, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let settings = RenderSettings { ambient_strength: 1.0, ..RenderSettings::default() };
+        cast_ray(&ray, &scene(objects, &[], Sky::Procedural, &Ambient::flat(Color::new(255, 255, 255))), &settings, &PixelSample::default(), 0)
+    }
+
+    #[test]
+    fn stained_glass_tints_what_is_behind_it() {
+        // A clear pane that doesn't bend light in front of a gray wall
+        let wall = Cube::new(Vec3::new(-2.0, -2.0, -3.0), Vec3::new(2.0, 2.0, -2.0), &gray());
+        let pane = |tint: Color| {
+            let glass = Material::new(Color::new(255, 255, 255), 50.0, [0.0, 0.0, 0.0, 1.0], 1.0).with_transmission_tint(tint);
+            Cube::new(Vec3::new(-1.0, -1.0, -0.1), Vec3::new(1.0, 1.0, 0.1), &glass)
+        };
+        let clear = seen_ahead(0.0, &[pane(Color::new(255, 255, 255)), wall.clone()]);
+        assert!(clear.r > 20 && clear.g > 20 && clear.b > 20, "{:?}", clear);
+        assert_eq!(clear, seen_ahead(0.0, &[wall.clone()]), "clear glass shows the wall as it is");
+        assert_eq!(seen_ahead(0.0, &[pane(Color::new(255, 0, 0)), wall.clone()]), Color::new(clear.r, 0, 0));
+        assert_eq!(seen_ahead(0.0, &[pane(Color::new(0, 255, 255)), wall]), Color::new(0, clear.g, clear.b));
+    }
+
+    #[test]
+    fn portal_pairs_show_each_other_s_side() {
+        // Two black portals 10 units apart, each in front of a wall of its own color
+        let portal = |x: Float, offset: Float| {
+            let material = Material::black().with_portal(Portal { target_offset: Vec3::new(offset, 0.0, 0.0), rotation: 0.0 });
+            Cube::new(Vec3::new(x - 0.5, -0.5, -0.1), Vec3::new(x + 0.5, 0.5, 0.1), &material)
+        };
+        let wall = |x: Float, color: Color| {
+            Cube::new(Vec3::new(x - 2.0, -2.0, -3.0), Vec3::new(x + 2.0, 2.0, -2.0), &Material::new(color, 0.0, [1.0, 0.0, 0.0, 0.0], 1.0))
+        };
+        let (green, red) = (wall(0.0, Color::new(0, 200, 0)), wall(10.0, Color::new(200, 0, 0)));
+        let objects = [portal(0.0, 10.0), portal(10.0, -10.0), green.clone(), red.clone()];
+
+        // Each portal shows the wall behind the other one, dimmed by its (here black) tint
+        let through = |wall: &Cube, x: Float| seen_ahead(x, &[wall.clone()]).mul_scalar(1.0 - PORTAL_TINT);
+        assert_eq!(seen_ahead(0.0, &objects), through(&red, 10.0));
+        assert_eq!(seen_ahead(10.0, &objects), through(&green, 0.0));
+        assert!(seen_ahead(0.0, &objects).r > 100);
+        // Past the portals the walls are seen as they are
+        assert_eq!(seen_ahead(1.5, &objects), seen_ahead(1.5, &[green]));
+    }
+
+    #[test]
+    fn side_by_side_with_an_odd_width_clears_the_spare_column() {
+        let settings = RenderSettings { width: 9, height: 4, stereo: StereoMode::SideBySide, ..RenderSettings::default() };
+        let mut framebuffer = Framebuffer::new(9, 4);
+        framebuffer.buffer.fill(0x123456);
+        render(&mut framebuffer, &scene(&[], &[], Sky::Procedural, &Ambient::flat(Color::new(0, 0, 0))), &settings);
+        for y in 0..4 {
+            assert_eq!(framebuffer.buffer[y * 9 + 8], 0, "row {} kept the old pixel", y);
+            assert!(framebuffer.buffer[y * 9..y * 9 + 8].iter().all(|&pixel| pixel != 0x123456), "row {} wasn't rendered", y);
+        }
+    }
+
+    #[test]
+    fn outlines_mark_the_edge_shared_by_coplanar_blocks() {
+        // Two blocks side by side seen from straight above; their shared edge runs along x = 0
+        let material = gray();
+        let objects = primitives(&[
+            Cube::new(Vec3::new(-0.5, -0.5, -0.25), Vec3::new(0.0, 0.0, 0.25), &material),
+            Cube::new(Vec3::new(0.0, -0.5, -0.25), Vec3::new(0.5, 0.0, 0.25), &material),
+        ]);
+        let outlined = |x: Float, z: Float| is_on_block_edge(&Vec3::new(x, 2.0, z), &Vec3::new(0.0, -1.0, 0.0), &objects, 1.0);
+        assert!(outlined(0.01, 0.0), "the shared edge should be outlined");
+        assert!(outlined(-0.25, 0.24), "the outer edge should be outlined");
+        assert!(!outlined(0.25, 0.0) && !outlined(-0.1, 0.1), "face interiors should stay clear");
+        assert!(!outlined(3.0, 0.0), "misses are never outlined");
+    }
+
+    #[test]
+    fn toon_mode_bands_light_and_outlines_silhouettes() {
+        assert_eq!([0.0, 0.1, 0.34, 0.9].map(|amount| toon_band(amount, 3)), [0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0]);
+
+        let material = gray();
+        let objects = [Cube::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0), &material)];
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let lights = [Light::new(Vec3::new(0.0, 0.0, 5.0), Color::new(255, 255, 255), 1.0, 10.0)];
+        let red = Color::new(255, 0, 0);
+        let settings = RenderSettings { width: 40, height: 40, render_mode: RenderMode::Toon, toon_outline_color: red, ..RenderSettings::default() };
+        let mut framebuffer = Framebuffer::new(40, 40);
+        render(&mut framebuffer, &scene_seen_from(&camera, &objects, &lights, Sky::Procedural, &Ambient::flat(Color::new(0, 0, 0))), &settings);
+
+        let image = framebuffer.to_image();
+        let is_red = |x: u32, y: u32| image.get_pixel(x, y).0 == [255, 0, 0];
+        // The cube covers x = 11 to 29; its left edge is outlined, its face and the sky aren't
+        let edge = (0..40).find(|&x| is_red(x, 20)).expect("the silhouette should be outlined");
+        assert!((10..=12).contains(&edge), "outline at x = {}", edge);
+        assert!(!is_red(20, 20) && !is_red(1, 20));
+    }
+
+    #[test]
+    fn snow_covers_only_tops_open_to_the_sky() {
+        // A ground slab with a roof over its right half
+        let mut material = gray();
+        material.snow_cover = 1.0;
+        let objects = primitives(&[
+            Cube::new(Vec3::new(-1.0, -0.5, -1.0), Vec3::new(1.0, 0.0, 1.0), &material),
+            Cube::new(Vec3::new(0.0, 1.0, -1.0), Vec3::new(1.0, 1.2, 1.0), &material),
+        ]);
+        let snowy = |origin: Vec3, direction: Vec3| {
+            let (_, hit) = nearest_hit(&origin, &direction, &objects).unwrap();
+            is_snow_covered(&hit, &material, &objects)
+        };
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        assert!(snowy(Vec3::new(-0.5, 2.0, 0.1), down), "open ground should be covered");
+        assert!(snowy(Vec3::new(0.5, 2.0, 0.1), down), "the roof should be covered");
+        assert!(!snowy(Vec3::new(0.5, 0.5, 0.1), down), "ground under the roof should stay clear");
+        assert!(!snowy(Vec3::new(-2.0, -0.25, 0.1), Vec3::new(1.0, 0.0, 0.0)), "side faces should stay clear");
+
+        // Partial coverage leaves some texels bare, roughly in proportion
+        let mut partial = gray();
+        partial.snow_cover = 0.5;
+        let ground = primitives(&[Cube::new(Vec3::new(-1.0, -0.5, -1.0), Vec3::new(1.0, 0.0, 1.0), &partial)]);
+        let covered = (0..64)
+            .filter(|&i| {
+                let origin = Vec3::new(-0.99 + i as Float * TEXEL_SIZE, 2.0, 0.1);
+                is_snow_covered(&ground[0].ray_intersect(&Ray::new(origin, down)).unwrap(), &partial, &ground)
+            })
+            .count();
+        assert!((20..=44).contains(&covered), "{} of 64 texels covered", covered);
+    }
+
+    #[test]
+    fn rain_wets_open_tops_as_it_starts() {
+        // A ground slab with a roof over its right half; the rain starts at 2 s and takes 4 s to soak it
+        let mut material = gray();
+        material.rain = Some(Rain { wetness: 0.8, start: 2.0, ramp: 4.0 });
+        let objects = [
+            Cube::new(Vec3::new(-1.0, -0.5, -1.0), Vec3::new(1.0, 0.0, 1.0), &material),
+            Cube::new(Vec3::new(0.0, 1.0, -1.0), Vec3::new(1.0, 1.2, 1.0), &material),
+        ];
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let open = objects[0].ray_intersect(&Ray::new(Vec3::new(-0.5, 2.0, 0.1), down)).unwrap();
+        let sheltered = objects[0].ray_intersect(&Ray::new(Vec3::new(0.5, 0.5, 0.1), down)).unwrap();
+        let solid = primitives(&objects);
+        assert_eq!(wetness(&open, &material, &solid, 1.0), 0.0);
+        assert!((wetness(&open, &material, &solid, 4.0) - 0.4).abs() < 1e-6);
+        assert!((wetness(&open, &material, &solid, 60.0) - 0.8).abs() < 1e-6);
+        assert_eq!(wetness(&sheltered, &material, &solid, 60.0), 0.0);
+
+        // Seen at a grazing angle the wet ground picks up the sky; the dry ground under the roof doesn't change
+        let color_at = |origin: Vec3, direction: Vec3, time: f32| {
+            let settings = RenderSettings { time, ..RenderSettings::default() };
+            let sky = Sky::Gradient { top: Color::new(150, 180, 255), bottom: Color::new(150, 180, 255) };
+            cast_ray(&Ray::new(origin, direction.normalize()), &scene(&objects, &[], sky, &Ambient::flat(Color::new(60, 60, 60))), &settings, &PixelSample::default(), 0)
+        };
+        let grazing = Vec3::new(1.0, -0.1, 0.0);
+        let (dry, wet) = (color_at(Vec3::new(-1.5, 0.1, 0.1), grazing, 0.0), color_at(Vec3::new(-1.5, 0.1, 0.1), grazing, 60.0));
+        assert!(wet.intensity() > dry.intensity() + 0.1, "wet {:?} should reflect the sky over dry {:?}", wet, dry);
+        let under_roof = Vec3::new(0.5, 0.5, 0.1);
+        assert_eq!(color_at(under_roof, down, 0.0), color_at(under_roof, down, 60.0));
+    }
+
+    #[test]
+    fn stars_are_fixed_in_the_world_and_show_in_reflections() {
+        let cycle = DayNightCycle::at(0.75);
+        let lights = [cycle.sun_light()];
+        let night = Ambient::flat(cycle.get_current_color()).with_night(cycle.night_amount());
+        let day = Ambient::flat(cycle.get_current_color());
+        let sky_at = |origin: Vec3, direction: Vec3, ambient: &Ambient| {
+            cast_ray(&Ray::new(origin, direction), &scene(&[], &lights, Sky::Procedural, ambient), &RenderSettings::default(), &PixelSample::default(), 0)
+        };
+
+        // Some upward directions show a star at night; the same direction seen from anywhere else still does
+        let star = (0..2000)
+            .map(|i| Vec3::new((i as Float * 0.37).sin(), 0.5 + (i % 7) as Float * 0.1, (i as Float * 0.37).cos()).normalize())
+            .find(|direction| sky_at(Vec3::zeros(), *direction, &night).intensity() > sky_at(Vec3::zeros(), *direction, &day).intensity() + 0.05)
+            .expect("the night sky has stars");
+        assert_eq!(sky_at(Vec3::zeros(), star, &night), sky_at(Vec3::new(30.0, 2.0, -7.0), star, &night));
+        assert_eq!(sky_at(Vec3::zeros(), -star, &night), night.sky.mul_scalar(0.5), "only the plain night sky below the horizon");
+
+        // A mirror floor reflects the same star
+        let mut mirror = gray();
+        mirror.properties = [0.0, 0.0, 1.0, 0.0];
+        let floor = [Cube::new(Vec3::new(-5.0, -1.0, -5.0), Vec3::new(5.0, 0.0, 5.0), &mirror)];
+        let reflected_at = |ambient: &Ambient| {
+            let direction = Vec3::new(star.x, -star.y, star.z);
+            cast_ray(&Ray::new(Vec3::new(0.0, 0.0, 0.0) - direction, direction), &scene(&floor, &lights, Sky::Procedural, ambient), &RenderSettings::default(), &PixelSample::default(), 0)
+        };
+        assert!(reflected_at(&night).intensity() > reflected_at(&day).intensity() + 0.05);
+    }
+
+    #[test]
+    fn block_variation_is_fixed_per_block_and_spread_over_a_field() {
+        // The eight variants are all different mappings of the same texel
+        let variants: HashSet<(u32, u32)> = (0..8).map(|variant| {
+            let (u, v) = vary_uv(0.2, 0.1, variant);
+            ((u * 100.0).round() as u32, (v * 100.0).round() as u32)
+        }).collect();
+        assert_eq!(variants.len(), 8);
+
+        // One big cube covering a 16x16 field of blocks: points of the same block share the variant,
+        // and the field uses all of them
+        let field = Cube::new(Vec3::new(0.0, -0.5, 0.0), Vec3::new(8.0, 0.0, 8.0), &gray());
+        let hash_at = |x: Float, z: Float| block_hash(&field.ray_intersect(&Ray::new(Vec3::new(x, 1.0, z), Vec3::new(0.0, -1.0, 0.0))).unwrap());
+        assert_eq!(hash_at(1.05, 2.1), hash_at(1.45, 2.4));
+        let used: HashSet<u32> = (0..16).flat_map(|i| (0..16).map(move |j| (i, j)))
+            .map(|(i, j)| (hash_at(i as Float * 0.5 + 0.25, j as Float * 0.5 + 0.25).0 * 8.0) as u32)
+            .collect();
+        assert_eq!(used.len(), 8);
+
+        // The tint stays within the jitter
+        let tinted = jitter_tint(Color::new(100, 100, 100), (0.99, 0.5), 0.1);
+        assert!((90..=110).contains(&tinted.g), "{:?}", tinted);
+    }
+
+    #[test]
+    fn smooth_lighting_darkens_corners_next_to_blocks() {
+        // A ground made of one big cube with a block on it, and a second block next to it forming an L
+        let material = gray();
+        let ground = || Cube::new(Vec3::new(-2.0, -0.5, -2.0), Vec3::new(2.0, 0.0, 2.0), &material);
+        let block = || Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.5, 0.5, 0.5), &material);
+        let second = Cube::new(Vec3::new(0.5, 0.0, -0.5), Vec3::new(1.0, 0.5, 0.0), &material);
+        let light_at = |objects: &[Primitive], x: Float, z: Float| {
+            let (object, hit) = nearest_hit(&Vec3::new(x, 2.0, z), &Vec3::new(0.0, -1.0, 0.0), objects).unwrap();
+            smooth_lighting(&hit, object, objects)
+        };
+        let single = primitives(&[ground(), block()]);
+        assert_eq!(light_at(&single, -1.25, -1.25), 1.0, "open ground stays lit");
+        // Right of the block its side darkens both near corners by one step, fading to nothing across the face
+        assert!((light_at(&single, 0.51, 0.3) - 0.8).abs() < 0.01, "{}", light_at(&single, 0.51, 0.3));
+        assert!((light_at(&single, 0.75, 0.3) - 0.9).abs() < 1e-3, "{}", light_at(&single, 0.75, 0.3));
+        // Diagonal neighbours count too, but only at their own corner
+        assert!((light_at(&single, 0.51, 0.75) - 0.9).abs() < 0.01, "{}", light_at(&single, 0.51, 0.75));
+        // The inside corner of the L has both sides covered
+        let l_shape = primitives(&[ground(), block(), second]);
+        assert!((light_at(&l_shape, 0.51, 0.01) - 0.4).abs() < 0.02, "{}", light_at(&l_shape, 0.51, 0.01));
+
+        // Off the 0.5 grid there's nothing to look up
+        let loose = primitives(&[Cube::new(Vec3::new(0.1, -0.5, 0.1), Vec3::new(0.4, 0.0, 0.4), &material)]);
+        let (object, hit) = nearest_hit(&Vec3::new(0.2, 2.0, 0.2), &Vec3::new(0.0, -1.0, 0.0), &loose).unwrap();
+        assert_eq!(smooth_lighting(&hit, object, &loose), 1.0);
+    }
+
+    #[test]
+    fn dithering_breaks_up_gradient_bands() {
+        // A shallow horizontal gradient: 4 levels over 256 columns, so plain truncation gives bands 64 columns wide
+        let (width, height) = (256u32, 16u32);
+        let longest_run = |dither: bool| {
+            let columns: Vec<Vec<u8>> = (0..width)
+                .map(|x| {
+                    let value = 100.0 + 4.0 * x as f32 / width as f32;
+                    (0..height).map(|y| quantize(value, if dither { dither_offset(x, y) } else { 0.0 })).collect()
+                })
+                .collect();
+            let (mut longest, mut run) = (1, 1);
+            for pair in columns.windows(2) {
+                run = if pair[0] == pair[1] { run + 1 } else { 1 };
+                longest = longest.max(run);
+            }
+            longest
+        };
+        assert_eq!(longest_run(false), 64);
+        assert!(longest_run(true) <= 2, "dithered gradient still has runs of {} identical columns", longest_run(true));
+
+        // The noise depends only on the pixel, so progressive frames don't shimmer, and it keeps the average
+        assert_eq!(dither_offset(17, 5), dither_offset(17, 5));
+        let mean = (0..64).flat_map(|x| (0..64).map(move |y| quantize(100.3, dither_offset(x, y)) as f32)).sum::<f32>() / 4096.0;
+        assert!((mean - 100.3).abs() < 0.05, "dithered mean {}", mean);
+    }
+
+    fn unit_vector() -> impl Strategy<Value = Vec3> {
+        (-1.0f64..1.0, -1.0f64..1.0, -1.0f64..1.0)
+            .prop_map(|(x, y, z)| Vec3::new(x as Float, y as Float, z as Float))
+            .prop_filter("too short to normalize", |v| v.magnitude() > 0.1)
+            .prop_map(|v| v.normalize())
+    }
+
+    fn any_face() -> impl Strategy<Value = CubeFace> {
+        (0..6usize).prop_map(|index| CubeFace::ALL[index])
+    }
+
+    proptest! {
+        #[test]
+        fn reflection_keeps_the_length_and_mirrors_the_angle(incident in unit_vector(), normal in unit_vector(), length in 0.1f64..10.0) {
+            let incident = incident * length as Float;
+            let reflected = reflect(&incident, &normal);
+            let epsilon = 1e-3 * length as Float;
+            prop_assert!((reflected.magnitude() - incident.magnitude()).abs() < epsilon);
+            // Same angle on the other side of the surface, and the change is all along the normal
+            prop_assert!((reflected.dot(&normal) + incident.dot(&normal)).abs() < epsilon);
+            prop_assert!((reflected - incident).cross(&normal).magnitude() < epsilon);
+        }
+
+        #[test]
+        fn refraction_reflects_exactly_when_snell_has_no_solution(incident in unit_vector(), normal in unit_vector(), eta_t in 1.0f64..2.5) {
+            let eta_t = eta_t as Float;
+            // `refract` flips the normal and the ratio when the ray runs along the normal
+            let along_normal = incident.dot(&normal) > 0.0;
+            let (eta, n_normal) = if along_normal { (1.0 / eta_t, -normal) } else { (eta_t, normal) };
+            let sin_incident = incident.cross(&normal).magnitude();
+            let sin_refracted = eta * sin_incident;
+            prop_assume!((sin_refracted - 1.0).abs() > 1e-3);
+
+            let refracted = refract(&incident, &normal, eta_t);
+            if sin_refracted > 1.0 {
+                prop_assert_eq!(refracted, reflect(&incident, &n_normal));
+            } else {
+                // Snell's law, and the ray goes on through the surface instead of bouncing back
+                let refracted = refracted.normalize();
+                prop_assert!((refracted.cross(&n_normal).magnitude() - sin_refracted).abs() < 1e-3, "sin {} != {}", refracted.cross(&n_normal).magnitude(), sin_refracted);
+                prop_assert!(refracted.dot(&n_normal).signum() == incident.dot(&n_normal).signum());
+            }
+        }
+
+        #[test]
+        fn offset_origins_leave_the_surface_on_the_side_of_the_ray(point in (-1.0e4f64..1.0e4, -1.0e4f64..1.0e4, -1.0e4f64..1.0e4), face in any_face(), t in 0.0f64..1.0e3, direction in unit_vector()) {
+            let point = Vec3::new(point.0 as Float, point.1 as Float, point.2 as Float);
+            let hit = Hit::new(point, face.normal(), t as Float, face);
+            let origin = offset_origin(&hit, &direction);
+            let axis = face.normal().iamax();
+            // Moved off the plane of the face, towards where the ray is going
+            let moved = (origin[axis] - point[axis]) * face.normal()[axis];
+            if direction.dot(&face.normal()) < 0.0 {
+                prop_assert!(moved < 0.0, "{:?} stayed on or in front of the face", origin);
+            } else {
+                prop_assert!(moved > 0.0, "{:?} stayed on or behind the face", origin);
+            }
+            // Only along the normal
+            prop_assert!((0..3).filter(|&other| other != axis).all(|other| origin[other] == point[other]));
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, RwLock};
+use crate::color::Color;
+use crate::error::RayTracerError;
+use crate::texture::Texture;
+
+// Paquete activo, elegido con --resource-pack; las texturas de bloques se toman de aquí cuando lo tiene
+static ACTIVE: RwLock<Option<Arc<ResourcePack>>> = RwLock::new(None);
+
+pub fn set_active(pack: Option<ResourcePack>) {
+    *ACTIVE.write().unwrap_or_else(|e| e.into_inner()) = pack.map(Arc::new);
+}
+
+pub fn active() -> Option<Arc<ResourcePack>> {
+    ACTIVE.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+// Nombres de las texturas antes de la 1.13, cuando además la carpeta se llamaba "blocks"
+const LEGACY_NAMES: [(&str, &str); 5] = [
+    ("grass_block_top", "grass_top"),
+    ("grass_block_side", "grass_side"),
+    ("oak_planks", "planks_oak"),
+    ("oak_log", "log_oak"),
+    ("oak_leaves", "leaves_oak"),
+];
+
+// El juego tiñe estas texturas grises con el color del bioma; se usa el de las llanuras
+const BIOME_TINTS: [(&str, Color); 2] = [
+    ("grass_block_top", Color::new(145, 189, 89)),
+    ("oak_leaves", Color::new(119, 171, 47)),
+];
+
+// Tamaño máximo de una entrada descomprimida: sobra para una textura de MAX_TEXTURE_SIZE de lado y
+// corta las bombas zip, que declaran poco y se inflan sin fin
+pub const MAX_ENTRY_BYTES: usize = 64 << 20;
+
+struct Entry {
+    offset: usize, // Cabecera local del archivo dentro del zip
+    compressed_size: usize,
+    size: usize,   // Descomprimida, según el directorio central
+    method: u16,   // 0 = sin comprimir, 8 = deflate
+}
+
+// Un resource pack de Minecraft en zip. Solo se lee el directorio central al abrirlo; cada
+// textura se descomprime cuando se pide.
+pub struct ResourcePack {
+    data: Vec<u8>,
+    // Ruta desde "assets/minecraft/" en adelante, aunque el zip tenga una carpeta raíz por encima
+    entries: HashMap<String, Entry>,
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?) as usize)
+}
+
+impl ResourcePack {
+    pub fn open(path: &str) -> Result<Self, RayTracerError> {
+        let data = std::fs::read(path).map_err(|e| RayTracerError::io(path, e))?;
+        Self::from_bytes(data).map_err(|e| RayTracerError::Asset(format!("{}: {}", path, e)))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, String> {
+        let invalid = || "not a zip file (or a zip64 archive, which isn't supported)".to_string();
+
+        // El registro de fin del directorio central está al final, seguido de un comentario opcional
+        let search_start = data.len().saturating_sub(22 + u16::MAX as usize);
+        let end = (search_start..data.len().saturating_sub(21)).rev()
+            .find(|&at| u32_at(&data, at) == Some(0x0605_4b50))
+            .ok_or_else(invalid)?;
+        let count = u16_at(&data, end + 10).ok_or_else(invalid)?;
+        let mut at = u32_at(&data, end + 16).ok_or_else(invalid)?;
+
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            if u32_at(&data, at) != Some(0x0201_4b50) {
+                return Err(invalid());
+            }
+            let field = |offset: usize| u16_at(&data, at + offset).ok_or_else(invalid);
+            let (method, name_length, extra_length, comment_length) = (field(10)?, field(28)? as usize, field(30)? as usize, field(32)? as usize);
+            let compressed_size = u32_at(&data, at + 20).ok_or_else(invalid)?;
+            let size = u32_at(&data, at + 24).ok_or_else(invalid)?;
+            let offset = u32_at(&data, at + 42).ok_or_else(invalid)?;
+            let name = data.get(at + 46..at + 46 + name_length).ok_or_else(invalid)?;
+            let name = String::from_utf8_lossy(name).replace('\\', "/");
+            if let Some(start) = name.find("assets/minecraft/") {
+                entries.insert(name[start + "assets/minecraft/".len()..].to_string(), Entry { offset, compressed_size, size, method });
+            }
+            at += 46 + name_length + extra_length + comment_length;
+        }
+        Ok(ResourcePack { data, entries })
+    }
+
+    fn read(&self, name: &str) -> Result<Vec<u8>, String> {
+        let entry = &self.entries[name];
+        let invalid = || format!("{} has a corrupt header", name);
+        if u32_at(&self.data, entry.offset) != Some(0x0403_4b50) {
+            return Err(invalid());
+        }
+        let name_length = u16_at(&self.data, entry.offset + 26).ok_or_else(invalid)? as usize;
+        let extra_length = u16_at(&self.data, entry.offset + 28).ok_or_else(invalid)? as usize;
+        let start = entry.offset + 30 + name_length + extra_length;
+        let compressed = self.data.get(start..start + entry.compressed_size).ok_or_else(invalid)?;
+        if entry.size > MAX_ENTRY_BYTES {
+            return Err(format!("{} is {} bytes unpacked, more than the {} allowed", name, entry.size, MAX_ENTRY_BYTES));
+        }
+        match entry.method {
+            0 => Ok(compressed.to_vec()),
+            8 => {
+                // Un byte más de lo declarado basta para saber que miente, sin inflar el resto
+                let mut bytes = Vec::with_capacity(entry.size);
+                flate2::read::DeflateDecoder::new(compressed).take(entry.size as u64 + 1).read_to_end(&mut bytes)
+                    .map_err(|e| format!("Failed to decompress {}: {}", name, e))?;
+                if bytes.len() != entry.size {
+                    return Err(format!("{} unpacks to a different size than its header says", name));
+                }
+                Ok(bytes)
+            }
+            method => Err(format!("{} uses unsupported compression method {}", name, method)),
+        }
+    }
+
+    // Textura de un bloque por su nombre (stone, oak_planks, ...), buscando en textures/block y en
+    // la carpeta textures/blocks de los paquetes antiguos. None si el paquete no la incluye.
+    pub fn block_texture(&self, block: &str) -> Option<Result<Texture, String>> {
+        let legacy = LEGACY_NAMES.iter().find(|(name, _)| *name == block).map_or(block, |(_, legacy)| legacy);
+        let path = [
+            format!("textures/block/{}.png", block),
+            format!("textures/blocks/{}.png", block),
+            format!("textures/blocks/{}.png", legacy),
+        ].into_iter().find(|path| self.entries.contains_key(path))?;
+
+        Some(self.read(&path).and_then(|bytes| {
+            let mut texture = Texture::from_bytes(&bytes).map_err(|e| format!("{}: {}", path, e))?;
+            // Las texturas animadas son una tira vertical de cuadros; se usa el primero
+            if texture.height > texture.width && texture.height % texture.width == 0 {
+                texture.pixels.truncate((texture.width * texture.width) as usize);
+                texture.height = texture.width;
+            }
+            if let Some((_, tint)) = BIOME_TINTS.iter().find(|(name, _)| *name == block) {
+                texture.pixels.iter_mut().for_each(|pixel| *pixel = pixel.mul(tint));
+            }
+            Ok(texture)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // Zip con una sola entrada comprimida con deflate
+    fn zip_with(name: &str, contents: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(contents).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut zip = Vec::new();
+        let header = |zip: &mut Vec<u8>, signature: u32, central: bool| {
+            zip.extend_from_slice(&signature.to_le_bytes());
+            if central {
+                zip.extend_from_slice(&20u16.to_le_bytes()); // Versión que lo creó
+            }
+            zip.extend_from_slice(&[20, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0]); // Versión, flags, método, fecha, CRC
+            zip.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            zip.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            zip.extend_from_slice(&0u16.to_le_bytes());
+            if central {
+                zip.extend_from_slice(&[0; 14]); // Comentario, disco, atributos y desplazamiento 0
+            }
+            zip.extend_from_slice(name.as_bytes());
+        };
+        header(&mut zip, 0x0403_4b50, false);
+        zip.extend_from_slice(&compressed);
+        let central_offset = zip.len();
+        header(&mut zip, 0x0201_4b50, true);
+        let central_size = zip.len() - central_offset;
+        zip.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        zip.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+        zip.extend_from_slice(&(central_size as u32).to_le_bytes());
+        zip.extend_from_slice(&(central_offset as u32).to_le_bytes());
+        zip.extend_from_slice(&0u16.to_le_bytes());
+        zip
+    }
+
+    #[test]
+    fn finds_legacy_block_textures_under_a_root_folder() {
+        let image = image::RgbImage::from_pixel(2, 2, image::Rgb([200, 100, 50]));
+        let mut png = Vec::new();
+        image.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+
+        let pack = ResourcePack::from_bytes(zip_with("My Pack/assets/minecraft/textures/blocks/planks_oak.png", &png)).unwrap();
+        let texture = pack.block_texture("oak_planks").expect("oak_planks should be found").unwrap();
+        assert_eq!((texture.width, texture.height), (2, 2));
+        assert_eq!(texture.pixels[0], Color::new(200, 100, 50));
+        assert!(pack.block_texture("stone").is_none());
+    }
+
+    #[test]
+    fn entries_never_inflate_past_their_declared_size() {
+        // A megabyte of zeros that claims to be 10 bytes, and then more than the limit
+        let mut zip = zip_with("assets/minecraft/textures/block/stone.png", &vec![0; 1 << 20]);
+        let central = zip.windows(4).position(|bytes| bytes == 0x0201_4b50u32.to_le_bytes()).unwrap();
+        zip[central + 24..central + 28].copy_from_slice(&10u32.to_le_bytes());
+        let pack = ResourcePack::from_bytes(zip.clone()).unwrap();
+        let error = pack.block_texture("stone").unwrap().unwrap_err();
+        assert!(error.contains("different size"), "{}", error);
+
+        zip[central + 24..central + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+        let pack = ResourcePack::from_bytes(zip).unwrap();
+        let error = pack.block_texture("stone").unwrap().unwrap_err();
+        assert!(error.contains("more than the"), "{}", error);
+    }
+}
@@ -0,0 +1,193 @@
+use std::ops::{Add, Mul};
+
+use crate::color::Color;
+
+// Color de 8 bits guardado en los carriles de un vector de cuatro f32 (r, g, b y uno sin usar), para
+// encadenar las operaciones del sombreado de cada luz sin pasar por u8 entre una y otra. Cada
+// operación trunca y satura igual que la de `Color`, así que el resultado es el mismo bit a bit;
+// `Color` sigue siendo la versión de referencia. Con la feature `simd` los carriles son un f32x4 de
+// `wide`; sin ella, un array que el compilador vectoriza si puede.
+#[derive(Clone, Copy)]
+pub struct Rgb(lanes::Lanes);
+
+// Las pocas operaciones carril a carril que necesita `Rgb`
+#[cfg(feature = "simd")]
+mod lanes {
+    pub type Lanes = wide::f32x4;
+
+    pub fn new(values: [f32; 4]) -> Lanes {
+        Lanes::new(values)
+    }
+
+    pub fn splat(value: f32) -> Lanes {
+        Lanes::splat(value)
+    }
+
+    pub fn to_array(lanes: Lanes) -> [f32; 4] {
+        lanes.to_array()
+    }
+
+    pub fn add(a: Lanes, b: Lanes) -> Lanes {
+        a + b
+    }
+
+    pub fn mul(a: Lanes, b: Lanes) -> Lanes {
+        a * b
+    }
+
+    pub fn div(a: Lanes, b: Lanes) -> Lanes {
+        a / b
+    }
+
+    pub fn floor(lanes: Lanes) -> Lanes {
+        lanes.floor()
+    }
+
+    pub fn min(a: Lanes, b: Lanes) -> Lanes {
+        a.min(b)
+    }
+
+    pub fn max(a: Lanes, b: Lanes) -> Lanes {
+        a.max(b)
+    }
+}
+
+#[cfg(not(feature = "simd"))]
+mod lanes {
+    pub type Lanes = [f32; 4];
+
+    fn zip(a: Lanes, b: Lanes, f: impl Fn(f32, f32) -> f32) -> Lanes {
+        [0, 1, 2, 3].map(|i| f(a[i], b[i]))
+    }
+
+    pub fn new(values: [f32; 4]) -> Lanes {
+        values
+    }
+
+    pub fn splat(value: f32) -> Lanes {
+        [value; 4]
+    }
+
+    pub fn to_array(lanes: Lanes) -> [f32; 4] {
+        lanes
+    }
+
+    pub fn add(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| a + b)
+    }
+
+    pub fn mul(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| a * b)
+    }
+
+    pub fn div(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| a / b)
+    }
+
+    pub fn floor(lanes: Lanes) -> Lanes {
+        lanes.map(f32::floor)
+    }
+
+    pub fn min(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, f32::min)
+    }
+
+    pub fn max(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, f32::max)
+    }
+}
+
+impl Rgb {
+    pub fn black() -> Self {
+        Rgb(lanes::splat(0.0))
+    }
+
+    // Como `as u8` en f32: trunca hacia cero y satura en 0 y 255. Los carriles de un Rgb siempre
+    // tienen enteros de 0 a 255, así que truncar un producto positivo es lo mismo que floor.
+    fn saturate(values: lanes::Lanes) -> Rgb {
+        Rgb(lanes::max(lanes::floor(lanes::min(values, lanes::splat(255.0))), lanes::splat(0.0)))
+    }
+
+    // Color::mul
+    pub fn mul(&self, other: &Rgb) -> Rgb {
+        Rgb::saturate(lanes::div(lanes::mul(self.0, other.0), lanes::splat(255.0)))
+    }
+
+    // Color::mul_scalar
+    pub fn mul_scalar(&self, scalar: f32) -> Rgb {
+        Rgb::saturate(lanes::mul(self.0, lanes::splat(scalar)))
+    }
+
+    pub fn to_color(self) -> Color {
+        let [r, g, b, _] = lanes::to_array(self.0);
+        Color::new(r as u8, g as u8, b as u8)
+    }
+}
+
+impl From<Color> for Rgb {
+    fn from(color: Color) -> Self {
+        Rgb(lanes::new([color.r as f32, color.g as f32, color.b as f32, 0.0]))
+    }
+}
+
+// Suma saturada, como Color + Color
+impl Add for Rgb {
+    type Output = Rgb;
+
+    fn add(self, other: Rgb) -> Rgb {
+        Rgb(lanes::min(lanes::add(self.0, other.0), lanes::splat(255.0)))
+    }
+}
+
+// Como Color * f32, que también recorta los valores negativos
+impl Mul<f32> for Rgb {
+    type Output = Rgb;
+
+    fn mul(self, scalar: f32) -> Rgb {
+        self.mul_scalar(scalar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A spread of channel values including both ends
+    fn colors() -> impl Iterator<Item = Color> {
+        let channel = |i: u32| ((i * 37) % 256) as u8;
+        (0..64).map(move |i| Color::new(channel(i), channel(i * 3 + 1), [0, 255, channel(i * 7 + 2)][i as usize % 3]))
+    }
+
+    const SCALARS: [f32; 11] = [0.0, 0.1, 0.3, 0.5, 0.7, 0.999, 1.0, 1.37, 4.0, -0.5, 1e6];
+
+    #[test]
+    fn every_operation_matches_color() {
+        // With Mul in scope `a.mul(&b)` picks Mul<f32> over the inherent method, so products are called by type
+        for a in colors() {
+            for b in colors() {
+                assert_eq!(Rgb::mul(&Rgb::from(a), &Rgb::from(b)).to_color(), Color::mul(&a, &b), "{} * {}", a, b);
+                assert_eq!((Rgb::from(a) + Rgb::from(b)).to_color(), a + b, "{} + {}", a, b);
+            }
+            for scalar in SCALARS {
+                assert_eq!(Rgb::from(a).mul_scalar(scalar).to_color(), a.mul_scalar(scalar), "{} mul_scalar {}", a, scalar);
+                assert_eq!((Rgb::from(a) * scalar).to_color(), a * scalar, "{} * {}", a, scalar);
+            }
+        }
+    }
+
+    #[test]
+    fn chained_light_sums_match_color() {
+        // The per-light combine from shade_ray, glowstone branch included
+        for (material, light) in colors().zip(colors().skip(5)) {
+            for (amount, specular) in [(0.8, 0.2), (1.7, 0.0), (0.05, 3.0)] {
+                let diffuse = Color::mul(&material, &light).mul_scalar(amount);
+                let expected = (Color::black() + diffuse + light.mul_scalar(specular)).mul_scalar(0.6) + material.mul_scalar(0.7) + (diffuse + light).mul_scalar(0.3);
+
+                let (material, light) = (Rgb::from(material), Rgb::from(light));
+                let diffuse = Rgb::mul(&material, &light).mul_scalar(amount);
+                let actual = (Rgb::black() + diffuse + light.mul_scalar(specular)).mul_scalar(0.6) + material.mul_scalar(0.7) + (diffuse + light).mul_scalar(0.3);
+                assert_eq!(actual.to_color(), expected);
+            }
+        }
+    }
+}
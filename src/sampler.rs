@@ -0,0 +1,163 @@
+use crate::math::Float;
+use crate::settings::Sampler;
+
+const BLUE_NOISE_SIZE: u32 = 16;
+
+// Máscara de ruido azul de 16x16 (void-and-cluster, sigma 1.5, con repetición en los bordes):
+// cada valor de 0 a 255 aparece una vez y los vecinos nunca tienen valores parecidos
+const BLUE_NOISE: [u8; 256] = [
+    234,  50, 188,  19,  58, 171, 121,  47, 163,   1, 247, 104,  22, 132,  14,  65,
+    209,   8, 118,  97, 240, 205,  23, 228, 138,  64, 123, 170,  72, 224,  99, 149,
+     85, 139, 229, 165,  78, 146, 111,  84, 176, 216,  30, 231, 153, 201,  42, 180,
+     25,  62, 195,  29,  43, 185,   7, 249,  41, 100, 191,  48,  87,   5, 128, 243,
+    221, 152, 101, 253, 130, 220,  59, 200, 156,  12, 136, 112, 254, 174,  69, 109,
+     46, 189,   0,  73, 172,  90, 142, 116,  80, 237, 210,  61, 147,  33, 206, 160,
+     81, 124, 217, 113, 208,  15, 241,  27, 168,  45, 178,  20, 193,  96, 225,  18,
+    242, 164,  60,  35, 157,  53, 181,  68, 223, 105, 125,  83, 236, 131,  55, 141,
+    197,  10, 227, 134, 246,  95, 126, 198, 148,   3, 244, 161,  71,   9, 182, 106,
+     40,  93, 179,  75, 192,   6, 218,  36,  91,  57, 202,  34, 215, 155, 233,  74,
+    252, 120, 150,  24, 110,  63, 166, 119, 232, 183, 133, 103,  49, 117,  31, 167,
+     16, 212,  51, 238, 207, 137, 255,  21,  76, 151,  13, 250, 190,  88, 203, 135,
+    102, 184,  82, 169,  38,  89, 187,  52, 204,  98, 173,  67, 129,   4, 222,  56,
+    230, 144,   2, 127, 226,  11, 154, 114, 239,  39, 219,  28, 235, 145, 175,  77,
+    196,  37, 248,  70, 107, 199,  66, 177,  17, 143, 115, 159,  86,  44, 108,  26,
+    122,  92, 158, 214, 140,  32, 245,  94, 213,  79, 194,  54, 211, 186, 251, 162,
+];
+
+// Hash entero de un píxel y un índice de muestra, como dos números en [0, 1)
+fn hash(x: u32, y: u32, sample: u32) -> (Float, Float) {
+    let mut h = x.wrapping_mul(0x8da6_b343) ^ y.wrapping_mul(0xd816_3841) ^ sample.wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    ((h & 0xffff) as Float / 65536.0, (h >> 16) as Float / 65536.0)
+}
+
+// Punto en [0, 1)² para el píxel. La segunda coordenada lee la máscara desplazada para no repetir la primera
+fn blue_noise(x: u32, y: u32) -> (Float, Float) {
+    let at = |x: u32, y: u32| (BLUE_NOISE[((y % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x % BLUE_NOISE_SIZE) as usize] as Float + 0.5) / 256.0;
+    (at(x, y), at(x + 5, y + 11))
+}
+
+// Qué par de dimensiones de la secuencia de Halton usa cada decisión. Cada par tiene sus propias bases
+// primas, así que las muestras de antialiasing, sombras y oclusión no se correlacionan entre sí.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dimension {
+    Pixel,     // Bases 2 y 3
+    Shadow,    // Bases 5 y 7
+    Occlusion, // Bases 11 y 13
+}
+
+impl Dimension {
+    fn bases(&self) -> (u32, u32) {
+        match self {
+            Dimension::Pixel => (2, 3),
+            Dimension::Shadow => (5, 7),
+            Dimension::Occlusion => (11, 13),
+        }
+    }
+}
+
+// Muestra de un píxel que se está calculando. Con halton las secuencias de sombras y oclusión siguen
+// de una muestra del píxel a la siguiente en lugar de empezar de nuevo, y todas se rotan por píxel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PixelSample {
+    pub x: u32,
+    pub y: u32,
+    pub index: u32,
+}
+
+impl PixelSample {
+    // Rotación de Cranley-Patterson de una dimensión en este píxel, distinta para cada dimensión
+    pub fn rotation(&self, dimension: Dimension) -> (Float, Float) {
+        hash(self.x, self.y, u32::MAX - dimension as u32)
+    }
+}
+
+// Inverso radical: los dígitos de `index` en `base` reflejados tras la coma
+fn radical_inverse(mut index: u32, base: u32) -> Float {
+    let (mut result, mut scale) = (0.0, 1.0 / base as Float);
+    while index > 0 {
+        result += (index % base) as Float * scale;
+        index /= base;
+        scale /= base as Float;
+    }
+    result
+}
+
+// Punto `index` de la secuencia de Halton en las dimensiones dadas, rotado por `shift` (Cranley-Patterson)
+// para que cada píxel o punto use una copia distinta y no se repitan los mismos patrones en toda la imagen
+pub fn halton(dimension: Dimension, index: u32, shift: (Float, Float)) -> (Float, Float) {
+    let (base_u, base_v) = dimension.bases();
+    ((radical_inverse(index, base_u) + shift.0).fract(), (radical_inverse(index, base_v) + shift.1).fract())
+}
+
+// Desplazamiento dentro del píxel (0 a 1 en cada eje) de la muestra `sample` de una cuadrícula de grid x grid.
+// Regular usa la esquina de cada celda, como siempre; stratified y blue_noise eligen un punto distinto por píxel
+// dentro de la celda.
+pub fn pixel_offset(sampler: Sampler, x: u32, y: u32, sample: u32, grid: u32) -> (Float, Float) {
+    let (cell_x, cell_y) = ((sample % grid) as Float, (sample / grid) as Float);
+    let (jitter_x, jitter_y) = match sampler {
+        Sampler::Regular => return (cell_x / grid as Float, cell_y / grid as Float),
+        Sampler::BlueNoise if sample == 0 => blue_noise(x, y),
+        Sampler::Stratified | Sampler::BlueNoise => hash(x, y, sample),
+        // La secuencia ya reparte las muestras por todo el píxel; la cuadrícula no se usa
+        Sampler::Halton => return halton(Dimension::Pixel, sample, PixelSample { x, y, index: sample }.rotation(Dimension::Pixel)),
+    };
+    ((cell_x + jitter_x) / grid as Float, (cell_y + jitter_y) / grid as Float)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_stay_in_their_cells_and_first_samples_follow_the_mask() {
+        // Pinned sampler per case: regular is the fixed grid, the others jitter inside each cell
+        assert_eq!(pixel_offset(Sampler::Regular, 3, 7, 5, 4), (0.25, 0.25));
+        for sampler in [Sampler::Stratified, Sampler::BlueNoise] {
+            for sample in 0..16 {
+                let (u, v) = pixel_offset(sampler, 9, 2, sample, 4);
+                let (cell_x, cell_y) = ((sample % 4) as Float * 0.25, (sample / 4) as Float * 0.25);
+                assert!(u >= cell_x && u < cell_x + 0.25 && v >= cell_y && v < cell_y + 0.25, "{:?} sample {} at {:?}", sampler, sample, (u, v));
+            }
+        }
+        assert_ne!(pixel_offset(Sampler::Stratified, 0, 0, 0, 1), pixel_offset(Sampler::Stratified, 1, 0, 0, 1));
+
+        // One sample per pixel: over a tile every sixteenth of the pixel gets exactly 16 pixels,
+        // and neighbours are never close, unlike white noise
+        let mut columns = [0; 16];
+        for y in 0..BLUE_NOISE_SIZE {
+            for x in 0..BLUE_NOISE_SIZE {
+                let (u, _) = pixel_offset(Sampler::BlueNoise, x, y, 0, 1);
+                columns[(u * 16.0) as usize] += 1;
+                let right = pixel_offset(Sampler::BlueNoise, x + 1, y, 0, 1).0;
+                let below = pixel_offset(Sampler::BlueNoise, x, y + 1, 0, 1).0;
+                assert!((u - right).abs() > 0.03 && (u - below).abs() > 0.03, "pixel ({}, {}) looks like its neighbours", x, y);
+            }
+        }
+        assert_eq!(columns, [16; 16]);
+    }
+
+    #[test]
+    fn halton_points_fill_the_square_evenly_after_rotation() {
+        assert_eq!(radical_inverse(6, 2), 0.375);
+        assert!((radical_inverse(7, 3) - 5.0 / 9.0).abs() < 1e-6);
+
+        // The first 16 points of every rotated pair spread evenly: each quarter of either axis gets 2 to 6 of them,
+        // and exactly 4 along base 2, whatever the rotation
+        for dimension in [Dimension::Pixel, Dimension::Shadow, Dimension::Occlusion] {
+            let (mut columns, mut rows) = ([0; 4], [0; 4]);
+            for index in 0..16 {
+                let (u, v) = halton(dimension, index, (0.37, 0.81));
+                assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                columns[(u * 4.0) as usize] += 1;
+                rows[(v * 4.0) as usize] += 1;
+            }
+            assert!(columns.iter().chain(&rows).all(|&count| (2..=6).contains(&count)), "{:?}: {:?} {:?}", dimension, columns, rows);
+            if dimension == Dimension::Pixel {
+                assert_eq!(columns, [4; 4]);
+            }
+        }
+    }
+}
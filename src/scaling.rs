@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+// Los frames que tardan entre estas fracciones del objetivo no cambian la escala, para que no oscile
+const SLOW_MARGIN: f32 = 1.1;
+const FAST_MARGIN: f32 = 0.75;
+// Lo que más puede crecer o encoger la escala de un frame al siguiente
+const MAX_STEP: f32 = 1.25;
+
+// Escala interna del render de la ventana, que se ajusta frame a frame para acercarse a un tiempo
+// por frame sin salir de [min_scale, max_scale]. Empieza en la escala máxima.
+#[derive(Clone, Debug)]
+pub struct DynamicResolution {
+    pub target: Duration,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    scale: f32,
+}
+
+impl DynamicResolution {
+    pub fn new(target: Duration, min_scale: f32, max_scale: f32) -> Self {
+        DynamicResolution { target, min_scale, max_scale, scale: max_scale }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    // Ajusta la escala con lo que ha tardado el último frame renderizado a la escala actual. El coste
+    // crece con los píxeles, es decir con el cuadrado de la escala, así que la escala se multiplica
+    // por la raíz de la proporción entre el objetivo y el tiempo medido.
+    pub fn update(&mut self, render_time: Duration) -> f32 {
+        let ratio = render_time.as_secs_f32() / self.target.as_secs_f32().max(1e-6);
+        if !(FAST_MARGIN..=SLOW_MARGIN).contains(&ratio) {
+            let factor = (1.0 / ratio.max(1e-6)).sqrt().clamp(1.0 / MAX_STEP, MAX_STEP);
+            self.scale = (self.scale * factor).clamp(self.min_scale, self.max_scale);
+        }
+        self.scale
+    }
+}
+
+// Tamaño de una imagen de width x height a `scale`, con al menos un píxel por lado
+pub fn scaled_size(width: usize, height: usize, scale: f32) -> (usize, usize) {
+    let side = |size: usize| ((size as f32 * scale).round() as usize).clamp(1, size.max(1));
+    (side(width), side(height))
+}
+
+// Amplía una imagen de width x height a target_width x target_height repitiendo el píxel más cercano
+pub fn upscale(pixels: &[u32], width: usize, height: usize, target_width: usize, target_height: usize) -> Vec<u32> {
+    if (width, height) == (target_width, target_height) {
+        return pixels.to_vec();
+    }
+    let columns: Vec<usize> = (0..target_width).map(|x| x * width / target_width).collect();
+    (0..target_height)
+        .flat_map(|y| {
+            let row = y * height / target_height * width;
+            columns.iter().map(move |&column| pixels[row + column])
+        })
+        .collect()
+}
+
+// El rectángulo más grande con la proporción `aspect` (ancho / alto) que cabe centrado en un marco de
+// width x height, como (x, y, ancho, alto) dentro del marco; lo que sobra son bandas arriba y abajo
+// o a los lados, en vez de estirar la imagen
+pub fn letterbox(width: usize, height: usize, aspect: f32) -> (usize, usize, usize, usize) {
+    let (fit_width, fit_height) = if width as f32 / height.max(1) as f32 > aspect {
+        (((height as f32 * aspect).round() as usize).clamp(1, width.max(1)), height)
+    } else {
+        (width, ((width as f32 / aspect).round() as usize).clamp(1, height.max(1)))
+    };
+    ((width - fit_width) / 2, (height - fit_height) / 2, fit_width, fit_height)
+}
+
+// Copia una imagen de `image_width` píxeles de ancho en `pixels`, de `width` de ancho, con su esquina
+// superior izquierda en (left, top). La imagen tiene que caber.
+pub fn blit(pixels: &mut [u32], width: usize, image: &[u32], image_width: usize, left: usize, top: usize) {
+    for (row, line) in image.chunks(image_width).enumerate() {
+        let start = (top + row) * width + left;
+        pixels[start..start + image_width].copy_from_slice(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_converges_on_the_budget_without_oscillating() {
+        let target = Duration::from_millis(33);
+        let mut resolution = DynamicResolution::new(target, 0.25, 1.0);
+        // A render that takes 100 ms at full scale, with the time proportional to the pixels
+        let time_at = |scale: f32| Duration::from_secs_f32(0.1 * scale * scale);
+        let scales: Vec<f32> = (0..20).map(|_| resolution.update(time_at(resolution.scale()))).collect();
+        let settled = scales[scales.len() - 1];
+        assert!(scales[10..].iter().all(|&scale| scale == settled), "still changing: {:?}", scales);
+        let ratio = time_at(settled).as_secs_f32() / target.as_secs_f32();
+        assert!((FAST_MARGIN..=SLOW_MARGIN).contains(&ratio), "settled at {} ({}x the budget)", settled, ratio);
+
+        // Bounds hold whatever the measurements
+        for _ in 0..20 {
+            resolution.update(Duration::from_secs(10));
+        }
+        assert_eq!(resolution.scale(), 0.25);
+        for _ in 0..20 {
+            resolution.update(Duration::ZERO);
+        }
+        assert_eq!(resolution.scale(), 1.0);
+    }
+
+    #[test]
+    fn upscale_repeats_the_nearest_pixel() {
+        assert_eq!(scaled_size(800, 600, 0.5), (400, 300));
+        assert_eq!(scaled_size(3, 3, 0.01), (1, 1));
+        let pixels = [1, 2, 3, 4];
+        assert_eq!(upscale(&pixels, 2, 2, 4, 2), vec![1, 1, 2, 2, 3, 3, 4, 4]);
+        assert_eq!(upscale(&pixels, 2, 2, 2, 2), pixels.to_vec());
+        assert_eq!(upscale(&pixels, 2, 2, 3, 3).len(), 9);
+    }
+
+    #[test]
+    fn letterbox_keeps_the_aspect_ratio() {
+        assert_eq!(letterbox(400, 300, 16.0 / 9.0), (0, 37, 400, 225), "bars above and below");
+        assert_eq!(letterbox(400, 300, 1.0), (50, 0, 300, 300), "bars at the sides");
+        assert_eq!(letterbox(160, 90, 16.0 / 9.0), (0, 0, 160, 90), "no bars when it fits");
+        assert_eq!(letterbox(10, 1, 0.01), (4, 0, 1, 1), "at least one pixel");
+    }
+
+    #[test]
+    fn blit_copies_the_image_into_place() {
+        let mut pixels = vec![0; 4 * 3];
+        blit(&mut pixels, 4, &[1, 2, 3, 4], 2, 1, 1);
+        assert_eq!(pixels, [0, 0, 0, 0, 0, 1, 2, 0, 0, 3, 4, 0]);
+    }
+}
@@ -0,0 +1,2170 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::assets::resolve_asset;
+use crate::camera::Camera;
+use crate::block::{merge_blocks, BlockShape, BlockTurn, Facing, BLOCK_SIZE, NEIGHBOR_OFFSETS};
+use crate::color::Color;
+use crate::cube::Cube;
+use crate::day_night::DayNightCycle;
+use crate::edit::{BlockGrid, Edit, EditHistory, FallAnimation};
+use crate::error::RayTracerError;
+use crate::light::{Falloff, Light, LightLink};
+use crate::light_grid::LightGrid;
+use crate::material::{Material, Portal, Rain};
+use crate::math::{Float, Vec3};
+use crate::primitive::Primitive;
+use crate::probes;
+use crate::resource_pack;
+use crate::sky::{Ambient, AmbientSettings, Fog, Sky};
+use crate::structures::{self, PlacedBlock, RoofStyle};
+use crate::terrain::{Caves, Terrain};
+use crate::village::Village;
+use crate::streaming::ChunkStreamer;
+use crate::world::{World, WorldChunks};
+use crate::texture::{Texture, UvTransform};
+
+// A scene file lists cubes using the named materials from `materials()`:
+//
+// [[cube]]
+// min = [-1.0, 0.0, -1.0]
+// max = [1.0, 1.0, 1.0]
+// material = "stone"
+//
+// A cube of the portal material can be turned into a portal that shows the scene from its pair,
+// placed at `target_offset` and turned `rotation` degrees around the vertical axis:
+//
+// [[cube]]
+// min = [0.0, 0.0, 0.0]
+// max = [0.5, 1.5, 0.1]
+// material = "portal"
+// portal = { target_offset = [3.0, 0.0, 0.0], rotation = 90.0 }
+//
+// `facing` or `axis` turns the textures of a cube like those of a block (see below).
+//
+// or places blocks on the 0.5-unit grid, optionally with a partial shape
+// (slab, top_slab, carpet, fence or stairs; fences connect to neighboring fences and full blocks,
+// stairs take the Minecraft block states `facing` and `half`):
+//
+// [[block]]
+// pos = [0, 0, 0]
+// material = "wood"
+// shape = "stairs"
+// facing = "north"
+// half = "bottom"
+//
+// A full block can be turned too, with the textures of every face. `facing` is where its front
+// (the +z face when it isn't turned) looks, like a furnace's; "up" and "down" tip the front onto
+// the top or the bottom. `axis` lays a log's grain along x or z instead of y:
+//
+// [[block]]
+// pos = [1, 0, 0]
+// material = "treewood"
+// axis = "x"               # or facing = "east": north, east, south (unturned), west, up or down
+//
+// Whole structures are placed as blocks, so their walls merge like any other blocks. `at` is the
+// lowest corner on the block grid (the bottom of the trunk for trees), and left out parameters
+// take the values shown:
+//
+// [[structure]]
+// kind = "house"       # walls one block thick, a door in the +z wall and a glass window in the +x wall
+// at = [-3, 0, -3]
+// width = 6            # including the walls
+// depth = 6
+// height = 4
+// material = "wood"    # the walls; the roof is stone
+// roof = "stepped"     # or "flat"
+//
+// [[structure]]
+// kind = "tree"        # treewood trunk, leaves around its top
+// at = [-6, 0, 6]
+// height = 4
+// canopy_radius = 1
+//
+// kind = "pyramid" takes `base` (5) and `material` ("stone"); kind = "fence_ring" puts wooden
+// fences around `width` x `depth` (4 x 4) blocks; kind = "random_tree" grows a different tree for
+// every `seed` (0), with a leaning trunk, branches and a round or conical canopy. A structure can
+// join a `group` like a block.
+//
+// Terrain fills a square centered on the origin with noise hills, grass on top of stone, and
+// scatters random trees over it:
+//
+// [terrain]
+// seed = 0
+// size = 32            # blocks per side
+// height = 4           # average height of the surface in blocks
+// amplitude = 3        # hills rise and sink up to this many blocks
+// scale = 12.0         # rough width of a hill in blocks
+// tree_density = 0.0   # chance of a tree on each surface block
+//
+// A village on the terrain places up to `count` houses, farms and a well on flat ground, turned
+// at random, at least `spacing` blocks apart, on stone foundations and joined by dirt paths.
+// Trees keep clear of it:
+//
+// [terrain.village]
+// seed = 0
+// count = 5
+// spacing = 2
+//
+// Caves are carved out of the stone where 3D ridged noise goes over `threshold`, leaving winding
+// tunnels. The bottom row and the top `crust` blocks of every column are never carved, and with a
+// `water_level` the carved blocks at or below it are filled with water:
+//
+// [terrain.caves]
+// seed = 0
+// threshold = 0.85     # 0 to 1, higher gives fewer and thinner tunnels
+// scale = 10.0         # rough width of a tunnel bend in blocks
+// squash = 2.0         # above 1 makes caves wider than they are tall
+// crust = 2
+// water_level = 3      # optional
+//
+// Textures of a material can be replaced, reoriented or pointed at a region of their image for
+// this scene. `slot` is the texture's index in the material (grass: 0 top, 1 sides):
+//
+// [[texture]]
+// material = "grass"
+// slot = 1
+// path = "textures/my_grass_side.png"   # next to the scene file first; missing files show as a magenta/black checkerboard
+// flip_v = true
+// rotate = 90               # clockwise: 0, 90, 180 or 270
+// uv_scale = [0.5, 0.5]     # region of the image covered by a face
+// uv_offset = [0.5, 0.0]    # top left corner of that region
+//
+// Blocks of a material can vary one from the next, so a large field doesn't repeat the same tile;
+// the choice is fixed by the block's position:
+//
+// [[material]]
+// name = "grass"
+// randomize_rotation = true   # turn or mirror the top face's texture
+// tint_jitter = 0.05          # vary brightness by up to ±5% and shift the hue slightly
+//
+// Transparent materials can split white light into its colors like a prism: red refracts with the
+// material's index minus half of `dispersion` and blue with it plus half (0, the default, keeps
+// them together; real glass is around 0.01, 0.05 shows clear rainbow fringes):
+//
+// [[material]]
+// name = "glass"
+// dispersion = 0.05
+//
+// Point lights add to the sun and the lights of emissive blocks. The color is either RGB or a
+// color temperature:
+//
+// [[light]]
+// position = [4.0, 1.0, 5.0]
+// color = { kelvin = 3200 }   # or [255, 200, 150]; white by default
+// intensity = 1.0
+// radius = 10.0               # reach of the light
+// falloff = "quadratic"       # inverse_square (default), none, linear or quadratic, which fades to 0 at the radius
+// enabled = true
+// cast_shadows = false        # a fill light that shines through blocks
+// include = ["interior"]      # light only these object groups (or exclude = [...] for all but them)
+//
+// Any `[[cube]]` or `[[block]]` can join a group with `group = "interior"`.
+//
+// Ambient light comes from the sky above and the ground below, blended by each face's normal.
+// Colors that are left out follow the active sky (the day/night color, or the upper and lower
+// halves of an environment map); equal colors give a flat ambient:
+//
+// [ambient]
+// sky = [120, 160, 255]
+// ground = [110, 90, 60]
+// intensity = 1.0
+//
+// With snow, the top of every block that is open to the sky turns white, texel by texel:
+//
+// weather = "snow"       # or "clear", the default
+// snow_coverage = 0.85   # fraction of the texels covered
+//
+// Rain leaves those tops wet instead: darker, glossier and reflecting the sky at grazing angles.
+// It can start while the window is open, taking `rain_ramp` seconds to soak them:
+//
+// weather = "rain"
+// wetness = 1.0          # 0 dry, 1 soaked
+// rain_start = 0.0       # seconds after opening the window; renders without a window use 0
+// rain_ramp = 0.0
+//
+// Neighboring full blocks of the same material and group are merged into as few boxes as possible,
+// so a 16x1x16 layer of grass is a single cube. Textures are mapped from world coordinates, so the
+// merged box looks exactly like its blocks. Emissive blocks, partial shapes, blocks of materials that vary per block and
+// blocks placed twice at one position stay separate. Turn it off to see every block's own outline:
+//
+// merge_blocks = false
+//
+// Reflection probes are small cubemaps of the scene rendered when it loads. Rough reflections and
+// reflections of reflections read the nearest probe instead of tracing more rays, and mirrors
+// sharper than --probe-roughness keep tracing. Probes go where the scene lists them, and with
+// `auto_probes` also one above the reflective blocks of every 16x16x16-block chunk:
+//
+// auto_probes = true
+//
+// [[probe]]
+// position = [0.0, 2.0, 0.0]
+//
+// The window opens with the camera of the scene, if it has one (Ctrl+S saves the current one):
+//
+// [camera]
+// eye = [0.0, 2.0, 6.5]
+// center = [0.0, 1.0, 0.0]
+// up = [0.0, 1.0, 0.0]
+// fov = 60.0   # degrees
+// roll = 0.0   # degrees
+//
+// `LoadedScene::save` writes this same format back; the values come before the tables so the
+// file stays valid TOML.
+#[derive(Deserialize, Serialize)]
+struct SceneFile {
+    weather: Option<String>,
+    #[serde(default = "default_snow_coverage")]
+    snow_coverage: f32,
+    #[serde(default = "default_light_intensity")]
+    wetness: f32,
+    #[serde(default)]
+    rain_start: f32,
+    #[serde(default)]
+    rain_ramp: f32,
+    #[serde(default = "default_true")]
+    merge_blocks: bool,
+    #[serde(default)]
+    auto_probes: bool,
+    camera: Option<CameraEntry>,
+    terrain: Option<TerrainEntry>,
+    #[serde(default)]
+    ambient: AmbientEntry,
+    #[serde(default, rename = "material", skip_serializing_if = "Vec::is_empty")]
+    material_options: Vec<MaterialEntry>,
+    #[serde(default, rename = "texture", skip_serializing_if = "Vec::is_empty")]
+    textures: Vec<TextureEntry>,
+    #[serde(default, rename = "cube", skip_serializing_if = "Vec::is_empty")]
+    cubes: Vec<CubeEntry>,
+    #[serde(default, rename = "block", skip_serializing_if = "Vec::is_empty")]
+    blocks: Vec<BlockEntry>,
+    #[serde(default, rename = "structure", skip_serializing_if = "Vec::is_empty")]
+    structures: Vec<StructureEntry>,
+    #[serde(default, rename = "light", skip_serializing_if = "Vec::is_empty")]
+    lights: Vec<LightEntry>,
+    #[serde(default, rename = "probe", skip_serializing_if = "Vec::is_empty")]
+    probes: Vec<ProbeEntry>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CameraEntry {
+    eye: [Float; 3],
+    center: [Float; 3],
+    #[serde(default = "default_up")]
+    up: [Float; 3],
+    #[serde(default = "default_fov")]
+    fov: Float, // Grados
+    #[serde(default)]
+    roll: Float, // Grados
+}
+
+fn default_up() -> [Float; 3] { [0.0, 1.0, 0.0] }
+fn default_fov() -> Float { 60.0 }
+
+impl CameraEntry {
+    fn from_camera(camera: &Camera) -> Self {
+        CameraEntry {
+            eye: to_array(&camera.eye),
+            center: to_array(&camera.center),
+            up: to_array(&camera.up),
+            fov: camera.fov.to_degrees(),
+            roll: camera.roll.to_degrees(),
+        }
+    }
+
+    fn camera(&self) -> Camera {
+        let mut camera = Camera::new(to_vec3(&self.eye), to_vec3(&self.center), to_vec3(&self.up));
+        camera.fov = self.fov.to_radians();
+        camera.roll = self.roll.to_radians();
+        camera
+    }
+}
+
+fn to_array(v: &Vec3) -> [Float; 3] {
+    [v.x, v.y, v.z]
+}
+
+fn to_vec3(a: &[Float; 3]) -> Vec3 {
+    Vec3::new(a[0], a[1], a[2])
+}
+
+fn default_snow_coverage() -> f32 { 0.85 }
+
+#[derive(Deserialize, Serialize)]
+struct AmbientEntry {
+    sky: Option<ColorEntry>,
+    ground: Option<ColorEntry>,
+    #[serde(default = "default_light_intensity")]
+    intensity: f32,
+}
+
+impl Default for AmbientEntry {
+    fn default() -> Self {
+        AmbientEntry { sky: None, ground: None, intensity: default_light_intensity() }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct ProbeEntry {
+    position: [Float; 3],
+}
+
+#[derive(Deserialize, Serialize)]
+struct LightEntry {
+    position: [Float; 3],
+    #[serde(default)]
+    color: ColorEntry,
+    #[serde(default = "default_light_intensity")]
+    intensity: f32,
+    #[serde(default = "default_light_radius")]
+    radius: Float,
+    #[serde(default = "default_true")]
+    enabled: bool,
+    #[serde(default = "default_true")]
+    cast_shadows: bool,
+    falloff: Option<String>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+}
+
+impl LightEntry {
+    fn light(&self, link: LightLink) -> Result<Light, String> {
+        let [x, y, z] = self.position;
+        let falloff = self.falloff.as_deref().map(str::parse::<Falloff>).transpose()?.unwrap_or_default();
+        let mut light = Light::new(Vec3::new(x, y, z), self.color.color(), self.intensity, self.radius)
+            .with_shadows(self.cast_shadows)
+            .with_link(link)
+            .with_falloff(falloff);
+        light.enabled = self.enabled;
+        Ok(light)
+    }
+}
+
+fn default_light_intensity() -> f32 { 1.0 }
+fn default_light_radius() -> Float { 10.0 }
+fn default_true() -> bool { true }
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum ColorEntry {
+    Rgb([u8; 3]),
+    Temperature { kelvin: f32 },
+}
+
+impl Default for ColorEntry {
+    fn default() -> Self { ColorEntry::Rgb([255, 255, 255]) }
+}
+
+impl ColorEntry {
+    fn color(&self) -> Color {
+        match self {
+            ColorEntry::Rgb([r, g, b]) => Color::new(*r, *g, *b),
+            ColorEntry::Temperature { kelvin } => Color::from_kelvin(*kelvin),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct MaterialEntry {
+    name: String,
+    randomize_rotation: Option<bool>,
+    tint_jitter: Option<f32>,
+    dispersion: Option<f32>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct TextureEntry {
+    material: String,
+    #[serde(default)]
+    slot: usize,
+    path: Option<String>,
+    #[serde(default)]
+    flip_v: bool,
+    #[serde(default)]
+    rotate: u32,
+    #[serde(default = "default_uv_scale")]
+    uv_scale: [f32; 2],
+    #[serde(default)]
+    uv_offset: [f32; 2],
+}
+
+fn default_uv_scale() -> [f32; 2] { [1.0, 1.0] }
+
+impl TextureEntry {
+    fn transform(&self) -> Result<UvTransform, String> {
+        if ![0, 90, 180, 270].contains(&self.rotate) {
+            return Err(format!("Texture rotation must be 0, 90, 180 or 270 (got {})", self.rotate));
+        }
+        Ok(UvTransform { flip_v: self.flip_v, rotate: self.rotate, scale: self.uv_scale, offset: self.uv_offset })
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct CubeEntry {
+    min: [Float; 3],
+    max: [Float; 3],
+    material: String,
+    portal: Option<PortalEntry>,
+    facing: Option<String>,
+    axis: Option<String>,
+    group: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct PortalEntry {
+    target_offset: [Float; 3],
+    #[serde(default)]
+    rotation: Float, // Grados
+}
+
+#[derive(Deserialize, Serialize)]
+struct BlockEntry {
+    pos: [i32; 3],
+    material: String,
+    shape: Option<String>,
+    facing: Option<String>,
+    axis: Option<String>,
+    half: Option<String>,
+    group: Option<String>,
+}
+
+impl BlockEntry {
+    fn shape(&self) -> Result<BlockShape, String> {
+        let shape = self.shape.as_deref().unwrap_or("full").parse::<BlockShape>()?;
+        match shape {
+            BlockShape::Stairs { .. } if self.axis.is_some() => Err("axis only applies to full blocks, not stairs".to_string()),
+            BlockShape::Stairs { .. } => {
+                let facing = self.facing.as_deref().unwrap_or("north").parse::<Facing>()?;
+                let upside_down = match self.half.as_deref().unwrap_or("bottom") {
+                    "bottom" => false,
+                    "top" => true,
+                    half => return Err(format!("Unknown half '{}' (expected bottom or top)", half)),
+                };
+                Ok(BlockShape::Stairs { facing, upside_down })
+            }
+            _ if self.half.is_some() => Err(format!("half only applies to stairs, not {}", shape.name())),
+            BlockShape::Full => Ok(shape),
+            _ if self.facing.is_some() || self.axis.is_some() => {
+                Err(format!("facing and axis only apply to full blocks (and facing to stairs), not {}", shape.name()))
+            }
+            _ => Ok(shape),
+        }
+    }
+
+    // Giro de un bloque completo; el de las escaleras va en su forma
+    fn turn(&self, shape: BlockShape) -> Result<Option<BlockTurn>, String> {
+        if shape != BlockShape::Full {
+            return Ok(None);
+        }
+        block_turn(self.facing.as_deref(), self.axis.as_deref())
+    }
+}
+
+// `facing` o `axis` de un [[block]] o un [[cube]]; no pueden ir los dos
+fn block_turn(facing: Option<&str>, axis: Option<&str>) -> Result<Option<BlockTurn>, String> {
+    match (facing, axis) {
+        (Some(_), Some(_)) => Err("a block takes either facing or axis, not both".to_string()),
+        (Some(facing), None) => Ok(Some(BlockTurn::Facing(facing.parse()?))),
+        (None, Some(axis)) => Ok(Some(BlockTurn::Axis(axis.parse()?))),
+        (None, None) => Ok(None),
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct TerrainEntry {
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "default_terrain_size")]
+    size: i32,
+    #[serde(default = "default_terrain_height")]
+    height: i32,
+    #[serde(default = "default_terrain_amplitude")]
+    amplitude: i32,
+    #[serde(default = "default_terrain_scale")]
+    scale: f32,
+    #[serde(default)]
+    tree_density: f32,
+    village: Option<VillageEntry>,
+    caves: Option<CavesEntry>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CavesEntry {
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "default_cave_threshold")]
+    threshold: f32,
+    #[serde(default = "default_cave_scale")]
+    scale: f32,
+    #[serde(default = "default_cave_squash")]
+    squash: f32,
+    #[serde(default = "default_cave_crust")]
+    crust: i32,
+    water_level: Option<i32>,
+}
+
+fn default_cave_threshold() -> f32 { Caves::default().threshold }
+fn default_cave_scale() -> f32 { Caves::default().scale }
+fn default_cave_squash() -> f32 { Caves::default().squash }
+fn default_cave_crust() -> i32 { Caves::default().crust }
+
+#[derive(Deserialize, Serialize)]
+struct VillageEntry {
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "default_village_count")]
+    count: usize,
+    #[serde(default = "default_village_spacing")]
+    spacing: i32,
+}
+
+fn default_village_count() -> usize { Village::default().count }
+fn default_village_spacing() -> i32 { Village::default().spacing }
+
+fn default_terrain_size() -> i32 { Terrain::default().size }
+fn default_terrain_height() -> i32 { Terrain::default().height }
+fn default_terrain_amplitude() -> i32 { Terrain::default().amplitude }
+fn default_terrain_scale() -> f32 { Terrain::default().scale }
+
+impl TerrainEntry {
+    fn terrain(&self) -> Result<Terrain, String> {
+        let terrain = Terrain {
+            seed: self.seed,
+            size: self.size,
+            height: self.height,
+            amplitude: self.amplitude,
+            scale: self.scale,
+            tree_density: self.tree_density,
+            village: self.village.as_ref().map(|village| Village { seed: village.seed, count: village.count, spacing: village.spacing }),
+            caves: self.caves.as_ref().map(|caves| Caves {
+                seed: caves.seed,
+                threshold: caves.threshold,
+                scale: caves.scale,
+                squash: caves.squash,
+                crust: caves.crust,
+                water_level: caves.water_level,
+            }),
+        };
+        terrain.validate()?;
+        Ok(terrain)
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct StructureEntry {
+    kind: String,
+    at: [i32; 3],
+    width: Option<i32>,
+    depth: Option<i32>,
+    height: Option<i32>,
+    canopy_radius: Option<i32>,
+    base: Option<i32>,
+    seed: Option<u64>,
+    material: Option<String>,
+    roof: Option<String>,
+    group: Option<String>,
+}
+
+impl StructureEntry {
+    fn blocks(&self) -> Result<Vec<PlacedBlock>, String> {
+        let material = self.material.as_deref();
+        match self.kind.as_str() {
+            "house" => {
+                let roof = self.roof.as_deref().unwrap_or("stepped").parse::<RoofStyle>()?;
+                structures::house(self.at, self.width.unwrap_or(6), self.depth.unwrap_or(6), self.height.unwrap_or(4), material.unwrap_or("wood"), roof)
+            }
+            "tree" => structures::tree(self.at, self.height.unwrap_or(4), self.canopy_radius.unwrap_or(1)),
+            "pyramid" => structures::pyramid(self.at, self.base.unwrap_or(5), material.unwrap_or("stone")),
+            "fence_ring" => structures::fence_ring(self.at, self.width.unwrap_or(4), self.depth.unwrap_or(4)),
+            "random_tree" => Ok(structures::random_tree(self.at, self.seed.unwrap_or(0))),
+            kind => Err(format!("Unknown structure '{}' (expected house, tree, random_tree, pyramid or fence_ring)", kind)),
+        }
+    }
+}
+
+// Lo que define un archivo de escena: los bloques, sus luces propias y las texturas que faltaron
+pub struct LoadedScene {
+    pub objects: Vec<Cube>,
+    pub lights: Vec<Light>, // Solo las del archivo; el sol y las de los bloques emisivos las añade quien renderiza
+    pub ambient: AmbientSettings,
+    pub missing_textures: Vec<String>,
+    pub warnings: Vec<SceneWarning>, // Lo que encontró `validate` al cargarla
+    pub camera: Option<Camera>,      // La sección [camera], si el archivo la tiene
+    pub groups: HashMap<String, HashSet<usize>>, // Índices de los objetos de cada grupo
+    pub probes: Vec<Vec3>, // Posiciones de las entradas [[probe]]
+    pub auto_probes: bool, // Añade las sondas de `probes::auto_positions` a las del archivo
+    pub blocks: Option<SceneBlocks>, // Los bloques que se pueden editar; sin ellos la escena no se edita
+}
+
+// Los bloques de la escena por posición y lo necesario para volver a convertirlos en cajas. Sus
+// cajas son las últimas de `objects`, a partir de `fixed`; las de antes son [[cube]]s que no se
+// editan. Los materiales ya tienen las texturas de la escena y el tiempo aplicados.
+pub struct SceneBlocks {
+    pub grid: BlockGrid,
+    fixed: usize,
+    materials: HashMap<String, Material>,
+    merge: bool,
+}
+
+impl SceneBlocks {
+    // La rejilla de `blocks` con gravedad para los materiales que la tienen
+    fn new(blocks: impl IntoIterator<Item = PlacedBlock>, fixed: usize, materials: HashMap<String, Material>, merge: bool) -> Self {
+        let falling: Vec<String> = materials.iter().filter(|(_, material)| material.gravity).map(|(name, _)| name.clone()).collect();
+        SceneBlocks { grid: BlockGrid::new(blocks).with_gravity(falling), fixed, materials, merge }
+    }
+
+    pub fn has_material(&self, name: &str) -> bool {
+        self.materials.contains_key(name)
+    }
+
+    // Los nombres de los materiales que se pueden poner, en orden alfabético
+    pub fn material_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.materials.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+// Todo lo que se renderiza: los objetos, las luces, el cielo, la cámara y la luz ambiental del
+// frame. Objetos y cielo van en Arc, así que copiar la escena para cambiarle las luces o la cámara
+// es barato. Los objetos son `Primitive`, así que junto a los bloques puede haber esferas y planos. La rejilla de niveles de luz (modo rápido y luz del cielo) se calcula la primera vez
+// que se pide, la comparten las copias y se descarta al cambiar los objetos; lo mismo pasa con los
+// objetos subidos a la GPU para el backend de GPU.
+#[derive(Clone)]
+pub struct Scene {
+    objects: Arc<Vec<Primitive>>,
+    pub lights: Vec<Light>,
+    pub sky: Arc<Sky>,
+    pub camera: Camera,
+    pub ambient: Ambient,
+    pub fog: Option<Fog>,
+    light_grid: Arc<OnceLock<LightGrid>>,
+    #[cfg(feature = "gpu")]
+    gpu_buffers: Arc<OnceLock<Result<crate::gpu::SceneBuffers, String>>>,
+}
+
+impl Scene {
+    pub fn new<P: Into<Primitive>>(objects: impl IntoIterator<Item = P>, lights: Vec<Light>, sky: Arc<Sky>, camera: Camera, ambient: Ambient) -> Self {
+        let objects: Vec<Primitive> = objects.into_iter().map(Into::into).collect();
+        Scene {
+            objects: Arc::new(objects),
+            lights,
+            sky,
+            camera,
+            ambient,
+            fog: None,
+            light_grid: Arc::new(OnceLock::new()),
+            #[cfg(feature = "gpu")]
+            gpu_buffers: Arc::new(OnceLock::new()),
+        }
+    }
+
+    pub fn objects(&self) -> &[Primitive] {
+        &self.objects
+    }
+
+    pub fn set_objects<P: Into<Primitive>>(&mut self, objects: impl IntoIterator<Item = P>) {
+        self.objects = Arc::new(objects.into_iter().map(Into::into).collect::<Vec<Primitive>>());
+        self.light_grid = Arc::new(OnceLock::new());
+        #[cfg(feature = "gpu")]
+        {
+            self.gpu_buffers = Arc::new(OnceLock::new());
+        }
+    }
+
+    // Solo las cajas ocupan celdas de la rejilla
+    pub fn light_grid(&self) -> &LightGrid {
+        self.light_grid.get_or_init(|| LightGrid::from_objects(self.objects.iter().filter_map(Primitive::as_cube)))
+    }
+
+    // Los objetos subidos a la GPU, o por qué no se pudieron subir
+    #[cfg(feature = "gpu")]
+    pub fn gpu_buffers(&self) -> &Result<crate::gpu::SceneBuffers, String> {
+        self.gpu_buffers.get_or_init(|| crate::gpu::SceneBuffers::new(&self.objects))
+    }
+}
+
+// Errores de autor que no impiden cargar la escena pero casi seguro no son lo que se quería.
+// `Cube::new` ya ordena las esquinas, así que InvertedCube solo aparece en cajas cuyos campos se
+// escribieron a mano después de construirlas.
+// Los objetos se numeran en el orden en que se renderizan ([[cube]] primero, luego los bloques) y
+// las luces en el orden de sus entradas [[light]], empezando en 0.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SceneWarning {
+    InvertedCube { index: usize, axis: char },
+    ZeroVolumeCube { index: usize, axis: char },
+    TooMuchReflection { index: usize, reflectivity: f32, transparency: f32 },
+    DarkLight { index: usize },
+    DuplicateCube { index: usize, first: usize },
+}
+
+impl std::fmt::Display for SceneWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SceneWarning::InvertedCube { index, axis } => {
+                write!(f, "object {} has min > max on {}, so it is never hit; swap the two values", index, axis)
+            }
+            SceneWarning::ZeroVolumeCube { index, axis } => {
+                write!(f, "object {} has no thickness on {} (min = max) and is never hit; give it some depth or remove it", index, axis)
+            }
+            SceneWarning::TooMuchReflection { index, reflectivity, transparency } => write!(
+                f,
+                "object {} has reflectivity {} + transparency {} above 1, so it reflects more light than it gets; lower one of them",
+                index, reflectivity, transparency,
+            ),
+            SceneWarning::DarkLight { index } => {
+                write!(f, "light {} has zero intensity and lights nothing; raise it or set enabled = false", index)
+            }
+            SceneWarning::DuplicateCube { index, first } => {
+                write!(f, "object {} has the same min and max as object {}; remove one of them", index, first)
+            }
+        }
+    }
+}
+
+impl LoadedScene {
+    // Escena con solo estos objetos, sin luces propias, cámara ni grupos
+    pub fn new(objects: Vec<Cube>) -> Self {
+        LoadedScene {
+            objects,
+            lights: Vec::new(),
+            ambient: AmbientSettings::default(),
+            missing_textures: Vec::new(),
+            warnings: Vec::new(),
+            camera: None,
+            groups: HashMap::new(),
+            probes: Vec::new(),
+            auto_probes: false,
+            blocks: None,
+        }
+    }
+
+    // Aplica `edit` a los bloques apuntándolo en `history` y rehace sus cajas. Devuelve si cambió
+    // algún bloque; quien renderiza tiene que volver a pasar los objetos y las luces de los
+    // emisivos a su `Scene`, que descarta la rejilla de luz al cambiarlos.
+    pub fn edit_blocks(&mut self, history: &mut EditHistory, edit: &Edit) -> Result<bool, String> {
+        let blocks = self.blocks.as_mut().ok_or_else(|| "This scene's blocks can't be edited (streamed worlds and scenes with grouped blocks aren't)".to_string())?;
+        if let Some(name) = edit.material().filter(|name| !blocks.has_material(name)) {
+            return Err(format!("Unknown material '{}'", name));
+        }
+        let changed = history.apply(&mut blocks.grid, edit)?;
+        if changed {
+            self.rebuild_blocks()?;
+        }
+        Ok(changed)
+    }
+
+    // Deshace o rehace la última edición de `history`, si había alguna
+    pub fn undo_blocks(&mut self, history: &mut EditHistory) -> Result<bool, String> {
+        let Some(blocks) = self.blocks.as_mut() else { return Ok(false) };
+        if !history.undo(&mut blocks.grid) {
+            return Ok(false);
+        }
+        self.rebuild_blocks().map(|_| true)
+    }
+
+    pub fn redo_blocks(&mut self, history: &mut EditHistory) -> Result<bool, String> {
+        let Some(blocks) = self.blocks.as_mut() else { return Ok(false) };
+        if !history.redo(&mut blocks.grid) {
+            return Ok(false);
+        }
+        self.rebuild_blocks().map(|_| true)
+    }
+
+    // Vuelve a convertir todos los bloques en cajas, uniéndolos como al cargar la escena. Con varios
+    // bloques en una posición, tras la primera edición solo queda el último.
+    fn rebuild_blocks(&mut self) -> Result<(), String> {
+        let Some(blocks) = &self.blocks else { return Ok(()) };
+        let placed: Vec<(PlacedBlock, Option<&str>)> = blocks.grid.blocks().map(|block| (block.clone(), None)).collect();
+        let built = block_objects(&placed, &blocks.materials, blocks.merge)?;
+        self.objects.truncate(blocks.fixed);
+        self.objects.extend(built.into_iter().map(|(object, _)| object));
+        Ok(())
+    }
+
+    // Las cajas de la escena durante la caída de `falls`: los bloques que siguen en el aire salen de
+    // su sitio en la rejilla y se dibujan a la altura a la que van, sin unirse a nada
+    pub fn falling_objects(&self, falls: &FallAnimation) -> Result<Vec<Cube>, String> {
+        let Some(blocks) = &self.blocks else { return Ok(self.objects.clone()) };
+        let airborne: Vec<(&PlacedBlock, f32)> = falls.airborne().collect();
+        let hidden: HashSet<[i32; 3]> = airborne.iter().map(|(block, _)| block.pos).collect();
+        let placed: Vec<(PlacedBlock, Option<&str>)> = blocks.grid.blocks()
+            .filter(|block| !hidden.contains(&block.pos))
+            .map(|block| (block.clone(), None))
+            .collect();
+        let mut objects = self.objects[..blocks.fixed].to_vec();
+        objects.extend(block_objects(&placed, &blocks.materials, blocks.merge)?.into_iter().map(|(object, _)| object));
+        for (block, height) in airborne {
+            let lift = Vec3::new(0.0, height as Float * BLOCK_SIZE, 0.0);
+            let pieces = block_objects(&[(block.clone(), None)], &blocks.materials, false)?;
+            objects.extend(pieces.into_iter().map(|(cube, _)| Cube { min: cube.min + lift, max: cube.max + lift, ..cube }));
+        }
+        Ok(objects)
+    }
+
+    // Escena lista para renderizar con `sky` a la hora de `day_night`: el sol (y la luna de noche), las
+    // luces del archivo y las de los bloques emisivos, la luz ambiental del cielo y la cámara del
+    // archivo o la de siempre.
+    pub fn scene(&self, sky: Arc<Sky>, day_night: &DayNightCycle) -> Scene {
+        let mut lights = vec![day_night.sun_light()];
+        lights.extend(day_night.moon_light());
+        lights.extend(self.lights.iter().cloned());
+        lights.extend(generate_lights_from_emissive_objects(&self.objects));
+        let ambient = sky.ambient(&day_night.get_current_color(), &self.ambient).with_night(day_night.night_amount());
+        let camera = self.camera.clone().unwrap_or_else(Camera::default_view);
+        Scene::new(self.objects.iter().cloned(), lights, sky, camera, ambient)
+    }
+
+    // Dónde renderizar las sondas de reflexión: las del archivo y, si las pide, las automáticas
+    pub fn probe_positions(&self) -> Vec<Vec3> {
+        let mut positions = self.probes.clone();
+        if self.auto_probes {
+            positions.extend(probes::auto_positions(&self.objects));
+        }
+        positions
+    }
+
+    pub fn validate(&self) -> Vec<SceneWarning> {
+        let mut warnings = Vec::new();
+        let mut seen: HashMap<[u64; 6], usize> = HashMap::new();
+        for (index, object) in self.objects.iter().enumerate() {
+            for (axis, name) in ['x', 'y', 'z'].into_iter().enumerate() {
+                if object.min[axis] > object.max[axis] {
+                    warnings.push(SceneWarning::InvertedCube { index, axis: name });
+                } else if object.min[axis] == object.max[axis] {
+                    warnings.push(SceneWarning::ZeroVolumeCube { index, axis: name });
+                }
+            }
+            let [_, _, reflectivity, transparency] = object.material.properties;
+            if reflectivity + transparency > 1.0 + 1e-6 {
+                warnings.push(SceneWarning::TooMuchReflection { index, reflectivity, transparency });
+            }
+            let key = [object.min.x, object.min.y, object.min.z, object.max.x, object.max.y, object.max.z].map(|value| (value as f64).to_bits());
+            match seen.get(&key) {
+                Some(&first) => warnings.push(SceneWarning::DuplicateCube { index, first }),
+                None => {
+                    seen.insert(key, index);
+                }
+            }
+        }
+        for (index, light) in self.lights.iter().enumerate() {
+            if light.intensity == 0.0 {
+                warnings.push(SceneWarning::DarkLight { index });
+            }
+        }
+        warnings
+    }
+
+    // La escena en el formato de los archivos de escena. Cada objeto se escribe como un [[cube]]
+    // con el nombre de su material, así que los bloques unidos quedan como una sola caja; las
+    // texturas cambiadas se guardan como la ruta de la que salieron, nunca como píxeles.
+    pub fn to_toml(&self) -> Result<String, RayTracerError> {
+        self.scene_file().and_then(|file| toml::to_string(&file).map_err(|e| format!("Failed to write scene: {}", e)))
+            .map_err(RayTracerError::Scene)
+    }
+
+    fn scene_file(&self) -> Result<SceneFile, String> {
+        let mut group_of = HashMap::new();
+        for (name, indices) in &self.groups {
+            for &index in indices {
+                group_of.insert(index, name.clone());
+            }
+        }
+
+        let mut cubes = Vec::new();
+        let mut used: Vec<&Material> = Vec::new();
+        for (index, object) in self.objects.iter().enumerate() {
+            let material = &object.material;
+            if material.name.is_empty() {
+                return Err(format!("object {} has a material that isn't one of the named materials and can't be saved", index));
+            }
+            if !used.iter().any(|known| known.name == material.name) {
+                used.push(material);
+            }
+            let turn = if object.faces == Cube::unturned_faces() {
+                None
+            } else {
+                Some(BlockTurn::all()
+                    .find(|turn| turn.face_descriptors() == object.faces)
+                    .ok_or_else(|| format!("object {} has turned faces that match no facing or axis and can't be saved", index))?)
+            };
+            cubes.push(CubeEntry {
+                min: to_array(&object.min),
+                max: to_array(&object.max),
+                material: material.name.clone(),
+                portal: material.portal.map(|portal| PortalEntry {
+                    target_offset: to_array(&portal.target_offset),
+                    rotation: portal.rotation.to_degrees(),
+                }),
+                facing: match turn {
+                    Some(BlockTurn::Facing(facing)) => Some(facing.name().to_string()),
+                    _ => None,
+                },
+                axis: match turn {
+                    Some(BlockTurn::Axis(axis)) => Some(axis.name().to_string()),
+                    _ => None,
+                },
+                group: group_of.get(&index).cloned(),
+            });
+        }
+
+        // Las opciones y texturas de cada material se toman del primer objeto que lo usa
+        let material_options = used.iter()
+            .filter(|material| material.varies_per_block() || material.dispersion > 0.0)
+            .map(|material| MaterialEntry {
+                name: material.name.clone(),
+                randomize_rotation: Some(material.randomize_rotation),
+                tint_jitter: Some(material.tint_jitter),
+                dispersion: (material.dispersion > 0.0).then_some(material.dispersion),
+            })
+            .collect();
+        let textures = used.iter()
+            .flat_map(|material| material.textures.iter().enumerate().map(move |(slot, texture)| (material, slot, texture)))
+            .filter(|(_, _, texture)| texture.source.is_some() || texture.uv_transform != UvTransform::default())
+            .map(|(material, slot, texture)| TextureEntry {
+                material: material.name.clone(),
+                slot,
+                path: texture.source.clone(),
+                flip_v: texture.uv_transform.flip_v,
+                rotate: texture.uv_transform.rotate,
+                uv_scale: texture.uv_transform.scale,
+                uv_offset: texture.uv_transform.offset,
+            })
+            .collect();
+
+        // Una luz enlazada se guarda con los grupos que caben enteros en su lista de objetos
+        let link_groups = |objects: &HashSet<usize>| -> Result<Vec<String>, String> {
+            let mut names: Vec<String> = self.groups.iter()
+                .filter(|(_, indices)| !indices.is_empty() && indices.is_subset(objects))
+                .map(|(name, _)| name.clone())
+                .collect();
+            names.sort();
+            let covered: HashSet<usize> = names.iter().flat_map(|name| self.groups[name].iter().copied()).collect();
+            if covered != *objects {
+                return Err("a light is linked to objects that don't form whole groups and can't be saved".to_string());
+            }
+            Ok(names)
+        };
+        let lights = self.lights.iter()
+            .map(|light| {
+                let (include, exclude) = match &light.link {
+                    LightLink::All => (None, None),
+                    LightLink::Only(objects) => (Some(link_groups(objects)?), None),
+                    LightLink::Except(objects) => (None, Some(link_groups(objects)?)),
+                };
+                Ok(LightEntry {
+                    position: to_array(&light.position),
+                    color: ColorEntry::Rgb([light.color.r, light.color.g, light.color.b]),
+                    intensity: light.intensity,
+                    radius: light.radius,
+                    enabled: light.enabled,
+                    cast_shadows: light.cast_shadows,
+                    falloff: (light.falloff != Falloff::default()).then(|| light.falloff.name().to_string()),
+                    include,
+                    exclude,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // El clima se aplica a todos los objetos por igual, así que basta con mirar el primero
+        let first = self.objects.first().map(|object| &object.material);
+        let rain = first.and_then(|material| material.rain);
+        let snow = first.map_or(0.0, |material| material.snow_cover);
+        let weather = match (rain, snow > 0.0) {
+            (Some(_), _) => Some("rain".to_string()),
+            (None, true) => Some("snow".to_string()),
+            (None, false) => None,
+        };
+        let rain = rain.unwrap_or(Rain { wetness: default_light_intensity(), start: 0.0, ramp: 0.0 });
+
+        let rgb = |color: &Color| ColorEntry::Rgb([color.r, color.g, color.b]);
+        let file = SceneFile {
+            weather,
+            snow_coverage: if snow > 0.0 { snow } else { default_snow_coverage() },
+            wetness: rain.wetness,
+            rain_start: rain.start,
+            rain_ramp: rain.ramp,
+            merge_blocks: true,
+            auto_probes: self.auto_probes,
+            camera: self.camera.as_ref().map(CameraEntry::from_camera),
+            terrain: None,
+            ambient: AmbientEntry {
+                sky: self.ambient.sky.as_ref().map(rgb),
+                ground: self.ambient.ground.as_ref().map(rgb),
+                intensity: self.ambient.intensity,
+            },
+            material_options,
+            textures,
+            cubes,
+            blocks: Vec::new(),
+            structures: Vec::new(),
+            lights,
+            probes: self.probes.iter().map(|position| ProbeEntry { position: to_array(position) }).collect(),
+        };
+        Ok(file)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), RayTracerError> {
+        let contents = self.to_toml()?;
+        std::fs::write(path, contents).map_err(|e| RayTracerError::io(path, e))
+    }
+}
+
+// Loads a scene: "demo" is the built-in house, a path ending in .world is a binary world and
+// anything else is a path to a scene file. Textures that fail to load are replaced by a
+// checkerboard instead of failing the scene.
+pub fn load(name: &str) -> Result<LoadedScene, RayTracerError> {
+    let mut missing = Vec::new();
+    let invalid = |resolved: &Path, e: String| RayTracerError::Scene(format!("{}: {}", resolved.display(), e));
+    let loaded = match name {
+        "demo" => demo(&mut missing),
+        path if path.ends_with(".world") => {
+            let resolved = resolve_scene(path)?;
+            from_world(World::load_binary(&resolved)?, &mut missing).map_err(|e| invalid(&resolved, e))?
+        }
+        path => {
+            let resolved = resolve_scene(path)?;
+            let contents = std::fs::read_to_string(&resolved).map_err(|e| RayTracerError::io(&resolved, e))?;
+            parse(&contents, resolved.parent(), &mut missing).map_err(|e| invalid(&resolved, e))?
+        }
+    };
+    Ok(checked(loaded, missing))
+}
+
+// Loads a binary world to be streamed around the camera instead of as a whole: the scene has the
+// world's lights and camera but no objects, which come from the returned streamer as the camera
+// moves (see `ChunkStreamer`). `radius` is in blocks.
+pub fn load_streamed(path: &str, radius: Float) -> Result<(LoadedScene, ChunkStreamer), RayTracerError> {
+    let resolved = resolve_scene(path)?;
+    let world = WorldChunks::load_binary(&resolved)?;
+    let mut missing = Vec::new();
+    let materials = materials(&mut missing);
+    let loaded = LoadedScene { lights: world.lights.clone(), camera: world.camera.clone(), ..LoadedScene::new(Vec::new()) };
+    Ok((checked(loaded, missing), ChunkStreamer::new(world, materials, radius)))
+}
+
+fn resolve_scene(path: &str) -> Result<PathBuf, RayTracerError> {
+    resolve_asset(path, None).map_err(|e| RayTracerError::Asset(format!("Scene {}", e)))
+}
+
+// Bloques del archivo con su grupo. Los de las estructuras y el terreno se tratan igual que los
+// [[block]] del archivo.
+fn placed_blocks(file: &SceneFile) -> Result<Vec<(PlacedBlock, Option<&str>)>, String> {
+    let mut placed = Vec::new();
+    for entry in &file.blocks {
+        structures::check_position(entry.pos)?;
+        let shape = entry.shape()?;
+        let block = PlacedBlock { pos: entry.pos, material: entry.material.clone(), shape, turn: entry.turn(shape)? };
+        placed.push((block, entry.group.as_deref()));
+    }
+    for entry in &file.structures {
+        structures::check_position(entry.at)?;
+        placed.extend(entry.blocks()?.into_iter().map(|block| (block, entry.group.as_deref())));
+    }
+    if let Some(terrain) = &file.terrain {
+        placed.extend(terrain.terrain()?.blocks().into_iter().map(|block| (block, None)));
+    }
+    Ok(placed)
+}
+
+// Reads a scene file as a binary world: its blocks (from [[block]], [[structure]] and [terrain]),
+// lights and camera. Only block scenes fit in a world, so [[cube]] entries and light
+// include/exclude lists are errors; materials keep their registry defaults.
+pub fn world(path: &str) -> Result<World, RayTracerError> {
+    let resolved = resolve_scene(path)?;
+    let contents = std::fs::read_to_string(&resolved).map_err(|e| RayTracerError::io(&resolved, e))?;
+    let invalid = |e: String| RayTracerError::Scene(format!("{}: {}", resolved.display(), e));
+    let file: SceneFile = toml::from_str(&contents)
+        .map_err(|e| invalid(format!("Failed to parse scene: {}", e)))?;
+    if !file.cubes.is_empty() {
+        return Err(invalid(format!("{} [[cube]] entries can't be saved in a world, only blocks", file.cubes.len())));
+    }
+    if file.lights.iter().any(|entry| entry.include.is_some() || entry.exclude.is_some()) {
+        return Err(invalid("Light include/exclude lists can't be saved in a world".to_string()));
+    }
+    let blocks = placed_blocks(&file).map_err(invalid)?;
+    Ok(World {
+        blocks: blocks.into_iter().map(|(block, _)| block).collect(),
+        lights: file.lights.iter().map(|entry| entry.light(LightLink::All)).collect::<Result<_, _>>().map_err(invalid)?,
+        camera: file.camera.as_ref().map(CameraEntry::camera),
+    })
+}
+
+// Escena de un mundo binario: sus bloques con los materiales del registro, unidos como en un
+// archivo de escena
+fn from_world(world: World, missing: &mut Vec<String>) -> Result<LoadedScene, String> {
+    let materials = materials(missing);
+    let placed: Vec<(PlacedBlock, Option<&str>)> = world.blocks.iter().map(|block| (block.clone(), None)).collect();
+    let objects = block_objects(&placed, &materials, true)?.into_iter().map(|(object, _)| object).collect();
+    let blocks = SceneBlocks::new(world.blocks, 0, materials, true);
+    Ok(LoadedScene { lights: world.lights, camera: world.camera, blocks: Some(blocks), ..LoadedScene::new(objects) })
+}
+
+// Parses a scene from the contents of a scene file.
+pub fn from_str(contents: &str) -> Result<LoadedScene, RayTracerError> {
+    let mut missing = Vec::new();
+    let loaded = parse(contents, None, &mut missing).map_err(RayTracerError::Scene)?;
+    Ok(checked(loaded, missing))
+}
+
+// Anota las texturas que faltaron, valida la escena recién cargada y avisa de cada problema
+fn checked(mut loaded: LoadedScene, missing: Vec<String>) -> LoadedScene {
+    warn_missing(&missing);
+    loaded.missing_textures = missing;
+    loaded.warnings = loaded.validate();
+    for warning in &loaded.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    loaded
+}
+
+// Resumen al terminar la carga, además del aviso de cada textura en el momento
+fn warn_missing(missing: &[String]) {
+    if !missing.is_empty() {
+        eprintln!(
+            "warning: {} texture(s) could not be loaded and are shown as a magenta/black checkerboard: {}",
+            missing.len(),
+            missing.join(", ")
+        );
+    }
+}
+
+// Las rutas de texturas de la escena se buscan primero junto a `scene_dir` (ver `resolve_asset`)
+fn parse(contents: &str, scene_dir: Option<&Path>, missing: &mut Vec<String>) -> Result<LoadedScene, String> {
+    let file: SceneFile = toml::from_str(contents)
+        .map_err(|e| format!("Failed to parse scene: {}", e))?;
+    let mut materials = materials(missing);
+    // Las imágenes que la escena pide se cargan todas a la vez antes de asignarlas
+    let paths: Vec<&str> = file.textures.iter().filter_map(|entry| entry.path.as_deref()).collect();
+    let mut loaded = load_textures(&paths, missing, |path, log| load_texture(path, scene_dir, log)).into_iter();
+    for entry in &file.textures {
+        let transform = entry.transform()?;
+        let material = materials.get_mut(&entry.material)
+            .ok_or_else(|| format!("Unknown material '{}'", entry.material))?;
+        let slots = material.textures.len();
+        let texture = material.textures.get_mut(entry.slot)
+            .ok_or_else(|| format!("Material '{}' has no texture slot {} ({} slots)", entry.material, entry.slot, slots))?;
+        if entry.path.is_some() {
+            *texture = loaded.next().expect("one texture per path");
+            texture.source = entry.path.clone();
+        }
+        texture.uv_transform = transform;
+    }
+
+    for entry in &file.material_options {
+        let material = materials.get_mut(&entry.name)
+            .ok_or_else(|| format!("Unknown material '{}'", entry.name))?;
+        if let Some(randomize_rotation) = entry.randomize_rotation {
+            material.randomize_rotation = randomize_rotation;
+        }
+        if let Some(jitter) = entry.tint_jitter {
+            if !(0.0..=1.0).contains(&jitter) {
+                return Err(format!("tint_jitter must be between 0 and 1, got {}", jitter));
+            }
+            material.tint_jitter = jitter;
+        }
+        if let Some(dispersion) = entry.dispersion {
+            if !(0.0..=1.0).contains(&dispersion) {
+                return Err(format!("dispersion must be between 0 and 1, got {}", dispersion));
+            }
+            material.dispersion = dispersion;
+        }
+    }
+
+    // El tiempo va en los materiales, así que llega a las cajas y a los bloques que se pongan después
+    if !(0.0..=1.0).contains(&file.snow_coverage) {
+        return Err(format!("snow_coverage must be between 0 and 1, got {}", file.snow_coverage));
+    }
+    if !(0.0..=1.0).contains(&file.wetness) || file.rain_ramp < 0.0 {
+        return Err(format!("wetness must be between 0 and 1 and rain_ramp not negative, got {} and {}", file.wetness, file.rain_ramp));
+    }
+    match file.weather.as_deref().unwrap_or("clear") {
+        "clear" => {}
+        "snow" => {
+            for material in materials.values_mut() {
+                material.snow_cover = file.snow_coverage;
+            }
+        }
+        "rain" => {
+            let rain = Rain { wetness: file.wetness, start: file.rain_start, ramp: file.rain_ramp };
+            for material in materials.values_mut() {
+                material.rain = Some(rain);
+            }
+        }
+        weather => return Err(format!("Unknown weather '{}' (expected clear, snow or rain)", weather)),
+    }
+
+    let material = |name: &str| materials.get(name).ok_or_else(|| format!("Unknown material '{}'", name));
+
+    // Índices de los objetos de cada grupo, para las listas include/exclude de las luces
+    let mut groups: HashMap<String, HashSet<usize>> = HashMap::new();
+    for (index, entry) in file.cubes.iter().enumerate() {
+        if let Some(group) = &entry.group {
+            groups.entry(group.clone()).or_default().insert(index);
+        }
+    }
+
+    let mut objects = file.cubes.iter()
+        .map(|entry| {
+            let mut material = material(&entry.material)?.clone();
+            if let Some(portal) = &entry.portal {
+                if entry.material != "portal" {
+                    return Err(format!("portal only applies to the portal material, not '{}'", entry.material));
+                }
+                let [x, y, z] = portal.target_offset;
+                material = material.with_portal(Portal {
+                    target_offset: Vec3::new(x, y, z),
+                    rotation: portal.rotation.to_radians(),
+                });
+            }
+            let faces = match block_turn(entry.facing.as_deref(), entry.axis.as_deref())? {
+                Some(turn) => turn.face_descriptors(),
+                None => Cube::unturned_faces(),
+            };
+            Ok(Cube::new(
+                Vec3::new(entry.min[0], entry.min[1], entry.min[2]),
+                Vec3::new(entry.max[0], entry.max[1], entry.max[2]),
+                &material,
+            ).with_faces(faces))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let placed = placed_blocks(&file)?;
+    for (object, group) in block_objects(&placed, &materials, file.merge_blocks)? {
+        if let Some(group) = group {
+            groups.entry(group.to_string()).or_default().insert(objects.len());
+        }
+        objects.push(object);
+    }
+    // Los grupos guardan índices de objetos, que cambian al rehacer las cajas de los bloques
+    let blocks = if placed.iter().all(|(_, group)| group.is_none()) {
+        Some(SceneBlocks::new(placed.into_iter().map(|(block, _)| block), file.cubes.len(), materials, file.merge_blocks))
+    } else {
+        None
+    };
+
+    let resolve = |names: &[String]| {
+        names.iter().try_fold(HashSet::new(), |mut indices, name| {
+            let group = groups.get(name.as_str()).ok_or_else(|| format!("Unknown object group '{}'", name))?;
+            indices.extend(group);
+            Ok::<_, String>(indices)
+        })
+    };
+    let lights = file.lights.iter()
+        .map(|entry| {
+            let link = match (&entry.include, &entry.exclude) {
+                (Some(_), Some(_)) => return Err("A light can't have both include and exclude".to_string()),
+                (Some(names), None) => LightLink::Only(resolve(names)?),
+                (None, Some(names)) => LightLink::Except(resolve(names)?),
+                (None, None) => LightLink::All,
+            };
+            entry.light(link)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let ambient = AmbientSettings {
+        sky: file.ambient.sky.as_ref().map(ColorEntry::color),
+        ground: file.ambient.ground.as_ref().map(ColorEntry::color),
+        intensity: file.ambient.intensity,
+    };
+
+    Ok(LoadedScene {
+        objects,
+        lights,
+        ambient,
+        camera: file.camera.as_ref().map(CameraEntry::camera),
+        groups,
+        probes: file.probes.iter().map(|entry| Vec3::new(entry.position[0], entry.position[1], entry.position[2])).collect(),
+        auto_probes: file.auto_probes,
+        blocks,
+        ..LoadedScene::new(Vec::new())
+    })
+}
+
+// Texturas de la escena de demostración incluidas en el ejecutable, para que funcione sin la
+// carpeta assets/ o desde otro directorio. Los archivos en disco tienen prioridad.
+const EMBEDDED_TEXTURES: [(&str, &[u8]); 7] = [
+    ("assets/UP_GRASSTEXTURE.jpg", include_bytes!("../assets/UP_GRASSTEXTURE.jpg")),
+    ("assets/SIDE_GRASSTEXTURE.jpg", include_bytes!("../assets/SIDE_GRASSTEXTURE.jpg")),
+    ("assets/wood_plank.jpg", include_bytes!("../assets/wood_plank.jpg")),
+    ("assets/stone_block.jpg", include_bytes!("../assets/stone_block.jpg")),
+    ("assets/wood_rawplank.jpg", include_bytes!("../assets/wood_rawplank.jpg")),
+    ("assets/leaves_texture.jpg", include_bytes!("../assets/leaves_texture.jpg")),
+    ("assets/glowstone_texture.jpg", include_bytes!("../assets/glowstone_texture.jpg")),
+];
+
+fn embedded_texture(path: &str) -> Option<Texture> {
+    let (_, bytes) = EMBEDDED_TEXTURES.iter().find(|(name, _)| *name == path)?;
+    Some(Texture::from_bytes(bytes).expect("embedded textures are valid images"))
+}
+
+// Avisos de la carga de una textura. Las texturas se cargan en paralelo, así que los avisos se
+// guardan y se imprimen al final en el orden de los materiales, no en el que terminan las cargas
+#[derive(Default)]
+struct TextureLog {
+    warnings: Vec<String>,
+    missing: Vec<String>,
+}
+
+// Carga (y decodifica) una textura por elemento de `items` en paralelo. Las texturas y los avisos
+// quedan en el orden de `items`, así que las asignaciones no dependen de qué carga termina antes.
+fn load_textures<T: Sync>(items: &[T], missing: &mut Vec<String>, load: impl Fn(&T, &mut TextureLog) -> Texture + Sync) -> Vec<Texture> {
+    let loaded: Vec<(Texture, TextureLog)> = items.par_iter()
+        .map(|item| {
+            let mut log = TextureLog::default();
+            (load(item, &mut log), log)
+        })
+        .collect();
+    loaded.into_iter()
+        .map(|(texture, log)| {
+            log.warnings.iter().for_each(|warning| eprintln!("warning: {}", warning));
+            missing.extend(log.missing);
+            texture
+        })
+        .collect()
+}
+
+// Textura de un bloque en el resource pack activo, si hay uno y la incluye
+fn pack_texture(block: &str, log: &mut TextureLog) -> Option<Texture> {
+    match resource_pack::active()?.block_texture(block)? {
+        Ok(texture) => Some(texture),
+        Err(e) => {
+            log.warnings.push(format!("failed to load {} from the resource pack: {}", block, e));
+            None
+        }
+    }
+}
+
+// Textura de un bloque: la del resource pack activo y, si no la trae, la propia (`path`)
+fn block_texture(block: &str, path: &str, log: &mut TextureLog) -> Texture {
+    if resource_pack::active().is_none() {
+        return load_texture(path, None, log);
+    }
+    pack_texture(block, log).unwrap_or_else(|| {
+        log.warnings.push(format!("the resource pack has no {} texture; using the default", block));
+        load_texture(path, None, log)
+    })
+}
+
+// Una textura que falta no detiene la carga: se usa la copia incluida si la hay y, si no, se
+// avisa, se anota en `missing` y se usa el damero
+fn load_texture(path: &str, scene_dir: Option<&Path>, log: &mut TextureLog) -> Texture {
+    let mut fail = |error: String| {
+        log.warnings.push(format!("failed to load texture {}", error));
+        log.missing.push(path.to_string());
+        Texture::missing()
+    };
+    match resolve_asset(path, scene_dir) {
+        Ok(resolved) => Texture::load(&resolved.to_string_lossy()).unwrap_or_else(|e| fail(e.to_string())),
+        Err(e) => embedded_texture(path).unwrap_or_else(|| fail(e.to_string())),
+    }
+}
+
+// Vetas de mineral sobre la piedra: pinta manchas de `ore` en la textura base y devuelve también
+// un mapa emisivo con solo esas manchas, así la piedra de alrededor no brilla
+fn ore_speckles(stone: &Texture, ore: Color) -> (Texture, Texture) {
+    // Manchas de 2x2 texeles (en una textura de 16 texeles de lado) repartidas con un hash fijo
+    let cell = (stone.width.min(stone.height) / 8).max(1);
+    let is_speckle = |x: u32, y: u32| {
+        let (cx, cy) = (x / cell, y / cell);
+        let hash = (cx.wrapping_mul(73_856_093) ^ cy.wrapping_mul(19_349_663)).wrapping_mul(2_654_435_761);
+        hash >> 28 < 3 // Alrededor de una de cada cinco celdas
+    };
+    let mut base = stone.clone();
+    let mut emissive = stone.clone();
+    for y in 0..stone.height {
+        for x in 0..stone.width {
+            let index = (y * stone.width + x) as usize;
+            if is_speckle(x, y) {
+                base.pixels[index] = ore;
+                emissive.pixels[index] = ore;
+            } else {
+                emissive.pixels[index] = Color::black();
+            }
+        }
+    }
+    (base, emissive)
+}
+
+// Named block materials shared by the demo scene and scene files; textures that fail to load
+// are replaced by a checkerboard and their paths added to `missing`
+pub fn materials(missing: &mut Vec<String>) -> HashMap<String, Material> {
+    // Texturas de los bloques (nombre en un resource pack, archivo propio), cargadas a la vez
+    let blocks = [
+        ("grass_block_top", "assets/UP_GRASSTEXTURE.jpg"),
+        ("grass_block_side", "assets/SIDE_GRASSTEXTURE.jpg"),
+        ("oak_planks", "assets/wood_plank.jpg"),
+        ("stone", "assets/stone_block.jpg"),
+        ("oak_log", "assets/wood_rawplank.jpg"),
+        ("oak_leaves", "assets/leaves_texture.jpg"),
+        ("glowstone", "assets/glowstone_texture.jpg"),
+    ];
+    let textures: [Texture; 7] = load_textures(&blocks, missing, |(block, path), log| block_texture(block, path, log))
+        .try_into()
+        .expect("one texture per block");
+    let [grass_top_texture, dirt_side_texture, wood_plank_texture, stone_texture, tree_plank_texture, leaves_texture, glowstone_texture] = textures;
+
+    let grass = Material::new(
+        Color::new(0, 255, 0),  // Color verde
+        50.0,                   // Ajuste el brillo si es necesario
+        [0.8, 0.2, 0.0, 0.0],   // Ajusta las propiedades: difuso, especular, reflectividad, transparencia
+        1.0
+    ).with_textures(vec![grass_top_texture, dirt_side_texture]);
+
+    let wood: Material = Material::new(
+        Color::new(170, 137, 85),   // Color marrón típico de la madera
+        30.0,                       // Ajuste el brillo
+        [0.7, 0.2, 0.0, 0.0],       // Propiedades: difuso, especular, reflectividad, transparencia
+        2.0                         // Índice de refracción (ajustado a 1.0 para superficies opacas)
+    ).with_textures(vec![wood_plank_texture.clone(), wood_plank_texture ]);
+
+    let stone: Material = Material::new(
+        Color::new(128, 128, 128),  // Color gris típico de la piedra
+        30.0,                       // Brillo moderado, la piedra no refleja mucha luz
+        [0.7, 0.1, 0.1, 0.0],       // Propiedades: difuso, especular, reflectividad, transparencia
+        1.0                         // Índice de refracción para superficies opacas
+    ).with_textures(vec![stone_texture.clone()]);  // Usa la misma textura para todas las caras
+
+    // Mineral brillante: piedra con vetas que emiten luz por sí mismas (no ilumina los bloques vecinos)
+    let (ore_texture, ore_emissive) = ore_speckles(&stone_texture, Color::new(255, 120, 40));
+    let glowing_ore: Material = Material::new(
+        Color::new(128, 128, 128),
+        30.0,
+        [0.7, 0.1, 0.0, 0.0],
+        1.0
+    ).with_textures(vec![ore_texture])
+     .with_emissive_map(ore_emissive, 1.5);
+
+    let treewood: Material = Material::new(
+        Color::new(139, 69, 19),    // Color marrón típico de la madera
+        10.0,                       // Ajuste el brillo (puede ser más bajo para que la madera no se vea muy brillante)
+        [0.7, 0.2, 0.0, 0.0],       // Propiedades: difuso, especular, reflectividad, transparencia
+        1.0                         // Índice de refracción (ajustado a 1.0 para superficies opacas)
+    ).with_textures(vec![tree_plank_texture]);
+
+    let leaves: Material = Material::new(
+        Color::new(34, 139, 34),    // Color verde
+        10.0,                       // Brillo ligeramente más bajo para las hojas
+        [0.6, 0.3, 0.0, 0.0],       // Propiedades: difuso, especular, reflectividad, transparencia
+        1.0                         // Índice de refracción para superficies opacas
+    ).with_textures(vec![leaves_texture]);
+
+    // Material para Cristal
+    let mut glass: Material = Material::new(
+        Color::new(0, 0, 0),
+        60.0,
+        [0.1, 0.1, 0.1, 0.5],       // Propiedades: bajo difuso, alto especular, sin reflectividad, alta transparencia
+        1.0                         // Índice de refracción típico para el vidrio
+    );
+    // El cristal no tiene textura propia; solo toma el marco del resource pack si lo trae
+    let mut log = TextureLog::default();
+    if let Some(texture) = pack_texture("glass", &mut log) {
+        glass = glass.with_textures(vec![texture]);
+    }
+    log.warnings.iter().for_each(|warning| eprintln!("warning: {}", warning));
+
+    // Vidrio tintado: igual que el cristal, pero la luz transmitida toma el color del bloque
+    let stained_glass = |tint: Color| glass.clone().with_transmission_tint(tint);
+    let red_stained_glass = stained_glass(Color::new(200, 40, 40));
+    let green_stained_glass = stained_glass(Color::new(60, 180, 60));
+    let blue_stained_glass = stained_glass(Color::new(50, 80, 210));
+
+    // Superficie morada del portal; el destino se indica en cada cubo del archivo de escena
+    let portal: Material = Material::new(
+        Color::new(150, 60, 220),   // Tinte morado del Nether
+        10.0,
+        [0.0, 0.0, 0.0, 0.0],
+        1.0
+    );
+
+    // Tierra pisada de los caminos de los pueblos, sin textura
+    let path: Material = Material::new(
+        Color::new(150, 120, 80),
+        5.0,
+        [0.9, 0.05, 0.0, 0.0],
+        1.0
+    );
+
+    // Arena y grava: caen cuando se les quita el bloque de debajo. Sin textura propia; la toman
+    // del resource pack si la trae
+    let mut log = TextureLog::default();
+    let mut sand: Material = Material::new(
+        Color::new(219, 207, 163),
+        5.0,
+        [0.9, 0.05, 0.0, 0.0],
+        1.0
+    ).with_gravity();
+    let mut gravel: Material = Material::new(
+        Color::new(136, 126, 126),
+        5.0,
+        [0.9, 0.05, 0.0, 0.0],
+        1.0
+    ).with_gravity();
+    for (name, material) in [("sand", &mut sand), ("gravel", &mut gravel)] {
+        if let Some(texture) = pack_texture(name, &mut log) {
+            *material = material.clone().with_textures(vec![texture]);
+        }
+    }
+    log.warnings.iter().for_each(|warning| eprintln!("warning: {}", warning));
+
+    // Agua de las cuevas inundadas: azulada, algo reflectante y transparente
+    let water: Material = Material::new(
+        Color::new(40, 90, 200),
+        60.0,
+        [0.3, 0.3, 0.1, 0.5],
+        1.33                        // Índice de refracción del agua
+    );
+
+    let glowstone: Material = Material::new(
+        Color::new(255, 255, 200),  // Color base amarillento
+        10.0,                       // Reducimos el brillo para que la textura sea más visible
+        [0.9, 0.1, 0.0, 0.0],       // Aumentamos el difuso, reducimos el especular
+        1.0
+    ).with_textures(vec![glowstone_texture])
+     .with_emission(Color::new(255, 255, 150), 1.0); // Mantenemos la emisión fuerte
+
+    // Cada material lleva su nombre, que es lo que se escribe al guardar la escena
+    [
+        ("grass", grass),
+        ("wood", wood),
+        ("stone", stone),
+        ("glowing_ore", glowing_ore),
+        ("treewood", treewood),
+        ("leaves", leaves),
+        ("glass", glass),
+        ("red_stained_glass", red_stained_glass),
+        ("green_stained_glass", green_stained_glass),
+        ("blue_stained_glass", blue_stained_glass),
+        ("glowstone", glowstone),
+        ("portal", portal),
+        ("path", path),
+        ("sand", sand),
+        ("gravel", gravel),
+        ("water", water),
+    ]
+    .into_iter()
+    .map(|(name, material)| (name.to_string(), Material { name: name.to_string(), ..material }))
+    .collect()
+}
+
+// Convierte bloques de la cuadrícula en cajas, cada una con el grupo de su bloque. Los bloques
+// enteros vecinos del mismo material, grupo y orientación se unen si `merge` (salvo los emisivos, los de
+// materiales que varían por bloque y los repetidos en una posición); el resto da una caja por
+// pieza de su forma, en el orden de `blocks`, y las cajas unidas van al final.
+pub(crate) fn block_objects<'a>(blocks: &[(PlacedBlock, Option<&'a str>)], materials: &HashMap<String, Material>, merge: bool) -> Result<Vec<(Cube, Option<&'a str>)>, String> {
+    let material = |name: &str| materials.get(name).ok_or_else(|| format!("Unknown material '{}'", name));
+
+    // Forma de cada posición ocupada, para que las vallas sepan con qué vecinos conectarse
+    let mut shapes = HashMap::new();
+    let mut positions = HashMap::new();
+    for (block, _) in blocks {
+        shapes.insert(block.pos, block.shape);
+        *positions.entry(block.pos).or_insert(0) += 1;
+    }
+
+    // Bloques que se pueden unir con sus vecinos, con el índice de su clase (material, grupo y
+    // orientación). Las texturas salen de las coordenadas del mundo, así que una caja unida de
+    // bloques girados igual se ve como ellos.
+    let mut mergeable: HashMap<[i32; 3], usize> = HashMap::new();
+    let mut kinds: Vec<(&str, Option<&'a str>, Option<BlockTurn>)> = Vec::new();
+    let faces = |turn: Option<BlockTurn>| turn.map_or_else(Cube::unturned_faces, |turn| turn.face_descriptors());
+    let mut objects = Vec::new();
+
+    for (block, group) in blocks {
+        let material = material(&block.material)?;
+        if merge && block.shape == BlockShape::Full && positions[&block.pos] == 1 && !material.varies_per_block()
+            && material.emission == Color::new(0, 0, 0)
+        {
+            let kind = (block.material.as_str(), *group, block.turn);
+            let index = kinds.iter().position(|known| *known == kind).unwrap_or_else(|| {
+                kinds.push(kind);
+                kinds.len() - 1
+            });
+            mergeable.insert(block.pos, index);
+            continue;
+        }
+        let [x, y, z] = block.pos;
+        let connected = NEIGHBOR_OFFSETS.map(|(dx, dz)| {
+            shapes.get(&[x + dx, y, z + dz]).is_some_and(BlockShape::connects_fence)
+        });
+        for (min, max) in block.shape.world_boxes(block.pos, connected) {
+            objects.push((Cube::new(min, max, material).with_faces(faces(block.turn)), *group));
+        }
+    }
+    for (first, end, kind) in merge_blocks(&mergeable) {
+        let (name, group, turn) = kinds[kind];
+        let corner = |pos: [i32; 3]| Vec3::new(pos[0] as Float, pos[1] as Float, pos[2] as Float) * BLOCK_SIZE;
+        objects.push((Cube::new(corner(first), corner(end), material(name)?).with_faces(faces(turn)), group));
+    }
+    Ok(objects)
+}
+
+pub fn demo_scene(missing: &mut Vec<String>) -> Vec<Cube> {
+    demo(missing).objects
+}
+
+// La escena de demostración con la casa y el árbol como bloques editables, detrás de las cajas sueltas
+fn demo(missing: &mut Vec<String>) -> LoadedScene {
+    let materials = materials(missing);
+    let grass = &materials["grass"];
+    let wood = &materials["wood"];
+    let glowstone = &materials["glowstone"];
+
+    // Define los objetos que componen la escena
+    let mut objects = vec![
+        Cube::new(Vec3::new(-4.0, -0.5, -4.0), Vec3::new(4.0, 0.0, 4.0), grass), // Base de cesped
+
+        // Bloque de piedra luminosa al lado de la casa
+        Cube::new(Vec3::new(2.0, 0.0, -1.0), Vec3::new(2.5, 0.5, -0.5), glowstone),
+    ];
+
+    // Escalones de madera delante de la puerta, subiendo hacia la casa
+    let stairs = BlockShape::Stairs { facing: Facing::North, upside_down: false };
+    for x in [-1, 0] {
+        for (min, max) in stairs.world_boxes([x, 0, 3], [false; 4]) {
+            objects.push(Cube::new(min, max, wood));
+        }
+    }
+    let fixed = objects.len();
+
+    // La casa, con la mitad delantera de la ventana de vidrio tintado rojo, y el árbol delante
+    let mut blocks = structures::house([-3, 0, -3], 6, 6, 4, "wood", RoofStyle::Stepped).expect("the demo house is valid");
+    for block in &mut blocks {
+        if block.material == structures::WINDOW_MATERIAL && block.pos[2] == 0 {
+            block.material = "red_stained_glass".to_string();
+        }
+    }
+    blocks.extend(structures::tree([-6, 0, 6], 4, 1).expect("the demo tree is valid"));
+    let placed: Vec<_> = blocks.iter().map(|block| (block.clone(), None)).collect();
+    let built = block_objects(&placed, &materials, true).expect("the demo uses known materials");
+    objects.extend(built.into_iter().map(|(object, _)| object));
+
+    let blocks = SceneBlocks::new(blocks, fixed, materials, true);
+    LoadedScene { blocks: Some(blocks), ..LoadedScene::new(objects) }
+}
+
+pub fn generate_lights_from_emissive_objects(objects: &[Cube]) -> Vec<Light> {
+    objects.iter()
+        .filter(|cube| cube.material.emission != Color::new(0, 0, 0))
+        .map(|cube| {
+            let position = (cube.min + cube.max) * 0.5;
+            let intensity = cube.material.emission.intensity() * cube.material.emission_strength * 10.0;  // Aumentamos significativamente la intensidad
+            let radius = (cube.max - cube.min).magnitude() * 10.0;  // Aumentamos aún más el radio
+            Light::new(position, cube.material.emission, intensity, radius)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{Axis, Direction};
+    use crate::cube::FaceDescriptor;
+    use crate::edit::Region;
+    use crate::framebuffer::Framebuffer;
+    use crate::render::render;
+    use crate::settings::RenderSettings;
+
+    #[test]
+    fn missing_texture_renders_as_magenta_checkerboard() {
+        let contents = r#"
+            [[texture]]
+            material = "stone"
+            path = "assets/does_not_exist.png"
+
+            [[cube]]
+            min = [-1.0, -1.0, -1.0]
+            max = [1.0, 1.0, 1.0]
+            material = "stone"
+        "#;
+        let mut missing = Vec::new();
+        let objects = parse(contents, None, &mut missing).expect("a missing texture should not fail the scene").objects;
+        assert!(missing.contains(&"assets/does_not_exist.png".to_string()));
+
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 4.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let lights = [Light::new(Vec3::new(0.0, 0.0, 4.0), Color::new(255, 255, 255), 1.0, 10.0)];
+        let settings = RenderSettings { width: 32, height: 32, ..RenderSettings::default() };
+        let mut framebuffer = Framebuffer::new(settings.width, settings.height);
+        let scene = Scene::new(objects, lights.to_vec(), Arc::new(Sky::Procedural), camera, Ambient::flat(Color::new(0, 0, 0)));
+        render(&mut framebuffer, &scene, &settings);
+
+        let is_magenta = |pixel: &u32| {
+            let (r, g, b) = ((pixel >> 16) & 0xff, (pixel >> 8) & 0xff, pixel & 0xff);
+            r > 100 && b > 100 && g * 4 < r.min(b)
+        };
+        assert!(framebuffer.buffer.iter().any(is_magenta), "no magenta pixels in the frame");
+    }
+
+    #[test]
+    fn parallel_texture_loads_keep_their_order() {
+        // Later items finish first; textures and missing paths must still follow the input order
+        let sizes: Vec<u32> = (1..=32).collect();
+        let mut missing = Vec::new();
+        let textures = load_textures(&sizes, &mut missing, |&size, log| {
+            std::thread::sleep(std::time::Duration::from_millis(u64::from(33 - size)));
+            if size % 2 == 0 {
+                log.missing.push(format!("texture {}", size));
+            }
+            Texture::checkerboard(1, size, Color::new(0, 0, 0), Color::new(0, 0, 0))
+        });
+        assert_eq!(textures.iter().map(|texture| texture.width).collect::<Vec<_>>(), sizes);
+        assert_eq!(missing, (1..=16).map(|n| format!("texture {}", n * 2)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn light_groups_resolve_to_object_indices() {
+        let contents = r#"
+            [[cube]]
+            min = [0.0, 0.0, 0.0]
+            max = [1.0, 1.0, 1.0]
+            material = "stone"
+            group = "outside"
+
+            [[block]]
+            pos = [4, 0, 0]
+            material = "wood"
+            group = "interior"
+
+            [[light]]
+            position = [0.0, 3.0, 0.0]
+            include = ["interior"]
+            cast_shadows = false
+
+            [[light]]
+            position = [0.0, 3.0, 0.0]
+            exclude = ["interior", "outside"]
+            enabled = false
+        "#;
+        let lights = parse(contents, None, &mut Vec::new()).unwrap().lights;
+        assert_eq!(lights[0].link, LightLink::Only(HashSet::from([1])));
+        assert!(!lights[0].cast_shadows && lights[0].enabled);
+        assert_eq!(lights[1].link, LightLink::Except(HashSet::from([0, 1])));
+        assert!(!lights[1].enabled);
+
+        let unknown = "[[light]]\nposition = [0.0, 0.0, 0.0]\ninclude = [\"attic\"]";
+        let error = parse(unknown, None, &mut Vec::new()).err().expect("an unknown group should fail the scene");
+        assert!(error.contains("attic"), "{}", error);
+    }
+
+    #[test]
+    fn printed_lights_parse_back() {
+        let mut light = Light::new(Vec3::new(4.1, 1.0, -5.25), Color::new(255, 180, 90), 1.5, 8.0).with_shadows(false).with_falloff(Falloff::Quadratic);
+        light.enabled = false;
+        let lights = parse(&light.to_toml(), None, &mut Vec::new()).unwrap().lights;
+        let parsed = &lights[0];
+        assert!((parsed.position - light.position).magnitude() < 1e-3);
+        assert_eq!((parsed.color, parsed.intensity, parsed.radius), (light.color, light.intensity, light.radius));
+        assert!(!parsed.enabled && !parsed.cast_shadows);
+        assert_eq!(parsed.falloff, Falloff::Quadratic);
+
+        let unknown = "[[light]]\nposition = [0.0, 0.0, 0.0]\nfalloff = \"cubic\"";
+        let error = parse(unknown, None, &mut Vec::new()).err().expect("an unknown falloff should fail the scene");
+        assert!(error.contains("Unknown falloff 'cubic'"), "{}", error);
+    }
+
+    #[test]
+    fn weather_reaches_every_object() {
+        let contents = r#"
+            weather = "snow"
+            snow_coverage = 0.5
+
+            [[cube]]
+            min = [0.0, 0.0, 0.0]
+            max = [1.0, 1.0, 1.0]
+            material = "stone"
+
+            [[block]]
+            pos = [4, 0, 0]
+            material = "leaves"
+        "#;
+        let objects = parse(contents, None, &mut Vec::new()).unwrap().objects;
+        assert!(objects.iter().all(|object| object.material.snow_cover == 0.5));
+
+        let objects = parse(&contents.replace("\"snow\"", "\"clear\""), None, &mut Vec::new()).unwrap().objects;
+        assert!(objects.iter().all(|object| object.material.snow_cover == 0.0));
+        let objects = parse(&contents.replace("\"snow\"", "\"rain\""), None, &mut Vec::new()).unwrap().objects;
+        assert!(objects.iter().all(|object| object.material.snow_cover == 0.0 && object.material.rain.is_some()));
+        assert!(parse("weather = \"hail\"", None, &mut Vec::new()).is_err());
+        assert!(parse("weather = \"snow\"\nsnow_coverage = 2.0", None, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn fences_reach_for_fences_and_full_blocks() {
+        // Stone to the east and another fence to the west connect; the slab to the north and the
+        // empty cell to the south don't
+        let contents = r#"
+            [[block]]
+            pos = [0, 0, 0]
+            material = "wood"
+            shape = "fence"
+
+            [[block]]
+            pos = [1, 0, 0]
+            material = "stone"
+
+            [[block]]
+            pos = [-1, 0, 0]
+            material = "wood"
+            shape = "fence"
+
+            [[block]]
+            pos = [0, 0, -1]
+            material = "stone"
+            shape = "slab"
+        "#;
+        let objects = parse(contents, None, &mut Vec::new()).unwrap().objects;
+        let fence = &objects[..5];
+        assert!(fence.iter().all(|part| part.min.z > 0.0 && part.max.z < BLOCK_SIZE), "a rail reaches north or south");
+        assert_eq!(fence.iter().filter(|part| part.max.x == BLOCK_SIZE).count(), 2);
+        assert_eq!(fence.iter().filter(|part| part.min.x == 0.0).count(), 2);
+        // The western fence only reaches back east, and the slab and the stone come after it
+        assert_eq!(objects.len(), 5 + 3 + 1 + 1);
+    }
+
+    #[test]
+    fn malformed_scenes_are_scene_errors() {
+        let error = from_str("[[cube]]\nmin = [0.0, 0.0").err().expect("the TOML is cut short");
+        assert!(matches!(error, RayTracerError::Scene(_)), "{:?}", error);
+        let error = from_str("[[cube]]\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 1.0, 1.0]\nmaterial = \"marble\"").err().expect("marble isn't a material");
+        assert!(matches!(error, RayTracerError::Scene(_)), "{:?}", error);
+        let error = load("/nonexistent-raytracer-dir/scene.toml").err().expect("the scene doesn't exist");
+        assert!(matches!(error, RayTracerError::Asset(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn absurd_sizes_and_positions_are_scene_errors() {
+        // Found by fuzzing: these overflowed an i32 or tried to allocate billions of blocks
+        for contents in [
+            "[[block]]\npos = [2147483647, 0, 0]\nmaterial = \"wood\"\nshape = \"fence\"",
+            "[[structure]]\nkind = \"house\"\nat = [0, 0, 0]\nwidth = 2000000000",
+            "[[structure]]\nkind = \"fence_ring\"\nat = [2147483000, 0, 0]",
+            "[terrain]\nsize = 16\nheight = 2000000000",
+        ] {
+            let error = from_str(contents).err().unwrap_or_else(|| panic!("accepted {:?}", contents));
+            assert!(matches!(error, RayTracerError::Scene(_)), "{:?}", error);
+        }
+        let edge = format!("[[block]]\npos = [{}, 0, 0]\nmaterial = \"wood\"\nshape = \"fence\"", structures::MAX_COORDINATE);
+        assert!(from_str(&edge).is_ok());
+    }
+
+    #[test]
+    fn material_entries_set_block_variation() {
+        let contents = r#"
+            [[material]]
+            name = "grass"
+            randomize_rotation = true
+            tint_jitter = 0.05
+
+            [[block]]
+            pos = [0, 0, 0]
+            material = "grass"
+
+            [[block]]
+            pos = [1, 0, 0]
+            material = "stone"
+        "#;
+        let objects = parse(contents, None, &mut Vec::new()).unwrap().objects;
+        assert!(objects[0].material.randomize_rotation && objects[0].material.tint_jitter == 0.05);
+        assert!(!objects[1].material.randomize_rotation && objects[1].material.tint_jitter == 0.0);
+        assert!(parse("[[material]]\nname = \"marble\"", None, &mut Vec::new()).is_err());
+        assert!(parse("[[material]]\nname = \"grass\"\ntint_jitter = 1.5", None, &mut Vec::new()).is_err());
+
+        let glass = parse("[[material]]\nname = \"glass\"\ndispersion = 0.05\n[[block]]\npos = [0, 0, 0]\nmaterial = \"glass\"", None, &mut Vec::new()).unwrap().objects;
+        assert_eq!(glass[0].material.dispersion, 0.05);
+        assert!(parse("[[material]]\nname = \"glass\"\ndispersion = -0.1", None, &mut Vec::new()).is_err());
+    }
+    #[test]
+    fn merged_blocks_render_the_same_as_separate_ones() {
+        // A 16x1x16 stone floor, a 2x2x2 wood cube on it, a slab and a glowstone block
+        let mut contents = String::new();
+        for x in 0..16 {
+            for z in 0..16 {
+                contents += &format!("[[block]]\npos = [{}, 0, {}]\nmaterial = \"stone\"\n", x - 8, z - 8);
+            }
+        }
+        for (x, y, z) in (0..8).map(|i| (i & 1, 1 + (i >> 1 & 1), -(i >> 2))) {
+            contents += &format!("[[block]]\npos = [{}, {}, {}]\nmaterial = \"wood\"\n", x, y, z);
+        }
+        contents += "[[block]]\npos = [-3, 1, 0]\nmaterial = \"wood\"\nshape = \"slab\"\n";
+        contents += "[[block]]\npos = [3, 1, 1]\nmaterial = \"glowstone\"\n";
+
+        let merged = parse(&contents, None, &mut Vec::new()).unwrap().objects;
+        let separate = parse(&format!("merge_blocks = false\n{}", contents), None, &mut Vec::new()).unwrap().objects;
+        assert_eq!(separate.len(), 266);
+        assert_eq!(merged.len(), 4);
+
+        let camera = Camera::new(Vec3::new(2.0, 3.0, 4.0), Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let settings = RenderSettings { width: 48, height: 36, ao_samples: 4, ..RenderSettings::default() };
+        let frame = |objects: &[Cube]| {
+            let mut lights = vec![Light::new(Vec3::new(3.0, 5.0, 2.0), Color::new(255, 255, 255), 1.0, 10.0)];
+            lights.extend(generate_lights_from_emissive_objects(objects));
+            let scene = Scene::new(objects.to_vec(), lights, Arc::new(Sky::Procedural), camera.clone(), Ambient::flat(Color::new(40, 40, 60)));
+            let mut framebuffer = Framebuffer::new(settings.width, settings.height);
+            render(&mut framebuffer, &scene, &settings);
+            framebuffer.buffer
+        };
+        assert!(frame(&merged) == frame(&separate), "merging blocks changed the image");
+    }
+    fn warnings_for(contents: &str) -> Vec<SceneWarning> {
+        from_str(contents).expect("the scene should still load").warnings
+    }
+
+    #[test]
+    fn loading_normalizes_swapped_corners() {
+        let mut loaded = from_str("[[cube]]\nmin = [0.0, 1.0, 0.0]\nmax = [1.0, 0.0, 1.0]\nmaterial = \"stone\"").unwrap();
+        assert_eq!(loaded.warnings, Vec::new());
+        assert_eq!((loaded.objects[0].min, loaded.objects[0].max), (Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0)));
+
+        let cube = &mut loaded.objects[0];
+        std::mem::swap(&mut cube.min.y, &mut cube.max.y);
+        let warnings = loaded.validate();
+        assert_eq!(warnings, vec![SceneWarning::InvertedCube { index: 0, axis: 'y' }]);
+        assert!(warnings[0].to_string().contains("object 0 has min > max on y"));
+    }
+
+    #[test]
+    fn validation_flags_zero_volume_cubes() {
+        let warnings = warnings_for("[[cube]]\nmin = [0.0, 0.0, 0.5]\nmax = [1.0, 1.0, 0.5]\nmaterial = \"stone\"");
+        assert_eq!(warnings, vec![SceneWarning::ZeroVolumeCube { index: 0, axis: 'z' }]);
+    }
+
+    #[test]
+    fn validation_flags_materials_reflecting_more_than_they_get() {
+        let mut loaded = from_str("[[cube]]\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 1.0, 1.0]\nmaterial = \"glass\"").unwrap();
+        assert!(loaded.warnings.is_empty(), "glass is exactly at the limit: {:?}", loaded.warnings);
+        loaded.objects[0].material.properties[2] = 0.7;
+        assert_eq!(loaded.validate(), vec![SceneWarning::TooMuchReflection { index: 0, reflectivity: 0.7, transparency: 0.5 }]);
+    }
+
+    #[test]
+    fn validation_flags_lights_with_zero_intensity() {
+        let warnings = warnings_for("[[light]]\nposition = [0.0, 2.0, 0.0]\n\n[[light]]\nposition = [1.0, 2.0, 0.0]\nintensity = 0.0");
+        assert_eq!(warnings, vec![SceneWarning::DarkLight { index: 1 }]);
+    }
+
+    #[test]
+    fn validation_flags_duplicate_cubes() {
+        let cube = "[[cube]]\nmin = [0.0, 0.0, 0.0]\nmax = [0.5, 0.5, 0.5]\nmaterial = \"stone\"\n";
+        let block = "[[block]]\npos = [0, 0, 0]\nmaterial = \"wood\"\n";
+        let warnings = warnings_for(&format!("{}{}{}", cube, cube, block));
+        assert_eq!(warnings, vec![SceneWarning::DuplicateCube { index: 1, first: 0 }, SceneWarning::DuplicateCube { index: 2, first: 0 }]);
+    }
+
+    #[test]
+    fn saved_demo_reloads_to_the_same_image() {
+        let mut demo = LoadedScene::new(demo_scene(&mut Vec::new()));
+        demo.lights.push(Light::new(Vec3::new(-2.0, 3.0, 2.0), Color::from_kelvin(3200.0), 0.8, 6.0).with_shadows(false));
+        demo.camera = Some(Camera::new(Vec3::new(2.0, 3.0, 6.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)));
+        let reloaded = from_str(&demo.to_toml().unwrap()).unwrap();
+        assert_eq!(reloaded.objects.len(), demo.objects.len());
+        assert_eq!(reloaded.lights.len(), 1);
+        let camera = reloaded.camera.clone().expect("the camera is saved");
+        assert!((camera.eye - Vec3::new(2.0, 3.0, 6.0)).magnitude() < 1e-4);
+
+        let settings = RenderSettings { width: 40, height: 30, ..RenderSettings::default() };
+        let frame = |loaded: &LoadedScene| {
+            let mut lights = loaded.lights.clone();
+            lights.extend(generate_lights_from_emissive_objects(&loaded.objects));
+            let scene = Scene::new(loaded.objects.iter().cloned(), lights, Arc::new(Sky::Procedural), camera.clone(), Ambient::flat(Color::new(40, 40, 60)));
+            let mut framebuffer = Framebuffer::new(settings.width, settings.height);
+            render(&mut framebuffer, &scene, &settings);
+            framebuffer.buffer
+        };
+        assert!(frame(&reloaded) == frame(&demo), "the saved scene renders differently");
+    }
+
+    // The library never links minifb, so this also runs under
+    // `cargo test --no-default-features --features headless` with no display server
+    #[test]
+    fn demo_renders_to_a_png_without_a_window() {
+        let loaded = load("demo").unwrap();
+        let mut lights = loaded.lights.clone();
+        lights.extend(generate_lights_from_emissive_objects(&loaded.objects));
+        let scene = Scene::new(loaded.objects.iter().cloned(), lights, Arc::new(Sky::Procedural), Camera::default_view(), Ambient::flat(Color::new(40, 40, 60)));
+        let settings = RenderSettings { width: 32, height: 24, ..RenderSettings::default() };
+        let mut framebuffer = Framebuffer::new(settings.width, settings.height);
+        render(&mut framebuffer, &scene, &settings);
+
+        let path = std::env::temp_dir().join("raytracer_minecraft_headless_demo.png");
+        framebuffer.save(&path.to_string_lossy()).unwrap();
+        let image = image::open(&path).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (32, 24));
+        assert!(image.pixels().any(|pixel| pixel != image.get_pixel(0, 0)), "the demo rendered as a flat image");
+    }
+
+    #[test]
+    fn saving_keeps_groups_portals_and_texture_paths() {
+        let contents = r#"
+            auto_probes = true
+
+            [[texture]]
+            material = "stone"
+            path = "assets/does_not_exist.png"
+            rotate = 90
+
+            [[cube]]
+            min = [0.0, 0.0, 0.0]
+            max = [0.5, 1.5, 0.1]
+            material = "portal"
+            portal = { target_offset = [3.0, 0.0, 0.0], rotation = 90.0 }
+
+            [[block]]
+            pos = [4, 0, 0]
+            material = "stone"
+            group = "interior"
+
+            [[light]]
+            position = [0.0, 3.0, 0.0]
+            include = ["interior"]
+
+            [[probe]]
+            position = [0.0, 2.0, 0.0]
+        "#;
+        let loaded = parse(contents, None, &mut Vec::new()).unwrap();
+        let misplaced = contents.replace("material = \"portal\"", "material = \"stone\"");
+        let error = parse(&misplaced, None, &mut Vec::new()).err().expect("only the portal material takes a portal");
+        assert!(error.contains("portal only applies to the portal material, not 'stone'"), "{}", error);
+        let saved = loaded.to_toml().unwrap();
+        assert!(saved.contains("assets/does_not_exist.png"), "{}", saved);
+        let reloaded = parse(&saved, None, &mut Vec::new()).unwrap();
+        assert_eq!(reloaded.lights[0].link, LightLink::Only(HashSet::from([1])));
+        let portal = reloaded.objects[0].material.portal.expect("the portal is saved");
+        assert!((portal.rotation - loaded.objects[0].material.portal.unwrap().rotation).abs() < 1e-5);
+        assert_eq!(reloaded.objects[1].material.textures[0].uv_transform.rotate, 90);
+        assert_eq!((reloaded.probes.clone(), reloaded.auto_probes), (vec![Vec3::new(0.0, 2.0, 0.0)], true));
+
+        let mut handmade = LoadedScene::new(vec![Cube::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), &Material::black())]);
+        assert!(handmade.to_toml().is_err());
+        handmade.objects.clear();
+        assert!(handmade.to_toml().is_ok());
+    }
+
+    #[test]
+    fn structures_load_like_their_blocks() {
+        let tree = "[[structure]]\nkind = \"tree\"\nat = [0, 0, 0]\nheight = 3\ncanopy_radius = 0\ngroup = \"garden\"";
+        let loaded = parse(tree, None, &mut Vec::new()).unwrap();
+        // Trunk, two leaves above it and the top, merged per material
+        assert_eq!(loaded.objects.len(), 2);
+        assert_eq!(loaded.groups["garden"], HashSet::from([0, 1]));
+
+        assert!(parse("[[structure]]\nkind = \"castle\"\nat = [0, 0, 0]", None, &mut Vec::new()).is_err());
+        assert!(parse("[[structure]]\nkind = \"pyramid\"\nat = [0, 0, 0]\nbase = 0", None, &mut Vec::new()).is_err());
+        assert!(parse("[[structure]]\nkind = \"house\"\nat = [0, 0, 0]\nroof = \"dome\"", None, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn turned_blocks_merge_by_turn_and_save() {
+        let contents = r#"
+            [[block]]
+            pos = [0, 0, 0]
+            material = "treewood"
+            axis = "x"
+
+            [[block]]
+            pos = [1, 0, 0]
+            material = "treewood"
+            axis = "x"
+
+            [[block]]
+            pos = [2, 0, 0]
+            material = "treewood"
+
+            [[block]]
+            pos = [0, 1, 0]
+            material = "stone"
+            facing = "east"
+        "#;
+        let loaded = parse(contents, None, &mut Vec::new()).unwrap();
+        let faces = |loaded: &LoadedScene| {
+            let mut faces: Vec<(String, Float, [FaceDescriptor; 6])> = loaded.objects.iter()
+                .map(|object| (object.material.name.clone(), object.max.x - object.min.x, object.faces))
+                .collect();
+            faces.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+            faces
+        };
+        // The two logs along x merge, the standing one stays apart
+        let expected = vec![
+            ("stone".to_string(), BLOCK_SIZE, BlockTurn::Facing(Direction::East).face_descriptors()),
+            ("treewood".to_string(), BLOCK_SIZE, Cube::unturned_faces()),
+            ("treewood".to_string(), BLOCK_SIZE * 2.0, BlockTurn::Axis(Axis::X).face_descriptors()),
+        ];
+        assert_eq!(faces(&loaded), expected);
+        let reloaded = parse(&loaded.to_toml().unwrap(), None, &mut Vec::new()).unwrap();
+        assert_eq!(faces(&reloaded), expected);
+
+        let block = |options: &str| parse(&format!("[[block]]\npos = [0, 0, 0]\nmaterial = \"stone\"\n{}", options), None, &mut Vec::new());
+        assert!(block("facing = \"up\"").is_ok());
+        assert!(block("axis = \"w\"").is_err());
+        assert!(block("facing = \"up\"\naxis = \"x\"").is_err());
+        assert!(block("shape = \"slab\"\naxis = \"x\"").is_err());
+        assert!(block("shape = \"stairs\"\naxis = \"x\"").is_err());
+    }
+
+    #[test]
+    fn demo_scene_is_clean() {
+        let mut missing = Vec::new();
+        let demo = LoadedScene::new(demo_scene(&mut missing));
+        assert_eq!(demo.validate(), Vec::new());
+    }
+
+    #[test]
+    fn undone_block_edits_rebuild_the_same_objects() {
+        let boxes = |loaded: &LoadedScene| {
+            let mut boxes: Vec<String> = loaded.objects.iter()
+                .map(|object| format!("{:?} {:?} {}", object.min, object.max, object.material.name))
+                .collect();
+            boxes.sort();
+            boxes
+        };
+        let mut demo = demo(&mut Vec::new());
+        let original = boxes(&demo);
+        let lights = generate_lights_from_emissive_objects(&demo.objects).len();
+        let mut history = EditHistory::new(10);
+
+        assert!(demo.edit_blocks(&mut history, &Edit::Place(PlacedBlock::full([0, 6, 0], "glowstone"))).unwrap());
+        assert_eq!(generate_lights_from_emissive_objects(&demo.objects).len(), lights + 1);
+        assert!(demo.edit_blocks(&mut history, &Edit::Fill { region: Region::new([-3, 0, -3], [2, 1, 2]), material: None }).unwrap());
+        let edited = boxes(&demo);
+        assert_ne!(edited, original);
+
+        let error = demo.edit_blocks(&mut history, &Edit::SetMaterial([-3, 2, -3], "unobtainium".to_string())).unwrap_err();
+        assert!(error.contains("Unknown material"), "{}", error);
+        assert!(!demo.edit_blocks(&mut history, &Edit::Remove([40, 0, 40])).unwrap());
+        assert_eq!(boxes(&demo), edited);
+
+        assert!(demo.undo_blocks(&mut history).unwrap());
+        assert!(demo.undo_blocks(&mut history).unwrap());
+        assert!(!demo.undo_blocks(&mut history).unwrap());
+        assert_eq!(boxes(&demo), original);
+        assert!(demo.redo_blocks(&mut history).unwrap() && demo.redo_blocks(&mut history).unwrap());
+        assert_eq!(boxes(&demo), edited);
+
+        let mut plain = LoadedScene::new(demo_scene(&mut Vec::new()));
+        assert!(plain.edit_blocks(&mut history, &Edit::Remove([0, 0, 0])).is_err(), "only scenes with blocks can be edited");
+    }
+
+    #[test]
+    fn retextured_blocks_survive_saving_and_undo() {
+        let contents = "[[block]]\npos = [0, 0, 0]\nmaterial = \"stone\"\n[[block]]\npos = [2, 0, 0]\nmaterial = \"stone\"\n";
+        let mut loaded = from_str(contents).unwrap();
+        let names = |loaded: &LoadedScene| {
+            let mut names: Vec<String> = loaded.objects.iter().map(|object| object.material.name.clone()).collect();
+            names.sort();
+            names
+        };
+        let blocks = loaded.blocks.as_ref().unwrap();
+        assert!(blocks.material_names().windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(blocks.material_names().iter().all(|name| blocks.has_material(name)));
+
+        let mut history = EditHistory::new(10);
+        assert!(loaded.edit_blocks(&mut history, &Edit::SetMaterial([2, 0, 0], "wood".to_string())).unwrap());
+        assert_eq!(names(&loaded), ["stone", "wood"]);
+        let reloaded = from_str(&loaded.to_toml().unwrap()).unwrap();
+        assert_eq!(names(&reloaded), ["stone", "wood"], "materials are saved by name");
+
+        assert!(loaded.undo_blocks(&mut history).unwrap());
+        assert_eq!(names(&loaded), ["stone", "stone"]);
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use raytracer_minecraft::camera::Camera;
+use raytracer_minecraft::day_night::DayNightCycle;
+use raytracer_minecraft::framebuffer::Framebuffer;
+use raytracer_minecraft::math::{Float, Vec3};
+use raytracer_minecraft::metadata::RenderMetadata;
+use raytracer_minecraft::render::render;
+use raytracer_minecraft::scene;
+use raytracer_minecraft::settings::RenderSettings;
+use raytracer_minecraft::sky::Sky;
+
+// Límites para que una sola petición no acapare el servidor
+const MAX_WIDTH: usize = 1920;
+const MAX_HEIGHT: usize = 1080;
+const MAX_SPP: u32 = 64;
+const QUEUE_CAPACITY: usize = 16;
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(60);
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+const CACHE_CAPACITY: usize = 32;
+// Conexiones que se leen a la vez, cada una en su hilo; las que pasen de aquí se rechazan
+const MAX_CONNECTIONS: usize = 64;
+
+// Sin --bind solo se atiende a esta máquina: el servidor no tiene autenticación y renderiza de uno en uno
+pub const DEFAULT_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+// Parámetros de una petición GET /render?eye=x,y,z&target=x,y,z&fov=60&roll=0&width=640&height=480&spp=1
+struct RenderRequest {
+    eye: [Float; 3],
+    target: [Float; 3],
+    fov: Float,
+    roll: Float,
+    width: usize,
+    height: usize,
+    spp: u32,
+}
+
+impl RenderRequest {
+    fn parse(query: &str) -> Result<Self, String> {
+        let mut request = RenderRequest {
+            eye: [0.0, 0.0, 6.5],
+            target: [0.0, 0.0, 0.0],
+            fov: 60.0,
+            roll: 0.0,
+            width: 640,
+            height: 480,
+            spp: 1,
+        };
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "eye" => request.eye = parse_vec3(value).ok_or("eye must be x,y,z")?,
+                "target" => request.target = parse_vec3(value).ok_or("target must be x,y,z")?,
+                "fov" => request.fov = value.parse().map_err(|_| "fov must be a number")?,
+                "roll" => request.roll = value.parse().map_err(|_| "roll must be a number")?,
+                "width" => request.width = value.parse().map_err(|_| "width must be an integer")?,
+                "height" => request.height = value.parse().map_err(|_| "height must be an integer")?,
+                "spp" => request.spp = value.parse().map_err(|_| "spp must be an integer")?,
+                _ => return Err(format!("unknown parameter '{}'", key)),
+            }
+        }
+
+        if request.width == 0 || request.height == 0 || request.width > MAX_WIDTH || request.height > MAX_HEIGHT {
+            return Err(format!("resolution must be between 1x1 and {}x{}", MAX_WIDTH, MAX_HEIGHT));
+        }
+        if request.spp == 0 || request.spp > MAX_SPP {
+            return Err(format!("spp must be between 1 and {}", MAX_SPP));
+        }
+        if !(request.fov > 0.0 && request.fov < 180.0) {
+            return Err("fov must be between 0 and 180 degrees".to_string());
+        }
+        if !request.roll.is_finite() {
+            return Err("roll must be a finite number of degrees".to_string());
+        }
+        if request.eye == request.target {
+            return Err("eye and target must differ".to_string());
+        }
+        Ok(request)
+    }
+
+    // La petición entera normalizada: los números por sus bits, con -0 igual a 0, así que dos
+    // peticiones comparten entrada en la caché solo si piden exactamente el mismo render
+    fn cache_key(&self) -> CacheKey {
+        let [ex, ey, ez] = self.eye;
+        let [tx, ty, tz] = self.target;
+        let camera = [ex, ey, ez, tx, ty, tz, self.fov, self.roll].map(|value| (value as f64 + 0.0).to_bits());
+        CacheKey { camera, width: self.width, height: self.height, spp: self.spp }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct CacheKey {
+    camera: [u64; 8], // eye, target, fov y roll
+    width: usize,
+    height: usize,
+    spp: u32,
+}
+
+fn parse_vec3(value: &str) -> Option<[Float; 3]> {
+    let parts: Vec<Float> = value.split(',').map(|part| part.trim().parse().ok()).collect::<Option<_>>()?;
+    match parts[..] {
+        [x, y, z] if x.is_finite() && y.is_finite() && z.is_finite() => Some([x, y, z]),
+        _ => None,
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) {
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, content_type, body.len()
+    );
+    // Si el cliente ya se fue no hay nada más que hacer
+    let _ = stream.write_all(header.as_bytes()).and_then(|_| stream.write_all(body));
+}
+
+fn respond_error(stream: &mut TcpStream, status: &str, message: &str) {
+    respond(stream, status, "text/plain; charset=utf-8", format!("{}\n", message).as_bytes());
+}
+
+// Lee la línea de petición y descarta las cabeceras; devuelve la ruta solicitada
+fn read_request_target(stream: &TcpStream) -> Result<String, String> {
+    // Nunca se leen más de MAX_HEADER_BYTES + 1 bytes, así que una línea sin salto no puede
+    // llenar la memoria; pasarse del límite se ve en la cuenta
+    let limited = stream.try_clone().map_err(|e| e.to_string())?.take(MAX_HEADER_BYTES as u64 + 1);
+    let mut reader = BufReader::new(limited);
+    let mut request_line = String::new();
+    let mut header_bytes = reader.read_line(&mut request_line).map_err(|e| e.to_string())?;
+    loop {
+        if header_bytes > MAX_HEADER_BYTES {
+            return Err("request headers too large".to_string());
+        }
+        let mut line = String::new();
+        let read = reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        header_bytes += read;
+        if read == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => Ok(target.to_string()),
+        _ => Err("only GET requests are supported".to_string()),
+    }
+}
+
+// Lee y valida la petición de una conexión y la pasa a la cola de render, o responde con el error
+fn accept(mut stream: TcpStream, sender: &SyncSender<(TcpStream, RenderRequest, Instant)>) {
+    let _ = stream.set_read_timeout(Some(SOCKET_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(SOCKET_TIMEOUT));
+
+    let target = match read_request_target(&stream) {
+        Ok(target) => target,
+        Err(e) => return respond_error(&mut stream, "400 Bad Request", &e),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    if path != "/render" {
+        return respond_error(&mut stream, "404 Not Found", "use GET /render?eye=x,y,z&target=x,y,z&fov=60&width=640&height=480&spp=1");
+    }
+    let request = match RenderRequest::parse(query) {
+        Ok(request) => request,
+        Err(e) => return respond_error(&mut stream, "400 Bad Request", &e),
+    };
+
+    match sender.try_send((stream, request, Instant::now())) {
+        Ok(()) => {}
+        Err(TrySendError::Full((mut stream, _, _))) => respond_error(&mut stream, "503 Service Unavailable", "render queue is full"),
+        Err(TrySendError::Disconnected((mut stream, _, _))) => respond_error(&mut stream, "503 Service Unavailable", "render worker stopped"),
+    }
+}
+
+// Sirve renders PNG de la escena cargada en `address`. Cada conexión se lee en su propio hilo, para
+// que un cliente lento no retenga a los demás, y las peticiones se encolan y se renderizan de una en una.
+pub fn serve(address: IpAddr, port: u16, scene_name: &str, defaults: &RenderSettings, sky: Sky, strict_assets: bool, strict: bool) -> Result<(), String> {
+    let loaded = scene::load(scene_name)?;
+    if strict_assets && !loaded.missing_textures.is_empty() {
+        return Err(format!("Missing textures: {}", loaded.missing_textures.join(", ")));
+    }
+    if strict && !loaded.warnings.is_empty() {
+        return Err(format!("Scene '{}' has {} warning(s) and --strict is set", scene_name, loaded.warnings.len()));
+    }
+    let mut scene = loaded.scene(Arc::new(sky), &DayNightCycle::new());
+    let address = SocketAddr::new(address, port);
+    let listener = TcpListener::bind(address)
+        .map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+    println!("Serving scene '{}' on http://{}/render", scene_name, address);
+
+    let defaults = defaults.clone();
+    let scene_name = scene_name.to_string();
+    let (sender, receiver) = sync_channel::<(TcpStream, RenderRequest, Instant)>(QUEUE_CAPACITY);
+
+    std::thread::spawn(move || {
+        let mut cache: HashMap<CacheKey, Vec<u8>> = HashMap::new();
+        let mut cache_order: VecDeque<CacheKey> = VecDeque::new();
+
+        for (mut stream, request, queued_at) in receiver {
+            if queued_at.elapsed() > QUEUE_TIMEOUT {
+                respond_error(&mut stream, "503 Service Unavailable", "timed out waiting in the render queue");
+                continue;
+            }
+
+            let key = request.cache_key();
+            if let Some(png) = cache.get(&key) {
+                respond(&mut stream, "200 OK", "image/png", png);
+                continue;
+            }
+
+            let start = Instant::now();
+            scene.camera = Camera::new(
+                Vec3::new(request.eye[0], request.eye[1], request.eye[2]),
+                Vec3::new(request.target[0], request.target[1], request.target[2]),
+                Vec3::new(0.0, 1.0, 0.0),
+            );
+            scene.camera.fov = request.fov.to_radians();
+            scene.camera.roll = request.roll.to_radians();
+
+            let settings = RenderSettings {
+                width: request.width,
+                height: request.height,
+                samples_per_pixel: request.spp,
+                ..defaults.clone()
+            };
+            let mut framebuffer = Framebuffer::new(request.width, request.height);
+            render(&mut framebuffer, &scene, &settings);
+
+            match framebuffer.to_png_with_metadata(&RenderMetadata::new(&scene_name, &scene.camera, &settings, start.elapsed())) {
+                Ok(png) => {
+                    println!("Rendered {}x{} ({} spp) in {:.2}s", request.width, request.height, request.spp, start.elapsed().as_secs_f32());
+                    respond(&mut stream, "200 OK", "image/png", &png);
+                    if cache_order.len() >= CACHE_CAPACITY {
+                        if let Some(oldest) = cache_order.pop_front() {
+                            cache.remove(&oldest);
+                        }
+                    }
+                    cache_order.push_back(key);
+                    cache.insert(key, png);
+                }
+                Err(e) => respond_error(&mut stream, "500 Internal Server Error", &e.to_string()),
+            }
+        }
+    });
+
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            let _ = stream.set_write_timeout(Some(SOCKET_TIMEOUT));
+            respond_error(&mut stream, "503 Service Unavailable", "too many connections");
+            continue;
+        }
+        let (sender, connections) = (sender.clone(), Arc::clone(&connections));
+        std::thread::spawn(move || {
+            accept(stream, &sender);
+            connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    Ok(())
+}
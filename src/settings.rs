@@ -0,0 +1,593 @@
+use std::sync::Arc;
+
+use crate::camera::DEFAULT_EYE_SEPARATION;
+use crate::color::Color;
+use crate::probes::ReflectionProbes;
+use crate::math::Float;
+use crate::sky::Fog;
+
+// Filtro usado al muestrear las texturas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+}
+
+impl TextureFilter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextureFilter::Nearest => "nearest",
+            TextureFilter::Bilinear => "bilinear",
+        }
+    }
+}
+
+impl std::str::FromStr for TextureFilter {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "nearest" => Ok(TextureFilter::Nearest),
+            "bilinear" => Ok(TextureFilter::Bilinear),
+            _ => Err(format!("Unknown texture filter '{}' (expected nearest or bilinear)", name)),
+        }
+    }
+}
+
+// Cómo se eligen las direcciones al reunir la luz del mapa de entorno
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnvSampling {
+    Cosine,     // Hemisferio con distribución coseno, sin mirar el mapa
+    Importance, // Proporcional a la luminancia del mapa
+}
+
+impl EnvSampling {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnvSampling::Cosine => "cosine",
+            EnvSampling::Importance => "importance",
+        }
+    }
+}
+
+impl std::str::FromStr for EnvSampling {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "cosine" => Ok(EnvSampling::Cosine),
+            "importance" => Ok(EnvSampling::Importance),
+            _ => Err(format!("Unknown environment sampling '{}' (expected cosine or importance)", name)),
+        }
+    }
+}
+
+// Cómo se reparten las muestras de antialiasing, sombras suaves y oclusión ambiental
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sampler {
+    Regular,    // Los mismos patrones fijos en todos los píxeles
+    Stratified, // Un punto distinto por píxel dentro de cada celda de la cuadrícula
+    BlueNoise,  // Como stratified, pero la primera muestra de cada píxel sale de una máscara de ruido azul
+    Halton,     // Secuencia de Halton rotada por píxel: converge antes en los renders finales
+}
+
+impl Sampler {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sampler::Regular => "regular",
+            Sampler::Stratified => "stratified",
+            Sampler::BlueNoise => "blue_noise",
+            Sampler::Halton => "halton",
+        }
+    }
+}
+
+impl std::str::FromStr for Sampler {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "regular" => Ok(Sampler::Regular),
+            "stratified" => Ok(Sampler::Stratified),
+            "blue_noise" => Ok(Sampler::BlueNoise),
+            "halton" => Ok(Sampler::Halton),
+            _ => Err(format!("Unknown sampler '{}' (expected regular, stratified, blue_noise or halton)", name)),
+        }
+    }
+}
+
+// Orden en que se renderizan los tiles de la imagen; con muestras altas, lo primero que se ve
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileOrder {
+    RowMajor, // Por filas, de arriba abajo
+    Spiral,   // En espiral desde el centro, donde suele estar lo que importa
+    Hilbert,  // Siguiendo una curva de Hilbert, por zonas compactas
+}
+
+impl TileOrder {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TileOrder::RowMajor => "row_major",
+            TileOrder::Spiral => "spiral",
+            TileOrder::Hilbert => "hilbert",
+        }
+    }
+}
+
+impl std::str::FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "row_major" => Ok(TileOrder::RowMajor),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(format!("Unknown tile order '{}' (expected row_major, spiral or hilbert)", name)),
+        }
+    }
+}
+
+// Dónde se trazan los rayos del modo shaded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    Cpu, // El render completo, con rayon
+    Gpu, // Sombreador de cómputo con wgpu (experimental); lo que no sabe hacer vuelve a la CPU
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Cpu => "cpu",
+            Backend::Gpu => "gpu",
+        }
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "cpu" => Ok(Backend::Cpu),
+            "gpu" if cfg!(feature = "gpu") => Ok(Backend::Gpu),
+            "gpu" => Err("The gpu backend needs a build with --features gpu".to_string()),
+            _ => Err(format!("Unknown backend '{}' (expected cpu or gpu)", name)),
+        }
+    }
+}
+
+// Cómo caen las muestras extra con la distancia al foco, medida en radios
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FocusFalloff {
+    Linear,   // De todo en el foco a nada en el radio
+    Gaussian, // Campana: cae despacio cerca del foco y queda en un 5% en el radio
+}
+
+impl FocusFalloff {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FocusFalloff::Linear => "linear",
+            FocusFalloff::Gaussian => "gaussian",
+        }
+    }
+
+    // Peso entre 0 y 1 de un punto a `distance` radios del foco
+    pub fn weight(&self, distance: f32) -> f32 {
+        match self {
+            FocusFalloff::Linear => (1.0 - distance).max(0.0),
+            FocusFalloff::Gaussian => (-3.0 * distance * distance).exp(),
+        }
+    }
+}
+
+impl std::str::FromStr for FocusFalloff {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "linear" => Ok(FocusFalloff::Linear),
+            "gaussian" => Ok(FocusFalloff::Gaussian),
+            _ => Err(format!("Unknown focus falloff '{}' (expected linear or gaussian)", name)),
+        }
+    }
+}
+
+// Curva que se aplica a cada canal del color final, en espacio lineal. Las dos curvas dejan el negro
+// y el blanco donde están: reinhard levanta los medios tonos y aplana las luces, aces da el
+// contraste de película
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapping {
+    None,
+    Reinhard,
+    Aces,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 3] = [ToneMapping::None, ToneMapping::Reinhard, ToneMapping::Aces];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapping::None => "none",
+            ToneMapping::Reinhard => "reinhard",
+            ToneMapping::Aces => "aces",
+        }
+    }
+
+    // Valor de un canal de 0 a 255 tal como se guarda (con gamma 2.2) después de la curva
+    pub fn apply(&self, value: f32) -> f32 {
+        // Aproximación de Narkowicz a la curva ACES
+        let aces = |x: f32| x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14);
+        let linear = (value / 255.0).max(0.0).powf(2.2);
+        let mapped = match self {
+            ToneMapping::None => return value,
+            ToneMapping::Reinhard => 2.0 * linear / (1.0 + linear),
+            ToneMapping::Aces => aces(linear) / aces(1.0),
+        };
+        mapped.powf(1.0 / 2.2) * 255.0
+    }
+}
+
+impl std::str::FromStr for ToneMapping {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        ToneMapping::ALL.into_iter().find(|mapping| mapping.name() == name)
+            .ok_or_else(|| format!("Unknown tone mapping '{}' (expected none, reinhard or aces)", name))
+    }
+}
+
+// Salida estereoscópica: una vista por ojo combinada en el mismo framebuffer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StereoMode {
+    Off,
+    Anaglyph,   // Rojo del ojo izquierdo, verde y azul del derecho, para gafas rojo/cian
+    SideBySide, // Ojo izquierdo en la mitad izquierda y derecho en la derecha, para visores VR
+}
+
+impl StereoMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StereoMode::Off => "off",
+            StereoMode::Anaglyph => "anaglyph",
+            StereoMode::SideBySide => "side_by_side",
+        }
+    }
+
+    pub fn next(&self) -> StereoMode {
+        match self {
+            StereoMode::Off => StereoMode::Anaglyph,
+            StereoMode::Anaglyph => StereoMode::SideBySide,
+            StereoMode::SideBySide => StereoMode::Off,
+        }
+    }
+}
+
+impl std::str::FromStr for StereoMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "off" => Ok(StereoMode::Off),
+            "anaglyph" => Ok(StereoMode::Anaglyph),
+            "side_by_side" => Ok(StereoMode::SideBySide),
+            _ => Err(format!("Unknown stereo mode '{}' (expected off, anaglyph or side_by_side)", name)),
+        }
+    }
+}
+
+// Canal alfa de los renders sin ventana: cuánto de cada píxel cubren los objetos de la escena
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+    Off,
+    Straight,      // El color es el de la parte cubierta, sin multiplicar por la cobertura
+    Premultiplied, // El color ya viene multiplicado por la cobertura, con el cielo en negro
+}
+
+impl AlphaMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlphaMode::Off => "off",
+            AlphaMode::Straight => "straight",
+            AlphaMode::Premultiplied => "premultiplied",
+        }
+    }
+}
+
+impl std::str::FromStr for AlphaMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "off" => Ok(AlphaMode::Off),
+            "straight" => Ok(AlphaMode::Straight),
+            "premultiplied" => Ok(AlphaMode::Premultiplied),
+            _ => Err(format!("Unknown alpha mode '{}' (expected off, straight or premultiplied)", name)),
+        }
+    }
+}
+
+// Partes del destello de lente que se dibujan
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlareElements {
+    pub streak: bool, // Raya horizontal que cruza la fuente
+    pub halo: bool,   // Anillo alrededor de la fuente
+    pub ghosts: bool, // Reflejos en la línea que va de la fuente al centro de la imagen
+}
+
+impl FlareElements {
+    pub const ALL: FlareElements = FlareElements { streak: true, halo: true, ghosts: true };
+
+    pub fn name(&self) -> String {
+        let names: Vec<&str> = [(self.streak, "streak"), (self.halo, "halo"), (self.ghosts, "ghosts")]
+            .into_iter()
+            .filter_map(|(on, name)| on.then_some(name))
+            .collect();
+        if names.is_empty() { "none".to_string() } else { names.join(",") }
+    }
+}
+
+// Lista separada por comas, como "streak,ghosts", o "none"
+impl std::str::FromStr for FlareElements {
+    type Err = String;
+
+    fn from_str(names: &str) -> Result<Self, String> {
+        let mut elements = FlareElements { streak: false, halo: false, ghosts: false };
+        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty() && *name != "none") {
+            match name {
+                "streak" => elements.streak = true,
+                "halo" => elements.halo = true,
+                "ghosts" => elements.ghosts = true,
+                _ => return Err(format!("Unknown flare element '{}' (expected streak, halo, ghosts or none)", name)),
+            }
+        }
+        Ok(elements)
+    }
+}
+
+// Qué se dibuja: la escena con sus materiales o la versión de arcilla para juzgar la composición
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderMode {
+    Shaded,
+    Clay, // Todo gris neutro, sin texturas ni reflejos, con oclusión ambiental y una luz principal suave
+    Toon, // Luz difusa en bandas, brillos duros y contornos oscuros
+    Fast, // Aproximado: luz de bloque propagada por la rejilla en lugar de rayos de sombra
+    Samples, // Depuración: cada píxel en gris según las muestras que ha recibido
+}
+
+impl RenderMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderMode::Shaded => "shaded",
+            RenderMode::Clay => "clay",
+            RenderMode::Toon => "toon",
+            RenderMode::Fast => "fast",
+            RenderMode::Samples => "samples",
+        }
+    }
+
+    pub fn next(&self) -> RenderMode {
+        match self {
+            RenderMode::Shaded => RenderMode::Clay,
+            RenderMode::Clay => RenderMode::Toon,
+            RenderMode::Toon => RenderMode::Fast,
+            RenderMode::Fast => RenderMode::Samples,
+            RenderMode::Samples => RenderMode::Shaded,
+        }
+    }
+}
+
+impl std::str::FromStr for RenderMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "shaded" => Ok(RenderMode::Shaded),
+            "clay" => Ok(RenderMode::Clay),
+            "toon" => Ok(RenderMode::Toon),
+            "fast" => Ok(RenderMode::Fast),
+            "samples" => Ok(RenderMode::Samples),
+            _ => Err(format!("Unknown render mode '{}' (expected shaded, clay, toon, fast or samples)", name)),
+        }
+    }
+}
+
+// Dónde se cruzan las miradas de los dos ojos
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Convergence {
+    Parallel, // Ejes paralelos: todo queda detrás de la pantalla
+    Shift,    // Ejes paralelos con la imagen desplazada para que el centro de la órbita quede en la pantalla
+    ToeIn,    // Cada ojo gira hacia el centro de la órbita
+}
+
+impl Convergence {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Convergence::Parallel => "parallel",
+            Convergence::Shift => "shift",
+            Convergence::ToeIn => "toe_in",
+        }
+    }
+}
+
+impl std::str::FromStr for Convergence {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "parallel" => Ok(Convergence::Parallel),
+            "shift" => Ok(Convergence::Shift),
+            "toe_in" => Ok(Convergence::ToeIn),
+            _ => Err(format!("Unknown convergence '{}' (expected parallel, shift or toe_in)", name)),
+        }
+    }
+}
+
+// Presets de calidad que agrupan varias opciones de render
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quality {
+    Draft,
+    Preview,
+    Final,
+}
+
+impl Quality {
+    pub const ALL: [Quality; 3] = [Quality::Draft, Quality::Preview, Quality::Final];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Quality::Draft => "draft",
+            Quality::Preview => "preview",
+            Quality::Final => "final",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Quality> {
+        Quality::ALL.into_iter().find(|quality| quality.name() == name)
+    }
+
+    pub fn next(&self) -> Quality {
+        match self {
+            Quality::Draft => Quality::Preview,
+            Quality::Preview => Quality::Final,
+            Quality::Final => Quality::Draft,
+        }
+    }
+
+    // Settings for this preset; resolution and thread count are kept from `base`
+    pub fn settings(&self, base: &RenderSettings) -> RenderSettings {
+        let (samples_per_pixel, max_depth, shadow_samples, ao_samples, texture_filter, parallax_steps, denoise) = match self {
+            Quality::Draft => (1, 1, 1, 0, TextureFilter::Nearest, 1, false),
+            Quality::Preview => (1, 3, 1, 0, TextureFilter::Nearest, 1, false),
+            Quality::Final => (16, 5, 8, 16, TextureFilter::Bilinear, 8, true),
+        };
+        RenderSettings {
+            quality: *self,
+            samples_per_pixel,
+            max_depth,
+            shadow_samples,
+            ao_samples,
+            texture_filter,
+            parallax_steps,
+            denoise,
+            ..base.clone()
+        }
+    }
+}
+
+// Opciones de render compartidas por el modo interactivo, los trabajos por lotes y el servidor
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    pub quality: Quality, // Preset del que parten el resto de valores
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,      // Rebotes máximos de reflexión/refracción
+    pub half_res_secondary: bool, // Reflejos y refracciones en uno de cada dos píxeles, reconstruidos en el resto
+    pub probe_roughness: f32, // Rugosidad a partir de la cual los reflejos primarios leen la sonda más cercana
+    pub shadow_samples: u32, // 1 = sombras duras
+    pub ao_samples: u32,     // 0 desactiva la oclusión ambiental
+    pub smooth_lighting: bool, // Esquinas oscurecidas por los bloques vecinos, como en Minecraft, sin rayos
+    pub skylight: bool,      // Oscurece la luz ambiental en cuevas e interiores según la luz del cielo propagada
+    pub ambient_strength: f32, // Fracción de la luz ambiental que devuelve cada cara; 0 deja negro lo que no da a una luz
+    pub texture_filter: TextureFilter,
+    pub parallax_steps: u32, // 1 = desplazamiento simple; con más pasos se recorre el relieve por capas
+    pub env_sampling: EnvSampling, // Solo se usa con un mapa de entorno y ao_samples > 0
+    pub sampler: Sampler,
+    pub threads: usize,      // 0 usa todos los núcleos disponibles
+    pub tile_order: TileOrder,
+    pub focus_radius: f32,   // Fracción de la diagonal alrededor del foco que recibe muestras extra; 0 las reparte por igual
+    pub focus_falloff: FocusFalloff,
+    pub equalize_samples: bool, // Ignora el foco para que toda la imagen tenga las mismas muestras
+    pub focus: Option<[f32; 2]>, // Píxel del foco; sin él se usa el centro de la imagen
+    pub stereo: StereoMode,
+    pub eye_separation: Float, // Distancia entre los ojos en unidades de la escena
+    pub convergence: Convergence,
+    pub shutter_angle: Float, // Grados del intervalo entre frames con el obturador abierto; 0 congela el instante
+    pub white_balance: f32,  // Temperatura en kelvin de la luz que se verá blanca; 0 deja los colores como están
+    pub tone_mapping: ToneMapping,
+    pub fog_start: Float,    // Distancias de la niebla en unidades de la escena; fog_end = 0 la desactiva
+    pub fog_end: Float,
+    pub dither: bool,        // Añade ruido antes de cuantizar a 8 bits para ocultar las bandas
+    pub denoise: bool,       // Filtra el ruido de las muestras sin cruzar bordes; lo activa el preset final
+    pub alpha: AlphaMode,    // Cielo transparente en la imagen guardada; la ventana lo ignora
+    pub lens_flare: f32,     // Fuerza del destello del sol y de las luces brillantes; 0 lo desactiva
+    pub flare_threshold: f32, // Intensidad a partir de la que una luz que no es el sol da destello
+    pub flare_elements: FlareElements,
+    pub light_gizmos: bool,  // Dibuja la posición de las luces encima de la imagen (depuración)
+    pub render_mode: RenderMode,
+    pub backend: Backend,
+    pub outlines: bool,      // Marca los bordes de cada bloque en los puntos que ve la cámara
+    pub outline_width: f32,  // Grosor de los bordes en texels (16 por bloque de 0.5)
+    pub outline_color: Color,
+    pub toon_bands: u32,         // Niveles de luz difusa del modo toon
+    pub toon_outline_width: u32, // Grosor en píxeles de los contornos del modo toon
+    pub toon_outline_color: Color,
+    pub time: f32,           // Segundos desde el inicio, para animar superficies como los portales
+    pub checkerboard: bool,  // Traza la mitad de los píxeles en damero y reconstruye el resto; solo lo activa la ventana
+    pub frame: u32,          // Frame de la ventana; el damero alterna con él la mitad que traza
+    pub probes: Option<Arc<ReflectionProbes>>, // Sondas de reflexión de la escena; las renderiza quien la carga
+}
+
+impl RenderSettings {
+    // La niebla de los ajustes; la de la escena (la del streaming) tiene prioridad
+    pub fn fog(&self) -> Option<Fog> {
+        (self.fog_end > 0.0).then_some(Fog { start: self.fog_start, end: self.fog_end })
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        let base = RenderSettings {
+            quality: Quality::Preview,
+            width: 800,
+            height: 600,
+            samples_per_pixel: 1,
+            max_depth: 3,
+            half_res_secondary: false,
+            probe_roughness: 0.5,
+            shadow_samples: 1,
+            ao_samples: 0,
+            smooth_lighting: false,
+            skylight: false,
+            ambient_strength: 0.1,
+            texture_filter: TextureFilter::Nearest,
+            parallax_steps: 1,
+            env_sampling: EnvSampling::Importance,
+            sampler: Sampler::Regular,
+            threads: 0,
+            tile_order: TileOrder::Spiral,
+            focus_radius: 0.0,
+            focus_falloff: FocusFalloff::Gaussian,
+            equalize_samples: false,
+            focus: None,
+            stereo: StereoMode::Off,
+            eye_separation: DEFAULT_EYE_SEPARATION,
+            convergence: Convergence::Shift,
+            shutter_angle: 0.0,
+            white_balance: 0.0,
+            tone_mapping: ToneMapping::None,
+            fog_start: 0.0,
+            fog_end: 0.0,
+            dither: false,
+            denoise: false,
+            alpha: AlphaMode::Off,
+            lens_flare: 0.0,
+            flare_threshold: 5.0,
+            flare_elements: FlareElements::ALL,
+            light_gizmos: false,
+            render_mode: RenderMode::Shaded,
+            backend: Backend::Cpu,
+            outlines: false,
+            outline_width: 1.0,
+            outline_color: Color::new(0, 0, 0),
+            toon_bands: 3,
+            toon_outline_width: 1,
+            toon_outline_color: Color::new(0, 0, 0),
+            time: 0.0,
+            checkerboard: false,
+            frame: 0,
+            probes: None,
+        };
+        Quality::Preview.settings(&base)
+    }
+}
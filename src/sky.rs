@@ -0,0 +1,448 @@
+use std::f32::consts::PI;
+use crate::color::Color;
+use crate::cubemap::{CubemapFace, CUBEMAP_FACES};
+use crate::light::Light;
+use crate::math::{consts, to_f32, Float, Vec3};
+
+// Fondo de la escena para los rayos que no golpean ningún objeto
+pub enum Sky {
+    // Cielo simulado a partir del color ambiental y la posición del sol
+    Procedural,
+    // Mapa de entorno HDR equirectangular
+    Hdri(EnvironmentMap),
+    // Degradado vertical liso, sin sol (fondo del modo arcilla)
+    Gradient { top: Color, bottom: Color },
+}
+
+impl Sky {
+    pub fn color(&self, direction: &Vec3, lights: &[Light], ambient: &Ambient) -> Color {
+        match self {
+            Sky::Procedural => {
+                // Simular el color del cielo basado en la dirección del rayo y la posición del sol
+                let sun_dir = lights[0].position.normalize();
+                let sun_intensity = to_f32(direction.dot(&sun_dir).max(0.0).powf(20.0)) * (1.0 - ambient.night);
+                let sky_color = ambient.sky.mul_scalar(0.5); // Color base del cielo
+                let sun_color = Color::new(255, 255, 200).mul_scalar(sun_intensity); // Color del sol
+                if ambient.night > 0.0 {
+                    // De noche la luna sale por el lado contrario al sol
+                    sky_color + sun_color + night_sky(direction, &-sun_dir).mul_scalar(ambient.night)
+                } else {
+                    sky_color + sun_color
+                }
+            }
+            Sky::Hdri(map) => Color::from_linear(map.sample(direction)),
+            Sky::Gradient { top, bottom } => Color::lerp(bottom, top, to_f32(direction.y).clamp(-1.0, 1.0) * 0.5 + 0.5),
+        }
+    }
+
+    // Luz ambiental de la escena. Los colores que la escena no fija salen del cielo activo: con un
+    // mapa de entorno, el promedio de su mitad superior y de la inferior; con el cielo simulado,
+    // el color del ciclo día/noche para ambos, que no tiene suelo distinto
+    pub fn ambient(&self, day_night_color: &Color, scene: &AmbientSettings) -> Ambient {
+        let (sky, ground) = match self {
+            Sky::Procedural => (*day_night_color, *day_night_color),
+            Sky::Hdri(map) => (Color::from_linear(map.upper_average), Color::from_linear(map.lower_average)),
+            Sky::Gradient { top, bottom } => (*top, *bottom),
+        };
+        Ambient {
+            sky: scene.sky.unwrap_or(sky),
+            ground: scene.ground.unwrap_or(ground),
+            intensity: scene.intensity,
+            night: 0.0,
+        }
+    }
+}
+
+// Celdas de estrellas por lado de cada cara del cubo que rodea la escena, y fracción que tiene una
+const STAR_CELLS: Float = 96.0;
+const STAR_DENSITY: Float = 0.04;
+// Radio de una estrella en celdas; el centro se queda lejos del borde para que no se corte
+const STAR_RADIUS: Float = 0.2;
+const MOON_ANGULAR_RADIUS: Float = 0.05;
+const MOON_DISC_COLOR: Color = Color::new(225, 230, 245);
+
+// Estrellas y disco de la luna en una dirección del cielo. Las estrellas están fijas en el mundo: se
+// reparten en una rejilla sobre las caras de un cubo y cada celda decide con un hash si tiene una.
+fn night_sky(direction: &Vec3, moon_dir: &Vec3) -> Color {
+    let direction = direction.normalize();
+    if moon_dir.y > 0.0 && direction.dot(moon_dir) > MOON_ANGULAR_RADIUS.cos() {
+        return MOON_DISC_COLOR;
+    }
+    if direction.y <= 0.0 {
+        return Color::black();
+    }
+    let (face, s, t) = CubemapFace::from_direction(&direction);
+    let (s, t) = ((s + 1.0) * 0.5 * STAR_CELLS, (t + 1.0) * 0.5 * STAR_CELLS);
+    let face_index = CUBEMAP_FACES.iter().position(|f| *f == face).unwrap_or(0) as u32;
+    let [presence, offset_s, offset_t, brightness] = star_hash(face_index, s.floor() as u32, t.floor() as u32);
+    if presence >= STAR_DENSITY {
+        return Color::black();
+    }
+    let margin = |offset: Float| STAR_RADIUS + offset * (1.0 - 2.0 * STAR_RADIUS);
+    let (ds, dt) = (s.fract() - margin(offset_s), t.fract() - margin(offset_t));
+    let falloff = 1.0 - (ds * ds + dt * dt).sqrt() / STAR_RADIUS;
+    if falloff <= 0.0 {
+        return Color::black();
+    }
+    Color::new(255, 250, 235).mul_scalar(to_f32(falloff * (0.3 + 0.7 * brightness)))
+}
+
+// Cuatro números en [0, 1) para una celda de estrellas
+fn star_hash(face: u32, s: u32, t: u32) -> [Float; 4] {
+    let mut h = face.wrapping_mul(0x8da6_b343) ^ s.wrapping_mul(0xd816_3841) ^ t.wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^= h >> 16;
+    [0, 8, 16, 24].map(|shift| ((h >> shift) & 0xff) as Float / 256.0)
+}
+
+// Colores de la luz ambiental que fija una escena; los que faltan se toman del cielo
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientSettings {
+    pub sky: Option<Color>,
+    pub ground: Option<Color>,
+    pub intensity: f32,
+}
+
+impl Default for AmbientSettings {
+    fn default() -> Self {
+        AmbientSettings { sky: None, ground: None, intensity: 1.0 }
+    }
+}
+
+// Luz ambiental hemisférica: las caras que miran hacia arriba reciben el color del cielo y las que
+// miran hacia abajo el del suelo, mezclados según la normal. Con ambos iguales es un ambiente plano.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ambient {
+    pub sky: Color,
+    pub ground: Color,
+    pub intensity: f32,
+    pub night: f32, // 0 de día; hacia 1 el cielo simulado muestra las estrellas y la luna
+}
+
+impl Ambient {
+    pub fn flat(color: Color) -> Self {
+        Ambient { sky: color, ground: color, intensity: 1.0, night: 0.0 }
+    }
+
+    pub fn with_night(mut self, night: f32) -> Self {
+        self.night = night;
+        self
+    }
+
+    pub fn at(&self, normal: &Vec3) -> Color {
+        // Sin mezclar cuando son iguales, para que el ambiente plano no pierda un nivel al redondear
+        if self.sky == self.ground {
+            return self.sky.mul_scalar(self.intensity);
+        }
+        let t = to_f32(normal.y).clamp(-1.0, 1.0) * 0.5 + 0.5;
+        Color::lerp(&self.ground, &self.sky, t).mul_scalar(self.intensity)
+    }
+}
+
+// Niebla que funde lo lejano con el cielo de su dirección, desde nada en `start` hasta taparlo del
+// todo en `end` (distancias desde el origen de cada rayo). La pone el streaming de chunks para que
+// el borde de lo cargado no se vea.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fog {
+    pub start: Float,
+    pub end: Float,
+}
+
+impl Fog {
+    pub fn amount(&self, distance: Float) -> f32 {
+        if distance <= self.start {
+            0.0
+        } else if distance >= self.end {
+            1.0
+        } else {
+            to_f32((distance - self.start) / (self.end - self.start))
+        }
+    }
+}
+
+pub struct EnvironmentMap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[f32; 3]>, // Radiancia lineal
+    pub rotation: Float,       // Giro alrededor del eje Y en radianes
+    pub average: [f32; 3],
+    // Promedios del hemisferio superior e inferior, para la luz ambiental del cielo y del suelo
+    pub upper_average: [f32; 3],
+    pub lower_average: [f32; 3],
+    // Distribuciones para el muestreo por importancia: CDF marginal por filas (height + 1 valores)
+    // y CDF condicional de cada fila (height * (width + 1) valores)
+    marginal_cdf: Vec<f32>,
+    conditional_cdf: Vec<f32>,
+    total_weight: f32,
+}
+
+// Lee una cara de cubemap como radiancia lineal. Las imágenes de 8 o 16 bits (PNG, JPG) son renders
+// guardados en sRGB y se deshace la misma curva que aplica Color::from_linear; las HDR/EXR ya son
+// lineales. Los mapas equirectangulares de --hdri se siguen leyendo tal cual.
+fn load_linear(path: &str) -> Result<(usize, usize, Vec<[f32; 3]>), image::ImageError> {
+    let image = image::open(path)?;
+    let is_linear = matches!(image, image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_));
+    let image = image.to_rgb32f();
+    let decode = |value: f32| if is_linear { value } else { value.powf(2.2) };
+    let pixels = image.pixels().map(|p| [decode(p[0]), decode(p[1]), decode(p[2])]).collect();
+    Ok((image.width() as usize, image.height() as usize, pixels))
+}
+
+fn dimension_error() -> image::ImageError {
+    image::ImageError::Parameter(image::error::ParameterError::from_kind(
+        image::error::ParameterErrorKind::DimensionMismatch,
+    ))
+}
+
+fn luminance(rgb: &[f32; 3]) -> f32 {
+    0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2]
+}
+
+// Primer índice i con cdf[i + 1] > value, dentro de una CDF normalizada de n + 1 valores
+fn find_interval(cdf: &[f32], value: f32) -> usize {
+    let n = cdf.len() - 1;
+    cdf.partition_point(|&c| c <= value).saturating_sub(1).min(n - 1)
+}
+
+// CDF acumulada y normalizada de una lista de pesos; con pesos nulos queda uniforme
+fn build_cdf(weights: impl Iterator<Item = f32>, cdf: &mut Vec<f32>) -> f32 {
+    let start = cdf.len();
+    cdf.push(0.0);
+    let mut sum = 0.0;
+    for weight in weights {
+        sum += weight;
+        cdf.push(sum);
+    }
+    let n = (cdf.len() - start - 1) as f32;
+    for (i, value) in cdf[start..].iter_mut().enumerate() {
+        *value = if sum > 0.0 { *value / sum } else { i as f32 / n };
+    }
+    sum
+}
+
+impl EnvironmentMap {
+    // Carga una imagen equirectangular (.hdr, .exr o cualquier formato que entienda `image`)
+    pub fn load(path: &str, rotation: Float) -> Result<Self, image::ImageError> {
+        let img = image::open(path)?.to_rgb32f();
+        let (width, height) = img.dimensions();
+        let pixels: Vec<[f32; 3]> = img.pixels().map(|p| [p[0], p[1], p[2]]).collect();
+        Ok(Self::from_pixels(width as usize, height as usize, pixels, rotation))
+    }
+
+    // Carga un cubemap y lo remuestrea a la proyección equirectangular. `path` es una cruz horizontal
+    // de 4x3 caras o, si ese archivo no existe, el nombre base de seis imágenes (probe.png -> probe_px.png, ...)
+    pub fn load_cubemap(path: &str, rotation: Float) -> Result<Self, image::ImageError> {
+        let mut faces: Vec<(usize, Vec<[f32; 3]>)> = Vec::with_capacity(6);
+        if std::path::Path::new(path).exists() {
+            let (width, height, pixels) = load_linear(path)?;
+            let size = width / 4;
+            if size == 0 || width != size * 4 || height != size * 3 {
+                return Err(dimension_error());
+            }
+            for face in CUBEMAP_FACES {
+                let (column, row) = face.cross_cell();
+                let cell = (0..size)
+                    .flat_map(|y| {
+                        let start = (row * size + y) * width + column * size;
+                        pixels[start..start + size].iter().copied()
+                    })
+                    .collect();
+                faces.push((size, cell));
+            }
+        } else {
+            for face in CUBEMAP_FACES {
+                let (width, height, pixels) = load_linear(&face.face_path(path))?;
+                if width != height || faces.first().is_some_and(|(size, _)| *size != width) {
+                    return Err(dimension_error());
+                }
+                faces.push((width, pixels));
+            }
+        }
+
+        // Con 4x2 texels por cara el mapa conserva aproximadamente la resolución del cubemap
+        let size = faces[0].0;
+        let (width, height) = (size * 4, size * 2);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                // Igual que uv_to_direction, sin giro: el giro se aplica al muestrear
+                let phi = ((x as Float + 0.5) / width as Float - 0.5) * 2.0 * consts::PI;
+                let theta = (y as Float + 0.5) / height as Float * consts::PI;
+                let direction = Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+                let (face, s, t) = CubemapFace::from_direction(&direction);
+                let index = CUBEMAP_FACES.iter().position(|f| *f == face).unwrap_or(0);
+                let to_texel = |coordinate: Float| (((coordinate + 1.0) * 0.5 * size as Float) as usize).min(size - 1);
+                pixels.push(faces[index].1[to_texel(t) * size + to_texel(s)]);
+            }
+        }
+        Ok(Self::from_pixels(width, height, pixels, rotation))
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<[f32; 3]>, rotation: Float) -> Self {
+        // Promedio ponderado por el ángulo sólido de cada fila (las filas cerca de los polos cubren menos cielo)
+        let weighted_average = |rows: std::ops::Range<usize>| {
+            let mut sum = [0.0f64; 3];
+            let mut weight_sum = 0.0f64;
+            for y in rows {
+                let theta = PI * (y as f32 + 0.5) / height as f32;
+                let weight = theta.sin() as f64;
+                for pixel in &pixels[y * width..(y + 1) * width] {
+                    for channel in 0..3 {
+                        sum[channel] += pixel[channel] as f64 * weight;
+                    }
+                    weight_sum += weight;
+                }
+            }
+            sum.map(|value| (value / weight_sum.max(1e-9)) as f32)
+        };
+        let average = weighted_average(0..height);
+        // Con una cantidad impar de filas la del horizonte cuenta en las dos mitades
+        let upper_average = weighted_average(0..height.div_ceil(2));
+        let lower_average = weighted_average(height / 2..height);
+
+        // Cada texel pesa su luminancia por sin(theta) para compensar que las filas de los polos
+        // están estiradas en la proyección equirectangular
+        let mut conditional_cdf = Vec::with_capacity(height * (width + 1));
+        let mut row_weights = Vec::with_capacity(height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+            let row = &pixels[y * width..(y + 1) * width];
+            row_weights.push(build_cdf(row.iter().map(|p| luminance(p) * sin_theta), &mut conditional_cdf));
+        }
+        let mut marginal_cdf = Vec::with_capacity(height + 1);
+        let total_weight = build_cdf(row_weights.into_iter(), &mut marginal_cdf);
+
+        EnvironmentMap { width, height, pixels, rotation, average, upper_average, lower_average, marginal_cdf, conditional_cdf, total_weight }
+    }
+
+    // Coordenadas (u, v) en [0, 1) para una dirección unitaria
+    pub fn direction_to_uv(&self, direction: &Vec3) -> (f32, f32) {
+        let phi = direction.x.atan2(-direction.z) + self.rotation;
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let u = (phi / (2.0 * consts::PI) + 0.5).rem_euclid(1.0);
+        let v = theta / consts::PI;
+        (to_f32(u), to_f32(v))
+    }
+
+    // Inversa de direction_to_uv
+    pub fn uv_to_direction(&self, u: f32, v: f32) -> Vec3 {
+        let phi = (u as Float - 0.5) * 2.0 * consts::PI - self.rotation;
+        let theta = v as Float * consts::PI;
+        Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+
+    // Densidad en (u, v) de la distribución usada por sample_direction
+    fn pdf_uv(&self, x: usize, y: usize) -> f32 {
+        if self.total_weight <= 0.0 {
+            return 1.0;
+        }
+        let sin_theta = (PI * (y as f32 + 0.5) / self.height as f32).sin();
+        let weight = luminance(&self.pixels[y * self.width + x]) * sin_theta;
+        weight * (self.width * self.height) as f32 / self.total_weight
+    }
+
+    // Elige una dirección con probabilidad proporcional a la luminancia del mapa a partir de dos
+    // números en [0, 1). Devuelve la dirección y su densidad respecto al ángulo sólido.
+    pub fn sample_direction(&self, u1: f32, u2: f32) -> (Vec3, Float) {
+        let y = find_interval(&self.marginal_cdf, u2);
+        let row = &self.conditional_cdf[y * (self.width + 1)..(y + 1) * (self.width + 1)];
+        let x = find_interval(row, u1);
+
+        // Posición continua dentro del texel elegido
+        let dv = (u2 - self.marginal_cdf[y]) / (self.marginal_cdf[y + 1] - self.marginal_cdf[y]).max(1e-9);
+        let du = (u1 - row[x]) / (row[x + 1] - row[x]).max(1e-9);
+        let u = (x as f32 + du.clamp(0.0, 1.0)) / self.width as f32;
+        let v = (y as f32 + dv.clamp(0.0, 1.0)) / self.height as f32;
+
+        let direction = self.uv_to_direction(u, v);
+        let sin_theta = (v as Float * consts::PI).sin();
+        if sin_theta <= 0.0 {
+            return (direction, 0.0);
+        }
+        // Cambio de variables (u, v) -> ángulo sólido: dω = 2π² sin(theta) du dv
+        (direction, self.pdf_uv(x, y) as Float / (2.0 * consts::PI * consts::PI * sin_theta))
+    }
+
+    fn texel(&self, x: i64, y: i64) -> [f32; 3] {
+        // Horizontalmente el mapa da la vuelta; verticalmente se limita a los polos
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    // Muestreo bilineal en espacio lineal
+    pub fn sample(&self, direction: &Vec3) -> [f32; 3] {
+        let (u, v) = self.direction_to_uv(direction);
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let a = self.texel(x0, y0);
+        let b = self.texel(x0 + 1, y0);
+        let c = self.texel(x0, y0 + 1);
+        let d = self.texel(x0 + 1, y0 + 1);
+        let mut result = [0.0; 3];
+        for channel in 0..3 {
+            let top = a[channel] * (1.0 - tx) + b[channel] * tx;
+            let bottom = c[channel] * (1.0 - tx) + d[channel] * tx;
+            result[channel] = top * (1.0 - ty) + bottom * ty;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A dim sky with a bright 4x4 texel sun about 45° up
+    fn sun_map() -> EnvironmentMap {
+        let (width, height) = (64, 32);
+        let mut pixels = vec![[0.2f32; 3]; width * height];
+        for y in 6..10 {
+            pixels[y * width + 38..y * width + 42].fill([30.0; 3]);
+        }
+        EnvironmentMap::from_pixels(width, height, pixels, 0.0)
+    }
+
+    // Directions for an evenly spread n x n grid of random number pairs, with their densities
+    fn sampled(map: &EnvironmentMap, n: usize) -> Vec<(Vec3, Float)> {
+        (0..n * n).map(|i| map.sample_direction(((i % n) as f32 + 0.5) / n as f32, ((i / n) as f32 + 0.5) / n as f32)).collect()
+    }
+
+    #[test]
+    fn importance_samples_land_on_texels_in_proportion_to_their_light() {
+        let map = sun_map();
+        let sun_weight: f32 = (6..10).map(|y| 4.0 * 30.0 * (PI * (y as f32 + 0.5) / 32.0).sin()).sum();
+        let samples = sampled(&map, 64);
+        let on_sun = samples.iter().filter(|(direction, _)| {
+            let (u, v) = map.direction_to_uv(direction);
+            (38..42).contains(&((u * 64.0) as usize)) && (6..10).contains(&((v * 32.0) as usize))
+        }).count();
+        let share = on_sun as f32 / samples.len() as f32;
+        assert!((share - sun_weight / map.total_weight).abs() < 0.01, "{} of the samples on the sun", share);
+    }
+
+    #[test]
+    fn sample_densities_add_up_over_the_sphere() {
+        // The average of 1 / pdf over samples drawn from pdf is the solid angle it covers
+        let samples = sampled(&sun_map(), 64);
+        assert!(samples.iter().all(|(_, pdf)| *pdf > 0.0));
+        let solid_angle = samples.iter().map(|(_, pdf)| 1.0 / pdf).sum::<Float>() / samples.len() as Float;
+        assert!((solid_angle / (4.0 * consts::PI) - 1.0).abs() < 0.02, "{}", solid_angle);
+    }
+
+    #[test]
+    fn uv_and_direction_are_inverse() {
+        let map = EnvironmentMap::from_pixels(8, 4, vec![[1.0; 3]; 32], 0.7);
+        for (u, v) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.8), (0.3, 0.05)] {
+            let (back_u, back_v) = map.direction_to_uv(&map.uv_to_direction(u, v));
+            assert!((back_u - u).abs() < 1e-4 && (back_v - v).abs() < 1e-4, "({}, {}) came back as ({}, {})", u, v, back_u, back_v);
+        }
+    }
+}
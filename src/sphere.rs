@@ -0,0 +1,60 @@
+use crate::math::{Float, Vec3};
+use crate::material::Material;
+use crate::ray_intersect::{RayIntersect, Hit, CubeFace, Ray};
+
+#[derive(Clone)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: Float,
+    pub material: Material,
+}
+
+impl Sphere {
+    pub fn new(center: Vec3, radius: Float, material: &Material) -> Self {
+        Sphere { center, radius: radius.abs(), material: material.clone() }
+    }
+}
+
+impl RayIntersect for Sphere {
+    // Igual que con las cajas, una esfera que el rayo atraviesa dentro de [t_min, t_max) cuenta
+    // aunque el origen esté dentro, y entonces la distancia es la de la entrada, negativa
+    fn ray_intersect(&self, ray: &Ray) -> Option<Hit> {
+        let to_origin = ray.origin - self.center;
+        let a = ray.dir.dot(&ray.dir);
+        let half_b = to_origin.dot(&ray.dir);
+        let c = to_origin.dot(&to_origin) - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 || a == 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let (near, far) = ((-half_b - root) / a, (-half_b + root) / a);
+        if far < ray.t_min || near >= ray.t_max {
+            return None;
+        }
+
+        let point = ray.at(near);
+        let normal = (point - self.center) / self.radius;
+        Some(Hit::new(point, normal, near, CubeFace::from_normal(&normal)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rays_hit_the_near_side_within_their_bounds() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0, &Material::black());
+        let hit = sphere.ray_intersect(&Ray::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0))).expect("the sphere is straight ahead");
+        assert!((hit.t - 4.0).abs() < 1e-9);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(hit.face, CubeFace::Front);
+
+        assert!(sphere.ray_intersect(&Ray::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 0.0, -1.0))).is_none());
+        assert!(sphere.ray_intersect(&Ray::segment(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0), 3.5)).is_none());
+        assert!(sphere.ray_intersect(&Ray::new(Vec3::zeros(), Vec3::new(0.0, 0.0, 1.0))).is_none());
+        // From inside the far side is still ahead, so it counts
+        assert!(sphere.ray_intersect(&Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(1.0, 0.0, 0.0))).is_some());
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::block::BLOCK_SIZE;
+use crate::cube::Cube;
+use crate::material::Material;
+use crate::math::{Float, Vec3};
+use crate::scene::block_objects;
+use crate::sky::Fog;
+use crate::structures::PlacedBlock;
+use crate::world::{WorldChunks, CHUNK_SIZE};
+
+// Fracción del radio a la que empieza la niebla que tapa el borde de lo cargado
+const FOG_START: Float = 0.7;
+
+type ChunkResult = ([i32; 3], Result<Vec<Cube>, String>);
+
+// Carga por partes de un mundo binario demasiado grande para tenerlo entero como cajas. Solo los
+// chunks a menos de `radius` bloques del chunk de la cámara se convierten en cajas, y los que se
+// alejan se sueltan, así que la memoria y el número de cajas que recorre cada rayo dependen del
+// radio y no del tamaño del mundo. Los chunks se convierten en un hilo aparte, los más cercanos
+// primero, y la ventana nunca los espera: hasta que llegan, la niebla de `fog` tapa el hueco.
+pub struct ChunkStreamer {
+    world: Arc<WorldChunks>,
+    radius: Float, // En bloques
+    center: Option<[i32; 3]>,
+    loaded: HashMap<[i32; 3], Vec<Cube>>,
+    wanted: Arc<Mutex<HashSet<[i32; 3]>>>, // Pedidos y sin llegar; el hilo se salta los que ya no están
+    requests: Sender<[i32; 3]>,
+    results: Receiver<ChunkResult>,
+}
+
+impl ChunkStreamer {
+    pub fn new(world: WorldChunks, materials: HashMap<String, Material>, radius: Float) -> Self {
+        let world = Arc::new(world);
+        let wanted = Arc::new(Mutex::new(HashSet::new()));
+        let (requests, queue) = channel::<[i32; 3]>();
+        let (done, results) = channel::<ChunkResult>();
+        let (worker_world, worker_wanted) = (world.clone(), wanted.clone());
+        std::thread::spawn(move || {
+            for chunk in queue {
+                if !worker_wanted.lock().unwrap_or_else(PoisonError::into_inner).contains(&chunk) {
+                    continue;
+                }
+                let objects = worker_world.blocks(chunk).and_then(|blocks| chunk_objects(blocks, &materials));
+                if done.send((chunk, objects)).is_err() {
+                    break;
+                }
+            }
+        });
+        ChunkStreamer { world, radius, center: None, loaded: HashMap::new(), wanted, requests, results }
+    }
+
+    pub fn world(&self) -> &WorldChunks {
+        &self.world
+    }
+
+    // Pide los chunks que entran en el radio del chunk de `eye`, suelta los que salen y recoge los
+    // que ya están convertidos. Devuelve true si cambiaron los chunks cargados.
+    pub fn update(&mut self, eye: &Vec3) -> bool {
+        let center = chunk_at(eye);
+        let mut changed = false;
+        if self.center != Some(center) {
+            self.center = Some(center);
+            let radius = self.radius;
+            let before = self.loaded.len();
+            self.loaded.retain(|&chunk, _| gap(chunk, center) <= radius);
+            changed = self.loaded.len() != before;
+
+            let mut wanted = self.wanted.lock().unwrap_or_else(PoisonError::into_inner);
+            wanted.retain(|&chunk| gap(chunk, center) <= radius);
+            let reach = (radius / CHUNK_SIZE as Float).ceil() as i32 + 1;
+            let mut missing: Vec<[i32; 3]> = (-reach..=reach)
+                .flat_map(|dy| (-reach..=reach).flat_map(move |dz| (-reach..=reach).map(move |dx| [dx, dy, dz])))
+                .map(|offset| [center[0] + offset[0], center[1] + offset[1], center[2] + offset[2]])
+                .filter(|&chunk| gap(chunk, center) <= radius && self.world.contains(chunk))
+                .filter(|chunk| !self.loaded.contains_key(chunk) && !wanted.contains(chunk))
+                .collect();
+            missing.sort_by_key(|chunk| (0..3).map(|axis| (chunk[axis] - center[axis]).pow(2)).sum::<i32>());
+            for chunk in missing {
+                wanted.insert(chunk);
+                // Si el hilo terminó, el chunk se queda en `wanted` y `pending_chunks` lo cuenta
+                let _ = self.requests.send(chunk);
+            }
+        }
+        while let Ok(result) = self.results.try_recv() {
+            changed |= self.receive(result);
+        }
+        changed
+    }
+
+    // Espera a que lleguen todos los chunks pedidos, para renders sin ventana y los tests
+    pub fn wait(&mut self) -> bool {
+        let mut changed = false;
+        while self.pending_chunks() > 0 {
+            match self.results.recv() {
+                Ok(result) => changed |= self.receive(result),
+                Err(_) => break,
+            }
+        }
+        changed
+    }
+
+    fn receive(&mut self, (chunk, objects): ChunkResult) -> bool {
+        // Los que salieron del radio mientras se convertían ya no se quieren
+        if !self.wanted.lock().unwrap_or_else(PoisonError::into_inner).remove(&chunk) {
+            return false;
+        }
+        match objects {
+            Ok(objects) => {
+                self.loaded.insert(chunk, objects);
+                true
+            }
+            Err(e) => {
+                eprintln!("warning: chunk {:?} could not be loaded: {}", chunk, e);
+                false
+            }
+        }
+    }
+
+    // Las cajas de todos los chunks cargados
+    pub fn objects(&self) -> impl Iterator<Item = &Cube> {
+        self.loaded.values().flatten()
+    }
+
+    pub fn loaded_chunks(&self) -> usize {
+        self.loaded.len()
+    }
+
+    pub fn pending_chunks(&self) -> usize {
+        self.wanted.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    // Niebla que termina en el radio, donde puede empezar lo que no está cargado
+    pub fn fog(&self) -> Fog {
+        let end = self.radius * BLOCK_SIZE;
+        Fog { start: end * FOG_START, end }
+    }
+}
+
+// Chunk del punto del mundo `point`
+fn chunk_at(point: &Vec3) -> [i32; 3] {
+    [point.x, point.y, point.z].map(|value| (value / BLOCK_SIZE / CHUNK_SIZE as Float).floor() as i32)
+}
+
+// Distancia en bloques entre dos chunks, de borde a borde: cualquier punto a menos de esa distancia
+// de un punto del chunk central cae en un chunk con `gap` menor, así que cargar los chunks hasta
+// `radius` cubre el radio entero alrededor de la cámara esté donde esté dentro de su chunk
+fn gap(chunk: [i32; 3], center: [i32; 3]) -> Float {
+    let squared: i32 = (0..3).map(|axis| ((chunk[axis] - center[axis]).abs() - 1).max(0) * CHUNK_SIZE).map(|gap| gap * gap).sum();
+    (squared as Float).sqrt()
+}
+
+// Las cajas de un chunk sin los bloques que tienen ocupados sus seis vecinos por bloques enteros
+// opacos, que nunca se ven. Los vecinos de otros chunks no se conocen, así que los bloques del borde
+// se quedan, y las vallas del borde no se conectan con las del chunk de al lado.
+fn chunk_objects(blocks: Vec<PlacedBlock>, materials: &HashMap<String, Material>) -> Result<Vec<Cube>, String> {
+    let opaque = |block: &PlacedBlock| {
+        block.shape.is_full()
+            && materials.get(&block.material).is_some_and(|material| material.properties[3] == 0.0 && material.portal.is_none())
+    };
+    let solid: HashSet<[i32; 3]> = blocks.iter().filter(|block| opaque(block)).map(|block| block.pos).collect();
+    let hidden = |[x, y, z]: [i32; 3]| {
+        [[x + 1, y, z], [x - 1, y, z], [x, y + 1, z], [x, y - 1, z], [x, y, z + 1], [x, y, z - 1]]
+            .iter()
+            .all(|neighbor| solid.contains(neighbor))
+    };
+    let placed: Vec<(PlacedBlock, Option<&str>)> = blocks.into_iter()
+        .filter(|block| !hidden(block.pos))
+        .map(|block| (block, None))
+        .collect();
+    Ok(block_objects(&placed, materials, true)?.into_iter().map(|(object, _)| object).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::materials;
+    use crate::world::World;
+
+    // A flat stone slab, 8 blocks deep and `size` blocks square, starting at the origin
+    fn slab(size: i32) -> WorldChunks {
+        let blocks = (0..8)
+            .flat_map(|y| (0..size).flat_map(move |z| (0..size).map(move |x| PlacedBlock::full([x, y - 8, z], "stone"))))
+            .collect();
+        WorldChunks::from_bytes(&World { blocks, lights: Vec::new(), camera: None }.to_bytes()).unwrap()
+    }
+
+    fn streamer(size: i32, radius: Float) -> ChunkStreamer {
+        ChunkStreamer::new(slab(size), materials(&mut Vec::new()), radius)
+    }
+
+    // Where a camera above block (x, z) of the slab is
+    fn above(x: i32, z: i32) -> Vec3 {
+        Vec3::new(x as Float, 4.0, z as Float) * BLOCK_SIZE
+    }
+
+    #[test]
+    fn only_chunks_within_the_radius_are_loaded() {
+        let mut streamer = streamer(256, 20.0);
+        assert_eq!(streamer.world().chunks().count(), 16 * 16);
+        // Within 20 blocks edge to edge: the eye's chunk, two more to each side but not the four
+        // diagonal corners two chunks away, which are 22.6 blocks away
+        streamer.update(&above(8, 8));
+        streamer.wait();
+        assert_eq!(streamer.loaded_chunks(), 3 * 3 - 1, "the world starts at the eye's chunk");
+        assert_eq!(streamer.pending_chunks(), 0);
+
+        // Flying across the world keeps the same number of chunks around the camera
+        for x in [40, 100, 180] {
+            streamer.update(&above(x, 128));
+            streamer.wait();
+            assert_eq!(streamer.loaded_chunks(), 5 * 5 - 4, "at x = {}", x);
+        }
+        streamer.update(&above(248, 128));
+        streamer.wait();
+        assert_eq!(streamer.loaded_chunks(), 3 * 5 - 2, "the world ends at the eye's chunk");
+        assert!(streamer.objects().all(|object| object.min.x >= 13.0 * 16.0 * BLOCK_SIZE), "the chunks left behind were dropped");
+    }
+
+    #[test]
+    fn buried_blocks_are_culled() {
+        let mut streamer = streamer(16, 8.0);
+        streamer.update(&above(8, 8));
+        assert!(streamer.wait());
+        // Only the blocks on the chunk's sides and on the slab's top and bottom are left
+        let block = BLOCK_SIZE.powi(3);
+        let volume: Float = streamer.objects().map(|object| (object.max - object.min).product()).sum();
+        assert!((volume - (16.0 * 16.0 * 8.0 - 14.0 * 14.0 * 6.0) * block).abs() < 1e-3, "{}", volume);
+
+        let blocks = (0..3)
+            .flat_map(|y| (0..3).flat_map(move |z| (0..3).map(move |x| PlacedBlock::full([x, y, z], "stone"))))
+            .collect();
+        let objects = chunk_objects(blocks, &materials(&mut Vec::new())).unwrap();
+        let volume: Float = objects.iter().map(|object| (object.max - object.min).product()).sum();
+        assert!((volume - 26.0 * block).abs() < 1e-4, "only the middle block is hidden: {}", volume);
+    }
+
+    #[test]
+    fn fog_covers_the_edge_of_the_radius() {
+        let fog = streamer(16, 64.0).fog();
+        assert_eq!(fog.end, 64.0 * BLOCK_SIZE);
+        assert_eq!(fog.amount(fog.end), 1.0);
+        assert_eq!(fog.amount(fog.start), 0.0);
+    }
+}
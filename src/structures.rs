@@ -0,0 +1,422 @@
+use std::collections::BTreeMap;
+use crate::block::{Axis, BlockShape, BlockTurn, Direction, Facing, NEIGHBOR_OFFSETS};
+use crate::noise::Rng;
+
+// Generadores de estructuras hechas de bloques de la cuadrícula de 0.5, para no colocar a mano
+// cada pared de una casa. Devuelven los bloques con el nombre de su material; la escena los
+// convierte en cajas como a los [[block]] del archivo, uniendo los vecinos iguales.
+
+// Bloque colocado por una estructura, con `pos` en la cuadrícula de bloques
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlacedBlock {
+    pub pos: [i32; 3],
+    pub material: String,
+    pub shape: BlockShape,
+    pub turn: Option<BlockTurn>, // Solo bloques completos; sin él, como el bloque sin girar
+}
+
+impl PlacedBlock {
+    pub fn full(pos: [i32; 3], material: &str) -> Self {
+        PlacedBlock { pos, material: material.to_string(), shape: BlockShape::Full, turn: None }
+    }
+}
+
+// Lo más lejos del origen que puede estar un bloque en cada eje. Más allá un f32 ya no separa las
+// esquinas de dos bloques vecinos, y así sumar el tamaño de una estructura o mirar a los vecinos de
+// una valla nunca se sale de un i32.
+pub const MAX_COORDINATE: i32 = 1 << 24;
+
+// Lado más largo de una casa, una valla o un huerto, en bloques
+pub const MAX_STRUCTURE_SIZE: i32 = 256;
+
+pub fn check_position(pos: [i32; 3]) -> Result<(), String> {
+    if pos.iter().any(|value| !(-MAX_COORDINATE..=MAX_COORDINATE).contains(value)) {
+        return Err(format!("Block position {:?} is too far from the origin (each coordinate must be within {})", pos, MAX_COORDINATE));
+    }
+    Ok(())
+}
+
+// Tejado de una casa, siempre de piedra y sobresaliendo un bloque de las paredes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoofStyle {
+    Flat,    // Una sola capa
+    Stepped, // Capas que se estrechan un bloque por lado hasta cerrarse
+}
+
+impl std::str::FromStr for RoofStyle {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "flat" => Ok(RoofStyle::Flat),
+            "stepped" => Ok(RoofStyle::Stepped),
+            _ => Err(format!("Unknown roof style '{}' (expected flat or stepped)", name)),
+        }
+    }
+}
+
+pub const ROOF_MATERIAL: &str = "stone";
+pub const WINDOW_MATERIAL: &str = "glass";
+pub const TRUNK_MATERIAL: &str = "treewood";
+pub const LEAVES_MATERIAL: &str = "leaves";
+pub const FENCE_MATERIAL: &str = "wood";
+pub const CROP_MATERIAL: &str = "leaves";
+
+// Capas de un rectángulo que se estrecha un bloque por lado en cada una, desde `at` hacia arriba
+fn stepped_layers(at: [i32; 3], width: i32, depth: i32, material: &str, blocks: &mut Vec<PlacedBlock>) {
+    let [x0, y0, z0] = at;
+    let mut inset = 0;
+    while width - 2 * inset > 0 && depth - 2 * inset > 0 {
+        for x in x0 + inset..x0 + width - inset {
+            for z in z0 + inset..z0 + depth - inset {
+                blocks.push(PlacedBlock::full([x, y0 + inset, z], material));
+            }
+        }
+        inset += 1;
+    }
+}
+
+// Casa de paredes de un bloque de grosor con la esquina inferior en `at`: una puerta de 2x2
+// centrada en la pared +z, una ventana de cristal de 2 bloques de ancho centrada en la pared +x y
+// el tejado encima. Las medidas incluyen las paredes.
+pub fn house(at: [i32; 3], width: i32, depth: i32, height: i32, wall_material: &str, roof_style: RoofStyle) -> Result<Vec<PlacedBlock>, String> {
+    if !(4..=MAX_STRUCTURE_SIZE).contains(&width) || !(4..=MAX_STRUCTURE_SIZE).contains(&depth) || !(3..=MAX_STRUCTURE_SIZE).contains(&height) {
+        return Err(format!(
+            "A house needs width and depth from 4 to {} and height from 3 to {}, got {}x{}x{}",
+            MAX_STRUCTURE_SIZE, MAX_STRUCTURE_SIZE, width, depth, height
+        ));
+    }
+    let [x0, y0, z0] = at;
+    let (x1, z1) = (x0 + width - 1, z0 + depth - 1);
+    let door_x = x0 + (width - 2) / 2;
+    let window_z = z0 + (depth - 2) / 2;
+    let mut blocks = Vec::new();
+    for y in y0..y0 + height {
+        for x in x0..=x1 {
+            for z in z0..=z1 {
+                if x != x0 && x != x1 && z != z0 && z != z1 {
+                    continue;
+                }
+                let (dy, in_door, in_window) = (y - y0, (door_x..door_x + 2).contains(&x), (window_z..window_z + 2).contains(&z));
+                if z == z1 && in_door && dy < 2 {
+                    continue;
+                }
+                if x == x1 && in_window && (1..height - 1).contains(&dy) {
+                    blocks.push(PlacedBlock::full([x, y, z], WINDOW_MATERIAL));
+                    continue;
+                }
+                blocks.push(PlacedBlock::full([x, y, z], wall_material));
+            }
+        }
+    }
+    let roof = [x0 - 1, y0 + height, z0 - 1];
+    match roof_style {
+        RoofStyle::Flat => {
+            for x in x0 - 1..=x1 + 1 {
+                for z in z0 - 1..=z1 + 1 {
+                    blocks.push(PlacedBlock::full([x, y0 + height, z], ROOF_MATERIAL));
+                }
+            }
+        }
+        RoofStyle::Stepped => stepped_layers(roof, width + 2, depth + 2, ROOF_MATERIAL, &mut blocks),
+    }
+    Ok(blocks)
+}
+
+// Árbol con el tronco en `at`: `height` bloques de tronco, dos capas de hojas de radio
+// `canopy_radius` alrededor de lo alto del tronco y un bloque de hojas en la punta
+pub fn tree(at: [i32; 3], height: i32, canopy_radius: i32) -> Result<Vec<PlacedBlock>, String> {
+    if !(1..=32).contains(&height) || !(0..=4).contains(&canopy_radius) {
+        return Err(format!("A tree needs a height from 1 to 32 and a canopy radius from 0 to 4, got {} and {}", height, canopy_radius));
+    }
+    let [x0, y0, z0] = at;
+    let mut blocks: Vec<PlacedBlock> = (y0..y0 + height).map(|y| PlacedBlock::full([x0, y, z0], TRUNK_MATERIAL)).collect();
+    for y in y0 + height..y0 + height + 2 {
+        for x in x0 - canopy_radius..=x0 + canopy_radius {
+            for z in z0 - canopy_radius..=z0 + canopy_radius {
+                blocks.push(PlacedBlock::full([x, y, z], LEAVES_MATERIAL));
+            }
+        }
+    }
+    blocks.push(PlacedBlock::full([x0, y0 + height + 2, z0], LEAVES_MATERIAL));
+    Ok(blocks)
+}
+
+// Árbol distinto para cada semilla, con la base del tronco en `at`: de 4 a 7 bloques de tronco,
+// a veces inclinado (se desplaza un bloque cada pocos), hasta dos ramas cortas en la mitad de
+// arriba y una copa redonda o en cono cuyo borde se come al azar. La misma semilla da siempre el
+// mismo árbol.
+pub fn random_tree(at: [i32; 3], seed: u64) -> Vec<PlacedBlock> {
+    let mut rng = Rng::new(seed);
+    let mut blocks: BTreeMap<[i32; 3], &str> = BTreeMap::new();
+
+    let height = rng.range(4, 7);
+    let (lean_x, lean_z) = NEIGHBOR_OFFSETS[rng.range(0, 3) as usize];
+    let lean_every = if rng.chance(0.5) { rng.range(2, 4) } else { 0 };
+    let [mut x, y0, mut z] = at;
+    let mut trunk = Vec::new();
+    for level in 0..height {
+        if lean_every > 0 && level > 0 && level % lean_every == 0 {
+            x += lean_x;
+            z += lean_z;
+        }
+        trunk.push([x, y0 + level, z]);
+        blocks.insert([x, y0 + level, z], TRUNK_MATERIAL);
+    }
+
+    for _ in 0..rng.range(0, 2) {
+        let [bx, by, bz] = trunk[rng.range(height / 2, height - 2) as usize];
+        let (dx, dz) = NEIGHBOR_OFFSETS[rng.range(0, 3) as usize];
+        blocks.insert([bx + dx, by, bz + dz], TRUNK_MATERIAL);
+    }
+
+    let [tx, ty, tz] = trunk[trunk.len() - 1];
+    let radius = rng.range(2, 3);
+    let cone = rng.chance(0.4);
+    for dy in -1..=2 * radius {
+        // El cono se estrecha un bloque cada dos capas; la bola es una esfera algo aplastada
+        let (layer_radius, limit) = if cone {
+            let layer_radius = radius - (dy + 1) / 2;
+            (layer_radius, (layer_radius * layer_radius) as f32 + 0.5)
+        } else if dy <= radius {
+            (radius, (radius * radius - dy * dy) as f32 + 0.5)
+        } else {
+            continue;
+        };
+        for dx in -layer_radius..=layer_radius {
+            for dz in -layer_radius..=layer_radius {
+                let distance = (dx * dx + dz * dz) as f32;
+                if distance > limit {
+                    continue;
+                }
+                // Las hojas del borde faltan a veces, para que la copa no sea una figura perfecta
+                let edge = distance > limit - 2.0 * layer_radius as f32;
+                if edge && rng.chance(0.35) {
+                    continue;
+                }
+                blocks.entry([tx + dx, ty + dy, tz + dz]).or_insert(LEAVES_MATERIAL);
+            }
+        }
+    }
+    blocks.entry([tx, ty + 1, tz]).or_insert(LEAVES_MATERIAL);
+
+    blocks.into_iter().map(|(pos, material)| PlacedBlock::full(pos, material)).collect()
+}
+
+// Pirámide escalonada maciza de `base` x `base` bloques con la esquina inferior en `at`
+pub fn pyramid(at: [i32; 3], base: i32, material: &str) -> Result<Vec<PlacedBlock>, String> {
+    if !(1..=64).contains(&base) {
+        return Err(format!("A pyramid needs a base from 1 to 64 blocks, got {}", base));
+    }
+    let mut blocks = Vec::new();
+    stepped_layers(at, base, base, material, &mut blocks);
+    Ok(blocks)
+}
+
+// Valla alrededor del rectángulo de `width` x `depth` bloques con la esquina en `at`; los postes
+// se conectan entre sí al cargarla, como cualquier valla
+pub fn fence_ring(at: [i32; 3], width: i32, depth: i32) -> Result<Vec<PlacedBlock>, String> {
+    if !(2..=MAX_STRUCTURE_SIZE).contains(&width) || !(2..=MAX_STRUCTURE_SIZE).contains(&depth) {
+        return Err(format!("A fence ring needs width and depth from 2 to {}, got {}x{}", MAX_STRUCTURE_SIZE, width, depth));
+    }
+    let [x0, y, z0] = at;
+    let (x1, z1) = (x0 + width - 1, z0 + depth - 1);
+    let mut blocks = Vec::new();
+    for x in x0..=x1 {
+        for z in z0..=z1 {
+            if x == x0 || x == x1 || z == z0 || z == z1 {
+                blocks.push(PlacedBlock { pos: [x, y, z], material: FENCE_MATERIAL.to_string(), shape: BlockShape::Fence, turn: None });
+            }
+        }
+    }
+    Ok(blocks)
+}
+
+// Pozo de 3x3 con la esquina en `at`: un anillo de piedra alrededor del hueco, cuatro postes
+// de valla en las esquinas y un techo plano de madera
+pub fn well(at: [i32; 3]) -> Vec<PlacedBlock> {
+    let [x0, y0, z0] = at;
+    let mut blocks = Vec::new();
+    for x in x0..x0 + 3 {
+        for z in z0..z0 + 3 {
+            if [x, z] != [x0 + 1, z0 + 1] {
+                blocks.push(PlacedBlock::full([x, y0, z], ROOF_MATERIAL));
+            }
+            blocks.push(PlacedBlock::full([x, y0 + 3, z], FENCE_MATERIAL));
+        }
+    }
+    for (x, z) in [(x0, z0), (x0 + 2, z0), (x0, z0 + 2), (x0 + 2, z0 + 2)] {
+        for y in y0 + 1..y0 + 3 {
+            blocks.push(PlacedBlock { pos: [x, y, z], material: FENCE_MATERIAL.to_string(), shape: BlockShape::Fence, turn: None });
+        }
+    }
+    blocks
+}
+
+// Huerto de `width` x `depth` con la esquina en `at`: una valla alrededor y filas de cultivo
+// (alfombras de hojas) a lo largo de x, una sí y otra no
+pub fn farm(at: [i32; 3], width: i32, depth: i32) -> Result<Vec<PlacedBlock>, String> {
+    if !(3..=MAX_STRUCTURE_SIZE).contains(&width) || !(3..=MAX_STRUCTURE_SIZE).contains(&depth) {
+        return Err(format!("A farm needs width and depth from 3 to {}, got {}x{}", MAX_STRUCTURE_SIZE, width, depth));
+    }
+    let [x0, y0, z0] = at;
+    let mut blocks = fence_ring(at, width, depth)?;
+    for z in (z0 + 1..z0 + depth - 1).step_by(2) {
+        for x in x0 + 1..x0 + width - 1 {
+            blocks.push(PlacedBlock { pos: [x, y0, z], material: CROP_MATERIAL.to_string(), shape: BlockShape::Carpet, turn: None });
+        }
+    }
+    Ok(blocks)
+}
+
+// Gira los bloques de una estructura `quarter_turns` cuartos de vuelta en sentido horario visto desde
+// arriba, de forma que la huella de `width` x `depth` con la esquina en `at` siga empezando en `at`
+// (con el ancho y el fondo intercambiados en los giros impares). Las escaleras y los bloques
+// orientados giran con ella.
+pub fn rotate(blocks: &mut [PlacedBlock], at: [i32; 3], width: i32, depth: i32, quarter_turns: u32) {
+    let (mut width, mut depth) = (width, depth);
+    for _ in 0..quarter_turns % 4 {
+        for block in blocks.iter_mut() {
+            let (lx, lz) = (block.pos[0] - at[0], block.pos[2] - at[2]);
+            block.pos[0] = at[0] + depth - 1 - lz;
+            block.pos[2] = at[2] + lx;
+            if let BlockShape::Stairs { facing, upside_down } = block.shape {
+                let facing = match facing {
+                    Facing::North => Facing::East,
+                    Facing::East => Facing::South,
+                    Facing::South => Facing::West,
+                    Facing::West => Facing::North,
+                };
+                block.shape = BlockShape::Stairs { facing, upside_down };
+            }
+            // Los bloques que miran arriba o abajo y los troncos de pie se quedan como están
+            block.turn = block.turn.map(|turn| match turn {
+                BlockTurn::Facing(Direction::North) => BlockTurn::Facing(Direction::East),
+                BlockTurn::Facing(Direction::East) => BlockTurn::Facing(Direction::South),
+                BlockTurn::Facing(Direction::South) => BlockTurn::Facing(Direction::West),
+                BlockTurn::Facing(Direction::West) => BlockTurn::Facing(Direction::North),
+                BlockTurn::Axis(Axis::X) => BlockTurn::Axis(Axis::Z),
+                BlockTurn::Axis(Axis::Z) => BlockTurn::Axis(Axis::X),
+                other => other,
+            });
+        }
+        std::mem::swap(&mut width, &mut depth);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(blocks: &[PlacedBlock]) -> ([i32; 3], [i32; 3]) {
+        let mut min = [i32::MAX; 3];
+        let mut max = [i32::MIN; 3];
+        for block in blocks {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(block.pos[axis]);
+                max[axis] = max[axis].max(block.pos[axis]);
+            }
+        }
+        (min, max)
+    }
+
+    fn count(blocks: &[PlacedBlock], material: &str) -> usize {
+        blocks.iter().filter(|block| block.material == material).count()
+    }
+
+    #[test]
+    fn house_has_walls_door_window_and_roof() {
+        let blocks = house([-3, 0, -3], 6, 6, 4, "wood", RoofStyle::Stepped).unwrap();
+        // 20 blocks around each of 4 rows, minus the 2x2 door and the 2x2 window
+        assert_eq!(count(&blocks, "wood"), 20 * 4 - 4 - 4);
+        assert_eq!(count(&blocks, WINDOW_MATERIAL), 4);
+        assert_eq!(count(&blocks, ROOF_MATERIAL), 64 + 36 + 16 + 4);
+        assert_eq!(bounds(&blocks), ([-4, 0, -4], [3, 7, 3]));
+        assert!(!blocks.iter().any(|block| block.pos == [-1, 0, 2] || block.pos == [0, 1, 2]), "the door is open");
+
+        let flat = house([0, 0, 0], 4, 5, 3, "stone", RoofStyle::Flat).unwrap();
+        assert_eq!(count(&flat, ROOF_MATERIAL), 6 * 7);
+        assert_eq!(bounds(&flat).1[1], 3);
+        assert!(house([0, 0, 0], 3, 6, 4, "wood", RoofStyle::Flat).is_err());
+        assert!(house([0, 0, 0], 6, 6, 2, "wood", RoofStyle::Flat).is_err());
+        assert!(house([0, 0, 0], 6, 6, 2_000_000_000, "wood", RoofStyle::Flat).is_err());
+    }
+
+    #[test]
+    fn tree_has_trunk_canopy_and_top() {
+        let blocks = tree([-6, 0, 6], 4, 1).unwrap();
+        assert_eq!(count(&blocks, TRUNK_MATERIAL), 4);
+        assert_eq!(count(&blocks, LEAVES_MATERIAL), 9 * 2 + 1);
+        assert_eq!(bounds(&blocks), ([-7, 0, 5], [-5, 6, 7]));
+        assert!(tree([0, 0, 0], 0, 1).is_err());
+        assert!(tree([0, 0, 0], 4, 5).is_err());
+    }
+
+    #[test]
+    fn random_trees_repeat_per_seed_and_differ_between_seeds() {
+        let relative = |seed: u64| {
+            let at = [10, 3, -4];
+            let blocks = random_tree(at, seed);
+            assert!(blocks.contains(&PlacedBlock::full(at, TRUNK_MATERIAL)), "seed {} has no trunk at its base", seed);
+            assert!(count(&blocks, LEAVES_MATERIAL) > 8, "seed {} has almost no leaves", seed);
+            blocks.into_iter().map(|block| ([block.pos[0] - at[0], block.pos[1] - at[1], block.pos[2] - at[2]], block.material)).collect::<Vec<_>>()
+        };
+        assert_eq!(random_tree([0, 0, 0], 9), random_tree([0, 0, 0], 9));
+        let forest: Vec<_> = (0..50).map(relative).collect();
+        for (i, a) in forest.iter().enumerate() {
+            for b in &forest[i + 1..] {
+                assert_ne!(a, b, "two seeds grew the same tree");
+            }
+        }
+    }
+
+    #[test]
+    fn well_and_farm_fill_their_footprint() {
+        let well = well([0, 0, 0]);
+        assert_eq!(well.len(), 8 + 9 + 4 * 2);
+        assert_eq!(bounds(&well), ([0, 0, 0], [2, 3, 2]));
+
+        let built = farm([0, 0, 0], 5, 6).unwrap();
+        assert_eq!(built.iter().filter(|block| block.shape == BlockShape::Carpet).count(), 3 * 2);
+        assert_eq!(bounds(&built), ([0, 0, 0], [4, 0, 5]));
+        assert!(farm([0, 0, 0], 2, 6).is_err());
+    }
+
+    #[test]
+    fn rotation_keeps_the_footprint_corner() {
+        let original = house([2, 0, 3], 4, 6, 3, "wood", RoofStyle::Flat).unwrap();
+        let mut turned = original.clone();
+        rotate(&mut turned, [2, 0, 3], 4, 6, 1);
+        // The walls now span 6 blocks along x and 4 along z, from the same corner; the roof overhangs by one
+        assert_eq!(bounds(&turned), ([1, 0, 2], [8, 3, 7]));
+        let door_wall = turned.iter().filter(|block| block.pos[0] == 2 && block.pos[1] == 0 && block.material == "wood").count();
+        assert_eq!(door_wall, 4 - 2, "the door moved to the -x wall");
+
+        rotate(&mut turned, [2, 0, 3], 6, 4, 3);
+        assert_eq!(turned, original);
+
+        let mut stairs = vec![PlacedBlock { pos: [0, 0, 0], material: "wood".to_string(), shape: BlockShape::Stairs { facing: Facing::North, upside_down: false }, turn: None }];
+        rotate(&mut stairs, [0, 0, 0], 1, 1, 2);
+        assert_eq!(stairs[0].shape, BlockShape::Stairs { facing: Facing::South, upside_down: false });
+    }
+
+    #[test]
+    fn pyramid_layers_shrink_to_the_top() {
+        let blocks = pyramid([0, 0, 0], 5, "stone").unwrap();
+        assert_eq!(blocks.len(), 25 + 9 + 1);
+        assert_eq!(bounds(&blocks), ([0, 0, 0], [4, 2, 4]));
+        assert!(pyramid([0, 0, 0], 0, "stone").is_err());
+    }
+
+    #[test]
+    fn fence_ring_follows_the_edge() {
+        let blocks = fence_ring([1, 2, 3], 4, 3).unwrap();
+        assert_eq!(blocks.len(), 2 * (4 + 3) - 4);
+        assert!(blocks.iter().all(|block| block.shape == BlockShape::Fence));
+        assert_eq!(bounds(&blocks), ([1, 2, 3], [4, 2, 5]));
+        assert!(fence_ring([0, 0, 0], 1, 4).is_err());
+        // Found by fuzzing: a huge ring overflowed its far corner instead of being refused
+        assert!(fence_ring([0, 0, 0], i32::MAX, 4).is_err());
+        assert!(farm([0, 0, 0], 4, MAX_STRUCTURE_SIZE + 1).is_err());
+    }
+}
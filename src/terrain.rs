@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use crate::noise::{fractal_noise, lattice, position_seed, ridged_noise};
+use crate::structures::{random_tree, PlacedBlock};
+use crate::village::Village;
+
+pub const SURFACE_MATERIAL: &str = "grass";
+pub const GROUND_MATERIAL: &str = "stone";
+pub const WATER_MATERIAL: &str = "water";
+
+// Semillas derivadas de la del terreno para cada cosa que se decide al azar, así cambiar la
+// densidad de árboles no mueve las colinas
+const TREE_SEED: u64 = 0x7265_6573;
+const CAVE_SEED: u64 = 0x6361_7665;
+
+// Altura media más alta que se acepta: con las colinas, un terreno de 256 de lado aún cabe en
+// `world::MAX_BLOCKS`
+pub const MAX_HEIGHT: i32 = 128;
+
+// Cuevas excavadas en la piedra donde un ruido de crestas 3D supera `threshold`, con lo que
+// quedan túneles que serpentean y se cruzan
+#[derive(Clone, Debug, PartialEq)]
+pub struct Caves {
+    pub seed: u64,
+    pub threshold: f32,            // Entre 0 y 1; cuanto más alto, más estrechos y escasos los túneles
+    pub scale: f32,                // Ancho aproximado de una curva de túnel, en bloques
+    pub squash: f32,               // Aplasta el ruido en vertical: por encima de 1 las cuevas son más anchas que altas
+    pub crust: i32,                // Bloques bajo la superficie que nunca se excavan
+    pub water_level: Option<i32>,  // Lo excavado a esta y o más abajo se llena de agua
+}
+
+impl Default for Caves {
+    fn default() -> Self {
+        Caves { seed: 0, threshold: 0.85, scale: 10.0, squash: 2.0, crust: 2, water_level: None }
+    }
+}
+
+impl Caves {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..1.0).contains(&self.threshold) {
+            return Err(format!("Cave threshold must be at least 0 and below 1, got {}", self.threshold));
+        }
+        if self.scale <= 0.0 || self.squash <= 0.0 {
+            return Err(format!("Cave scale and squash must be positive, got {} and {}", self.scale, self.squash));
+        }
+        if self.crust < 1 {
+            return Err(format!("Cave crust must be at least 1 block, got {}", self.crust));
+        }
+        Ok(())
+    }
+
+    // Si el bloque en `pos` queda dentro de una cueva
+    pub fn carves(&self, pos: [i32; 3]) -> bool {
+        let [x, y, z] = pos.map(|value| value as f32 / self.scale);
+        ridged_noise([x, y * self.squash, z], 3, self.seed ^ CAVE_SEED) > self.threshold
+    }
+}
+
+// Colinas generadas con ruido: una columna maciza de bloques desde y = 0 por cada posición de un
+// cuadrado de `size` bloques centrado en el origen, con hierba arriba y piedra debajo, un pueblo
+// si se pide, cuevas bajo la corteza y árboles distintos repartidos por el resto de la superficie
+#[derive(Clone, Debug, PartialEq)]
+pub struct Terrain {
+    pub seed: u64,
+    pub size: i32,         // Bloques por lado
+    pub height: i32,       // Altura media de la superficie, en bloques
+    pub amplitude: i32,    // Cuánto pueden subir o bajar las colinas respecto a la media
+    pub scale: f32,        // Ancho aproximado de una colina, en bloques
+    pub tree_density: f32, // Probabilidad de que crezca un árbol en cada bloque de la superficie
+    pub village: Option<Village>,
+    pub caves: Option<Caves>,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Terrain { seed: 0, size: 32, height: 4, amplitude: 3, scale: 12.0, tree_density: 0.0, village: None, caves: None }
+    }
+}
+
+impl Terrain {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=256).contains(&self.size) {
+            return Err(format!("Terrain size must be between 1 and 256 blocks, got {}", self.size));
+        }
+        if self.amplitude < 0 || self.height < self.amplitude {
+            return Err(format!("Terrain amplitude can't be negative or above the height, got {} and {}", self.amplitude, self.height));
+        }
+        if self.height > MAX_HEIGHT {
+            return Err(format!("Terrain height can be at most {} blocks, got {}", MAX_HEIGHT, self.height));
+        }
+        if self.scale <= 0.0 {
+            return Err(format!("Terrain scale must be positive, got {}", self.scale));
+        }
+        if !(0.0..=1.0).contains(&self.tree_density) {
+            return Err(format!("tree_density must be between 0 and 1, got {}", self.tree_density));
+        }
+        self.caves.as_ref().map_or(Ok(()), Caves::validate)?;
+        self.village.as_ref().map_or(Ok(()), Village::validate)
+    }
+
+    // Posiciones x (y z) de las columnas del terreno
+    pub fn span(&self) -> Range<i32> {
+        -self.size / 2..self.size - self.size / 2
+    }
+
+    // y del bloque más alto de la columna en (x, z)
+    pub fn surface(&self, x: i32, z: i32) -> i32 {
+        let noise = fractal_noise([x as f32 / self.scale, 0.0, z as f32 / self.scale], 4, self.seed);
+        self.height + ((noise * 2.0 - 1.0) * self.amplitude as f32).round() as i32
+    }
+
+    // Bloques del terreno, del pueblo y de los árboles, sin posiciones repetidas y siempre en el
+    // mismo orden. Las cuevas no tocan la fila y = 0 ni los `crust` bloques de arriba de cada columna. Los árboles no crecen cerca de los edificios ni en los caminos, y no reemplazan
+    // el terreno ni a los árboles plantados antes.
+    pub fn blocks(&self) -> Vec<PlacedBlock> {
+        let mut blocks: BTreeMap<[i32; 3], PlacedBlock> = BTreeMap::new();
+        for x in self.span() {
+            for z in self.span() {
+                let top = self.surface(x, z);
+                for y in 0..top {
+                    let material = match &self.caves {
+                        Some(caves) if y > 0 && y <= top - caves.crust && caves.carves([x, y, z]) => match caves.water_level {
+                            Some(level) if y <= level => WATER_MATERIAL,
+                            _ => continue,
+                        },
+                        _ => GROUND_MATERIAL,
+                    };
+                    blocks.insert([x, y, z], PlacedBlock::full([x, y, z], material));
+                }
+                blocks.insert([x, top, z], PlacedBlock::full([x, top, z], SURFACE_MATERIAL));
+            }
+        }
+        let reserved = match &self.village {
+            Some(village) => village.build(self, &mut blocks),
+            None => Default::default(),
+        };
+        for x in self.span() {
+            for z in self.span() {
+                if reserved.contains(&[x, z]) || lattice([x, 0, z], self.seed ^ TREE_SEED) >= self.tree_density {
+                    continue;
+                }
+                let at = [x, self.surface(x, z) + 1, z];
+                for block in random_tree(at, position_seed(at, self.seed ^ TREE_SEED)) {
+                    blocks.entry(block.pos).or_insert(block);
+                }
+            }
+        }
+        blocks.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::{LEAVES_MATERIAL, TRUNK_MATERIAL};
+
+    #[test]
+    fn surface_stays_within_the_amplitude() {
+        let terrain = Terrain { seed: 11, size: 24, ..Terrain::default() };
+        let heights: Vec<i32> = terrain.span().flat_map(|x| terrain.span().map(move |z| (x, z))).map(|(x, z)| terrain.surface(x, z)).collect();
+        assert!(heights.iter().all(|height| (1..=7).contains(height)));
+        assert!(heights.iter().any(|&height| height != heights[0]), "the terrain is flat");
+        assert_eq!(terrain.span(), -12..12);
+    }
+
+    #[test]
+    fn same_seed_same_terrain_and_trees_stand_on_grass() {
+        let terrain = Terrain { seed: 5, size: 16, tree_density: 0.05, ..Terrain::default() };
+        let blocks = terrain.blocks();
+        assert_eq!(blocks, terrain.blocks());
+        assert_ne!(blocks, Terrain { seed: 6, ..terrain.clone() }.blocks());
+
+        let material_at = |pos: [i32; 3]| blocks.iter().find(|block| block.pos == pos).map(|block| block.material.as_str());
+        let mut trees = 0;
+        for x in terrain.span() {
+            for z in terrain.span() {
+                let top = terrain.surface(x, z);
+                assert_eq!(material_at([x, top, z]), Some(SURFACE_MATERIAL));
+                if material_at([x, top + 1, z]) == Some(TRUNK_MATERIAL) {
+                    trees += 1;
+                }
+            }
+        }
+        assert!(trees > 0, "no trees at density 0.05");
+        assert!(blocks.iter().any(|block| block.material == LEAVES_MATERIAL));
+
+        let bare = Terrain { tree_density: 0.0, ..terrain }.blocks();
+        assert!(bare.iter().all(|block| block.material == SURFACE_MATERIAL || block.material == GROUND_MATERIAL));
+        assert!(Terrain { amplitude: 9, ..Terrain::default() }.validate().is_err());
+        assert!(Terrain { height: 1_000_000, ..Terrain::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn caves_keep_the_crust_and_flood_below_the_water_level() {
+        let caves = Caves { seed: 3, threshold: 0.8, water_level: Some(4), ..Caves::default() };
+        let terrain = Terrain { seed: 1, size: 24, height: 12, caves: Some(caves.clone()), ..Terrain::default() };
+        let blocks: BTreeMap<[i32; 3], String> = terrain.blocks().into_iter().map(|block| (block.pos, block.material)).collect();
+        assert_eq!(terrain.blocks(), Terrain { caves: Some(caves.clone()), ..terrain.clone() }.blocks());
+
+        let (mut carved, mut flooded) = (0, 0);
+        for x in terrain.span() {
+            for z in terrain.span() {
+                let top = terrain.surface(x, z);
+                assert_eq!(blocks.get(&[x, 0, z]).map(String::as_str), Some(GROUND_MATERIAL));
+                for y in top - caves.crust + 1..=top {
+                    assert!(blocks.contains_key(&[x, y, z]), "crust opened at {:?}", [x, y, z]);
+                }
+                for y in 1..=top - caves.crust {
+                    match blocks.get(&[x, y, z]).map(String::as_str) {
+                        None => {
+                            assert!(y > 4, "dry cave below the water level at {:?}", [x, y, z]);
+                            carved += 1;
+                        }
+                        Some(WATER_MATERIAL) => {
+                            assert!(y <= 4 && caves.carves([x, y, z]));
+                            flooded += 1;
+                        }
+                        Some(_) => assert!(!caves.carves([x, y, z])),
+                    }
+                }
+            }
+        }
+        assert!(carved > 0 && flooded > 0, "{} carved and {} flooded blocks", carved, flooded);
+        assert!(Caves { threshold: 1.0, ..caves }.validate().is_err());
+    }
+}
@@ -1,26 +1,344 @@
 use crate::color::Color;
+use crate::error::RayTracerError;
+
+// Repite una coordenada en [0, 1). rem_euclid puede redondear a 1.0 para valores negativos muy
+// pequeños, que deben quedar en el último texel y no saltar al primero
+fn wrap(coordinate: f32) -> f32 {
+    coordinate.rem_euclid(1.0).min(1.0 - f32::EPSILON / 2.0)
+}
+
+// Limita una coordenada a [0, 1) sin repetir, para que un desplazamiento no salga de la cara
+pub fn clamp_to_face(coordinate: f32) -> f32 {
+    coordinate.clamp(0.0, 1.0 - f32::EPSILON / 2.0)
+}
+
+// Cómo se colocan las coordenadas de la cara sobre la imagen, para orientar cada cara o elegir
+// una región de un atlas sin editar el arte
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvTransform {
+    pub flip_v: bool,      // Invierte la imagen de arriba abajo
+    pub rotate: u32,       // Giro de la imagen en sentido horario: 0, 90, 180 o 270 grados
+    pub scale: [f32; 2],   // Tamaño de la región de la imagen que cubre la cara (1 = la imagen entera)
+    pub offset: [f32; 2],  // Esquina superior izquierda de esa región
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        UvTransform { flip_v: false, rotate: 0, scale: [1.0, 1.0], offset: [0.0, 0.0] }
+    }
+}
+
+impl UvTransform {
+    // Lleva (u, v) de la cara a la imagen: primero se repite dentro de la cara, luego se invierte y
+    // gira, y por último se escala y desplaza a la región de la imagen
+    pub fn apply(&self, u: f32, v: f32) -> (f32, f32) {
+        let (u, v) = (wrap(u), wrap(v));
+        let v = if self.flip_v { 1.0 - v } else { v };
+        let (u, v) = match self.rotate {
+            90 => (v, 1.0 - u),
+            180 => (1.0 - u, 1.0 - v),
+            270 => (1.0 - v, u),
+            _ => (u, v),
+        };
+        (self.offset[0] + u * self.scale[0], self.offset[1] + v * self.scale[1])
+    }
+}
 
 #[derive(Debug, Clone)] // Añade Debug y Clone aquí
 pub struct Texture {
     pub width: u32,
     pub height: u32,
     pub pixels: Vec<Color>,
+    pub uv_transform: UvTransform,
+    pub source: Option<String>, // Ruta con la que la pidió un archivo de escena, para volver a guardarla
+}
+
+// Lado más largo de una textura. Las de los bloques tienen unos pocos cientos de texeles y aún
+// caben atlas grandes; una cabecera que diga más se rechaza antes de reservar memoria para ella.
+pub const MAX_TEXTURE_SIZE: u32 = 4096;
+
+fn decode<R: std::io::BufRead + std::io::Seek>(mut reader: image::ImageReader<R>) -> image::ImageResult<image::DynamicImage> {
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(MAX_TEXTURE_SIZE);
+    limits.max_image_height = Some(MAX_TEXTURE_SIZE);
+    reader.limits(limits);
+    reader.decode()
 }
 
 impl Texture {
-    pub fn load(path: &str) -> Result<Self, image::ImageError> {
-        let img = image::open(path)?.to_rgba8();
+    pub fn load(path: &str) -> Result<Self, RayTracerError> {
+        let image = image::ImageReader::open(path)
+            .map_err(image::ImageError::IoError)
+            .and_then(decode)
+            .map_err(|e| RayTracerError::image(path, e))?;
+        Ok(Self::from_image(image))
+    }
+
+    // Decodifica una imagen ya en memoria, como las texturas incluidas en el ejecutable
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, image::ImageError> {
+        let reader = image::ImageReader::new(std::io::Cursor::new(bytes)).with_guessed_format().map_err(image::ImageError::IoError)?;
+        Ok(Self::from_image(decode(reader)?))
+    }
+
+    fn from_image(image: image::DynamicImage) -> Self {
+        let img = image.to_rgba8();
         let (width, height) = img.dimensions();
         let pixels = img.pixels()
             .map(|p| Color::new(p[0], p[1], p[2]))
             .collect();
 
-        Ok(Self { width, height, pixels })
+        Self { width, height, pixels, uv_transform: UvTransform::default(), source: None }
+    }
+
+    // Damero de `cells` x `cells` casillas de `cell_size` texeles, alternando `a` y `b` desde la esquina
+    pub fn checkerboard(cells: u32, cell_size: u32, a: Color, b: Color) -> Self {
+        let size = cells * cell_size;
+        let pixels = (0..size * size)
+            .map(|index| {
+                let (x, y) = (index % size / cell_size, index / size / cell_size);
+                if (x + y) % 2 == 0 { a } else { b }
+            })
+            .collect();
+        Self { width: size, height: size, pixels, uv_transform: UvTransform::default(), source: None }
+    }
+
+    // El damero magenta y negro de siempre, para las texturas que no se pudieron cargar
+    pub fn missing() -> Self {
+        Self::checkerboard(4, 4, Color::new(255, 0, 255), Color::new(0, 0, 0))
+    }
+
+    pub fn with_uv_transform(mut self, uv_transform: UvTransform) -> Self {
+        self.uv_transform = uv_transform;
+        self
     }
 
+    // La textura se repite: u y v se llevan a [0, 1) antes de buscar el texel, y el índice se limita
+    // por si el redondeo de un valor justo por debajo de 1 lo deja en el borde
     pub fn sample(&self, u: f32, v: f32) -> Color {
-        let x = ((u * self.width as f32) as u32).min(self.width - 1);
-        let y = ((v * self.height as f32) as u32).min(self.height - 1);
+        debug_assert!(!u.is_nan() && !v.is_nan(), "NaN texture coordinates ({}, {})", u, v);
+        let (u, v) = self.uv_transform.apply(u, v);
+        let x = ((wrap(u) * self.width as f32) as u32).min(self.width - 1);
+        let y = ((wrap(v) * self.height as f32) as u32).min(self.height - 1);
         self.pixels[(y * self.width + x) as usize]
     }
+
+    // Interpolación bilineal entre los cuatro texels vecinos; la textura se repite en los bordes
+    pub fn sample_bilinear(&self, u: f32, v: f32) -> Color {
+        debug_assert!(!u.is_nan() && !v.is_nan(), "NaN texture coordinates ({}, {})", u, v);
+        let (u, v) = self.uv_transform.apply(u, v);
+        let x = wrap(u) * self.width as f32 - 0.5;
+        let y = wrap(v) * self.height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+
+        let texel = |tx: i64, ty: i64| {
+            let tx = tx.rem_euclid(self.width as i64) as u32;
+            let ty = ty.rem_euclid(self.height as i64) as u32;
+            self.pixels[(ty * self.width + tx) as usize]
+        };
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = Color::lerp(&texel(x0, y0), &texel(x0 + 1, y0), tx);
+        let bottom = Color::lerp(&texel(x0, y0 + 1), &texel(x0 + 1, y0 + 1), tx);
+        Color::lerp(&top, &bottom, ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COORDS: [f32; 6] = [-0.5, -0.0, 0.0, 0.999, 1.0, 1.5];
+
+    // 2x2 texels with distinct colors: (0, 0) red, (1, 0) green, (0, 1) blue, (1, 1) white
+    fn checker() -> Texture {
+        Texture {
+            width: 2,
+            height: 2,
+            pixels: vec![
+                Color::new(255, 0, 0),
+                Color::new(0, 255, 0),
+                Color::new(0, 0, 255),
+                Color::new(255, 255, 255),
+            ],
+            uv_transform: UvTransform::default(),
+            source: None,
+        }
+    }
+
+    // Corner colors of the checker in image space
+    const TOP_LEFT: Color = Color { r: 255, g: 0, b: 0 };
+    const TOP_RIGHT: Color = Color { r: 0, g: 255, b: 0 };
+    const BOTTOM_LEFT: Color = Color { r: 0, g: 0, b: 255 };
+    const BOTTOM_RIGHT: Color = Color { r: 255, g: 255, b: 255 };
+
+    fn corner_at_origin(transform: UvTransform) -> Color {
+        checker().with_uv_transform(transform).sample(0.1, 0.1)
+    }
+
+    #[test]
+    fn uv_transform_places_expected_corner_at_origin() {
+        let flip_v = |flip_v| UvTransform { flip_v, ..UvTransform::default() };
+        let rotate = |rotate| UvTransform { rotate, ..UvTransform::default() };
+        assert_eq!(corner_at_origin(UvTransform::default()), TOP_LEFT);
+        assert_eq!(corner_at_origin(flip_v(true)), BOTTOM_LEFT);
+        assert_eq!(corner_at_origin(rotate(90)), BOTTOM_LEFT);
+        assert_eq!(corner_at_origin(rotate(180)), BOTTOM_RIGHT);
+        assert_eq!(corner_at_origin(rotate(270)), TOP_RIGHT);
+        assert_eq!(corner_at_origin(UvTransform { flip_v: true, rotate: 90, ..UvTransform::default() }), BOTTOM_RIGHT);
+        assert_eq!(corner_at_origin(UvTransform { offset: [0.5, 0.0], ..UvTransform::default() }), TOP_RIGHT);
+        assert_eq!(corner_at_origin(UvTransform { scale: [0.5, 0.5], offset: [0.5, 0.5], ..UvTransform::default() }), BOTTOM_RIGHT);
+    }
+
+    #[test]
+    fn uv_scale_selects_an_atlas_region() {
+        // Half-size region at the top right: the whole face shows the green texel in both filters
+        let transform = UvTransform { scale: [0.5, 0.5], offset: [0.5, 0.0], ..UvTransform::default() };
+        let texture = checker().with_uv_transform(transform);
+        for (u, v) in [(0.1, 0.1), (0.5, 0.5), (0.9, 0.9), (1.4, -0.3)] {
+            assert_eq!(texture.sample(u, v), TOP_RIGHT, "u = {}, v = {}", u, v);
+        }
+    }
+
+    // Texel index the repeat wrap should pick for a coordinate on a 2-texel axis
+    // (-0.5 wraps to 0.5; -0.0, 0.0 and 1.0 all land on the first texel)
+    fn wrapped_index(coord: f32) -> u32 {
+        if coord == -0.5 || coord == 0.999 || coord == 1.5 { 1 } else { 0 }
+    }
+
+    #[test]
+    fn nearest_wraps_boundary_coordinates() {
+        let texture = checker();
+        for u in COORDS {
+            for v in COORDS {
+                let expected = texture.pixels[(wrapped_index(v) * 2 + wrapped_index(u)) as usize];
+                assert_eq!(texture.sample(u, v), expected, "u = {}, v = {}", u, v);
+            }
+        }
+    }
+
+    // 2x2 texels whose halfway blends are whole numbers: (0, 0) red, (1, 0) green, (0, 1) blue,
+    // (1, 1) a dark gray-blue
+    fn blendable() -> Texture {
+        Texture {
+            width: 2,
+            height: 2,
+            pixels: vec![Color::new(200, 0, 0), Color::new(0, 200, 0), Color::new(0, 0, 200), Color::new(40, 80, 120)],
+            uv_transform: UvTransform::default(),
+            source: None,
+        }
+    }
+
+    const BELOW_ONE: f32 = 1.0 - f32::EPSILON / 2.0; // Largest f32 below 1
+
+    #[test]
+    fn nearest_picks_exact_texels_at_the_edges() {
+        let texture = blendable();
+        let (red, green, blue, gray) = (texture.pixels[0], texture.pixels[1], texture.pixels[2], texture.pixels[3]);
+        for (u, v, expected) in [
+            (0.0, 0.0, red),
+            (1.0, 0.0, red),
+            (-1.0, 1.0, red),
+            (BELOW_ONE, 0.0, green),
+            (-0.25, 0.25, green),
+            (-f32::EPSILON / 4.0, 0.0, green),
+            (0.0, BELOW_ONE, blue),
+            (0.25, -0.5, blue),
+            (BELOW_ONE, BELOW_ONE, gray),
+            (-0.25, -0.25, gray),
+        ] {
+            assert_eq!(texture.sample(u, v), expected, "u = {}, v = {}", u, v);
+        }
+    }
+
+    #[test]
+    fn bilinear_blends_exactly_across_the_wrapped_edges() {
+        let texture = blendable();
+        for (u, v, expected) in [
+            // Texel centers take the texel's own color
+            (0.25, 0.25, Color::new(200, 0, 0)),
+            (0.75, 0.25, Color::new(0, 200, 0)),
+            (-0.25, 0.25, Color::new(0, 200, 0)),
+            (0.25, -0.25, Color::new(0, 0, 200)),
+            // On an edge the texture wraps: half of the last column and half of the first
+            (0.0, 0.25, Color::new(100, 100, 0)),
+            (1.0, 0.25, Color::new(100, 100, 0)),
+            (-1.0, 0.25, Color::new(100, 100, 0)),
+            (0.25, 0.0, Color::new(100, 0, 100)),
+            (0.25, 1.0, Color::new(100, 0, 100)),
+            // At a corner all four texels count the same
+            (0.0, 0.0, Color::new(60, 70, 80)),
+            (1.0, -1.0, Color::new(60, 70, 80)),
+            // Just under 1 the weight is a hair short of one half, so red rounds down one step
+            (BELOW_ONE, 0.25, Color::new(99, 100, 0)),
+        ] {
+            assert_eq!(texture.sample_bilinear(u, v), expected, "u = {}, v = {}", u, v);
+        }
+    }
+
+    #[test]
+    fn just_below_one_does_not_overflow() {
+        let texture = checker();
+        let below_one = 1.0 - f32::EPSILON / 2.0; // Largest f32 below 1
+        assert_eq!(texture.sample(-f32::EPSILON / 4.0, below_one), texture.sample(below_one, below_one));
+        texture.sample_bilinear(-f32::EPSILON / 4.0, below_one);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "NaN texture coordinates")]
+    fn nearest_rejects_nan() {
+        checker().sample(f32::NAN, 0.5);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "NaN texture coordinates")]
+    fn bilinear_rejects_nan() {
+        checker().sample_bilinear(0.5, f32::NAN);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn nan_falls_back_to_a_texel() {
+        let texture = checker();
+        for coord in COORDS {
+            assert!(texture.pixels.contains(&texture.sample(f32::NAN, coord)));
+            assert!(texture.pixels.contains(&texture.sample(coord, f32::NAN)));
+            texture.sample_bilinear(f32::NAN, coord);
+        }
+    }
+
+    #[test]
+    fn missing_files_are_io_errors_and_bad_images_image_errors() {
+        let error = Texture::load("/nonexistent-raytracer-dir/stone.png").err().expect("the file doesn't exist");
+        assert!(matches!(error, RayTracerError::Io { .. }), "{:?}", error);
+
+        let path = std::env::temp_dir().join("raytracer_minecraft_not_an_image.png");
+        std::fs::write(&path, b"not a png").unwrap();
+        let error = Texture::load(&path.to_string_lossy()).err().expect("the file isn't an image");
+        assert!(matches!(error, RayTracerError::Image(_)), "{:?}", error);
+        assert!(error.to_string().contains("raytracer_minecraft_not_an_image.png"), "{}", error);
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Vec::new();
+        image::RgbImage::new(width, height).write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+        png
+    }
+
+    #[test]
+    fn oversized_and_truncated_images_are_refused() {
+        assert_eq!(Texture::from_bytes(&png(MAX_TEXTURE_SIZE, 1)).unwrap().width, MAX_TEXTURE_SIZE);
+        // Found by fuzzing: a header can claim any size, and decoding it reserved the memory first
+        let error = Texture::from_bytes(&png(MAX_TEXTURE_SIZE + 1, 1)).err().expect("the image is too wide");
+        assert!(matches!(error, image::ImageError::Limits(_)), "{:?}", error);
+        // Cut anywhere up to the middle of the pixel data
+        let data = png(4, 4);
+        for end in 0..data.len() / 2 {
+            assert!(Texture::from_bytes(&data[..end]).is_err(), "{} of {} bytes decoded", end, data.len());
+        }
+    }
 }
@@ -0,0 +1,163 @@
+use crate::settings::{FocusFalloff, TileOrder};
+
+// Lado de los bloques de píxeles en que se reparte el render
+pub const TILE_SIZE: usize = 32;
+// Veces que se multiplican las muestras por píxel en el propio foco
+pub const FOCUS_BOOST: u32 = 4;
+
+// Rectángulo de la imagen; los del borde derecho e inferior pueden ser más pequeños
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// Tiles que cubren una imagen de width x height en el orden en que se renderizan. El orden solo
+// depende del tamaño y de `order`, así que dos renders de la misma imagen terminan los mismos tiles
+// en el mismo orden.
+pub fn ordered_tiles(width: usize, height: usize, order: TileOrder) -> Vec<Tile> {
+    let (columns, rows) = (width.div_ceil(TILE_SIZE), height.div_ceil(TILE_SIZE));
+    let cells: Vec<(usize, usize)> = match order {
+        TileOrder::RowMajor => (0..rows).flat_map(|row| (0..columns).map(move |column| (column, row))).collect(),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => hilbert(columns, rows),
+    };
+    cells.into_iter()
+        .map(|(column, row)| {
+            let (x, y) = (column * TILE_SIZE, row * TILE_SIZE);
+            Tile { x, y, width: TILE_SIZE.min(width - x), height: TILE_SIZE.min(height - y) }
+        })
+        .collect()
+}
+
+// Muestras por píxel de un tile al refinar alrededor de `focus` (en píxeles): `samples` lejos del
+// foco y hasta FOCUS_BOOST veces más cerca de él, según el peso de `falloff` a la distancia del
+// centro del tile. Con `radius` 0 todos los tiles reciben `samples`.
+pub fn focused_samples(tile: &Tile, samples: u32, focus: [f32; 2], radius: f32, falloff: FocusFalloff) -> u32 {
+    if radius <= 0.0 {
+        return samples;
+    }
+    let center_x = tile.x as f32 + tile.width as f32 * 0.5;
+    let center_y = tile.y as f32 + tile.height as f32 * 0.5;
+    let distance = (center_x - focus[0]).hypot(center_y - focus[1]) / radius;
+    let extra = samples as f32 * (FOCUS_BOOST - 1) as f32 * falloff.weight(distance);
+    samples + extra.round() as u32
+}
+
+// Espiral desde el tile central: un paso a la derecha, uno abajo, dos a la izquierda, dos arriba,
+// tres a la derecha... saltando las casillas que caen fuera de la imagen
+fn spiral(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let total = columns * rows;
+    let mut cells = Vec::with_capacity(total);
+    let (mut x, mut y) = (((columns as i64) - 1) / 2, ((rows as i64) - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let (mut direction, mut length) = (0, 1);
+    let inside = |x: i64, y: i64| (0..columns as i64).contains(&x) && (0..rows as i64).contains(&y);
+    if inside(x, y) {
+        cells.push((x as usize, y as usize));
+    }
+    while cells.len() < total {
+        // Cada longitud se recorre dos veces antes de crecer
+        for _ in 0..2 {
+            let (dx, dy) = directions[direction];
+            for _ in 0..length {
+                x += dx;
+                y += dy;
+                if inside(x, y) {
+                    cells.push((x as usize, y as usize));
+                }
+            }
+            direction = (direction + 1) % 4;
+        }
+        length += 1;
+    }
+    cells
+}
+
+// Curva de Hilbert sobre el cuadrado de lado potencia de dos que cubre la cuadrícula: los tiles
+// seguidos quedan siempre juntos, así que la imagen crece por zonas en lugar de por filas
+fn hilbert(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let side = columns.max(rows).max(1).next_power_of_two();
+    (0..side * side)
+        .map(|d| hilbert_point(side, d))
+        .filter(|&(x, y)| x < columns && y < rows)
+        .collect()
+}
+
+// Punto `d` de la curva de Hilbert de lado `side`
+fn hilbert_point(side: usize, d: usize) -> (usize, usize) {
+    let (mut x, mut y, mut t) = (0, 0, d);
+    let mut s = 1;
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn every_order_covers_each_pixel_once() {
+        for order in [TileOrder::RowMajor, TileOrder::Spiral, TileOrder::Hilbert] {
+            for (width, height) in [(800, 600), (70, 33), (1, 1), (31, 200)] {
+                let tiles = ordered_tiles(width, height, order);
+                assert_eq!(tiles, ordered_tiles(width, height, order), "{:?} is not deterministic", order);
+                let mut covered = HashSet::new();
+                for tile in &tiles {
+                    for y in tile.y..tile.y + tile.height {
+                        for x in tile.x..tile.x + tile.width {
+                            assert!(x < width && y < height && covered.insert((x, y)), "{:?} {}x{}: pixel {:?} twice or outside", order, width, height, (x, y));
+                        }
+                    }
+                }
+                assert_eq!(covered.len(), width * height);
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_starts_in_the_center_and_hilbert_stays_adjacent() {
+        let tiles = ordered_tiles(800, 600, TileOrder::Spiral);
+        // 25 x 19 tiles: el central es el (12, 9)
+        assert_eq!((tiles[0].x, tiles[0].y), (12 * TILE_SIZE, 9 * TILE_SIZE));
+        let distance = |tile: &Tile| (tile.x as i64 / TILE_SIZE as i64 - 12).abs().max((tile.y as i64 / TILE_SIZE as i64 - 9).abs());
+        assert!(tiles.windows(2).all(|pair| distance(&pair[0]) <= distance(&pair[1])), "the spiral moves back inward");
+        assert_eq!(ordered_tiles(800, 600, TileOrder::RowMajor)[1].x, TILE_SIZE);
+
+        let curve: Vec<(usize, usize)> = (0..64).map(|d| hilbert_point(8, d)).collect();
+        assert!(curve.windows(2).all(|pair| pair[0].0.abs_diff(pair[1].0) + pair[0].1.abs_diff(pair[1].1) == 1));
+    }
+
+    #[test]
+    fn samples_fall_off_away_from_the_focus() {
+        let tiles = ordered_tiles(640, 480, TileOrder::RowMajor);
+        let focus = [100.0, 100.0];
+        for falloff in [FocusFalloff::Linear, FocusFalloff::Gaussian] {
+            let samples = |tile: &Tile| focused_samples(tile, 4, focus, 200.0, falloff);
+            let distance = |tile: &Tile| (tile.x as f32 + 16.0 - focus[0]).hypot(tile.y as f32 + 16.0 - focus[1]);
+            let mut by_distance: Vec<&Tile> = tiles.iter().collect();
+            by_distance.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+            assert!(by_distance.windows(2).all(|pair| samples(pair[0]) >= samples(pair[1])), "{:?} grows with distance", falloff);
+            assert!(samples(by_distance[0]) > 4 * (FOCUS_BOOST - 1));
+            assert_eq!(samples(by_distance[by_distance.len() - 1]), 4);
+        }
+        assert!(tiles.iter().all(|tile| focused_samples(tile, 4, focus, 0.0, FocusFalloff::Linear) == 4));
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashSet};
+use crate::noise::Rng;
+use crate::structures::{self, PlacedBlock, RoofStyle};
+use crate::terrain::Terrain;
+
+pub const PATH_MATERIAL: &str = "path";
+pub const FOUNDATION_MATERIAL: &str = "stone";
+pub const HOUSE_MATERIAL: &str = "wood";
+
+// Intentos de colocar cada sitio antes de rendirse, para que un terreno muy abrupto no cuelgue la carga
+const ATTEMPTS_PER_SITE: usize = 64;
+// Columnas alrededor de cada sitio donde no crecen árboles, lo que ocupan el alero y las hojas
+const TREE_CLEARANCE: i32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SiteKind {
+    House,
+    Well,
+    Farm,
+}
+
+impl SiteKind {
+    // Ancho y fondo sin girar
+    fn footprint(self) -> (i32, i32) {
+        match self {
+            SiteKind::House => (6, 6),
+            SiteKind::Well => (3, 3),
+            SiteKind::Farm => (5, 7),
+        }
+    }
+
+    fn blocks(self, at: [i32; 3]) -> Vec<PlacedBlock> {
+        let (width, depth) = self.footprint();
+        match self {
+            SiteKind::House => structures::house(at, width, depth, 4, HOUSE_MATERIAL, RoofStyle::Stepped),
+            SiteKind::Well => Ok(structures::well(at)),
+            SiteKind::Farm => structures::farm(at, width, depth),
+        }
+        .expect("village templates have valid sizes")
+    }
+}
+
+// Un edificio del pueblo: la esquina de su huella (ya girada) y el y del suelo sobre el que se
+// levanta, uno por encima del bloque más alto del terreno que tapa
+#[derive(Clone, Debug, PartialEq)]
+pub struct Site {
+    pub kind: SiteKind,
+    pub at: [i32; 3],
+    pub width: i32,
+    pub depth: i32,
+    pub quarter_turns: u32,
+}
+
+impl Site {
+    // Si la columna (x, z) cae en la huella ampliada `margin` bloques por cada lado
+    pub fn covers(&self, x: i32, z: i32, margin: i32) -> bool {
+        (self.at[0] - margin..self.at[0] + self.width + margin).contains(&x) && (self.at[2] - margin..self.at[2] + self.depth + margin).contains(&z)
+    }
+
+    fn center(&self) -> [i32; 2] {
+        [self.at[0] + self.width / 2, self.at[2] + self.depth / 2]
+    }
+}
+
+// Pueblo repartido sobre un terreno: hasta `count` casas, pozos y huertos en zonas llanas, con
+// al menos `spacing` bloques libres entre ellos y unidos por caminos
+#[derive(Clone, Debug, PartialEq)]
+pub struct Village {
+    pub seed: u64,
+    pub count: usize,
+    pub spacing: i32,
+}
+
+impl Default for Village {
+    fn default() -> Self {
+        Village { seed: 0, count: 5, spacing: 2 }
+    }
+}
+
+impl Village {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.count > 64 {
+            return Err(format!("A village can have at most 64 sites, got {}", self.count));
+        }
+        if !(0..=16).contains(&self.spacing) {
+            return Err(format!("Village spacing must be between 0 and 16 blocks, got {}", self.spacing));
+        }
+        Ok(())
+    }
+
+    // Elige los sitios al azar con la semilla del pueblo. Solo se aceptan huellas dentro del
+    // terreno, sin más de un bloque de desnivel y sin invadir el espacio de otro sitio; si no hay
+    // sitio para todos, el pueblo se queda más pequeño.
+    pub fn sites(&self, terrain: &Terrain) -> Vec<Site> {
+        let mut rng = Rng::new(self.seed);
+        let span = terrain.span();
+        let mut sites: Vec<Site> = Vec::new();
+        for _ in 0..self.count * ATTEMPTS_PER_SITE {
+            if sites.len() == self.count {
+                break;
+            }
+            // Un pozo por pueblo como mucho, y más casas que huertos
+            let kind = [SiteKind::House, SiteKind::House, SiteKind::Farm, SiteKind::Well][rng.range(0, 3) as usize];
+            let quarter_turns = rng.range(0, 3) as u32;
+            if kind == SiteKind::Well && sites.iter().any(|site| site.kind == SiteKind::Well) {
+                continue;
+            }
+            let (mut width, mut depth) = kind.footprint();
+            if quarter_turns % 2 == 1 {
+                std::mem::swap(&mut width, &mut depth);
+            }
+            // El alero de las casas sobresale un bloque, que también tiene que caber
+            if width + 2 > terrain.size || depth + 2 > terrain.size {
+                continue;
+            }
+            let x = rng.range(span.start + 1, span.end - width - 1);
+            let z = rng.range(span.start + 1, span.end - depth - 1);
+            let candidate = Site { kind, at: [x, 0, z], width, depth, quarter_turns };
+            let crowded = sites.iter().any(|site| {
+                (0..width).any(|dx| (0..depth).any(|dz| site.covers(x + dx, z + dz, self.spacing + 2)))
+            });
+            if crowded {
+                continue;
+            }
+            let heights: Vec<i32> = (x..x + width).flat_map(|x| (z..z + depth).map(move |z| (x, z))).map(|(x, z)| terrain.surface(x, z)).collect();
+            let (lowest, highest) = (*heights.iter().min().unwrap(), *heights.iter().max().unwrap());
+            if highest - lowest > 1 {
+                continue;
+            }
+            sites.push(Site { at: [x, highest + 1, z], ..candidate });
+        }
+        sites
+    }
+
+    // Coloca el pueblo sobre los bloques del terreno: rellena con cimientos de piedra lo que
+    // falte hasta el suelo de cada sitio, pone la estructura encima y traza caminos en L entre
+    // sitios consecutivos cambiando la hierba de la superficie. Devuelve las columnas que tienen
+    // que quedar libres de árboles.
+    pub fn build(&self, terrain: &Terrain, blocks: &mut BTreeMap<[i32; 3], PlacedBlock>) -> HashSet<[i32; 2]> {
+        let sites = self.sites(terrain);
+        let mut reserved = HashSet::new();
+        for site in &sites {
+            for x in site.at[0]..site.at[0] + site.width {
+                for z in site.at[2]..site.at[2] + site.depth {
+                    for y in terrain.surface(x, z) + 1..site.at[1] {
+                        blocks.insert([x, y, z], PlacedBlock::full([x, y, z], FOUNDATION_MATERIAL));
+                    }
+                }
+            }
+            let (width, depth) = site.kind.footprint();
+            let mut structure = site.kind.blocks(site.at);
+            structures::rotate(&mut structure, site.at, width, depth, site.quarter_turns);
+            for block in structure {
+                blocks.insert(block.pos, block);
+            }
+            for x in site.at[0] - TREE_CLEARANCE..site.at[0] + site.width + TREE_CLEARANCE {
+                for z in site.at[2] - TREE_CLEARANCE..site.at[2] + site.depth + TREE_CLEARANCE {
+                    reserved.insert([x, z]);
+                }
+            }
+        }
+        for pair in sites.windows(2) {
+            let ([x0, z0], [x1, z1]) = (pair[0].center(), pair[1].center());
+            let along_x = (x0.min(x1)..=x0.max(x1)).map(|x| (x, z0));
+            let along_z = (z0.min(z1)..=z0.max(z1)).map(|z| (x1, z));
+            for (x, z) in along_x.chain(along_z) {
+                if sites.iter().any(|site| site.covers(x, z, 0)) {
+                    continue;
+                }
+                let top = terrain.surface(x, z);
+                blocks.insert([x, top, z], PlacedBlock::full([x, top, z], PATH_MATERIAL));
+                reserved.insert([x, z]);
+            }
+        }
+        reserved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn village_terrain() -> Terrain {
+        Terrain { seed: 9, size: 48, amplitude: 2, scale: 20.0, village: Some(Village { seed: 4, count: 6, spacing: 2 }), ..Terrain::default() }
+    }
+
+    #[test]
+    fn sites_are_flat_apart_and_repeatable() {
+        let terrain = village_terrain();
+        let village = terrain.village.clone().unwrap();
+        let sites = village.sites(&terrain);
+        assert!(sites.len() >= 2, "only {} sites", sites.len());
+        assert_eq!(sites, village.sites(&terrain));
+        let terrain = &terrain;
+        for (i, site) in sites.iter().enumerate() {
+            let heights: Vec<i32> = (site.at[0]..site.at[0] + site.width).flat_map(|x| (site.at[2]..site.at[2] + site.depth).map(move |z| terrain.surface(x, z))).collect();
+            assert!(heights.iter().max().unwrap() - heights.iter().min().unwrap() <= 1);
+            assert_eq!(site.at[1], heights.iter().max().unwrap() + 1);
+            for other in &sites[i + 1..] {
+                let touching = (other.at[0]..other.at[0] + other.width).any(|x| (other.at[2]..other.at[2] + other.depth).any(|z| site.covers(x, z, village.spacing)));
+                assert!(!touching, "{:?} and {:?} overlap", site, other);
+            }
+        }
+        assert!(Village { spacing: -1, ..village }.validate().is_err());
+    }
+
+    #[test]
+    fn foundations_reach_the_ground_and_paths_join_the_sites() {
+        let terrain = village_terrain();
+        let sites = terrain.village.as_ref().unwrap().sites(&terrain);
+        let blocks = terrain.blocks();
+        let material_at = |pos: [i32; 3]| blocks.iter().find(|block| block.pos == pos).map(|block| block.material.as_str());
+        for site in &sites {
+            for x in site.at[0]..site.at[0] + site.width {
+                for z in site.at[2]..site.at[2] + site.depth {
+                    for y in 0..site.at[1] {
+                        assert!(material_at([x, y, z]).is_some(), "gap under {:?} at {:?}", site.kind, [x, y, z]);
+                    }
+                }
+            }
+        }
+        assert!(blocks.iter().any(|block| block.material == PATH_MATERIAL));
+        assert!(blocks.iter().any(|block| block.material == HOUSE_MATERIAL || block.material == structures::FENCE_MATERIAL));
+    }
+}
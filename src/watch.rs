@@ -0,0 +1,32 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Vigila la fecha de modificación del archivo de escena para recargarlo mientras la ventana está
+// abierta. Un archivo que no se puede leer (a medio guardar, o borrado un momento por el editor)
+// cuenta como sin cambios hasta que vuelva a aparecer.
+pub struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl FileWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified_time(&path);
+        FileWatcher { path, modified }
+    }
+
+    // true una vez por cada cambio de la fecha desde la última llamada
+    pub fn changed(&mut self) -> bool {
+        match modified_time(&self.path) {
+            Some(modified) if Some(modified) != self.modified => {
+                self.modified = Some(modified);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -0,0 +1,868 @@
+use raytracer_minecraft::math::{consts::PI, to_f32, Float, Vec3};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use raytracer_minecraft::assets::resolve_asset;
+use raytracer_minecraft::framebuffer::Framebuffer;
+use raytracer_minecraft::color::Color;
+use raytracer_minecraft::cube::Cube;
+use raytracer_minecraft::edit::{draw_region, next_material, pick_block, Clipboard, Edit, EditHistory, FallAnimation, Region};
+use raytracer_minecraft::camera::Camera;
+use raytracer_minecraft::light::Light;
+use raytracer_minecraft::presets::CameraPresets;
+use raytracer_minecraft::probes::ReflectionProbes;
+use raytracer_minecraft::material::Material;
+use raytracer_minecraft::minimap::{Corner, Minimap};
+use raytracer_minecraft::day_night::DayNightCycle;
+use raytracer_minecraft::error::RayTracerError;
+use raytracer_minecraft::metadata::RenderMetadata;
+use raytracer_minecraft::render::{pick, render, render_with_progress};
+use raytracer_minecraft::scaling::{blit, letterbox, scaled_size, upscale, DynamicResolution};
+use raytracer_minecraft::scene::{self, generate_lights_from_emissive_objects, Scene};
+use raytracer_minecraft::settings::{AlphaMode, Quality, RenderMode, RenderSettings, StereoMode};
+use raytracer_minecraft::sky::{Ambient, Sky};
+use raytracer_minecraft::streaming::ChunkStreamer;
+use raytracer_minecraft::structures::PlacedBlock;
+use crate::config::Controls;
+use crate::keys::KeyBindings;
+use crate::watch::FileWatcher;
+use crate::Failure;
+
+fn window_failure(error: minifb::Error) -> Failure {
+    Failure::failed(RayTracerError::Window(error.to_string()))
+}
+
+// Guarda el frame en screenshots/ con el instante en el nombre, para no pisar las anteriores
+fn save_screenshot(framebuffer: &Framebuffer, metadata: &RenderMetadata) -> Result<String, RayTracerError> {
+    let dir = std::path::Path::new("screenshots");
+    std::fs::create_dir_all(dir).map_err(|e| RayTracerError::io(dir, e))?;
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis());
+    let path = dir.join(format!("screenshot-{}.png", millis)).to_string_lossy().into_owned();
+    framebuffer.save_with_metadata(&path, metadata)?;
+    Ok(path)
+}
+
+// Material de los bloques que pone Insert hasta que se elige otro con D
+const BLOCK_MATERIAL: &str = "stone";
+// Contorno de la región seleccionada
+const SELECTION_COLOR: Color = Color::new(255, 220, 0);
+
+// Píxeles entre el recuadro de la otra cámara y los bordes de la ventana
+const INSET_MARGIN: usize = 8;
+
+// El último recuadro renderizado: su esquina en la ventana y sus píxeles, bandas incluidas
+struct Inset {
+    left: usize,
+    top: usize,
+    width: usize,
+    pixels: Vec<u32>,
+}
+
+// Renderiza la vista de `camera` para el recuadro: en borrador, a media resolución y con la proporción
+// inset_aspect centrada en un marco de inset_size de la ventana, con bandas negras en lo que sobra
+fn render_inset(scene: &Scene, camera: Camera, settings: &RenderSettings, controls: &Controls, width: usize, height: usize) -> Inset {
+    let (box_width, box_height) = scaled_size(width, height, controls.inset_size);
+    let (x, y, image_width, image_height) = letterbox(box_width, box_height, controls.inset_aspect);
+    let (render_width, render_height) = scaled_size(image_width, image_height, 0.5);
+    let inset_settings = RenderSettings {
+        width: render_width,
+        height: render_height,
+        focus: None,
+        checkerboard: false,
+        stereo: StereoMode::Off,
+        ..Quality::Draft.settings(settings)
+    };
+    let mut view = scene.clone();
+    view.camera = camera;
+    let mut framebuffer = Framebuffer::new(render_width, render_height);
+    render(&mut framebuffer, &view, &inset_settings);
+    let mut pixels = vec![0; box_width * box_height];
+    let image = upscale(&framebuffer.buffer, render_width, render_height, image_width, image_height);
+    blit(&mut pixels, box_width, &image, image_width, x, y);
+
+    let (margin_x, margin_y) = (INSET_MARGIN.min(width - box_width), INSET_MARGIN.min(height - box_height));
+    let left = match controls.inset_corner {
+        Corner::TopLeft | Corner::BottomLeft => margin_x,
+        Corner::TopRight | Corner::BottomRight => width - box_width - margin_x,
+    };
+    let top = match controls.inset_corner {
+        Corner::TopLeft | Corner::TopRight => margin_y,
+        Corner::BottomLeft | Corner::BottomRight => height - box_height - margin_y,
+    };
+    Inset { left, top, width: box_width, pixels }
+}
+
+// El modo interactivo: abre la ventana con la escena y la renderiza en cada frame hasta que se cierra
+pub fn run(scene_name: &str, mut settings: RenderSettings, controls: Controls, sky: Sky, strict: bool) -> Result<(), Failure> {
+    let window_width = settings.width;
+    let window_height = settings.height;
+    let framebuffer_width = settings.width;
+    let framebuffer_height = settings.height;
+    let frame_delay = Duration::from_millis(16);
+    // Con frames lentos la ventana enseña los tiles que ya están cada este tiempo
+    let partial_frame_interval = Duration::from_millis(100);
+    // La ventana no tiene dónde enseñar la transparencia; el alfa solo vale para los renders guardados
+    settings.alpha = AlphaMode::Off;
+    let keys = match &controls.keybindings {
+        Some(path) => KeyBindings::load(path).map_err(Failure::Startup)?,
+        None => KeyBindings::default(),
+    };
+
+    let mut framebuffer = Framebuffer::new(framebuffer_width, framebuffer_height);
+    let mut window = Window::new(
+        "Rust Graphics - Raytracer Example",
+        window_width,
+        window_height,
+        WindowOptions::default(),
+    ).map_err(window_failure)?;
+
+
+    // move the window around
+    window.set_position(500, 500);
+    window.update();
+
+
+    let light = Light::new(
+         Vec3::new(4.0, 1.0, 5.0),
+        Color::new(255, 255, 255), // Luz blanca
+        2.0,                       // Intensidad
+        10.0                       // Radio de influencia (ajusta este valor según sea necesario)
+    );
+
+
+    let rubber = Material::new(
+        Color::new(80, 0, 0),
+        1.0,
+        [0.9, 0.1, 0.0, 0.0],
+        0.0,
+    );
+
+
+    let ivory = Material::new(
+        Color::new(100, 100, 80),
+        50.0,
+        [0.6, 0.3, 0.6, 0.0],
+        0.0,
+    );
+
+
+    let glass = Material::new(
+        Color::new(255, 255, 255),
+        1425.0,
+        [0.0, 10.0, 0.5, 0.5],
+        0.3,
+    );
+
+
+    let load_start = Instant::now();
+    // Con stream_radius un mundo binario se carga por chunks alrededor de la cámara
+    let (mut loaded, mut streamer) = if controls.stream_radius > 0.0 && scene_name.ends_with(".world") {
+        let (loaded, streamer) = scene::load_streamed(scene_name, controls.stream_radius).map_err(Failure::startup)?;
+        (loaded, Some(streamer))
+    } else {
+        (scene::load(scene_name).map_err(Failure::startup)?, None)
+    };
+    if strict && !loaded.warnings.is_empty() {
+        return Err(Failure::Startup(format!("Scene '{}' has {} warning(s) and --strict is set", scene_name, loaded.warnings.len())));
+    }
+    println!("Loaded scene '{}' and its textures in {:.2}s", scene_name, load_start.elapsed().as_secs_f32());
+    let mut camera_presets = CameraPresets::load(CameraPresets::path_for_scene(scene_name))
+        .map_err(Failure::Startup)?;
+
+
+    // Genera luces adicionales a partir de materiales emisivos
+    let mut lights = vec![
+        Light::new(
+            Vec3::new(4.0, 1.0, 5.0),
+            Color::new(255, 255, 255),
+            1.0,  // Reducimos la intensidad de la luz principal
+            10.0
+        )
+    ];
+
+    // Añade las luces del archivo de escena y las de los objetos emisivos. Las del archivo van
+    // justo después del sol, así que Ctrl+S las recupera de lights[1..] con lo que se haya editado
+    lights.extend(loaded.lights.iter().cloned());
+    lights.extend(generate_lights_from_emissive_objects(&loaded.objects));
+    // Ctrl+S guarda en el archivo de la escena; la demo no tiene archivo y se guarda en scene.toml,
+    // y un mundo binario se guarda como escena al lado para no pisarlo
+    let save_path = match scene_name {
+        "demo" => std::path::PathBuf::from("scene.toml"),
+        name if name.ends_with(".world") => std::path::Path::new(name).with_extension("toml"),
+        name => std::path::PathBuf::from(name),
+    };
+    // El archivo de escena se recarga en cuanto cambia; si la nueva versión no carga se sigue
+    // mostrando la anterior y el error se ve en el título hasta que se arregle
+    let mut scene_watcher = match scene_name {
+        _ if streamer.is_some() => None,
+        "demo" => None,
+        path => resolve_asset(path, None).ok().map(FileWatcher::new),
+    };
+    let mut reload_error: Option<String> = None;
+    // Las ediciones de bloques de Insert y Supr, que Ctrl+Z deshace y Ctrl+Y rehace
+    let mut history = EditHistory::new(controls.undo_depth);
+    // La arena y la grava que una edición dejó sin apoyo ya están en su sitio en la rejilla, pero se
+    // ven caer hasta él: mientras tanto sus cajas se rehacen cada frame
+    let mut falling: Option<FallAnimation> = None;
+    // Modo selección (A): dos clics marcan las esquinas de una región que Insert llena, Supr vacía y
+    // Ctrl+C copia; Ctrl+V pega lo copiado en cualquier modo
+    let mut selecting = false;
+    let mut selection_start: Option<[i32; 3]> = None;
+    let mut selection: Option<Region> = None;
+    let mut clipboard: Option<Clipboard> = None;
+    // R y Shift+R recorren la paleta con el bloque bajo el ratón; sin palette, todo el registro por nombre
+    let palette = match (&loaded.blocks, controls.palette.is_empty()) {
+        (Some(blocks), true) => blocks.material_names(),
+        _ => controls.palette.clone(),
+    };
+    if let Some(unknown) = loaded.blocks.as_ref().and_then(|blocks| palette.iter().find(|name| !blocks.has_material(name))) {
+        return Err(Failure::Startup(format!("Unknown material '{}' in palette", unknown)));
+    }
+    let mut block_material = BLOCK_MATERIAL.to_string();
+
+
+    // Inicializa la cámara, o usa la de la escena si tiene una
+    let mut camera = loaded.camera.clone().unwrap_or_else(Camera::default_view);
+    // Los controles mueven `camera`; lo que se dibuja es la cámara de `scene`, que la sigue suavemente.
+    // Las luces y el ambiente de la escena se rehacen en cada frame con el ciclo de día y noche.
+    let mut scene = Scene::new(loaded.objects.iter().cloned(), Vec::new(), Arc::new(sky), camera.clone(), Ambient::flat(Color::new(0, 0, 0)));
+    scene.fog = streamer.as_ref().map(ChunkStreamer::fog);
+    // Con target_frame_time la escala del render se adapta mientras la vista se mueve; quieta, va al 100%
+    let mut resolution = (controls.target_frame_time > 0.0).then(|| {
+        DynamicResolution::new(Duration::from_secs_f32(controls.target_frame_time / 1000.0), controls.min_render_scale, controls.max_render_scale)
+    });
+    let mut last_view = scene.camera.clone();
+    let mut render_scale = 1.0;
+    let mut smoothing = controls.camera_smoothing > 0.0;
+    // M muestra u oculta el mapa de la zona vista desde arriba, encima de la imagen ya ampliada
+    let mut minimap = Minimap::new(controls.minimap_size, controls.minimap_corner, controls.minimap_zoom);
+    let mut show_minimap = false;
+    // Con inset_camera, un recuadro muestra la vista de ese preset; Y la intercambia con la principal
+    let mut swap_views = false;
+    let mut inset: Option<Inset> = None;
+    let mut last_frame = std::time::Instant::now();
+    const SMOOTHING_EPSILON: Float = 1e-4;
+    let rotation_speed = PI / 50.0;
+    let zoom_speed = 0.5;
+    let pan_speed = 0.1;
+    let mut last_mouse_position: Option<(f32, f32)> = None;
+    // Posición donde se pulsó el botón izquierdo o derecho y si ya se está arrastrando
+    let mut press_position: Option<(f32, f32)> = None;
+    let mut dragging = false;
+    // Centro del último bloque elegido con clic o F, hacia el que se refina el render
+    let mut focus_block: Option<Vec3> = None;
+    let orbit_per_pixel = controls.mouse_sensitivity.to_radians();
+    const DRAG_THRESHOLD: f32 = 4.0;
+    const MAX_ZOOM: Float = 1.0;
+    const MIN_ZOOM: Float = 10.0;
+
+
+    // Modo de edición de luces (G): las flechas y RePág/AvPág mueven la luz elegida con Tab por
+    // los ejes del mundo y +/- cambian su intensidad. La luz 0 es el sol, que sigue al ciclo día/noche,
+    // así que solo se editan las demás.
+    let mut light_edit = false;
+    let mut selected_light = 1;
+    const LIGHT_STEP: Float = 0.1;
+    const INTENSITY_STEP: f32 = 1.1;
+
+    let mut day_night_cycle = DayNightCycle::new();
+    let start_time = std::time::Instant::now();
+
+
+    while window.is_open() {
+        // Escuchar entradas
+        if window.is_key_down(Key::Escape) {
+            break;
+        }
+
+
+        let ctrl_down = window.is_key_down(Key::LeftCtrl) || window.is_key_down(Key::RightCtrl);
+        let shift_down = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+
+        // Ctrl+S guarda la escena con las luces editadas y la cámara actual
+        if ctrl_down && window.is_key_pressed(Key::S, KeyRepeat::No) && streamer.is_some() {
+            eprintln!("A streamed world can't be saved as a scene; open it with stream_radius = 0 to save it");
+        } else if ctrl_down && window.is_key_pressed(Key::S, KeyRepeat::No) {
+            let scene_lights = loaded.lights.len();
+            loaded.lights.clone_from_slice(&lights[1..=scene_lights]);
+            loaded.camera = Some(camera.clone());
+            match loaded.save(&save_path) {
+                Ok(()) => println!("Saved scene to {}", save_path.display()),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        if scene_watcher.as_mut().is_some_and(FileWatcher::changed) {
+            match scene::load(scene_name) {
+                Ok(reloaded) if strict && !reloaded.warnings.is_empty() => {
+                    reload_error = Some(format!("{} warning(s) and --strict is set", reloaded.warnings.len()));
+                }
+                Ok(reloaded) => {
+                    // La cámara solo salta si cambió la sección [camera] del archivo
+                    let camera_changed = match (&loaded.camera, &reloaded.camera) {
+                        (Some(old), Some(new)) => !old.is_near(new, SMOOTHING_EPSILON),
+                        (old, new) => old.is_some() != new.is_some(),
+                    };
+                    if let (true, Some(new)) = (camera_changed, &reloaded.camera) {
+                        camera = new.clone();
+                    }
+                    loaded = reloaded;
+                    history = EditHistory::new(controls.undo_depth);
+                    falling = None;
+                    lights.truncate(1);
+                    lights.extend(loaded.lights.iter().cloned());
+                    lights.extend(generate_lights_from_emissive_objects(&loaded.objects));
+                    if lights.len() <= 1 {
+                        light_edit = false;
+                    }
+                    selected_light = selected_light.min(lights.len() - 1).max(1);
+                    scene.set_objects(loaded.objects.iter().cloned());
+                    minimap.invalidate();
+                    settings.probes = None;
+                    reload_error = None;
+                    println!("Reloaded scene '{}'", scene_name);
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    reload_error = Some(e.to_string());
+                }
+            }
+        }
+
+        // Si presionas la tecla W, la cámara se acerca
+        if window.is_key_down(keys.zoom_in) {
+            if camera.eye.z - zoom_speed > MAX_ZOOM {
+                camera.eye.z -= zoom_speed;
+            } else {
+                camera.eye.z = MAX_ZOOM;
+            }
+        }
+   
+        // Si presionas la tecla S, la cámara se aleja (Ctrl+S guarda)
+        if window.is_key_down(keys.zoom_out) && !ctrl_down {
+            if camera.eye.z + zoom_speed < MIN_ZOOM {
+                camera.eye.z += zoom_speed;
+            } else {
+                camera.eye.z = MIN_ZOOM;
+            }
+        }
+        // La rueda acerca y aleja en pasos proporcionales a la distancia, con los mismos límites que W y S
+        if let Some((_, scroll_y)) = window.get_scroll_wheel() {
+            camera.dolly(controls.zoom_step.powf(-scroll_y as Float), MAX_ZOOM, MIN_ZOOM);
+        }
+
+        // H muestra u oculta los marcadores de las luces
+        if window.is_key_pressed(Key::H, KeyRepeat::No) {
+            settings.light_gizmos = !settings.light_gizmos;
+        }
+        if window.is_key_pressed(Key::G, KeyRepeat::No) {
+            light_edit = !light_edit && lights.len() > 1;
+            if lights.len() <= 1 {
+                println!("No lights to edit besides the sun; add [[light]] entries to the scene");
+            }
+        }
+        if light_edit {
+            if window.is_key_pressed(Key::Tab, KeyRepeat::No) {
+                selected_light = selected_light % (lights.len() - 1) + 1;
+                println!("Editing light {}", selected_light);
+            }
+            let pressed = |key: Key| window.is_key_pressed(key, KeyRepeat::Yes);
+            let light = &mut lights[selected_light];
+            let mut changed = true;
+            if pressed(Key::Left) {
+                light.position.x -= LIGHT_STEP;
+            } else if pressed(Key::Right) {
+                light.position.x += LIGHT_STEP;
+            } else if pressed(Key::Up) {
+                light.position.z -= LIGHT_STEP;
+            } else if pressed(Key::Down) {
+                light.position.z += LIGHT_STEP;
+            } else if pressed(Key::PageUp) {
+                light.position.y += LIGHT_STEP;
+            } else if pressed(Key::PageDown) {
+                light.position.y -= LIGHT_STEP;
+            } else if pressed(Key::Equal) || pressed(Key::NumPadPlus) {
+                light.intensity *= INTENSITY_STEP;
+            } else if pressed(Key::Minus) || pressed(Key::NumPadMinus) {
+                light.intensity /= INTENSITY_STEP;
+            } else {
+                changed = false;
+            }
+            if changed {
+                println!(
+                    "Light {}: position = [{:.2}, {:.2}, {:.2}], intensity = {:.2}",
+                    selected_light, light.position.x, light.position.y, light.position.z, light.intensity,
+                );
+            }
+            // C imprime todas las luces editables como entradas [[light]] listas para el archivo de escena
+            if window.is_key_pressed(Key::C, KeyRepeat::No) && !ctrl_down {
+                for light in &lights[1..] {
+                    println!("{}", light.to_toml());
+                }
+            }
+        }
+
+        // Controles de órbita de la cámara; en el modo de edición las flechas mueven la luz
+        if !light_edit {
+            if window.is_key_down(keys.orbit_left) {
+                camera.orbit(rotation_speed, 0.0);
+            }
+            if window.is_key_down(keys.orbit_right) {
+                camera.orbit(-rotation_speed, 0.0);
+            }
+            if window.is_key_down(keys.orbit_up) {
+                camera.orbit(0.0, -rotation_speed);
+            }
+            if window.is_key_down(keys.orbit_down) {
+                camera.orbit(0.0, rotation_speed);
+            }
+        }
+
+
+        // I/J/K/L desplazan la cámara y el centro de la órbita juntos
+        if window.is_key_down(keys.pan_up) {
+            camera.pan(0.0, pan_speed);
+        }
+        if window.is_key_down(keys.pan_down) {
+            camera.pan(0.0, -pan_speed);
+        }
+        if window.is_key_down(keys.pan_left) {
+            camera.pan(-pan_speed, 0.0);
+        }
+        if window.is_key_down(keys.pan_right) {
+            camera.pan(pan_speed, 0.0);
+        }
+
+        // Arrastrar con el botón central hace lo mismo; la escena sigue al ratón
+        let mouse_position = window.get_mouse_pos(MouseMode::Discard);
+        if window.get_mouse_down(MouseButton::Middle) {
+            if let (Some((x, y)), Some((last_x, last_y))) = (mouse_position, last_mouse_position) {
+                let distance = (camera.center - camera.eye).magnitude();
+                let units_per_pixel = 2.0 * distance * (camera.fov * 0.5).tan() / framebuffer_height as Float;
+                camera.pan(-(x - last_x) as Float * units_per_pixel, (y - last_y) as Float * units_per_pixel);
+            }
+        }
+
+        // Arrastrar con el botón izquierdo o derecho orbita; un clic sin arrastrar encuadra el bloque
+        let orbit_button_down = window.get_mouse_down(MouseButton::Left) || window.get_mouse_down(MouseButton::Right);
+        let mut clicked_at = None;
+        if orbit_button_down {
+            if let Some((x, y)) = mouse_position {
+                let (press_x, press_y) = *press_position.get_or_insert((x, y));
+                if !dragging && ((x - press_x).powi(2) + (y - press_y).powi(2)).sqrt() > DRAG_THRESHOLD {
+                    dragging = true;
+                }
+                // Fuera de la ventana no hay posición anterior, así que al volver no se salta
+                if let (true, Some((last_x, last_y))) = (dragging, last_mouse_position) {
+                    camera.orbit((x - last_x) as Float * orbit_per_pixel, -(y - last_y) as Float * orbit_per_pixel);
+                }
+            }
+        } else {
+            if !dragging {
+                clicked_at = press_position;
+            }
+            press_position = None;
+            dragging = false;
+        }
+        last_mouse_position = mouse_position;
+
+        if window.is_key_pressed(Key::A, KeyRepeat::No) {
+            selecting = !selecting;
+            selection_start = None;
+        }
+        let block_at = |(x, y): (f32, f32)| {
+            pick_block(scene.objects(), &scene.camera, x as Float, y as Float, framebuffer_width as Float, framebuffer_height as Float)
+        };
+        // En modo selección un clic pone una esquina de la región en vez de encuadrar el bloque; el
+        // tercero empieza otra
+        if selecting {
+            if let Some((target, _)) = clicked_at.take().and_then(block_at) {
+                match selection_start.take() {
+                    Some(start) => selection = Some(Region::new(start, target)),
+                    None => {
+                        selection_start = Some(target);
+                        selection = Some(Region::new(target, target));
+                    }
+                }
+            }
+        }
+
+        // F centra la órbita en el bloque bajo el ratón y lo encuadra
+        if window.is_key_pressed(Key::F, KeyRepeat::No) {
+            clicked_at = mouse_position;
+        }
+        if let Some((x, y)) = clicked_at {
+            let picked = pick(scene.objects(), &scene.camera, x as Float, y as Float, framebuffer_width as Float, framebuffer_height as Float);
+            if let Some((min, max)) = picked.and_then(|object| object.bounds()) {
+                camera.frame(&min, &max);
+                focus_block = Some((min + max) * 0.5);
+            }
+        }
+
+
+        // Insert pone un bloque contra la cara bajo el ratón y Supr quita el bloque bajo el ratón; en
+        // modo selección llenan y vacían la región. Cada cambio, sea de un bloque o de una región
+        // entera, rehace una vez las cajas de los bloques, las luces de los emisivos y lo que sale de ellas.
+        let mut blocks_changed = Ok(false);
+        let place = window.is_key_pressed(Key::Insert, KeyRepeat::No);
+        let delete = window.is_key_pressed(Key::Delete, KeyRepeat::No);
+        if selecting && (place || delete) {
+            match selection {
+                Some(region) => {
+                    let material = place.then(|| block_material.clone());
+                    blocks_changed = loaded.edit_blocks(&mut history, &Edit::Fill { region, material });
+                }
+                None => println!("Click two blocks to select a region first"),
+            }
+        } else if place || delete {
+            if let Some((target, neighbor)) = mouse_position.and_then(block_at) {
+                let edit = if place { Edit::Place(PlacedBlock::full(neighbor, &block_material)) } else { Edit::Remove(target) };
+                blocks_changed = loaded.edit_blocks(&mut history, &edit);
+            }
+        }
+        // Ctrl+C copia la región seleccionada, Ctrl+V la pega con su esquina menor contra la cara bajo el ratón y U
+        // gira lo copiado un cuarto de vuelta
+        if ctrl_down && window.is_key_pressed(Key::C, KeyRepeat::No) {
+            if let (Some(region), Some(blocks)) = (selection.filter(|_| selecting), &loaded.blocks) {
+                clipboard = blocks.grid.copy(&region);
+                if let Some(copied) = &clipboard {
+                    let [x, y, z] = copied.size();
+                    println!("Copied {} block(s) from a {}x{}x{} region", copied.blocks().len(), x, y, z);
+                }
+            }
+        }
+        if ctrl_down && window.is_key_pressed(Key::V, KeyRepeat::No) {
+            if let (Some(copied), Some((_, neighbor))) = (&clipboard, mouse_position.and_then(block_at)) {
+                blocks_changed = loaded.edit_blocks(&mut history, &Edit::Paste { at: neighbor, clipboard: copied.clone() });
+            }
+        }
+        if window.is_key_pressed(Key::U, KeyRepeat::No) && clipboard.is_some() {
+            clipboard = clipboard.as_ref().map(Clipboard::rotated);
+            println!("Turned the copied blocks a quarter turn clockwise");
+        }
+        // R y Shift+R cambian el bloque bajo el ratón al material siguiente o anterior de la paleta, y
+        // D toma el material de lo que hay bajo el ratón para los bloques que se pongan
+        if window.is_key_pressed(Key::R, KeyRepeat::Yes) {
+            let retextured = mouse_position.and_then(block_at).and_then(|(target, _)| {
+                let current = &loaded.blocks.as_ref()?.grid.get(target)?.material;
+                Some(Edit::SetMaterial(target, next_material(&palette, current, !shift_down)?.to_string()))
+            });
+            if let Some(edit) = retextured {
+                blocks_changed = loaded.edit_blocks(&mut history, &edit);
+            }
+        }
+        if window.is_key_pressed(Key::D, KeyRepeat::No) {
+            let picked = mouse_position.and_then(|(x, y)| {
+                pick(scene.objects(), &scene.camera, x as Float, y as Float, framebuffer_width as Float, framebuffer_height as Float)
+            });
+            match picked.map(|object| object.material().name.clone()) {
+                Some(name) if loaded.blocks.as_ref().is_some_and(|blocks| blocks.has_material(&name)) => {
+                    println!("Placing {}", name);
+                    block_material = name;
+                }
+                Some(_) => println!("That object's material can't be placed as a block"),
+                None => {}
+            }
+        }
+        // Deshacer y rehacer no vuelven a animar las caídas: los bloques saltan a donde estaban o a donde cayeron
+        let mut replayed = false;
+        if ctrl_down && window.is_key_pressed(Key::Z, KeyRepeat::Yes) && !shift_down {
+            blocks_changed = loaded.undo_blocks(&mut history);
+            replayed = true;
+        } else if ctrl_down && (window.is_key_pressed(Key::Y, KeyRepeat::Yes) || window.is_key_pressed(Key::Z, KeyRepeat::Yes)) {
+            blocks_changed = loaded.redo_blocks(&mut history);
+            replayed = true;
+        }
+        match blocks_changed {
+            Ok(true) => {
+                falling = if replayed { None } else { history.last_changes().and_then(FallAnimation::new) };
+                lights.truncate(1 + loaded.lights.len());
+                lights.extend(generate_lights_from_emissive_objects(&loaded.objects));
+                if lights.len() <= 1 {
+                    light_edit = false;
+                }
+                selected_light = selected_light.min(lights.len() - 1).max(1);
+                scene.set_objects(loaded.objects.iter().cloned());
+                minimap.invalidate();
+                settings.probes = None;
+            }
+            Ok(false) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+
+        // Z y X inclinan la cámara alrededor de la dirección de visión; Inicio la vuelve a nivelar
+        if window.is_key_down(keys.roll_left) && !ctrl_down {
+            camera.roll += rotation_speed;
+        }
+        if window.is_key_down(keys.roll_right) {
+            camera.roll -= rotation_speed;
+        }
+        if window.is_key_pressed(Key::Home, KeyRepeat::No) {
+            camera.roll = 0.0;
+        }
+
+
+        // Ctrl+1..9 guarda la cámara y 1..9 la recupera; con el suavizado activo la vista viaja hasta ella.
+        // Shift+1..4 enciende o apaga las cuatro primeras luces (la 1 es el sol)
+        let slot_keys = [Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9];
+        for (index, key) in slot_keys.iter().enumerate() {
+            if !window.is_key_pressed(*key, KeyRepeat::No) {
+                continue;
+            }
+            let slot = index + 1;
+            if ctrl_down {
+                match camera_presets.store(slot, &camera) {
+                    Ok(()) => println!("Saved camera {}", slot),
+                    Err(e) => eprintln!("{}", e),
+                }
+            } else if shift_down {
+                if let Some(light) = lights.get_mut(index).filter(|_| slot <= 4) {
+                    light.enabled = !light.enabled;
+                    println!("Light {} {}", slot, if light.enabled { "on" } else { "off" });
+                }
+            } else if let Some(preset) = camera_presets.recall(slot) {
+                camera = preset;
+            }
+        }
+
+        // N activa o desactiva el suavizado de la cámara
+        if window.is_key_pressed(Key::N, KeyRepeat::No) {
+            smoothing = !smoothing && controls.camera_smoothing > 0.0;
+        }
+
+        // Y intercambia la vista principal con la del recuadro
+        if window.is_key_pressed(Key::Y, KeyRepeat::No) && !ctrl_down && controls.inset_camera > 0 {
+            swap_views = !swap_views;
+        }
+
+        // M muestra u oculta el minimapa
+        if window.is_key_pressed(Key::M, KeyRepeat::No) {
+            show_minimap = !show_minimap;
+        }
+
+        // P cambia entre los presets de calidad
+        if window.is_key_pressed(Key::P, KeyRepeat::No) {
+            settings = settings.quality.next().settings(&settings);
+        }
+
+        // T activa o desactiva el damero: cada frame traza la mitad de los píxeles
+        if window.is_key_pressed(Key::T, KeyRepeat::No) {
+            settings.checkerboard = !settings.checkerboard;
+        }
+
+        // O muestra u oculta los bordes de los bloques
+        if window.is_key_pressed(Key::O, KeyRepeat::No) {
+            settings.outlines = !settings.outlines;
+        }
+
+        // B pasa por los modos de render: normal, arcilla, toon, rápido y muestras
+        if window.is_key_pressed(Key::B, KeyRepeat::No) {
+            settings.render_mode = settings.render_mode.next();
+        }
+
+        // V cambia el modo estéreo; [ y ] acercan y separan los ojos
+        if window.is_key_pressed(Key::V, KeyRepeat::No) && !ctrl_down {
+            settings.stereo = settings.stereo.next();
+        }
+        if window.is_key_pressed(Key::LeftBracket, KeyRepeat::Yes) {
+            settings.eye_separation /= 1.25;
+        }
+        if window.is_key_pressed(Key::RightBracket, KeyRepeat::Yes) {
+            settings.eye_separation *= 1.25;
+        }
+        let stereo_title = match settings.stereo {
+            StereoMode::Off => String::new(),
+            stereo => format!(", {} {:.4}", stereo.name(), settings.eye_separation),
+        };
+        let mode_title = match settings.render_mode {
+            RenderMode::Shaded => String::new(),
+            RenderMode::Fast => ", fast lighting (approximate)".to_string(),
+            mode => format!(", {}", mode.name()),
+        };
+        let checkerboard_title = if settings.checkerboard { ", checkerboard" } else { "" };
+        let light_title = if light_edit { format!(", editing light {}", selected_light) } else { String::new() };
+        let placing_title = match &loaded.blocks {
+            Some(_) => format!(", placing {}", block_material),
+            None => String::new(),
+        };
+        let selection_title = match selection.filter(|_| selecting) {
+            Some(region) => {
+                let [x, y, z] = region.size();
+                format!(", selected {}x{}x{}", x, y, z)
+            }
+            None if selecting => ", selecting".to_string(),
+            None => String::new(),
+        };
+        let error_title = match &reload_error {
+            Some(e) => format!(" - scene not reloaded: {}", e.lines().next().unwrap_or_default()),
+            None => String::new(),
+        };
+        let scale_title = if resolution.is_some() { format!(", {:.0}%", render_scale * 100.0) } else { String::new() };
+        let stream_title = match &streamer {
+            Some(streamer) => format!(", {} chunks ({} loading)", streamer.loaded_chunks(), streamer.pending_chunks()),
+            None => String::new(),
+        };
+        window.set_title(&format!(
+            "Rust Graphics - Raytracer Example [{}{}{}{}{}{}{}{}{}]{}",
+            settings.quality.name(), scale_title, stream_title, mode_title, checkerboard_title, stereo_title, light_title, placing_title, selection_title, error_title
+        ));
+
+
+        if window.is_key_down(keys.sun_back) {
+            day_night_cycle.update(-0.005); // Avanzar hacia la noche
+        }
+        if window.is_key_down(keys.sun_forward) {
+            day_night_cycle.update(0.005);  // Avanzar hacia el día
+        }
+
+
+        scene.ambient = scene.sky.ambient(&day_night_cycle.get_current_color(), &loaded.ambient).with_night(day_night_cycle.night_amount());
+
+
+        // Actualizar la luz principal (sol)
+        lights[0] = Light { enabled: lights[0].enabled, ..day_night_cycle.sun_light() };
+
+
+        // La vista recorre cada frame la fracción 1 - e^(-dt/τ) de lo que le falta para llegar a la cámara
+        let frame_time = last_frame.elapsed().as_secs_f32() as Float;
+        last_frame = std::time::Instant::now();
+        if smoothing && !scene.camera.is_near(&camera, SMOOTHING_EPSILON) {
+            scene.camera.ease_toward(&camera, 1.0 - (-frame_time / controls.camera_smoothing).exp());
+        } else {
+            scene.camera = camera.clone();
+        }
+
+        // Los bloques que caen bajan un poco cada frame; cuando llegan todos quedan las cajas de la rejilla
+        if let Some(animation) = falling.as_mut() {
+            animation.advance(to_f32(frame_time));
+            let done = animation.is_done();
+            let objects = if done { Ok(loaded.objects.clone()) } else { loaded.falling_objects(animation) };
+            if done || objects.is_err() {
+                falling = None;
+            }
+            scene.set_objects(objects.unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                loaded.objects.clone()
+            }));
+        }
+
+        // Los chunks que entran en el radio llegan del hilo de carga; al cambiar se rehacen las
+        // cajas de la escena y las luces de sus bloques emisivos
+        if let Some(streamer) = streamer.as_mut() {
+            if streamer.update(&scene.camera.eye) {
+                let objects: Vec<Cube> = streamer.objects().cloned().collect();
+                lights.truncate(1 + loaded.lights.len());
+                lights.extend(generate_lights_from_emissive_objects(&objects));
+                if lights.len() <= 1 {
+                    light_edit = false;
+                }
+                selected_light = selected_light.min(lights.len() - 1).max(1);
+                scene.set_objects(objects);
+                minimap.invalidate();
+            }
+        }
+
+        // Dibuja los objetos
+        settings.time = start_time.elapsed().as_secs_f32();
+        settings.frame = settings.frame.wrapping_add(1);
+        // De noche la luna se suma a las luces solo para este frame, así que no se puede editar
+        scene.lights = lights.iter().cloned().chain(day_night_cycle.moon_light()).collect();
+        // Las sondas se renderizan con la luz del primer frame y se rehacen solo al recargar la escena
+        if settings.probes.is_none() {
+            let positions = loaded.probe_positions();
+            if !positions.is_empty() {
+                let start = std::time::Instant::now();
+                settings.probes = Some(Arc::new(ReflectionProbes::render(&positions, &scene, &settings)));
+                println!("Rendered {} reflection probe(s) in {:.2}s", positions.len(), start.elapsed().as_secs_f32());
+            }
+        }
+        // Mientras la vista se mueve se renderiza a la escala dinámica y se amplía al tamaño de la ventana
+        let moving = !scene.camera.is_near(&last_view, SMOOTHING_EPSILON);
+        last_view = scene.camera.clone();
+        render_scale = match &resolution {
+            Some(resolution) if moving => resolution.scale(),
+            _ => 1.0,
+        };
+        let (render_width, render_height) = scaled_size(framebuffer_width, framebuffer_height, render_scale);
+        if (framebuffer.width, framebuffer.height) != (render_width, render_height) {
+            framebuffer = Framebuffer::new(render_width, render_height);
+        }
+        // Intercambiadas, la vista principal es la del preset y el recuadro muestra la cámara que se
+        // mueve; los controles siguen moviendo esa cámara
+        let inset_preset = match controls.inset_camera {
+            0 => None,
+            slot => camera_presets.recall(slot),
+        };
+        let swapped_scene = inset_preset.as_ref().filter(|_| swap_views).map(|preset| {
+            let mut swapped = scene.clone();
+            swapped.camera = preset.clone();
+            swapped
+        });
+        let main_scene = swapped_scene.as_ref().unwrap_or(&scene);
+        // Con focus_radius las muestras extra van al último bloque elegido, o al centro si no se ve
+        settings.focus = focus_block
+            .and_then(|point| main_scene.camera.project(&point, render_width as Float, render_height as Float))
+            .map(|(x, y)| [to_f32(x), to_f32(y)]);
+        let render_start = Instant::now();
+        let mut last_partial = render_start;
+        // El render no se puede cortar desde aquí, así que un fallo de la ventana se guarda hasta que acabe
+        let mut partial_error = None;
+        render_with_progress(&mut framebuffer, main_scene, &settings, &mut |pixels, done, total| {
+            if done < total && last_partial.elapsed() >= partial_frame_interval && partial_error.is_none() {
+                last_partial = Instant::now();
+                let mut shown = upscale(pixels, render_width, render_height, framebuffer_width, framebuffer_height);
+                if let Some(inset) = &inset {
+                    blit(&mut shown, framebuffer_width, &inset.pixels, inset.width, inset.left, inset.top);
+                }
+                if let Some(region) = selection.filter(|_| selecting) {
+                    draw_region(&mut shown, framebuffer_width, framebuffer_height, &main_scene.camera, &region, SELECTION_COLOR);
+                }
+                if show_minimap {
+                    minimap.draw(scene.objects(), &scene.camera, &mut shown, framebuffer_width, framebuffer_height);
+                }
+                partial_error = window.update_with_buffer(&shown, framebuffer_width, framebuffer_height).err();
+            }
+        });
+        let render_time = render_start.elapsed();
+        if let Some(error) = partial_error {
+            return Err(window_failure(error));
+        }
+        if let (Some(resolution), true) = (&mut resolution, moving) {
+            resolution.update(render_time);
+        }
+        inset = inset_preset.map(|preset| {
+            let view = if swap_views { scene.camera.clone() } else { preset };
+            render_inset(&scene, view, &settings, &controls, framebuffer_width, framebuffer_height)
+        });
+
+        // F2 guarda el frame tal como se renderizó, con la escena, la cámara y los ajustes en el PNG
+        if window.is_key_pressed(Key::F2, KeyRepeat::No) {
+            let frame_settings = RenderSettings { width: render_width, height: render_height, ..settings.clone() };
+            match save_screenshot(&framebuffer, &RenderMetadata::new(scene_name, &main_scene.camera, &frame_settings, render_time)) {
+                Ok(path) => println!("Saved screenshot to {}", path),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+
+        // Actualiza la ventana con el contenido del framebuffer
+        let mut shown = upscale(&framebuffer.buffer, render_width, render_height, framebuffer_width, framebuffer_height);
+        if let Some(inset) = &inset {
+            blit(&mut shown, framebuffer_width, &inset.pixels, inset.width, inset.left, inset.top);
+        }
+        if let Some(region) = selection.filter(|_| selecting) {
+            draw_region(&mut shown, framebuffer_width, framebuffer_height, &main_scene.camera, &region, SELECTION_COLOR);
+        }
+        if show_minimap {
+            minimap.draw(scene.objects(), &scene.camera, &mut shown, framebuffer_width, framebuffer_height);
+        }
+        window
+            .update_with_buffer(&shown, framebuffer_width, framebuffer_height)
+            .map_err(window_failure)?;
+
+
+        std::thread::sleep(frame_delay);
+    }
+    Ok(())
+}
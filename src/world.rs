@@ -0,0 +1,594 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use crate::block::{BlockShape, BlockTurn, Facing};
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::error::RayTracerError;
+use crate::light::{Falloff, Light};
+use crate::math::{Float, Vec3};
+use crate::structures::{PlacedBlock, MAX_COORDINATE};
+
+// Formato binario de mundo (.world): mucho más pequeño y rápido de leer que un archivo de escena
+// con un [[block]] por bloque. Todo en little-endian:
+//
+// cabecera   "RTMW", versión u16, largo del resto u64 y CRC-32 del resto u32
+// paleta     u16 entradas, cada una con el nombre del material (u16 + UTF-8) y la forma con su
+//            giro (u8)
+// luces      u16 luces: posición, color rgb, intensidad, radio y un byte de flags: bit 0 encendida,
+//            bit 1 sombras y bits 2-3 la caída, el índice en Falloff::ALL (desde la versión 3;
+//            antes valen 0, la inversa del cuadrado)
+// cámara     u8 0/1 y, si está, eye, center, up, fov y roll
+// chunks     u32 chunks de 16x16x16: su posición en chunks, su paleta local (índices de la global,
+//            donde 0 es aire) y los índices locales empaquetados en palabras u64 con los bits justos
+//            para la paleta local, sin partir ninguno entre dos palabras. Un chunk con una sola
+//            entrada no guarda palabras, y los que son todo aire no se guardan.
+//
+// Los números con decimales van como f64 para no perder precisión con la feature f64.
+
+const MAGIC: &[u8; 4] = b"RTMW";
+// Sube con cada cambio del formato; load_binary sigue leyendo todas las versiones anteriores
+pub const VERSION: u16 = 3; // 2: bloques completos girados; 3: caída de las luces
+const HEADER_SIZE: usize = 4 + 2 + 8 + 4;
+// Lado de los chunks del archivo, en bloques; también es el de los chunks que carga `streaming`
+pub const CHUNK_SIZE: i32 = 16;
+const CHUNK: i32 = CHUNK_SIZE;
+const CHUNK_VOLUME: usize = (CHUNK * CHUNK * CHUNK) as usize;
+// Bloques que se leen como mucho de un archivo, unos 2 GB en memoria: un chunk de 17 bytes puede
+// traer 4096 bloques, así que sin límite un archivo pequeño bastaría para agotar la memoria. El
+// terreno más grande que acepta una escena se queda en la mitad.
+pub const MAX_BLOCKS: usize = 1 << 25;
+
+// Bloques de la cuadrícula con las luces y la cámara de la escena
+pub struct World {
+    pub blocks: Vec<PlacedBlock>,
+    pub lights: Vec<Light>, // Sin light linking: los grupos no se guardan
+    pub camera: Option<Camera>,
+}
+
+impl World {
+    pub fn save_binary(&self, path: &Path) -> Result<(), RayTracerError> {
+        std::fs::write(path, self.to_bytes()).map_err(|e| RayTracerError::io(path, e))
+    }
+
+    pub fn load_binary(path: &Path) -> Result<World, RayTracerError> {
+        let data = std::fs::read(path).map_err(|e| RayTracerError::io(path, e))?;
+        World::from_bytes(&data).map_err(|e| RayTracerError::Scene(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Bloques por posición, así el último de una posición repetida gana como en la escena
+        let mut palette: Vec<(&str, u8)> = Vec::new();
+        let mut palette_index: HashMap<(&str, u8), u16> = HashMap::new();
+        let mut chunks: BTreeMap<[i32; 3], Vec<u16>> = BTreeMap::new();
+        for block in &self.blocks {
+            let key = (block.material.as_str(), shape_code(block.shape, block.turn));
+            let index = *palette_index.entry(key).or_insert_with(|| {
+                palette.push(key);
+                palette.len() as u16 // El 0 es el aire
+            });
+            let chunk = block.pos.map(|value| value.div_euclid(CHUNK));
+            let entries = chunks.entry(chunk).or_insert_with(|| vec![0; CHUNK_VOLUME]);
+            entries[local_index(block.pos)] = index;
+        }
+
+        let mut body = Vec::new();
+        put_u16(&mut body, palette.len() as u16);
+        for (material, code) in &palette {
+            put_str(&mut body, material);
+            body.push(*code);
+        }
+        put_u16(&mut body, self.lights.len() as u16);
+        for light in &self.lights {
+            put_vec3(&mut body, &light.position);
+            body.extend_from_slice(&[light.color.r, light.color.g, light.color.b]);
+            put_f64(&mut body, light.intensity as f64);
+            put_f64(&mut body, light.radius as f64);
+            // La caída va en los bits 2 y 3; los mundos anteriores a la versión 3 los tienen a 0
+            body.push(u8::from(light.enabled) | (u8::from(light.cast_shadows) << 1) | ((light.falloff as u8) << 2));
+        }
+        match &self.camera {
+            Some(camera) => {
+                body.push(1);
+                put_vec3(&mut body, &camera.eye);
+                put_vec3(&mut body, &camera.center);
+                put_vec3(&mut body, &camera.up);
+                put_f64(&mut body, camera.fov as f64);
+                put_f64(&mut body, camera.roll as f64);
+            }
+            None => body.push(0),
+        }
+        put_u32(&mut body, chunks.len() as u32);
+        for (chunk, entries) in &chunks {
+            for value in chunk {
+                body.extend_from_slice(&value.to_le_bytes());
+            }
+            let mut local: Vec<u16> = Vec::new();
+            let mut local_of: HashMap<u16, u64> = HashMap::new();
+            let indices: Vec<u64> = entries.iter().map(|&global| {
+                *local_of.entry(global).or_insert_with(|| {
+                    local.push(global);
+                    local.len() as u64 - 1
+                })
+            }).collect();
+            put_u16(&mut body, local.len() as u16);
+            for global in &local {
+                put_u16(&mut body, *global);
+            }
+            let bits = bits_for(local.len());
+            body.push(bits as u8);
+            if bits > 0 {
+                let per_word = 64 / bits;
+                for group in indices.chunks(per_word) {
+                    let word = group.iter().enumerate().fold(0u64, |word, (i, &index)| word | (index << (i * bits)));
+                    body.extend_from_slice(&word.to_le_bytes());
+                }
+            }
+        }
+
+        let mut crc = flate2::Crc::new();
+        crc.update(&body);
+        let mut data = Vec::with_capacity(HEADER_SIZE + body.len());
+        data.extend_from_slice(MAGIC);
+        put_u16(&mut data, VERSION);
+        data.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        put_u32(&mut data, crc.sum());
+        data.extend_from_slice(&body);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<World, String> {
+        World::read(data, MAX_BLOCKS)
+    }
+
+    fn read(data: &[u8], max_blocks: usize) -> Result<World, String> {
+        let world = WorldChunks::from_bytes(data)?;
+        let mut blocks = Vec::new();
+        for (&chunk, &offset) in &world.chunks {
+            world.decode(chunk, offset, max_blocks, &mut blocks)?;
+        }
+        Ok(World { blocks, lights: world.lights, camera: world.camera })
+    }
+}
+
+type PaletteEntry = (String, (BlockShape, Option<BlockTurn>));
+
+// Un mundo binario leído sin colocar sus bloques: sus luces, su cámara y dónde empieza cada chunk
+// en los datos, que se guardan tal cual (unos pocos bits por bloque). Así se decodifican solo los
+// chunks que se piden, y un mundo enorme cabe en memoria aunque no quepan todos sus bloques.
+pub struct WorldChunks {
+    pub lights: Vec<Light>,
+    pub camera: Option<Camera>,
+    palette: Vec<PaletteEntry>,
+    body: Vec<u8>,
+    chunks: BTreeMap<[i32; 3], usize>, // Posición de cada chunk en `body`, tras sus coordenadas
+}
+
+impl WorldChunks {
+    pub fn load_binary(path: &Path) -> Result<WorldChunks, RayTracerError> {
+        let data = std::fs::read(path).map_err(|e| RayTracerError::io(path, e))?;
+        WorldChunks::from_bytes(&data).map_err(|e| RayTracerError::Scene(format!("{}: {}", path.display(), e)))
+    }
+
+    // Valida el archivo entero, paletas de los chunks incluidas, pero no coloca ningún bloque
+    pub fn from_bytes(data: &[u8]) -> Result<WorldChunks, String> {
+        if data.len() < HEADER_SIZE || &data[..4] != MAGIC {
+            return Err("Not a world file".to_string());
+        }
+        let mut header = Reader { data: &data[4..HEADER_SIZE], at: 0 };
+        let version = header.u16()?;
+        if version == 0 || version > VERSION {
+            return Err(format!("World file version {} is not supported (this build reads up to {})", version, VERSION));
+        }
+        let length = header.u64()?;
+        let checksum = header.u32()?;
+        let body = &data[HEADER_SIZE..];
+        if body.len() as u64 != length {
+            return Err(format!("World file is corrupted: expected {} bytes of data, found {}", length, body.len()));
+        }
+        let mut crc = flate2::Crc::new();
+        crc.update(body);
+        if crc.sum() != checksum {
+            return Err("World file is corrupted: checksum mismatch".to_string());
+        }
+
+        let mut reader = Reader { data: body, at: 0 };
+        let palette = (0..reader.u16()?)
+            .map(|_| Ok((reader.string()?, shape_from_code(reader.u8()?)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let lights = (0..reader.u16()?)
+            .map(|_| {
+                let position = reader.vec3()?;
+                let [r, g, b] = [reader.u8()?, reader.u8()?, reader.u8()?];
+                let (intensity, radius) = (reader.f64()? as f32, reader.f64()? as Float);
+                let flags = reader.u8()?;
+                let mut light = Light::new(position, Color::new(r, g, b), intensity, radius)
+                    .with_shadows(flags & 2 != 0)
+                    .with_falloff(Falloff::ALL[usize::from((flags >> 2) & 3)]);
+                light.enabled = flags & 1 != 0;
+                Ok(light)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let camera = match reader.u8()? {
+            0 => None,
+            _ => {
+                let mut camera = Camera::new(reader.vec3()?, reader.vec3()?, reader.vec3()?);
+                camera.fov = reader.f64()? as Float;
+                camera.roll = reader.f64()? as Float;
+                Some(camera)
+            }
+        };
+
+        let mut chunks = BTreeMap::new();
+        for _ in 0..reader.u32()? {
+            let chunk = [reader.i32()?, reader.i32()?, reader.i32()?];
+            // Con los chunks dentro de MAX_COORDINATE, las posiciones de sus bloques caben en un i32
+            if chunk.iter().any(|value| !(-MAX_COORDINATE / CHUNK..MAX_COORDINATE / CHUNK).contains(value)) {
+                return Err(format!("World file is corrupted: chunk {:?} is too far from the origin", chunk));
+            }
+            if chunks.insert(chunk, reader.at).is_some() {
+                return Err(format!("World file is corrupted: chunk {:?} appears twice", chunk));
+            }
+            let (_, bits) = chunk_palette(&mut reader, &palette)?;
+            if bits > 0 {
+                reader.skip(CHUNK_VOLUME.div_ceil(64 / bits) * 8)?;
+            }
+        }
+        if reader.at != body.len() {
+            return Err("World file is corrupted: unexpected data after the last chunk".to_string());
+        }
+        Ok(WorldChunks { lights, camera, palette, body: body.to_vec(), chunks })
+    }
+
+    // Posiciones de los chunks que tienen algún bloque, en chunks de CHUNK_SIZE bloques
+    pub fn chunks(&self) -> impl Iterator<Item = [i32; 3]> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn contains(&self, chunk: [i32; 3]) -> bool {
+        self.chunks.contains_key(&chunk)
+    }
+
+    // Los bloques de un chunk; ninguno si el archivo no lo tiene
+    pub fn blocks(&self, chunk: [i32; 3]) -> Result<Vec<PlacedBlock>, String> {
+        let mut blocks = Vec::new();
+        if let Some(&offset) = self.chunks.get(&chunk) {
+            self.decode(chunk, offset, usize::MAX, &mut blocks)?;
+        }
+        Ok(blocks)
+    }
+
+    fn decode(&self, chunk: [i32; 3], offset: usize, max_blocks: usize, blocks: &mut Vec<PlacedBlock>) -> Result<(), String> {
+        let mut reader = Reader { data: &self.body, at: offset };
+        let (local, bits) = chunk_palette(&mut reader, &self.palette)?;
+        let origin = chunk.map(|value| value * CHUNK);
+        let mut place = |index: usize, local_index: usize| -> Result<(), String> {
+            let entry = local.get(local_index).ok_or("World file is corrupted: block index out of range")?;
+            if let Some((material, (shape, turn))) = entry {
+                if blocks.len() == max_blocks {
+                    return Err(format!("World file has more than {} blocks", max_blocks));
+                }
+                let pos = [
+                    origin[0] + (index % CHUNK as usize) as i32,
+                    origin[1] + (index / (CHUNK * CHUNK) as usize) as i32,
+                    origin[2] + (index / CHUNK as usize % CHUNK as usize) as i32,
+                ];
+                blocks.push(PlacedBlock { pos, material: material.clone(), shape: *shape, turn: *turn });
+            }
+            Ok(())
+        };
+        if bits == 0 {
+            for index in 0..CHUNK_VOLUME {
+                place(index, 0)?;
+            }
+            return Ok(());
+        }
+        let per_word = 64 / bits;
+        let mask = (1u64 << bits) - 1;
+        for word_index in 0..CHUNK_VOLUME.div_ceil(per_word) {
+            let word = reader.u64()?;
+            for slot in 0..per_word {
+                let index = word_index * per_word + slot;
+                if index < CHUNK_VOLUME {
+                    place(index, ((word >> (slot * bits)) & mask) as usize)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// La paleta local de un chunk (None es el aire) y los bits por bloque de sus índices
+fn chunk_palette<'a>(reader: &mut Reader<'_>, palette: &'a [PaletteEntry]) -> Result<(Vec<Option<&'a PaletteEntry>>, usize), String> {
+    let local = (0..reader.u16()?)
+        .map(|_| {
+            let global = reader.u16()? as usize;
+            match global {
+                0 => Ok(None),
+                _ => palette.get(global - 1).map(Some).ok_or_else(|| format!("World file is corrupted: palette entry {} out of range", global)),
+            }
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let bits = reader.u8()? as usize;
+    if local.is_empty() || bits > 16 || bits != bits_for(local.len()) {
+        return Err("World file is corrupted: bad chunk palette".to_string());
+    }
+    Ok((local, bits))
+}
+
+// Posición dentro de su chunk, x primero, luego z y luego y
+fn local_index(pos: [i32; 3]) -> usize {
+    let [x, y, z] = pos.map(|value| value.rem_euclid(CHUNK) as usize);
+    (y * CHUNK as usize + z) * CHUNK as usize + x
+}
+
+// Bits por bloque para distinguir `entries` valores; un chunk de una sola entrada no necesita ninguno
+fn bits_for(entries: usize) -> usize {
+    match entries {
+        0 | 1 => 0,
+        entries => (usize::BITS - (entries - 1).leading_zeros()) as usize,
+    }
+}
+
+// Forma en un byte: 0 a 4 las formas simples, 8 + facing * 2 + al revés las escaleras y 16 más el
+// índice en BlockTurn::all() los bloques completos girados. El giro de otras formas no se guarda.
+fn shape_code(shape: BlockShape, turn: Option<BlockTurn>) -> u8 {
+    match shape {
+        BlockShape::Full => match turn.and_then(|turn| BlockTurn::all().position(|known| known == turn)) {
+            Some(index) => 16 + index as u8,
+            None => 0,
+        },
+        BlockShape::BottomSlab => 1,
+        BlockShape::TopSlab => 2,
+        BlockShape::Carpet => 3,
+        BlockShape::Fence => 4,
+        BlockShape::Stairs { facing, upside_down } => 8 + facing as u8 * 2 + u8::from(upside_down),
+    }
+}
+
+fn shape_from_code(code: u8) -> Result<(BlockShape, Option<BlockTurn>), String> {
+    const FACINGS: [Facing; 4] = [Facing::North, Facing::East, Facing::South, Facing::West];
+    match code {
+        0 => Ok((BlockShape::Full, None)),
+        1 => Ok((BlockShape::BottomSlab, None)),
+        2 => Ok((BlockShape::TopSlab, None)),
+        3 => Ok((BlockShape::Carpet, None)),
+        4 => Ok((BlockShape::Fence, None)),
+        8..=15 => Ok((BlockShape::Stairs { facing: FACINGS[(code as usize - 8) / 2], upside_down: code % 2 == 1 }, None)),
+        16.. if (code as usize - 16) < BlockTurn::all().count() => {
+            Ok((BlockShape::Full, BlockTurn::all().nth(code as usize - 16)))
+        }
+        _ => Err(format!("World file is corrupted: unknown block shape {}", code)),
+    }
+}
+
+fn put_u16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_f64(data: &mut Vec<u8>, value: f64) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_vec3(data: &mut Vec<u8>, v: &Vec3) {
+    for value in [v.x, v.y, v.z] {
+        put_f64(data, value as f64);
+    }
+}
+
+fn put_str(data: &mut Vec<u8>, value: &str) {
+    put_u16(data, value.len() as u16);
+    data.extend_from_slice(value.as_bytes());
+}
+
+// Lectura secuencial que falla con un error en lugar de entrar en pánico si el archivo se acaba
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let bytes = self.data.get(self.at..self.at + N).ok_or("World file is corrupted: data ends too early")?;
+        self.at += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.bytes()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.bytes()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    fn skip(&mut self, length: usize) -> Result<(), String> {
+        if self.data.len() - self.at < length {
+            return Err("World file is corrupted: data ends too early".to_string());
+        }
+        self.at += length;
+        Ok(())
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.bytes()?))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, String> {
+        Ok(Vec3::new(self.f64()? as Float, self.f64()? as Float, self.f64()? as Float))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u16()? as usize;
+        let bytes = self.data.get(self.at..self.at + length).ok_or("World file is corrupted: data ends too early")?;
+        self.at += length;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "World file is corrupted: material name is not UTF-8".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{Axis, Direction};
+    use crate::terrain::Terrain;
+    use crate::village::Village;
+
+    fn sorted(mut blocks: Vec<PlacedBlock>) -> Vec<PlacedBlock> {
+        blocks.sort_by_key(|block| block.pos);
+        blocks
+    }
+
+    #[test]
+    fn generated_terrain_round_trips() {
+        let terrain = Terrain { seed: 3, size: 40, tree_density: 0.02, village: Some(Village::default()), ..Terrain::default() };
+        let mut blocks = terrain.blocks();
+        blocks.push(PlacedBlock { pos: [-30, 2, 5], material: "wood".to_string(), shape: BlockShape::Stairs { facing: Facing::West, upside_down: true }, turn: None });
+        blocks.push(PlacedBlock { pos: [-31, 2, 5], material: "treewood".to_string(), shape: BlockShape::Full, turn: Some(BlockTurn::Axis(Axis::X)) });
+        blocks.push(PlacedBlock { pos: [-32, 2, 5], material: "stone".to_string(), shape: BlockShape::Full, turn: Some(BlockTurn::Facing(Direction::Down)) });
+        let mut camera = Camera::new(Vec3::new(1.0, 8.0, 12.0), Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        camera.roll = 0.25;
+        let lights = vec![Light::new(Vec3::new(2.0, 9.5, -1.0), Color::new(255, 200, 150), 1.5, 30.0).with_shadows(false).with_falloff(Falloff::Linear)];
+        let world = World { blocks: blocks.clone(), lights, camera: Some(camera) };
+
+        let data = world.to_bytes();
+        let loaded = World::from_bytes(&data).unwrap();
+        assert_eq!(sorted(loaded.blocks), sorted(blocks));
+        let light = &loaded.lights[0];
+        assert_eq!((light.position, light.color.g, light.intensity, light.radius), (Vec3::new(2.0, 9.5, -1.0), 200, 1.5, 30.0));
+        assert!(light.enabled && !light.cast_shadows && light.falloff == Falloff::Linear);
+        let camera = loaded.camera.unwrap();
+        assert_eq!((camera.eye, camera.roll), (Vec3::new(1.0, 8.0, 12.0), 0.25));
+    }
+
+    // World no es Debug (las luces y la cámara tampoco), así que unwrap_err no sirve
+    fn refused(loaded: Result<World, String>) -> String {
+        loaded.err().expect("the file should be refused")
+    }
+
+    #[test]
+    fn chunks_decode_one_at_a_time() {
+        let world = World { blocks: Terrain { size: 40, ..Terrain::default() }.blocks(), lights: Vec::new(), camera: None };
+        let chunks = WorldChunks::from_bytes(&world.to_bytes()).unwrap();
+        let decoded: Vec<PlacedBlock> = chunks.chunks().flat_map(|chunk| chunks.blocks(chunk).unwrap()).collect();
+        assert_eq!(sorted(decoded), sorted(world.blocks));
+
+        let first = chunks.chunks().next().unwrap();
+        assert!(chunks.blocks(first).unwrap().iter().all(|block| block.pos.map(|value| value.div_euclid(CHUNK)) == first));
+        assert!(!chunks.contains([1000, 0, 0]) && chunks.blocks([1000, 0, 0]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn corruption_and_newer_versions_are_rejected() {
+        let world = World { blocks: Terrain { size: 8, ..Terrain::default() }.blocks(), lights: Vec::new(), camera: None };
+        let data = world.to_bytes();
+        assert!(World::from_bytes(&data).is_ok());
+
+        let mut flipped = data.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 1;
+        assert!(refused(World::from_bytes(&flipped)).contains("checksum"));
+        assert!(refused(World::from_bytes(&data[..data.len() - 3])).contains("corrupted"));
+        let mut newer = data.clone();
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(refused(World::from_bytes(&newer)).contains("not supported"));
+        assert!(World::from_bytes(b"not a world").is_err());
+    }
+
+    // Hand-written body: a stone palette, no lights or camera and a solid stone chunk at each position
+    fn stone_body(chunks: &[[i32; 3]]) -> Vec<u8> {
+        let mut body = Vec::new();
+        put_u16(&mut body, 1);
+        put_str(&mut body, "stone");
+        body.push(0);
+        put_u16(&mut body, 0);
+        body.push(0);
+        put_u32(&mut body, chunks.len() as u32);
+        for chunk in chunks {
+            for value in chunk {
+                body.extend_from_slice(&value.to_le_bytes());
+            }
+            put_u16(&mut body, 1);
+            put_u16(&mut body, 1);
+            body.push(0);
+        }
+        body
+    }
+
+    fn sealed(body: &[u8]) -> Vec<u8> {
+        let mut crc = flate2::Crc::new();
+        crc.update(body);
+        let mut data = MAGIC.to_vec();
+        put_u16(&mut data, VERSION);
+        data.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        put_u32(&mut data, crc.sum());
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn absurd_chunks_are_rejected() {
+        assert_eq!(World::from_bytes(&sealed(&stone_body(&[[-1, 0, 2]]))).unwrap().blocks.len(), CHUNK_VOLUME);
+        // Found by fuzzing: the chunk origin overflowed an i32, and 17 bytes per chunk could ask for
+        // 4096 blocks each until memory ran out
+        assert!(refused(World::from_bytes(&sealed(&stone_body(&[[i32::MAX, 0, 0]])))).contains("too far"));
+        assert!(refused(World::from_bytes(&sealed(&stone_body(&[[0, i32::MIN, 0]])))).contains("too far"));
+        assert!(refused(World::from_bytes(&sealed(&stone_body(&[[0, 0, 0], [0, 0, 0]])))).contains("twice"));
+        let four = sealed(&stone_body(&[[0, 0, 0], [1, 0, 0], [2, 0, 0], [3, 0, 0]]));
+        assert!(refused(World::read(&four, 3 * CHUNK_VOLUME)).contains("more than"));
+        assert_eq!(World::read(&four, 4 * CHUNK_VOLUME).unwrap().blocks.len(), 4 * CHUNK_VOLUME);
+    }
+
+    #[test]
+    fn bad_palettes_and_truncated_files_are_errors() {
+        let body = stone_body(&[[0, 0, 0]]);
+        let data = sealed(&body);
+        for end in 0..data.len() {
+            assert!(World::from_bytes(&data[..end]).is_err(), "{} of {} bytes loaded", end, data.len());
+        }
+        // The chunk ends with its one palette entry and its bit count
+        let mut out_of_range = body.clone();
+        let entry = out_of_range.len() - 3;
+        out_of_range[entry..entry + 2].copy_from_slice(&7u16.to_le_bytes());
+        assert!(refused(World::from_bytes(&sealed(&out_of_range))).contains("out of range"));
+        let mut bits = body.clone();
+        *bits.last_mut().unwrap() = 5;
+        assert!(refused(World::from_bytes(&sealed(&bits))).contains("bad chunk palette"));
+        // The shape byte follows the material name in the global palette
+        let mut shape = body.clone();
+        shape[2 + 2 + "stone".len()] = 200;
+        assert!(refused(World::from_bytes(&sealed(&shape))).contains("unknown block shape"));
+    }
+
+    #[test]
+    fn a_million_blocks_load_quickly() {
+        let materials = ["stone", "grass", "wood", "glass"];
+        let blocks: Vec<PlacedBlock> = (0..100)
+            .flat_map(|y| (0..100).flat_map(move |z| (0..100).map(move |x| [x - 50, y, z - 50])))
+            .map(|pos| PlacedBlock::full(pos, materials[(pos[0] + pos[1] * 3 + pos[2] * 7).rem_euclid(4) as usize]))
+            .collect();
+        let data = World { blocks, lights: Vec::new(), camera: None }.to_bytes();
+        let start = std::time::Instant::now();
+        let loaded = World::from_bytes(&data).unwrap();
+        let elapsed = start.elapsed();
+        assert_eq!(loaded.blocks.len(), 1_000_000);
+        // Sin optimizar es varias veces más lento; el límite solo tiene sentido en release
+        if !cfg!(debug_assertions) {
+            assert!(elapsed.as_secs_f32() < 1.0, "took {:?}", elapsed);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use image::{Rgb, RgbImage};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_RayTracerMinecraft"))
+        .args(args)
+        .output()
+        .expect("the binary runs")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+// A directory of its own for each test, so they can run in parallel
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("raytracer_cli_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn path(path: &std::path::Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn help_lists_every_command_and_their_options() {
+    let output = run(&["--help"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let text = stdout(&output);
+    for command in ["view", "render", "bench", "convert", "export", "serve", "diff", "info"] {
+        assert!(text.contains(&format!("  {}", command)), "{} is missing from\n{}", command, text);
+    }
+    assert!(text.contains("--resource-pack") && !text.contains("--jobs"), "the old flags are hidden\n{}", text);
+
+    let text = stdout(&run(&["help", "render"]));
+    assert!(text.contains("Usage: RayTracerMinecraft render <job file>") && text.contains("--panorama"), "{}", text);
+    assert_eq!(stdout(&run(&["render", "--help"])), text);
+
+    let output = run(&["frobnicate"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Unknown command 'frobnicate'"), "{}", stderr(&output));
+}
+
+#[test]
+fn view_takes_its_own_options_and_no_operands() {
+    let text = stdout(&run(&["view", "--help"]));
+    assert!(text.contains("--mouse-sensitivity") && text.contains("Global options"), "{}", text);
+
+    let output = run(&["view", "scene.toml"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Usage: RayTracerMinecraft view"), "{}", stderr(&output));
+}
+
+#[test]
+fn render_writes_every_job_and_info_reads_it_back() {
+    let dir = scratch("render");
+    let image = dir.join("shot.png");
+    let jobs = dir.join("jobs.toml");
+    std::fs::write(&jobs, format!(
+        "[[job]]\neye = [0.0, 2.0, 6.5]\ntarget = [0.0, 1.0, 0.0]\nwidth = 8\nheight = 6\nspp = 1\noutput = {:?}\n",
+        path(&image)
+    )).unwrap();
+
+    let output = run(&["render", path(&jobs)]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("1 of 1 jobs succeeded"), "{}", stdout(&output));
+
+    let output = run(&["info", path(&image)]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let text = stdout(&output);
+    assert!(text.contains("Scene: demo") && text.contains("Resolution: 8x6"), "{}", text);
+
+    assert_eq!(run(&["render"]).status.code(), Some(2), "the job file is required");
+    assert_eq!(run(&["render", path(&dir.join("missing.toml"))]).status.code(), Some(1));
+}
+
+#[test]
+fn render_with_alpha_writes_rgba_with_a_transparent_sky() {
+    let dir = scratch("alpha");
+    let image = dir.join("shot.png");
+    let jobs = dir.join("jobs.toml");
+    std::fs::write(&jobs, format!(
+        "[[job]]\neye = [0.0, 2.0, 6.5]\ntarget = [0.0, 4.0, 0.0]\nwidth = 8\nheight = 6\nspp = 4\noutput = {:?}\n",
+        path(&image)
+    )).unwrap();
+
+    let output = run(&["render", path(&jobs), "--alpha", "premultiplied"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let rendered = image::open(&image).unwrap();
+    assert_eq!(rendered.color(), image::ColorType::Rgba8);
+    let rendered = rendered.to_rgba8();
+    assert!(rendered.pixels().any(|pixel| pixel.0 == [0, 0, 0, 0]), "the sky above the scene should be transparent");
+    assert!(stdout(&run(&["info", path(&image)])).contains("Alpha: premultiplied"));
+
+    let output = run(&["render", path(&jobs), "--alpha", "sideways"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Invalid value 'sideways' for --alpha"), "{}", stderr(&output));
+}
+
+#[test]
+fn render_overlays_a_logo_and_caption_only_when_asked() {
+    let dir = scratch("overlay");
+    let (plain, signed) = (dir.join("plain.png"), dir.join("signed.png"));
+    let jobs = dir.join("jobs.toml");
+    let job = |output: &std::path::Path| format!(
+        "[[job]]\neye = [0.0, 2.0, 6.5]\ntarget = [0.0, 1.0, 0.0]\nwidth = 32\nheight = 24\nspp = 1\noutput = {:?}\n",
+        path(output)
+    );
+    let logo = dir.join("logo.png");
+    RgbImage::from_pixel(4, 4, Rgb([255, 0, 255])).save(&logo).unwrap();
+
+    std::fs::write(&jobs, job(&plain)).unwrap();
+    assert!(run(&["render", path(&jobs)]).status.success());
+    std::fs::write(&jobs, job(&signed)).unwrap();
+    let output = run(&["render", path(&jobs), "--overlay", path(&logo), "--caption", "Hi", "--overlay-offset", "2"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let (plain, signed) = (image::open(&plain).unwrap().to_rgb8(), image::open(&signed).unwrap().to_rgb8());
+    // The logo sits above the caption in the bottom right corner; the top left is untouched
+    assert!(signed.pixels().any(|pixel| pixel.0 == [255, 0, 255]), "the logo is missing");
+    assert_eq!(plain.get_pixel(0, 0), signed.get_pixel(0, 0));
+
+    let output = run(&["render", path(&jobs), "--caption", "far too long for a 32 pixel wide shot"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("even with the smallest letters"), "{}", stderr(&output));
+}
+
+#[test]
+fn info_reports_images_without_metadata() {
+    let dir = scratch("info");
+    let plain = dir.join("plain.png");
+    RgbImage::new(2, 2).save(&plain).unwrap();
+    let output = run(&["info", path(&plain)]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("has no render metadata"), "{}", stdout(&output));
+    assert_eq!(run(&["info", path(&dir.join("missing.png"))]).status.code(), Some(2));
+}
+
+#[test]
+fn bench_times_each_frame() {
+    let output = run(&["bench", "--width", "8", "--height", "6", "--spp", "1", "--frames", "2"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let text = stdout(&output);
+    assert!(text.contains("frame 2:") && !text.contains("frame 3:") && text.contains("M samples/s"), "{}", text);
+    let precision = if cfg!(feature = "f64") { "f64 precision" } else { "f32 precision" };
+    assert!(text.contains(precision), "{}", text);
+
+    assert_eq!(run(&["bench", "--frames", "0"]).status.code(), Some(2));
+}
+
+// Only in f64 builds: cargo builds an f32 binary of the same tree into its own target directory,
+// and the two renders of the demo may differ only on a few edge pixels
+#[test]
+#[cfg(feature = "f64")]
+fn f64_renders_the_demo_like_f32() {
+    let target = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("f32");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--bin", "RayTracerMinecraft", "--no-default-features", "--features", "headless", "--target-dir"])
+        .arg(&target)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .status()
+        .expect("cargo runs");
+    assert!(status.success(), "the f32 build failed");
+    let f32_binary = target.join("debug").join(format!("RayTracerMinecraft{}", std::env::consts::EXE_SUFFIX));
+
+    let dir = scratch("precision");
+    let render_with = |binary: &std::path::Path, name: &str| {
+        let image = dir.join(name);
+        let jobs = dir.join(format!("{}.toml", name));
+        std::fs::write(&jobs, format!(
+            "[[job]]\neye = [0.0, 2.0, 6.5]\ntarget = [0.0, 1.0, 0.0]\nwidth = 80\nheight = 60\nspp = 1\noutput = {:?}\n",
+            path(&image)
+        )).unwrap();
+        let output = Command::new(binary).args(["render", path(&jobs)]).output().expect("the binary runs");
+        assert!(output.status.success(), "{}", stderr(&output));
+        image::open(&image).unwrap().to_rgb8()
+    };
+    let single = render_with(&f32_binary, "f32.png");
+    let double = render_with(std::path::Path::new(env!("CARGO_BIN_EXE_RayTracerMinecraft")), "f64.png");
+
+    let diff = raytracer_minecraft::image_diff::ImageDiff::new(&single, &double, 0.1).unwrap();
+    assert!(diff.over_threshold <= 80 * 60 / 100 && diff.mean_error < 0.005, "{}", diff.summary());
+}
+
+#[test]
+fn convert_writes_a_world_that_loads_as_a_scene() {
+    let dir = scratch("convert");
+    let world = dir.join("forest.world");
+    let output = run(&["convert", "scenes/forest.toml", path(&world)]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    // The world is a scene like any other
+    let mesh = dir.join("forest.obj");
+    let output = run(&["export", path(&mesh), "--scene", path(&world)]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(std::fs::read_to_string(&mesh).unwrap().contains("\nf "));
+
+    let output = run(&["convert", "castle.vox", path(&dir.join("castle.world"))]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("isn't supported yet"), "{}", stderr(&output));
+}
+
+#[test]
+fn export_picks_the_format_from_the_extension_or_format() {
+    let dir = scratch("export");
+    let glb = dir.join("demo.glb");
+    let output = run(&["export", path(&glb)]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(std::fs::read(&glb).unwrap().starts_with(b"glTF"));
+
+    let unknown = dir.join("demo.mesh");
+    let output = run(&["export", path(&unknown)]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("--format"), "{}", stderr(&output));
+    let output = run(&["export", path(&unknown), "--format", "obj"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(std::fs::read_to_string(&unknown).unwrap().contains("\nf "), "--format obj wins over the extension");
+}
+
+#[test]
+fn old_flags_still_work_with_a_warning() {
+    let dir = scratch("legacy");
+    let mesh = dir.join("demo.data");
+    let output = run(&["--export-gltf", path(&mesh)]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("--export-gltf is deprecated"), "{}", stderr(&output));
+    assert!(std::fs::read(&mesh).unwrap().starts_with(b"glTF"), "the flag still picks the format");
+
+    let world = dir.join("forest.world");
+    let output = run(&["--scene", "scenes/forest.toml", "--export-world", path(&world)]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(world.exists());
+}
+
+#[test]
+fn serve_requires_a_port() {
+    let output = run(&["serve", "http"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("serve requires a port number"), "{}", stderr(&output));
+    assert_eq!(run(&["serve"]).status.code(), Some(2));
+
+    let output = run(&["serve", "8080", "--bind", "everywhere"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("--bind requires an IP address"), "{}", stderr(&output));
+}
+
+#[test]
+fn diff_exits_by_threshold_and_writes_a_heatmap() {
+    let dir = scratch("diff");
+    let first = dir.join("first.png");
+    let second = dir.join("second.png");
+    let heatmap = dir.join("heatmap.png");
+    let image = RgbImage::from_fn(4, 4, |x, y| Rgb([x as u8 * 60, y as u8 * 60, 90]));
+    image.save(&first).unwrap();
+    let mut changed = image.clone();
+    changed.put_pixel(1, 2, Rgb([0, 0, 0]));
+    changed.save(&second).unwrap();
+
+    assert!(run(&["diff", path(&first), path(&first)]).status.success());
+    let output = run(&["diff", path(&first), path(&second), "--out", path(&heatmap)]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("1 of 16 pixels differ"), "{}", stdout(&output));
+    assert_eq!(image::open(&heatmap).unwrap().to_rgb8().get_pixel(1, 2).0, [255, 255, 255]);
+    assert!(run(&["diff", path(&first), path(&second), "--threshold", "1"]).status.success());
+
+    RgbImage::new(2, 2).save(&second).unwrap();
+    assert_eq!(run(&["diff", path(&first), path(&second)]).status.code(), Some(2));
+}
+
+#[test]
+fn print_config_layers_the_command_line_over_the_file() {
+    let dir = scratch("print_config");
+    std::fs::create_dir_all(dir.join("textures")).unwrap();
+    let config = dir.join("raytracer.toml");
+    std::fs::write(&config, "tone_mapping = \"aces\"\nfog_start = 10.0\nfog_end = 40.0\nkeybindings = \"keys.toml\"\nassets = \"textures\"\n").unwrap();
+
+    let output = run(&["--print-config", "--config", path(&config), "--tone-mapping", "reinhard"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let text = stdout(&output);
+    let line = |key: &str| text.lines().find(|line| line.starts_with(&format!("{} ", key))).unwrap_or_default().to_string();
+    assert!(line("tone_mapping").contains("\"reinhard\"") && line("tone_mapping").ends_with("# command line --tone-mapping"), "{}", text);
+    assert!(line("fog_end").contains("40") && line("fog_end").ends_with(path(&config)), "{}", text);
+    assert!(line("keybindings").contains(path(&dir.join("keys.toml"))), "paths in the file are relative to it\n{}", text);
+    assert!(line("assets").contains(path(&dir.join("textures"))) && line("assets").ends_with(path(&config)), "{}", text);
+
+    let text = stdout(&run(&["--print-config", "--config", path(&config), "--quality", "final"]));
+    assert!(text.lines().any(|line| line.starts_with("denoise ") && line.contains("true") && line.ends_with("# final preset")), "{}", text);
+
+    let output = run(&["--print-config", "--config", path(&config), "--fog-start", "50"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("fog_start < fog_end"), "{}", stderr(&output));
+}